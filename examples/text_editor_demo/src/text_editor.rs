@@ -1,4 +1,4 @@
-use leptos_sync_core::crdt::advanced::Rga;
+use leptos_sync_core::crdt::advanced::{Rga, TextCrdt};
 use leptos_sync_core::crdt::{PositionId, ReplicaId, Mergeable};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -6,11 +6,12 @@ use uuid::Uuid;
 /// A collaborative text editor using RGA (Replicated Growable Array)
 /// 
 /// This implementation provides real-time collaborative text editing capabilities
-/// with conflict-free replication using the RGA CRDT.
+/// with conflict-free replication using the RGA-based `TextCrdt`. The cursor is
+/// the position of the character before it, or `None` at the start of the text.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TextEditor {
-    /// The underlying RGA storing the text content
-    content: Rga<char>,
+    /// The underlying text CRDT storing the content
+    content: TextCrdt,
     /// Current cursor position
     cursor_position: Option<PositionId>,
     /// User ID for this editor instance
@@ -28,7 +29,7 @@ pub struct TextEditor {
 /// Represents a snapshot of the editor state for undo/redo
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct EditorState {
-    content: Rga<char>,
+    content: TextCrdt,
     cursor_position: Option<PositionId>,
     selection: Option<(PositionId, PositionId)>,
 }
@@ -79,12 +80,12 @@ impl TextEditor {
     pub fn with_config(user_id: Uuid, config: TextEditorConfig) -> Self {
         let replica_id = ReplicaId::default();
         Self {
-            content: Rga::new(replica_id),
+            content: TextCrdt::new(replica_id),
             cursor_position: None,
             user_id,
             selection: None,
             history: vec![EditorState {
-                content: Rga::new(replica_id),
+                content: TextCrdt::new(replica_id),
                 cursor_position: None,
                 selection: None,
             }],
@@ -95,7 +96,7 @@ impl TextEditor {
 
     /// Get the current text content as a string
     pub fn get_text(&self) -> String {
-        self.content.to_vec().into_iter().collect()
+        self.content.to_string()
     }
 
     /// Get the length of the text content
@@ -110,17 +111,16 @@ impl TextEditor {
 
     /// Insert a character at the current cursor position
     pub fn insert_char(&mut self, character: char) -> Result<PositionId, String> {
-        let position = self.content.insert_after(character, self.cursor_position.clone())
-            .map_err(|e| e.to_string())?;
-        self.cursor_position = Some(position.clone());
-        self.save_state();
-        Ok(position)
+        self.insert_char_at(character, self.cursor_position.clone())
     }
 
     /// Insert a character at a specific position
     pub fn insert_char_at(&mut self, character: char, position: Option<PositionId>) -> Result<PositionId, String> {
-        let new_position = self.content.insert_after(character, position)
+        let index = self.index_after(position.as_ref())?;
+        self.content.insert(index, &character.to_string())
             .map_err(|e| e.to_string())?;
+        let new_position = self.content.position_at(index)
+            .ok_or_else(|| format!("No character at index {}", index))?;
         self.cursor_position = Some(new_position.clone());
         self.save_state();
         Ok(new_position)
@@ -128,21 +128,21 @@ impl TextEditor {
 
     /// Delete the character at the current cursor position
     pub fn delete_char(&mut self) -> Result<(), String> {
-        if let Some(pos) = &self.cursor_position {
-            self.content.delete(pos).map_err(|e| e.to_string())?;
-            // Move cursor to previous position if possible
-            self.cursor_position = self.get_previous_position(pos);
-            self.save_state();
+        if let Some(pos) = self.cursor_position.clone() {
+            self.delete_char_at(pos)?;
         }
         Ok(())
     }
 
     /// Delete a character at a specific position
     pub fn delete_char_at(&mut self, position: PositionId) -> Result<(), String> {
-        self.content.delete(&position).map_err(|e| e.to_string())?;
-        // Update cursor position if it was affected
+        let index = self.content.index_of(&position)
+            .ok_or_else(|| format!("Position {:?} is not in the text", position))?;
+        self.content.delete(index..index + 1).map_err(|e| e.to_string())?;
+        // Move the cursor to the previous character if it was affected
         if Some(&position) == self.cursor_position.as_ref() {
-            self.cursor_position = self.get_previous_position(&position);
+            self.cursor_position = index.checked_sub(1)
+                .and_then(|previous| self.content.position_at(previous));
         }
         self.save_state();
         Ok(())
@@ -175,16 +175,13 @@ impl TextEditor {
 
     /// Move the cursor to the beginning of the document
     pub fn move_cursor_to_beginning(&mut self) {
-        // For simplicity, we'll just clear the cursor position
-        // In a real implementation, you'd find the first position
         self.cursor_position = None;
     }
 
     /// Move the cursor to the end of the document
     pub fn move_cursor_to_end(&mut self) {
-        // For simplicity, we'll just clear the cursor position
-        // In a real implementation, you'd find the last position
-        self.cursor_position = None;
+        self.cursor_position = self.content.len().checked_sub(1)
+            .and_then(|last| self.content.position_at(last));
     }
 
     /// Undo the last operation
@@ -238,8 +235,12 @@ impl TextEditor {
                 self.delete_char_at(position)?;
             }
             TextOperation::Replace { position, new_character } => {
-                self.delete_char_at(position.clone())?;
-                self.insert_char_at(new_character, Some(position))?;
+                let index = self.content.index_of(&position)
+                    .ok_or_else(|| format!("Position {:?} is not in the text", position))?;
+                let previous = index.checked_sub(1)
+                    .and_then(|previous| self.content.position_at(previous));
+                self.delete_char_at(position)?;
+                self.insert_char_at(new_character, previous)?;
             }
         }
         Ok(())
@@ -262,22 +263,14 @@ impl TextEditor {
         }
     }
 
-    /// Find and replace text (simplified implementation)
+    /// Replace the first occurrence of `find`, returning the number of replacements
     pub fn find_and_replace(&mut self, find: &str, replace: &str) -> Result<usize, String> {
         let text = self.get_text();
         if let Some(start) = text.find(find) {
-            // Simple implementation: clear and rebuild
-            let replica_id = ReplicaId::default();
-            self.content = Rga::new(replica_id);
-            self.cursor_position = None;
-            
-            // Insert the replacement text
-            let mut last_pos = None;
-            for ch in replace.chars() {
-                last_pos = Some(self.content.insert_after(ch, last_pos)
-                    .map_err(|e| e.to_string())?);
-            }
-            
+            let start = text[..start].chars().count();
+            let len = find.chars().count();
+            self.content.delete(start..start + len).map_err(|e| e.to_string())?;
+            self.content.insert(start, replace).map_err(|e| e.to_string())?;
             self.save_state();
             Ok(1)
         } else {
@@ -290,13 +283,21 @@ impl TextEditor {
         self.user_id
     }
 
-    /// Get the underlying RGA for advanced operations
-    pub fn get_rga(&self) -> &Rga<char> {
+    /// Get the content as an RGA of characters for advanced operations
+    ///
+    /// The content is stored in blocks, so this is a copy; edit through
+    /// [`get_text_crdt_mut`](Self::get_text_crdt_mut) instead.
+    pub fn get_rga(&self) -> Rga<char> {
+        self.content.to_rga()
+    }
+
+    /// Get the underlying text CRDT for advanced operations
+    pub fn get_text_crdt(&self) -> &TextCrdt {
         &self.content
     }
 
-    /// Get a mutable reference to the underlying RGA
-    pub fn get_rga_mut(&mut self) -> &mut Rga<char> {
+    /// Get a mutable reference to the underlying text CRDT
+    pub fn get_text_crdt_mut(&mut self) -> &mut TextCrdt {
         &mut self.content
     }
 
@@ -324,9 +325,14 @@ impl TextEditor {
         }
     }
 
-    fn get_previous_position(&self, _current_pos: &PositionId) -> Option<PositionId> {
-        // Simplified implementation - in reality you'd traverse the RGA structure
-        None
+    /// Index just after the character at `position`, or the start of the text for `None`
+    fn index_after(&self, position: Option<&PositionId>) -> Result<usize, String> {
+        match position {
+            Some(position) => self.content.index_of(position)
+                .map(|index| index + 1)
+                .ok_or_else(|| format!("Position {:?} is not in the text", position)),
+            None => Ok(0),
+        }
     }
 }
 
@@ -366,6 +372,7 @@ mod tests {
         editor.insert_char('l').unwrap();
         editor.insert_char('o').unwrap();
         assert_eq!(editor.get_text(), "Hello");
+        assert_eq!(editor.get_rga().to_vec(), vec!['H', 'e', 'l', 'l', 'o']);
     }
 
     #[test]
@@ -415,4 +422,20 @@ mod tests {
         assert!(text.contains("Hello"));
         assert!(text.contains("World"));
     }
+
+    #[test]
+    fn test_text_editor_cursor_and_replace() {
+        let mut editor = TextEditor::new(Uuid::new_v4());
+        for ch in "Hello".chars() {
+            editor.insert_char(ch).unwrap();
+        }
+        editor.move_cursor_to_beginning();
+        editor.insert_char('>').unwrap();
+        editor.move_cursor_to_end();
+        editor.insert_char('!').unwrap();
+        assert_eq!(editor.get_text(), ">Hello!");
+
+        assert_eq!(editor.find_and_replace("Hello", "Bye").unwrap(), 1);
+        assert_eq!(editor.get_text(), ">Bye!");
+    }
 }
\ No newline at end of file
@@ -9,4 +9,4 @@ pub mod resolver;
 
 pub use provider::LocalFirstProvider;
 pub use status::SyncStatusIndicator;
-pub use resolver::ConflictResolver;
+pub use resolver::{ConflictAlternatives, ConflictResolver};
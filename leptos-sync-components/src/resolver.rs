@@ -2,12 +2,63 @@
 
 use leptos::*;
 use leptos::prelude::*;
+use std::fmt::Debug;
 
+/// Conflict resolver
+///
+/// Without props it renders the bare resolver as before. Given the
+/// concurrent alternatives of a conflict, e.g. `MvRegister::values()` shown
+/// as strings, it lists them and calls `on_resolve` with the one the user
+/// keeps; use [`ConflictAlternatives`] to keep the values' own type.
 #[component]
-pub fn ConflictResolver() -> impl IntoView {
+pub fn ConflictResolver(
+    #[prop(optional, into)] alternatives: Option<Signal<Vec<String>>>,
+    #[prop(optional)] on_resolve: Option<Callback<String>>,
+) -> impl IntoView {
+    match (alternatives, on_resolve) {
+        (Some(alternatives), Some(on_resolve)) => view! {
+            <ConflictAlternatives alternatives=alternatives on_resolve=on_resolve/>
+        }
+        .into_any(),
+        _ => view! {
+            <div>
+                <span>"Conflict Resolver"</span>
+            </div>
+        }
+        .into_any(),
+    }
+}
+
+/// Lists the concurrent alternatives of a conflict, e.g. `MvRegister::values()`,
+/// and calls `on_resolve` with the one the user keeps
+#[component]
+pub fn ConflictAlternatives<T>(
+    #[prop(into)] alternatives: Signal<Vec<T>>,
+    on_resolve: Callback<T>,
+) -> impl IntoView
+where
+    T: Clone + Debug + Send + Sync + 'static,
+{
     view! {
-        <div>
+        <div class="conflict-resolver">
             <span>"Conflict Resolver"</span>
+            {move || {
+                alternatives
+                    .get()
+                    .into_iter()
+                    .map(|value| {
+                        let label = format!("{:#?}", value);
+                        view! {
+                            <div class="option-group">
+                                <pre>{label}</pre>
+                                <button on:click=move |_| on_resolve.run(value.clone())>
+                                    "Keep this"
+                                </button>
+                            </div>
+                        }
+                    })
+                    .collect_view()
+            }}
         </div>
     }
 }
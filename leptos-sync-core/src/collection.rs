@@ -2,14 +2,18 @@
 
 use crate::{
     crdt::{Mergeable, ReplicaId},
-    storage::{LocalStorage, Storage, StorageError},
-    sync::{SyncEngine, SyncState},
+    storage::{
+        indexed::{IndexConfig, IndexError, IndexedStorage},
+        LocalStorage, Storage, StorageError,
+    },
+    sync::{SyncEngine, SyncEvent, SyncState},
     transport::{SyncTransport, TransportError},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::marker::PhantomData;
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -22,12 +26,17 @@ pub enum CollectionError {
     Sync(#[from] crate::sync::SyncEngineError),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Index error: {0}")]
+    Index(#[from] IndexError),
     #[error("Item not found: {0}")]
     NotFound(String),
     #[error("Invalid operation: {0}")]
     InvalidOperation(String),
 }
 
+/// Extracts the indexed value of a document
+pub type IndexExtractor<T> = Arc<dyn Fn(&T) -> String + Send + Sync>;
+
 /// Local-first collection that can synchronize with remote peers
 pub struct LocalFirstCollection<T, Tr>
 where
@@ -37,6 +46,9 @@ where
     storage: Storage,
     sync_engine: Arc<RwLock<SyncEngine<Tr>>>,
     auto_sync: bool,
+    indexed: Arc<IndexedStorage>,
+    extractors: Arc<RwLock<HashMap<String, IndexExtractor<T>>>>,
+    changes: broadcast::Sender<String>,
     _phantom: PhantomData<T>,
 }
 
@@ -84,12 +96,7 @@ where
             SyncEngine::new(self.storage.clone(), self.transport.clone())
         };
 
-        LocalFirstCollection::<T, Tr> {
-            storage: self.storage,
-            sync_engine: Arc::new(RwLock::new(sync_engine)),
-            auto_sync: self.auto_sync,
-            _phantom: PhantomData,
-        }
+        LocalFirstCollection::from_parts(self.storage, sync_engine, self.auto_sync)
     }
 }
 
@@ -101,23 +108,25 @@ where
     /// Create a new collection
     pub fn new(storage: Storage, transport: Tr) -> Self {
         let sync_engine = SyncEngine::new(storage.clone(), transport);
-        
-        Self {
-            storage,
-            sync_engine: Arc::new(RwLock::new(sync_engine)),
-            auto_sync: false,
-            _phantom: PhantomData,
-        }
+        Self::from_parts(storage, sync_engine, false)
     }
 
     /// Create a collection with a specific replica ID
     pub fn with_replica_id(storage: Storage, transport: Tr, replica_id: ReplicaId) -> Self {
         let sync_engine = SyncEngine::with_replica_id(storage.clone(), transport, replica_id);
-        
+        Self::from_parts(storage, sync_engine, false)
+    }
+
+    fn from_parts(storage: Storage, sync_engine: SyncEngine<Tr>, auto_sync: bool) -> Self {
+        let (changes, _) = broadcast::channel(1000);
+
         Self {
+            indexed: Arc::new(IndexedStorage::new(Arc::new(storage.clone()))),
             storage,
             sync_engine: Arc::new(RwLock::new(sync_engine)),
-            auto_sync: false,
+            auto_sync,
+            extractors: Arc::new(RwLock::new(HashMap::new())),
+            changes,
             _phantom: PhantomData,
         }
     }
@@ -130,8 +139,10 @@ where
 
     /// Insert or update an item
     pub async fn insert(&self, key: &str, value: &T) -> Result<(), CollectionError> {
-        // Store locally first
+        // Store locally first, indexing only what was stored
         self.storage.set(key, value).await?;
+        self.reindex(key, Some(value)).await?;
+        self.notify_changed(key);
 
         // Sync if auto-sync is enabled
         if self.auto_sync {
@@ -149,7 +160,10 @@ where
 
     /// Remove an item
     pub async fn remove(&self, key: &str) -> Result<(), CollectionError> {
-        self.storage.remove(key).await.map_err(Into::into)
+        self.storage.remove(key).await?;
+        self.reindex(key, None).await?;
+        self.notify_changed(key);
+        Ok(())
     }
 
     /// Get all keys
@@ -225,12 +239,25 @@ where
     pub async fn force_sync(&self) -> Result<(), CollectionError> {
         let mut engine = self.sync_engine.write().await;
         
-        // Process any pending messages
-        engine.process_messages().await.map_err(|e| CollectionError::Sync(e))?;
+        // Merge any pending remote changes into local storage
+        let applied = engine.process_messages::<T>().await.map_err(CollectionError::Sync)?;
+        drop(engine);
+
+        for key in applied {
+            let value = self.storage.get::<T>(&key).await?;
+            self.reindex(&key, value.as_ref()).await?;
+            self.notify_changed(&key);
+        }
         
         Ok(())
     }
 
+    /// Subscribe to remote changes applied to this collection
+    pub async fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        let engine = self.sync_engine.read().await;
+        engine.subscribe()
+    }
+
     /// Insert or update multiple items in a batch
     pub async fn insert_batch(&self, items: impl IntoIterator<Item = (String, T)>) -> Result<(), CollectionError> {
         let items: Vec<_> = items.into_iter().collect();
@@ -238,6 +265,8 @@ where
         // Store locally first in batch
         for (key, value) in &items {
             self.storage.set(key, value).await?;
+            self.reindex(key, Some(value)).await?;
+            self.notify_changed(key);
         }
 
         // Sync if auto-sync is enabled
@@ -265,6 +294,8 @@ where
         // Update locally in batch
         for (key, value) in &updates {
             self.storage.set(key, value).await?;
+            self.reindex(key, Some(value)).await?;
+            self.notify_changed(key);
         }
 
         // Sync if auto-sync is enabled
@@ -285,6 +316,8 @@ where
         // Remove locally in batch
         for key in &keys {
             self.storage.remove(key).await?;
+            self.reindex(key, None).await?;
+            self.notify_changed(key);
         }
 
         // Sync if auto-sync is enabled
@@ -332,6 +365,70 @@ where
         Ok(engine.peers().await)
     }
 
+    /// Create a secondary index over the collection
+    ///
+    /// `extractor` derives the indexed value from each item. Existing items are
+    /// indexed immediately, and the index is kept up to date on local writes and
+    /// remote merges. Queries use it for `where_eq`/`where_range` on `config.name`.
+    pub async fn create_index<F>(&self, config: IndexConfig, extractor: F) -> Result<(), CollectionError>
+    where
+        F: Fn(&T) -> String + Send + Sync + 'static,
+    {
+        let name = config.name.clone();
+        self.indexed.create_index(config).await?;
+
+        let extractor: IndexExtractor<T> = Arc::new(extractor);
+        for key in self.storage.keys().await? {
+            if let Some(value) = self.storage.get::<T>(&key).await? {
+                if let Err(e) = self.indexed.update_document(&name, &key, Some(&extractor(&value))).await {
+                    self.indexed.drop_index(&name).await?;
+                    return Err(e.into());
+                }
+            }
+        }
+        self.extractors.write().await.insert(name, extractor);
+
+        Ok(())
+    }
+
+    /// Drop a secondary index
+    pub async fn drop_index(&self, name: &str) -> Result<(), CollectionError> {
+        self.indexed.drop_index(name).await?;
+        self.extractors.write().await.remove(name);
+        Ok(())
+    }
+
+    pub(crate) fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub(crate) fn indexed_storage(&self) -> &IndexedStorage {
+        &self.indexed
+    }
+
+    pub(crate) async fn index_extractors(&self) -> HashMap<String, IndexExtractor<T>> {
+        self.extractors.read().await.clone()
+    }
+
+    /// Subscribe to the keys of items changed locally or by remote merges
+    pub(crate) fn subscribe_changes(&self) -> broadcast::Receiver<String> {
+        self.changes.subscribe()
+    }
+
+    async fn reindex(&self, key: &str, value: Option<&T>) -> Result<(), CollectionError> {
+        let extractors = self.extractors.read().await;
+        for (name, extractor) in extractors.iter() {
+            let indexed_value = value.map(|value| extractor(value));
+            self.indexed.update_document(name, key, indexed_value.as_deref()).await?;
+        }
+        Ok(())
+    }
+
+    fn notify_changed(&self, key: &str) {
+        // Sending only fails when no query is watching
+        let _ = self.changes.send(key.to_string());
+    }
+
     /// Get sync information
     pub async fn sync_info(&self) -> Result<SyncInfo, CollectionError> {
         let engine = self.sync_engine.read().await;
@@ -442,6 +539,92 @@ mod tests {
         assert!(collection.auto_sync);
     }
 
+    #[tokio::test]
+    async fn test_collections_converge_over_shared_transport() {
+        let transport = InMemoryTransport::new();
+        let collection1 = CollectionBuilder::new(Storage::memory(), transport.clone())
+            .with_auto_sync(true)
+            .build::<LwwRegister<String>>();
+        let collection2 = CollectionBuilder::new(Storage::memory(), transport)
+            .with_auto_sync(true)
+            .build::<LwwRegister<String>>();
+        let mut events = collection2.subscribe().await;
+
+        let value = LwwRegister::new("from replica 1".to_string(), ReplicaId::default());
+        collection1.insert("doc", &value).await.unwrap();
+        collection2.force_sync().await.unwrap();
+
+        assert_eq!(collection2.get("doc").await.unwrap(), Some(value));
+        match events.try_recv().unwrap() {
+            SyncEvent::RemoteChangeApplied { key, conflict, .. } => {
+                assert_eq!(key, "doc");
+                assert_eq!(conflict, None);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_remote_change_uses_resolver() {
+        let transport = InMemoryTransport::new();
+        let collection1 = CollectionBuilder::new(Storage::memory(), transport.clone())
+            .with_auto_sync(true)
+            .build::<LwwRegister<String>>();
+        let collection2 = CollectionBuilder::new(Storage::memory(), transport)
+            .build::<LwwRegister<String>>();
+        let mut events = collection2.subscribe().await;
+
+        let timestamp = crate::crdt::HlcTimestamp::now();
+        let local = LwwRegister::new("local".to_string(), ReplicaId::default()).with_timestamp(timestamp);
+        let remote = LwwRegister::new("remote".to_string(), ReplicaId::default()).with_timestamp(timestamp);
+        collection2.insert("doc", &local).await.unwrap();
+        collection1.insert("doc", &remote).await.unwrap();
+        collection2.force_sync().await.unwrap();
+
+        let mut expected = local.clone();
+        expected.merge(&remote).unwrap();
+        assert_eq!(collection2.get("doc").await.unwrap(), Some(expected));
+        match events.try_recv().unwrap() {
+            SyncEvent::RemoteChangeApplied { conflict, .. } => {
+                assert_eq!(conflict, Some(crate::sync::conflict::ConflictStrategy::LastWriteWins));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_remote_change_from_skewed_clock_is_rejected() {
+        use crate::crdt::HlcTimestamp;
+        use crate::protocol::Message;
+        use crate::transport::SyncTransport;
+
+        let transport = InMemoryTransport::new();
+        let collection = CollectionBuilder::new(Storage::memory(), transport.clone())
+            .build::<LwwRegister<String>>();
+        let mut events = collection.subscribe().await;
+
+        // A peer whose clock is two days ahead
+        let skewed = HlcTimestamp::new(HlcTimestamp::now().physical + 2 * 24 * 60 * 60 * 1000, 0);
+        let peer = ReplicaId::default();
+        let value = LwwRegister::new("from the future".to_string(), peer)
+            .with_timestamp(skewed);
+        let message = Message::Sync {
+            key: "doc".to_string(),
+            data: serde_json::to_vec(&value).unwrap(),
+            replica_id: peer,
+            timestamp: skewed,
+        };
+        transport.send(&Message::hello(peer).encode().unwrap()).await.unwrap();
+        transport.send(&message.encode().unwrap()).await.unwrap();
+        collection.force_sync().await.unwrap();
+
+        assert_eq!(collection.get("doc").await.unwrap(), None);
+        assert!(matches!(
+            events.try_recv().unwrap(),
+            SyncEvent::RemoteChangeRejected { .. }
+        ));
+    }
+
     #[tokio::test]
     async fn test_collection_batch_operations() {
         let storage = Storage::memory();
@@ -487,6 +670,68 @@ mod tests {
         assert_eq!(remaining[0].0, "key3");
     }
 
+    /// Item whose storage write fails when its status is "unwritable"
+    #[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+    struct Note {
+        status: String,
+    }
+
+    impl Serialize for Note {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            if self.status == "unwritable" {
+                return Err(serde::ser::Error::custom("write refused"));
+            }
+            serializer.serialize_newtype_struct("Note", &self.status)
+        }
+    }
+
+    impl Mergeable for Note {
+        type Error = std::io::Error;
+
+        fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+            self.status = other.status.clone();
+            Ok(())
+        }
+
+        fn has_conflict(&self, _other: &Self) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_write_leaves_index_untouched() {
+        let collection = LocalFirstCollection::<Note, _>::new(Storage::memory(), InMemoryTransport::new());
+        let config = IndexConfig {
+            name: "status".to_string(),
+            index_type: crate::storage::indexed::IndexType::Hash,
+            unique: false,
+            sparse: true,
+        };
+        collection.create_index(config, |note: &Note| note.status.clone()).await.unwrap();
+        let note = |status: &str| Note { status: status.to_string() };
+        let index = collection.indexed_storage();
+        let indexed = |status: &'static str| async move {
+            let mut keys = index.query_by_index("status", status).await.unwrap();
+            keys.sort();
+            keys
+        };
+
+        collection.insert("a", &note("open")).await.unwrap();
+        assert!(collection.insert("a", &note("unwritable")).await.is_err());
+        assert_eq!(indexed("open").await, vec!["a".to_string()]);
+        assert!(indexed("unwritable").await.is_empty());
+
+        let batch = vec![("b".to_string(), note("open")), ("c".to_string(), note("unwritable"))];
+        assert!(collection.insert_batch(batch).await.is_err());
+        assert_eq!(indexed("open").await, vec!["a".to_string(), "b".to_string()]);
+        assert!(indexed("unwritable").await.is_empty());
+        assert_eq!(collection.get("c").await.unwrap(), None);
+
+        let updates = vec![("b".to_string(), note("unwritable"))];
+        assert!(collection.update_batch(updates).await.is_err());
+        assert_eq!(indexed("open").await.len(), 2);
+    }
+
     #[tokio::test]
     async fn test_collection_batch_performance() {
         let storage = Storage::memory();
@@ -2,6 +2,7 @@
 
 use super::super::ReplicaId;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
 use uuid::Uuid;
 
@@ -37,8 +38,25 @@ impl fmt::Display for AdvancedCrdtError {
 
 impl std::error::Error for AdvancedCrdtError {}
 
-/// Position identifier for RGA and LSEQ
+/// One level of a dense LSEQ path
+///
+/// Levels compare by digit first; the replica and timestamp that allocated the
+/// digit keep concurrently allocated paths apart.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct PathDigit {
+    /// Digit at this level, below the level's base
+    pub digit: u64,
+    /// Replica that allocated the digit
+    pub replica_id: ReplicaId,
+    /// Logical timestamp of the allocation
+    pub timestamp: u64,
+}
+
+/// Position identifier for RGA and LSEQ
+///
+/// Positions are ordered by their path, then by timestamp and replica. RGA
+/// leaves the path empty and orders elements through `prev` links instead.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct PositionId {
     /// Replica ID that created this position
     pub replica_id: ReplicaId,
@@ -46,6 +64,9 @@ pub struct PositionId {
     pub timestamp: u64,
     /// Additional disambiguation value
     pub disambiguation: u64,
+    /// Dense LSEQ path, empty for positions not placed by LSEQ
+    #[serde(default)]
+    pub path: Vec<PathDigit>,
 }
 
 impl PositionId {
@@ -55,8 +76,78 @@ impl PositionId {
             replica_id,
             timestamp,
             disambiguation,
+            path: Vec::new(),
         }
     }
+
+    /// Create a position ID placed at a dense LSEQ path
+    pub fn with_path(replica_id: ReplicaId, timestamp: u64, disambiguation: u64, path: Vec<PathDigit>) -> Self {
+        Self {
+            path,
+            ..Self::new(replica_id, timestamp, disambiguation)
+        }
+    }
+}
+
+impl PartialOrd for PositionId {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PositionId {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.path, self.timestamp, &self.replica_id, self.disambiguation)
+            .cmp(&(&other.path, other.timestamp, &other.replica_id, other.disambiguation))
+    }
+}
+
+/// Logical timestamp and replica of a deletion
+///
+/// Stamps are ordered by timestamp, then replica, so replicas that deleted the
+/// same element concurrently agree on which deletion to keep.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Tombstone {
+    /// Logical timestamp of the deletion
+    pub timestamp: u64,
+    /// Replica that deleted the element
+    pub replica_id: ReplicaId,
+}
+
+impl Tombstone {
+    /// Create a new deletion stamp
+    pub fn new(replica_id: ReplicaId, timestamp: u64) -> Self {
+        Self { timestamp, replica_id }
+    }
+}
+
+/// Serde adapter storing a map as a sequence of `(key, value)` pairs
+///
+/// JSON only has string keys, so maps keyed by [`PositionId`] would fail to
+/// serialize with serde_json. Bincode encodes both forms identically.
+pub(crate) mod map_as_seq {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<'a, M, K, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+        K: Serialize + 'a,
+        V: Serialize + 'a,
+        S: Serializer,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, M, K, V, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: FromIterator<(K, V)>,
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let entries = Vec::<(K, V)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
 }
 
 #[cfg(test)]
@@ -3,7 +3,11 @@
 use super::common::{PositionId, AdvancedCrdtError};
 use super::super::{CRDT, Mergeable, ReplicaId};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+/// Order of edge changes, see [`DagEdge::stamp`]
+type Stamp = (u64, ReplicaId);
 
 /// DAG (Directed Acyclic Graph) node
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -33,25 +37,99 @@ impl<T> DagNode<T> {
     }
 }
 
+/// Record of an edge, stamped by its last add or removal
+///
+/// Replicas keep the record with the latest stamp, so concurrent adds and
+/// removals of the same edge converge.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DagEdge {
+    /// Source node
+    pub from: PositionId,
+    /// Target node
+    pub to: PositionId,
+    /// Logical timestamp of the last change
+    pub timestamp: u64,
+    /// Replica that made the last change
+    pub replica_id: ReplicaId,
+    /// Whether the last change removed the edge
+    pub removed: bool,
+}
+
+impl DagEdge {
+    /// Order of changes to the same edge, and priority when breaking cycles
+    pub fn stamp(&self) -> (u64, ReplicaId) {
+        (self.timestamp, self.replica_id)
+    }
+}
+
 /// DAG (Directed Acyclic Graph) for complex relationships
+///
+/// Edges added concurrently on different replicas can close a cycle that
+/// neither replica saw. Such cycles are broken deterministically: live edges
+/// are applied oldest first by `(timestamp, replica)`, and an edge that would
+/// close a cycle is suppressed. Suppressed edges are kept and come back once
+/// the edges they conflict with are removed, see [`Dag::suppressed_edges`].
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "DagState<T>")]
 pub struct Dag<T> {
     /// Replica ID
     replica_id: ReplicaId,
-    /// Nodes indexed by ID
+    /// Nodes indexed by ID, their edge sets holding the visible edges
     nodes: HashMap<PositionId, DagNode<T>>,
+    /// Every edge ever added, by source and target
+    edges: HashMap<(PositionId, PositionId), DagEdge>,
     /// Logical timestamp counter
     timestamp_counter: u64,
     /// Disambiguation counter
     disambiguation_counter: u64,
 }
 
+/// Stored form of a [`Dag`], which may predate edge records
+#[derive(Deserialize)]
+struct DagState<T> {
+    replica_id: ReplicaId,
+    nodes: HashMap<PositionId, DagNode<T>>,
+    #[serde(default)]
+    edges: HashMap<(PositionId, PositionId), DagEdge>,
+    timestamp_counter: u64,
+    disambiguation_counter: u64,
+}
+
+impl<T> From<DagState<T>> for Dag<T> {
+    fn from(state: DagState<T>) -> Self {
+        let mut edges = state.edges;
+        if edges.is_empty() {
+            // Dags saved before edges had records only have the node edge sets;
+            // their edges get the oldest stamp, so any later change wins
+            for node in state.nodes.values() {
+                for to in &node.outgoing {
+                    edges.insert((node.id.clone(), to.clone()), DagEdge {
+                        from: node.id.clone(),
+                        to: to.clone(),
+                        timestamp: 0,
+                        replica_id: node.id.replica_id,
+                        removed: false,
+                    });
+                }
+            }
+        }
+        Self {
+            replica_id: state.replica_id,
+            nodes: state.nodes,
+            edges,
+            timestamp_counter: state.timestamp_counter,
+            disambiguation_counter: state.disambiguation_counter,
+        }
+    }
+}
+
 impl<T: Clone + PartialEq> Dag<T> {
     /// Create a new DAG
     pub fn new(replica_id: ReplicaId) -> Self {
         Self {
             replica_id,
             nodes: HashMap::new(),
+            edges: HashMap::new(),
             timestamp_counter: 0,
             disambiguation_counter: 0,
         }
@@ -63,7 +141,7 @@ impl<T: Clone + PartialEq> Dag<T> {
         self.disambiguation_counter += 1;
         
         let id = PositionId::new(
-            self.replica_id.clone(),
+            self.replica_id,
             self.timestamp_counter,
             self.disambiguation_counter,
         );
@@ -75,81 +153,214 @@ impl<T: Clone + PartialEq> Dag<T> {
     }
     
     /// Add an edge from source to target
+    ///
+    /// Fails if either node is missing or deleted, or if the edge would close
+    /// a cycle among the visible edges.
     pub fn add_edge(&mut self, from: &PositionId, to: &PositionId) -> Result<(), AdvancedCrdtError> {
-        if !self.nodes.contains_key(from) || !self.nodes.contains_key(to) {
+        if !self.is_visible(from) || !self.is_visible(to) {
             return Err(AdvancedCrdtError::ElementNotFound("Node not found".to_string()));
         }
+        if self.nodes[from].outgoing.contains(to) {
+            return Ok(());
+        }
         
         // Check for cycle
-        if self.would_create_cycle(from, to) {
+        if self.would_create_cycle(from, to, None) {
             return Err(AdvancedCrdtError::CycleDetected("Adding edge would create cycle".to_string()));
         }
         
-        // Add edge
+        // The newest edge is applied last when rebuilding, so linking it directly
+        // matches what a rebuild would do
+        self.record_edge(from, to, false);
+        self.link(from, to);
+        
+        Ok(())
+    }
+    
+    /// Remove an edge
+    pub fn remove_edge(&mut self, from: &PositionId, to: &PositionId) -> Result<(), AdvancedCrdtError> {
+        let key = (from.clone(), to.clone());
+        let Some(edge) = self.edges.get(&key).filter(|edge| !edge.removed) else {
+            return Ok(());
+        };
+        let lost: Vec<_> = self.is_linked(edge)
+            .then(|| (edge.stamp(), from.clone(), to.clone()))
+            .into_iter()
+            .collect();
+        self.record_edge(from, to, true);
+        // Edges suppressed by this one may be visible now
+        self.relink(lost, HashSet::new());
+        
+        Ok(())
+    }
+    
+    /// Delete a node
+    ///
+    /// The node and its edges are hidden but kept.
+    pub fn delete_node(&mut self, node_id: &PositionId) -> Result<(), AdvancedCrdtError> {
+        match self.nodes.get_mut(node_id) {
+            Some(node) if node.visible => {
+                node.visible = false;
+                let lost = self.linked_edges(node_id);
+                self.relink(lost, HashSet::new());
+                Ok(())
+            }
+            Some(_) => Ok(()),
+            None => Err(AdvancedCrdtError::ElementNotFound(format!("Node {:?}", node_id))),
+        }
+    }
+    
+    /// Edges that are live but hidden because they would close a cycle
+    ///
+    /// Sorted by stamp, so the order is the same on every replica.
+    pub fn suppressed_edges(&self) -> Vec<&DagEdge> {
+        let mut suppressed: Vec<_> = self.edges.values()
+            .filter(|edge| self.is_live(edge) && !self.is_linked(edge))
+            .collect();
+        suppressed.sort_by_key(|edge| edge.stamp());
+        suppressed
+    }
+    
+    /// Whether the node exists and is not deleted
+    fn is_visible(&self, id: &PositionId) -> bool {
+        self.nodes.get(id).is_some_and(|node| node.visible)
+    }
+    
+    /// Whether the edge is added and both its nodes are visible
+    fn is_live(&self, edge: &DagEdge) -> bool {
+        !edge.removed && self.is_visible(&edge.from) && self.is_visible(&edge.to)
+    }
+    
+    /// Whether the edge is visible
+    fn is_linked(&self, edge: &DagEdge) -> bool {
+        self.nodes.get(&edge.from).is_some_and(|node| node.outgoing.contains(&edge.to))
+    }
+    
+    /// Visible edges into and out of a node, with their stamps
+    fn linked_edges(&self, id: &PositionId) -> Vec<(Stamp, PositionId, PositionId)> {
+        let Some(node) = self.nodes.get(id) else {
+            return Vec::new();
+        };
+        let incoming = node.incoming.iter().map(|from| (from.clone(), id.clone()));
+        let outgoing = node.outgoing.iter().map(|to| (id.clone(), to.clone()));
+        incoming.chain(outgoing)
+            .filter_map(|key| Some((self.edges.get(&key)?.stamp(), key.0, key.1)))
+            .collect()
+    }
+    
+    /// Stamp a local add or removal of an edge
+    fn record_edge(&mut self, from: &PositionId, to: &PositionId, removed: bool) {
+        self.timestamp_counter += 1;
+        let edge = DagEdge {
+            from: from.clone(),
+            to: to.clone(),
+            timestamp: self.timestamp_counter,
+            replica_id: self.replica_id,
+            removed,
+        };
+        self.edges.insert((from.clone(), to.clone()), edge);
+    }
+    
+    /// Make an edge visible
+    fn link(&mut self, from: &PositionId, to: &PositionId) {
         if let Some(from_node) = self.nodes.get_mut(from) {
             from_node.outgoing.insert(to.clone());
         }
         if let Some(to_node) = self.nodes.get_mut(to) {
             to_node.incoming.insert(from.clone());
         }
-        
-        Ok(())
     }
     
-    /// Remove an edge
-    pub fn remove_edge(&mut self, from: &PositionId, to: &PositionId) -> Result<(), AdvancedCrdtError> {
+    /// Make an edge invisible
+    fn unlink(&mut self, from: &PositionId, to: &PositionId) {
         if let Some(from_node) = self.nodes.get_mut(from) {
             from_node.outgoing.remove(to);
         }
         if let Some(to_node) = self.nodes.get_mut(to) {
             to_node.incoming.remove(from);
         }
-        
-        Ok(())
     }
     
-    /// Delete a node
-    pub fn delete_node(&mut self, node_id: &PositionId) -> Result<(), AdvancedCrdtError> {
-        if let Some(node) = self.nodes.get(node_id) {
-            let incoming_edges = node.incoming.clone();
-            let outgoing_edges = node.outgoing.clone();
-            
-            // Mark node as invisible
-            if let Some(node) = self.nodes.get_mut(node_id) {
-                node.visible = false;
+    /// Update the visible edges after `lost` visible edges stopped being live
+    /// and the records of `candidates` changed
+    ///
+    /// The result is the same as applying every live edge oldest first and
+    /// skipping those that would close a cycle, so replicas with the same
+    /// records end up with the same graph. Only the edges from the earliest
+    /// decision that can change on are reapplied.
+    fn relink(&mut self, lost: Vec<(Stamp, PositionId, PositionId)>, candidates: HashSet<(PositionId, PositionId)>) {
+        for (_, from, to) in &lost {
+            self.unlink(from, to);
+        }
+        let mut added: Vec<_> = candidates.into_iter()
+            .filter_map(|key| {
+                let edge = self.edges.get(&key)?;
+                (self.is_live(edge) && !self.is_linked(edge)).then(|| (edge.stamp(), key.0, key.1))
+            })
+            .collect();
+        added.sort();
+        
+        let start = match lost.iter().map(|(stamp, ..)| *stamp).min() {
+            Some(lost_since) => {
+                // Edges suppressed after the first lost one may fit now
+                let revived = self.edges.values()
+                    .filter(|edge| edge.stamp() > lost_since && self.is_live(edge) && !self.is_linked(edge))
+                    .map(DagEdge::stamp)
+                    .min();
+                revived.into_iter().chain(added.first().map(|(stamp, ..)| *stamp)).min()
             }
-            
-            // Remove all edges involving this node
-            for incoming in &incoming_edges {
-                if let Some(incoming_node) = self.nodes.get_mut(incoming) {
-                    incoming_node.outgoing.remove(node_id);
+            None => {
+                // Adding an edge that closes no cycle keeps every other decision
+                let mut start = None;
+                for (stamp, from, to) in added {
+                    if !self.would_create_cycle(&from, &to, None) {
+                        self.link(&from, &to);
+                    } else if !self.would_create_cycle(&from, &to, Some(stamp)) {
+                        // Only closes a cycle with newer edges, which it takes precedence over
+                        start = Some(stamp);
+                        break;
+                    }
                 }
+                start
             }
-            for outgoing in &outgoing_edges {
-                if let Some(outgoing_node) = self.nodes.get_mut(outgoing) {
-                    outgoing_node.incoming.remove(node_id);
-                }
+        };
+        if let Some(start) = start {
+            self.relink_from(start);
+        }
+    }
+    
+    /// Reapply the live edges stamped `start` or later, oldest first
+    fn relink_from(&mut self, start: Stamp) {
+        let mut later: Vec<_> = self.edges.values()
+            .filter(|edge| edge.stamp() >= start)
+            .map(|edge| (edge.stamp(), edge.from.clone(), edge.to.clone(), self.is_live(edge)))
+            .collect();
+        later.sort();
+        
+        for (_, from, to, _) in &later {
+            self.unlink(from, to);
+        }
+        for (_, from, to, live) in later {
+            if live && !self.would_create_cycle(&from, &to, None) {
+                self.link(&from, &to);
             }
-            
-            Ok(())
-        } else {
-            Err(AdvancedCrdtError::ElementNotFound(format!("Node {:?}", node_id)))
         }
     }
     
-    /// Check if adding an edge would create a cycle
-    fn would_create_cycle(&self, from: &PositionId, to: &PositionId) -> bool {
+    /// Check if adding an edge would create a cycle, through visible edges
+    /// stamped before `before` if given
+    fn would_create_cycle(&self, from: &PositionId, to: &PositionId, before: Option<Stamp>) -> bool {
         if from == to {
             return true;
         }
         
         // Use DFS to check for path from 'to' to 'from'
         let mut visited = HashSet::new();
-        self.dfs_cycle_check(to, from, &mut visited)
+        self.dfs_cycle_check(to, from, before, &mut visited)
     }
     
     /// DFS helper for cycle detection
-    fn dfs_cycle_check(&self, current: &PositionId, target: &PositionId, visited: &mut HashSet<PositionId>) -> bool {
+    fn dfs_cycle_check(&self, current: &PositionId, target: &PositionId, before: Option<Stamp>, visited: &mut HashSet<PositionId>) -> bool {
         if current == target {
             return true;
         }
@@ -162,7 +373,10 @@ impl<T: Clone + PartialEq> Dag<T> {
         
         if let Some(node) = self.nodes.get(current) {
             for next in &node.outgoing {
-                if self.dfs_cycle_check(next, target, visited) {
+                let older = before.is_none_or(|before| {
+                    self.edges.get(&(current.clone(), next.clone())).is_some_and(|edge| edge.stamp() < before)
+                });
+                if older && self.dfs_cycle_check(next, target, before, visited) {
                     return true;
                 }
             }
@@ -172,37 +386,34 @@ impl<T: Clone + PartialEq> Dag<T> {
     }
     
     /// Get topological sort of the DAG
+    ///
+    /// Among nodes whose dependencies are all placed, the smallest ID comes
+    /// first, so every replica with the same state gets the same order.
     pub fn topological_sort(&self) -> Vec<PositionId> {
-        let mut result = Vec::new();
-        let mut visited = HashSet::new();
-        
-        for node_id in self.nodes.keys() {
-            if !visited.contains(node_id) {
-                self.dfs_topological(node_id, &mut visited, &mut result);
+        let mut pending: HashMap<&PositionId, usize> = self.nodes.iter()
+            .map(|(id, node)| (id, node.incoming.len()))
+            .collect();
+        let mut ready: BinaryHeap<Reverse<&PositionId>> = pending.iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(id, _)| Reverse(*id))
+            .collect();
+        
+        let mut result = Vec::with_capacity(self.nodes.len());
+        while let Some(Reverse(id)) = ready.pop() {
+            result.push(id.clone());
+            for next in &self.nodes[id].outgoing {
+                if let Some(count) = pending.get_mut(next) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(Reverse(next));
+                    }
+                }
             }
         }
         
-        result.reverse();
         result
     }
     
-    /// DFS helper for topological sort
-    fn dfs_topological(&self, node_id: &PositionId, visited: &mut HashSet<PositionId>, result: &mut Vec<PositionId>) {
-        if visited.contains(node_id) {
-            return;
-        }
-        
-        visited.insert(node_id.clone());
-        
-        if let Some(node) = self.nodes.get(node_id) {
-            for next in &node.outgoing {
-                self.dfs_topological(next, visited, result);
-            }
-        }
-        
-        result.push(node_id.clone());
-    }
-    
     /// Get node count
     pub fn len(&self) -> usize {
         self.nodes.len()
@@ -229,19 +440,54 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for Dag<T> {
     type Error = AdvancedCrdtError;
     
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let mut lost = Vec::new();
+        let mut arrived = HashSet::new();
+        
         // Merge all nodes from other DAG
         for (node_id, other_node) in &other.nodes {
-            if let Some(self_node) = self.nodes.get_mut(node_id) {
-                // Node exists in both, keep the one with higher timestamp
-                if other_node.id.timestamp > self_node.id.timestamp {
-                    *self_node = other_node.clone();
+            match self.nodes.get(node_id).map(|node| node.visible) {
+                Some(true) if !other_node.visible => {
+                    // Deletions are never undone, so a deletion on either side wins
+                    lost.extend(self.linked_edges(node_id));
+                    if let Some(node) = self.nodes.get_mut(node_id) {
+                        node.visible = false;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    // Node only exists in other, add it; its edges are placed below
+                    let mut node = other_node.clone();
+                    node.incoming.clear();
+                    node.outgoing.clear();
+                    self.nodes.insert(node_id.clone(), node);
+                    arrived.insert(node_id.clone());
                 }
-            } else {
-                // Node only exists in other, add it
-                self.nodes.insert(node_id.clone(), other_node.clone());
             }
         }
         
+        // Later change to each edge wins
+        let mut candidates = HashSet::new();
+        for (key, other_edge) in &other.edges {
+            match self.edges.get(key) {
+                Some(self_edge) if self_edge.stamp() >= other_edge.stamp() => {}
+                self_edge => {
+                    if let Some(self_edge) = self_edge.filter(|edge| self.is_linked(edge)) {
+                        lost.push((self_edge.stamp(), key.0.clone(), key.1.clone()));
+                    }
+                    self.edges.insert(key.clone(), other_edge.clone());
+                    candidates.insert(key.clone());
+                }
+            }
+        }
+        // Edges waiting for a node that just arrived
+        if !arrived.is_empty() {
+            candidates.extend(self.edges.keys()
+                .filter(|(from, to)| arrived.contains(from) || arrived.contains(to))
+                .cloned());
+        }
+        
+        self.timestamp_counter = self.timestamp_counter.max(other.timestamp_counter);
+        self.relink(lost, candidates);
         Ok(())
     }
     
@@ -350,4 +596,140 @@ mod tests {
         // Should contain both nodes
         assert_eq!(dag1.len(), 2);
     }
+    
+    #[test]
+    fn test_dag_merge_breaks_concurrent_cycle() {
+        let mut dag1 = Dag::<String>::new(create_replica(1));
+        let x = dag1.add_node("x".to_string()).unwrap();
+        let y = dag1.add_node("y".to_string()).unwrap();
+        let mut dag2 = Dag::<String>::new(create_replica(2));
+        dag2.merge(&dag1).unwrap();
+        
+        // Each replica adds one direction, both pass the local cycle check
+        dag1.add_edge(&x, &y).unwrap();
+        dag2.add_edge(&y, &x).unwrap();
+        
+        let snapshot1 = dag1.clone();
+        dag1.merge(&dag2).unwrap();
+        dag2.merge(&snapshot1).unwrap();
+        
+        // Same stamp on both sides, so replica 2's edge loses the tie
+        let suppressed = dag1.suppressed_edges();
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!((&suppressed[0].from, &suppressed[0].to), (&y, &x));
+        assert_eq!(dag2.suppressed_edges(), suppressed);
+        assert_eq!(dag1.topological_sort(), vec![x.clone(), y.clone()]);
+        assert_eq!(dag2.topological_sort(), dag1.topological_sort());
+        
+        // Removing the winning edge brings the suppressed one back
+        dag1.remove_edge(&x, &y).unwrap();
+        assert!(dag1.suppressed_edges().is_empty());
+        assert_eq!(dag1.topological_sort(), vec![y.clone(), x.clone()]);
+        dag2.merge(&dag1).unwrap();
+        assert_eq!(dag2.topological_sort(), vec![y, x]);
+    }
+    
+    #[test]
+    fn test_dag_merge_three_way_cycle() {
+        let mut dag1 = Dag::<String>::new(create_replica(1));
+        let a = dag1.add_node("a".to_string()).unwrap();
+        let b = dag1.add_node("b".to_string()).unwrap();
+        let c = dag1.add_node("c".to_string()).unwrap();
+        let mut dag2 = Dag::<String>::new(create_replica(2));
+        let mut dag3 = Dag::<String>::new(create_replica(3));
+        dag2.merge(&dag1).unwrap();
+        dag3.merge(&dag1).unwrap();
+        
+        dag1.add_edge(&a, &b).unwrap();
+        dag2.add_edge(&b, &c).unwrap();
+        dag3.add_edge(&c, &a).unwrap();
+        
+        let mut merged = dag3.clone();
+        merged.merge(&dag2).unwrap();
+        merged.merge(&dag1).unwrap();
+        dag1.merge(&dag2).unwrap();
+        dag1.merge(&dag3).unwrap();
+        
+        assert_eq!(merged.topological_sort(), dag1.topological_sort());
+        assert_eq!(dag1.topological_sort(), vec![a, b, c.clone()]);
+        let suppressed = dag1.suppressed_edges();
+        assert_eq!(suppressed.len(), 1);
+        assert_eq!(suppressed[0].from, c);
+    }
+    
+    #[test]
+    fn test_dag_loads_edges_saved_without_records() {
+        let mut dag = Dag::<String>::new(create_replica(1));
+        let a = dag.add_node("a".to_string()).unwrap();
+        let b = dag.add_node("b".to_string()).unwrap();
+        let c = dag.add_node("c".to_string()).unwrap();
+        dag.add_edge(&a, &b).unwrap();
+        dag.add_edge(&b, &c).unwrap();
+        // As stored before edges had records
+        let legacy = DagState {
+            replica_id: dag.replica_id,
+            nodes: dag.nodes.clone(),
+            edges: HashMap::new(),
+            timestamp_counter: dag.timestamp_counter,
+            disambiguation_counter: dag.disambiguation_counter,
+        };
+        
+        let mut loaded = Dag::from(legacy);
+        assert_eq!(loaded.topological_sort(), vec![a.clone(), b.clone(), c.clone()]);
+        assert!(loaded.add_edge(&c, &a).is_err());
+        
+        // The edges survive a merge and can still be removed
+        let mut other = Dag::<String>::new(create_replica(2));
+        other.merge(&loaded).unwrap();
+        loaded.merge(&other).unwrap();
+        assert_eq!(other.topological_sort(), vec![a.clone(), b.clone(), c.clone()]);
+        loaded.remove_edge(&a, &b).unwrap();
+        other.merge(&loaded).unwrap();
+        assert!(other.get_nodes()[&a].outgoing.is_empty());
+        assert!(other.get_nodes()[&b].outgoing.contains(&c));
+    }
+    
+    #[test]
+    fn test_dag_merge_places_older_edge_before_newer_ones() {
+        let mut dag1 = Dag::<String>::new(create_replica(1));
+        let x = dag1.add_node("x".to_string()).unwrap();
+        let y = dag1.add_node("y".to_string()).unwrap();
+        let mut dag2 = Dag::<String>::new(create_replica(2));
+        dag2.merge(&dag1).unwrap();
+        
+        // dag2's edge is older, so it wins over dag1's newer opposite edge
+        dag2.add_edge(&y, &x).unwrap();
+        dag1.add_node("z".to_string()).unwrap();
+        dag1.add_edge(&x, &y).unwrap();
+        
+        let snapshot1 = dag1.clone();
+        dag1.merge(&dag2).unwrap();
+        dag2.merge(&snapshot1).unwrap();
+        assert_eq!(dag1.topological_sort()[..2], [y.clone(), x.clone()]);
+        assert_eq!(dag1.topological_sort(), dag2.topological_sort());
+        assert_eq!(dag1.suppressed_edges(), dag2.suppressed_edges());
+        assert_eq!(dag1.suppressed_edges()[0].from, x);
+    }
+    
+    #[test]
+    fn test_dag_merge_propagates_edge_removal_and_deletion() {
+        let mut dag1 = Dag::<String>::new(create_replica(1));
+        let a = dag1.add_node("a".to_string()).unwrap();
+        let b = dag1.add_node("b".to_string()).unwrap();
+        let c = dag1.add_node("c".to_string()).unwrap();
+        dag1.add_edge(&a, &b).unwrap();
+        dag1.add_edge(&b, &c).unwrap();
+        let mut dag2 = Dag::<String>::new(create_replica(2));
+        dag2.merge(&dag1).unwrap();
+        
+        dag1.remove_edge(&a, &b).unwrap();
+        dag2.delete_node(&c).unwrap();
+        dag1.merge(&dag2).unwrap();
+        
+        let nodes = dag1.get_nodes();
+        assert!(nodes[&a].outgoing.is_empty());
+        assert!(nodes[&b].outgoing.is_empty());
+        assert!(!nodes[&c].visible);
+        assert!(dag1.add_edge(&c, &a).is_err());
+    }
 }
@@ -1,7 +1,9 @@
 //! LSEQ (Logoot Sequence) for ordered sequences
 
-use super::common::{PositionId, AdvancedCrdtError};
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::common::{map_as_seq, PathDigit, PositionId, AdvancedCrdtError, Tombstone};
+use super::super::{CRDT, MergeReport, Mergeable, OpCrdt, OpId, Operation, ReplicaId, ReportingMerge, VersionVector};
+use super::super::cursor::{Bias, RelativePosition};
+use super::super::stability::{GarbageCollect, StabilityFrontier};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
@@ -14,6 +16,9 @@ pub struct LseqElement<T> {
     pub value: T,
     /// Whether the element is visible (not deleted)
     pub visible: bool,
+    /// When and by whom the element was deleted
+    #[serde(default)]
+    pub deleted_at: Option<Tombstone>,
 }
 
 impl<T> LseqElement<T> {
@@ -23,21 +28,128 @@ impl<T> LseqElement<T> {
             position,
             value,
             visible: true,
+            deleted_at: None,
         }
     }
 }
 
+/// Local change to an [`Lseq`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum LseqIntent<T> {
+    /// Insert a value between two neighbours; `None` stands for the start or end
+    InsertBetween { value: T, left: Option<PositionId>, right: Option<PositionId> },
+    /// Delete an element
+    Delete(PositionId),
+}
+
+/// Operation replicated by an [`Lseq`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LseqOp<T> {
+    /// Insert an element at its position
+    Insert { position: PositionId, value: T },
+    /// Delete an element
+    Delete { position: PositionId, deleted_at: Tombstone },
+}
+
+impl<T> LseqOp<T> {
+    /// Position of the element the operation inserts or deletes
+    pub fn position(&self) -> &PositionId {
+        match self {
+            LseqOp::Insert { position, .. } | LseqOp::Delete { position, .. } => position,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync> Operation for LseqOp<T> {
+    fn id(&self) -> OpId {
+        match self {
+            LseqOp::Insert { position, .. } => OpId::new(position.replica_id, position.timestamp),
+            LseqOp::Delete { deleted_at, .. } => OpId::new(deleted_at.replica_id, deleted_at.timestamp),
+        }
+    }
+}
+
+/// Base of the first path level; each deeper level doubles it
+const BASE_BITS: u32 = 5;
+
+/// Largest step taken away from a neighbour when allocating a digit
+const BOUNDARY: u64 = 10;
+
+/// Number of digits available at `depth`
+fn base(depth: usize) -> u64 {
+    1 << (BASE_BITS + depth as u32).min(62)
+}
+
+/// Allocate a path strictly between `left` and `right`
+///
+/// Missing neighbours stand for the start and end of the sequence. Digit 0 is
+/// never the last level of a path, so there is always room below any path.
+/// Levels alternate between boundary+ (allocate close to the left neighbour)
+/// and boundary- (close to the right one), which keeps paths short both for
+/// appends and for prepends.
+fn allocate_path(left: &[PathDigit], right: Option<&[PathDigit]>, replica_id: ReplicaId, timestamp: u64) -> Vec<PathDigit> {
+    let mut path = Vec::new();
+    // Whether the path so far equals the neighbour's prefix, so its digits still bound ours
+    let mut left_bound = true;
+    let mut right_bound = right.is_some();
+    for depth in 0.. {
+        let low = left.get(depth).filter(|_| left_bound);
+        let high = right.and_then(|right| right.get(depth)).filter(|_| right_bound);
+        let low_digit = low.map_or(0, |level| level.digit);
+        let high_digit = high.map_or(base(depth), |level| level.digit);
+
+        if high_digit > low_digit + 1 {
+            let step = 1 + rand::random::<u64>() % BOUNDARY.min(high_digit - low_digit - 1);
+            let digit = if depth % 2 == 0 { low_digit + step } else { high_digit - step };
+            path.push(PathDigit { digit, replica_id, timestamp });
+            return path;
+        }
+
+        // No free digit at this level, descend below a copy of a neighbour's level
+        match (low, high) {
+            (Some(low), Some(high)) => {
+                right_bound = low == high;
+                path.push(low.clone());
+            }
+            (Some(low), None) => path.push(low.clone()),
+            (None, Some(high)) if high.digit == 0 => {
+                left_bound = false;
+                path.push(high.clone());
+            }
+            _ => {
+                left_bound = false;
+                right_bound = false;
+                path.push(PathDigit { digit: 0, replica_id, timestamp });
+            }
+        }
+        if low.is_none() {
+            left_bound = false;
+        }
+    }
+    unreachable!("every level below the bounded ones has free digits")
+}
+
 /// LSEQ (Logoot Sequence) for ordered sequences
+///
+/// Every element gets a dense path between its neighbours, so the sequence is
+/// simply the elements sorted by position and never needs rebalancing. No
+/// element depends on another one staying around, so tombstones can be
+/// purged as soon as their deletion is stable.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
 pub struct Lseq<T> {
     /// Replica ID
     replica_id: ReplicaId,
     /// Elements indexed by position
+    #[serde(with = "map_as_seq")]
     elements: BTreeMap<PositionId, LseqElement<T>>,
     /// Logical timestamp counter
     timestamp_counter: u64,
     /// Disambiguation counter
     disambiguation_counter: u64,
+    /// Updates whose tombstones may have been purged
+    #[serde(default)]
+    collected: VersionVector,
 }
 
 impl<T: Clone + PartialEq> Lseq<T> {
@@ -48,33 +160,142 @@ impl<T: Clone + PartialEq> Lseq<T> {
             elements: BTreeMap::new(),
             timestamp_counter: 0,
             disambiguation_counter: 0,
+            collected: VersionVector::new(),
         }
     }
     
-    /// Insert an element at the given position
-    pub fn insert(&mut self, value: T, _position: Option<PositionId>) -> Result<PositionId, AdvancedCrdtError> {
+    /// Continue editing as `replica_id`, e.g. in a copy received from another replica
+    pub fn set_replica_id(&mut self, replica_id: ReplicaId) {
+        self.replica_id = replica_id;
+    }
+    
+    /// Insert an element after the given one, or at the end for `None`
+    pub fn insert(&mut self, value: T, after: Option<PositionId>) -> Result<PositionId, AdvancedCrdtError> {
+        let right = match &after {
+            Some(after) => {
+                self.get_existing(after)?;
+                self.elements.range(after..).nth(1).map(|(position, _)| position.clone())
+            }
+            None => None,
+        };
+        let left = match after {
+            Some(after) => Some(after),
+            None => self.elements.keys().next_back().cloned(),
+        };
+        self.insert_between(value, left, right)
+    }
+    
+    /// Insert an element between two neighbours; `None` stands for the start or end
+    pub fn insert_between(
+        &mut self,
+        value: T,
+        left: Option<PositionId>,
+        right: Option<PositionId>,
+    ) -> Result<PositionId, AdvancedCrdtError> {
+        let op = self.prepare_insert(value, left, right)?;
+        self.apply_op(&op)?;
+        Ok(op.position().clone())
+    }
+    
+    fn prepare_insert(
+        &mut self,
+        value: T,
+        left: Option<PositionId>,
+        right: Option<PositionId>,
+    ) -> Result<LseqOp<T>, AdvancedCrdtError> {
+        let left_path = match &left {
+            Some(left) => self.get_existing(left)?.path.clone(),
+            None => Vec::new(),
+        };
+        let right_path = match &right {
+            Some(right) => Some(self.get_existing(right)?.path.clone()),
+            None => None,
+        };
+        if let (Some(left), Some(right)) = (&left, &right) {
+            if left >= right {
+                return Err(AdvancedCrdtError::InvalidPosition(format!("{:?} is not before {:?}", left, right)));
+            }
+        }
+        
         self.timestamp_counter += 1;
         self.disambiguation_counter += 1;
         
-        let new_position = PositionId::new(
-            self.replica_id.clone(),
+        let path = allocate_path(&left_path, right_path.as_deref(), self.replica_id, self.timestamp_counter);
+        let new_position = PositionId::with_path(
+            self.replica_id,
             self.timestamp_counter,
             self.disambiguation_counter,
+            path,
         );
         
-        let element = LseqElement::new(new_position.clone(), value);
-        self.elements.insert(new_position.clone(), element);
-        
-        Ok(new_position)
+        Ok(LseqOp::Insert { position: new_position, value })
+    }
+    
+    /// Insert an element so that it ends up at visible index `index`
+    pub fn insert_at(&mut self, value: T, index: usize) -> Result<PositionId, AdvancedCrdtError> {
+        let mut visible = self.elements.keys().zip(self.elements.values()).filter(|(_, e)| e.visible);
+        let left = match index.checked_sub(1) {
+            Some(before) => match visible.nth(before) {
+                Some((position, _)) => Some(position.clone()),
+                None => return Err(AdvancedCrdtError::InvalidPosition(format!("Index {} beyond length", index))),
+            },
+            None => None,
+        };
+        let right = visible.next().map(|(position, _)| position.clone());
+        self.insert_between(value, left, right)
+    }
+    
+    /// Stored element for a position, erroring if it is unknown
+    fn get_existing(&self, position: &PositionId) -> Result<&PositionId, AdvancedCrdtError> {
+        self.elements.get_key_value(position)
+            .map(|(position, _)| position)
+            .ok_or_else(|| AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)))
     }
     
     /// Delete an element at the given position
     pub fn delete(&mut self, position: &PositionId) -> Result<(), AdvancedCrdtError> {
-        if let Some(element) = self.elements.get_mut(position) {
-            element.visible = false;
-            Ok(())
-        } else {
-            Err(AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)))
+        let op = self.prepare_delete(position)?;
+        self.apply_op(&op)?;
+        Ok(())
+    }
+    
+    fn prepare_delete(&mut self, position: &PositionId) -> Result<LseqOp<T>, AdvancedCrdtError> {
+        let element = self.elements.get(position)
+            .ok_or_else(|| AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)))?;
+        // Deleting twice repeats the original deletion
+        let deleted_at = match element.deleted_at {
+            Some(deleted_at) if !element.visible => deleted_at,
+            _ => {
+                self.timestamp_counter += 1;
+                Tombstone::new(self.replica_id, self.timestamp_counter)
+            }
+        };
+        Ok(LseqOp::Delete { position: position.clone(), deleted_at })
+    }
+    
+    fn apply_op(&mut self, op: &LseqOp<T>) -> Result<bool, AdvancedCrdtError> {
+        match op {
+            LseqOp::Insert { position, value } => {
+                self.timestamp_counter = self.timestamp_counter.max(position.timestamp);
+                if self.elements.contains_key(position) || self.collected.includes(&position.replica_id, position.timestamp) {
+                    return Ok(false);
+                }
+                self.elements.insert(position.clone(), LseqElement::new(position.clone(), value.clone()));
+                Ok(true)
+            }
+            LseqOp::Delete { position, deleted_at } => {
+                self.timestamp_counter = self.timestamp_counter.max(deleted_at.timestamp);
+                let Some(element) = self.elements.get_mut(position) else {
+                    if self.collected.includes(&deleted_at.replica_id, deleted_at.timestamp) {
+                        return Ok(false);
+                    }
+                    return Err(AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)));
+                };
+                element.deleted_at = element.deleted_at.max(Some(*deleted_at));
+                let deleted = element.visible;
+                element.visible = false;
+                Ok(deleted)
+            }
         }
     }
     
@@ -86,6 +307,41 @@ impl<T: Clone + PartialEq> Lseq<T> {
             .collect()
     }
     
+    /// Positions of the visible elements in order
+    pub fn positions(&self) -> impl Iterator<Item = &PositionId> + '_ {
+        self.elements.values()
+            .filter(|e| e.visible)
+            .map(|e| &e.position)
+    }
+    
+    /// Anchor the gap in front of visible index `index` so it survives remote edits
+    pub fn relative_position(&self, index: usize, bias: Bias) -> Result<RelativePosition<PositionId>, AdvancedCrdtError> {
+        RelativePosition::at(self.order(), index, bias)
+            .ok_or_else(|| AdvancedCrdtError::InvalidPosition(format!("Index {} beyond length", index)))
+    }
+    
+    /// Current index of a relative position, or `None` if its element is unknown here
+    pub fn resolve_position(&self, position: &RelativePosition<PositionId>) -> Option<usize> {
+        position.resolve(self.order())
+    }
+    
+    /// All positions in document order with their visibility
+    fn order(&self) -> impl Iterator<Item = (&PositionId, bool)> {
+        self.elements.values().map(|e| (&e.position, e.visible))
+    }
+    
+    /// Latest insert or deletion seen from each replica
+    pub fn version_vector(&self) -> VersionVector {
+        let mut version = self.collected.clone();
+        for element in self.elements.values() {
+            version.observe(element.position.replica_id, element.position.timestamp);
+            if let Some(tombstone) = element.deleted_at {
+                version.observe(tombstone.replica_id, tombstone.timestamp);
+            }
+        }
+        version
+    }
+    
     /// Get element count
     pub fn len(&self) -> usize {
         self.elements.len()
@@ -112,32 +368,86 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for Lseq<T> {
     type Error = AdvancedCrdtError;
     
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
+    }
+    
+    fn has_conflict(&self, other: &Self) -> bool {
+        // Check for conflicting elements (same position, different values)
+        for (position, self_element) in &self.elements {
+            if let Some(other_element) = other.elements.get(position) {
+                if self_element.value != other_element.value {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync> ReportingMerge for Lseq<T> {
+    type Key = PositionId;
+    
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<PositionId>, Self::Error> {
+        let mut report = MergeReport::new();
         // Merge all elements from other LSEQ
         for (position, other_element) in &other.elements {
             if let Some(self_element) = self.elements.get_mut(position) {
+                let was_visible = self_element.visible;
+                let mut changed = false;
                 // Element exists in both, keep the one with higher timestamp
                 if other_element.position.timestamp > self_element.position.timestamp {
+                    changed = self_element.value != other_element.value;
                     *self_element = other_element.clone();
                 }
+                // Deletions are never undone, so a tombstone on either side wins
+                self_element.visible &= other_element.visible;
+                self_element.deleted_at = self_element.deleted_at.max(other_element.deleted_at);
+                report.record(position.clone(), was_visible, self_element.visible, changed);
+            } else if other_element.deleted_at
+                .is_some_and(|tombstone| self.collected.includes(&tombstone.replica_id, tombstone.timestamp))
+            {
+                // Purged here already
+                continue;
             } else {
                 // Element only exists in other, add it
                 self.elements.insert(position.clone(), other_element.clone());
+                report.record(position.clone(), false, other_element.visible, true);
             }
+            let deleted_at = other_element.deleted_at.map_or(0, |tombstone| tombstone.timestamp);
+            self.timestamp_counter = self.timestamp_counter.max(position.timestamp).max(deleted_at);
         }
         
-        Ok(())
+        Ok(report)
     }
+}
+
+impl<T: Clone + PartialEq + Send + Sync> OpCrdt for Lseq<T> {
+    type Intent = LseqIntent<T>;
+    type Op = LseqOp<T>;
+    type Error = AdvancedCrdtError;
     
-    fn has_conflict(&self, other: &Self) -> bool {
-        // Check for conflicting elements (same position, different values)
-        for (position, self_element) in &self.elements {
-            if let Some(other_element) = other.elements.get(position) {
-                if self_element.value != other_element.value {
-                    return true;
-                }
-            }
+    fn prepare(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error> {
+        match intent {
+            LseqIntent::InsertBetween { value, left, right } => self.prepare_insert(value, left, right),
+            LseqIntent::Delete(position) => self.prepare_delete(&position),
         }
-        false
+    }
+    
+    fn apply(&mut self, op: &Self::Op) -> Result<bool, Self::Error> {
+        self.apply_op(op)
+    }
+}
+
+impl<T: Clone + PartialEq> GarbageCollect for Lseq<T> {
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let frontier = frontier.meet(&self.version_vector());
+        let before = self.elements.len();
+        self.elements.retain(|_, element| {
+            !element.deleted_at
+                .is_some_and(|tombstone| frontier.is_stable(&tombstone.replica_id, tombstone.timestamp))
+        });
+        self.collected.merge(frontier.version_vector());
+        before - self.elements.len()
     }
 }
 
@@ -205,4 +515,136 @@ mod tests {
         assert!(elements.contains(&"hello".to_string()));
         assert!(elements.contains(&"world".to_string()));
     }
+
+    #[test]
+    fn test_lseq_merge_with_changes() {
+        let mut lseq1 = Lseq::<String>::new(create_replica(1));
+        let hello = lseq1.insert("hello".to_string(), None).unwrap();
+        let mut lseq2 = Lseq::<String>::new(create_replica(2));
+        lseq2.merge(&lseq1).unwrap();
+
+        let world = lseq2.insert("world".to_string(), Some(hello.clone())).unwrap();
+        lseq2.delete(&hello).unwrap();
+
+        let report = lseq1.merge_with_changes(&lseq2).unwrap();
+        assert_eq!(report.inserted, vec![world]);
+        assert_eq!(report.removed, vec![hello]);
+        assert!(lseq1.merge_with_changes(&lseq2).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_lseq_relative_position() {
+        let mut lseq = Lseq::<String>::new(create_replica(1));
+        lseq.insert("a".to_string(), None).unwrap();
+        let b = lseq.insert("b".to_string(), None).unwrap();
+        lseq.insert("c".to_string(), None).unwrap();
+        
+        let after_b = lseq.relative_position(2, Bias::After).unwrap();
+        let end = lseq.relative_position(3, Bias::Before).unwrap();
+        assert_eq!(end, RelativePosition::End);
+        
+        lseq.delete(&b).unwrap();
+        assert_eq!(lseq.resolve_position(&after_b), Some(1));
+        assert_eq!(lseq.resolve_position(&end), Some(2));
+    }
+    
+    #[test]
+    fn test_lseq_insert_honours_position() {
+        let mut lseq = Lseq::<String>::new(create_replica(1));
+        let a = lseq.insert("a".to_string(), None).unwrap();
+        let c = lseq.insert("c".to_string(), None).unwrap();
+        lseq.insert("b".to_string(), Some(a.clone())).unwrap();
+        lseq.insert_at("start".to_string(), 0).unwrap();
+        lseq.insert_at("end".to_string(), 4).unwrap();
+        
+        assert_eq!(lseq.to_vec(), vec!["start", "a", "b", "c", "end"]);
+        assert!(lseq.insert_between("x".to_string(), Some(c), Some(a)).is_err());
+        assert!(lseq.insert_at("x".to_string(), 7).is_err());
+    }
+    
+    #[test]
+    fn test_lseq_dense_between_neighbours() {
+        let mut lseq = Lseq::<u32>::new(create_replica(1));
+        let left = lseq.insert(0, None).unwrap();
+        let mut right = lseq.insert(u32::MAX, None).unwrap();
+        
+        // Keep squeezing new items right after `left`
+        for value in (1..=500).rev() {
+            right = lseq.insert_between(value, Some(left.clone()), Some(right)).unwrap();
+        }
+        
+        let values = lseq.to_vec();
+        assert_eq!(values.len(), 502);
+        assert!(values[..501].windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(right.path.len() < 40);
+    }
+    
+    #[test]
+    fn test_lseq_concurrent_inserts_at_same_index_converge() {
+        let mut lseq1 = Lseq::<String>::new(create_replica(1));
+        lseq1.insert("a".to_string(), None).unwrap();
+        lseq1.insert("d".to_string(), None).unwrap();
+        let mut lseq2 = Lseq::<String>::new(create_replica(2));
+        lseq2.merge(&lseq1).unwrap();
+        
+        lseq1.insert_at("b".to_string(), 1).unwrap();
+        lseq2.insert_at("c".to_string(), 1).unwrap();
+        
+        let mut merged1 = lseq1.clone();
+        merged1.merge(&lseq2).unwrap();
+        lseq2.merge(&lseq1).unwrap();
+        
+        let values = merged1.to_vec();
+        assert_eq!(values, lseq2.to_vec());
+        assert_eq!(values.first().map(String::as_str), Some("a"));
+        assert_eq!(values.last().map(String::as_str), Some("d"));
+        assert_eq!(values.len(), 4);
+    }
+    
+    #[test]
+    fn test_lseq_gc_purges_stable_tombstones() {
+        let mut lseq1 = Lseq::<String>::new(create_replica(1));
+        let a = lseq1.insert("a".to_string(), None).unwrap();
+        let b = lseq1.insert("b".to_string(), Some(a.clone())).unwrap();
+        let mut lseq2 = Lseq::<String>::new(create_replica(2));
+        lseq2.merge(&lseq1).unwrap();
+        
+        lseq1.delete(&a).unwrap();
+        assert_eq!(lseq1.gc(&StabilityFrontier::new(lseq2.version_vector())), 0);
+        
+        lseq2.merge(&lseq1).unwrap();
+        assert_eq!(lseq1.gc(&StabilityFrontier::new(lseq2.version_vector())), 1);
+        assert_eq!(lseq1.len(), 1);
+        
+        // The purged tombstone stays gone, and inserting around it still works
+        lseq1.merge(&lseq2).unwrap();
+        assert_eq!(lseq1.len(), 1);
+        lseq1.insert_between("c".to_string(), None, Some(b)).unwrap();
+        assert_eq!(lseq1.to_vec(), vec!["c", "b"]);
+    }
+    
+    #[test]
+    fn test_lseq_ops_converge_when_delivered_twice() {
+        let mut lseq1 = Lseq::<String>::new(create_replica(1));
+        let mut lseq2 = Lseq::<String>::new(create_replica(2));
+        
+        let a = lseq1.perform(LseqIntent::InsertBetween { value: "a".to_string(), left: None, right: None }).unwrap();
+        let c = lseq1.perform(LseqIntent::InsertBetween { value: "c".to_string(), left: Some(a.position().clone()), right: None }).unwrap();
+        for op in [&c, &a, &c] {
+            lseq2.apply(op).unwrap();
+        }
+        
+        let b = lseq2.perform(LseqIntent::InsertBetween {
+            value: "b".to_string(),
+            left: Some(a.position().clone()),
+            right: Some(c.position().clone()),
+        }).unwrap();
+        let delete = lseq1.perform(LseqIntent::Delete(a.position().clone())).unwrap();
+        assert!(lseq1.apply(&b).unwrap());
+        assert!(lseq2.apply(&delete).unwrap());
+        assert!(!lseq2.apply(&delete).unwrap());
+        
+        assert_eq!(lseq1.to_vec(), vec!["b", "c"]);
+        assert_eq!(lseq1.to_vec(), lseq2.to_vec());
+    }
 }
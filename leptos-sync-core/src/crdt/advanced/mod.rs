@@ -2,22 +2,31 @@
 //!
 //! This module provides advanced CRDT implementations including:
 //! - RGA (Replicated Growable Array) for collaborative text editing
+//! - TextCrdt, run-length-encoded plain text ordered like RGA
+//! - RichText, Peritext-style formatting marks over TextCrdt
 //! - LSEQ (Logoot Sequence) for ordered sequences
 //! - Yjs-style trees for hierarchical data
 //! - DAG (Directed Acyclic Graph) for complex relationships
 
 pub mod common;
+mod order_tree;
 pub mod rga;
+pub mod text;
+pub mod rich_text;
 pub mod lseq;
 pub mod yjs_tree;
 pub mod dag;
 
 // Re-export main types for convenience
-pub use common::{PositionId, AdvancedCrdtError};
-pub use rga::{Rga, RgaElement};
-pub use lseq::{Lseq, LseqElement};
+pub use common::{PathDigit, PositionId, Tombstone, AdvancedCrdtError};
+pub use rga::{Rga, RgaChange, RgaElement, RgaIntent, RgaOp};
+pub use text::{TextChange, TextCrdt};
+pub use rich_text::{
+    ExpandRule, FormattedSpan, FormattingMarks, MarkAction, MarkAnchor, MarkOp, MarkType, RichText,
+};
+pub use lseq::{Lseq, LseqElement, LseqIntent, LseqOp};
 pub use yjs_tree::{YjsTree, YjsNode, YjsTreeNode};
-pub use dag::{Dag, DagNode};
+pub use dag::{Dag, DagEdge, DagNode};
 
 #[cfg(test)]
 mod integration_tests {
@@ -0,0 +1,378 @@
+//! Order-statistic tree holding the linear order of a sequence CRDT
+//!
+//! An implicit treap: nodes are ordered by their place in the sequence rather
+//! than by key, and every node counts the elements and the visible elements in
+//! its subtree. Inserting at an index, looking up the index of a key and picking
+//! the n-th visible element all take O(log n) expected time.
+//!
+//! A node may also stand for a run of elements, such as a block of text, in
+//! which case it counts as wide as the run towards the visible elements.
+
+use serde::ser::SerializeSeq;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+
+#[derive(Clone)]
+struct Node<K> {
+    key: K,
+    visible: bool,
+    /// Elements in the run this node stands for
+    width: usize,
+    priority: u64,
+    left: Option<usize>,
+    right: Option<usize>,
+    parent: Option<usize>,
+    /// Elements in the subtree, this one included
+    size: usize,
+    /// Visible elements in the subtree, runs counted in full
+    visible_size: usize,
+}
+
+/// Sequence of keys, deleted ones included, with O(log n) indexed access
+#[derive(Clone)]
+pub(crate) struct OrderTree<K> {
+    nodes: Vec<Node<K>>,
+    root: Option<usize>,
+    slots: HashMap<K, usize>,
+    /// State of the xorshift generator used for node priorities
+    seed: u64,
+}
+
+impl<K: Clone + Eq + Hash> OrderTree<K> {
+    pub(crate) fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: None,
+            slots: HashMap::new(),
+            seed: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+
+    /// Number of elements, deleted ones included
+    pub(crate) fn len(&self) -> usize {
+        self.size(self.root)
+    }
+
+    /// Number of visible elements
+    pub(crate) fn visible_len(&self) -> usize {
+        self.visible_size(self.root)
+    }
+
+    /// Insert `key` so that it ends up at index `rank` among all elements
+    pub(crate) fn insert(&mut self, rank: usize, key: K, visible: bool) {
+        self.insert_run(rank, key, visible, 1);
+    }
+
+    /// Insert `key` at index `rank` as a run of `width` elements
+    ///
+    /// Widths only count towards visible ranks and are not serialized, so
+    /// owners of runs rebuild the tree when they load.
+    pub(crate) fn insert_run(&mut self, rank: usize, key: K, visible: bool, width: usize) {
+        let node = self.nodes.len();
+        let priority = self.next_priority();
+        self.nodes.push(Node {
+            key: key.clone(),
+            visible,
+            width,
+            priority,
+            left: None,
+            right: None,
+            parent: None,
+            size: 1,
+            visible_size: if visible { width } else { 0 },
+        });
+        self.slots.insert(key, node);
+
+        let (before, after) = self.split(self.root, rank);
+        let joined = self.join(before, Some(node));
+        self.root = self.join(joined, after);
+        if let Some(root) = self.root {
+            self.nodes[root].parent = None;
+        }
+    }
+
+    /// Show or hide an element, returning `false` if the key is unknown
+    pub(crate) fn set_visible(&mut self, key: &K, visible: bool) -> bool {
+        let Some(&node) = self.slots.get(key) else {
+            return false;
+        };
+        self.nodes[node].visible = visible;
+        self.update_path(node);
+        true
+    }
+
+    /// Change the width of a run, returning `false` if the key is unknown
+    pub(crate) fn set_width(&mut self, key: &K, width: usize) -> bool {
+        let Some(&node) = self.slots.get(key) else {
+            return false;
+        };
+        self.nodes[node].width = width;
+        self.update_path(node);
+        true
+    }
+
+    /// Index of a key among all elements
+    pub(crate) fn rank(&self, key: &K) -> Option<usize> {
+        self.rank_by(key, |tree, node| tree.size(node), |_| 1)
+    }
+
+    /// Number of visible elements before a key
+    pub(crate) fn visible_rank(&self, key: &K) -> Option<usize> {
+        self.rank_by(key, |tree, node| tree.visible_size(node), Node::visible_width)
+    }
+
+    /// Element at index `rank` among all elements, with its visibility
+    pub(crate) fn get(&self, rank: usize) -> Option<(&K, bool)> {
+        self.select(rank, |tree, node| tree.size(node), |_| 1)
+            .map(|(node, _)| (&self.nodes[node].key, self.nodes[node].visible))
+    }
+
+    /// Visible element at index `index`
+    pub(crate) fn get_visible(&self, index: usize) -> Option<&K> {
+        self.locate_visible(index).map(|(key, _)| key)
+    }
+
+    /// Visible run holding index `index`, with the offset of the index in it
+    pub(crate) fn locate_visible(&self, index: usize) -> Option<(&K, usize)> {
+        self.select(index, |tree, node| tree.visible_size(node), Node::visible_width)
+            .map(|(node, offset)| (&self.nodes[node].key, offset))
+    }
+
+    /// All elements in order, with their visibility
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, bool)> + '_ {
+        let mut stack = Vec::new();
+        let mut current = self.root;
+        std::iter::from_fn(move || {
+            while let Some(node) = current {
+                stack.push(node);
+                current = self.nodes[node].left;
+            }
+            let node = stack.pop()?;
+            current = self.nodes[node].right;
+            Some((&self.nodes[node].key, self.nodes[node].visible))
+        })
+    }
+
+    fn size(&self, node: Option<usize>) -> usize {
+        node.map_or(0, |node| self.nodes[node].size)
+    }
+
+    fn visible_size(&self, node: Option<usize>) -> usize {
+        node.map_or(0, |node| self.nodes[node].visible_size)
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        self.seed
+    }
+
+    /// Recompute the counts of a node and everything above it
+    fn update_path(&mut self, node: usize) {
+        let mut current = Some(node);
+        while let Some(node) = current {
+            self.update(node);
+            current = self.nodes[node].parent;
+        }
+    }
+
+    /// Recompute the counts of a node and adopt its children
+    fn update(&mut self, node: usize) {
+        let (left, right) = (self.nodes[node].left, self.nodes[node].right);
+        for child in [left, right].into_iter().flatten() {
+            self.nodes[child].parent = Some(node);
+        }
+        let visible = self.nodes[node].visible_width();
+        self.nodes[node].size = self.size(left) + 1 + self.size(right);
+        self.nodes[node].visible_size = self.visible_size(left) + visible + self.visible_size(right);
+    }
+
+    /// Split a subtree into its first `rank` elements and the rest
+    fn split(&mut self, node: Option<usize>, rank: usize) -> (Option<usize>, Option<usize>) {
+        let Some(node) = node else {
+            return (None, None);
+        };
+        let left_size = self.size(self.nodes[node].left);
+        if rank <= left_size {
+            let (before, after) = self.split(self.nodes[node].left, rank);
+            self.nodes[node].left = after;
+            self.update(node);
+            (before, Some(node))
+        } else {
+            let (before, after) = self.split(self.nodes[node].right, rank - left_size - 1);
+            self.nodes[node].right = before;
+            self.update(node);
+            (Some(node), after)
+        }
+    }
+
+    /// Concatenate two subtrees
+    fn join(&mut self, left: Option<usize>, right: Option<usize>) -> Option<usize> {
+        match (left, right) {
+            (None, node) | (node, None) => node,
+            (Some(left), Some(right)) => {
+                if self.nodes[left].priority > self.nodes[right].priority {
+                    let joined = self.join(self.nodes[left].right, Some(right));
+                    self.nodes[left].right = joined;
+                    self.update(left);
+                    Some(left)
+                } else {
+                    let joined = self.join(Some(left), self.nodes[right].left);
+                    self.nodes[right].left = joined;
+                    self.update(right);
+                    Some(right)
+                }
+            }
+        }
+    }
+
+    fn rank_by<S, W>(&self, key: &K, size: S, weight: W) -> Option<usize>
+    where
+        S: Fn(&Self, Option<usize>) -> usize,
+        W: Fn(&Node<K>) -> usize,
+    {
+        let mut node = *self.slots.get(key)?;
+        let mut rank = size(self, self.nodes[node].left);
+        while let Some(parent) = self.nodes[node].parent {
+            if self.nodes[parent].right == Some(node) {
+                rank += size(self, self.nodes[parent].left) + weight(&self.nodes[parent]);
+            }
+            node = parent;
+        }
+        Some(rank)
+    }
+
+    /// Node holding `rank`, and how far into the node's own weight it lies
+    fn select<S, W>(&self, mut rank: usize, size: S, weight: W) -> Option<(usize, usize)>
+    where
+        S: Fn(&Self, Option<usize>) -> usize,
+        W: Fn(&Node<K>) -> usize,
+    {
+        let mut current = self.root;
+        while let Some(node) = current {
+            let left_size = size(self, self.nodes[node].left);
+            let own = weight(&self.nodes[node]);
+            if rank < left_size {
+                current = self.nodes[node].left;
+            } else if rank < left_size + own {
+                return Some((node, rank - left_size));
+            } else {
+                rank -= left_size + own;
+                current = self.nodes[node].right;
+            }
+        }
+        None
+    }
+}
+
+impl<K> Node<K> {
+    fn visible_width(&self) -> usize {
+        if self.visible { self.width } else { 0 }
+    }
+}
+
+impl<K: Clone + Eq + Hash> Default for OrderTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Clone + Eq + Hash> PartialEq for OrderTree<K> {
+    fn eq(&self, other: &Self) -> bool {
+        // The shape depends on insertion history, only the sequence matters
+        self.iter().eq(other.iter())
+    }
+}
+
+impl<K: Clone + Eq + Hash + fmt::Debug> fmt::Debug for OrderTree<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Clone + Eq + Hash + Serialize> Serialize for OrderTree<K> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // The iterator has no size hint, which formats like bincode need up front
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for entry in self.iter() {
+            seq.serialize_element(&entry)?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de, K: Clone + Eq + Hash + Deserialize<'de>> Deserialize<'de> for OrderTree<K> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<(K, bool)> = Vec::deserialize(deserializer)?;
+        let mut tree = Self::new();
+        for (rank, (key, visible)) in entries.into_iter().enumerate() {
+            tree.insert(rank, key, visible);
+        }
+        Ok(tree)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_indexed_access() {
+        let mut tree = OrderTree::new();
+        for (rank, key) in [(0, 'b'), (0, 'a'), (2, 'd'), (2, 'c')] {
+            tree.insert(rank, key, true);
+        }
+        tree.set_visible(&'b', false);
+
+        let keys: Vec<_> = tree.iter().collect();
+        assert_eq!(keys, vec![(&'a', true), (&'b', false), (&'c', true), (&'d', true)]);
+        assert_eq!(tree.len(), 4);
+        assert_eq!(tree.rank(&'c'), Some(2));
+        assert_eq!(tree.visible_rank(&'c'), Some(1));
+        assert_eq!(tree.get(1), Some((&'b', false)));
+        assert_eq!(tree.get_visible(1), Some(&'c'));
+        assert_eq!(tree.get_visible(3), None);
+    }
+
+    #[test]
+    fn test_large_sequence_matches_vec() {
+        let mut tree = OrderTree::new();
+        let mut expected = Vec::new();
+        for key in 0..1000usize {
+            let rank = (key * 7919) % (expected.len() + 1);
+            tree.insert(rank, key, key % 3 != 0);
+            expected.insert(rank, key);
+        }
+
+        let keys: Vec<_> = tree.iter().map(|(key, _)| *key).collect();
+        assert_eq!(keys, expected);
+        for (rank, key) in expected.iter().enumerate() {
+            assert_eq!(tree.rank(key), Some(rank));
+        }
+        let visible: Vec<_> = expected.iter().filter(|key| *key % 3 != 0).collect();
+        assert_eq!(tree.get_visible(100), Some(visible[100]));
+    }
+
+    #[test]
+    fn test_runs_count_in_full_towards_visible_ranks() {
+        let mut tree = OrderTree::new();
+        tree.insert_run(0, 'a', true, 5);
+        tree.insert_run(1, 'b', false, 3);
+        tree.insert_run(2, 'c', true, 4);
+
+        assert_eq!(tree.len(), 3);
+        assert_eq!(tree.visible_len(), 9);
+        assert_eq!(tree.visible_rank(&'c'), Some(5));
+        assert_eq!(tree.locate_visible(4), Some((&'a', 4)));
+        assert_eq!(tree.locate_visible(6), Some((&'c', 1)));
+        assert_eq!(tree.locate_visible(9), None);
+
+        tree.set_width(&'a', 2);
+        tree.set_visible(&'b', true);
+        assert_eq!(tree.visible_len(), 9);
+        assert_eq!(tree.locate_visible(4), Some((&'b', 2)));
+    }
+}
@@ -1,9 +1,13 @@
 //! RGA (Replicated Growable Array) for collaborative text editing
 
-use super::common::{PositionId, AdvancedCrdtError};
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::common::{map_as_seq, PositionId, AdvancedCrdtError, Tombstone};
+use super::order_tree::OrderTree;
+use super::super::{CRDT, MergeReport, Mergeable, OpCrdt, OpId, Operation, ReplicaId, ReportingMerge, Reset, VersionVector};
+use super::super::cursor::{Bias, RelativePosition};
+use super::super::stability::{GarbageCollect, StabilityFrontier};
+use super::super::undo::{IdMap, Reverted, Undoable};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// RGA (Replicated Growable Array) element
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -16,6 +20,9 @@ pub struct RgaElement<T> {
     pub visible: bool,
     /// Reference to previous element
     pub prev: Option<PositionId>,
+    /// When and by whom the element was deleted
+    #[serde(default)]
+    pub deleted_at: Option<Tombstone>,
 }
 
 impl<T> RgaElement<T> {
@@ -26,21 +33,84 @@ impl<T> RgaElement<T> {
             value,
             visible: true,
             prev,
+            deleted_at: None,
         }
     }
 }
 
+/// Local change to an [`Rga`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum RgaIntent<T> {
+    /// Insert a value after an element, or at the start for `None`
+    InsertAfter { value: T, after: Option<PositionId> },
+    /// Delete an element
+    Delete(PositionId),
+}
+
+/// Operation replicated by an [`Rga`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RgaOp<T> {
+    /// Insert an element right after `prev`, or at the start for `None`
+    Insert { position: PositionId, value: T, prev: Option<PositionId> },
+    /// Delete an element
+    Delete { position: PositionId, deleted_at: Tombstone },
+}
+
+/// Local change to an [`Rga`] recorded for undo
+#[derive(Debug, Clone, PartialEq)]
+pub enum RgaChange<T> {
+    /// An element was inserted
+    Inserted(PositionId),
+    /// An element was deleted, with the value to bring back
+    Deleted { position: PositionId, value: T, deleted_at: Tombstone },
+}
+
+impl<T> RgaOp<T> {
+    /// Position of the element the operation inserts or deletes
+    pub fn position(&self) -> &PositionId {
+        match self {
+            RgaOp::Insert { position, .. } | RgaOp::Delete { position, .. } => position,
+        }
+    }
+}
+
+impl<T: Clone + Send + Sync> Operation for RgaOp<T> {
+    fn id(&self) -> OpId {
+        match self {
+            RgaOp::Insert { position, .. } => OpId::new(position.replica_id, position.timestamp),
+            RgaOp::Delete { deleted_at, .. } => OpId::new(deleted_at.replica_id, deleted_at.timestamp),
+        }
+    }
+}
+
+/// Whether `a` is ordered before `b` when both were inserted after the same element
+fn is_newer(a: &PositionId, b: &PositionId) -> bool {
+    (a.timestamp, a.replica_id) > (b.timestamp, b.replica_id)
+}
+
 /// RGA (Replicated Growable Array) for collaborative text editing
+///
+/// Each element is placed right after the element it was inserted after, with
+/// concurrent inserts at the same spot ordered newest first. Deleted elements
+/// stay in place as tombstones so that concurrent inserts can still anchor to
+/// them, until [`GarbageCollect::gc`] finds their deletion stable.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>"))]
 pub struct Rga<T> {
     /// Replica ID
     replica_id: ReplicaId,
     /// Elements indexed by position
+    #[serde(with = "map_as_seq")]
     elements: HashMap<PositionId, RgaElement<T>>,
-    /// Logical timestamp counter
+    /// Document order of all positions, tombstones included
+    order: OrderTree<PositionId>,
+    /// Logical timestamp counter, kept ahead of every timestamp seen
     timestamp_counter: u64,
     /// Disambiguation counter
     disambiguation_counter: u64,
+    /// Updates whose tombstones may have been purged
+    #[serde(default)]
+    collected: VersionVector,
 }
 
 impl<T: Clone + PartialEq> Rga<T> {
@@ -49,83 +119,200 @@ impl<T: Clone + PartialEq> Rga<T> {
         Self {
             replica_id,
             elements: HashMap::new(),
+            order: OrderTree::new(),
             timestamp_counter: 0,
             disambiguation_counter: 0,
+            collected: VersionVector::new(),
         }
     }
     
-    /// Insert an element after the given position
+    /// Continue editing as `replica_id`, e.g. in a copy received from another replica
+    pub fn set_replica_id(&mut self, replica_id: ReplicaId) {
+        self.replica_id = replica_id;
+    }
+    
+    /// Insert an element after the given position, or at the start for `None`
+    ///
+    /// The position may be a tombstone; garbage collection keeps tombstones
+    /// that elements were inserted after.
     pub fn insert_after(&mut self, value: T, after: Option<PositionId>) -> Result<PositionId, AdvancedCrdtError> {
+        let op = self.prepare_insert(value, after)?;
+        self.apply_op(&op)?;
+        Ok(op.position().clone())
+    }
+    
+    /// Delete an element at the given position
+    pub fn delete(&mut self, position: &PositionId) -> Result<(), AdvancedCrdtError> {
+        let op = self.prepare_delete(position)?;
+        self.apply_op(&op)?;
+        Ok(())
+    }
+    
+    fn prepare_insert(&mut self, value: T, after: Option<PositionId>) -> Result<RgaOp<T>, AdvancedCrdtError> {
+        if let Some(after) = &after {
+            if !self.elements.contains_key(after) {
+                return Err(AdvancedCrdtError::ElementNotFound(format!("Position {:?}", after)));
+            }
+        }
+        
         self.timestamp_counter += 1;
         self.disambiguation_counter += 1;
         
         let position = PositionId::new(
-            self.replica_id.clone(),
+            self.replica_id,
             self.timestamp_counter,
             self.disambiguation_counter,
         );
         
-        let element = RgaElement::new(position.clone(), value, after);
-        self.elements.insert(position.clone(), element);
-        
-        Ok(position)
+        Ok(RgaOp::Insert { position, value, prev: after })
     }
     
-    /// Delete an element at the given position
-    pub fn delete(&mut self, position: &PositionId) -> Result<(), AdvancedCrdtError> {
-        if let Some(element) = self.elements.get_mut(position) {
-            element.visible = false;
-            Ok(())
-        } else {
-            Err(AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)))
+    fn prepare_delete(&mut self, position: &PositionId) -> Result<RgaOp<T>, AdvancedCrdtError> {
+        let element = self.elements.get(position)
+            .ok_or_else(|| AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)))?;
+        // Deleting twice repeats the original deletion
+        let deleted_at = match element.deleted_at {
+            Some(deleted_at) if !element.visible => deleted_at,
+            _ => {
+                self.timestamp_counter += 1;
+                Tombstone::new(self.replica_id, self.timestamp_counter)
+            }
+        };
+        Ok(RgaOp::Delete { position: position.clone(), deleted_at })
+    }
+    
+    fn apply_op(&mut self, op: &RgaOp<T>) -> Result<bool, AdvancedCrdtError> {
+        match op {
+            RgaOp::Insert { position, value, prev } => {
+                self.timestamp_counter = self.timestamp_counter.max(position.timestamp);
+                if self.elements.contains_key(position) || self.collected.includes(&position.replica_id, position.timestamp) {
+                    return Ok(false);
+                }
+                self.integrate(RgaElement::new(position.clone(), value.clone(), prev.clone()))?;
+                Ok(true)
+            }
+            RgaOp::Delete { position, deleted_at } => {
+                self.timestamp_counter = self.timestamp_counter.max(deleted_at.timestamp);
+                let Some(element) = self.elements.get_mut(position) else {
+                    if self.collected.includes(&deleted_at.replica_id, deleted_at.timestamp) {
+                        return Ok(false);
+                    }
+                    return Err(AdvancedCrdtError::ElementNotFound(format!("Position {:?}", position)));
+                };
+                element.deleted_at = element.deleted_at.max(Some(*deleted_at));
+                if !element.visible {
+                    return Ok(false);
+                }
+                element.visible = false;
+                self.order.set_visible(position, false);
+                Ok(true)
+            }
         }
     }
     
     /// Get the visible elements in order
     pub fn to_vec(&self) -> Vec<T> {
-        let mut result = Vec::new();
-        
-        // For RGA, we need to handle multiple root elements (elements with prev: None)
-        // We'll collect all visible elements and sort them by position
-        let mut elements: Vec<_> = self.elements.values()
-            .filter(|e| e.visible)
-            .collect();
-        
-        // Sort by position (replica_id, timestamp, disambiguation)
-        elements.sort_by(|a, b| a.position.cmp(&b.position));
-        
-        // Add values to result
-        for element in elements {
-            result.push(element.value.clone());
-        }
-        
-        result
+        self.order.iter()
+            .filter(|(_, visible)| *visible)
+            .map(|(position, _)| self.elements[position].value.clone())
+            .collect()
+    }
+    
+    /// Get the visible element at `index`
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.order.get_visible(index).map(|position| &self.elements[position].value)
+    }
+    
+    /// Position of the visible element at `index`
+    pub fn position_at(&self, index: usize) -> Option<PositionId> {
+        self.order.get_visible(index).cloned()
+    }
+    
+    /// Element at `position`, deleted or not
+    pub fn element(&self, position: &PositionId) -> Option<&RgaElement<T>> {
+        self.elements.get(position)
+    }
+    
+    /// Positions of all elements in order, tombstones included, with the values of the visible ones
+    pub fn entries(&self) -> impl Iterator<Item = (&PositionId, Option<&T>)> + '_ {
+        self.order.iter()
+            .map(|(position, visible)| (position, visible.then(|| &self.elements[position].value)))
+    }
+    
+    /// Positions of the visible elements in order
+    pub fn positions(&self) -> impl Iterator<Item = &PositionId> + '_ {
+        self.order.iter()
+            .filter(|(_, visible)| *visible)
+            .map(|(position, _)| position)
     }
     
-    /// Find the first element in the sequence
-    fn find_first_element(&self) -> Option<PositionId> {
-        // Find element with no previous element
-        self.elements.values()
-            .find(|e| e.prev.is_none())
-            .map(|e| e.position.clone())
+    /// Index of a visible element, or `None` if it is deleted or unknown
+    pub fn index_of(&self, position: &PositionId) -> Option<usize> {
+        let visible = self.elements.get(position)?.visible;
+        visible.then(|| self.order.visible_rank(position)).flatten()
     }
     
-    /// Find the next element after the given position
-    fn find_next_element(&self, position: &PositionId) -> Option<PositionId> {
-        self.elements.values()
-            .find(|e| e.prev.as_ref() == Some(position))
-            .map(|e| e.position.clone())
+    /// Anchor the gap in front of visible index `index` so it survives remote edits
+    pub fn relative_position(&self, index: usize, bias: Bias) -> Result<RelativePosition<PositionId>, AdvancedCrdtError> {
+        RelativePosition::at(self.order.iter(), index, bias)
+            .ok_or_else(|| AdvancedCrdtError::InvalidPosition(format!("Index {} beyond length", index)))
+    }
+    
+    /// Current index of a relative position, or `None` if its element is unknown here
+    pub fn resolve_position(&self, position: &RelativePosition<PositionId>) -> Option<usize> {
+        position.resolve(self.order.iter())
+    }
+    
+    /// Latest insert or deletion seen from each replica
+    pub fn version_vector(&self) -> VersionVector {
+        let mut version = self.collected.clone();
+        for element in self.elements.values() {
+            version.observe(element.position.replica_id, element.position.timestamp);
+            if let Some(tombstone) = element.deleted_at {
+                version.observe(tombstone.replica_id, tombstone.timestamp);
+            }
+        }
+        version
     }
     
     /// Get element count
     pub fn len(&self) -> usize {
-        self.elements.len()
+        self.order.len()
+    }
+    
+    /// Number of visible elements
+    pub fn visible_len(&self) -> usize {
+        self.order.visible_len()
+    }
+    
+    /// Index of an element among all elements, and the number of visible elements before it
+    pub(super) fn ranks(&self, position: &PositionId) -> Option<(usize, usize)> {
+        Some((self.order.rank(position)?, self.order.visible_rank(position)?))
     }
     
     /// Check if RGA is empty
     pub fn is_empty(&self) -> bool {
         self.elements.is_empty()
     }
+    
+    /// Place an element at its RGA position
+    fn integrate(&mut self, element: RgaElement<T>) -> Result<(), AdvancedCrdtError> {
+        let mut rank = match &element.prev {
+            Some(prev) => self.order.rank(prev)
+                .ok_or_else(|| AdvancedCrdtError::ElementNotFound(format!("Position {:?}", prev)))? + 1,
+            None => 0,
+        };
+        // Skip newer siblings and everything inserted after them
+        while let Some((position, _)) = self.order.get(rank) {
+            if !is_newer(position, &element.position) {
+                break;
+            }
+            rank += 1;
+        }
+        self.order.insert(rank, element.position.clone(), element.visible);
+        self.elements.insert(element.position.clone(), element);
+        Ok(())
+    }
 }
 
 impl<T: Clone + PartialEq> CRDT for Rga<T> {
@@ -138,20 +325,7 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for Rga<T> {
     type Error = AdvancedCrdtError;
     
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        // Merge all elements from other RGA
-        for (position, other_element) in &other.elements {
-            if let Some(self_element) = self.elements.get_mut(position) {
-                // Element exists in both, keep the one with higher timestamp
-                if other_element.position.timestamp > self_element.position.timestamp {
-                    *self_element = other_element.clone();
-                }
-            } else {
-                // Element only exists in other, add it
-                self.elements.insert(position.clone(), other_element.clone());
-            }
-        }
-        
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
     
     fn has_conflict(&self, other: &Self) -> bool {
@@ -167,6 +341,167 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for Rga<T> {
     }
 }
 
+/// Sequences are never split; elements only make sense next to what they were inserted after
+impl<T: Clone + PartialEq + Send + Sync> Reset for Rga<T> {
+    fn reset(&mut self, observed: &Self) -> bool {
+        !observed.version_vector().dominates(&self.version_vector())
+    }
+}
+
+/// Elements are immutable, so a merge only reports inserted and removed positions
+impl<T: Clone + PartialEq + Send + Sync> ReportingMerge for Rga<T> {
+    type Key = PositionId;
+    
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<PositionId>, Self::Error> {
+        let mut report = MergeReport::new();
+        // An element is always newer than the one it was inserted after,
+        // so integrating in timestamp order always finds the anchor in place
+        let mut incoming: Vec<_> = other.elements.values().collect();
+        incoming.sort_by_key(|e| (e.position.timestamp, e.position.replica_id));
+        
+        for other_element in incoming {
+            let position = &other_element.position;
+            if let Some(self_element) = self.elements.get_mut(position) {
+                // Deletions are never undone, so a tombstone on either side wins
+                if self_element.visible && !other_element.visible {
+                    self_element.visible = false;
+                    self.order.set_visible(position, false);
+                    report.removed.push(position.clone());
+                }
+                self_element.deleted_at = self_element.deleted_at.max(other_element.deleted_at);
+            } else if other_element.deleted_at
+                .is_some_and(|tombstone| self.collected.includes(&tombstone.replica_id, tombstone.timestamp))
+            {
+                // Purged here already
+                continue;
+            } else {
+                // Element only exists in other, add it
+                self.integrate(other_element.clone())
+                    .map_err(|e| AdvancedCrdtError::MergeError(e.to_string()))?;
+                report.record(position.clone(), false, other_element.visible, true);
+            }
+            let deleted_at = other_element.deleted_at.map_or(0, |tombstone| tombstone.timestamp);
+            self.timestamp_counter = self.timestamp_counter.max(position.timestamp).max(deleted_at);
+        }
+        
+        Ok(report)
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync> OpCrdt for Rga<T> {
+    type Intent = RgaIntent<T>;
+    type Op = RgaOp<T>;
+    type Error = AdvancedCrdtError;
+    
+    fn prepare(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error> {
+        match intent {
+            RgaIntent::InsertAfter { value, after } => self.prepare_insert(value, after),
+            RgaIntent::Delete(position) => self.prepare_delete(&position),
+        }
+    }
+    
+    fn apply(&mut self, op: &Self::Op) -> Result<bool, Self::Error> {
+        self.apply_op(op)
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync> Undoable for Rga<T> {
+    type Edit = RgaIntent<T>;
+    type Change = RgaChange<T>;
+    type Output = RgaOp<T>;
+    type Id = PositionId;
+    type Error = AdvancedCrdtError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        let op = self.perform(edit)?;
+        let change = match &op {
+            RgaOp::Insert { position, .. } => RgaChange::Inserted(position.clone()),
+            RgaOp::Delete { position, deleted_at } => RgaChange::Deleted {
+                position: position.clone(),
+                value: self.elements[position].value.clone(),
+                deleted_at: *deleted_at,
+            },
+        };
+        Ok((change, op))
+    }
+
+    /// Delete an inserted element, or insert a deleted value again in its place
+    ///
+    /// Deletions are never undone in an RGA, so bringing a value back inserts
+    /// it as a new element right after the tombstone. Values that another
+    /// replica deleted as well stay deleted.
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        match change {
+            RgaChange::Inserted(position) => {
+                let position = ids.resolve(position);
+                if !self.elements.get(&position).is_some_and(|element| element.visible) {
+                    return Ok(None);
+                }
+                self.edit(RgaIntent::Delete(position)).map(Some)
+            }
+            RgaChange::Deleted { position, value, deleted_at } => {
+                let position = ids.resolve(position);
+                let ours = self.elements.get(&position).is_some_and(|element| element.deleted_at == Some(*deleted_at));
+                if !ours {
+                    return Ok(None);
+                }
+                let (change, op) = self.edit(RgaIntent::InsertAfter { value: value.clone(), after: Some(position.clone()) })?;
+                ids.insert(position, op.position().clone());
+                Ok(Some((change, op)))
+            }
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> GarbageCollect for Rga<T> {
+    /// Purge tombstones whose deletion is stable and that no element follows
+    ///
+    /// A run of deleted elements goes from its end backwards. Tombstones that
+    /// a live element was inserted after stay, to keep its place for replicas
+    /// that integrate it later.
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let frontier = frontier.meet(&self.version_vector());
+        let mut anchors: HashMap<PositionId, usize> = HashMap::new();
+        for prev in self.elements.values().filter_map(|element| element.prev.clone()) {
+            *anchors.entry(prev).or_default() += 1;
+        }
+        
+        let mut purged = HashSet::new();
+        let mut candidates: Vec<PositionId> = self.elements.keys().cloned().collect();
+        while let Some(position) = candidates.pop() {
+            let element = &self.elements[&position];
+            let stable = element.deleted_at
+                .is_some_and(|tombstone| frontier.is_stable(&tombstone.replica_id, tombstone.timestamp));
+            if !stable || anchors.get(&position).is_some_and(|count| *count > 0) || purged.contains(&position) {
+                continue;
+            }
+            purged.insert(position.clone());
+            if let Some(prev) = &element.prev {
+                let count = anchors.get_mut(prev).expect("prev is counted");
+                *count -= 1;
+                if *count == 0 {
+                    candidates.push(prev.clone());
+                }
+            }
+        }
+        if purged.is_empty() {
+            return 0;
+        }
+        
+        let mut order = OrderTree::new();
+        for (rank, (position, visible)) in self.order.iter()
+            .filter(|(position, _)| !purged.contains(*position))
+            .enumerate()
+        {
+            order.insert(rank, position.clone(), visible);
+        }
+        self.order = order;
+        self.elements.retain(|position, _| !purged.contains(position));
+        self.collected.merge(frontier.version_vector());
+        purged.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -233,4 +568,167 @@ mod tests {
         assert!(elements.contains(&"hello".to_string()));
         assert!(elements.contains(&"world".to_string()));
     }
+
+    #[test]
+    fn test_rga_merge_with_changes() {
+        let mut rga1 = Rga::<String>::new(create_replica(1));
+        let hello = rga1.insert_after("hello".to_string(), None).unwrap();
+        let mut rga2 = Rga::<String>::new(create_replica(2));
+        rga2.merge(&rga1).unwrap();
+
+        let world = rga2.insert_after("world".to_string(), Some(hello.clone())).unwrap();
+        rga2.delete(&hello).unwrap();
+
+        let report = rga1.merge_with_changes(&rga2).unwrap();
+        assert_eq!(report.inserted, vec![world]);
+        assert!(report.updated.is_empty());
+        assert_eq!(report.removed, vec![hello]);
+        assert!(rga1.merge_with_changes(&rga2).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn test_rga_relative_position_survives_merge() {
+        let mut rga1 = Rga::<String>::new(create_replica(1));
+        let a = rga1.insert_after("a".to_string(), None).unwrap();
+        let b = rga1.insert_after("b".to_string(), Some(a)).unwrap();
+        let mut rga2 = rga1.clone();
+        
+        // Cursor sits between "a" and "b"
+        let cursor = rga1.relative_position(1, Bias::Before).unwrap();
+        
+        // Remote replica deletes "b" and appends "c"
+        rga2.delete(&b).unwrap();
+        rga2.insert_after("c".to_string(), Some(b)).unwrap();
+        rga1.merge(&rga2).unwrap();
+        
+        assert_eq!(rga1.to_vec(), vec!["a", "c"]);
+        assert_eq!(rga1.resolve_position(&cursor), Some(1));
+        assert!(rga1.relative_position(3, Bias::Before).is_err());
+    }
+    
+    #[test]
+    fn test_rga_insert_after_is_honoured() {
+        let mut rga = Rga::<String>::new(create_replica(1));
+        let a = rga.insert_after("a".to_string(), None).unwrap();
+        rga.insert_after("c".to_string(), Some(a.clone())).unwrap();
+        let b = rga.insert_after("b".to_string(), Some(a)).unwrap();
+        rga.insert_after("start".to_string(), None).unwrap();
+        
+        assert_eq!(rga.to_vec(), vec!["start", "a", "b", "c"]);
+        assert_eq!(rga.get(2), Some(&"b".to_string()));
+        assert_eq!(rga.position_at(2), Some(b.clone()));
+        assert_eq!(rga.index_of(&b), Some(2));
+        assert_eq!(rga.get(4), None);
+        
+        let unknown = PositionId::new(create_replica(9), 1, 1);
+        assert!(rga.insert_after("x".to_string(), Some(unknown)).is_err());
+    }
+    
+    #[test]
+    fn test_rga_concurrent_runs_converge_without_interleaving() {
+        let mut base = Rga::<String>::new(create_replica(1));
+        let head = base.insert_after("head".to_string(), None).unwrap();
+        let tail = base.insert_after("tail".to_string(), Some(head.clone())).unwrap();
+        
+        let mut rga1 = base.clone();
+        let mut rga2 = Rga::<String>::new(create_replica(2));
+        rga2.merge(&base).unwrap();
+        
+        let x1 = rga1.insert_after("x1".to_string(), Some(head.clone())).unwrap();
+        rga1.insert_after("x2".to_string(), Some(x1)).unwrap();
+        let y1 = rga2.insert_after("y1".to_string(), Some(head)).unwrap();
+        rga2.insert_after("y2".to_string(), Some(y1)).unwrap();
+        rga2.delete(&tail).unwrap();
+        
+        let mut merged1 = rga1.clone();
+        merged1.merge(&rga2).unwrap();
+        let mut merged2 = rga2.clone();
+        merged2.merge(&rga1).unwrap();
+        
+        assert_eq!(merged1.to_vec(), merged2.to_vec());
+        // Equal timestamps, so the higher replica ID goes first
+        assert_eq!(merged1.to_vec(), vec!["head", "y1", "y2", "x1", "x2"]);
+        
+        // A later local insert at the same spot goes before both concurrent runs
+        let head = merged1.position_at(0).unwrap();
+        merged1.insert_after("z".to_string(), Some(head)).unwrap();
+        assert_eq!(merged1.get(1), Some(&"z".to_string()));
+    }
+    
+    #[test]
+    fn test_rga_gc_purges_stable_runs_of_tombstones() {
+        let mut rga1 = Rga::<String>::new(create_replica(1));
+        let a = rga1.insert_after("a".to_string(), None).unwrap();
+        let b = rga1.insert_after("b".to_string(), Some(a.clone())).unwrap();
+        let c = rga1.insert_after("c".to_string(), Some(b.clone())).unwrap();
+        rga1.insert_after("d".to_string(), Some(c.clone())).unwrap();
+        let mut rga2 = Rga::<String>::new(create_replica(2));
+        rga2.merge(&rga1).unwrap();
+        
+        rga1.delete(&b).unwrap();
+        rga1.delete(&c).unwrap();
+        // "d" was typed after "c", so only "b" and "c" form a run that can go
+        let frontier = StabilityFrontier::new(rga2.version_vector());
+        assert_eq!(rga1.gc(&frontier), 0);
+        
+        rga2.merge(&rga1).unwrap();
+        let frontier = StabilityFrontier::new(rga2.version_vector());
+        assert_eq!(rga1.gc(&frontier), 0);
+        assert_eq!(rga2.gc(&frontier), 0);
+        
+        let e = rga1.insert_after("e".to_string(), Some(a.clone())).unwrap();
+        rga1.delete(&a).unwrap();
+        rga1.delete(&e).unwrap();
+        rga2.merge(&rga1).unwrap();
+        let frontier = StabilityFrontier::new(rga2.version_vector());
+        assert_eq!(rga1.gc(&frontier), 1);
+        assert_eq!(rga1.to_vec(), vec!["d"]);
+        
+        // A replica still holding the purged tombstone does not bring it back
+        rga1.merge(&rga2).unwrap();
+        assert_eq!(rga1.len(), 4);
+        assert_eq!(rga1.to_vec(), vec!["d"]);
+    }
+    
+    #[test]
+    fn test_rga_insert_after_tombstone_keeps_it_as_anchor() {
+        let mut rga = Rga::<String>::new(create_replica(1));
+        let a = rga.insert_after("a".to_string(), None).unwrap();
+        let b = rga.insert_after("b".to_string(), Some(a)).unwrap();
+        rga.delete(&b).unwrap();
+        
+        let c = rga.insert_after("c".to_string(), Some(b.clone())).unwrap();
+        assert_eq!(rga.to_vec(), vec!["a", "c"]);
+        assert_eq!(rga.elements[&c].prev, Some(b.clone()));
+        
+        // The anchored tombstone survives collection
+        let frontier = StabilityFrontier::new(rga.version_vector());
+        rga.gc(&frontier);
+        assert!(rga.elements.contains_key(&b));
+    }
+    
+    #[test]
+    fn test_rga_ops_converge_when_delivered_twice() {
+        let mut rga1 = Rga::<String>::new(create_replica(1));
+        let mut rga2 = Rga::<String>::new(create_replica(2));
+        
+        let a = rga1.perform(RgaIntent::InsertAfter { value: "a".to_string(), after: None }).unwrap();
+        let b = rga1.perform(RgaIntent::InsertAfter { value: "b".to_string(), after: Some(a.position().clone()) }).unwrap();
+        for op in [&a, &b, &a] {
+            rga2.apply(op).unwrap();
+        }
+        
+        let x = rga2.perform(RgaIntent::InsertAfter { value: "x".to_string(), after: Some(a.position().clone()) }).unwrap();
+        let delete = rga1.perform(RgaIntent::Delete(b.position().clone())).unwrap();
+        assert!(rga1.apply(&x).unwrap());
+        assert!(rga2.apply(&delete).unwrap());
+        assert!(!rga2.apply(&delete).unwrap());
+        
+        assert_eq!(rga1.to_vec(), vec!["a", "x"]);
+        assert_eq!(rga1.to_vec(), rga2.to_vec());
+        
+        // An insert whose anchor has not arrived yet is refused
+        let mut rga3 = Rga::<String>::new(create_replica(3));
+        assert!(rga3.apply(&b).is_err());
+    }
 }
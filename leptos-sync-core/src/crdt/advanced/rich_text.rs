@@ -0,0 +1,446 @@
+//! Rich-text formatting marks (Peritext-style) anchored to RGA positions
+
+use super::common::{PositionId, AdvancedCrdtError};
+use super::text::TextCrdt;
+use super::super::{CRDT, Mergeable, ReplicaId};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+/// Kind of formatting applied to a span of text
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MarkType {
+    Bold,
+    Italic,
+    /// Link to a URL
+    Link(String),
+    /// Comment thread, identified by its ID
+    Comment(String),
+}
+
+impl MarkType {
+    /// Marks with the same key override each other; different keys coexist
+    fn key(&self) -> String {
+        match self {
+            MarkType::Bold => "bold".to_string(),
+            MarkType::Italic => "italic".to_string(),
+            MarkType::Link(_) => "link".to_string(),
+            MarkType::Comment(id) => format!("comment:{}", id),
+        }
+    }
+
+    /// Boundary rule used by [`RichText::add_mark`]
+    ///
+    /// Typing at the end of a bold word continues in bold, while text typed next
+    /// to a link or a comment stays outside of it.
+    pub fn default_expand(&self) -> ExpandRule {
+        match self {
+            MarkType::Bold | MarkType::Italic => ExpandRule::After,
+            MarkType::Link(_) | MarkType::Comment(_) => ExpandRule::None,
+        }
+    }
+}
+
+/// Whether text inserted at a boundary of a mark, later or concurrently, is covered by it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExpandRule {
+    None,
+    Before,
+    After,
+    Both,
+}
+
+impl ExpandRule {
+    fn expands_before(self) -> bool {
+        matches!(self, ExpandRule::Before | ExpandRule::Both)
+    }
+
+    fn expands_after(self) -> bool {
+        matches!(self, ExpandRule::After | ExpandRule::Both)
+    }
+}
+
+/// Gap between characters that a mark starts or ends at
+///
+/// Text inserted after a character lands between its `After` gap and the
+/// `Before` gap of the next character.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MarkAnchor {
+    /// Start of the document
+    Start,
+    /// Just before a character
+    Before(PositionId),
+    /// Just after a character
+    After(PositionId),
+    /// End of the document
+    End,
+}
+
+/// Whether a mark operation adds or removes formatting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkAction {
+    Add(MarkType),
+    Remove(MarkType),
+}
+
+impl MarkAction {
+    fn mark_type(&self) -> &MarkType {
+        match self {
+            MarkAction::Add(mark) | MarkAction::Remove(mark) => mark,
+        }
+    }
+}
+
+/// A single add or remove of formatting between two anchors
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MarkOp {
+    /// Unique ID; among overlapping operations with the same key the newest wins
+    pub id: PositionId,
+    pub action: MarkAction,
+    pub start: MarkAnchor,
+    pub end: MarkAnchor,
+}
+
+/// Run of visible text sharing the same formatting
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FormattedSpan {
+    pub text: String,
+    pub marks: Vec<MarkType>,
+}
+
+/// Formatting layer over any sequence of RGA positions
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FormattingMarks {
+    /// Replica ID
+    replica_id: ReplicaId,
+    /// All mark operations, never removed so that late inserts resolve the same way
+    ops: Vec<MarkOp>,
+    /// Logical timestamp counter, kept ahead of every operation seen
+    timestamp_counter: u64,
+}
+
+impl FormattingMarks {
+    /// Create an empty formatting layer
+    pub fn new(replica_id: ReplicaId) -> Self {
+        Self {
+            replica_id,
+            ops: Vec::new(),
+            timestamp_counter: 0,
+        }
+    }
+
+    /// Record a mark operation between two anchors
+    pub fn apply(&mut self, action: MarkAction, start: MarkAnchor, end: MarkAnchor) -> &MarkOp {
+        self.timestamp_counter += 1;
+        let id = PositionId::new(self.replica_id, self.timestamp_counter, self.timestamp_counter);
+        self.ops.push(MarkOp { id, action, start, end });
+        &self.ops[self.ops.len() - 1]
+    }
+
+    /// All mark operations
+    pub fn ops(&self) -> &[MarkOp] {
+        &self.ops
+    }
+
+    /// Split visible text into formatted spans
+    ///
+    /// `positions` lists every character in document order, deleted ones included
+    /// as `None`, like [`TextCrdt::positions`].
+    pub fn spans<I>(&self, positions: I) -> Vec<FormattedSpan>
+    where
+        I: IntoIterator<Item = (PositionId, Option<char>)>,
+    {
+        let mut opening: HashMap<&MarkAnchor, Vec<usize>> = HashMap::new();
+        let mut closing: HashMap<&MarkAnchor, Vec<usize>> = HashMap::new();
+        for (index, op) in self.ops.iter().enumerate() {
+            opening.entry(&op.start).or_default().push(index);
+            closing.entry(&op.end).or_default().push(index);
+        }
+
+        let mut active: Vec<usize> = Vec::new();
+        let cross = |anchor: &MarkAnchor, active: &mut Vec<usize>| {
+            if let Some(ops) = opening.get(anchor) {
+                active.extend(ops);
+            }
+            if let Some(ops) = closing.get(anchor) {
+                active.retain(|index| !ops.contains(index));
+            }
+        };
+
+        let mut spans: Vec<FormattedSpan> = Vec::new();
+        cross(&MarkAnchor::Start, &mut active);
+        for (position, character) in positions {
+            cross(&MarkAnchor::Before(position.clone()), &mut active);
+            if let Some(character) = character {
+                let marks = self.resolve(&active);
+                match spans.last_mut() {
+                    Some(span) if span.marks == marks => span.text.push(character),
+                    _ => spans.push(FormattedSpan { text: character.to_string(), marks }),
+                }
+            }
+            cross(&MarkAnchor::After(position), &mut active);
+        }
+        spans
+    }
+
+    /// Marks in effect given the active operations: the newest one wins for each key
+    fn resolve(&self, active: &[usize]) -> Vec<MarkType> {
+        let mut winners: BTreeMap<String, &MarkOp> = BTreeMap::new();
+        for op in active.iter().map(|index| &self.ops[*index]) {
+            let key = op.action.mark_type().key();
+            match winners.get(&key) {
+                Some(winner) if (winner.id.timestamp, winner.id.replica_id) > (op.id.timestamp, op.id.replica_id) => {}
+                _ => {
+                    winners.insert(key, op);
+                }
+            }
+        }
+        winners
+            .into_values()
+            .filter_map(|op| match &op.action {
+                MarkAction::Add(mark) => Some(mark.clone()),
+                MarkAction::Remove(_) => None,
+            })
+            .collect()
+    }
+}
+
+impl CRDT for FormattingMarks {
+    fn replica_id(&self) -> &ReplicaId {
+        &self.replica_id
+    }
+}
+
+impl Mergeable for FormattingMarks {
+    type Error = AdvancedCrdtError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let known: HashSet<PositionId> = self.ops.iter().map(|op| op.id.clone()).collect();
+        for op in &other.ops {
+            if !known.contains(&op.id) {
+                self.ops.push(op.clone());
+            }
+            self.timestamp_counter = self.timestamp_counter.max(op.id.timestamp);
+        }
+        Ok(())
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        // Overlapping marks are resolved per character by operation ID
+        false
+    }
+}
+
+/// Collaborative rich text: a [`TextCrdt`] with formatting marks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RichText {
+    text: TextCrdt,
+    marks: FormattingMarks,
+}
+
+impl RichText {
+    /// Create an empty rich text
+    pub fn new(replica_id: ReplicaId) -> Self {
+        Self {
+            text: TextCrdt::new(replica_id),
+            marks: FormattingMarks::new(replica_id),
+        }
+    }
+
+    /// Insert plain text before the character at index `pos`
+    pub fn insert(&mut self, pos: usize, text: &str) -> Result<(), AdvancedCrdtError> {
+        self.text.insert(pos, text)
+    }
+
+    /// Delete the characters in `range`; marks anchored to them keep resolving
+    pub fn delete(&mut self, range: Range<usize>) -> Result<(), AdvancedCrdtError> {
+        self.text.delete(range)
+    }
+
+    /// Apply a mark to `range` using its default boundary rule
+    pub fn add_mark(&mut self, range: Range<usize>, mark: MarkType) -> Result<(), AdvancedCrdtError> {
+        let expand = mark.default_expand();
+        self.add_mark_with(range, mark, expand)
+    }
+
+    /// Apply a mark to `range` with an explicit boundary rule
+    pub fn add_mark_with(&mut self, range: Range<usize>, mark: MarkType, expand: ExpandRule) -> Result<(), AdvancedCrdtError> {
+        let (start, end) = self.anchors(&range, expand)?;
+        self.marks.apply(MarkAction::Add(mark), start, end);
+        Ok(())
+    }
+
+    /// Remove a mark from `range`, using the same boundary rule as when adding it
+    pub fn remove_mark(&mut self, range: Range<usize>, mark: MarkType) -> Result<(), AdvancedCrdtError> {
+        let (start, end) = self.anchors(&range, mark.default_expand())?;
+        self.marks.apply(MarkAction::Remove(mark), start, end);
+        Ok(())
+    }
+
+    /// Visible text split into spans of identical formatting
+    pub fn spans(&self) -> Vec<FormattedSpan> {
+        self.marks.spans(self.text.positions())
+    }
+
+    /// The plain text
+    pub fn text(&self) -> &TextCrdt {
+        &self.text
+    }
+
+    /// The formatting layer
+    pub fn marks(&self) -> &FormattingMarks {
+        &self.marks
+    }
+
+    fn anchors(&self, range: &Range<usize>, expand: ExpandRule) -> Result<(MarkAnchor, MarkAnchor), AdvancedCrdtError> {
+        let len = self.text.len();
+        if range.start >= range.end || range.end > len {
+            return Err(AdvancedCrdtError::InvalidPosition(format!("Range {:?} for length {}", range, len)));
+        }
+        let position = |index: usize| {
+            self.text.position_at(index)
+                .ok_or_else(|| AdvancedCrdtError::InvalidPosition(format!("Index {}", index)))
+        };
+
+        let start = if !expand.expands_before() {
+            MarkAnchor::Before(position(range.start)?)
+        } else if range.start == 0 {
+            MarkAnchor::Start
+        } else {
+            MarkAnchor::After(position(range.start - 1)?)
+        };
+        let end = if !expand.expands_after() {
+            MarkAnchor::After(position(range.end - 1)?)
+        } else if range.end == len {
+            MarkAnchor::End
+        } else {
+            MarkAnchor::Before(position(range.end)?)
+        };
+        Ok((start, end))
+    }
+}
+
+impl CRDT for RichText {
+    fn replica_id(&self) -> &ReplicaId {
+        self.text.replica_id()
+    }
+}
+
+impl Mergeable for RichText {
+    type Error = AdvancedCrdtError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.text.merge(&other.text)?;
+        self.marks.merge(&other.marks)
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    fn span(text: &str, marks: Vec<MarkType>) -> FormattedSpan {
+        FormattedSpan { text: text.to_string(), marks }
+    }
+
+    fn replicas(text: &str) -> (RichText, RichText) {
+        let mut doc1 = RichText::new(create_replica(1));
+        doc1.insert(0, text).unwrap();
+        let mut doc2 = RichText::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+        (doc1, doc2)
+    }
+
+    #[test]
+    fn test_bold_covers_concurrent_insert_in_the_middle() {
+        let (mut doc1, mut doc2) = replicas("hello world");
+        doc1.add_mark(0..5, MarkType::Bold).unwrap();
+        doc2.insert(2, "XX").unwrap();
+
+        doc1.merge(&doc2).unwrap();
+        doc2.merge(&doc1).unwrap();
+        let expected = vec![span("heXXllo", vec![MarkType::Bold]), span(" world", vec![])];
+        assert_eq!(doc1.spans(), expected);
+        assert_eq!(doc2.spans(), expected);
+    }
+
+    #[test]
+    fn test_expand_rules_at_boundaries() {
+        let mut doc = RichText::new(create_replica(1));
+        doc.insert(0, "bold link").unwrap();
+        doc.add_mark(0..4, MarkType::Bold).unwrap();
+        let link = MarkType::Link("https://example.com".to_string());
+        doc.add_mark(5..9, link.clone()).unwrap();
+
+        // Bold grows when typing at its end, the link does not
+        doc.insert(4, "er").unwrap();
+        doc.insert(11, "!").unwrap();
+        doc.insert(7, "<").unwrap();
+        assert_eq!(
+            doc.spans(),
+            vec![
+                span("bolder", vec![MarkType::Bold]),
+                span(" <", vec![]),
+                span("link", vec![link]),
+                span("!", vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_concurrent_bold_and_unbold_newest_wins() {
+        let (mut doc1, mut doc2) = replicas("abcdef");
+        doc1.add_mark(0..6, MarkType::Bold).unwrap();
+        doc2.merge(&doc1).unwrap();
+        doc2.remove_mark(2..4, MarkType::Bold).unwrap();
+        doc1.add_mark(0..6, MarkType::Italic).unwrap();
+
+        doc1.merge(&doc2).unwrap();
+        assert_eq!(
+            doc1.spans(),
+            vec![
+                span("ab", vec![MarkType::Bold, MarkType::Italic]),
+                span("cd", vec![MarkType::Italic]),
+                span("ef", vec![MarkType::Bold, MarkType::Italic]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_overlap_and_survive_deletion_of_anchor() {
+        let (mut doc1, mut doc2) = replicas("one two three");
+        let first = MarkType::Comment("c1".to_string());
+        let second = MarkType::Comment("c2".to_string());
+        doc1.add_mark(0..7, first.clone()).unwrap();
+        doc2.add_mark(4..13, second.clone()).unwrap();
+        // Deleting the first characters of a comment leaves it anchored to the tombstone
+        doc2.delete(0..2).unwrap();
+
+        doc1.merge(&doc2).unwrap();
+        assert_eq!(
+            doc1.spans(),
+            vec![
+                span("e ", vec![first.clone()]),
+                span("two", vec![first, second.clone()]),
+                span(" three", vec![second]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_mark_range() {
+        let mut doc = RichText::new(create_replica(1));
+        doc.insert(0, "abc").unwrap();
+        assert!(doc.add_mark(2..2, MarkType::Bold).is_err());
+        assert!(doc.add_mark(1..4, MarkType::Bold).is_err());
+    }
+}
@@ -0,0 +1,647 @@
+//! Collaborative plain text built on RGA with run-length-encoded character blocks
+
+use super::common::{AdvancedCrdtError, PositionId, Tombstone};
+use super::order_tree::OrderTree;
+use super::rga::{Rga, RgaOp};
+use super::super::{CRDT, Mergeable, OpCrdt, ReplicaId, Reset, VersionVector};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+/// Index-based change to the visible text
+///
+/// Changes are listed in the order they were applied, so each index is relative
+/// to the text left by the previous change, as a textarea binding expects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextChange {
+    /// `text` was inserted at character index `pos`
+    Insert { pos: usize, text: String },
+    /// `len` characters were deleted from character index `pos`
+    Delete { pos: usize, len: usize },
+}
+
+/// Run of characters, each inserted after the previous one by the same replica
+///
+/// Character `i` behaves exactly like an [`Rga`] element at position `id`
+/// advanced by `i`, inserted after character `i - 1` (or after `prev` for the
+/// first one).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct TextBlock {
+    /// Position of the first character
+    id: PositionId,
+    /// Character the first character was inserted after
+    prev: Option<PositionId>,
+    /// Number of characters
+    len: usize,
+    /// Characters of the block, cleared once deleted
+    text: String,
+    /// When and by whom the block was deleted
+    deleted_at: Option<Tombstone>,
+}
+
+impl TextBlock {
+    fn char_id(&self, offset: usize) -> PositionId {
+        PositionId::new(
+            self.id.replica_id,
+            self.id.timestamp + offset as u64,
+            self.id.disambiguation + offset as u64,
+        )
+    }
+
+    fn offset_of(&self, id: &PositionId) -> Option<usize> {
+        let contains = id.replica_id == self.id.replica_id
+            && id.timestamp >= self.id.timestamp
+            && id.timestamp < self.id.timestamp + self.len as u64;
+        contains.then(|| (id.timestamp - self.id.timestamp) as usize)
+    }
+
+    fn is_deleted(&self) -> bool {
+        self.deleted_at.is_some()
+    }
+
+    /// Split off the characters from `offset` on into a new block
+    fn split_off(&mut self, offset: usize) -> TextBlock {
+        let text = self.text.split_off(byte_offset(&self.text, offset));
+        let tail = TextBlock {
+            id: self.char_id(offset),
+            prev: Some(self.char_id(offset - 1)),
+            len: self.len - offset,
+            text,
+            deleted_at: self.deleted_at,
+        };
+        self.len = offset;
+        tail
+    }
+}
+
+/// Blocks are keyed by the replica and timestamp of their first character
+type BlockKey = (ReplicaId, u64);
+
+fn block_key(id: &PositionId) -> BlockKey {
+    (id.replica_id, id.timestamp)
+}
+
+/// Whether `a` is ordered before `b` when both were inserted after the same character
+fn is_newer(a: &BlockKey, b: &BlockKey) -> bool {
+    (a.1, a.0) > (b.1, b.0)
+}
+
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices().nth(chars).map_or(text.len(), |(index, _)| index)
+}
+
+/// Collaborative plain text
+///
+/// Follows the RGA insertion rules of [`Rga`], but consecutive characters
+/// typed by one replica share a single block, so a large document is stored
+/// in a handful of blocks rather than an element per character. Blocks are
+/// split when an edit lands inside them, and an order tree over the blocks
+/// keeps every lookup by index logarithmic.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(from = "TextState<TextBlock>")]
+pub struct TextCrdt {
+    /// Replica ID
+    replica_id: ReplicaId,
+    /// Blocks by their first character, deleted ones included
+    blocks: BTreeMap<BlockKey, TextBlock>,
+    /// Document order of the blocks, each as wide as its characters
+    order: OrderTree<BlockKey>,
+    /// Logical timestamp counter, kept ahead of every timestamp seen
+    timestamp_counter: u64,
+}
+
+/// Stored form of a [`TextCrdt`], its blocks in document order
+#[derive(Serialize, Deserialize)]
+struct TextState<B> {
+    replica_id: ReplicaId,
+    blocks: Vec<B>,
+    timestamp_counter: u64,
+}
+
+impl From<TextState<TextBlock>> for TextCrdt {
+    fn from(state: TextState<TextBlock>) -> Self {
+        let mut text = Self::new(state.replica_id);
+        text.timestamp_counter = state.timestamp_counter;
+        for (rank, block) in state.blocks.into_iter().enumerate() {
+            let key = block_key(&block.id);
+            text.order.insert_run(rank, key, !block.is_deleted(), block.len);
+            text.blocks.insert(key, block);
+        }
+        text
+    }
+}
+
+impl Serialize for TextCrdt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TextState {
+            replica_id: self.replica_id,
+            blocks: self.order.iter().map(|(key, _)| &self.blocks[key]).collect(),
+            timestamp_counter: self.timestamp_counter,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl TextCrdt {
+    /// Create an empty text
+    pub fn new(replica_id: ReplicaId) -> Self {
+        Self {
+            replica_id,
+            blocks: BTreeMap::new(),
+            order: OrderTree::new(),
+            timestamp_counter: 0,
+        }
+    }
+
+    /// Continue editing as `replica_id`, e.g. in a copy received from another replica
+    pub fn set_replica_id(&mut self, replica_id: ReplicaId) {
+        self.replica_id = replica_id;
+    }
+
+    /// Insert `text` before the character at index `pos`
+    pub fn insert(&mut self, pos: usize, text: &str) -> Result<(), AdvancedCrdtError> {
+        let len = self.len();
+        if pos > len {
+            return Err(AdvancedCrdtError::InvalidPosition(format!("Index {} beyond length {}", pos, len)));
+        }
+        let count = text.chars().count();
+        if count == 0 {
+            return Ok(());
+        }
+
+        let prev = pos.checked_sub(1).and_then(|index| self.position_at(index));
+        let timestamp = self.timestamp_counter + 1;
+        self.timestamp_counter += count as u64;
+        let id = PositionId::new(self.replica_id, timestamp, timestamp);
+
+        if !self.try_extend(prev.as_ref(), &id, text, count) {
+            self.integrate(TextBlock {
+                id,
+                prev,
+                len: count,
+                text: text.to_string(),
+                deleted_at: None,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Delete the characters in `range`
+    pub fn delete(&mut self, range: Range<usize>) -> Result<(), AdvancedCrdtError> {
+        let len = self.len();
+        if range.start > range.end || range.end > len {
+            return Err(AdvancedCrdtError::InvalidPosition(format!("Range {:?} beyond length {}", range, len)));
+        }
+        let Some((key, offset)) = self.locate(range.start) else {
+            return Ok(());
+        };
+
+        self.timestamp_counter += 1;
+        let tombstone = Tombstone::new(self.replica_id, self.timestamp_counter);
+        let key = self.split_at(key, offset);
+        let mut rank = self.order.rank(&key).expect("block is ordered");
+        let mut remaining = range.len();
+        while remaining > 0 {
+            let Some((&key, visible)) = self.order.get(rank) else {
+                break;
+            };
+            if visible {
+                let count = self.blocks[&key].len.min(remaining);
+                self.delete_block(key, count, tombstone);
+                remaining -= count;
+            }
+            rank += 1;
+        }
+        Ok(())
+    }
+
+    /// Merge another replica's text, returning the changes to apply to a view of this one
+    pub fn merge_with_changes(&mut self, other: &Self) -> Result<Vec<TextChange>, AdvancedCrdtError> {
+        let mut changes = Vec::new();
+        // A character is always newer than the one it was inserted after,
+        // so integrating in timestamp order always finds the anchor in place
+        let mut remote: Vec<&TextBlock> = other.blocks.values().collect();
+        remote.sort_by_key(|block| (block.id.timestamp, block.id.replica_id));
+        for block in remote {
+            self.merge_block(block, &mut changes)?;
+        }
+        self.timestamp_counter = self.timestamp_counter.max(other.timestamp_counter);
+        Ok(changes)
+    }
+
+    /// Position of the visible character at `index`
+    pub fn position_at(&self, index: usize) -> Option<PositionId> {
+        self.locate(index).map(|(key, offset)| self.blocks[&key].char_id(offset))
+    }
+
+    /// Index of a visible character, or `None` if it is deleted or unknown
+    pub fn index_of(&self, position: &PositionId) -> Option<usize> {
+        let (key, offset) = self.find(position)?;
+        if self.blocks[&key].is_deleted() {
+            return None;
+        }
+        self.order.visible_rank(&key).map(|before| before + offset)
+    }
+
+    /// Positions of all characters in document order, with the character if it is visible
+    ///
+    /// Deleted characters are included so that anchors placed on them still resolve.
+    pub fn positions(&self) -> impl Iterator<Item = (PositionId, Option<char>)> + '_ {
+        self.order.iter().flat_map(move |(key, _)| {
+            let block = &self.blocks[key];
+            // Deleted blocks have no characters left
+            let chars = block.text.chars().map(Some).chain(std::iter::repeat(None));
+            (0..block.len).map(move |offset| block.char_id(offset)).zip(chars)
+        })
+    }
+
+    /// Number of visible characters
+    pub fn len(&self) -> usize {
+        self.order.visible_len()
+    }
+
+    /// Check if there is no visible text
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of blocks the text is stored in, including deleted ones
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Latest insert or deletion seen from each replica
+    pub fn version_vector(&self) -> VersionVector {
+        let mut version = VersionVector::new();
+        for block in self.blocks.values() {
+            version.observe(block.id.replica_id, block.id.timestamp + block.len as u64 - 1);
+            if let Some(tombstone) = block.deleted_at {
+                version.observe(tombstone.replica_id, tombstone.timestamp);
+            }
+        }
+        version
+    }
+
+    /// The text as an [`Rga`] of characters, one element per character at the same positions
+    pub fn to_rga(&self) -> Rga<char> {
+        let mut rga = Rga::new(self.replica_id);
+        // Every character comes after the one it was inserted after
+        for (key, _) in self.order.iter() {
+            let block = &self.blocks[key];
+            let mut prev = block.prev.clone();
+            // Deleted blocks have no characters left, any value will do
+            let values = block.text.chars().chain(std::iter::repeat(char::REPLACEMENT_CHARACTER));
+            for (offset, value) in values.take(block.len).enumerate() {
+                let position = block.char_id(offset);
+                rga.apply(&RgaOp::Insert { position: position.clone(), value, prev })
+                    .expect("anchor precedes the character");
+                if let Some(deleted_at) = block.deleted_at {
+                    rga.apply(&RgaOp::Delete { position: position.clone(), deleted_at })
+                        .expect("character was just inserted");
+                }
+                prev = Some(position);
+            }
+        }
+        rga
+    }
+
+    /// Append to the block ending at `prev` if this is a continuation of local typing
+    fn try_extend(&mut self, prev: Option<&PositionId>, id: &PositionId, text: &str, count: usize) -> bool {
+        let Some((key, offset)) = prev.and_then(|prev| self.find(prev)) else {
+            return false;
+        };
+        let block = self.blocks.get_mut(&key).expect("found block exists");
+        // Contiguous timestamps mean nothing was inserted after `prev` since
+        let continues = offset + 1 == block.len
+            && !block.is_deleted()
+            && block.char_id(block.len) == *id;
+        if continues {
+            block.text.push_str(text);
+            block.len += count;
+            self.order.set_width(&key, block.len);
+        }
+        continues
+    }
+
+    /// Place a block at its RGA position, returning its key
+    fn integrate(&mut self, block: TextBlock) -> Result<BlockKey, AdvancedCrdtError> {
+        let key = block_key(&block.id);
+        let mut rank = match &block.prev {
+            Some(prev) => {
+                let (anchor, offset) = self.find(prev)
+                    .ok_or_else(|| AdvancedCrdtError::ElementNotFound(format!("Position {:?}", prev)))?;
+                if offset + 1 < self.blocks[&anchor].len {
+                    self.split_at(anchor, offset + 1);
+                }
+                self.order.rank(&anchor).expect("block is ordered") + 1
+            }
+            None => 0,
+        };
+        // Skip newer siblings and everything inserted after them
+        while let Some((next, _)) = self.order.get(rank) {
+            if !is_newer(next, &key) {
+                break;
+            }
+            rank += 1;
+        }
+        self.order.insert_run(rank, key, !block.is_deleted(), block.len);
+        self.blocks.insert(key, block);
+        Ok(key)
+    }
+
+    fn merge_block(&mut self, remote: &TextBlock, changes: &mut Vec<TextChange>) -> Result<(), AdvancedCrdtError> {
+        // Each character depends on the previous one, so the known part is a prefix
+        let mut offset = 0;
+        while offset < remote.len {
+            let Some((key, local_offset)) = self.find(&remote.char_id(offset)) else {
+                break;
+            };
+            let count = (self.blocks[&key].len - local_offset).min(remote.len - offset);
+            if let Some(tombstone) = remote.deleted_at {
+                let key = self.split_at(key, local_offset);
+                let visible = !self.blocks[&key].is_deleted();
+                let pos = self.order.visible_rank(&key).expect("block is ordered");
+                self.delete_block(key, count, tombstone);
+                if visible {
+                    push_change(changes, TextChange::Delete { pos, len: count });
+                }
+            }
+            offset += count;
+        }
+
+        if offset < remote.len {
+            let mut block = remote.clone();
+            if offset > 0 {
+                block = block.split_off(offset);
+            }
+            let text = (!block.is_deleted()).then(|| block.text.clone());
+            let key = self.integrate(block)?;
+            if let Some(text) = text {
+                let pos = self.order.visible_rank(&key).expect("block is ordered");
+                push_change(changes, TextChange::Insert { pos, text });
+            }
+        }
+        Ok(())
+    }
+
+    /// Mark the first `count` characters of a block as deleted
+    fn delete_block(&mut self, key: BlockKey, count: usize, tombstone: Tombstone) {
+        if self.blocks[&key].len > count {
+            self.split_at(key, count);
+        }
+        let block = self.blocks.get_mut(&key).expect("block exists");
+        // Replicas that deleted concurrently agree on the latest deletion
+        block.deleted_at = block.deleted_at.max(Some(tombstone));
+        block.text.clear();
+        self.order.set_visible(&key, false);
+    }
+
+    /// Make the character at `offset` of a block start a block, returning that block's key
+    fn split_at(&mut self, key: BlockKey, offset: usize) -> BlockKey {
+        if offset == 0 {
+            return key;
+        }
+        let block = self.blocks.get_mut(&key).expect("block exists");
+        let tail = block.split_off(offset);
+        let tail_key = block_key(&tail.id);
+        let rank = self.order.rank(&key).expect("block is ordered");
+        self.order.set_width(&key, offset);
+        self.order.insert_run(rank + 1, tail_key, !tail.is_deleted(), tail.len);
+        self.blocks.insert(tail_key, tail);
+        tail_key
+    }
+
+    /// Block and offset holding the visible character at `index`
+    fn locate(&self, index: usize) -> Option<(BlockKey, usize)> {
+        self.order.locate_visible(index).map(|(key, offset)| (*key, offset))
+    }
+
+    /// Block and offset holding the character at `position`, deleted or not
+    fn find(&self, position: &PositionId) -> Option<(BlockKey, usize)> {
+        let (key, block) = self.blocks.range(..=block_key(position)).next_back()?;
+        block.offset_of(position).map(|offset| (*key, offset))
+    }
+}
+
+/// Record a change, joining it to the previous one where they touch
+fn push_change(changes: &mut Vec<TextChange>, change: TextChange) {
+    match (changes.last_mut(), change) {
+        (Some(TextChange::Insert { pos, text }), TextChange::Insert { pos: next, text: more })
+            if *pos + text.chars().count() == next =>
+        {
+            text.push_str(&more);
+        }
+        (Some(TextChange::Delete { pos, len }), TextChange::Delete { pos: next, len: more }) if *pos == next => {
+            *len += more;
+        }
+        (_, change) => changes.push(change),
+    }
+}
+
+impl fmt::Display for TextCrdt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, visible) in self.order.iter() {
+            if visible {
+                f.write_str(&self.blocks[key].text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CRDT for TextCrdt {
+    fn replica_id(&self) -> &ReplicaId {
+        &self.replica_id
+    }
+}
+
+impl Mergeable for TextCrdt {
+    type Error = AdvancedCrdtError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        // Concurrent edits are always interleaved deterministically
+        false
+    }
+}
+
+/// Text is never split; characters only make sense next to what they were inserted after
+impl Reset for TextCrdt {
+    fn reset(&mut self, observed: &Self) -> bool {
+        !observed.version_vector().dominates(&self.version_vector())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    fn byte_offset(text: &str, chars: usize) -> usize {
+        text.char_indices().nth(chars).map_or(text.len(), |(index, _)| index)
+    }
+
+    fn apply(view: &mut String, changes: &[TextChange]) {
+        for change in changes {
+            match change {
+                TextChange::Insert { pos, text } => {
+                    view.insert_str(byte_offset(view, *pos), text);
+                }
+                TextChange::Delete { pos, len } => {
+                    let start = byte_offset(view, *pos);
+                    let end = byte_offset(view, pos + len);
+                    view.replace_range(start..end, "");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_insert_and_delete() {
+        let mut text = TextCrdt::new(create_replica(1));
+        text.insert(0, "hello world").unwrap();
+        text.insert(5, ",").unwrap();
+        text.delete(0..1).unwrap();
+        text.insert(0, "H").unwrap();
+
+        assert_eq!(text.to_string(), "Hello, world");
+        assert_eq!(text.len(), 12);
+        assert!(text.insert(13, "!").is_err());
+        assert!(text.delete(10..13).is_err());
+
+        text.delete(5..12).unwrap();
+        assert_eq!(text.to_string(), "Hello");
+    }
+
+    #[test]
+    fn test_text_multibyte_characters() {
+        let mut text = TextCrdt::new(create_replica(1));
+        text.insert(0, "héllo wörld").unwrap();
+        text.delete(1..2).unwrap();
+        text.insert(1, "ë").unwrap();
+        assert_eq!(text.to_string(), "hëllo wörld");
+    }
+
+    #[test]
+    fn test_text_large_document() {
+        let mut text = TextCrdt::new(create_replica(1));
+        for index in 0..100_000 {
+            text.insert(index, "x").unwrap();
+        }
+        assert_eq!(text.len(), 100_000);
+        assert_eq!(text.block_count(), 1);
+
+        // Both edits land mid-block and split it
+        text.insert(50_000, "y").unwrap();
+        text.delete(10..20).unwrap();
+        assert_eq!(text.len(), 99_991);
+        assert_eq!(text.block_count(), 5);
+        assert_eq!(text.position_at(49_990).and_then(|id| text.index_of(&id)), Some(49_990));
+        assert_eq!(text.to_string().chars().nth(49_990), Some('y'));
+    }
+
+    #[test]
+    fn test_text_splits_blocks_like_rga() {
+        let mut text1 = TextCrdt::new(create_replica(1));
+        text1.insert(0, "abcdef").unwrap();
+        let mut text2 = TextCrdt::new(create_replica(2));
+        text2.merge(&text1).unwrap();
+
+        // Concurrent inserts into the middle of the same block, and a delete across it
+        text1.insert(3, "XY").unwrap();
+        text2.insert(3, "12").unwrap();
+        text2.delete(1..5).unwrap();
+        let snapshot = text1.clone();
+        text1.merge(&text2).unwrap();
+        text2.merge(&snapshot).unwrap();
+        assert_eq!(text1.to_string(), text2.to_string());
+        assert_eq!(text1.to_string(), "aXYdef");
+
+        // The same characters as an RGA of single characters end up in the same order
+        let mut rga = snapshot.to_rga();
+        rga.merge(&text2.to_rga()).unwrap();
+        assert_eq!(rga.to_vec().into_iter().collect::<String>(), text1.to_string());
+        let positions: Vec<_> = text1.positions().map(|(position, _)| position).collect();
+        let rga_positions: Vec<_> = rga.entries().map(|(position, _)| position.clone()).collect();
+        assert_eq!(positions, rga_positions);
+    }
+
+    #[test]
+    fn test_text_concurrent_inserts_converge() {
+        let mut text1 = TextCrdt::new(create_replica(1));
+        text1.insert(0, "ac").unwrap();
+        let mut text2 = TextCrdt::new(create_replica(2));
+        text2.merge(&text1).unwrap();
+
+        text1.insert(1, "b").unwrap();
+        text2.insert(1, "XY").unwrap();
+        let snapshot = text1.clone();
+        text1.merge(&text2).unwrap();
+        text2.merge(&snapshot).unwrap();
+
+        assert_eq!(text1.to_string(), text2.to_string());
+        let merged = text1.to_string();
+        assert!(merged.starts_with('a') && merged.ends_with('c'));
+        assert!(merged.contains('b') && merged.contains("XY"));
+    }
+
+    #[test]
+    fn test_text_merge_emits_index_changes() {
+        let mut text1 = TextCrdt::new(create_replica(1));
+        text1.insert(0, "hello").unwrap();
+        let mut text2 = TextCrdt::new(create_replica(2));
+        text2.merge(&text1).unwrap();
+
+        // Replica 1 keeps typing while replica 2 edits the same word
+        text1.insert(5, " there").unwrap();
+        text2.insert(2, "LL").unwrap();
+        text2.delete(0..1).unwrap();
+        text2.insert(text2.len(), "!").unwrap();
+
+        let mut view = text1.to_string();
+        let changes = text1.merge_with_changes(&text2).unwrap();
+        apply(&mut view, &changes);
+        assert_eq!(view, text1.to_string());
+        assert!(changes.contains(&TextChange::Delete { pos: 0, len: 1 }));
+
+        // Merging again changes nothing
+        assert!(text1.merge_with_changes(&text2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_text_merge_extended_block() {
+        let mut text1 = TextCrdt::new(create_replica(1));
+        text1.insert(0, "hel").unwrap();
+        let mut text2 = TextCrdt::new(create_replica(2));
+        text2.merge(&text1).unwrap();
+
+        text1.insert(3, "lo").unwrap();
+        assert_eq!(text1.block_count(), 1);
+        let changes = text2.merge_with_changes(&text1).unwrap();
+        assert_eq!(changes, vec![TextChange::Insert { pos: 3, text: "lo".to_string() }]);
+        assert_eq!(text2.to_string(), "hello");
+        assert_eq!(text2.block_count(), 2);
+    }
+
+    #[test]
+    fn test_text_serialization() {
+        let mut text = TextCrdt::new(create_replica(1));
+        text.insert(0, "hello").unwrap();
+        text.delete(1..2).unwrap();
+
+        let json = serde_json::to_string(&text).unwrap();
+        let mut decoded: TextCrdt = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, text);
+        assert_eq!(decoded.to_string(), "hllo");
+        decoded.insert(4, "!").unwrap();
+        assert_eq!(decoded.to_string(), "hllo!");
+    }
+}
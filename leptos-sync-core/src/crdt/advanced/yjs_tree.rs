@@ -1,9 +1,11 @@
 //! Yjs-style tree for hierarchical data
 
-use super::common::{PositionId, AdvancedCrdtError};
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::common::{PositionId, AdvancedCrdtError, Tombstone};
+use super::super::{CRDT, Mergeable, ReplicaId, VersionVector};
+use super::super::stability::{GarbageCollect, StabilityFrontier};
+use super::super::tree::{MoveLog, MoveOp, TreeHierarchy};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Yjs-style tree node
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -18,6 +20,9 @@ pub struct YjsNode<T> {
     pub children: Vec<PositionId>,
     /// Whether the node is visible (not deleted)
     pub visible: bool,
+    /// When and by whom the node was deleted
+    #[serde(default)]
+    pub deleted_at: Option<Tombstone>,
 }
 
 impl<T> YjsNode<T> {
@@ -29,6 +34,7 @@ impl<T> YjsNode<T> {
             parent,
             children: Vec::new(),
             visible: true,
+            deleted_at: None,
         }
     }
 }
@@ -57,6 +63,12 @@ pub struct YjsTree<T> {
     timestamp_counter: u64,
     /// Disambiguation counter
     disambiguation_counter: u64,
+    /// Parent changes ordered by logical timestamp and replica
+    #[serde(default)]
+    moves: MoveLog<PositionId, (u64, ReplicaId)>,
+    /// Updates whose tombstones may have been purged
+    #[serde(default)]
+    collected: VersionVector,
 }
 
 impl<T: Clone + PartialEq> YjsTree<T> {
@@ -68,6 +80,8 @@ impl<T: Clone + PartialEq> YjsTree<T> {
             root: None,
             timestamp_counter: 0,
             disambiguation_counter: 0,
+            moves: MoveLog::new(),
+            collected: VersionVector::new(),
         }
     }
     
@@ -91,9 +105,7 @@ impl<T: Clone + PartialEq> YjsTree<T> {
     
     /// Add a child node
     pub fn add_child(&mut self, parent_id: &PositionId, value: T) -> Result<PositionId, AdvancedCrdtError> {
-        if !self.nodes.contains_key(parent_id) {
-            return Err(AdvancedCrdtError::ElementNotFound(format!("Parent {:?}", parent_id)));
-        }
+        self.check_parent(parent_id)?;
         
         self.timestamp_counter += 1;
         self.disambiguation_counter += 1;
@@ -104,21 +116,53 @@ impl<T: Clone + PartialEq> YjsTree<T> {
             self.disambiguation_counter,
         );
         
-        let node = YjsNode::new(id.clone(), value, Some(parent_id.clone()));
+        let node = YjsNode::new(id.clone(), value, None);
         self.nodes.insert(id.clone(), node);
-        
-        // Add to parent's children
-        if let Some(parent) = self.nodes.get_mut(parent_id) {
-            parent.children.push(id.clone());
-        }
-        
+
+        // Attaching the child is the first move of the node
+        let op = MoveOp {
+            timestamp: (self.timestamp_counter, self.replica_id),
+            child: id.clone(),
+            parent: Some(parent_id.clone()),
+        };
+        self.moves.apply(op, &mut self.nodes);
         Ok(id)
     }
+
+    /// Move a node under a new parent
+    ///
+    /// Moves are replicated with a logical timestamp; concurrent moves that
+    /// would form a cycle are resolved the same way on every replica.
+    pub fn move_node(&mut self, node_id: &PositionId, new_parent_id: &PositionId) -> Result<(), AdvancedCrdtError> {
+        if !self.nodes.contains_key(node_id) {
+            return Err(AdvancedCrdtError::ElementNotFound(format!("Node {:?}", node_id)));
+        }
+        self.check_parent(new_parent_id)?;
+        let op = MoveOp {
+            timestamp: (self.timestamp_counter + 1, self.replica_id),
+            child: node_id.clone(),
+            parent: Some(new_parent_id.clone()),
+        };
+        if !MoveLog::is_valid(&op, &self.nodes) {
+            return Err(AdvancedCrdtError::CycleDetected(format!(
+                "Node {:?} cannot move under its descendant {:?}",
+                node_id, new_parent_id
+            )));
+        }
+
+        self.timestamp_counter += 1;
+        self.moves.apply(op, &mut self.nodes);
+        Ok(())
+    }
     
     /// Delete a node
     pub fn delete(&mut self, node_id: &PositionId) -> Result<(), AdvancedCrdtError> {
         if let Some(node) = self.nodes.get_mut(node_id) {
-            node.visible = false;
+            if node.visible {
+                self.timestamp_counter += 1;
+                node.visible = false;
+                node.deleted_at = Some(Tombstone::new(self.replica_id, self.timestamp_counter));
+            }
             Ok(())
         } else {
             Err(AdvancedCrdtError::ElementNotFound(format!("Node {:?}", node_id)))
@@ -156,6 +200,21 @@ impl<T: Clone + PartialEq> YjsTree<T> {
         }
     }
     
+    /// Latest change seen from each replica
+    pub fn version_vector(&self) -> VersionVector {
+        let mut version = self.collected.clone();
+        for node in self.nodes.values() {
+            version.observe(node.id.replica_id, node.id.timestamp);
+            if let Some(tombstone) = node.deleted_at {
+                version.observe(tombstone.replica_id, tombstone.timestamp);
+            }
+        }
+        for op in self.moves.ops() {
+            version.observe(op.timestamp.1, op.timestamp.0);
+        }
+        version
+    }
+    
     /// Get node count
     pub fn len(&self) -> usize {
         self.nodes.len()
@@ -165,6 +224,42 @@ impl<T: Clone + PartialEq> YjsTree<T> {
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
+
+    /// Deleted nodes take no new children, so they can be purged once their deletion is stable
+    fn check_parent(&self, parent_id: &PositionId) -> Result<(), AdvancedCrdtError> {
+        match self.nodes.get(parent_id) {
+            None => Err(AdvancedCrdtError::ElementNotFound(format!("Parent {:?}", parent_id))),
+            Some(parent) if !parent.visible => {
+                Err(AdvancedCrdtError::InvalidRelationship(format!("Parent {:?} is deleted", parent_id)))
+            }
+            Some(_) => Ok(()),
+        }
+    }
+}
+
+impl<T> TreeHierarchy<PositionId> for HashMap<PositionId, YjsNode<T>> {
+    fn contains_node(&self, id: &PositionId) -> bool {
+        self.contains_key(id)
+    }
+
+    fn parent_of(&self, id: &PositionId) -> Option<PositionId> {
+        self.get(id).and_then(|node| node.parent.clone())
+    }
+
+    fn set_parent(&mut self, id: &PositionId, parent: Option<PositionId>) {
+        let Some(node) = self.get_mut(id) else {
+            return;
+        };
+        let old_parent = std::mem::replace(&mut node.parent, parent.clone());
+        if let Some(old_parent) = old_parent.and_then(|old_parent| self.get_mut(&old_parent)) {
+            old_parent.children.retain(|child| child != id);
+        }
+        // Children are kept in ID order so every replica lists them alike
+        if let Some(parent) = parent.and_then(|parent| self.get_mut(&parent)) {
+            let index = parent.children.partition_point(|child| child < id);
+            parent.children.insert(index, id.clone());
+        }
+    }
 }
 
 impl<T: Clone + PartialEq> CRDT for YjsTree<T> {
@@ -180,13 +275,32 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for YjsTree<T> {
         // Merge all nodes from other tree
         for (node_id, other_node) in &other.nodes {
             if let Some(self_node) = self.nodes.get_mut(node_id) {
-                // Node exists in both, keep the one with higher timestamp
-                if other_node.id.timestamp > self_node.id.timestamp {
-                    *self_node = other_node.clone();
-                }
+                // Deletion wins; the hierarchy comes from the move log
+                self_node.visible &= other_node.visible;
+                self_node.deleted_at = self_node.deleted_at.max(other_node.deleted_at);
+            } else if other_node.deleted_at
+                .is_some_and(|tombstone| self.collected.includes(&tombstone.replica_id, tombstone.timestamp))
+            {
+                // Purged here already
+                continue;
             } else {
-                // Node only exists in other, add it
-                self.nodes.insert(node_id.clone(), other_node.clone());
+                // Node only exists in other, attached by its moves below
+                let mut node = other_node.clone();
+                node.parent = None;
+                node.children.clear();
+                self.nodes.insert(node_id.clone(), node);
+            }
+            let deleted_at = other_node.deleted_at.map_or(0, |tombstone| tombstone.timestamp);
+            self.timestamp_counter = self.timestamp_counter.max(node_id.timestamp).max(deleted_at);
+        }
+
+        for op in other.moves.ops() {
+            self.timestamp_counter = self.timestamp_counter.max(op.timestamp.0);
+            // Every node of other is known by now, so missing ones were purged
+            let purged = !self.nodes.contains_key(&op.child)
+                || op.parent.as_ref().is_some_and(|parent| !self.nodes.contains_key(parent));
+            if !purged {
+                self.moves.apply(op.clone(), &mut self.nodes);
             }
         }
         
@@ -222,6 +336,46 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for YjsTree<T> {
     }
 }
 
+impl<T: Clone + PartialEq> GarbageCollect for YjsTree<T> {
+    /// Purge deleted nodes whose deletion is stable, leaves first
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let frontier = frontier.meet(&self.version_vector());
+        let mut purged = HashSet::new();
+        loop {
+            let leaves: Vec<PositionId> = self
+                .nodes
+                .values()
+                .filter(|node| {
+                    node.children.is_empty()
+                        && node.deleted_at
+                            .is_some_and(|tombstone| frontier.is_stable(&tombstone.replica_id, tombstone.timestamp))
+                })
+                .map(|node| node.id.clone())
+                .collect();
+            if leaves.is_empty() {
+                break;
+            }
+            for id in leaves {
+                self.nodes.set_parent(&id, None);
+                self.nodes.remove(&id);
+                purged.insert(id);
+            }
+        }
+        if purged.is_empty() {
+            return 0;
+        }
+
+        self.moves.retain(|op| {
+            !purged.contains(&op.child) && op.parent.as_ref().is_none_or(|parent| !purged.contains(parent))
+        });
+        if self.root.as_ref().is_some_and(|root| purged.contains(root)) {
+            self.root = None;
+        }
+        self.collected.merge(frontier.version_vector());
+        purged.len()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -295,4 +449,58 @@ mod tests {
         // Should contain both trees
         assert_eq!(tree1.len(), 4); // 2 roots + 2 children
     }
+
+    #[test]
+    fn test_yjs_tree_concurrent_moves_converge_without_cycle() {
+        let mut tree1 = YjsTree::<String>::new(create_replica(1));
+        let root_id = tree1.add_root("root".to_string()).unwrap();
+        let a_id = tree1.add_child(&root_id, "a".to_string()).unwrap();
+        let b_id = tree1.add_child(&root_id, "b".to_string()).unwrap();
+        let mut tree2 = YjsTree::<String>::new(create_replica(2));
+        tree2.merge(&tree1).unwrap();
+
+        tree1.move_node(&a_id, &b_id).unwrap();
+        tree2.move_node(&b_id, &a_id).unwrap();
+        assert!(matches!(
+            tree1.move_node(&b_id, &a_id),
+            Err(AdvancedCrdtError::CycleDetected(_))
+        ));
+
+        let mut merged1 = tree1.clone();
+        merged1.merge(&tree2).unwrap();
+        let mut merged2 = tree2.clone();
+        merged2.merge(&tree1).unwrap();
+
+        // Both moves share a timestamp, so replica 2's sorts last and is skipped
+        let expected = merged1.to_tree().unwrap();
+        assert_eq!(merged2.to_tree().unwrap(), expected);
+        assert_eq!(expected.children.len(), 1);
+        assert_eq!(expected.children[0].id, b_id);
+        assert_eq!(expected.children[0].children[0].id, a_id);
+    }
+
+    #[test]
+    fn test_yjs_tree_gc_purges_stable_deleted_leaves() {
+        let mut tree1 = YjsTree::<String>::new(create_replica(1));
+        let root = tree1.add_root("root".to_string()).unwrap();
+        let child = tree1.add_child(&root, "child".to_string()).unwrap();
+        let grandchild = tree1.add_child(&child, "grandchild".to_string()).unwrap();
+        let mut tree2 = YjsTree::<String>::new(create_replica(2));
+        tree2.merge(&tree1).unwrap();
+
+        tree1.delete(&child).unwrap();
+        tree1.delete(&grandchild).unwrap();
+        assert!(tree1.add_child(&child, "orphan".to_string()).is_err());
+        assert_eq!(tree1.gc(&StabilityFrontier::new(tree2.version_vector())), 0);
+
+        tree2.merge(&tree1).unwrap();
+        assert_eq!(tree1.gc(&StabilityFrontier::new(tree2.version_vector())), 2);
+        assert_eq!(tree1.len(), 1);
+        assert!(tree1.to_tree().unwrap().children.is_empty());
+
+        // Merging a replica that still holds the tombstones does not revive them
+        tree1.merge(&tree2).unwrap();
+        assert_eq!(tree1.len(), 1);
+        assert_eq!(tree1.moves.len(), 0);
+    }
 }
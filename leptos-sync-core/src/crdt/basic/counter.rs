@@ -1,11 +1,22 @@
 //! Counter CRDT implementations
 
-use super::{replica_id::ReplicaId, traits::{CRDT, Mergeable}};
+use super::{
+    replica_id::ReplicaId,
+    traits::{CRDT, DeltaCrdt, Mergeable, Reset},
+    version_vector::VersionVector,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use thiserror::Error;
 
-/// Counter that can be incremented/decremented
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CounterError {
+    #[error("Insufficient rights: {available} available, {requested} requested")]
+    InsufficientRights { available: u64, requested: u64 },
+}
+
+/// Grow-only counter
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct GCounter {
     increments: HashMap<ReplicaId, u64>,
 }
@@ -18,7 +29,11 @@ impl GCounter {
     }
 
     pub fn increment(&mut self, replica_id: ReplicaId) {
-        *self.increments.entry(replica_id).or_insert(0) += 1;
+        self.increment_by(replica_id, 1);
+    }
+
+    pub fn increment_by(&mut self, replica_id: ReplicaId, amount: u64) {
+        *self.increments.entry(replica_id).or_insert(0) += amount;
     }
 
     pub fn value(&self) -> u64 {
@@ -57,6 +72,30 @@ impl Mergeable for GCounter {
     }
 }
 
+impl Reset for GCounter {
+    fn reset(&mut self, observed: &Self) -> bool {
+        self.increments.retain(|replica_id, count| *count > observed.replica_value(*replica_id));
+        !self.increments.is_empty()
+    }
+}
+
+impl DeltaCrdt for GCounter {
+    fn version_vector(&self) -> VersionVector {
+        // Each replica's count only ever grows, so it doubles as its version
+        self.increments.iter().map(|(replica_id, count)| (*replica_id, *count)).collect()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        let increments: HashMap<_, _> = self
+            .increments
+            .iter()
+            .filter(|(replica_id, count)| !since.includes(replica_id, **count))
+            .map(|(replica_id, count)| (*replica_id, *count))
+            .collect();
+        (!increments.is_empty()).then_some(Self { increments })
+    }
+}
+
 impl CRDT for GCounter {
     fn replica_id(&self) -> &ReplicaId {
         // GCounter doesn't have a single replica ID, so we'll use a default
@@ -66,6 +105,195 @@ impl CRDT for GCounter {
     }
 }
 
+/// Counter that can be incremented and decremented
+///
+/// Pairs a grow-only counter of increments with one of decrements.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PNCounter {
+    increments: GCounter,
+    decrements: GCounter,
+}
+
+impl PNCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn increment(&mut self, replica_id: ReplicaId) {
+        self.increments.increment(replica_id);
+    }
+
+    pub fn increment_by(&mut self, replica_id: ReplicaId, amount: u64) {
+        self.increments.increment_by(replica_id, amount);
+    }
+
+    pub fn decrement(&mut self, replica_id: ReplicaId) {
+        self.decrements.increment(replica_id);
+    }
+
+    pub fn decrement_by(&mut self, replica_id: ReplicaId, amount: u64) {
+        self.decrements.increment_by(replica_id, amount);
+    }
+
+    pub fn value(&self) -> i64 {
+        self.increments.value() as i64 - self.decrements.value() as i64
+    }
+
+    /// Net contribution of a single replica
+    pub fn replica_value(&self, replica_id: ReplicaId) -> i64 {
+        self.increments.replica_value(replica_id) as i64 - self.decrements.replica_value(replica_id) as i64
+    }
+}
+
+/// A counter whose increments are those of a grow-only counter
+impl From<GCounter> for PNCounter {
+    fn from(increments: GCounter) -> Self {
+        Self { increments, decrements: GCounter::new() }
+    }
+}
+
+impl Mergeable for PNCounter {
+    type Error = std::io::Error;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.increments.merge(&other.increments)?;
+        self.decrements.merge(&other.decrements)
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        // PN-Counters are conflict-free by design
+        false
+    }
+}
+
+impl Reset for PNCounter {
+    fn reset(&mut self, observed: &Self) -> bool {
+        let increments = self.increments.reset(&observed.increments);
+        let decrements = self.decrements.reset(&observed.decrements);
+        increments || decrements
+    }
+}
+
+impl CRDT for PNCounter {
+    fn replica_id(&self) -> &ReplicaId {
+        // Like GCounter, a PN-Counter is shared by all replicas
+        self.increments.replica_id()
+    }
+}
+
+/// Counter that never drops below zero, even across offline replicas
+///
+/// Uses escrow: every unit of value is a right owned by one replica. A replica
+/// may only decrement using rights it owns, and can transfer rights to others,
+/// so concurrent decrements can never overdraw the counter.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BoundedCounter {
+    /// `rights[from][to]` is the total transferred from `from` to `to`;
+    /// `rights[r][r]` holds the increments made by `r`
+    rights: HashMap<ReplicaId, HashMap<ReplicaId, u64>>,
+    decrements: GCounter,
+}
+
+impl BoundedCounter {
+    /// Create an empty counter; nobody holds any rights yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `amount` to the counter, granting the rights to `replica_id`
+    pub fn increment(&mut self, replica_id: ReplicaId, amount: u64) {
+        *self.right_mut(replica_id, replica_id) += amount;
+    }
+
+    /// Subtract `amount` using rights owned by `replica_id`
+    pub fn decrement(&mut self, replica_id: ReplicaId, amount: u64) -> Result<(), CounterError> {
+        self.check_rights(replica_id, amount)?;
+        self.decrements.increment_by(replica_id, amount);
+        Ok(())
+    }
+
+    /// Move `amount` of `from`'s rights to `to`
+    pub fn transfer(&mut self, from: ReplicaId, to: ReplicaId, amount: u64) -> Result<(), CounterError> {
+        self.check_rights(from, amount)?;
+        if from != to {
+            *self.right_mut(from, to) += amount;
+        }
+        Ok(())
+    }
+
+    /// Current value: every increment minus every decrement
+    ///
+    /// A partially merged state may hold decrements whose increments have not
+    /// arrived yet; the value then reads as zero instead of underflowing.
+    pub fn value(&self) -> u64 {
+        let increments: u64 = self
+            .rights
+            .iter()
+            .filter_map(|(from, row)| row.get(from))
+            .sum();
+        increments.saturating_sub(self.decrements.value())
+    }
+
+    /// Rights `replica_id` can currently spend on decrements or transfers
+    pub fn local_rights(&self, replica_id: ReplicaId) -> u64 {
+        let received: u64 = self
+            .rights
+            .values()
+            .filter_map(|row| row.get(&replica_id))
+            .sum();
+        let sent: u64 = self
+            .rights
+            .get(&replica_id)
+            .map(|row| {
+                row.iter()
+                    .filter(|(to, _)| **to != replica_id)
+                    .map(|(_, amount)| amount)
+                    .sum()
+            })
+            .unwrap_or(0);
+        received
+            .saturating_sub(sent)
+            .saturating_sub(self.decrements.replica_value(replica_id))
+    }
+
+    fn check_rights(&self, replica_id: ReplicaId, requested: u64) -> Result<(), CounterError> {
+        let available = self.local_rights(replica_id);
+        if available < requested {
+            return Err(CounterError::InsufficientRights { available, requested });
+        }
+        Ok(())
+    }
+
+    fn right_mut(&mut self, from: ReplicaId, to: ReplicaId) -> &mut u64 {
+        self.rights.entry(from).or_default().entry(to).or_insert(0)
+    }
+}
+
+impl Mergeable for BoundedCounter {
+    type Error = std::io::Error;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        // Each replica only ever grows its own row, so entries merge by maximum
+        for (from, row) in &other.rights {
+            for (to, amount) in row {
+                let current = self.right_mut(*from, *to);
+                *current = (*current).max(*amount);
+            }
+        }
+        self.decrements.merge(&other.decrements)
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        false
+    }
+}
+
+impl CRDT for BoundedCounter {
+    fn replica_id(&self) -> &ReplicaId {
+        self.decrements.replica_id()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,4 +376,107 @@ mod tests {
         // Should keep the maximum value
         assert_eq!(counter1.replica_value(replica_id), 5);
     }
+
+    #[test]
+    fn test_gcounter_delta_since() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut counter = GCounter::new();
+        counter.increment(replica1);
+        counter.increment(replica2);
+
+        let mut peer = counter.clone();
+        let since = peer.version_vector();
+        assert!(counter.delta_since(&since).is_none());
+
+        counter.increment(replica1);
+        let delta = counter.delta_since(&since).unwrap();
+        assert_eq!(delta.len(), 1);
+
+        peer.apply_delta(&delta).unwrap();
+        assert_eq!(peer.value(), counter.value());
+    }
+
+    #[test]
+    fn test_pn_counter_operations_and_merge() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut counter1 = PNCounter::new();
+        let mut counter2 = PNCounter::new();
+
+        counter1.increment_by(replica1, 5);
+        counter1.decrement(replica1);
+        counter2.decrement_by(replica2, 10);
+        assert_eq!(counter1.value(), 4);
+        assert_eq!(counter2.value(), -10);
+
+        counter1.merge(&counter2).unwrap();
+        counter2.merge(&counter1).unwrap();
+        assert_eq!(counter1.value(), -6);
+        assert_eq!(counter1, counter2);
+        assert_eq!(counter1.replica_value(replica2), -10);
+    }
+
+    #[test]
+    fn test_bounded_counter_never_goes_below_zero() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut counter1 = BoundedCounter::new();
+        counter1.increment(replica1, 10);
+        counter1.transfer(replica1, replica2, 4).unwrap();
+        let mut counter2 = counter1.clone();
+
+        // Both replicas decrement while offline, each within its own rights
+        counter1.decrement(replica1, 6).unwrap();
+        assert_eq!(
+            counter1.decrement(replica1, 1),
+            Err(CounterError::InsufficientRights { available: 0, requested: 1 })
+        );
+        counter2.decrement(replica2, 4).unwrap();
+        assert!(counter2.decrement(replica2, 1).is_err());
+        assert!(counter2.transfer(replica2, replica1, 1).is_err());
+
+        counter1.merge(&counter2).unwrap();
+        counter2.merge(&counter1).unwrap();
+        assert_eq!(counter1.value(), 0);
+        assert_eq!(counter1, counter2);
+    }
+
+    #[test]
+    fn test_bounded_counter_rights_follow_transfers() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut counter = BoundedCounter::new();
+        counter.increment(replica1, 3);
+        counter.increment(replica2, 2);
+        counter.transfer(replica2, replica1, 2).unwrap();
+
+        assert_eq!(counter.value(), 5);
+        assert_eq!(counter.local_rights(replica1), 5);
+        assert_eq!(counter.local_rights(replica2), 0);
+
+        let json = serde_json::to_string(&counter).unwrap();
+        let decoded: BoundedCounter = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, counter);
+    }
+
+    #[test]
+    fn test_bounded_counter_tolerates_partial_state() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut counter = BoundedCounter::new();
+        counter.increment(replica1, 3);
+        counter.transfer(replica1, replica2, 3).unwrap();
+        counter.decrement(replica2, 2).unwrap();
+
+        // Decrements that arrived without the rights they were spent from
+        let partial = BoundedCounter { rights: HashMap::new(), decrements: counter.decrements.clone() };
+        assert_eq!(partial.value(), 0);
+        assert_eq!(partial.local_rights(replica2), 0);
+
+        let mut merged = partial.clone();
+        merged.merge(&counter).unwrap();
+        assert_eq!(merged.value(), 1);
+        assert_eq!(merged.local_rights(replica2), 1);
+    }
 }
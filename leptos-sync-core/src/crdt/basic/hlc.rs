@@ -0,0 +1,296 @@
+//! Hybrid logical clock
+//!
+//! Timestamps combine wall-clock milliseconds with a logical counter, so they
+//! stay close to real time while still respecting causality: an event stamped
+//! after observing a remote timestamp always orders after it, regardless of
+//! how far the local wall clock is skewed.
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use thiserror::Error;
+
+/// Number of bits of the packed representation used by the logical counter
+const LOGICAL_BITS: u32 = 16;
+
+/// Default bound on how far ahead of the local wall clock a remote timestamp may be
+pub const DEFAULT_MAX_DRIFT_MS: u64 = 24 * 60 * 60 * 1000;
+
+/// Last timestamp handed out by [`HlcTimestamp::now`], packed
+static PROCESS_LAST: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ClockError {
+    #[error("Remote timestamp is {drift_ms}ms ahead of the local clock (max {max_drift_ms}ms)")]
+    DriftExceeded { drift_ms: u64, max_drift_ms: u64 },
+}
+
+/// Hybrid logical clock timestamp
+///
+/// Ordered by physical time, then logical counter. Serialized as a single
+/// `u64` (`physical << 16 | logical`), which preserves that ordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "u64", into = "u64")]
+pub struct HlcTimestamp {
+    /// Milliseconds since the Unix epoch
+    pub physical: u64,
+    /// Counter distinguishing events within the same millisecond
+    pub logical: u16,
+}
+
+impl HlcTimestamp {
+    /// The smallest timestamp; orders before every real event
+    pub const ZERO: Self = Self { physical: 0, logical: 0 };
+
+    pub fn new(physical: u64, logical: u16) -> Self {
+        Self { physical, logical }
+    }
+
+    /// Timestamp at the current wall-clock time
+    ///
+    /// Backed by a process-wide clock, so successive calls never return the
+    /// same timestamp. Replicas that exchange timestamps with peers should use
+    /// their own [`HybridLogicalClock`] instead.
+    pub fn now() -> Self {
+        let wall = Self::new(wall_clock_ms(), 0).as_u64();
+        let previous = PROCESS_LAST
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                Some(wall.max(last + 1))
+            })
+            .unwrap_or_else(|last| last);
+        Self::from_u64(wall.max(previous + 1))
+    }
+
+    /// Next timestamp for a local event that follows `self`
+    ///
+    /// Uses the wall clock when it is ahead of `self`, otherwise bumps the
+    /// logical counter so the result is always strictly greater.
+    pub fn tick(&self) -> Self {
+        let wall = wall_clock_ms();
+        if wall > self.physical {
+            Self::new(wall, 0)
+        } else {
+            self.successor()
+        }
+    }
+
+    /// Smallest timestamp strictly greater than `self`
+    pub fn successor(&self) -> Self {
+        match self.logical.checked_add(1) {
+            Some(logical) => Self::new(self.physical, logical),
+            None => Self::new(self.physical + 1, 0),
+        }
+    }
+
+    /// Packed `u64` representation
+    pub fn as_u64(&self) -> u64 {
+        (self.physical << LOGICAL_BITS) | u64::from(self.logical)
+    }
+
+    /// Unpack a timestamp produced by [`HlcTimestamp::as_u64`]
+    pub fn from_u64(packed: u64) -> Self {
+        Self::new(packed >> LOGICAL_BITS, (packed & 0xFFFF) as u16)
+    }
+
+    /// Wall-clock time of the physical component
+    pub fn to_datetime(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp_millis(self.physical as i64).unwrap_or_default()
+    }
+
+    /// Timestamp for a wall-clock time
+    pub fn from_datetime(datetime: chrono::DateTime<chrono::Utc>) -> Self {
+        Self::new(datetime.timestamp_millis().max(0) as u64, 0)
+    }
+}
+
+impl From<u64> for HlcTimestamp {
+    fn from(packed: u64) -> Self {
+        Self::from_u64(packed)
+    }
+}
+
+impl From<HlcTimestamp> for u64 {
+    fn from(timestamp: HlcTimestamp) -> Self {
+        timestamp.as_u64()
+    }
+}
+
+impl fmt::Display for HlcTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.physical, self.logical)
+    }
+}
+
+/// Deserialize a timestamp that may have been stored before timestamps were
+/// hybrid logical clock readings
+///
+/// Human-readable formats also accept the RFC 3339 string a
+/// `chrono::DateTime<Utc>` was serialized as, read as that millisecond with a
+/// zero logical counter. Binary formats only ever stored the packed `u64`.
+pub(crate) fn deserialize_legacy<'de, D: Deserializer<'de>>(deserializer: D) -> Result<HlcTimestamp, D::Error> {
+    struct LegacyVisitor;
+
+    impl<'de> de::Visitor<'de> for LegacyVisitor {
+        type Value = HlcTimestamp;
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("a packed timestamp or an RFC 3339 date")
+        }
+
+        fn visit_u64<E: de::Error>(self, packed: u64) -> Result<Self::Value, E> {
+            Ok(HlcTimestamp::from_u64(packed))
+        }
+
+        fn visit_str<E: de::Error>(self, date: &str) -> Result<Self::Value, E> {
+            let datetime = chrono::DateTime::parse_from_rfc3339(date).map_err(E::custom)?;
+            Ok(HlcTimestamp::from_datetime(datetime.into()))
+        }
+    }
+
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(LegacyVisitor)
+    } else {
+        HlcTimestamp::deserialize(deserializer)
+    }
+}
+
+/// Hybrid logical clock owned by a replica
+///
+/// Call [`HybridLogicalClock::now`] for every local event and
+/// [`HybridLogicalClock::receive`] whenever a remote timestamp arrives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HybridLogicalClock {
+    last: HlcTimestamp,
+    max_drift_ms: u64,
+}
+
+impl HybridLogicalClock {
+    pub fn new() -> Self {
+        Self {
+            last: HlcTimestamp::ZERO,
+            max_drift_ms: DEFAULT_MAX_DRIFT_MS,
+        }
+    }
+
+    /// Set how far ahead of the wall clock a remote timestamp may be before it is rejected
+    pub fn with_max_drift(mut self, max_drift_ms: u64) -> Self {
+        self.max_drift_ms = max_drift_ms;
+        self
+    }
+
+    /// The most recent timestamp issued or observed
+    pub fn last(&self) -> HlcTimestamp {
+        self.last
+    }
+
+    /// Timestamp a local event
+    pub fn now(&mut self) -> HlcTimestamp {
+        self.last = self.last.tick();
+        self.last
+    }
+
+    /// Advance past a remote timestamp, returning the timestamp of the receive event
+    ///
+    /// Fails without changing the clock if `remote` is further ahead of the local
+    /// wall clock than the configured drift bound.
+    pub fn receive(&mut self, remote: HlcTimestamp) -> Result<HlcTimestamp, ClockError> {
+        let wall = wall_clock_ms();
+        let drift_ms = remote.physical.saturating_sub(wall);
+        if drift_ms > self.max_drift_ms {
+            return Err(ClockError::DriftExceeded {
+                drift_ms,
+                max_drift_ms: self.max_drift_ms,
+            });
+        }
+
+        self.witness(remote);
+        self.last = self.last.tick();
+        Ok(self.last)
+    }
+
+    /// Make sure future timestamps order after `timestamp`, without a drift check
+    ///
+    /// Intended for timestamps already accepted into local state.
+    pub fn witness(&mut self, timestamp: HlcTimestamp) {
+        self.last = self.last.max(timestamp);
+    }
+}
+
+impl Default for HybridLogicalClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn wall_clock_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packed_round_trip_preserves_order() {
+        let a = HlcTimestamp::new(1_000, 5);
+        let b = HlcTimestamp::new(1_000, 6);
+        let c = HlcTimestamp::new(1_001, 0);
+
+        assert_eq!(HlcTimestamp::from_u64(a.as_u64()), a);
+        assert!(a < b && b < c);
+        assert!(a.as_u64() < b.as_u64() && b.as_u64() < c.as_u64());
+    }
+
+    #[test]
+    fn test_local_events_are_monotonic() {
+        let mut clock = HybridLogicalClock::new();
+        let mut previous = clock.now();
+        for _ in 0..1000 {
+            let next = clock.now();
+            assert!(next > previous);
+            previous = next;
+        }
+    }
+
+    #[test]
+    fn test_receive_orders_after_remote_even_with_slow_clock() {
+        let mut clock = HybridLogicalClock::new();
+        // A peer whose clock runs ten minutes ahead
+        let remote = HlcTimestamp::new(HlcTimestamp::now().physical + 10 * 60 * 1000, 3);
+
+        let received = clock.receive(remote).unwrap();
+        assert!(received > remote);
+        assert!(clock.now() > received);
+    }
+
+    #[test]
+    fn test_receive_rejects_excessive_drift() {
+        let mut clock = HybridLogicalClock::new().with_max_drift(1_000);
+        let before = clock.last();
+        let remote = HlcTimestamp::new(HlcTimestamp::now().physical + 60_000, 0);
+
+        assert!(matches!(clock.receive(remote), Err(ClockError::DriftExceeded { .. })));
+        assert_eq!(clock.last(), before);
+    }
+
+    #[test]
+    fn test_now_is_unique_within_process() {
+        let a = HlcTimestamp::now();
+        let b = HlcTimestamp::now();
+        assert!(b > a);
+    }
+
+    #[test]
+    fn test_logical_overflow_carries_into_physical() {
+        let timestamp = HlcTimestamp::new(10, u16::MAX);
+        assert_eq!(timestamp.successor(), HlcTimestamp::new(11, 0));
+    }
+
+    #[test]
+    fn test_serializes_as_u64() {
+        let timestamp = HlcTimestamp::new(1_700_000_000_000, 2);
+        let json = serde_json::to_string(&timestamp).unwrap();
+        assert_eq!(json, timestamp.as_u64().to_string());
+        assert_eq!(serde_json::from_str::<HlcTimestamp>(&json).unwrap(), timestamp);
+    }
+}
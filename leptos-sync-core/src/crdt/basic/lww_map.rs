@@ -1,13 +1,32 @@
 //! Last-Write-Wins Map implementation
 
-use super::{replica_id::ReplicaId, traits::{CRDT, Mergeable}, lww_register::LwwRegister};
+use super::{
+    hlc::{HlcTimestamp, HybridLogicalClock},
+    lww_register::LwwRegister,
+    replica_id::ReplicaId,
+    traits::{CRDT, DeltaCrdt, MergeReport, Mergeable, ReportingMerge},
+    version_vector::VersionVector,
+};
+use crate::crdt::undo::{IdMap, Reverted, Undoable};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::hash::Hash;
 
 /// Last-Write-Wins Map
-#[derive(Debug, Clone)]
+///
+/// Entries are [`LwwRegister`]s, so entries stored with RFC 3339 timestamps
+/// still deserialize from JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "K: Serialize, V: Serialize",
+    deserialize = "K: Deserialize<'de> + Eq + Hash, V: Deserialize<'de>"
+))]
 pub struct LwwMap<K, V> {
     data: HashMap<K, LwwRegister<V>>,
+    /// Latest entry timestamp seen from each replica
+    #[serde(default)]
+    version: VersionVector,
 }
 
 impl<K, V> LwwMap<K, V>
@@ -18,12 +37,48 @@ where
     pub fn new() -> Self {
         Self {
             data: HashMap::new(),
+            version: VersionVector::new(),
         }
     }
 
+    /// Insert or overwrite a value
+    ///
+    /// Overwrites are stamped after the entry they replace, see [`LwwRegister::update`].
+    /// New entries take the process-wide clock and skip the replica's; prefer
+    /// [`LwwMap::insert_with_clock`] on a replica.
     pub fn insert(&mut self, key: K, value: V, replica_id: ReplicaId) {
-        let register = LwwRegister::new(value, replica_id);
-        self.data.insert(key, register);
+        let register = match self.data.remove(&key) {
+            Some(mut register) => {
+                register.update(value, replica_id);
+                register
+            }
+            None => LwwRegister::new(value, replica_id),
+        };
+        self.insert_register(key, register);
+    }
+
+    /// Insert or overwrite a value using the replica's clock for the timestamp
+    pub fn insert_with_clock(&mut self, key: K, value: V, replica_id: ReplicaId, clock: &mut HybridLogicalClock) {
+        let register = match self.data.remove(&key) {
+            Some(mut register) => {
+                register.update_with_clock(value, replica_id, clock);
+                register
+            }
+            None => LwwRegister::new_with_clock(value, replica_id, clock),
+        };
+        self.insert_register(key, register);
+    }
+
+    /// Store a locally written register, keeping its replica's versions increasing
+    fn insert_register(&mut self, key: K, register: LwwRegister<V>) {
+        let replica_id = register.replica_id();
+        let timestamp = self.version.advance(replica_id, register.timestamp());
+        self.data.insert(key, register.with_timestamp(timestamp));
+    }
+
+    /// Latest timestamp of any entry, for advancing a replica's clock after a merge
+    pub fn max_timestamp(&self) -> Option<HlcTimestamp> {
+        self.data.values().map(|register| register.timestamp()).max()
     }
 
     pub fn get(&self, key: &K) -> Option<&V> {
@@ -81,28 +136,133 @@ where
     type Error = std::io::Error;
     
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
+    }
+    
+    fn has_conflict(&self, other: &Self) -> bool {
+        for (key, other_register) in &other.data {
+            if let Some(existing_register) = self.data.get(key) {
+                if existing_register.has_conflict(other_register) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Removals are local only, so a merge never reports removed keys
+impl<K, V> ReportingMerge for LwwMap<K, V>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Clone + PartialEq + Send + Sync,
+{
+    type Key = K;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<K>, Self::Error> {
+        let mut report = MergeReport::new();
         for (key, other_register) in &other.data {
             match self.data.get_mut(key) {
                 Some(existing_register) => {
+                    let changed = existing_register.value() != other_register.value();
+                    let before = (existing_register.timestamp(), existing_register.replica_id());
                     existing_register.merge(other_register)?;
+                    let after = (existing_register.timestamp(), existing_register.replica_id());
+                    report.record(key.clone(), true, true, changed && after != before);
                 }
                 None => {
                     self.data.insert(key.clone(), other_register.clone());
+                    report.record(key.clone(), false, true, true);
                 }
             }
         }
-        Ok(())
+        self.version.merge(&other.version);
+        Ok(report)
     }
-    
-    fn has_conflict(&self, other: &Self) -> bool {
-        for (key, other_register) in &other.data {
-            if let Some(existing_register) = self.data.get(key) {
-                if existing_register.has_conflict(other_register) {
-                    return true;
-                }
+}
+
+impl<K, V> DeltaCrdt for LwwMap<K, V>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Clone + PartialEq + Send + Sync,
+{
+    fn version_vector(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        let data: HashMap<_, _> = self
+            .data
+            .iter()
+            .filter(|(_, register)| !since.includes(&register.replica_id(), register.timestamp().as_u64()))
+            .map(|(key, register)| (key.clone(), register.clone()))
+            .collect();
+        (!data.is_empty()).then(|| Self {
+            data,
+            version: self.version.clone(),
+        })
+    }
+}
+
+/// Write to an [`LwwMap`] made through an [`UndoManager`](crate::crdt::UndoManager)
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapWrite<K, V> {
+    pub key: K,
+    pub value: V,
+    pub replica_id: ReplicaId,
+}
+
+/// Write to an [`LwwMap`] recorded for undo
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapChange<K, V> {
+    pub key: K,
+    /// Value the write replaced, `None` if the key was absent
+    pub previous: Option<V>,
+    /// Value written, `None` if the key was removed
+    pub value: Option<V>,
+    /// Replica that wrote
+    pub replica_id: ReplicaId,
+}
+
+impl<K, V> Undoable for LwwMap<K, V>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    V: Clone + PartialEq + Send + Sync,
+{
+    type Edit = MapWrite<K, V>;
+    type Change = MapChange<K, V>;
+    type Output = ();
+    type Id = K;
+    type Error = Infallible;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        let MapWrite { key, value, replica_id } = edit;
+        let previous = self.get(&key).cloned();
+        self.insert(key.clone(), value.clone(), replica_id);
+        Ok((MapChange { key, previous, value: Some(value), replica_id }, ()))
+    }
+
+    /// Write back the previous value, as long as the entry still holds the written one
+    ///
+    /// The map keeps no tombstones, so undoing the write that created a key
+    /// only removes it here; replicas that merged the write keep it.
+    fn revert(&mut self, change: &Self::Change, _ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        if self.get(&change.key) != change.value.as_ref() {
+            return Ok(None);
+        }
+        match &change.previous {
+            Some(previous) => self.insert(change.key.clone(), previous.clone(), change.replica_id),
+            None => {
+                self.data.remove(&change.key);
             }
         }
-        false
+        let inverse = MapChange {
+            key: change.key.clone(),
+            previous: change.value.clone(),
+            value: change.previous.clone(),
+            replica_id: change.replica_id,
+        };
+        Ok(Some((inverse, ())))
     }
 }
 
@@ -156,6 +316,25 @@ mod tests {
         assert_eq!(map1.get(&"key2".to_string()), Some(&"value2".to_string()));
     }
 
+    #[test]
+    fn test_lww_map_merge_with_changes() {
+        let mut map1 = LwwMap::new();
+        let replica_id1 = ReplicaId::default();
+        let replica_id2 = ReplicaId::default();
+
+        map1.insert("same", 1, replica_id1);
+        map1.insert("changed", 1, replica_id1);
+        let mut map2 = map1.clone();
+        map2.insert("changed", 2, replica_id2);
+        map2.insert("added", 3, replica_id2);
+
+        let report = map1.merge_with_changes(&map2).unwrap();
+        assert_eq!(report.inserted, vec!["added"]);
+        assert_eq!(report.updated, vec!["changed"]);
+        assert!(report.removed.is_empty());
+        assert!(map1.merge_with_changes(&map2).unwrap().is_empty());
+    }
+
     #[test]
     fn test_lww_map_iteration() {
         let mut map = LwwMap::new();
@@ -181,8 +360,8 @@ mod tests {
         let replica_id2 = ReplicaId::default();
         
         // Create conflicting entries with same timestamp
-        let timestamp = chrono::Utc::now();
-        let mut reg1 = LwwRegister::new("value1", replica_id1).with_timestamp(timestamp);
+        let timestamp = HlcTimestamp::now();
+        let reg1 = LwwRegister::new("value1", replica_id1).with_timestamp(timestamp);
         let reg2 = LwwRegister::new("value2", replica_id2).with_timestamp(timestamp);
         
         map1.data.insert("key1".to_string(), reg1);
@@ -190,4 +369,54 @@ mod tests {
         
         assert!(map1.has_conflict(&map2));
     }
+
+    #[test]
+    fn test_lww_map_insert_with_clock() {
+        let mut map = LwwMap::new();
+        let replica_id = ReplicaId::default();
+        let mut clock = HybridLogicalClock::new();
+
+        map.insert_with_clock("key", 1, replica_id, &mut clock);
+        let first = map.get_register(&"key").unwrap().timestamp();
+        map.insert_with_clock("key", 2, replica_id, &mut clock);
+
+        assert_eq!(map.get(&"key"), Some(&2));
+        assert!(map.get_register(&"key").unwrap().timestamp() > first);
+        assert_eq!(map.max_timestamp(), Some(clock.last()));
+    }
+
+    #[test]
+    fn test_lww_map_delta_since() {
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut map = LwwMap::new();
+        for i in 0..100 {
+            map.insert(i, format!("value {}", i), replica1);
+        }
+
+        let mut peer = map.clone();
+        assert!(map.delta_since(&peer.version_vector()).is_none());
+
+        map.insert(7, "edited".to_string(), replica1);
+        map.insert(100, "added".to_string(), replica2);
+        let delta = map.delta_since(&peer.version_vector()).unwrap();
+        assert_eq!(delta.len(), 2);
+
+        peer.apply_delta(&delta).unwrap();
+        assert_eq!(peer.get(&7), Some(&"edited".to_string()));
+        assert_eq!(peer.get(&100), Some(&"added".to_string()));
+        assert_eq!(peer.version_vector(), map.version_vector());
+        assert!(map.delta_since(&peer.version_vector()).is_none());
+    }
+
+    #[test]
+    fn test_lww_map_serialization() {
+        let mut map = LwwMap::new();
+        map.insert("key".to_string(), 1, ReplicaId::default());
+
+        let json = serde_json::to_string(&map).unwrap();
+        let decoded: LwwMap<String, i32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.get(&"key".to_string()), Some(&1));
+        assert_eq!(decoded.version_vector(), map.version_vector());
+    }
 }
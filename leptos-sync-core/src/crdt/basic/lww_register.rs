@@ -1,13 +1,26 @@
 //! Last-Write-Wins Register implementation
 
-use super::{replica_id::ReplicaId, traits::{CRDT, Mergeable}};
+use super::{
+    hlc::{HlcTimestamp, HybridLogicalClock},
+    replica_id::ReplicaId,
+    traits::{CRDT, Mergeable, Reset},
+};
 use serde::{Deserialize, Serialize};
 
 /// Last-Write-Wins Register
+///
+/// Writes are ordered by hybrid logical clock timestamp, then replica ID.
+/// Replicas should stamp writes with their own [`HybridLogicalClock`] through
+/// [`LwwRegister::new_with_clock`] and [`LwwRegister::update_with_clock`], so
+/// their writes order after every remote write they have seen.
+///
+/// Registers stored before timestamps were clock readings, with an RFC 3339
+/// timestamp, still deserialize from JSON.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct LwwRegister<T> {
     value: T,
-    timestamp: chrono::DateTime<chrono::Utc>,
+    #[serde(deserialize_with = "super::hlc::deserialize_legacy")]
+    timestamp: HlcTimestamp,
     replica_id: ReplicaId,
 }
 
@@ -15,17 +28,30 @@ impl<T: Default> Default for LwwRegister<T> {
     fn default() -> Self {
         Self {
             value: T::default(),
-            timestamp: chrono::Utc::now(),
+            timestamp: HlcTimestamp::now(),
             replica_id: ReplicaId::default(),
         }
     }
 }
 
 impl<T> LwwRegister<T> {
+    /// Create a register stamped by the process-wide clock
+    ///
+    /// This skips the replica's clock, which has seen the timestamps of remote
+    /// writes; prefer [`LwwRegister::new_with_clock`] on a replica.
     pub fn new(value: T, replica_id: ReplicaId) -> Self {
         Self {
             value,
-            timestamp: chrono::Utc::now(),
+            timestamp: HlcTimestamp::now(),
+            replica_id,
+        }
+    }
+
+    /// Create a register stamped by the replica's clock
+    pub fn new_with_clock(value: T, replica_id: ReplicaId, clock: &mut HybridLogicalClock) -> Self {
+        Self {
+            value,
+            timestamp: clock.now(),
             replica_id,
         }
     }
@@ -34,7 +60,10 @@ impl<T> LwwRegister<T> {
         &self.value
     }
 
-    pub fn timestamp(&self) -> chrono::DateTime<chrono::Utc> {
+    /// Timestamp of the current value
+    ///
+    /// Use [`HlcTimestamp::to_datetime`] where the wall-clock time is needed.
+    pub fn timestamp(&self) -> HlcTimestamp {
         self.timestamp
     }
 
@@ -42,13 +71,25 @@ impl<T> LwwRegister<T> {
         self.replica_id
     }
 
+    /// Overwrite the value
+    ///
+    /// The new timestamp always orders after the current one, so a local write
+    /// wins over the value it replaces even if the wall clock is behind.
     pub fn update(&mut self, value: T, replica_id: ReplicaId) {
         self.value = value;
-        self.timestamp = chrono::Utc::now();
+        self.timestamp = self.timestamp.tick();
         self.replica_id = replica_id;
     }
 
-    pub fn with_timestamp(mut self, timestamp: chrono::DateTime<chrono::Utc>) -> Self {
+    /// Overwrite the value using the replica's clock for the timestamp
+    pub fn update_with_clock(&mut self, value: T, replica_id: ReplicaId, clock: &mut HybridLogicalClock) {
+        clock.witness(self.timestamp);
+        self.value = value;
+        self.timestamp = clock.now();
+        self.replica_id = replica_id;
+    }
+
+    pub fn with_timestamp(mut self, timestamp: HlcTimestamp) -> Self {
         self.timestamp = timestamp;
         self
     }
@@ -72,6 +113,13 @@ impl<T: Clone + PartialEq + Send + Sync> Mergeable for LwwRegister<T> {
     }
 }
 
+impl<T: Clone + PartialEq + Send + Sync> Reset for LwwRegister<T> {
+    fn reset(&mut self, observed: &Self) -> bool {
+        // Only a later write is left over
+        (self.timestamp, self.replica_id.0) > (observed.timestamp, observed.replica_id.0)
+    }
+}
+
 impl<T> CRDT for LwwRegister<T> {
     fn replica_id(&self) -> &ReplicaId {
         &self.replica_id
@@ -118,13 +166,43 @@ mod tests {
         let replica_id2 = ReplicaId::default();
         
         // Create registers with same timestamp but different replica IDs
-        let timestamp = chrono::Utc::now();
+        let timestamp = HlcTimestamp::now();
         let reg1 = LwwRegister::new("value1", replica_id1).with_timestamp(timestamp);
         let reg2 = LwwRegister::new("value2", replica_id2).with_timestamp(timestamp);
         
         assert!(reg1.has_conflict(&reg2));
     }
 
+    #[test]
+    fn test_lww_register_update_beats_future_timestamp() {
+        let local = ReplicaId::default();
+        let remote = ReplicaId::default();
+        // Remote replica's clock is five minutes ahead
+        let future = HlcTimestamp::new(HlcTimestamp::now().physical + 5 * 60 * 1000, 0);
+        let remote_reg = LwwRegister::new("remote", remote).with_timestamp(future);
+
+        let mut reg = LwwRegister::new("local", local);
+        reg.merge(&remote_reg).unwrap();
+        reg.update("edited after seeing remote", local);
+        assert!(reg.timestamp() > future);
+
+        let mut other = remote_reg.clone();
+        other.merge(&reg).unwrap();
+        assert_eq!(other.value(), &"edited after seeing remote");
+    }
+
+    #[test]
+    fn test_lww_register_update_with_clock() {
+        let replica_id = ReplicaId::default();
+        let mut clock = HybridLogicalClock::new();
+        let mut register = LwwRegister::new_with_clock("a", replica_id, &mut clock);
+        let first = register.timestamp();
+
+        register.update_with_clock("b", replica_id, &mut clock);
+        assert!(register.timestamp() > first);
+        assert_eq!(clock.last(), register.timestamp());
+    }
+
     #[test]
     fn test_lww_register_serialization() {
         let replica_id = ReplicaId::default();
@@ -136,4 +214,24 @@ mod tests {
         assert_eq!(register.value(), deserialized.value());
         assert_eq!(register.replica_id(), deserialized.replica_id());
     }
+
+    #[test]
+    fn test_lww_register_loads_rfc3339_timestamps() {
+        // Stored by releases whose registers carried a `chrono::DateTime<Utc>`
+        let stored = r#"{"value":"draft","timestamp":"2024-05-01T12:30:45.123456789Z","replica_id":"6a3c1f2e-8a9b-4c1d-9e2f-0a1b2c3d4e5f"}"#;
+        let register: LwwRegister<String> = serde_json::from_str(stored).unwrap();
+
+        assert_eq!(register.value(), "draft");
+        assert_eq!(register.timestamp(), HlcTimestamp::new(1_714_566_645_123, 0));
+        assert_eq!(register.replica_id().to_string(), "6a3c1f2e-8a9b-4c1d-9e2f-0a1b2c3d4e5f");
+
+        // Saved again, it uses the packed form, in JSON and in binary formats
+        let json = serde_json::to_string(&register).unwrap();
+        assert_eq!(serde_json::from_str::<LwwRegister<String>>(&json).unwrap(), register);
+        let binary = bincode::serialize(&register).unwrap();
+        assert_eq!(bincode::deserialize::<LwwRegister<String>>(&binary).unwrap(), register);
+
+        let garbled = stored.replace("2024-05-01T12", "yesterday");
+        assert!(serde_json::from_str::<LwwRegister<String>>(&garbled).is_err());
+    }
 }
@@ -2,22 +2,39 @@
 //!
 //! This module provides fundamental CRDT types including:
 //! - ReplicaId: Unique identifier for replicas
+//! - HybridLogicalClock: Per-replica clock for causally ordered timestamps
 //! - LwwRegister: Last-Write-Wins Register
 //! - LwwMap: Last-Write-Wins Map
+//! - MvRegister: Multi-Value Register keeping concurrent writes
 //! - GCounter: Grow-only Counter
+//! - PNCounter: Increment/decrement Counter
+//! - BoundedCounter: Counter that cannot drop below zero
+//! - OrSet: Observed-Remove Set with add-wins semantics
+//! - OrMap: Observed-Remove Map with nested CRDT values
+//! - VersionVector: Per-replica update summary used for delta sync
 
 pub mod counter;
+pub mod hlc;
 pub mod lww_map;
 pub mod lww_register;
+pub mod mv_register;
+pub mod or_map;
+pub mod or_set;
 pub mod replica_id;
 pub mod traits;
+pub mod version_vector;
 
 // Re-export main types for convenience
-pub use counter::GCounter;
-pub use lww_map::LwwMap;
+pub use counter::{BoundedCounter, CounterError, GCounter, PNCounter};
+pub use hlc::{ClockError, HlcTimestamp, HybridLogicalClock};
+pub use lww_map::{LwwMap, MapChange, MapWrite};
 pub use lww_register::LwwRegister;
+pub use mv_register::MvRegister;
+pub use or_map::OrMap;
+pub use or_set::{Dot, OrSet};
 pub use replica_id::ReplicaId;
-pub use traits::{CRDT, Mergeable};
+pub use traits::{CRDT, DeltaCrdt, MergeReport, Mergeable, OpCrdt, OpId, Operation, ReportingMerge, Reset};
+pub use version_vector::VersionVector;
 
 #[cfg(test)]
 mod integration_tests {
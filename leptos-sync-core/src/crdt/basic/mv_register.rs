@@ -0,0 +1,222 @@
+//! Multi-Value Register implementation
+
+use super::{
+    replica_id::ReplicaId,
+    traits::{CRDT, DeltaCrdt, Mergeable, Reset},
+    version_vector::VersionVector,
+};
+use serde::{Deserialize, Serialize};
+
+/// Multi-Value Register
+///
+/// Unlike [`super::LwwRegister`], concurrent writes are all kept and exposed by
+/// [`MvRegister::values`]. A write made after observing them replaces them all,
+/// which is how an application resolves the conflict.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MvRegister<T> {
+    /// Concurrent writes, each with the version vector it was made at
+    entries: Vec<(T, VersionVector)>,
+    /// Every write seen by this register
+    version: VersionVector,
+}
+
+impl<T> MvRegister<T>
+where
+    T: Clone + PartialEq + Send + Sync,
+{
+    /// Create an empty register
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            version: VersionVector::new(),
+        }
+    }
+
+    /// Create a register holding a single value
+    pub fn with_value(value: T, replica_id: ReplicaId) -> Self {
+        let mut register = Self::new();
+        register.update(value, replica_id);
+        register
+    }
+
+    /// Write a value, superseding every value currently held
+    pub fn update(&mut self, value: T, replica_id: ReplicaId) {
+        self.version.increment(replica_id);
+        self.entries = vec![(value, self.version.clone())];
+    }
+
+    /// Values of all concurrent writes, empty if nothing has been written
+    pub fn values(&self) -> Vec<&T> {
+        self.entries.iter().map(|(value, _)| value).collect()
+    }
+
+    /// The value, if there is exactly one
+    pub fn value(&self) -> Option<&T> {
+        match self.entries.as_slice() {
+            [(value, _)] => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Whether concurrent writes are waiting to be resolved
+    pub fn is_conflicted(&self) -> bool {
+        self.entries.len() > 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<T> Default for MvRegister<T>
+where
+    T: Clone + PartialEq + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: PartialEq> PartialEq for MvRegister<T> {
+    fn eq(&self, other: &Self) -> bool {
+        // Entries are a set; their order depends on merge order
+        self.version == other.version
+            && self.entries.len() == other.entries.len()
+            && self.entries.iter().all(|entry| other.entries.contains(entry))
+    }
+}
+
+impl<T> Mergeable for MvRegister<T>
+where
+    T: Clone + PartialEq + Send + Sync,
+{
+    type Error = std::io::Error;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let mut entries: Vec<(T, VersionVector)> = Vec::new();
+        for entry in self.entries.iter().chain(other.entries.iter()) {
+            if !entries.iter().any(|(_, version)| *version == entry.1) {
+                entries.push(entry.clone());
+            }
+        }
+        let dominated = |version: &VersionVector| {
+            entries.iter().any(|(_, other_version)| other_version > version)
+        };
+        self.entries = entries
+            .iter()
+            .filter(|(_, version)| !dominated(version))
+            .cloned()
+            .collect();
+        self.version.merge(&other.version);
+        Ok(())
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        self.entries.iter().any(|(_, version)| {
+            other
+                .entries
+                .iter()
+                .any(|(_, other_version)| version.is_concurrent(other_version))
+        })
+    }
+}
+
+impl<T> Reset for MvRegister<T>
+where
+    T: Clone + PartialEq + Send + Sync,
+{
+    fn reset(&mut self, observed: &Self) -> bool {
+        // Writes are never split, so the register stays whole
+        !observed.version.dominates(&self.version)
+    }
+}
+
+impl<T> CRDT for MvRegister<T> {
+    fn replica_id(&self) -> &ReplicaId {
+        // MvRegister is shared by all replicas, which pass their ID to each write
+        static DEFAULT_REPLICA: std::sync::LazyLock<ReplicaId> = std::sync::LazyLock::new(|| ReplicaId::from(uuid::Uuid::nil()));
+        &DEFAULT_REPLICA
+    }
+}
+
+impl<T> DeltaCrdt for MvRegister<T>
+where
+    T: Clone + PartialEq + Send + Sync,
+{
+    fn version_vector(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        // The register only holds its latest writes, so the delta is the whole state
+        (!since.dominates(&self.version)).then(|| self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    #[test]
+    fn test_mv_register_keeps_concurrent_writes() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut register1 = MvRegister::with_value("draft", replica1);
+        let mut register2 = register1.clone();
+
+        register1.update("title A", replica1);
+        register2.update("title B", replica2);
+        assert!(register1.has_conflict(&register2));
+
+        register1.merge(&register2).unwrap();
+        register2.merge(&register1).unwrap();
+        let mut values = register1.values();
+        values.sort();
+        assert_eq!(values, vec![&"title A", &"title B"]);
+        assert!(register1.is_conflicted());
+        assert_eq!(register1.value(), None);
+        assert_eq!(register1, register2);
+    }
+
+    #[test]
+    fn test_mv_register_later_write_collapses_values() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut register1 = MvRegister::new();
+        let mut register2 = MvRegister::new();
+        register1.update(1, replica1);
+        register2.update(2, replica2);
+        register1.merge(&register2).unwrap();
+
+        // Resolving the conflict dominates both alternatives
+        register1.update(3, replica1);
+        register2.merge(&register1).unwrap();
+        assert_eq!(register2.values(), vec![&3]);
+        assert!(!register1.has_conflict(&register2));
+
+        // A stale state does not bring the old values back
+        let mut stale = MvRegister::new();
+        stale.update(2, replica2);
+        register2.merge(&stale).unwrap();
+        assert_eq!(register2.value(), Some(&3));
+    }
+
+    #[test]
+    fn test_mv_register_delta_and_serialization() {
+        let replica = create_replica(1);
+        let mut register = MvRegister::with_value("a".to_string(), replica);
+        let peer = register.clone();
+        assert!(register.delta_since(&peer.version_vector()).is_none());
+
+        register.update("b".to_string(), replica);
+        let delta = register.delta_since(&peer.version_vector()).unwrap();
+        assert_eq!(delta.value(), Some(&"b".to_string()));
+
+        let json = serde_json::to_string(&register).unwrap();
+        let decoded: MvRegister<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, register);
+    }
+}
@@ -0,0 +1,418 @@
+//! Observed-Remove Map implementation
+
+use super::{
+    or_set::{join_dots, Dot},
+    replica_id::ReplicaId,
+    traits::{CRDT, Mergeable, Reset},
+    version_vector::VersionVector,
+};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+/// Updates of a key that are still live, with the value they add up to
+#[derive(Debug, Clone)]
+struct OrMapEntry<C> {
+    /// What each update added, by the dot that tagged it, in dot order
+    contributions: Vec<(Dot, C)>,
+    /// Merge of all contributions
+    value: C,
+}
+
+impl<C: Mergeable> OrMapEntry<C> {
+    fn new(dot: Dot, value: C) -> Self {
+        Self {
+            contributions: vec![(dot, value.clone())],
+            value,
+        }
+    }
+
+    /// Rebuild an entry from its contributions, `None` if there are none
+    fn from_contributions(contributions: Vec<(Dot, C)>) -> Result<Option<Self>, C::Error> {
+        let mut parts = contributions.iter().map(|(_, contribution)| contribution);
+        let Some(first) = parts.next() else {
+            return Ok(None);
+        };
+        let mut value = first.clone();
+        for part in parts {
+            value.merge(part)?;
+        }
+        Ok(Some(Self { contributions, value }))
+    }
+
+    fn dots(&self) -> BTreeSet<Dot> {
+        self.contributions.iter().map(|(dot, _)| *dot).collect()
+    }
+
+    fn contribution(&self, dot: &Dot) -> Option<&(Dot, C)> {
+        let index = self.contributions.binary_search_by(|(other, _)| other.cmp(dot)).ok()?;
+        self.contributions.get(index)
+    }
+}
+
+impl<C: Reset> OrMapEntry<C> {
+    /// Record the update tagged `dot`, which left the key at `value`
+    fn record(&mut self, dot: Dot, value: C) {
+        let mut added = value.clone();
+        added.reset(&self.value);
+        // Contributions the new one covers would never be needed on their own
+        self.contributions
+            .retain(|(_, contribution)| contribution.clone().reset(&added));
+        let index = self.contributions.partition_point(|(other, _)| *other < dot);
+        self.contributions.insert(index, (dot, added));
+        self.value = value;
+    }
+}
+
+impl<C: PartialEq> PartialEq for OrMapEntry<C> {
+    fn eq(&self, other: &Self) -> bool {
+        // The value is derived from the contributions, though its layout may depend on merge order
+        self.contributions == other.contributions
+    }
+}
+
+impl<C: Serialize> Serialize for OrMapEntry<C> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.contributions.serialize(serializer)
+    }
+}
+
+impl<'de, C> Deserialize<'de> for OrMapEntry<C>
+where
+    C: Deserialize<'de> + Mergeable,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut contributions = Vec::<(Dot, C)>::deserialize(deserializer)?;
+        contributions.sort_by_key(|(dot, _)| *dot);
+        Self::from_contributions(contributions)
+            .map_err(D::Error::custom)?
+            .ok_or_else(|| D::Error::custom("map entry without updates"))
+    }
+}
+
+/// Observed-Remove Map whose values are themselves CRDTs
+///
+/// Values of the same key are merged recursively. Keys follow [`super::OrSet`]
+/// semantics: a remove only discards the updates it has observed, so a key
+/// updated concurrently with its removal survives. Each update is kept as just
+/// what it added (see [`Reset`]), so the key then holds only the concurrent
+/// updates, not the state the remove cleared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "K: Serialize, C: Serialize",
+    deserialize = "K: Deserialize<'de> + Eq + Hash, C: Deserialize<'de> + Mergeable"
+))]
+pub struct OrMap<K, C> {
+    entries: HashMap<K, OrMapEntry<C>>,
+    /// Every dot ever seen, including those of removed keys
+    context: VersionVector,
+}
+
+impl<K, C> OrMap<K, C>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    C: Mergeable,
+{
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            context: VersionVector::new(),
+        }
+    }
+
+    /// Insert a value, merging it into the existing value of the key if there is one
+    pub fn insert(&mut self, key: K, value: C, replica_id: ReplicaId) -> Result<Dot, C::Error>
+    where
+        C: Reset,
+    {
+        let dot = Dot::next(replica_id, &mut self.context);
+        match self.entries.get_mut(&key) {
+            Some(entry) => {
+                let mut merged = entry.value.clone();
+                merged.merge(&value)?;
+                entry.record(dot, merged);
+            }
+            None => {
+                self.entries.insert(key, OrMapEntry::new(dot, value));
+            }
+        }
+        Ok(dot)
+    }
+
+    /// Update the value of a key in place, starting from `C::default()` if absent
+    pub fn update<F>(&mut self, key: K, replica_id: ReplicaId, f: F) -> Dot
+    where
+        C: Reset + Default,
+        F: FnOnce(&mut C),
+    {
+        self.update_with(key, replica_id, C::default, f)
+    }
+
+    /// Update the value of a key in place, starting from `init()` if absent
+    pub fn update_with<I, F>(&mut self, key: K, replica_id: ReplicaId, init: I, f: F) -> Dot
+    where
+        C: Reset,
+        I: FnOnce() -> C,
+        F: FnOnce(&mut C),
+    {
+        let dot = Dot::next(replica_id, &mut self.context);
+        match self.entries.get_mut(&key) {
+            Some(entry) => {
+                let mut value = entry.value.clone();
+                f(&mut value);
+                entry.record(dot, value);
+            }
+            None => {
+                let mut value = init();
+                f(&mut value);
+                self.entries.insert(key, OrMapEntry::new(dot, value));
+            }
+        }
+        dot
+    }
+
+    /// Update the value of a key in place, or return `None` without recording anything if absent
+    pub fn update_existing<F, R>(&mut self, key: &K, replica_id: ReplicaId, f: F) -> Option<R>
+    where
+        C: Reset,
+        F: FnOnce(&mut C) -> R,
+    {
+        let entry = self.entries.get_mut(key)?;
+        let mut value = entry.value.clone();
+        let result = f(&mut value);
+        entry.record(Dot::next(replica_id, &mut self.context), value);
+        Some(result)
+    }
+
+    /// Remove a key, returning its value
+    ///
+    /// Updates made concurrently elsewhere bring the key back with just what they added.
+    pub fn remove(&mut self, key: &K) -> Option<C> {
+        self.entries.remove(key).map(|entry| entry.value)
+    }
+
+    pub fn get(&self, key: &K) -> Option<&C> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &K> {
+        self.entries.keys()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &C)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Operations seen by this map
+    pub fn context(&self) -> &VersionVector {
+        &self.context
+    }
+}
+
+impl<K, C> Default for OrMap<K, C>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    C: Mergeable,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, C> PartialEq for OrMap<K, C>
+where
+    K: Eq + Hash,
+    C: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries && self.context == other.context
+    }
+}
+
+impl<K, C> Mergeable for OrMap<K, C>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    C: Mergeable,
+{
+    type Error = C::Error;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let keys: HashSet<K> = self.entries.keys().chain(other.entries.keys()).cloned().collect();
+        for key in keys {
+            let ours = self.entries.get(&key);
+            let theirs = other.entries.get(&key);
+            let our_dots = ours.map(OrMapEntry::dots).unwrap_or_default();
+            let dots = join_dots(&our_dots, &self.context, theirs.map(OrMapEntry::dots).as_ref(), &other.context);
+            if theirs.is_none() && dots == our_dots {
+                continue;
+            }
+
+            let mut contributions = Vec::with_capacity(dots.len());
+            for dot in &dots {
+                let our_part = ours.and_then(|entry| entry.contribution(dot));
+                let their_part = theirs.and_then(|entry| entry.contribution(dot));
+                match (our_part, their_part) {
+                    (Some((_, our_part)), Some((_, their_part))) => {
+                        // Normally identical, unless a replica was cloned and both copies edited
+                        let mut part = our_part.clone();
+                        part.merge(their_part)?;
+                        contributions.push((*dot, part));
+                    }
+                    (Some(part), None) | (None, Some(part)) => contributions.push(part.clone()),
+                    (None, None) => {}
+                }
+            }
+            match OrMapEntry::from_contributions(contributions)? {
+                Some(entry) => self.entries.insert(key, entry),
+                None => self.entries.remove(&key),
+            };
+        }
+        self.context.merge(&other.context);
+        Ok(())
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        self.entries.iter().any(|(key, entry)| {
+            other
+                .entries
+                .get(key)
+                .is_some_and(|their_entry| entry.value.has_conflict(&their_entry.value))
+        })
+    }
+}
+
+/// Maps are never split: dropping a dot the context covers would remove its key
+impl<K, C> Reset for OrMap<K, C>
+where
+    K: Clone + Eq + Hash + Send + Sync,
+    C: Mergeable,
+{
+    fn reset(&mut self, observed: &Self) -> bool {
+        // Removes leave no trace in the context, so look for observed keys removed here too
+        let removed = observed.entries.iter().any(|(key, entry)| {
+            entry.contributions.iter().any(|(dot, _)| {
+                self.context.includes(&dot.replica, dot.counter)
+                    && self.entries.get(key).is_none_or(|ours| ours.contribution(dot).is_none())
+            })
+        });
+        removed || !observed.context.dominates(&self.context)
+    }
+}
+
+impl<K, C> CRDT for OrMap<K, C> {
+    fn replica_id(&self) -> &ReplicaId {
+        // OrMap is shared by all replicas, which pass their ID to each operation
+        static DEFAULT_REPLICA: std::sync::LazyLock<ReplicaId> = std::sync::LazyLock::new(|| ReplicaId::from(uuid::Uuid::nil()));
+        &DEFAULT_REPLICA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{GCounter, LwwRegister, OrSet};
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    #[test]
+    fn test_or_map_merges_nested_counters() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut visits1: OrMap<&str, GCounter> = OrMap::new();
+        let mut visits2: OrMap<&str, GCounter> = OrMap::new();
+
+        visits1.update("home", replica1, |counter| counter.increment(replica1));
+        visits2.update("home", replica2, |counter| counter.increment_by(replica2, 2));
+        visits2.update("about", replica2, |counter| counter.increment(replica2));
+
+        visits1.merge(&visits2).unwrap();
+        assert_eq!(visits1.get(&"home").unwrap().value(), 3);
+        assert_eq!(visits1.get(&"about").unwrap().value(), 1);
+        assert_eq!(visits1.len(), 2);
+    }
+
+    #[test]
+    fn test_or_map_update_wins_over_concurrent_remove() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut map1: OrMap<String, GCounter> = OrMap::new();
+        map1.update("stays".to_string(), replica1, |counter| counter.increment(replica1));
+        map1.update("goes".to_string(), replica1, |counter| counter.increment(replica1));
+        let mut map2 = map1.clone();
+
+        map1.remove(&"stays".to_string());
+        map1.remove(&"goes".to_string());
+        map2.update("stays".to_string(), replica2, |counter| counter.increment(replica2));
+
+        map1.merge(&map2).unwrap();
+        map2.merge(&map1).unwrap();
+        assert!(!map1.contains_key(&"goes".to_string()));
+        // The remove cleared the increment it saw; only the concurrent one is left
+        assert_eq!(map1.get(&"stays".to_string()).unwrap().value(), 1);
+        assert_eq!(map1, map2);
+    }
+
+    #[test]
+    fn test_or_map_nested_documents() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        // project -> tasks -> tags
+        let mut project1: OrMap<String, OrMap<String, OrSet<String>>> = OrMap::new();
+        project1.update("tasks".to_string(), replica1, |tasks| {
+            tasks.update("task-1".to_string(), replica1, |tags| {
+                tags.add("urgent".to_string(), replica1);
+            });
+        });
+        let mut project2 = project1.clone();
+
+        project1.update("tasks".to_string(), replica1, |tasks| {
+            tasks.update("task-1".to_string(), replica1, |tags| {
+                tags.remove(&"urgent".to_string());
+            });
+        });
+        project2.update("tasks".to_string(), replica2, |tasks| {
+            tasks.update("task-1".to_string(), replica2, |tags| {
+                tags.add("backend".to_string(), replica2);
+            });
+        });
+
+        project1.merge(&project2).unwrap();
+        let tags = project1.get(&"tasks".to_string()).unwrap().get(&"task-1".to_string()).unwrap();
+        assert!(!tags.contains(&"urgent".to_string()));
+        assert!(tags.contains(&"backend".to_string()));
+    }
+
+    #[test]
+    fn test_or_map_of_registers() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut map1 = OrMap::new();
+        map1.insert("title", LwwRegister::new("draft", replica1), replica1).unwrap();
+        let mut map2 = map1.clone();
+
+        map2.update_with(
+            "title",
+            replica2,
+            || LwwRegister::new("", replica2),
+            |register| register.update("final", replica2),
+        );
+
+        map1.merge(&map2).unwrap();
+        assert_eq!(map1.get(&"title").unwrap().value(), &"final");
+
+        let json = serde_json::to_string(&map1).unwrap();
+        let decoded: OrMap<String, LwwRegister<String>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.get(&"title".to_string()).unwrap().value(), "final");
+    }
+}
@@ -0,0 +1,266 @@
+//! Observed-Remove Set implementation
+
+use super::{
+    replica_id::ReplicaId,
+    traits::{CRDT, Mergeable, Reset},
+    version_vector::VersionVector,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+/// Unique tag of a single add or update, made by `replica` as its `counter`-th operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Dot {
+    pub replica: ReplicaId,
+    pub counter: u64,
+}
+
+impl Dot {
+    /// Allocate the next dot of `replica`, recording it in `context`
+    pub(super) fn next(replica: ReplicaId, context: &mut VersionVector) -> Self {
+        Self {
+            replica,
+            counter: context.increment(replica),
+        }
+    }
+}
+
+/// Dots of an element that survive a merge
+///
+/// A dot is kept if both sides still have it, or if the side that lacks it has
+/// never seen it (so its absence is not a removal).
+pub(super) fn join_dots(
+    ours: &BTreeSet<Dot>,
+    our_context: &VersionVector,
+    theirs: Option<&BTreeSet<Dot>>,
+    their_context: &VersionVector,
+) -> BTreeSet<Dot> {
+    let empty = BTreeSet::new();
+    let theirs = theirs.unwrap_or(&empty);
+    let unseen = |dot: &Dot, context: &VersionVector| !context.includes(&dot.replica, dot.counter);
+
+    ours.iter()
+        .filter(|dot| theirs.contains(dot) || unseen(dot, their_context))
+        .chain(theirs.iter().filter(|dot| unseen(dot, our_context)))
+        .copied()
+        .collect()
+}
+
+/// Observed-Remove Set with add-wins semantics
+///
+/// Every add is tagged with a fresh [`Dot`]; a remove only discards the dots it
+/// has observed, so an add concurrent with a remove survives the merge.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "T: Serialize",
+    deserialize = "T: Deserialize<'de> + Eq + Hash"
+))]
+pub struct OrSet<T> {
+    entries: HashMap<T, BTreeSet<Dot>>,
+    /// Every dot ever seen, including those of removed elements
+    context: VersionVector,
+}
+
+impl<T> OrSet<T>
+where
+    T: Clone + Eq + Hash + Send + Sync,
+{
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            context: VersionVector::new(),
+        }
+    }
+
+    /// Add a value, returning the dot that tags this add
+    pub fn add(&mut self, value: T, replica_id: ReplicaId) -> Dot {
+        let dot = Dot::next(replica_id, &mut self.context);
+        // Earlier dots of the value are all observed here, so the new one supersedes them
+        self.entries.insert(value, BTreeSet::from([dot]));
+        dot
+    }
+
+    /// Remove a value, returning whether it was present
+    pub fn remove(&mut self, value: &T) -> bool {
+        self.entries.remove(value).is_some()
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.entries.contains_key(value)
+    }
+
+    /// Dots currently supporting a value
+    pub fn dots(&self, value: &T) -> Option<&BTreeSet<Dot>> {
+        self.entries.get(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Remove every value currently observed
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Operations seen by this set
+    pub fn context(&self) -> &VersionVector {
+        &self.context
+    }
+}
+
+impl<T> Default for OrSet<T>
+where
+    T: Clone + Eq + Hash + Send + Sync,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> PartialEq for OrSet<T>
+where
+    T: Eq + Hash,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.entries == other.entries && self.context == other.context
+    }
+}
+
+impl<T> Mergeable for OrSet<T>
+where
+    T: Clone + Eq + Hash + Send + Sync,
+{
+    type Error = std::io::Error;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let empty = BTreeSet::new();
+        let values: HashSet<T> = self.entries.keys().chain(other.entries.keys()).cloned().collect();
+        for value in values {
+            let ours = self.entries.get(&value).unwrap_or(&empty);
+            let dots = join_dots(ours, &self.context, other.entries.get(&value), &other.context);
+            if dots.is_empty() {
+                self.entries.remove(&value);
+            } else {
+                self.entries.insert(value, dots);
+            }
+        }
+        self.context.merge(&other.context);
+        Ok(())
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        // Concurrent add and remove always resolve to add-wins
+        false
+    }
+}
+
+impl<T> Reset for OrSet<T>
+where
+    T: Clone + Eq + Hash + Send + Sync,
+{
+    fn reset(&mut self, observed: &Self) -> bool {
+        // The set stays whole: dropping a dot the context covers would remove its value.
+        // Removes leave no trace in the context, so look for observed dots removed here too.
+        let removed = observed.entries.iter().any(|(value, dots)| {
+            dots.iter().any(|dot| {
+                self.context.includes(&dot.replica, dot.counter)
+                    && !self.entries.get(value).is_some_and(|ours| ours.contains(dot))
+            })
+        });
+        removed || !observed.context.dominates(&self.context)
+    }
+}
+
+impl<T> CRDT for OrSet<T> {
+    fn replica_id(&self) -> &ReplicaId {
+        // OrSet is shared by all replicas, which pass their ID to each operation
+        static DEFAULT_REPLICA: std::sync::LazyLock<ReplicaId> = std::sync::LazyLock::new(|| ReplicaId::from(uuid::Uuid::nil()));
+        &DEFAULT_REPLICA
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    #[test]
+    fn test_or_set_add_remove() {
+        let replica = create_replica(1);
+        let mut set = OrSet::new();
+
+        let dot = set.add("a", replica);
+        set.add("b", replica);
+        assert_eq!(dot, Dot { replica, counter: 1 });
+        assert!(set.contains(&"a"));
+        assert_eq!(set.len(), 2);
+
+        assert!(set.remove(&"a"));
+        assert!(!set.remove(&"a"));
+        assert!(!set.contains(&"a"));
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_or_set_concurrent_add_wins() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut set1 = OrSet::new();
+        set1.add("tag", replica1);
+        let mut set2 = set1.clone();
+
+        // Replica 1 removes the tag while replica 2 re-adds it
+        set1.remove(&"tag");
+        set2.add("tag", replica2);
+
+        set1.merge(&set2).unwrap();
+        set2.merge(&set1).unwrap();
+        assert!(set1.contains(&"tag"));
+        assert_eq!(set1, set2);
+    }
+
+    #[test]
+    fn test_or_set_observed_remove_propagates() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut set1 = OrSet::new();
+        set1.add("x", replica1);
+        set1.add("y", replica1);
+        let mut set2 = OrSet::new();
+        set2.merge(&set1).unwrap();
+        let stale = set1.clone();
+
+        set2.remove(&"x");
+        set2.add("z", replica2);
+        set1.merge(&set2).unwrap();
+
+        assert!(!set1.contains(&"x"));
+        assert!(set1.contains(&"y") && set1.contains(&"z"));
+
+        // Merging a state from before the remove must not resurrect the value
+        set1.merge(&stale).unwrap();
+        assert_eq!(set1.len(), 2);
+    }
+
+    #[test]
+    fn test_or_set_serialization() {
+        let mut set = OrSet::new();
+        set.add("a".to_string(), create_replica(1));
+
+        let json = serde_json::to_string(&set).unwrap();
+        let decoded: OrSet<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, set);
+    }
+}
@@ -1,6 +1,7 @@
 //! Core CRDT traits
 
-use super::replica_id::ReplicaId;
+use super::{replica_id::ReplicaId, version_vector::VersionVector};
+use serde::{Deserialize, Serialize};
 
 /// Trait for types that can be merged with other instances
 pub trait Mergeable: Clone + Send + Sync {
@@ -13,6 +14,168 @@ pub trait Mergeable: Clone + Send + Sync {
     fn has_conflict(&self, other: &Self) -> bool;
 }
 
+/// Trait for states that can forget what another state already holds
+///
+/// [`super::OrMap`] records each update of a key as just what the update added,
+/// so that removing the key discards exactly the updates the remove observed.
+pub trait Reset: Mergeable {
+    /// Drop what `observed` already holds, returning whether anything is left
+    ///
+    /// When `observed` is part of `self`, merging the result into `observed`
+    /// must give back `self`. States that cannot be split stay whole and only
+    /// report whether `observed` is missing part of them.
+    fn reset(&mut self, observed: &Self) -> bool;
+}
+
+/// Keys of the entries or elements a merge changed
+///
+/// Lets a view patch only what a remote merge touched instead of re-rendering
+/// the whole document. Each key appears in at most one list.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MergeReport<K> {
+    /// Keys that became visible
+    pub inserted: Vec<K>,
+    /// Keys that stayed visible but whose value or placement changed
+    pub updated: Vec<K>,
+    /// Keys that stopped being visible
+    pub removed: Vec<K>,
+}
+
+impl<K> MergeReport<K> {
+    /// Create an empty report
+    pub fn new() -> Self {
+        Self {
+            inserted: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+        }
+    }
+
+    /// Record the outcome for one key from its visibility before and after the merge
+    pub fn record(&mut self, key: K, was_visible: bool, is_visible: bool, changed: bool) {
+        match (was_visible, is_visible) {
+            (false, true) => self.inserted.push(key),
+            (true, false) => self.removed.push(key),
+            (true, true) if changed => self.updated.push(key),
+            _ => {}
+        }
+    }
+
+    /// Whether the merge changed nothing visible
+    pub fn is_empty(&self) -> bool {
+        self.inserted.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+
+    /// Number of changed keys
+    pub fn len(&self) -> usize {
+        self.inserted.len() + self.updated.len() + self.removed.len()
+    }
+
+    /// Convert every key, e.g. to a type shared by several reports
+    pub fn map<L>(self, mut f: impl FnMut(K) -> L) -> MergeReport<L> {
+        MergeReport {
+            inserted: self.inserted.into_iter().map(&mut f).collect(),
+            updated: self.updated.into_iter().map(&mut f).collect(),
+            removed: self.removed.into_iter().map(&mut f).collect(),
+        }
+    }
+
+    /// Append another report's keys
+    pub fn extend(&mut self, other: MergeReport<K>) {
+        self.inserted.extend(other.inserted);
+        self.updated.extend(other.updated);
+        self.removed.extend(other.removed);
+    }
+}
+
+impl<K> Default for MergeReport<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Trait for CRDTs that can tell which keys a merge changed
+///
+/// Implementations merge exactly like [`Mergeable::merge`], which usually
+/// just discards the report.
+pub trait ReportingMerge: Mergeable {
+    /// Key of the entries or elements a report refers to
+    type Key;
+
+    /// Merge another instance and report the keys whose visible state changed
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<Self::Key>, Self::Error>;
+}
+
+/// Trait for delta-state CRDTs that can ship only what a peer is missing
+///
+/// A delta is itself a state of the same type, so merging it is always safe.
+/// It is only guaranteed to be complete for a replica whose version vector
+/// dominates the one it was computed against.
+pub trait DeltaCrdt: Mergeable {
+    /// Updates reflected in this state
+    fn version_vector(&self) -> VersionVector;
+
+    /// The part of this state not covered by `since`, or `None` if there is nothing new
+    fn delta_since(&self, since: &VersionVector) -> Option<Self>;
+
+    /// Merge a delta produced by [`DeltaCrdt::delta_since`]
+    fn apply_delta(&mut self, delta: &Self) -> Result<(), Self::Error> {
+        self.merge(delta)
+    }
+}
+
+/// Identity of an operation: the replica that prepared it and its sequence number there
+///
+/// Sequence numbers only have to grow per replica; logical and hybrid
+/// timestamps both qualify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct OpId {
+    /// Replica that prepared the operation
+    pub replica: ReplicaId,
+    /// Sequence number on that replica
+    pub seq: u64,
+}
+
+impl OpId {
+    /// Create a new operation ID
+    pub fn new(replica: ReplicaId, seq: u64) -> Self {
+        Self { replica, seq }
+    }
+}
+
+/// Operation replicated by an operation-based CRDT
+pub trait Operation: Clone + Send + Sync {
+    /// Unique ID, so that every operation takes effect at most once
+    fn id(&self) -> OpId;
+}
+
+/// Trait for operation-based CRDTs (CmRDTs), which replicate operations instead of states
+///
+/// A local change is prepared against the local state, which yields an
+/// operation. The operation is then applied on every replica, the local one
+/// included. Operations must be delivered in causal order; applying one a
+/// second time has no effect.
+pub trait OpCrdt {
+    /// Local change requested by the application
+    type Intent;
+    /// Operation shipped to other replicas
+    type Op: Operation;
+    type Error: std::error::Error + Send + Sync + 'static;
+
+    /// Turn a local change into an operation, without applying it yet
+    fn prepare(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error>;
+
+    /// Apply an operation, returning `false` if it had been applied already
+    fn apply(&mut self, op: &Self::Op) -> Result<bool, Self::Error>;
+
+    /// Prepare and apply a local change, returning the operation to ship
+    fn perform(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error> {
+        let op = self.prepare(intent)?;
+        self.apply(&op)?;
+        Ok(op)
+    }
+}
+
 /// Trait for CRDTs that have a replica ID
 pub trait CRDT {
     fn replica_id(&self) -> &ReplicaId;
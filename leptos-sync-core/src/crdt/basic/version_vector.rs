@@ -0,0 +1,174 @@
+//! Version vectors for tracking causal history across replicas
+
+use super::{hlc::HlcTimestamp, replica_id::ReplicaId};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+/// Version vector mapping each replica to the latest of its updates that has been seen
+///
+/// Versions are plain counters, or packed [`HlcTimestamp`]s for CRDTs whose
+/// updates are already stamped by a hybrid logical clock. Either way they must
+/// increase with every update made by the same replica.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionVector {
+    versions: BTreeMap<ReplicaId, u64>,
+}
+
+impl VersionVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Latest version seen from `replica`, or 0 if none
+    pub fn get(&self, replica: &ReplicaId) -> u64 {
+        self.versions.get(replica).copied().unwrap_or(0)
+    }
+
+    /// Record a new update by `replica`, returning its version
+    pub fn increment(&mut self, replica: ReplicaId) -> u64 {
+        let version = self.versions.entry(replica).or_insert(0);
+        *version += 1;
+        *version
+    }
+
+    /// Record that updates from `replica` up to `version` have been seen
+    pub fn observe(&mut self, replica: ReplicaId, version: u64) {
+        let current = self.versions.entry(replica).or_insert(0);
+        *current = (*current).max(version);
+    }
+
+    /// Record a local update by `replica` at `timestamp`
+    ///
+    /// The timestamp is bumped past the replica's previous update if needed, so
+    /// versions stay strictly increasing even when callers pass stale timestamps.
+    pub fn advance(&mut self, replica: ReplicaId, timestamp: HlcTimestamp) -> HlcTimestamp {
+        let previous = HlcTimestamp::from_u64(self.get(&replica));
+        let timestamp = timestamp.max(previous.successor());
+        self.observe(replica, timestamp.as_u64());
+        timestamp
+    }
+
+    /// Whether the update `version` by `replica` has been seen
+    pub fn includes(&self, replica: &ReplicaId, version: u64) -> bool {
+        version <= self.get(replica)
+    }
+
+    /// Pointwise maximum of both vectors
+    pub fn merge(&mut self, other: &Self) {
+        for (replica, version) in &other.versions {
+            self.observe(*replica, *version);
+        }
+    }
+
+    /// Pointwise minimum of both vectors: the updates seen by both
+    pub fn meet(&self, other: &Self) -> Self {
+        let versions = self
+            .versions
+            .iter()
+            .filter_map(|(replica, version)| {
+                let common = (*version).min(other.get(replica));
+                (common > 0).then_some((*replica, common))
+            })
+            .collect();
+        Self { versions }
+    }
+
+    /// Whether every update seen by `other` has also been seen by `self`
+    pub fn dominates(&self, other: &Self) -> bool {
+        other
+            .versions
+            .iter()
+            .all(|(replica, version)| self.includes(replica, *version))
+    }
+
+    /// Whether neither vector dominates the other
+    pub fn is_concurrent(&self, other: &Self) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&ReplicaId, &u64)> {
+        self.versions.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+}
+
+impl PartialOrd for VersionVector {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self.dominates(other), other.dominates(self)) {
+            (true, true) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Greater),
+            (false, true) => Some(Ordering::Less),
+            (false, false) => None,
+        }
+    }
+}
+
+impl FromIterator<(ReplicaId, u64)> for VersionVector {
+    fn from_iter<I: IntoIterator<Item = (ReplicaId, u64)>>(iter: I) -> Self {
+        let mut vector = Self::new();
+        for (replica, version) in iter {
+            vector.observe(replica, version);
+        }
+        vector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    #[test]
+    fn test_increment_and_merge() {
+        let (r1, r2) = (create_replica(1), create_replica(2));
+        let mut a = VersionVector::new();
+        let mut b = VersionVector::new();
+
+        assert_eq!(a.increment(r1), 1);
+        assert_eq!(a.increment(r1), 2);
+        b.increment(r2);
+
+        assert!(a.is_concurrent(&b));
+        a.merge(&b);
+        assert_eq!(a.get(&r1), 2);
+        assert_eq!(a.get(&r2), 1);
+        assert!(a > b);
+        assert!(a.dominates(&b) && !b.dominates(&a));
+    }
+
+    #[test]
+    fn test_meet_keeps_common_history() {
+        let (r1, r2) = (create_replica(1), create_replica(2));
+        let a: VersionVector = [(r1, 5), (r2, 1)].into_iter().collect();
+        let b: VersionVector = [(r1, 3)].into_iter().collect();
+
+        let meet = a.meet(&b);
+        assert_eq!(meet.get(&r1), 3);
+        assert_eq!(meet.get(&r2), 0);
+        assert_eq!(meet.len(), 1);
+    }
+
+    #[test]
+    fn test_advance_never_goes_backwards() {
+        let replica = create_replica(1);
+        let mut vector = VersionVector::new();
+
+        let first = vector.advance(replica, HlcTimestamp::new(100, 0));
+        let stale = vector.advance(replica, HlcTimestamp::new(50, 0));
+        assert_eq!(first, HlcTimestamp::new(100, 0));
+        assert!(stale > first);
+        assert_eq!(vector.get(&replica), stale.as_u64());
+    }
+}
@@ -3,8 +3,12 @@
 //! This module provides a framework for users to define their own CRDT types
 //! using declarative macros and trait implementations.
 
-use crate::crdt::{CRDT, Mergeable, ReplicaId};
-use serde::{Deserialize, Serialize};
+use crate::crdt::{
+    AdvancedCrdtError, BoundedCounter, CRDT, GCounter, Lseq, MergeReport, Mergeable, PNCounter, ReplicaId,
+    ReportingMerge, Rga,
+};
+use crate::crdt::undo::{IdMap, Reverted, Undoable};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
@@ -24,6 +28,8 @@ pub enum BuilderError {
     SerializationError(String),
     /// Merge operation failed
     MergeError(String),
+    /// No migration leads to the requested schema version
+    MigrationError(String),
 }
 
 impl fmt::Display for BuilderError {
@@ -35,6 +41,7 @@ impl fmt::Display for BuilderError {
             BuilderError::UnsupportedStrategy(strategy) => write!(f, "Unsupported strategy: {}", strategy),
             BuilderError::SerializationError(msg) => write!(f, "Serialization error: {}", msg),
             BuilderError::MergeError(msg) => write!(f, "Merge error: {}", msg),
+            BuilderError::MigrationError(msg) => write!(f, "Migration error: {}", msg),
         }
     }
 }
@@ -52,6 +59,10 @@ pub enum CrdtStrategy {
     RemoveWins,
     /// Grow-Only Counter
     GCounter,
+    /// Increment/decrement counter
+    PNCounter,
+    /// Counter that cannot drop below zero (escrow rights per replica)
+    BoundedCounter,
     /// Multi-Value Register
     MvRegister,
     /// Replicated Growable Array
@@ -62,6 +73,11 @@ pub enum CrdtStrategy {
     YjsTree,
     /// Directed Acyclic Graph
     Dag,
+    /// Nested record with its own fields
+    ///
+    /// The field value stays `null`; the record is reached through
+    /// [`CustomCrdt::record`] and [`CustomCrdt::get_record`].
+    Record(Box<CrdtBuilderConfig>),
 }
 
 /// Field configuration for CRDT builder
@@ -86,6 +102,75 @@ pub struct CrdtBuilderConfig {
     pub fields: Vec<FieldConfig>,
     /// Replica ID field name (optional, defaults to auto-generated)
     pub replica_id_field: Option<String>,
+    /// Schema version, starting at 1
+    #[serde(default = "first_schema_version")]
+    pub version: u32,
+    /// Migrations from every earlier schema version to the next
+    #[serde(default)]
+    pub migrations: MigrationRegistry,
+}
+
+fn first_schema_version() -> u32 {
+    1
+}
+
+/// Change applied to a replica when moving it to the next schema version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum MigrationStep {
+    /// Add a field, starting from its default
+    AddField(FieldConfig),
+    /// Drop a field and its value
+    RemoveField(String),
+    /// Rename a field, keeping its value and CRDT state
+    RenameField { from: String, to: String },
+    /// Switch a field to another strategy
+    ///
+    /// A `GCounter` switched to a `PNCounter` keeps its counts as increments.
+    /// Otherwise the value is kept if the new strategy takes plain values,
+    /// and the field starts over from its default if not.
+    ChangeStrategy(FieldConfig),
+}
+
+/// Steps that move a replica from `from_version` to `from_version + 1`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Migration {
+    /// Schema version the steps apply to
+    pub from_version: u32,
+    /// Steps in the order they are applied
+    pub steps: Vec<MigrationStep>,
+}
+
+/// Migrations of a schema, one per version step
+///
+/// The registry travels with [`CrdtBuilderConfig`] so that a replica can bring
+/// any older peer up to its own version while merging.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MigrationRegistry {
+    migrations: Vec<Migration>,
+}
+
+impl MigrationRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the steps from `from_version` to the next version, replacing earlier ones
+    pub fn register(&mut self, from_version: u32, steps: Vec<MigrationStep>) {
+        self.migrations.retain(|m| m.from_version != from_version);
+        self.migrations.push(Migration { from_version, steps });
+        self.migrations.sort_by_key(|m| m.from_version);
+    }
+
+    /// Migration from `from_version` to the next version
+    pub fn get(&self, from_version: u32) -> Option<&Migration> {
+        self.migrations.iter().find(|m| m.from_version == from_version)
+    }
+
+    /// All migrations, oldest first
+    pub fn migrations(&self) -> &[Migration] {
+        &self.migrations
+    }
 }
 
 /// Trait for CRDT field operations
@@ -117,6 +202,111 @@ pub struct GenericCrdtField {
     pub strategy: CrdtStrategy,
     /// Field metadata (timestamps, replica IDs, etc.)
     pub metadata: HashMap<String, serde_json::Value>,
+    /// CRDT state of text, list and record fields
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<FieldState>,
+}
+
+/// CRDT state kept alongside a [`GenericCrdtField`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FieldState {
+    /// Characters of a text field
+    Text(SequenceState<char>),
+    /// Items of a list field
+    List(SequenceState<serde_json::Value>),
+    /// Fields of a nested record
+    Record(Box<CustomCrdt>),
+}
+
+/// Sequence CRDT behind an `Rga` or `Lseq` field
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SequenceState<T> {
+    /// Replicated Growable Array
+    Rga(Rga<T>),
+    /// Logoot Sequence
+    Lseq(Lseq<T>),
+}
+
+impl<T: Clone + PartialEq + Send + Sync> SequenceState<T> {
+    /// Create an empty sequence for a field strategy
+    pub fn new(strategy: &CrdtStrategy, replica_id: ReplicaId) -> Result<Self, BuilderError> {
+        match strategy {
+            CrdtStrategy::Rga => Ok(Self::Rga(Rga::new(replica_id))),
+            CrdtStrategy::Lseq => Ok(Self::Lseq(Lseq::new(replica_id))),
+            other => Err(BuilderError::UnsupportedStrategy(format!("{:?}", other))),
+        }
+    }
+
+    /// Continue editing as `replica_id`
+    pub fn set_replica_id(&mut self, replica_id: ReplicaId) {
+        match self {
+            Self::Rga(rga) => rga.set_replica_id(replica_id),
+            Self::Lseq(lseq) => lseq.set_replica_id(replica_id),
+        }
+    }
+
+    /// Visible items in order
+    pub fn to_vec(&self) -> Vec<T> {
+        match self {
+            Self::Rga(rga) => rga.to_vec(),
+            Self::Lseq(lseq) => lseq.to_vec(),
+        }
+    }
+
+    /// Insert `values` so that the first one ends up at visible index `index`
+    pub fn insert(&mut self, index: usize, values: impl IntoIterator<Item = T>) -> Result<(), AdvancedCrdtError> {
+        let beyond = || AdvancedCrdtError::InvalidPosition(format!("Index {} beyond length", index));
+        match self {
+            Self::Rga(rga) => {
+                let mut after = match index.checked_sub(1) {
+                    Some(before) => Some(rga.position_at(before).ok_or_else(beyond)?),
+                    None => None,
+                };
+                for value in values {
+                    after = Some(rga.insert_after(value, after)?);
+                }
+            }
+            Self::Lseq(lseq) => {
+                let mut values = values.into_iter();
+                let Some(first) = values.next() else {
+                    return Ok(());
+                };
+                let mut after = lseq.insert_at(first, index)?;
+                for value in values {
+                    after = lseq.insert(value, Some(after))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete `len` visible items starting at `index`
+    pub fn delete(&mut self, index: usize, len: usize) -> Result<(), AdvancedCrdtError> {
+        let positions: Vec<_> = match self {
+            Self::Rga(rga) => rga.positions().skip(index).take(len).cloned().collect(),
+            Self::Lseq(lseq) => lseq.positions().skip(index).take(len).cloned().collect(),
+        };
+        if positions.len() < len {
+            return Err(AdvancedCrdtError::InvalidPosition(format!("Range {}..{} beyond length", index, index + len)));
+        }
+        for position in &positions {
+            match self {
+                Self::Rga(rga) => rga.delete(position)?,
+                Self::Lseq(lseq) => lseq.delete(position)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Merge with a sequence of the same kind
+    pub fn merge(&mut self, other: &Self) -> Result<(), BuilderError> {
+        match (self, other) {
+            (Self::Rga(rga), Self::Rga(other)) => rga.merge(other),
+            (Self::Lseq(lseq), Self::Lseq(other)) => lseq.merge(other),
+            _ => return Err(BuilderError::TypeMismatch("Cannot merge RGA and LSEQ sequences".to_string())),
+        }
+        .map_err(|e| BuilderError::MergeError(e.to_string()))
+    }
 }
 
 impl CrdtField for GenericCrdtField {
@@ -124,7 +314,24 @@ impl CrdtField for GenericCrdtField {
         self.value.clone()
     }
     
+    /// Overwrite the value of a register or set field
+    ///
+    /// Counter, sequence and record fields only change through their
+    /// [`CustomCrdt`] handles; only a grow-only counter that was never counted
+    /// through one still takes a plain number.
     fn set_value(&mut self, value: serde_json::Value) -> Result<(), BuilderError> {
+        let handle = match self.strategy {
+            CrdtStrategy::GCounter if !self.metadata.contains_key("state") && value.is_u64() => None,
+            CrdtStrategy::GCounter | CrdtStrategy::PNCounter | CrdtStrategy::BoundedCounter => Some("counter"),
+            CrdtStrategy::Rga | CrdtStrategy::Lseq => Some("text or list"),
+            CrdtStrategy::Record(_) => Some("record"),
+            _ => None,
+        };
+        if let Some(handle) = handle {
+            return Err(BuilderError::TypeMismatch(
+                format!("Field {} can only be changed through its {} handle", self.name, handle)
+            ));
+        }
         self.value = value;
         Ok(())
     }
@@ -142,11 +349,14 @@ impl CrdtField for GenericCrdtField {
             CrdtStrategy::AddWins => self.merge_add_wins(other),
             CrdtStrategy::RemoveWins => self.merge_remove_wins(other),
             CrdtStrategy::GCounter => self.merge_gcounter(other),
+            CrdtStrategy::PNCounter => self.merge_counter_state::<PNCounter>(other),
+            CrdtStrategy::BoundedCounter => self.merge_counter_state::<BoundedCounter>(other),
             CrdtStrategy::MvRegister => self.merge_mv_register(other),
             CrdtStrategy::Rga => self.merge_rga(other),
             CrdtStrategy::Lseq => self.merge_lseq(other),
             CrdtStrategy::YjsTree => self.merge_yjs_tree(other),
             CrdtStrategy::Dag => self.merge_dag(other),
+            CrdtStrategy::Record(_) => self.merge_state(other),
         }
     }
     
@@ -160,11 +370,13 @@ impl CrdtField for GenericCrdtField {
             CrdtStrategy::AddWins => self.has_add_wins_conflict(other),
             CrdtStrategy::RemoveWins => self.has_remove_wins_conflict(other),
             CrdtStrategy::GCounter => false, // G-Counters never conflict
+            CrdtStrategy::PNCounter | CrdtStrategy::BoundedCounter => false,
             CrdtStrategy::MvRegister => self.has_mv_register_conflict(other),
             CrdtStrategy::Rga => self.has_rga_conflict(other),
             CrdtStrategy::Lseq => self.has_lseq_conflict(other),
             CrdtStrategy::YjsTree => self.has_yjs_tree_conflict(other),
             CrdtStrategy::Dag => self.has_dag_conflict(other),
+            CrdtStrategy::Record(_) => self.has_record_conflict(other),
         }
     }
     
@@ -181,6 +393,7 @@ impl GenericCrdtField {
             value,
             strategy,
             metadata: HashMap::new(),
+            state: None,
         }
     }
     
@@ -255,7 +468,13 @@ impl GenericCrdtField {
     }
     
     /// Merge using G-Counter strategy
+    ///
+    /// Plain numbers written with `set_value` merge by taking the maximum
+    /// until either side counts through a [`CounterField`].
     fn merge_gcounter(&mut self, other: &Self) -> Result<(), BuilderError> {
+        if self.metadata.contains_key("state") || other.metadata.contains_key("state") {
+            return self.merge_counter_state::<GCounter>(other);
+        }
         if let (Some(self_count), Some(other_count)) = (
             self.value.as_u64(),
             other.value.as_u64()
@@ -268,6 +487,96 @@ impl GenericCrdtField {
         Ok(())
     }
     
+    /// Merge counters whose full CRDT state is kept in the `state` metadata entry
+    fn merge_counter_state<C>(&mut self, other: &Self) -> Result<(), BuilderError>
+    where
+        C: CounterState,
+    {
+        if !other.metadata.contains_key("state") {
+            return Ok(());
+        }
+        let mut counter: C = self.counter_state()?;
+        counter
+            .merge(&other.counter_state()?)
+            .map_err(|e| BuilderError::MergeError(e.to_string()))?;
+        self.store_counter_state(&counter)
+    }
+
+    /// Decode the counter state, starting from an empty counter
+    fn counter_state<C: CounterState>(&self) -> Result<C, BuilderError> {
+        match self.metadata.get("state") {
+            Some(state) => serde_json::from_value(state.clone())
+                .map_err(|e| BuilderError::SerializationError(e.to_string())),
+            None => Ok(C::default()),
+        }
+    }
+
+    /// Store the counter state and expose its value as the field value
+    fn store_counter_state<C: CounterState>(&mut self, counter: &C) -> Result<(), BuilderError> {
+        let state = serde_json::to_value(counter)
+            .map_err(|e| BuilderError::SerializationError(e.to_string()))?;
+        self.metadata.insert("state".to_string(), state);
+        self.value = counter.json_value();
+        Ok(())
+    }
+
+    /// Apply `update` to the decoded counter state and store the result
+    fn update_counter<C: CounterState>(
+        &mut self,
+        update: impl FnOnce(&mut C) -> Result<(), BuilderError>,
+    ) -> Result<(), BuilderError> {
+        let mut counter: C = self.counter_state()?;
+        update(&mut counter)?;
+        self.store_counter_state(&counter)
+    }
+
+    /// Take over the value of a field that used another strategy
+    ///
+    /// The counts of a `GCounter` become the increments of a `PNCounter`.
+    /// Other values are kept where the new strategy accepts them as plain
+    /// values; strategies that only take their own handles start over.
+    fn carry_over(&mut self, previous: &Self, replica_id: ReplicaId) -> Result<(), BuilderError> {
+        if previous.strategy == CrdtStrategy::GCounter && self.strategy == CrdtStrategy::PNCounter {
+            let mut increments: GCounter = previous.counter_state()?;
+            if !previous.metadata.contains_key("state") {
+                // A plain number set before counting is the replica's own share
+                increments.increment_by(replica_id, previous.value.as_u64().unwrap_or(0));
+            }
+            return self.store_counter_state(&PNCounter::from(increments));
+        }
+        let _ = self.set_value(previous.value.clone());
+        Ok(())
+    }
+
+    /// Merge the typed state of text, list and record fields
+    fn merge_state(&mut self, other: &Self) -> Result<(), BuilderError> {
+        let Some(other_state) = &other.state else {
+            return Ok(());
+        };
+        match (&mut self.state, other_state) {
+            (None, _) => self.state = Some(other_state.clone()),
+            (Some(FieldState::Text(text)), FieldState::Text(other)) => text.merge(other)?,
+            (Some(FieldState::List(list)), FieldState::List(other)) => list.merge(other)?,
+            (Some(FieldState::Record(record)), FieldState::Record(other)) => record.merge(other)?,
+            _ => {
+                return Err(BuilderError::TypeMismatch(
+                    format!("Field {} holds a different kind of state on each side", self.name)
+                ));
+            }
+        }
+        self.refresh_value();
+        Ok(())
+    }
+
+    /// Expose the text or items of a sequence field as its value
+    fn refresh_value(&mut self) {
+        match &self.state {
+            Some(FieldState::Text(text)) => self.value = serde_json::Value::String(text.to_vec().into_iter().collect()),
+            Some(FieldState::List(list)) => self.value = serde_json::Value::Array(list.to_vec()),
+            Some(FieldState::Record(_)) | None => {}
+        }
+    }
+
     /// Merge using Multi-Value Register strategy
     fn merge_mv_register(&mut self, other: &Self) -> Result<(), BuilderError> {
         // For MV-Register, we keep all concurrent values
@@ -290,15 +599,19 @@ impl GenericCrdtField {
         Ok(())
     }
     
-    /// Merge using RGA strategy (simplified)
+    /// Merge using RGA strategy, falling back to add-wins for plain arrays
     fn merge_rga(&mut self, other: &Self) -> Result<(), BuilderError> {
-        // Simplified RGA merge - in practice, this would be much more complex
+        if self.state.is_some() || other.state.is_some() {
+            return self.merge_state(other);
+        }
         self.merge_add_wins(other)
     }
     
-    /// Merge using LSEQ strategy (simplified)
+    /// Merge using LSEQ strategy, falling back to add-wins for plain arrays
     fn merge_lseq(&mut self, other: &Self) -> Result<(), BuilderError> {
-        // Simplified LSEQ merge - in practice, this would be much more complex
+        if self.state.is_some() || other.state.is_some() {
+            return self.merge_state(other);
+        }
         self.merge_add_wins(other)
     }
     
@@ -353,6 +666,36 @@ impl GenericCrdtField {
     fn has_dag_conflict(&self, other: &Self) -> bool {
         self.value != other.value
     }
+    
+    fn has_record_conflict(&self, other: &Self) -> bool {
+        match (&self.state, &other.state) {
+            (Some(FieldState::Record(record)), Some(FieldState::Record(other))) => record.has_conflict(other),
+            _ => false,
+        }
+    }
+}
+
+/// Counter CRDTs that can back a builder field
+trait CounterState: Mergeable + Default + Serialize + DeserializeOwned {
+    fn json_value(&self) -> serde_json::Value;
+}
+
+impl CounterState for GCounter {
+    fn json_value(&self) -> serde_json::Value {
+        serde_json::Value::from(self.value())
+    }
+}
+
+impl CounterState for PNCounter {
+    fn json_value(&self) -> serde_json::Value {
+        serde_json::Value::from(self.value())
+    }
+}
+
+impl CounterState for BoundedCounter {
+    fn json_value(&self) -> serde_json::Value {
+        serde_json::Value::from(self.value())
+    }
 }
 
 /// Custom CRDT built using the builder framework
@@ -376,24 +719,7 @@ impl Mergeable for CustomCrdt {
     type Error = BuilderError;
     
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        if self.config.type_name != other.config.type_name {
-            return Err(BuilderError::TypeMismatch(
-                format!("Cannot merge CRDTs of different types: {} vs {}", 
-                        self.config.type_name, other.config.type_name)
-            ));
-        }
-        
-        // Merge each field
-        for (field_name, other_field) in &other.fields {
-            if let Some(self_field) = self.fields.get_mut(field_name) {
-                self_field.merge(other_field)?;
-            } else {
-                // Add new field from other CRDT
-                self.fields.insert(field_name.clone(), other_field.clone());
-            }
-        }
-        
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
     
     fn has_conflict(&self, other: &Self) -> bool {
@@ -414,24 +740,42 @@ impl Mergeable for CustomCrdt {
     }
 }
 
+/// Fields are never removed, so a merge reports inserted and updated field names
+///
+/// Changes inside nested records are reported by their dotted path, e.g. `address.city`.
+///
+/// Replicas on different schema versions still merge. A peer on an older
+/// version is first migrated to ours. A peer on a newer version leaves us on
+/// our version: shared fields merge as usual, fields only it knows are carried
+/// along untouched so they reach newer peers again, and fields whose strategy
+/// changed keep our value.
+impl ReportingMerge for CustomCrdt {
+    type Key = String;
+    
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<String>, Self::Error> {
+        if self.config.type_name != other.config.type_name {
+            return Err(BuilderError::TypeMismatch(
+                format!("Cannot merge CRDTs of different types: {} vs {}", 
+                        self.config.type_name, other.config.type_name)
+            ));
+        }
+        
+        if other.config.version < self.config.version {
+            let mut migrated = other.clone();
+            migrated.migrate_to(&self.config)?;
+            return self.merge_fields(&migrated, false);
+        }
+        self.merge_fields(other, other.config.version > self.config.version)
+    }
+}
+
 impl CustomCrdt {
     /// Create a new custom CRDT
     pub fn new(config: CrdtBuilderConfig, replica_id: ReplicaId) -> Self {
-        let mut fields = HashMap::new();
-        
         // Initialize fields with default values
-        for field_config in &config.fields {
-            let default_value = field_config.default.clone()
-                .unwrap_or_else(|| serde_json::Value::Null);
-            
-            let field = GenericCrdtField::new(
-                field_config.name.clone(),
-                default_value,
-                field_config.strategy.clone(),
-            );
-            
-            fields.insert(field_config.name.clone(), field);
-        }
+        let fields = config.fields.iter()
+            .map(|field_config| (field_config.name.clone(), Self::new_field(field_config, replica_id)))
+            .collect();
         
         Self {
             config,
@@ -440,9 +784,239 @@ impl CustomCrdt {
         }
     }
     
-    /// Get a field value
+    fn new_field(field_config: &FieldConfig, replica_id: ReplicaId) -> GenericCrdtField {
+        let default_value = field_config.default.clone()
+            .unwrap_or(serde_json::Value::Null);
+        
+        let mut field = GenericCrdtField::new(
+            field_config.name.clone(),
+            default_value,
+            field_config.strategy.clone(),
+        );
+        if let CrdtStrategy::Record(record_config) = &field_config.strategy {
+            let record = CustomCrdt::new((**record_config).clone(), replica_id);
+            field.value = serde_json::Value::Null;
+            field.state = Some(FieldState::Record(Box::new(record)));
+        }
+        field
+    }
+    
+    /// Load a replica stored as JSON and bring it up to the schema of `config`
+    pub fn load(bytes: &[u8], config: &CrdtBuilderConfig) -> Result<Self, BuilderError> {
+        let mut crdt: Self = serde_json::from_slice(bytes)
+            .map_err(|e| BuilderError::SerializationError(e.to_string()))?;
+        crdt.migrate_to(config)?;
+        Ok(crdt)
+    }
+    
+    /// Schema version of this replica
+    pub fn version(&self) -> u32 {
+        self.config.version
+    }
+    
+    /// Bring a replica, e.g. one loaded from storage, up to the schema of `config`
+    ///
+    /// Applies the registered migrations of `config` one version at a time,
+    /// then adds any field `config` lists that the replica still lacks and
+    /// migrates nested records to their own configs.
+    pub fn migrate_to(&mut self, config: &CrdtBuilderConfig) -> Result<(), BuilderError> {
+        if self.config.type_name != config.type_name {
+            return Err(BuilderError::TypeMismatch(
+                format!("Cannot migrate {} to {}", self.config.type_name, config.type_name)
+            ));
+        }
+        if self.config.version > config.version {
+            return Err(BuilderError::MigrationError(
+                format!("Cannot downgrade {} from version {} to {}", config.type_name, self.config.version, config.version)
+            ));
+        }
+        
+        for version in self.config.version..config.version {
+            let migration = config.migrations.get(version).ok_or_else(|| BuilderError::MigrationError(
+                format!("No migration of {} from version {} to {}", config.type_name, version, version + 1)
+            ))?;
+            for step in &migration.steps {
+                self.apply_migration_step(step)?;
+            }
+        }
+        
+        for field_config in &config.fields {
+            match self.fields.get_mut(&field_config.name) {
+                Some(field) => {
+                    if let (CrdtStrategy::Record(record_config), Some(FieldState::Record(record))) =
+                        (&field_config.strategy, &mut field.state)
+                    {
+                        record.migrate_to(record_config)?;
+                        field.strategy = field_config.strategy.clone();
+                    }
+                }
+                None => {
+                    self.fields.insert(field_config.name.clone(), Self::new_field(field_config, self.replica_id));
+                }
+            }
+        }
+        self.config = config.clone();
+        Ok(())
+    }
+    
+    /// Fields already present, e.g. carried over from a newer peer, are left as they are
+    fn apply_migration_step(&mut self, step: &MigrationStep) -> Result<(), BuilderError> {
+        match step {
+            MigrationStep::AddField(field_config) => {
+                if !self.fields.contains_key(&field_config.name) {
+                    self.fields.insert(field_config.name.clone(), Self::new_field(field_config, self.replica_id));
+                }
+            }
+            MigrationStep::RemoveField(name) => {
+                self.fields.remove(name);
+            }
+            MigrationStep::RenameField { from, to } => {
+                let Some(mut field) = self.fields.remove(from) else {
+                    return Ok(());
+                };
+                field.name = to.clone();
+                match self.fields.get_mut(to) {
+                    Some(existing) => existing.merge(&field)?,
+                    None => {
+                        self.fields.insert(to.clone(), field);
+                    }
+                }
+            }
+            MigrationStep::ChangeStrategy(field_config) => {
+                let previous = match self.fields.get(&field_config.name) {
+                    Some(field) if Self::same_kind(&field.strategy, &field_config.strategy) => return Ok(()),
+                    Some(field) => Some(field.clone()),
+                    None => None,
+                };
+                let mut field = Self::new_field(field_config, self.replica_id);
+                if let Some(previous) = previous {
+                    field.carry_over(&previous, self.replica_id)?;
+                }
+                self.fields.insert(field_config.name.clone(), field);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Whether two strategies hold the same kind of state; records may differ in schema
+    fn same_kind(a: &CrdtStrategy, b: &CrdtStrategy) -> bool {
+        matches!((a, b), (CrdtStrategy::Record(_), CrdtStrategy::Record(_))) || a == b
+    }
+    
+    /// Merge each field, skipping strategy mismatches when `lenient`
+    fn merge_fields(&mut self, other: &Self, lenient: bool) -> Result<MergeReport<String>, BuilderError> {
+        let mut report = MergeReport::new();
+        for (field_name, other_field) in &other.fields {
+            if let Some(self_field) = self.fields.get_mut(field_name) {
+                if let (Some(FieldState::Record(record)), Some(FieldState::Record(other_record))) =
+                    (&mut self_field.state, &other_field.state)
+                {
+                    let nested = record.merge_with_changes(other_record)?;
+                    report.extend(nested.map(|key| format!("{}.{}", field_name, key)));
+                    continue;
+                }
+                if lenient && self_field.strategy != other_field.strategy {
+                    continue;
+                }
+                let previous = self_field.value.clone();
+                self_field.merge(other_field)?;
+                report.record(field_name.clone(), true, true, self_field.value != previous);
+            } else {
+                // Add new field from other CRDT
+                self.fields.insert(field_name.clone(), other_field.clone());
+                report.record(field_name.clone(), false, true, true);
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Get a field value, looking into nested records for dotted names like `address.city`
     pub fn get_field(&self, field_name: &str) -> Option<&serde_json::Value> {
-        self.fields.get(field_name).map(|f| &f.value)
+        if let Some(field) = self.fields.get(field_name) {
+            return Some(&field.value);
+        }
+        let (record, rest) = field_name.split_once('.')?;
+        self.get_record(record)?.get_field(rest)
+    }
+    
+    /// Get a nested record
+    pub fn get_record(&self, field_name: &str) -> Option<&CustomCrdt> {
+        match &self.fields.get(field_name)?.state {
+            Some(FieldState::Record(record)) => Some(record),
+            _ => None,
+        }
+    }
+    
+    /// Edit a nested record
+    pub fn record(&mut self, field_name: &str) -> Result<&mut CustomCrdt, BuilderError> {
+        let replica_id = self.replica_id;
+        let field = self.field_mut(field_name)?;
+        match &mut field.state {
+            Some(FieldState::Record(record)) => {
+                record.replica_id = replica_id;
+                Ok(record)
+            }
+            _ => Err(BuilderError::UnsupportedStrategy(format!("{:?}", field.strategy))),
+        }
+    }
+    
+    /// Count through a `GCounter`, `PNCounter` or `BoundedCounter` field
+    pub fn counter(&mut self, field_name: &str) -> Result<CounterField<'_>, BuilderError> {
+        let replica_id = self.replica_id;
+        let field = self.field_mut(field_name)?;
+        match field.strategy {
+            CrdtStrategy::GCounter | CrdtStrategy::PNCounter | CrdtStrategy::BoundedCounter => {}
+            ref other => return Err(BuilderError::UnsupportedStrategy(format!("{:?}", other))),
+        }
+        // A plain number set before becomes this replica's share
+        if field.strategy == CrdtStrategy::GCounter && !field.metadata.contains_key("state") {
+            let count = field.value.as_u64().unwrap_or(0);
+            field.update_counter(|counter: &mut GCounter| {
+                counter.increment_by(replica_id, count);
+                Ok(())
+            })?;
+        }
+        Ok(CounterField { field, replica_id })
+    }
+    
+    /// Edit an `Rga` or `Lseq` field as text
+    pub fn text(&mut self, field_name: &str) -> Result<TextField<'_>, BuilderError> {
+        let replica_id = self.replica_id;
+        let field = self.field_mut(field_name)?;
+        if field.state.is_none() {
+            field.state = Some(FieldState::Text(SequenceState::new(&field.strategy, replica_id)?));
+            field.refresh_value();
+        }
+        match &mut field.state {
+            Some(FieldState::Text(text)) => {
+                text.set_replica_id(replica_id);
+                Ok(TextField { text, value: &mut field.value })
+            }
+            _ => Err(BuilderError::TypeMismatch(format!("Field {} is not a text field", field_name))),
+        }
+    }
+    
+    /// Edit an `Rga` or `Lseq` field as a list of JSON values
+    pub fn list(&mut self, field_name: &str) -> Result<ListField<'_>, BuilderError> {
+        let replica_id = self.replica_id;
+        let field = self.field_mut(field_name)?;
+        if field.state.is_none() {
+            field.state = Some(FieldState::List(SequenceState::new(&field.strategy, replica_id)?));
+            field.refresh_value();
+        }
+        match &mut field.state {
+            Some(FieldState::List(list)) => {
+                list.set_replica_id(replica_id);
+                Ok(ListField { list, value: &mut field.value })
+            }
+            _ => Err(BuilderError::TypeMismatch(format!("Field {} is not a list field", field_name))),
+        }
+    }
+    
+    fn field_mut(&mut self, field_name: &str) -> Result<&mut GenericCrdtField, BuilderError> {
+        self.fields.get_mut(field_name)
+            .ok_or_else(|| BuilderError::MissingField(field_name.to_string()))
     }
     
     /// Set a field value
@@ -465,6 +1039,23 @@ impl CustomCrdt {
         }
     }
     
+    /// Add `amount` to a counter field
+    pub fn increment_counter(&mut self, field_name: &str, amount: u64) -> Result<(), BuilderError> {
+        self.counter(field_name)?.increment_by(amount)
+    }
+
+    /// Subtract `amount` from a `PNCounter` or `BoundedCounter` field
+    ///
+    /// Bounded counters fail with `InvalidFieldConfig` if this replica holds too few rights.
+    pub fn decrement_counter(&mut self, field_name: &str, amount: u64) -> Result<(), BuilderError> {
+        self.counter(field_name)?.decrement_by(amount)
+    }
+
+    /// Transfer decrement rights of a `BoundedCounter` field to another replica
+    pub fn transfer_counter_rights(&mut self, field_name: &str, to: ReplicaId, amount: u64) -> Result<(), BuilderError> {
+        self.counter(field_name)?.transfer_rights(to, amount)
+    }
+
     /// Get all field names
     pub fn field_names(&self) -> Vec<String> {
         self.fields.keys().cloned().collect()
@@ -476,6 +1067,250 @@ impl CustomCrdt {
     }
 }
 
+/// Handle on a counter field of a [`CustomCrdt`]
+pub struct CounterField<'a> {
+    field: &'a mut GenericCrdtField,
+    replica_id: ReplicaId,
+}
+
+impl CounterField<'_> {
+    /// Add one
+    pub fn increment(&mut self) -> Result<(), BuilderError> {
+        self.increment_by(1)
+    }
+
+    /// Add `amount`
+    pub fn increment_by(&mut self, amount: u64) -> Result<(), BuilderError> {
+        let replica = self.replica_id;
+        match self.field.strategy {
+            CrdtStrategy::GCounter => self.field.update_counter(|counter: &mut GCounter| {
+                counter.increment_by(replica, amount);
+                Ok(())
+            }),
+            CrdtStrategy::PNCounter => self.field.update_counter(|counter: &mut PNCounter| {
+                counter.increment_by(replica, amount);
+                Ok(())
+            }),
+            _ => self.field.update_counter(|counter: &mut BoundedCounter| {
+                counter.increment(replica, amount);
+                Ok(())
+            }),
+        }
+    }
+
+    /// Subtract one
+    pub fn decrement(&mut self) -> Result<(), BuilderError> {
+        self.decrement_by(1)
+    }
+
+    /// Subtract `amount`
+    ///
+    /// Grow-only counters fail with `UnsupportedStrategy`, bounded counters
+    /// with `InvalidFieldConfig` if this replica holds too few rights.
+    pub fn decrement_by(&mut self, amount: u64) -> Result<(), BuilderError> {
+        let replica = self.replica_id;
+        match self.field.strategy {
+            CrdtStrategy::PNCounter => self.field.update_counter(|counter: &mut PNCounter| {
+                counter.decrement_by(replica, amount);
+                Ok(())
+            }),
+            CrdtStrategy::BoundedCounter => self.field.update_counter(|counter: &mut BoundedCounter| {
+                counter
+                    .decrement(replica, amount)
+                    .map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+            }),
+            ref other => Err(BuilderError::UnsupportedStrategy(format!("{:?}", other))),
+        }
+    }
+
+    /// Transfer decrement rights of a `BoundedCounter` to another replica
+    pub fn transfer_rights(&mut self, to: ReplicaId, amount: u64) -> Result<(), BuilderError> {
+        if self.field.strategy != CrdtStrategy::BoundedCounter {
+            return Err(BuilderError::UnsupportedStrategy(format!("{:?}", self.field.strategy)));
+        }
+        let replica = self.replica_id;
+        self.field.update_counter(|counter: &mut BoundedCounter| {
+            counter
+                .transfer(replica, to, amount)
+                .map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+        })
+    }
+
+    /// Current count
+    pub fn value(&self) -> i64 {
+        self.field.value.as_i64().unwrap_or(0)
+    }
+}
+
+/// Handle on a text field of a [`CustomCrdt`]
+pub struct TextField<'a> {
+    text: &'a mut SequenceState<char>,
+    value: &'a mut serde_json::Value,
+}
+
+impl TextField<'_> {
+    /// Insert `text` at character index `index`
+    pub fn insert(&mut self, index: usize, text: &str) -> Result<(), BuilderError> {
+        let result = self.text.insert(index, text.chars());
+        self.refresh();
+        result.map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+    }
+
+    /// Append `text`
+    pub fn push_str(&mut self, text: &str) -> Result<(), BuilderError> {
+        let len = self.len();
+        self.insert(len, text)
+    }
+
+    /// Delete `len` characters starting at character index `index`
+    pub fn delete(&mut self, index: usize, len: usize) -> Result<(), BuilderError> {
+        let result = self.text.delete(index, len);
+        self.refresh();
+        result.map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+    }
+
+    /// Current text
+    pub fn as_str(&self) -> &str {
+        self.value.as_str().unwrap_or_default()
+    }
+
+    /// Number of characters
+    pub fn len(&self) -> usize {
+        self.as_str().chars().count()
+    }
+
+    /// Whether the text is empty
+    pub fn is_empty(&self) -> bool {
+        self.as_str().is_empty()
+    }
+
+    fn refresh(&mut self) {
+        *self.value = serde_json::Value::String(self.text.to_vec().into_iter().collect());
+    }
+}
+
+/// Handle on a list field of a [`CustomCrdt`]
+pub struct ListField<'a> {
+    list: &'a mut SequenceState<serde_json::Value>,
+    value: &'a mut serde_json::Value,
+}
+
+impl ListField<'_> {
+    /// Insert `item` at index `index`
+    pub fn insert(&mut self, index: usize, item: serde_json::Value) -> Result<(), BuilderError> {
+        let result = self.list.insert(index, [item]);
+        self.refresh();
+        result.map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+    }
+
+    /// Append `item`
+    pub fn push(&mut self, item: serde_json::Value) -> Result<(), BuilderError> {
+        let len = self.len();
+        self.insert(len, item)
+    }
+
+    /// Remove the item at index `index`
+    pub fn remove(&mut self, index: usize) -> Result<(), BuilderError> {
+        let result = self.list.delete(index, 1);
+        self.refresh();
+        result.map_err(|e| BuilderError::InvalidFieldConfig(e.to_string()))
+    }
+
+    /// Item at index `index`
+    pub fn get(&self, index: usize) -> Option<&serde_json::Value> {
+        self.items().get(index)
+    }
+
+    /// Current items
+    pub fn items(&self) -> &[serde_json::Value] {
+        self.value.as_array().map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Number of items
+    pub fn len(&self) -> usize {
+        self.items().len()
+    }
+
+    /// Whether the list is empty
+    pub fn is_empty(&self) -> bool {
+        self.items().is_empty()
+    }
+
+    fn refresh(&mut self) {
+        *self.value = serde_json::Value::Array(self.list.to_vec());
+    }
+}
+
+/// Local edit of a [`CustomCrdt`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CustomEdit {
+    /// Set a field value
+    Set { field: String, value: serde_json::Value },
+    /// Add to a counter field
+    Increment { field: String, amount: u64 },
+    /// Subtract from a counter field
+    Decrement { field: String, amount: u64 },
+}
+
+/// Local change to a [`CustomCrdt`] recorded for undo
+#[derive(Debug, Clone, PartialEq)]
+pub enum CustomChange {
+    /// A field was set, with its previous and new value
+    Set { field: String, previous: serde_json::Value, value: serde_json::Value },
+    /// A counter field was incremented
+    Incremented { field: String, amount: u64 },
+    /// A counter field was decremented
+    Decremented { field: String, amount: u64 },
+}
+
+impl Undoable for CustomCrdt {
+    type Edit = CustomEdit;
+    type Change = CustomChange;
+    type Output = ();
+    type Id = String;
+    type Error = BuilderError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        let change = match edit {
+            CustomEdit::Set { field, value } => {
+                let previous = self.get_field(&field)
+                    .cloned()
+                    .ok_or_else(|| BuilderError::MissingField(field.clone()))?;
+                self.set_field(&field, value.clone())?;
+                CustomChange::Set { field, previous, value }
+            }
+            CustomEdit::Increment { field, amount } => {
+                self.increment_counter(&field, amount)?;
+                CustomChange::Incremented { field, amount }
+            }
+            CustomEdit::Decrement { field, amount } => {
+                self.decrement_counter(&field, amount)?;
+                CustomChange::Decremented { field, amount }
+            }
+        };
+        Ok((change, ()))
+    }
+
+    /// Write back the previous value of a field, or count the other way
+    ///
+    /// Field values are only written back while the field still holds the
+    /// value of the change. Counters only ever add up the
+    /// contributions of each replica, so reverting one never touches others'.
+    fn revert(&mut self, change: &Self::Change, _ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        let edit = match change {
+            CustomChange::Set { field, previous, value } => {
+                if self.get_field(field) != Some(value) {
+                    return Ok(None);
+                }
+                CustomEdit::Set { field: field.clone(), value: previous.clone() }
+            }
+            CustomChange::Incremented { field, amount } => CustomEdit::Decrement { field: field.clone(), amount: *amount },
+            CustomChange::Decremented { field, amount } => CustomEdit::Increment { field: field.clone(), amount: *amount },
+        };
+        self.edit(edit).map(Some)
+    }
+}
+
 /// CRDT Builder for creating custom CRDT types
 pub struct CrdtBuilder {
     config: CrdtBuilderConfig,
@@ -489,6 +1324,8 @@ impl CrdtBuilder {
                 type_name,
                 fields: Vec::new(),
                 replica_id_field: None,
+                version: first_schema_version(),
+                migrations: MigrationRegistry::new(),
             },
         }
     }
@@ -515,6 +1352,29 @@ impl CrdtBuilder {
         self
     }
     
+    /// Add a nested record with its own fields
+    pub fn add_record_field(mut self, name: String, config: CrdtBuilderConfig) -> Self {
+        self.config.fields.push(FieldConfig {
+            name,
+            strategy: CrdtStrategy::Record(Box::new(config)),
+            optional: false,
+            default: None,
+        });
+        self
+    }
+    
+    /// Set the schema version
+    pub fn version(mut self, version: u32) -> Self {
+        self.config.version = version;
+        self
+    }
+    
+    /// Register the migration from `from_version` to the next schema version
+    pub fn migration(mut self, from_version: u32, steps: Vec<MigrationStep>) -> Self {
+        self.config.migrations.register(from_version, steps);
+        self
+    }
+    
     /// Set the replica ID field name
     pub fn replica_id_field(mut self, field_name: String) -> Self {
         self.config.replica_id_field = Some(field_name);
@@ -625,6 +1485,25 @@ mod tests {
         // Count should be 20 (GCounter takes max)
         assert_eq!(crdt1.get_field("count"), Some(&serde_json::Value::Number(serde_json::Number::from(20))));
     }
+
+    #[test]
+    fn test_custom_crdt_merge_with_changes() {
+        let config = CrdtBuilder::new("TestCRDT".to_string())
+            .add_field("name".to_string(), CrdtStrategy::Lww)
+            .add_field("count".to_string(), CrdtStrategy::GCounter)
+            .build();
+
+        let mut crdt1 = CustomCrdt::new(config.clone(), ReplicaId::from(Uuid::new_v4()));
+        let mut crdt2 = CustomCrdt::new(config, ReplicaId::from(Uuid::new_v4()));
+        crdt1.set_field("count", serde_json::Value::Number(serde_json::Number::from(1))).unwrap();
+        crdt2.merge(&crdt1).unwrap();
+        crdt2.set_field("count", serde_json::Value::Number(serde_json::Number::from(5))).unwrap();
+
+        let report = crdt1.merge_with_changes(&crdt2).unwrap();
+        assert!(report.inserted.is_empty());
+        assert_eq!(report.updated, vec!["count".to_string()]);
+        assert!(report.removed.is_empty());
+    }
     
     #[test]
     fn test_custom_crdt_conflict_detection() {
@@ -708,4 +1587,251 @@ mod tests {
         assert!(result.is_err());
         assert_eq!(result.unwrap_err(), BuilderError::MissingField("nonexistent".to_string()));
     }
+
+    #[test]
+    fn test_counter_field_strategies() {
+        let config = CrdtBuilder::new("Product".to_string())
+            .add_field("likes".to_string(), CrdtStrategy::PNCounter)
+            .add_field("stock".to_string(), CrdtStrategy::BoundedCounter)
+            .add_field("name".to_string(), CrdtStrategy::Lww)
+            .build();
+        let replica1 = ReplicaId::default();
+        let replica2 = ReplicaId::default();
+        let mut crdt1 = CustomCrdt::new(config.clone(), replica1);
+        let mut crdt2 = CustomCrdt::new(config, replica2);
+
+        crdt1.increment_counter("likes", 3).unwrap();
+        crdt2.decrement_counter("likes", 1).unwrap();
+        crdt1.increment_counter("stock", 5).unwrap();
+        crdt1.transfer_counter_rights("stock", replica2, 2).unwrap();
+        crdt2.merge(&crdt1).unwrap();
+
+        // Each replica may only sell the stock it holds rights to
+        crdt1.decrement_counter("stock", 3).unwrap();
+        crdt2.decrement_counter("stock", 2).unwrap();
+        assert!(crdt2.decrement_counter("stock", 1).is_err());
+        crdt1.merge(&crdt2).unwrap();
+
+        assert_eq!(crdt1.get_field("likes"), Some(&serde_json::json!(2)));
+        assert_eq!(crdt1.get_field("stock"), Some(&serde_json::json!(0)));
+        assert!(matches!(
+            crdt1.increment_counter("name", 1),
+            Err(BuilderError::UnsupportedStrategy(_))
+        ));
+    }
+
+    #[test]
+    fn test_typed_field_handles() {
+        let config = CrdtBuilder::new("Profile".to_string())
+            .add_field("visits".to_string(), CrdtStrategy::GCounter)
+            .add_field("bio".to_string(), CrdtStrategy::Rga)
+            .add_field("links".to_string(), CrdtStrategy::Lseq)
+            .build();
+        let mut profile1 = CustomCrdt::new(config.clone(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        let mut profile2 = CustomCrdt::new(config, ReplicaId::from(Uuid::from_u64_pair(0, 2)));
+
+        profile1.counter("visits").unwrap().increment().unwrap();
+        profile1.text("bio").unwrap().insert(0, "Hello").unwrap();
+        profile2.merge(&profile1).unwrap();
+
+        // Concurrent edits on both replicas
+        profile1.counter("visits").unwrap().increment_by(2).unwrap();
+        profile1.text("bio").unwrap().push_str(" world").unwrap();
+        profile1.list("links").unwrap().push(serde_json::json!("a")).unwrap();
+        profile2.counter("visits").unwrap().increment().unwrap();
+        profile2.text("bio").unwrap().insert(0, ">> ").unwrap();
+        profile2.list("links").unwrap().push(serde_json::json!("b")).unwrap();
+        profile1.merge(&profile2).unwrap();
+        profile2.merge(&profile1).unwrap();
+
+        for field in ["visits", "bio", "links"] {
+            assert_eq!(profile1.get_field(field), profile2.get_field(field));
+        }
+        assert_eq!(profile1.get_field("visits"), Some(&serde_json::json!(4)));
+        assert_eq!(profile1.text("bio").unwrap().as_str(), ">> Hello world");
+        assert_eq!(profile1.list("links").unwrap().len(), 2);
+
+        profile1.text("bio").unwrap().delete(0, 3).unwrap();
+        assert_eq!(profile1.get_field("bio"), Some(&serde_json::json!("Hello world")));
+        assert!(profile1.text("bio").unwrap().delete(5, 20).is_err());
+    }
+
+    #[test]
+    fn test_typed_fields_reject_mismatched_values() {
+        let config = CrdtBuilder::new("Profile".to_string())
+            .add_field("visits".to_string(), CrdtStrategy::GCounter)
+            .add_field("likes".to_string(), CrdtStrategy::PNCounter)
+            .add_field("bio".to_string(), CrdtStrategy::Rga)
+            .add_field("name".to_string(), CrdtStrategy::Lww)
+            .build();
+        let mut profile = CustomCrdt::new(config, ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+
+        assert!(matches!(
+            profile.set_field("visits", serde_json::json!("many")),
+            Err(BuilderError::TypeMismatch(_))
+        ));
+        assert!(matches!(profile.set_field("likes", serde_json::json!(3)), Err(BuilderError::TypeMismatch(_))));
+        assert!(matches!(profile.set_field("bio", serde_json::json!("text")), Err(BuilderError::TypeMismatch(_))));
+
+        // A plain count is kept once the counter handle takes over
+        profile.set_field("visits", serde_json::json!(5)).unwrap();
+        profile.counter("visits").unwrap().increment().unwrap();
+        assert_eq!(profile.counter("visits").unwrap().value(), 6);
+        assert!(profile.set_field("visits", serde_json::json!(7)).is_err());
+        assert!(matches!(profile.counter("visits").unwrap().decrement(), Err(BuilderError::UnsupportedStrategy(_))));
+
+        profile.text("bio").unwrap();
+        assert!(matches!(profile.list("bio"), Err(BuilderError::TypeMismatch(_))));
+        assert!(matches!(profile.counter("name"), Err(BuilderError::UnsupportedStrategy(_))));
+        assert!(matches!(profile.text("name"), Err(BuilderError::UnsupportedStrategy(_))));
+    }
+
+    #[test]
+    fn test_nested_record_fields() {
+        let address = CrdtBuilder::new("Address".to_string())
+            .add_field("city".to_string(), CrdtStrategy::Lww)
+            .add_field("moves".to_string(), CrdtStrategy::PNCounter)
+            .build();
+        let config = CrdtBuilder::new("Person".to_string())
+            .add_field("name".to_string(), CrdtStrategy::Lww)
+            .add_record_field("address".to_string(), address)
+            .build();
+        let mut person1 = CustomCrdt::new(config.clone(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        let mut person2 = CustomCrdt::new(config, ReplicaId::from(Uuid::from_u64_pair(0, 2)));
+
+        person1.record("address").unwrap().set_field("city", serde_json::json!("Paris")).unwrap();
+        person1.record("address").unwrap().increment_counter("moves", 1).unwrap();
+        person2.record("address").unwrap().increment_counter("moves", 1).unwrap();
+
+        let report = person2.merge_with_changes(&person1).unwrap();
+        assert_eq!(person2.get_field("address.city"), Some(&serde_json::json!("Paris")));
+        assert_eq!(person2.get_record("address").unwrap().get_field("moves"), Some(&serde_json::json!(2)));
+        let mut updated = report.updated;
+        updated.sort();
+        assert_eq!(updated, vec!["address.city".to_string(), "address.moves".to_string()]);
+
+        assert!(matches!(person1.set_field("address", serde_json::json!({})), Err(BuilderError::TypeMismatch(_))));
+        assert!(matches!(person1.record("name"), Err(BuilderError::UnsupportedStrategy(_))));
+    }
+
+    #[test]
+    fn test_custom_crdt_round_trips_through_serde_json() {
+        let address = CrdtBuilder::new("Address".to_string())
+            .add_field("city".to_string(), CrdtStrategy::Lww)
+            .build();
+        let config = CrdtBuilder::new("Profile".to_string())
+            .add_field("likes".to_string(), CrdtStrategy::PNCounter)
+            .add_field("bio".to_string(), CrdtStrategy::Rga)
+            .add_field("motto".to_string(), CrdtStrategy::Lseq)
+            .add_field("links".to_string(), CrdtStrategy::Lseq)
+            .add_record_field("address".to_string(), address)
+            .build();
+        let mut profile = CustomCrdt::new(config, ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        profile.counter("likes").unwrap().increment_by(3).unwrap();
+        profile.counter("likes").unwrap().decrement().unwrap();
+        profile.text("bio").unwrap().insert(0, "Hello").unwrap();
+        profile.text("motto").unwrap().insert(0, "Carpe diem").unwrap();
+        profile.list("links").unwrap().push(serde_json::json!("a")).unwrap();
+        profile.list("links").unwrap().push(serde_json::json!({"url": "b"})).unwrap();
+        profile.record("address").unwrap().set_field("city", serde_json::json!("Paris")).unwrap();
+
+        let json = serde_json::to_string(&profile).unwrap();
+        let mut loaded: CustomCrdt = serde_json::from_str(&json).unwrap();
+        for field in ["likes", "bio", "motto", "links", "address.city"] {
+            assert_eq!(loaded.get_field(field), profile.get_field(field));
+        }
+
+        // The loaded replica keeps editing and merging where it left off
+        loaded.text("motto").unwrap().push_str("!").unwrap();
+        loaded.list("links").unwrap().push(serde_json::json!("c")).unwrap();
+        profile.merge(&loaded).unwrap();
+        assert_eq!(profile.get_field("likes"), Some(&serde_json::json!(2)));
+        assert_eq!(profile.text("motto").unwrap().as_str(), "Carpe diem!");
+        assert_eq!(profile.list("links").unwrap().len(), 3);
+    }
+
+    fn profile_v1() -> CrdtBuilderConfig {
+        CrdtBuilder::new("Profile".to_string())
+            .add_field("name".to_string(), CrdtStrategy::Lww)
+            .add_field("visits".to_string(), CrdtStrategy::GCounter)
+            .build()
+    }
+
+    fn profile_v2() -> CrdtBuilderConfig {
+        CrdtBuilder::new("Profile".to_string())
+            .version(2)
+            .add_field("full_name".to_string(), CrdtStrategy::Lww)
+            .add_field("visits".to_string(), CrdtStrategy::PNCounter)
+            .add_optional_field("tags".to_string(), CrdtStrategy::AddWins, serde_json::json!([]))
+            .migration(1, vec![
+                MigrationStep::RenameField { from: "name".to_string(), to: "full_name".to_string() },
+                MigrationStep::ChangeStrategy(FieldConfig {
+                    name: "visits".to_string(),
+                    strategy: CrdtStrategy::PNCounter,
+                    optional: false,
+                    default: None,
+                }),
+            ])
+            .build()
+    }
+
+    #[test]
+    fn test_migrate_stored_replica() {
+        let mut profile = CustomCrdt::new(profile_v1(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        profile.set_field("name", serde_json::json!("Alice")).unwrap();
+        profile.counter("visits").unwrap().increment_by(2).unwrap();
+        let stored = serde_json::to_vec(&profile).unwrap();
+
+        let mut profile = CustomCrdt::load(&stored, &profile_v2()).unwrap();
+        assert_eq!(profile.version(), 2);
+        assert_eq!(profile.get_field("full_name"), Some(&serde_json::json!("Alice")));
+        assert_eq!(profile.get_field("name"), None);
+        assert_eq!(profile.get_field("tags"), Some(&serde_json::json!([])));
+        // The grow-only count becomes the increments of the new counter
+        assert_eq!(profile.counter("visits").unwrap().value(), 2);
+        profile.counter("visits").unwrap().decrement().unwrap();
+        assert_eq!(profile.get_field("visits"), Some(&serde_json::json!(1)));
+
+        // A plain count set before counting carries over too
+        let mut plain = CustomCrdt::new(profile_v1(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        plain.set_field("visits", serde_json::json!(3)).unwrap();
+        plain.migrate_to(&profile_v2()).unwrap();
+        assert_eq!(plain.counter("visits").unwrap().value(), 3);
+        assert!(matches!(CustomCrdt::load(b"{}", &profile_v2()), Err(BuilderError::SerializationError(_))));
+
+        let mut stale = CustomCrdt::new(profile_v2(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        assert!(matches!(stale.migrate_to(&profile_v1()), Err(BuilderError::MigrationError(_))));
+        let v3 = CrdtBuilder::new("Profile".to_string()).version(3).build();
+        assert!(matches!(stale.migrate_to(&v3), Err(BuilderError::MigrationError(_))));
+    }
+
+    #[test]
+    fn test_merge_across_schema_versions() {
+        let mut old = CustomCrdt::new(profile_v1(), ReplicaId::from(Uuid::from_u64_pair(0, 1)));
+        let mut new = CustomCrdt::new(profile_v2(), ReplicaId::from(Uuid::from_u64_pair(0, 2)));
+        old.set_field("name", serde_json::json!("Alice")).unwrap();
+        new.set_field("tags", serde_json::json!(["admin"])).unwrap();
+        new.counter("visits").unwrap().decrement().unwrap();
+
+        // The older replica stays on its version and carries the newer fields along
+        old.merge(&new).unwrap();
+        assert_eq!(old.version(), 1);
+        assert_eq!(old.get_field("name"), Some(&serde_json::json!("Alice")));
+        assert_eq!(old.get_field("tags"), Some(&serde_json::json!(["admin"])));
+        assert_eq!(old.get_field_config("visits").unwrap().strategy, CrdtStrategy::GCounter);
+        old.counter("visits").unwrap().increment().unwrap();
+
+        // The newer replica migrates the older one before merging
+        let report = new.merge_with_changes(&old).unwrap();
+        assert_eq!(new.version(), 2);
+        assert_eq!(new.get_field("full_name"), Some(&serde_json::json!("Alice")));
+        assert_eq!(new.get_field("name"), None);
+        assert_eq!(new.get_field("tags"), Some(&serde_json::json!(["admin"])));
+        // The older replica's increment survives next to the newer one's decrement
+        assert_eq!(new.get_field("visits"), Some(&serde_json::json!(0)));
+        assert_eq!(new.counter("visits").unwrap().value(), 0);
+        let mut updated = report.updated;
+        updated.sort();
+        assert_eq!(updated, vec!["full_name".to_string(), "visits".to_string()]);
+    }
 }
@@ -0,0 +1,129 @@
+//! Stable cursor anchors for sequence CRDTs
+//!
+//! An index into a sequence goes stale as soon as a remote edit lands before it.
+//! A [`RelativePosition`] instead points at an element ID, which every replica
+//! agrees on, and is turned back into an index with the replica's current state.
+
+use serde::{Deserialize, Serialize};
+
+/// Side of the anchored element that a relative position sticks to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Bias {
+    /// Just before the element; text inserted in front of it pushes the position along
+    Before,
+    /// Just after the element; text inserted behind it lands after the position
+    After,
+}
+
+/// Position in a sequence that survives concurrent edits
+///
+/// Resolves to the same gap between elements after any number of merges. When
+/// the anchored element is deleted the position collapses onto the gap it left.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RelativePosition<Id> {
+    /// Start of the sequence
+    Start,
+    /// Next to an element
+    Element { id: Id, bias: Bias },
+    /// End of the sequence
+    End,
+}
+
+impl<Id: PartialEq + Clone> RelativePosition<Id> {
+    /// Anchor the gap in front of visible index `index`
+    ///
+    /// `order` lists every element in document order with its visibility,
+    /// deleted ones included. Returns `None` if `index` is beyond the end.
+    pub(crate) fn at<'a, I>(order: I, index: usize, bias: Bias) -> Option<Self>
+    where
+        I: IntoIterator<Item = (&'a Id, bool)>,
+        Id: 'a,
+    {
+        let visible: Vec<&Id> = order.into_iter().filter(|(_, visible)| *visible).map(|(id, _)| id).collect();
+        if index > visible.len() {
+            return None;
+        }
+        let anchored = match bias {
+            Bias::Before => visible.get(index),
+            Bias::After => index.checked_sub(1).and_then(|before| visible.get(before)),
+        };
+        Some(match (anchored, bias) {
+            (Some(id), _) => Self::Element { id: (*id).clone(), bias },
+            (None, Bias::Before) => Self::End,
+            (None, Bias::After) => Self::Start,
+        })
+    }
+
+    /// Index of the gap this position points at
+    ///
+    /// `order` is the same listing as for [`RelativePosition::at`]. Returns `None`
+    /// if the anchored element is unknown, e.g. because it has not been synced yet.
+    pub(crate) fn resolve<'a, I>(&self, order: I) -> Option<usize>
+    where
+        I: IntoIterator<Item = (&'a Id, bool)>,
+        Id: 'a,
+    {
+        let (target, bias) = match self {
+            Self::Start => return Some(0),
+            Self::End => return Some(order.into_iter().filter(|(_, visible)| *visible).count()),
+            Self::Element { id, bias } => (id, bias),
+        };
+        let mut index = 0;
+        for (id, visible) in order {
+            if id == target {
+                let after = *bias == Bias::After && visible;
+                return Some(index + after as usize);
+            }
+            index += visible as usize;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(elements: &[(u32, bool)]) -> impl Iterator<Item = (&u32, bool)> {
+        elements.iter().map(|(id, visible)| (id, *visible))
+    }
+
+    #[test]
+    fn test_anchor_and_resolve_round_trip() {
+        let elements = [(1, true), (2, false), (3, true), (4, true)];
+
+        for index in 0..=3 {
+            for bias in [Bias::Before, Bias::After] {
+                let position = RelativePosition::at(order(&elements), index, bias).unwrap();
+                assert_eq!(position.resolve(order(&elements)), Some(index));
+            }
+        }
+        assert_eq!(RelativePosition::at(order(&elements), 0, Bias::After), Some(RelativePosition::Start));
+        assert_eq!(RelativePosition::at(order(&elements), 3, Bias::Before), Some(RelativePosition::End));
+        assert_eq!(RelativePosition::at(order(&elements), 4, Bias::Before), None);
+    }
+
+    #[test]
+    fn test_bias_decides_side_of_insert() {
+        let before = RelativePosition::Element { id: 3, bias: Bias::Before };
+        let after = RelativePosition::Element { id: 1, bias: Bias::After };
+
+        // Element 5 is inserted into the gap both positions point at
+        let elements = [(1, true), (5, true), (3, true)];
+        assert_eq!(after.resolve(order(&elements)), Some(1));
+        assert_eq!(before.resolve(order(&elements)), Some(2));
+    }
+
+    #[test]
+    fn test_resolve_deleted_and_unknown_elements() {
+        let elements = [(1, true), (2, false), (3, true)];
+
+        for bias in [Bias::Before, Bias::After] {
+            let position = RelativePosition::Element { id: 2, bias };
+            assert_eq!(position.resolve(order(&elements)), Some(1));
+        }
+        let unknown = RelativePosition::Element { id: 9, bias: Bias::Before };
+        assert_eq!(unknown.resolve(order(&elements)), None);
+        assert_eq!(RelativePosition::<u32>::End.resolve(order(&elements)), Some(2));
+    }
+}
@@ -5,8 +5,12 @@
 
 use super::vertex::{Vertex, VertexId, GraphError};
 use super::edge::{Edge, EdgeId};
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::query::{EdgeDirection, GraphView, Traversal, WeightedPath};
+use super::super::{CRDT, DeltaCrdt, HlcTimestamp, MergeReport, Mergeable, OpCrdt, OpId, Operation, ReplicaId, ReportingMerge, VersionVector};
+use super::GraphElementId;
+use super::super::stability::{GarbageCollect, StabilityFrontier};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet, VecDeque};
 
 /// Configuration for graph CRDTs
@@ -36,6 +40,49 @@ impl Default for GraphConfig {
     }
 }
 
+/// Local change to a graph
+#[derive(Debug, Clone, PartialEq)]
+pub enum GraphIntent<T> {
+    /// Add a vertex
+    AddVertex { value: T, timestamp: HlcTimestamp },
+    /// Add an edge between two vertices
+    AddEdge { source: VertexId, target: VertexId, weight: Option<f64>, timestamp: HlcTimestamp },
+    /// Replace the value of a vertex
+    UpdateVertex { id: VertexId, value: T, timestamp: HlcTimestamp },
+    /// Replace the weight of an edge
+    UpdateEdge { id: EdgeId, weight: f64, timestamp: HlcTimestamp },
+    /// Mark a vertex and its edges as deleted
+    RemoveVertex { id: VertexId, timestamp: HlcTimestamp },
+    /// Mark an edge as deleted
+    RemoveEdge { id: EdgeId, timestamp: HlcTimestamp },
+}
+
+/// Operation replicated by a graph
+///
+/// Operations carry the new state of what they change, which replaces the
+/// local state if it is newer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GraphOp<T> {
+    /// Add or overwrite a vertex
+    PutVertex(Vertex<T>),
+    /// Add or overwrite an edge
+    PutEdge(Edge),
+    /// Delete a vertex together with the edges it had when deleted
+    RemoveVertex { vertex: Vertex<T>, edges: Vec<Edge> },
+}
+
+impl<T: Clone + Send + Sync> Operation for GraphOp<T> {
+    fn id(&self) -> OpId {
+        let metadata = match self {
+            GraphOp::PutVertex(vertex) | GraphOp::RemoveVertex { vertex, .. } => {
+                (vertex.metadata.last_modified_by, vertex.metadata.modified_at)
+            }
+            GraphOp::PutEdge(edge) => (edge.metadata.last_modified_by, edge.metadata.modified_at),
+        };
+        OpId::new(metadata.0, metadata.1.as_u64())
+    }
+}
+
 /// Add-Wins Graph CRDT implementation
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AddWinsGraph<T> {
@@ -47,6 +94,9 @@ pub struct AddWinsGraph<T> {
     edges: HashMap<EdgeId, Edge>,
     /// Replica ID for this instance
     replica: ReplicaId,
+    /// Latest modification timestamp seen from each replica
+    #[serde(default)]
+    version: VersionVector,
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsGraph<T> {
@@ -57,6 +107,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsGraph<T> {
             vertices: HashMap::new(),
             edges: HashMap::new(),
             replica,
+            version: VersionVector::new(),
         }
     }
 
@@ -67,22 +118,71 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsGraph<T> {
             vertices: HashMap::new(),
             edges: HashMap::new(),
             replica,
+            version: VersionVector::new(),
         }
     }
 
     /// Add a vertex to the graph
-    pub fn add_vertex(&mut self, value: T, timestamp: u64) -> VertexId {
-        let vertex = Vertex::new(value, self.replica, timestamp);
+    pub fn add_vertex(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> VertexId {
+        let vertex = Vertex::new(value, self.replica, self.next_timestamp(timestamp.into()));
         let id = vertex.id.clone();
-        self.vertices.insert(id.clone(), vertex);
+        self.apply_op(&GraphOp::PutVertex(vertex));
         id
     }
 
     /// Add an edge between two vertices
-    pub fn add_edge(&mut self, source: &VertexId, target: &VertexId, timestamp: u64, weight: Option<f64>) -> Result<EdgeId, GraphError> {
+    pub fn add_edge(&mut self, source: &VertexId, target: &VertexId, timestamp: impl Into<HlcTimestamp>, weight: Option<f64>) -> Result<EdgeId, GraphError> {
+        let op = self.prepare_edge(source, target, weight, timestamp.into())?;
+        self.apply_op(&op);
+        match op {
+            GraphOp::PutEdge(edge) => Ok(edge.id),
+            _ => unreachable!("edges are added by a put operation"),
+        }
+    }
+
+    /// Update an existing vertex
+    pub fn update_vertex(&mut self, id: &VertexId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), GraphError> {
+        let op = self.prepare_update_vertex(id, value, timestamp.into())?;
+        self.apply_op(&op);
+        Ok(())
+    }
+
+    /// Update an existing edge
+    pub fn update_edge(&mut self, id: &EdgeId, weight: f64, timestamp: impl Into<HlcTimestamp>) -> Result<(), GraphError> {
+        let op = self.prepare_update_edge(id, Some(weight), timestamp.into())?;
+        self.apply_op(&op);
+        Ok(())
+    }
+
+    /// Mark a vertex as deleted
+    pub fn remove_vertex(&mut self, id: &VertexId, timestamp: impl Into<HlcTimestamp>) -> Result<(), GraphError> {
+        let op = self.prepare_remove_vertex(id, timestamp.into())?;
+        self.apply_op(&op);
+        Ok(())
+    }
+
+    /// Mark an edge as deleted
+    pub fn remove_edge(&mut self, id: &EdgeId, timestamp: impl Into<HlcTimestamp>) -> Result<(), GraphError> {
+        let op = self.prepare_update_edge(id, None, timestamp.into())?;
+        self.apply_op(&op);
+        Ok(())
+    }
+
+    /// Timestamp for a local change, ordered after every change of this replica
+    ///
+    /// Only applying the change records it in the version vector.
+    fn next_timestamp(&self, timestamp: HlcTimestamp) -> HlcTimestamp {
+        timestamp.max(HlcTimestamp::from_u64(self.version.get(&self.replica)).successor())
+    }
+
+    fn prepare_edge(&self, source: &VertexId, target: &VertexId, weight: Option<f64>, timestamp: HlcTimestamp) -> Result<GraphOp<T>, GraphError> {
         // Check if vertices exist
-        if !self.vertices.contains_key(source) || !self.vertices.contains_key(target) {
+        let (Some(source_vertex), Some(target_vertex)) = (self.vertices.get(source), self.vertices.get(target)) else {
             return Err(GraphError::new("Source or target vertex not found".to_string()));
+        };
+        // Deleted vertices take no new edges, so they can be purged once their deletion is stable
+        if source_vertex.metadata.deleted || target_vertex.metadata.deleted {
+            return Err(GraphError::new("Source or target vertex is deleted".to_string()));
         }
 
         // Check for self-loops
@@ -99,64 +199,98 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsGraph<T> {
             }
         }
 
+        let timestamp = self.next_timestamp(timestamp);
         let edge = if let Some(w) = weight {
             Edge::with_weight(source.clone(), target.clone(), w, self.replica, timestamp)
         } else {
             Edge::new(source.clone(), target.clone(), self.replica, timestamp)
         };
-        
-        let id = edge.id.clone();
-        self.edges.insert(id.clone(), edge);
-        Ok(id)
+        Ok(GraphOp::PutEdge(edge))
     }
 
-    /// Update an existing vertex
-    pub fn update_vertex(&mut self, id: &VertexId, value: T, timestamp: u64) -> Result<(), GraphError> {
-        if let Some(vertex) = self.vertices.get_mut(id) {
-            vertex.value = value;
-            vertex.mark_modified(self.replica, timestamp);
-            Ok(())
-        } else {
-            Err(GraphError::new("Vertex not found".to_string()))
-        }
+    fn prepare_update_vertex(&self, id: &VertexId, value: T, timestamp: HlcTimestamp) -> Result<GraphOp<T>, GraphError> {
+        let mut vertex = self.vertices.get(id).cloned().ok_or_else(|| GraphError::new("Vertex not found".to_string()))?;
+        let timestamp = self.next_timestamp(timestamp.max(vertex.metadata.modified_at.successor()));
+        vertex.value = value;
+        vertex.mark_modified(self.replica, timestamp);
+        Ok(GraphOp::PutVertex(vertex))
     }
 
-    /// Update an existing edge
-    pub fn update_edge(&mut self, id: &EdgeId, weight: f64, timestamp: u64) -> Result<(), GraphError> {
-        if let Some(edge) = self.edges.get_mut(id) {
-            edge.weight = Some(weight);
-            edge.mark_modified(self.replica, timestamp);
-            Ok(())
-        } else {
-            Err(GraphError::new("Edge not found".to_string()))
+    /// Update operation setting the weight of an edge, or deleting it for `None`
+    fn prepare_update_edge(&self, id: &EdgeId, weight: Option<f64>, timestamp: HlcTimestamp) -> Result<GraphOp<T>, GraphError> {
+        let mut edge = self.edges.get(id).cloned().ok_or_else(|| GraphError::new("Edge not found".to_string()))?;
+        let timestamp = self.next_timestamp(timestamp.max(edge.metadata.modified_at.successor()));
+        match weight {
+            Some(weight) => {
+                edge.weight = Some(weight);
+                edge.mark_modified(self.replica, timestamp);
+            }
+            None => edge.mark_deleted(self.replica, timestamp),
         }
+        Ok(GraphOp::PutEdge(edge))
     }
 
-    /// Mark a vertex as deleted
-    pub fn remove_vertex(&mut self, id: &VertexId, timestamp: u64) -> Result<(), GraphError> {
-        if let Some(vertex) = self.vertices.get_mut(id) {
-            vertex.mark_deleted(self.replica, timestamp);
-            
-            // Mark all incident edges as deleted
-            for edge in self.edges.values_mut() {
-                if !edge.metadata.deleted && (edge.source == *id || edge.target == *id) {
-                    edge.mark_deleted(self.replica, timestamp);
+    fn prepare_remove_vertex(&self, id: &VertexId, timestamp: HlcTimestamp) -> Result<GraphOp<T>, GraphError> {
+        let mut vertex = self.vertices.get(id).cloned().ok_or_else(|| GraphError::new("Vertex not found".to_string()))?;
+        let timestamp = self.next_timestamp(timestamp);
+        vertex.mark_deleted(self.replica, timestamp);
+
+        // Mark all incident edges as deleted
+        let edges = self
+            .edges
+            .values()
+            .filter(|edge| !edge.metadata.deleted && (edge.source == *id || edge.target == *id))
+            .map(|edge| {
+                let mut edge = edge.clone();
+                edge.mark_deleted(self.replica, timestamp);
+                edge
+            })
+            .collect();
+        Ok(GraphOp::RemoveVertex { vertex, edges })
+    }
+
+    /// Apply an operation, returning whether anything changed
+    fn apply_op(&mut self, op: &GraphOp<T>) -> bool {
+        match op {
+            GraphOp::PutVertex(vertex) => self.put_vertex(vertex),
+            GraphOp::PutEdge(edge) => self.put_edge(edge),
+            GraphOp::RemoveVertex { vertex, edges } => {
+                let mut changed = self.put_vertex(vertex);
+                for edge in edges {
+                    changed |= self.put_edge(edge);
                 }
+                changed
             }
-            Ok(())
-        } else {
-            Err(GraphError::new("Vertex not found".to_string()))
         }
     }
 
-    /// Mark an edge as deleted
-    pub fn remove_edge(&mut self, id: &EdgeId, timestamp: u64) -> Result<(), GraphError> {
-        if let Some(edge) = self.edges.get_mut(id) {
-            edge.mark_deleted(self.replica, timestamp);
-            Ok(())
-        } else {
-            Err(GraphError::new("Edge not found".to_string()))
+    /// Store a vertex state unless the local one is as new
+    fn put_vertex(&mut self, vertex: &Vertex<T>) -> bool {
+        let (replica, stamp) = (vertex.metadata.last_modified_by, vertex.metadata.modified_at);
+        let newer = match self.vertices.get(&vertex.id) {
+            Some(existing) => stamp > existing.metadata.modified_at,
+            // Seen before but gone, so it was purged
+            None => !self.version.includes(&replica, stamp.as_u64()),
+        };
+        self.version.observe(replica, stamp.as_u64());
+        if newer {
+            self.vertices.insert(vertex.id.clone(), vertex.clone());
         }
+        newer
+    }
+
+    /// Store an edge state unless the local one is as new
+    fn put_edge(&mut self, edge: &Edge) -> bool {
+        let (replica, stamp) = (edge.metadata.last_modified_by, edge.metadata.modified_at);
+        let newer = match self.edges.get(&edge.id) {
+            Some(existing) => stamp > existing.metadata.modified_at,
+            None => !self.version.includes(&replica, stamp.as_u64()),
+        };
+        self.version.observe(replica, stamp.as_u64());
+        if newer {
+            self.edges.insert(edge.id.clone(), edge.clone());
+        }
+        newer
     }
 
     /// Get a vertex by ID
@@ -276,6 +410,36 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsGraph<T> {
         None
     }
 
+    /// Index the visible vertices and edges for repeated queries
+    pub fn view(&self) -> GraphView<'_, T> {
+        GraphView::new(&self.vertices, &self.edges)
+    }
+
+    /// Start a traversal at a visible vertex, e.g. `graph.traverse(&id).out().collect()`
+    pub fn traverse(&self, start: &VertexId) -> Traversal<'_, '_, T> {
+        Traversal::new(Cow::Owned(self.view()), start)
+    }
+
+    /// Cheapest directed path by edge weight, see [`GraphView::weighted_shortest_path`]
+    pub fn weighted_shortest_path(&self, source: &VertexId, target: &VertexId) -> Result<Option<WeightedPath>, GraphError> {
+        self.view().weighted_shortest_path(source, target)
+    }
+
+    /// Topological order of the visible vertices, or a cycle, see [`GraphView::topological_order`]
+    pub fn topological_order(&self) -> Result<Vec<VertexId>, Vec<VertexId>> {
+        self.view().topological_order()
+    }
+
+    /// Whether `target` can be reached from `source` along directed edges
+    pub fn is_reachable(&self, source: &VertexId, target: &VertexId) -> bool {
+        self.view().is_reachable(source, target)
+    }
+
+    /// Vertices at most `hops` steps from `start`, see [`GraphView::neighborhood`]
+    pub fn neighborhood(&self, start: &VertexId, hops: usize, direction: EdgeDirection) -> Vec<(VertexId, usize)> {
+        self.view().neighborhood(start, hops, direction)
+    }
+
     /// Check if the graph contains a vertex
     pub fn contains_vertex(&self, id: &VertexId) -> bool {
         self.vertices.contains_key(id)
@@ -318,64 +482,179 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for AddWinsGraph<T> {
     type Error = GraphError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        // Check for conflicts in overlapping vertices
+        for (id, vertex) in &other.vertices {
+            if let Some(existing) = self.vertices.get(id) {
+                if vertex.metadata.modified_at == existing.metadata.modified_at
+                    && vertex.metadata.last_modified_by != existing.metadata.last_modified_by
+                {
+                    return true;
+                }
+            }
+        }
+
+        // Check for conflicts in overlapping edges
+        for (id, edge) in &other.edges {
+            if let Some(existing) = self.edges.get(id) {
+                if edge.metadata.modified_at == existing.metadata.modified_at
+                    && edge.metadata.last_modified_by != existing.metadata.last_modified_by
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for AddWinsGraph<T> {
+    type Key = GraphElementId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<GraphElementId>, Self::Error> {
+        let mut report = MergeReport::new();
         // Merge vertices
         for (id, vertex) in &other.vertices {
+            let key = GraphElementId::Vertex(id.clone());
             match self.vertices.get(id) {
                 Some(existing) => {
                     // Conflict resolution: later timestamp wins
                     if vertex.metadata.modified_at > existing.metadata.modified_at {
+                        let was_visible = !existing.metadata.deleted;
+                        let changed = existing.value != vertex.value;
                         self.vertices.insert(id.clone(), vertex.clone());
+                        report.record(key, was_visible, !vertex.metadata.deleted, changed);
                     }
                 }
+                // Deletion already seen, so the vertex was purged here
+                None if vertex.metadata.deleted
+                    && self.version.includes(&vertex.metadata.last_modified_by, vertex.metadata.modified_at.as_u64()) => {}
                 None => {
                     // New vertex, add it
                     self.vertices.insert(id.clone(), vertex.clone());
+                    report.record(key, false, !vertex.metadata.deleted, true);
                 }
             }
         }
 
         // Merge edges
         for (id, edge) in &other.edges {
+            let key = GraphElementId::Edge(id.clone());
             match self.edges.get(id) {
                 Some(existing) => {
                     // Conflict resolution: later timestamp wins
                     if edge.metadata.modified_at > existing.metadata.modified_at {
+                        let was_visible = !existing.metadata.deleted;
+                        let changed = (&existing.source, &existing.target, existing.weight)
+                            != (&edge.source, &edge.target, edge.weight);
                         self.edges.insert(id.clone(), edge.clone());
+                        report.record(key, was_visible, !edge.metadata.deleted, changed);
                     }
                 }
+                // Deletion already seen, so the edge was purged here
+                None if edge.metadata.deleted
+                    && self.version.includes(&edge.metadata.last_modified_by, edge.metadata.modified_at.as_u64()) => {}
                 None => {
                     // New edge, add it
                     self.edges.insert(id.clone(), edge.clone());
+                    report.record(key, false, !edge.metadata.deleted, true);
                 }
             }
         }
-        
-        Ok(())
+
+        self.version.merge(&other.version);
+        Ok(report)
     }
+}
 
-    fn has_conflict(&self, other: &Self) -> bool {
-        // Check for conflicts in overlapping vertices
-        for (id, vertex) in &other.vertices {
-            if let Some(existing) = self.vertices.get(id) {
-                if vertex.metadata.modified_at == existing.metadata.modified_at
-                    && vertex.metadata.last_modified_by != existing.metadata.last_modified_by
-                {
-                    return true;
-                }
-            }
+impl<T: Clone + PartialEq + Eq + Send + Sync> DeltaCrdt for AddWinsGraph<T> {
+    fn version_vector(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        let vertices: HashMap<_, _> = self
+            .vertices
+            .iter()
+            .filter(|(_, vertex)| {
+                !since.includes(&vertex.metadata.last_modified_by, vertex.metadata.modified_at.as_u64())
+            })
+            .map(|(id, vertex)| (id.clone(), vertex.clone()))
+            .collect();
+        let edges: HashMap<_, _> = self
+            .edges
+            .iter()
+            .filter(|(_, edge)| {
+                !since.includes(&edge.metadata.last_modified_by, edge.metadata.modified_at.as_u64())
+            })
+            .map(|(id, edge)| (id.clone(), edge.clone()))
+            .collect();
+        if vertices.is_empty() && edges.is_empty() {
+            return None;
         }
+        Some(Self {
+            config: self.config.clone(),
+            vertices,
+            edges,
+            replica: self.replica,
+            version: self.version.clone(),
+        })
+    }
+}
 
-        // Check for conflicts in overlapping edges
-        for (id, edge) in &other.edges {
-            if let Some(existing) = self.edges.get(id) {
-                if edge.metadata.modified_at == existing.metadata.modified_at
-                    && edge.metadata.last_modified_by != existing.metadata.last_modified_by
-                {
-                    return true;
-                }
+impl<T: Clone + PartialEq + Eq + Send + Sync> OpCrdt for AddWinsGraph<T> {
+    type Intent = GraphIntent<T>;
+    type Op = GraphOp<T>;
+    type Error = GraphError;
+
+    fn prepare(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error> {
+        match intent {
+            GraphIntent::AddVertex { value, timestamp } => {
+                Ok(GraphOp::PutVertex(Vertex::new(value, self.replica, self.next_timestamp(timestamp))))
             }
+            GraphIntent::AddEdge { source, target, weight, timestamp } => self.prepare_edge(&source, &target, weight, timestamp),
+            GraphIntent::UpdateVertex { id, value, timestamp } => self.prepare_update_vertex(&id, value, timestamp),
+            GraphIntent::UpdateEdge { id, weight, timestamp } => self.prepare_update_edge(&id, Some(weight), timestamp),
+            GraphIntent::RemoveVertex { id, timestamp } => self.prepare_remove_vertex(&id, timestamp),
+            GraphIntent::RemoveEdge { id, timestamp } => self.prepare_update_edge(&id, None, timestamp),
         }
-        false
+    }
+
+    fn apply(&mut self, op: &Self::Op) -> Result<bool, Self::Error> {
+        Ok(self.apply_op(op))
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> GarbageCollect for AddWinsGraph<T> {
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let before = self.vertices.len() + self.edges.len();
+        self.edges.retain(|_, edge| {
+            !(edge.metadata.deleted && frontier.is_stable_at(&edge.metadata.last_modified_by, edge.metadata.modified_at))
+        });
+
+        // A vertex goes once no edge, deleted or not, refers to it any more
+        let referenced: HashSet<&VertexId> = self
+            .edges
+            .values()
+            .flat_map(|edge| [&edge.source, &edge.target])
+            .collect();
+        let collectable: Vec<VertexId> = self
+            .vertices
+            .values()
+            .filter(|vertex| {
+                vertex.metadata.deleted
+                    && !referenced.contains(&vertex.id)
+                    && frontier.is_stable_at(&vertex.metadata.last_modified_by, vertex.metadata.modified_at)
+            })
+            .map(|vertex| vertex.id.clone())
+            .collect();
+        for id in collectable {
+            self.vertices.remove(&id);
+        }
+        before - self.vertices.len() - self.edges.len()
     }
 }
 
@@ -482,6 +761,57 @@ mod tests {
         assert!(graph1.contains_vertex(&v2_id));
     }
 
+    #[test]
+    fn test_graph_delta_since() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut graph1 = AddWinsGraph::new(replica1);
+        let v1 = graph1.add_vertex("v1", 1000);
+        let v2 = graph1.add_vertex("v2", 1001);
+        let mut graph2 = AddWinsGraph::new(replica2);
+        graph2.merge(&graph1).unwrap();
+
+        let edge_id = graph1.add_edge(&v1, &v2, 1002, None).unwrap();
+        graph1.remove_vertex(&v2, 1003).unwrap();
+        let delta = graph1.delta_since(&graph2.version_vector()).unwrap();
+        assert_eq!(delta.vertex_count(), 0);
+        assert!(delta.contains_vertex(&v2));
+        assert!(delta.get_edge(&edge_id).is_some());
+
+        graph2.apply_delta(&delta).unwrap();
+        assert!(graph2.get_vertex(&v2).unwrap().metadata.deleted);
+        assert!(graph2.get_edge(&edge_id).unwrap().metadata.deleted);
+        assert!(graph1.delta_since(&graph2.version_vector()).is_none());
+    }
+
+    #[test]
+    fn test_graph_gc_purges_stable_tombstones() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut graph1 = AddWinsGraph::new(replica1);
+        let v1 = graph1.add_vertex("v1", 1000);
+        let v2 = graph1.add_vertex("v2", 1001);
+        let edge_id = graph1.add_edge(&v1, &v2, 1002, None).unwrap();
+        let mut graph2 = AddWinsGraph::new(replica2);
+        graph2.merge(&graph1).unwrap();
+
+        graph1.remove_vertex(&v2, 2000).unwrap();
+        assert!(graph1.add_edge(&v1, &v2, 2001, None).is_err());
+        assert_eq!(graph1.gc(&StabilityFrontier::new(graph2.version_vector())), 0);
+
+        graph2.merge(&graph1).unwrap();
+        assert_eq!(graph1.gc(&StabilityFrontier::new(graph2.version_vector())), 2);
+        assert!(!graph1.contains_vertex(&v2));
+        assert!(graph1.get_edge(&edge_id).is_none());
+        assert_eq!(graph1.vertex_count(), 1);
+
+        graph1.merge(&graph2).unwrap();
+        assert!(!graph1.contains_vertex(&v2));
+        assert!(graph1.get_edge(&edge_id).is_none());
+    }
+
     #[test]
     fn test_graph_configuration() {
         let replica = create_replica(1);
@@ -517,5 +847,40 @@ mod tests {
         let result = graph.add_edge(&v1_id, &v1_id, 3000, None);
         assert!(result.is_err());
     }
-}
 
+    #[test]
+    fn test_graph_ops_converge_when_delivered_twice() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut graph1 = AddWinsGraph::new(replica1);
+        let mut graph2 = AddWinsGraph::new(replica2);
+        let v1 = graph1.perform(GraphIntent::AddVertex { value: "v1", timestamp: 1000.into() }).unwrap();
+        let v2 = graph1.perform(GraphIntent::AddVertex { value: "v2", timestamp: 1001.into() }).unwrap();
+        let (GraphOp::PutVertex(source), GraphOp::PutVertex(target)) = (&v1, &v2) else {
+            panic!("adding a vertex puts it");
+        };
+        let edge = graph1
+            .perform(GraphIntent::AddEdge { source: source.id.clone(), target: target.id.clone(), weight: None, timestamp: 1002.into() })
+            .unwrap();
+        for op in [&v1, &v2, &edge, &v1, &edge] {
+            graph2.apply(op).unwrap();
+        }
+        assert_eq!(graph2.edge_count(), 1);
+
+        // Concurrent update and removal: the removal carries the later timestamp
+        let update = graph2
+            .perform(GraphIntent::UpdateVertex { id: target.id.clone(), value: "renamed", timestamp: 2000.into() })
+            .unwrap();
+        let remove = graph1.perform(GraphIntent::RemoveVertex { id: target.id.clone(), timestamp: 3000.into() }).unwrap();
+        assert!(graph1.apply(&update).is_ok_and(|changed| !changed));
+        assert!(graph2.apply(&remove).unwrap());
+        assert!(!graph2.apply(&remove).unwrap());
+
+        for graph in [&graph1, &graph2] {
+            assert!(graph.get_vertex(&target.id).unwrap().metadata.deleted);
+            assert_eq!(graph.vertex_count(), 1);
+            assert_eq!(graph.edge_count(), 0);
+        }
+    }
+}
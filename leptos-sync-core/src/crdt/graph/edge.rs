@@ -1,7 +1,7 @@
 //! Graph edge operations and types
 
 use super::vertex::{VertexId, GraphError};
-use super::super::ReplicaId;
+use super::super::{HlcTimestamp, ReplicaId};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -33,9 +33,9 @@ impl EdgeId {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EdgeMetadata {
     /// When the edge was created
-    pub created_at: u64,
+    pub created_at: HlcTimestamp,
     /// When the edge was last modified
-    pub modified_at: u64,
+    pub modified_at: HlcTimestamp,
     /// Whether the edge is marked as deleted
     pub deleted: bool,
     /// Replica that last modified the edge
@@ -44,7 +44,8 @@ pub struct EdgeMetadata {
 
 impl EdgeMetadata {
     /// Create new metadata
-    pub fn new(replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
+        let timestamp = timestamp.into();
         Self {
             created_at: timestamp,
             modified_at: timestamp,
@@ -54,13 +55,16 @@ impl EdgeMetadata {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
-        self.modified_at = timestamp;
+    ///
+    /// The modification always orders after the previous one, even if the
+    /// supplied timestamp is behind it.
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
+        self.modified_at = timestamp.into().max(self.modified_at.successor());
         self.last_modified_by = replica;
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.deleted = true;
         self.mark_modified(replica, timestamp);
     }
@@ -83,7 +87,7 @@ pub struct Edge {
 
 impl Edge {
     /// Create a new edge
-    pub fn new(source: VertexId, target: VertexId, replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(source: VertexId, target: VertexId, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
         Self {
             id: EdgeId::new(replica),
             source,
@@ -94,7 +98,7 @@ impl Edge {
     }
 
     /// Create a new edge with weight
-    pub fn with_weight(source: VertexId, target: VertexId, weight: f64, replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn with_weight(source: VertexId, target: VertexId, weight: f64, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
         Self {
             id: EdgeId::new(replica),
             source,
@@ -105,12 +109,12 @@ impl Edge {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_modified(replica, timestamp);
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_deleted(replica, timestamp);
     }
 }
@@ -145,7 +149,7 @@ mod tests {
         assert_eq!(edge.source, source);
         assert_eq!(edge.target, target);
         assert_eq!(edge.weight, None);
-        assert_eq!(edge.metadata.created_at, timestamp);
+        assert_eq!(edge.metadata.created_at, HlcTimestamp::from(timestamp));
         assert_eq!(edge.metadata.deleted, false);
     }
 
@@ -170,13 +174,13 @@ mod tests {
         // Test modification
         let new_timestamp = 1234567891;
         metadata.mark_modified(replica, new_timestamp);
-        assert_eq!(metadata.modified_at, new_timestamp);
+        assert_eq!(metadata.modified_at, HlcTimestamp::from(new_timestamp));
         
         // Test deletion
         let delete_timestamp = 1234567892;
         metadata.mark_deleted(replica, delete_timestamp);
         assert_eq!(metadata.deleted, true);
-        assert_eq!(metadata.modified_at, delete_timestamp);
+        assert_eq!(metadata.modified_at, HlcTimestamp::from(delete_timestamp));
     }
 }
 
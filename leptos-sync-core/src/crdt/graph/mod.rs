@@ -4,20 +4,32 @@
 //! - Add-Wins Graph: Preserves deleted elements for potential recovery
 //! - Remove-Wins Graph: Completely removes deleted elements for memory efficiency
 //! - Graph algorithms: Path finding, connectivity analysis, etc.
+//! - Graph queries: Indexed traversals, weighted paths and ordering over visible elements
 
 pub mod add_wins;
 pub mod algorithms;
 pub mod edge;
+pub mod query;
 pub mod remove_wins;
 pub mod vertex;
 
 // Re-export main types for convenience
-pub use add_wins::{AddWinsGraph, GraphConfig};
+pub use add_wins::{AddWinsGraph, GraphConfig, GraphIntent, GraphOp};
 pub use algorithms::GraphAlgorithms;
 pub use edge::{Edge, EdgeId, EdgeMetadata};
+pub use query::{EdgeDirection, GraphView, Traversal, WeightedPath};
 pub use remove_wins::RemoveWinsGraph;
 pub use vertex::{GraphError, Vertex, VertexId, VertexMetadata};
 
+/// Key of a vertex or an edge, for reports covering both
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum GraphElementId {
+    /// A vertex
+    Vertex(VertexId),
+    /// An edge
+    Edge(EdgeId),
+}
+
 /// Strategy for handling graph conflicts
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum GraphStrategy {
@@ -29,7 +41,7 @@ pub enum GraphStrategy {
 
 #[cfg(test)]
 mod integration_tests {
-    use super::super::{ReplicaId, basic::traits::Mergeable};
+    use super::super::{ReplicaId, ReportingMerge, basic::traits::Mergeable};
     use super::*;
     use uuid::Uuid;
 
@@ -108,6 +120,61 @@ mod integration_tests {
         assert!(graph1.contains_vertex(&v2_id));
     }
 
+    #[test]
+    fn test_graph_merge_with_changes() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut graph1 = AddWinsGraph::new(replica1);
+        let v1_id = graph1.add_vertex("vertex1", 1000);
+        let v2_id = graph1.add_vertex("vertex2", 1001);
+        let edge_id = graph1.add_edge(&v1_id, &v2_id, 1002, None).unwrap();
+        let mut graph2 = AddWinsGraph::new(replica2);
+        graph2.merge(&graph1).unwrap();
+
+        graph2.update_vertex(&v1_id, "renamed", 2000).unwrap();
+        graph2.remove_edge(&edge_id, 2001).unwrap();
+        let v3_id = graph2.add_vertex("vertex3", 2002);
+
+        let report = graph1.merge_with_changes(&graph2).unwrap();
+        assert_eq!(report.inserted, vec![GraphElementId::Vertex(v3_id)]);
+        assert_eq!(report.updated, vec![GraphElementId::Vertex(v1_id)]);
+        assert_eq!(report.removed, vec![GraphElementId::Edge(edge_id)]);
+    }
+
+    #[test]
+    fn test_graph_queries_skip_removed_elements() {
+        let replica = create_replica(1);
+        let mut graph = AddWinsGraph::new(replica);
+        let project = graph.add_vertex("project", 1000);
+        let design = graph.add_vertex("design", 1001);
+        let build = graph.add_vertex("build", 1002);
+        let dropped = graph.add_vertex("dropped", 1003);
+        graph.add_edge(&project, &design, 1004, Some(2.0)).unwrap();
+        graph.add_edge(&design, &build, 1005, Some(2.0)).unwrap();
+        graph.add_edge(&project, &build, 1006, Some(5.0)).unwrap();
+        graph.add_edge(&project, &dropped, 1007, None).unwrap();
+        graph.remove_vertex(&dropped, 1008).unwrap();
+
+        let next: Vec<_> = graph.traverse(&project).out().filter(|v| v.value != "build").collect();
+        assert_eq!(next.len(), 1);
+        assert_eq!(next[0].id, design);
+
+        let path = graph.weighted_shortest_path(&project, &build).unwrap().unwrap();
+        assert_eq!(path.vertices, vec![project.clone(), design.clone(), build.clone()]);
+        assert_eq!(path.cost, 4.0);
+        assert_eq!(graph.topological_order().unwrap(), vec![project.clone(), design.clone(), build.clone()]);
+        assert!(!graph.is_reachable(&project, &dropped));
+        assert_eq!(graph.neighborhood(&build, 1, EdgeDirection::Incoming).len(), 2);
+
+        let mut graph = RemoveWinsGraph::new(replica);
+        let a = graph.add_vertex("a", 1000);
+        let b = graph.add_vertex("b", 1001);
+        graph.add_edge(&a, &b, 1002, None).unwrap();
+        graph.add_edge(&b, &a, 1003, None).unwrap();
+        assert_eq!(graph.topological_order().unwrap_err().len(), 2);
+    }
+
     #[test]
     fn test_graph_configuration() {
         let replica = create_replica(1);
@@ -0,0 +1,518 @@
+//! Indexed queries and traversals over the visible part of a graph
+//!
+//! [`GraphView`] indexes the visible vertices and edges once, in time linear
+//! in the size of the graph, so each query afterwards only touches the part
+//! of the graph it explores. Build a view once and reuse it when running
+//! several queries against the same state.
+
+use super::edge::{Edge, EdgeId};
+use super::vertex::{GraphError, Vertex, VertexId};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Which edges to follow from a vertex
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeDirection {
+    /// From source to target
+    Outgoing,
+    /// From target to source
+    Incoming,
+    /// Either way
+    Both,
+}
+
+/// Path found by [`GraphView::weighted_shortest_path`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct WeightedPath {
+    /// Vertices from source to target
+    pub vertices: Vec<VertexId>,
+    /// Sum of the edge weights along the path
+    pub cost: f64,
+}
+
+/// Read-only index of the visible vertices and edges of a graph
+///
+/// Edges are visible when they and both their endpoints are not deleted.
+#[derive(Debug, Clone)]
+pub struct GraphView<'a, T> {
+    vertices: Vec<&'a Vertex<T>>,
+    index: HashMap<&'a VertexId, usize>,
+    outgoing: Vec<Vec<(usize, &'a Edge)>>,
+    incoming: Vec<Vec<(usize, &'a Edge)>>,
+    edge_count: usize,
+}
+
+impl<'a, T> GraphView<'a, T> {
+    /// Index the visible part of a graph
+    pub fn new(vertices: &'a HashMap<VertexId, Vertex<T>>, edges: &'a HashMap<EdgeId, Edge>) -> Self {
+        let vertices: Vec<_> = vertices.values().filter(|v| !v.metadata.deleted).collect();
+        let index: HashMap<_, _> = vertices.iter().enumerate().map(|(i, v)| (&v.id, i)).collect();
+        let mut outgoing = vec![Vec::new(); vertices.len()];
+        let mut incoming = vec![Vec::new(); vertices.len()];
+        let mut edge_count = 0;
+
+        for edge in edges.values().filter(|e| !e.metadata.deleted) {
+            if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+                outgoing[source].push((target, edge));
+                incoming[target].push((source, edge));
+                edge_count += 1;
+            }
+        }
+
+        Self {
+            vertices,
+            index,
+            outgoing,
+            incoming,
+            edge_count,
+        }
+    }
+
+    /// Number of visible vertices
+    pub fn vertex_count(&self) -> usize {
+        self.vertices.len()
+    }
+
+    /// Number of visible edges
+    pub fn edge_count(&self) -> usize {
+        self.edge_count
+    }
+
+    /// Get a visible vertex
+    pub fn vertex(&self, id: &VertexId) -> Option<&'a Vertex<T>> {
+        self.index.get(id).map(|&i| self.vertices[i])
+    }
+
+    /// Visible edges leaving a vertex
+    pub fn outgoing_edges(&self, id: &VertexId) -> impl Iterator<Item = &'a Edge> + '_ {
+        self.edges_at(id, &self.outgoing)
+    }
+
+    /// Visible edges entering a vertex
+    pub fn incoming_edges(&self, id: &VertexId) -> impl Iterator<Item = &'a Edge> + '_ {
+        self.edges_at(id, &self.incoming)
+    }
+
+    fn edges_at<'s>(&'s self, id: &VertexId, adjacency: &'s [Vec<(usize, &'a Edge)>]) -> impl Iterator<Item = &'a Edge> + 's {
+        self.index
+            .get(id)
+            .into_iter()
+            .flat_map(move |&i| adjacency[i].iter().map(|(_, edge)| *edge))
+    }
+
+    /// Indices of the vertices one step away in `direction`
+    fn step(&self, i: usize, direction: EdgeDirection) -> impl Iterator<Item = usize> + '_ {
+        let outgoing = matches!(direction, EdgeDirection::Outgoing | EdgeDirection::Both).then(|| &self.outgoing[i]);
+        let incoming = matches!(direction, EdgeDirection::Incoming | EdgeDirection::Both).then(|| &self.incoming[i]);
+        outgoing.into_iter().chain(incoming).flatten().map(|(j, _)| *j)
+    }
+
+    /// Cheapest directed path by edge weight, using Dijkstra's algorithm
+    ///
+    /// Edges without a weight cost 1. Fails on a negative or NaN weight met
+    /// during the search.
+    pub fn weighted_shortest_path(&self, source: &VertexId, target: &VertexId) -> Result<Option<WeightedPath>, GraphError> {
+        let (Some(&source), Some(&target)) = (self.index.get(source), self.index.get(target)) else {
+            return Ok(None);
+        };
+
+        let mut cost = vec![f64::INFINITY; self.vertices.len()];
+        let mut parent = vec![usize::MAX; self.vertices.len()];
+        let mut queue = BinaryHeap::new();
+        cost[source] = 0.0;
+        queue.push(Reverse(Candidate(0.0, source)));
+
+        while let Some(Reverse(Candidate(reached, i))) = queue.pop() {
+            if i == target {
+                let mut vertices = vec![self.vertices[i].id.clone()];
+                let mut current = i;
+                while current != source {
+                    current = parent[current];
+                    vertices.push(self.vertices[current].id.clone());
+                }
+                vertices.reverse();
+                return Ok(Some(WeightedPath { vertices, cost: reached }));
+            }
+            if reached > cost[i] {
+                // Stale entry, a cheaper one was already expanded
+                continue;
+            }
+
+            for &(j, edge) in &self.outgoing[i] {
+                let weight = edge.weight.unwrap_or(1.0);
+                if weight.is_nan() || weight < 0.0 {
+                    return Err(GraphError::new(format!("Edge {:?} has invalid weight {}", edge.id, weight)));
+                }
+                let next = reached + weight;
+                if next < cost[j] {
+                    cost[j] = next;
+                    parent[j] = i;
+                    queue.push(Reverse(Candidate(next, j)));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Vertices ordered so every edge points forward, or a cycle if there is none
+    ///
+    /// Among vertices whose predecessors are all placed, the smallest ID comes
+    /// first, so replicas with the same state get the same order. The cycle is
+    /// returned as the vertices along it, starting from its smallest ID.
+    pub fn topological_order(&self) -> Result<Vec<VertexId>, Vec<VertexId>> {
+        let mut pending: Vec<usize> = self.incoming.iter().map(Vec::len).collect();
+        let mut ready: BinaryHeap<_> = (0..self.vertices.len())
+            .filter(|&i| pending[i] == 0)
+            .map(|i| Reverse((&self.vertices[i].id, i)))
+            .collect();
+
+        let mut order = Vec::with_capacity(self.vertices.len());
+        while let Some(Reverse((id, i))) = ready.pop() {
+            order.push(id.clone());
+            for &(j, _) in &self.outgoing[i] {
+                pending[j] -= 1;
+                if pending[j] == 0 {
+                    ready.push(Reverse((&self.vertices[j].id, j)));
+                }
+            }
+        }
+
+        if order.len() == self.vertices.len() {
+            Ok(order)
+        } else {
+            Err(self.find_cycle(&pending))
+        }
+    }
+
+    /// A cycle among the vertices Kahn's algorithm could not place
+    fn find_cycle(&self, pending: &[usize]) -> Vec<VertexId> {
+        // Every stuck vertex has a stuck predecessor, so walking predecessors
+        // from any of them must revisit a vertex
+        let Some(start) = (0..self.vertices.len())
+            .filter(|&i| pending[i] > 0)
+            .min_by_key(|&i| &self.vertices[i].id)
+        else {
+            return Vec::new();
+        };
+
+        let mut seen = HashMap::new();
+        let mut walk = Vec::new();
+        let mut current = start;
+        while !seen.contains_key(&current) {
+            seen.insert(current, walk.len());
+            walk.push(current);
+            current = self.incoming[current]
+                .iter()
+                .map(|&(j, _)| j)
+                .filter(|&j| pending[j] > 0)
+                .min_by_key(|&j| &self.vertices[j].id)
+                .expect("stuck vertex has a stuck predecessor");
+        }
+
+        // Walked against the edges, so reverse to follow them
+        let mut cycle: Vec<_> = walk[seen[&current]..].iter().rev().copied().collect();
+        let smallest = (0..cycle.len()).min_by_key(|&k| &self.vertices[cycle[k]].id).unwrap_or(0);
+        cycle.rotate_left(smallest);
+        cycle.into_iter().map(|i| self.vertices[i].id.clone()).collect()
+    }
+
+    /// Whether `target` can be reached from `source` along directed edges
+    pub fn is_reachable(&self, source: &VertexId, target: &VertexId) -> bool {
+        let (Some(&source), Some(&target)) = (self.index.get(source), self.index.get(target)) else {
+            return false;
+        };
+        let mut found = source == target;
+        self.breadth_first(source, EdgeDirection::Outgoing, usize::MAX, |i, _| {
+            found |= i == target;
+            !found
+        });
+        found
+    }
+
+    /// Vertices reachable from `source` along directed edges, `source` excluded
+    pub fn reachable(&self, source: &VertexId) -> Vec<VertexId> {
+        self.neighborhood(source, usize::MAX, EdgeDirection::Outgoing)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Vertices at most `hops` steps from `start`, with their distance
+    ///
+    /// `start` itself is not included. Vertices come in breadth-first order.
+    pub fn neighborhood(&self, start: &VertexId, hops: usize, direction: EdgeDirection) -> Vec<(VertexId, usize)> {
+        let Some(&start) = self.index.get(start) else {
+            return Vec::new();
+        };
+        let mut found = Vec::new();
+        self.breadth_first(start, direction, hops, |i, depth| {
+            found.push((self.vertices[i].id.clone(), depth));
+            true
+        });
+        found
+    }
+
+    /// Visit vertices up to `hops` steps from `start` until `visit` returns false
+    fn breadth_first(&self, start: usize, direction: EdgeDirection, hops: usize, mut visit: impl FnMut(usize, usize) -> bool) {
+        let mut visited = vec![false; self.vertices.len()];
+        let mut queue = VecDeque::from([(start, 0)]);
+        visited[start] = true;
+
+        while let Some((i, depth)) = queue.pop_front() {
+            if depth == hops {
+                continue;
+            }
+            for j in self.step(i, direction) {
+                if !visited[j] {
+                    visited[j] = true;
+                    if !visit(j, depth + 1) {
+                        return;
+                    }
+                    queue.push_back((j, depth + 1));
+                }
+            }
+        }
+    }
+
+    /// Start a traversal at `start`
+    ///
+    /// The traversal is empty if `start` is not a visible vertex.
+    pub fn traverse(&self, start: &VertexId) -> Traversal<'_, 'a, T>
+    where
+        T: Clone,
+    {
+        Traversal::new(Cow::Borrowed(self), start)
+    }
+}
+
+/// Dijkstra queue entry, ordered by cost
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Candidate(f64, usize);
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(self.1.cmp(&other.1))
+    }
+}
+
+/// Step-by-step traversal over a [`GraphView`]
+///
+/// Each step replaces the current set of vertices, e.g.
+/// `graph.traverse(&id).out().filter(|v| v.value.done).collect()`.
+/// A vertex reached several ways is kept once per step.
+#[derive(Debug, Clone)]
+pub struct Traversal<'v, 'a, T: Clone> {
+    view: Cow<'v, GraphView<'a, T>>,
+    current: Vec<usize>,
+}
+
+impl<'v, 'a, T: Clone> Traversal<'v, 'a, T> {
+    pub(crate) fn new(view: Cow<'v, GraphView<'a, T>>, start: &VertexId) -> Self {
+        let current = view.index.get(start).copied().into_iter().collect();
+        Self { view, current }
+    }
+
+    /// Move to the targets of the visible outgoing edges
+    pub fn out(self) -> Self {
+        self.step(EdgeDirection::Outgoing)
+    }
+
+    /// Move to the sources of the visible incoming edges
+    pub fn incoming(self) -> Self {
+        self.step(EdgeDirection::Incoming)
+    }
+
+    /// Move along visible edges in either direction
+    pub fn both(self) -> Self {
+        self.step(EdgeDirection::Both)
+    }
+
+    /// Move to the neighbours in `direction`
+    pub fn step(mut self, direction: EdgeDirection) -> Self {
+        let mut seen = HashSet::new();
+        let next = self
+            .current
+            .iter()
+            .flat_map(|&i| self.view.step(i, direction))
+            .filter(|&j| seen.insert(j))
+            .collect();
+        self.current = next;
+        self
+    }
+
+    /// Keep the vertices matching `predicate`
+    pub fn filter(mut self, predicate: impl Fn(&Vertex<T>) -> bool) -> Self {
+        let vertices = &self.view.vertices;
+        self.current.retain(|&i| predicate(vertices[i]));
+        self
+    }
+
+    /// Number of vertices at the current step
+    pub fn count(&self) -> usize {
+        self.current.len()
+    }
+
+    /// IDs of the vertices at the current step
+    pub fn ids(&self) -> Vec<VertexId> {
+        self.current.iter().map(|&i| self.view.vertices[i].id.clone()).collect()
+    }
+
+    /// Vertices at the current step
+    pub fn collect(self) -> Vec<&'a Vertex<T>> {
+        let vertices = &self.view.vertices;
+        self.current.iter().map(|&i| vertices[i]).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::ReplicaId;
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    type Fixture = (HashMap<VertexId, Vertex<&'static str>>, HashMap<EdgeId, Edge>, Vec<VertexId>);
+
+    /// Vertices named by `names` and weighted edges between them by index
+    fn build(names: &[&'static str], links: &[(usize, usize, Option<f64>)]) -> Fixture {
+        let replica = create_replica(1);
+        let mut vertices = HashMap::new();
+        let mut ids = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let mut vertex = Vertex::new(*name, replica, 1000 + i as u64);
+            vertex.id = VertexId::from_parts(Uuid::from_u64_pair(0, i as u64), replica);
+            ids.push(vertex.id.clone());
+            vertices.insert(vertex.id.clone(), vertex);
+        }
+        let mut edges = HashMap::new();
+        for (k, &(from, to, weight)) in links.iter().enumerate() {
+            let mut edge = Edge::new(ids[from].clone(), ids[to].clone(), replica, 2000 + k as u64);
+            edge.weight = weight;
+            edges.insert(edge.id.clone(), edge);
+        }
+        (vertices, edges, ids)
+    }
+
+    #[test]
+    fn test_weighted_shortest_path() {
+        // a -> b -> d is shorter by hops, a -> c -> d by weight
+        let (vertices, edges, ids) = build(
+            &["a", "b", "c", "d"],
+            &[(0, 1, Some(5.0)), (1, 3, Some(5.0)), (0, 2, Some(1.0)), (2, 3, None)],
+        );
+        let view = GraphView::new(&vertices, &edges);
+
+        let path = view.weighted_shortest_path(&ids[0], &ids[3]).unwrap().unwrap();
+        assert_eq!(path.vertices, vec![ids[0].clone(), ids[2].clone(), ids[3].clone()]);
+        assert_eq!(path.cost, 2.0);
+        // Edges are directed
+        assert_eq!(view.weighted_shortest_path(&ids[3], &ids[0]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_weighted_shortest_path_rejects_negative_weight() {
+        let (vertices, edges, ids) = build(&["a", "b"], &[(0, 1, Some(-1.0))]);
+        let view = GraphView::new(&vertices, &edges);
+        assert!(view.weighted_shortest_path(&ids[0], &ids[1]).is_err());
+    }
+
+    #[test]
+    fn test_topological_order_and_cycle() {
+        let (vertices, edges, ids) = build(&["a", "b", "c", "d"], &[(2, 1, None), (1, 0, None), (3, 0, None)]);
+        let order = GraphView::new(&vertices, &edges).topological_order().unwrap();
+        assert_eq!(order, vec![ids[2].clone(), ids[1].clone(), ids[3].clone(), ids[0].clone()]);
+
+        let (vertices, edges, ids) = build(
+            &["a", "b", "c", "d"],
+            &[(0, 1, None), (1, 2, None), (2, 3, None), (3, 1, None)],
+        );
+        let cycle = GraphView::new(&vertices, &edges).topological_order().unwrap_err();
+        assert_eq!(cycle, vec![ids[1].clone(), ids[2].clone(), ids[3].clone()]);
+    }
+
+    #[test]
+    fn test_reachability_and_neighborhood() {
+        let (vertices, edges, ids) = build(
+            &["a", "b", "c", "d", "e"],
+            &[(0, 1, None), (1, 2, None), (2, 3, None), (4, 0, None)],
+        );
+        let view = GraphView::new(&vertices, &edges);
+
+        assert!(view.is_reachable(&ids[0], &ids[3]));
+        assert!(!view.is_reachable(&ids[3], &ids[0]));
+        assert_eq!(view.reachable(&ids[1]), vec![ids[2].clone(), ids[3].clone()]);
+
+        let within_two = view.neighborhood(&ids[0], 2, EdgeDirection::Outgoing);
+        assert_eq!(within_two, vec![(ids[1].clone(), 1), (ids[2].clone(), 2)]);
+        let mut around = view.neighborhood(&ids[0], 1, EdgeDirection::Both);
+        around.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(around, vec![(ids[1].clone(), 1), (ids[4].clone(), 1)]);
+    }
+
+    #[test]
+    fn test_view_skips_deleted_elements() {
+        let (mut vertices, mut edges, ids) = build(&["a", "b", "c"], &[(0, 1, None), (1, 2, None)]);
+        let replica = create_replica(1);
+        vertices.get_mut(&ids[2]).unwrap().mark_deleted(replica, 3000);
+        let first = edges.values().find(|e| e.source == ids[0]).unwrap().id.clone();
+        edges.get_mut(&first).unwrap().mark_deleted(replica, 3001);
+
+        let view = GraphView::new(&vertices, &edges);
+        assert_eq!(view.vertex_count(), 2);
+        assert_eq!(view.edge_count(), 0);
+        assert!(view.vertex(&ids[2]).is_none());
+        assert!(!view.is_reachable(&ids[0], &ids[1]));
+    }
+
+    #[test]
+    fn test_traversal_steps() {
+        let (vertices, edges, ids) = build(
+            &["root", "done", "open", "leaf"],
+            &[(0, 1, None), (0, 2, None), (1, 3, None), (2, 3, None)],
+        );
+        let view = GraphView::new(&vertices, &edges);
+
+        let done: Vec<_> = view.traverse(&ids[0]).out().filter(|v| v.value == "done").ids();
+        assert_eq!(done, vec![ids[1].clone()]);
+        // Reached twice, kept once
+        let leaves = view.traverse(&ids[0]).out().out().collect();
+        assert_eq!(leaves.len(), 1);
+        assert_eq!(leaves[0].value, "leaf");
+        assert_eq!(view.traverse(&ids[3]).incoming().incoming().ids(), vec![ids[0].clone()]);
+        assert_eq!(view.traverse(&ids[1]).both().count(), 2);
+    }
+
+    #[test]
+    fn test_large_graph_queries() {
+        // 20k vertices in a chain plus 80k forward shortcuts, 100k edges in all
+        let n = 20_000;
+        let names = vec!["v"; n];
+        let mut links: Vec<_> = (0..n - 1).map(|i| (i, i + 1, Some(1.0))).collect();
+        for k in 0..80_000 {
+            let from = (k * 7919) % (n - 1);
+            let to = (from + 1 + k % 50).min(n - 1);
+            links.push((from, to, Some(2.0)));
+        }
+        let (vertices, edges, ids) = build(&names, &links);
+        let view = GraphView::new(&vertices, &edges);
+        assert_eq!(view.edge_count(), 100_000 - 1);
+
+        assert_eq!(view.topological_order().unwrap().len(), n);
+        assert_eq!(view.reachable(&ids[0]).len(), n - 1);
+        let path = view.weighted_shortest_path(&ids[0], &ids[n - 1]).unwrap().unwrap();
+        assert!(path.cost <= (n - 1) as f64);
+    }
+}
@@ -3,11 +3,14 @@
 //! This implementation completely removes deleted vertices and edges.
 //! It's more memory-efficient but elements cannot be recovered.
 
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::super::{CRDT, HlcTimestamp, MergeReport, Mergeable, ReplicaId, ReportingMerge};
 use super::add_wins::GraphConfig;
 use super::edge::{Edge, EdgeId};
+use super::query::{EdgeDirection, GraphView, Traversal, WeightedPath};
 use super::vertex::{GraphError, Vertex, VertexId};
+use super::GraphElementId;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet, VecDeque};
 
 /// Remove-Wins Graph CRDT implementation
@@ -51,7 +54,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsGraph<T> {
     }
 
     /// Add a vertex to the graph
-    pub fn add_vertex(&mut self, value: T, timestamp: u64) -> VertexId {
+    pub fn add_vertex(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> VertexId {
         let vertex = Vertex::new(value, self.replica, timestamp);
         let id = vertex.id.clone();
         self.vertices.insert(id.clone(), vertex);
@@ -63,7 +66,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsGraph<T> {
         &mut self,
         source: &VertexId,
         target: &VertexId,
-        timestamp: u64,
+        timestamp: impl Into<HlcTimestamp>,
         weight: Option<f64>,
     ) -> Result<EdgeId, GraphError> {
         // Check if vertices exist
@@ -105,7 +108,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsGraph<T> {
         &mut self,
         id: &VertexId,
         value: T,
-        timestamp: u64,
+        timestamp: impl Into<HlcTimestamp>,
     ) -> Result<(), GraphError> {
         if let Some(vertex) = self.vertices.get_mut(id) {
             vertex.value = value;
@@ -121,7 +124,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsGraph<T> {
         &mut self,
         id: &EdgeId,
         weight: f64,
-        timestamp: u64,
+        timestamp: impl Into<HlcTimestamp>,
     ) -> Result<(), GraphError> {
         if let Some(edge) = self.edges.get_mut(id) {
             edge.weight = Some(weight);
@@ -242,6 +245,36 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsGraph<T> {
         None
     }
 
+    /// Index the visible vertices and edges for repeated queries
+    pub fn view(&self) -> GraphView<'_, T> {
+        GraphView::new(&self.vertices, &self.edges)
+    }
+
+    /// Start a traversal at a visible vertex, e.g. `graph.traverse(&id).out().collect()`
+    pub fn traverse(&self, start: &VertexId) -> Traversal<'_, '_, T> {
+        Traversal::new(Cow::Owned(self.view()), start)
+    }
+
+    /// Cheapest directed path by edge weight, see [`GraphView::weighted_shortest_path`]
+    pub fn weighted_shortest_path(&self, source: &VertexId, target: &VertexId) -> Result<Option<WeightedPath>, GraphError> {
+        self.view().weighted_shortest_path(source, target)
+    }
+
+    /// Topological order of the visible vertices, or a cycle, see [`GraphView::topological_order`]
+    pub fn topological_order(&self) -> Result<Vec<VertexId>, Vec<VertexId>> {
+        self.view().topological_order()
+    }
+
+    /// Whether `target` can be reached from `source` along directed edges
+    pub fn is_reachable(&self, source: &VertexId, target: &VertexId) -> bool {
+        self.view().is_reachable(source, target)
+    }
+
+    /// Vertices at most `hops` steps from `start`, see [`GraphView::neighborhood`]
+    pub fn neighborhood(&self, start: &VertexId, hops: usize, direction: EdgeDirection) -> Vec<(VertexId, usize)> {
+        self.view().neighborhood(start, hops, direction)
+    }
+
     /// Check if the graph contains a vertex
     pub fn contains_vertex(&self, id: &VertexId) -> bool {
         self.vertices.contains_key(id)
@@ -284,64 +317,84 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsGraph<T> {
     type Error = GraphError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        // Check for conflicts in overlapping vertices
+        for (id, vertex) in &other.vertices {
+            if let Some(existing) = self.vertices.get(id) {
+                if vertex.metadata.modified_at == existing.metadata.modified_at
+                    && vertex.metadata.last_modified_by != existing.metadata.last_modified_by
+                {
+                    return true;
+                }
+            }
+        }
+
+        // Check for conflicts in overlapping edges
+        for (id, edge) in &other.edges {
+            if let Some(existing) = self.edges.get(id) {
+                if edge.metadata.modified_at == existing.metadata.modified_at
+                    && edge.metadata.last_modified_by != existing.metadata.last_modified_by
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for RemoveWinsGraph<T> {
+    type Key = GraphElementId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<GraphElementId>, Self::Error> {
+        let mut report = MergeReport::new();
         // Merge vertices
         for (id, vertex) in &other.vertices {
+            let key = GraphElementId::Vertex(id.clone());
             match self.vertices.get(id) {
                 Some(existing) => {
                     // Conflict resolution: later timestamp wins
                     if vertex.metadata.modified_at > existing.metadata.modified_at {
+                        let was_visible = !existing.metadata.deleted;
+                        let changed = existing.value != vertex.value;
                         self.vertices.insert(id.clone(), vertex.clone());
+                        report.record(key, was_visible, !vertex.metadata.deleted, changed);
                     }
                 }
                 None => {
                     // New vertex, add it
                     self.vertices.insert(id.clone(), vertex.clone());
+                    report.record(key, false, !vertex.metadata.deleted, true);
                 }
             }
         }
 
         // Merge edges
         for (id, edge) in &other.edges {
+            let key = GraphElementId::Edge(id.clone());
             match self.edges.get(id) {
                 Some(existing) => {
                     // Conflict resolution: later timestamp wins
                     if edge.metadata.modified_at > existing.metadata.modified_at {
+                        let was_visible = !existing.metadata.deleted;
+                        let changed = (&existing.source, &existing.target, existing.weight)
+                            != (&edge.source, &edge.target, edge.weight);
                         self.edges.insert(id.clone(), edge.clone());
+                        report.record(key, was_visible, !edge.metadata.deleted, changed);
                     }
                 }
                 None => {
                     // New edge, add it
                     self.edges.insert(id.clone(), edge.clone());
+                    report.record(key, false, !edge.metadata.deleted, true);
                 }
             }
         }
 
-        Ok(())
-    }
-
-    fn has_conflict(&self, other: &Self) -> bool {
-        // Check for conflicts in overlapping vertices
-        for (id, vertex) in &other.vertices {
-            if let Some(existing) = self.vertices.get(id) {
-                if vertex.metadata.modified_at == existing.metadata.modified_at
-                    && vertex.metadata.last_modified_by != existing.metadata.last_modified_by
-                {
-                    return true;
-                }
-            }
-        }
-
-        // Check for conflicts in overlapping edges
-        for (id, edge) in &other.edges {
-            if let Some(existing) = self.edges.get(id) {
-                if edge.metadata.modified_at == existing.metadata.modified_at
-                    && edge.metadata.last_modified_by != existing.metadata.last_modified_by
-                {
-                    return true;
-                }
-            }
-        }
-        false
+        Ok(report)
     }
 }
 
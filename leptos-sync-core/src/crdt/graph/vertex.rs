@@ -1,6 +1,6 @@
 //! Graph vertex operations and types
 
-use super::super::{CRDT, Mergeable, ReplicaId};
+use super::super::{HlcTimestamp, ReplicaId};
 use serde::{Deserialize, Serialize};
 use std::error::Error;
 use uuid::Uuid;
@@ -26,7 +26,7 @@ impl std::fmt::Display for GraphError {
 impl Error for GraphError {}
 
 /// Unique identifier for a graph vertex
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct VertexId {
     /// Unique identifier for the vertex
     pub id: Uuid,
@@ -53,9 +53,9 @@ impl VertexId {
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct VertexMetadata {
     /// When the vertex was created
-    pub created_at: u64,
+    pub created_at: HlcTimestamp,
     /// When the vertex was last modified
-    pub modified_at: u64,
+    pub modified_at: HlcTimestamp,
     /// Whether the vertex is marked as deleted
     pub deleted: bool,
     /// Replica that last modified the vertex
@@ -64,7 +64,8 @@ pub struct VertexMetadata {
 
 impl VertexMetadata {
     /// Create new metadata
-    pub fn new(replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
+        let timestamp = timestamp.into();
         Self {
             created_at: timestamp,
             modified_at: timestamp,
@@ -74,13 +75,16 @@ impl VertexMetadata {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
-        self.modified_at = timestamp;
+    ///
+    /// The modification always orders after the previous one, even if the
+    /// supplied timestamp is behind it.
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
+        self.modified_at = timestamp.into().max(self.modified_at.successor());
         self.last_modified_by = replica;
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.deleted = true;
         self.mark_modified(replica, timestamp);
     }
@@ -99,7 +103,7 @@ pub struct Vertex<T> {
 
 impl<T> Vertex<T> {
     /// Create a new vertex
-    pub fn new(value: T, replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(value: T, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
         Self {
             id: VertexId::new(replica),
             value,
@@ -108,12 +112,12 @@ impl<T> Vertex<T> {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_modified(replica, timestamp);
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_deleted(replica, timestamp);
     }
 }
@@ -144,8 +148,8 @@ mod tests {
         let vertex = Vertex::new("test_value", replica, timestamp);
 
         assert_eq!(vertex.value, "test_value");
-        assert_eq!(vertex.metadata.created_at, timestamp);
-        assert_eq!(vertex.metadata.modified_at, timestamp);
+        assert_eq!(vertex.metadata.created_at, HlcTimestamp::from(timestamp));
+        assert_eq!(vertex.metadata.modified_at, HlcTimestamp::from(timestamp));
         assert_eq!(vertex.metadata.deleted, false);
         assert_eq!(vertex.metadata.last_modified_by, replica);
     }
@@ -159,12 +163,12 @@ mod tests {
         // Test modification
         let new_timestamp = 1234567891;
         metadata.mark_modified(replica, new_timestamp);
-        assert_eq!(metadata.modified_at, new_timestamp);
+        assert_eq!(metadata.modified_at, HlcTimestamp::from(new_timestamp));
 
         // Test deletion
         let delete_timestamp = 1234567892;
         metadata.mark_deleted(replica, delete_timestamp);
         assert_eq!(metadata.deleted, true);
-        assert_eq!(metadata.modified_at, delete_timestamp);
+        assert_eq!(metadata.modified_at, HlcTimestamp::from(delete_timestamp));
     }
 }
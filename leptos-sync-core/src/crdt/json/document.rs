@@ -0,0 +1,216 @@
+//! Path-based JSON document
+
+use super::error::JsonError;
+use super::node::{counter_add, JsonNode, NodeInit, NodeValue};
+use super::patch::{diff, JsonPatch};
+use super::path::JsonPath;
+use crate::crdt::{Mergeable, OpId, ReplicaId, CRDT};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// JSON document CRDT
+///
+/// Objects are observed-remove maps, arrays are RGA lists, counters are
+/// PN-counters and text is a [`TextCrdt`](crate::crdt::TextCrdt). Other
+/// values are multi-value registers: [`JsonDoc::get`] returns the latest of
+/// concurrent writes, and [`JsonDoc::conflicts`] returns all of them.
+///
+/// Every value is addressed by a [`JsonPath`]. Editing a value keeps the map
+/// keys leading to it alive if another replica removes them concurrently.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonDoc {
+    replica_id: ReplicaId,
+    root: JsonNode,
+    /// Lamport clock, kept ahead of every operation seen
+    clock: u64,
+}
+
+impl JsonDoc {
+    /// Create an empty document
+    pub fn new(replica_id: ReplicaId) -> Self {
+        Self {
+            replica_id,
+            root: JsonNode::root(),
+            clock: 0,
+        }
+    }
+
+    /// The value at `path` as plain JSON
+    pub fn get(&self, path: impl Into<JsonPath>) -> Option<Value> {
+        self.root.find(path.into().segments()).map(JsonNode::to_json)
+    }
+
+    /// Concurrent values written at `path`, latest first
+    ///
+    /// Holds more than one value until a write made after seeing all of them.
+    pub fn conflicts(&self, path: impl Into<JsonPath>) -> Vec<Value> {
+        self.root
+            .find(path.into().segments())
+            .map(JsonNode::values)
+            .unwrap_or_default()
+    }
+
+    /// The whole document as plain JSON
+    pub fn to_json(&self) -> Value {
+        self.root.to_json()
+    }
+
+    /// Set the value at `path`, which must be a key of a map or an index of a list
+    pub fn set(&mut self, path: impl Into<JsonPath>, value: impl Into<Value>) -> Result<(), JsonError> {
+        self.put(path.into(), NodeInit::Json(value.into()))
+    }
+
+    /// Set a counter at `path`, starting from `initial`
+    pub fn set_counter(&mut self, path: impl Into<JsonPath>, initial: i64) -> Result<(), JsonError> {
+        self.put(path.into(), NodeInit::Counter(initial))
+    }
+
+    /// Set collaborative text at `path`
+    pub fn set_text(&mut self, path: impl Into<JsonPath>, text: &str) -> Result<(), JsonError> {
+        self.put(path.into(), NodeInit::Text(text.to_string()))
+    }
+
+    /// Insert a value into a list, before the element at the index that ends `path`
+    pub fn insert(&mut self, path: impl Into<JsonPath>, value: impl Into<Value>) -> Result<(), JsonError> {
+        let path = path.into();
+        let (list_path, segment) = path.split_last().ok_or(JsonError::EmptyPath)?;
+        let index = segment.as_index().ok_or_else(|| JsonError::NotFound(path.clone()))?;
+        self.insert_at(list_path, Some(index), value.into())
+    }
+
+    /// Append a value to the list at `path`
+    pub fn push(&mut self, path: impl Into<JsonPath>, value: impl Into<Value>) -> Result<(), JsonError> {
+        self.insert_at(path.into(), None, value.into())
+    }
+
+    /// Remove a key from a map or an element from a list
+    pub fn delete(&mut self, path: impl Into<JsonPath>) -> Result<(), JsonError> {
+        let path = path.into();
+        let (parent_path, segment) = path.split_last().ok_or(JsonError::EmptyPath)?;
+        if self.root.find(path.segments()).is_none() {
+            return Err(JsonError::NotFound(path.clone()));
+        }
+        let replica = self.replica_id;
+        self.root
+            .modify(parent_path.segments(), replica, |parent| parent.remove_child(segment))
+            .ok_or_else(|| JsonError::NotFound(path.clone()))??;
+        Ok(())
+    }
+
+    /// Add `delta` to the counter at `path`
+    pub fn increment(&mut self, path: impl Into<JsonPath>, delta: i64) -> Result<(), JsonError> {
+        let path = path.into();
+        if !matches!(self.node(&path)?.value, NodeValue::Counter(_)) {
+            return Err(JsonError::TypeMismatch { path, expected: "counter" });
+        }
+        let replica = self.replica_id;
+        self.root.modify(path.segments(), replica, |node| {
+            if let NodeValue::Counter(counter) = &mut node.value {
+                counter_add(counter, replica, delta);
+            }
+        });
+        Ok(())
+    }
+
+    /// Replace `delete` characters from `index` of the text at `path` with `text`
+    pub fn splice_text(&mut self, path: impl Into<JsonPath>, index: usize, delete: usize, text: &str) -> Result<(), JsonError> {
+        let path = path.into();
+        let len = match &self.node(&path)?.value {
+            NodeValue::Text(crdt) => crdt.len(),
+            _ => return Err(JsonError::TypeMismatch { path, expected: "text" }),
+        };
+        if index + delete > len {
+            return Err(JsonError::IndexOutOfBounds { path, index: index + delete });
+        }
+        let replica = self.replica_id;
+        self.root
+            .modify(path.segments(), replica, |node| match &mut node.value {
+                NodeValue::Text(crdt) => {
+                    crdt.set_replica_id(replica);
+                    crdt.delete(index..index + delete)?;
+                    crdt.insert(index, text)
+                }
+                _ => Ok(()),
+            })
+            .unwrap_or(Ok(()))?;
+        Ok(())
+    }
+
+    /// Merge another replica's document, returning the patches to apply to a view of this one
+    pub fn merge_with_changes(&mut self, other: &Self) -> Result<Vec<JsonPatch>, JsonError> {
+        let before = self.root.clone();
+        self.merge(other)?;
+        let mut patches = Vec::new();
+        diff(&before, &self.root, &mut JsonPath::root(), &mut patches);
+        Ok(patches)
+    }
+
+    fn next_id(&mut self) -> OpId {
+        self.clock += 1;
+        OpId::new(self.replica_id, self.clock)
+    }
+
+    fn node(&self, path: &JsonPath) -> Result<&JsonNode, JsonError> {
+        self.root.find(path.segments()).ok_or_else(|| JsonError::NotFound(path.clone()))
+    }
+
+    fn put(&mut self, path: JsonPath, init: NodeInit) -> Result<(), JsonError> {
+        let (parent_path, segment) = path.split_last().ok_or(JsonError::EmptyPath)?;
+        match &self.node(&parent_path)?.value {
+            NodeValue::Map(_) => {}
+            NodeValue::List(list) => {
+                let index = segment.as_index().ok_or_else(|| JsonError::NotFound(path.clone()))?;
+                if list.get(index).is_none() {
+                    return Err(JsonError::IndexOutOfBounds { path: parent_path, index });
+                }
+            }
+            _ => return Err(JsonError::TypeMismatch { path: parent_path, expected: "map or list" }),
+        }
+
+        let id = self.next_id();
+        let replica = self.replica_id;
+        let node = JsonNode::new(init, id, replica)?;
+        self.root.modify(parent_path.segments(), replica, |parent| parent.set_child(segment, node, replica));
+        Ok(())
+    }
+
+    /// Insert into the list at `list_path` before `index`, or at the end for `None`
+    fn insert_at(&mut self, list_path: JsonPath, index: Option<usize>, value: Value) -> Result<(), JsonError> {
+        let len = match &self.node(&list_path)?.value {
+            NodeValue::List(list) => list.iter().count(),
+            _ => return Err(JsonError::TypeMismatch { path: list_path, expected: "list" }),
+        };
+        let index = index.unwrap_or(len);
+        if index > len {
+            return Err(JsonError::IndexOutOfBounds { path: list_path, index });
+        }
+
+        let id = self.next_id();
+        let replica = self.replica_id;
+        let node = JsonNode::new(NodeInit::Json(value), id, replica)?;
+        self.root
+            .modify(list_path.segments(), replica, |list| list.insert_child(index, node, replica))
+            .unwrap_or(Ok(false))?;
+        Ok(())
+    }
+}
+
+impl CRDT for JsonDoc {
+    fn replica_id(&self) -> &ReplicaId {
+        &self.replica_id
+    }
+}
+
+impl Mergeable for JsonDoc {
+    type Error = JsonError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.root.merge(&other.root)?;
+        self.clock = self.clock.max(other.clock);
+        Ok(())
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        self.root.has_conflict(&other.root)
+    }
+}
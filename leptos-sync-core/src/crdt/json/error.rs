@@ -0,0 +1,31 @@
+//! Error types for JSON document operations
+
+use super::path::JsonPath;
+use crate::crdt::AdvancedCrdtError;
+use thiserror::Error;
+
+/// Errors from editing or merging a JSON document
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum JsonError {
+    /// The root of a document cannot be replaced or removed
+    #[error("Operation needs a non-empty path")]
+    EmptyPath,
+    /// Nothing exists at the path
+    #[error("No value at {0}")]
+    NotFound(JsonPath),
+    /// The value at the path has the wrong type for the operation
+    #[error("Expected {expected} at {path}")]
+    TypeMismatch { path: JsonPath, expected: &'static str },
+    /// A list index or text range is past the end
+    #[error("Index {index} out of bounds at {path}")]
+    IndexOutOfBounds { path: JsonPath, index: usize },
+    /// A string is not a valid JSON pointer
+    #[error("Invalid JSON pointer: {0}")]
+    InvalidPointer(String),
+    /// Merging a nested CRDT failed
+    #[error("Merge error: {0}")]
+    Merge(String),
+    /// A list or text operation failed
+    #[error(transparent)]
+    Sequence(#[from] AdvancedCrdtError),
+}
@@ -0,0 +1,276 @@
+//! JSON document CRDT
+//!
+//! A [`JsonDoc`] holds arbitrarily nested JSON, composed from the CRDTs of
+//! this crate: objects are [`OrMap`](crate::crdt::OrMap)s, arrays are
+//! [`Rga`](crate::crdt::Rga) lists and scalars are
+//! [`MvRegister`](crate::crdt::MvRegister)s, with counters and collaborative
+//! text as first-class values. Values are addressed by [`JsonPath`], and
+//! merges report what changed as [`JsonPatch`] operations.
+
+pub mod document;
+pub mod error;
+mod node;
+pub mod patch;
+pub mod path;
+
+// Re-export public types
+pub use document::JsonDoc;
+pub use error::JsonError;
+pub use patch::JsonPatch;
+pub use path::{JsonPath, PathSegment};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Mergeable, ReplicaId};
+    use crate::json_path;
+    use crate::storage::{memory::MemoryStorage, LocalStorage};
+    use serde_json::{json, Value};
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    /// Apply patches to a plain JSON view, as a UI binding would
+    fn apply(view: &mut Value, patches: &[JsonPatch]) {
+        for patch in patches {
+            let (path, value) = match patch {
+                JsonPatch::Add { path, value } | JsonPatch::Replace { path, value } => (path, Some(value.clone())),
+                JsonPatch::Remove { path } => (path, None),
+            };
+            let tokens: Vec<String> = path.segments().iter().map(PathSegment::as_key).collect();
+            let (last, parents) = tokens.split_last().unwrap();
+            let parent = parents.iter().fold(&mut *view, |node, token| match node {
+                Value::Array(items) => &mut items[token.parse::<usize>().unwrap()],
+                node => &mut node[token.as_str()],
+            });
+            match (parent, patch, value) {
+                (Value::Array(items), JsonPatch::Add { .. }, Some(value)) => items.insert(last.parse().unwrap(), value),
+                (Value::Array(items), _, Some(value)) => items[last.parse::<usize>().unwrap()] = value,
+                (Value::Array(items), _, None) => {
+                    items.remove(last.parse::<usize>().unwrap());
+                }
+                (Value::Object(map), _, Some(value)) => {
+                    map.insert(last.clone(), value);
+                }
+                (Value::Object(map), _, None) => {
+                    map.remove(last);
+                }
+                (parent, _, _) => panic!("cannot patch {parent}"),
+            }
+        }
+    }
+
+    fn form(replica: u64) -> JsonDoc {
+        let mut doc = JsonDoc::new(create_replica(replica));
+        doc.set(["title"], "Inspection").unwrap();
+        doc.set(["tasks"], json!([{ "title": "Roof", "done": false }, { "title": "Walls", "done": false }])).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_path_based_editing() {
+        let mut doc = form(1);
+        doc.set(json_path!["tasks", 1, "done"], true).unwrap();
+        doc.insert(json_path!["tasks", 1], json!({ "title": "Windows" })).unwrap();
+        doc.push(["tasks"], json!({ "title": "Floor" })).unwrap();
+        doc.delete(json_path!["tasks", 0]).unwrap();
+
+        assert_eq!(doc.get(json_path!["tasks", 0, "title"]), Some(json!("Windows")));
+        assert_eq!(
+            doc.to_json(),
+            json!({
+                "title": "Inspection",
+                "tasks": [{ "title": "Windows" }, { "title": "Walls", "done": true }, { "title": "Floor" }],
+            })
+        );
+
+        // Paths parsed from JSON pointers resolve the same way
+        let path: JsonPath = "/tasks/2/title".parse().unwrap();
+        assert_eq!(doc.get(path.clone()), Some(json!("Floor")));
+        assert_eq!(path, json_path!["tasks", "2", "title"]);
+        assert_eq!(json_path!["a/b", "c~d", 0].to_string(), "/a~1b/c~0d/0");
+        assert_eq!("/a~1b/c~0d".parse::<JsonPath>().unwrap(), json_path!["a/b", "c~d"]);
+
+        assert_eq!(doc.set(json_path!["tasks", 3], 1), Err(JsonError::IndexOutOfBounds { path: json_path!["tasks"], index: 3 }));
+        assert_eq!(doc.set(json_path!["missing", "key"], 1), Err(JsonError::NotFound(json_path!["missing"])));
+        assert_eq!(doc.push(["title"], 1), Err(JsonError::TypeMismatch { path: json_path!["title"], expected: "list" }));
+        assert_eq!(doc.delete(JsonPath::root()), Err(JsonError::EmptyPath));
+    }
+
+    #[test]
+    fn test_concurrent_edits_converge() {
+        let mut doc1 = form(1);
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+
+        doc1.set(json_path!["tasks", 0, "done"], true).unwrap();
+        doc1.insert(json_path!["tasks", 0], json!({ "title": "Gutters" })).unwrap();
+        doc2.set(json_path!["tasks", 1, "title"], "Outer walls").unwrap();
+        doc2.push(["tasks"], json!({ "title": "Floor" })).unwrap();
+        doc2.set(["inspector"], "Sam").unwrap();
+
+        let mut merged1 = doc1.clone();
+        merged1.merge(&doc2).unwrap();
+        let mut merged2 = doc2.clone();
+        merged2.merge(&doc1).unwrap();
+
+        assert_eq!(merged1.to_json(), merged2.to_json());
+        assert_eq!(
+            merged1.to_json(),
+            json!({
+                "title": "Inspection",
+                "inspector": "Sam",
+                "tasks": [
+                    { "title": "Gutters" },
+                    { "title": "Roof", "done": true },
+                    { "title": "Outer walls", "done": false },
+                    { "title": "Floor" },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_concurrent_writes_are_kept_until_resolved() {
+        let mut doc1 = form(1);
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+
+        doc1.set(["title"], "Survey").unwrap();
+        doc2.set(["title"], "Audit").unwrap();
+        doc1.merge(&doc2).unwrap();
+        doc2.merge(&doc1).unwrap();
+
+        // Equal clocks, so the higher replica ID wins the plain view
+        assert_eq!(doc1.get(["title"]), Some(json!("Audit")));
+        assert_eq!(doc1.conflicts(["title"]), vec![json!("Audit"), json!("Survey")]);
+        assert_eq!(doc2.conflicts(["title"]), doc1.conflicts(["title"]));
+
+        doc1.set(["title"], "Survey").unwrap();
+        doc2.merge(&doc1).unwrap();
+        assert_eq!(doc2.conflicts(["title"]), vec![json!("Survey")]);
+    }
+
+    #[test]
+    fn test_counters_and_text_merge_edits() {
+        let mut doc1 = JsonDoc::new(create_replica(1));
+        doc1.set_counter(["visits"], 10).unwrap();
+        doc1.set_text(["notes"], "Roof ok").unwrap();
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+
+        doc1.increment(["visits"], 2).unwrap();
+        doc1.splice_text(["notes"], 4, 0, " mostly").unwrap();
+        doc2.increment(["visits"], -5).unwrap();
+        doc2.splice_text(["notes"], 5, 2, "fine").unwrap();
+        doc1.merge(&doc2).unwrap();
+        doc2.merge(&doc1).unwrap();
+
+        assert_eq!(doc1.get(["visits"]), Some(json!(7)));
+        assert_eq!(doc1.get(["notes"]), Some(json!("Roof mostly fine")));
+        assert_eq!(doc1.to_json(), doc2.to_json());
+
+        assert!(doc1.splice_text(["notes"], 10, 10, "").is_err());
+        assert_eq!(doc1.increment(["notes"], 1), Err(JsonError::TypeMismatch { path: json_path!["notes"], expected: "counter" }));
+    }
+
+    #[test]
+    fn test_edits_survive_concurrent_key_removal() {
+        let mut doc1 = form(1);
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+
+        // Editing inside "tasks" keeps it, but the edited element was deleted
+        doc1.delete(["tasks"]).unwrap();
+        doc1.delete(["title"]).unwrap();
+        doc2.set(json_path!["tasks", 0, "done"], true).unwrap();
+        doc2.delete(json_path!["tasks", 1]).unwrap();
+        let mut doc3 = doc2.clone();
+        doc3.set(json_path!["tasks", 0, "title"], "Roof and chimney").unwrap();
+        doc2.delete(json_path!["tasks", 0]).unwrap();
+        doc3.merge(&doc2).unwrap();
+
+        for (first, second) in [(&doc1, &doc3), (&doc3, &doc1)] {
+            let mut merged = first.clone();
+            merged.merge(second).unwrap();
+            assert_eq!(merged.to_json(), json!({ "tasks": [] }));
+        }
+    }
+
+    #[test]
+    fn test_later_value_replaces_concurrent_one() {
+        let mut doc1 = form(1);
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+
+        doc1.set(["owner"], json!({ "name": "Ana" })).unwrap();
+        doc2.set(["owner"], "unassigned").unwrap();
+        doc2.set(["owner"], json!(["Bo", "Cy"])).unwrap();
+        doc1.merge(&doc2).unwrap();
+        doc2.merge(&doc1).unwrap();
+
+        assert_eq!(doc1.get(["owner"]), Some(json!(["Bo", "Cy"])));
+        assert_eq!(doc1.to_json(), doc2.to_json());
+    }
+
+    #[test]
+    fn test_merge_patches_update_a_view() {
+        let mut doc1 = form(1);
+        let mut doc2 = JsonDoc::new(create_replica(2));
+        doc2.merge(&doc1).unwrap();
+        let mut view = doc1.to_json();
+
+        doc2.delete(json_path!["tasks", 0]).unwrap();
+        doc2.push(["tasks"], json!({ "title": "Floor" })).unwrap();
+        doc2.insert(json_path!["tasks", 0], "Gutters").unwrap();
+        doc2.set(json_path!["tasks", 1, "done"], true).unwrap();
+        doc2.set_counter(["visits"], 1).unwrap();
+        doc2.delete(["title"]).unwrap();
+
+        let patches = doc1.merge_with_changes(&doc2).unwrap();
+        apply(&mut view, &patches);
+        assert_eq!(view, doc1.to_json());
+        assert_eq!(patches[0], JsonPatch::Remove { path: json_path!["tasks", 0] });
+        assert!(doc1.merge_with_changes(&doc2).unwrap().is_empty());
+
+        let patch = serde_json::to_value(&patches[0]).unwrap();
+        assert_eq!(patch, json!({ "op": "remove", "path": "/tasks/0" }));
+    }
+
+    #[test]
+    fn test_document_round_trips_through_bincode() {
+        let mut doc = form(1);
+        doc.set_counter(["visits"], 3).unwrap();
+        doc.set_text(["notes"], "ok").unwrap();
+
+        let bytes = bincode::serialize(&doc).unwrap();
+        let mut restored: JsonDoc = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(restored.to_json(), doc.to_json());
+
+        restored.push(["tasks"], "Floor").unwrap();
+        doc.merge(&restored).unwrap();
+        assert_eq!(doc.get(json_path!["tasks", 2]), Some(json!("Floor")));
+    }
+
+    #[tokio::test]
+    async fn test_document_with_arrays_round_trips_through_storage() {
+        let mut doc = form(1);
+        doc.push(["tasks"], json!({ "title": "Floor", "tags": ["wood", ["oak", "ash"]] })).unwrap();
+        doc.delete(json_path!["tasks", 0]).unwrap();
+
+        let json = serde_json::to_string(&doc).unwrap();
+        let restored: JsonDoc = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.to_json(), doc.to_json());
+
+        let storage = MemoryStorage::new();
+        storage.set("doc", &doc).await.unwrap();
+        let mut stored: JsonDoc = storage.get("doc").await.unwrap().unwrap();
+        assert_eq!(stored.get(json_path!["tasks", 1, "tags", 1, 0]), Some(json!("oak")));
+
+        stored.push(json_path!["tasks", 1, "tags"], "pine").unwrap();
+        doc.merge(&stored).unwrap();
+        assert_eq!(doc.get(json_path!["tasks", 1, "tags", 2]), Some(json!("pine")));
+    }
+}
@@ -0,0 +1,409 @@
+//! Nodes of a JSON document, each backed by an existing CRDT
+
+use super::error::JsonError;
+use super::path::PathSegment;
+use crate::crdt::advanced::common::map_as_seq;
+use crate::crdt::{Mergeable, MvRegister, OpId, OrMap, PNCounter, PositionId, ReplicaId, Reset, Rga, TextCrdt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/// Scalar held by a register
+///
+/// Kept apart from `serde_json::Value` so that documents also serialize with
+/// formats that are not self-describing, such as bincode.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum Scalar {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    String(String),
+}
+
+impl Scalar {
+    /// The scalar for a JSON value, `None` for objects and arrays
+    fn from_json(value: &Value) -> Option<Self> {
+        Some(match value {
+            Value::Null => Scalar::Null,
+            Value::Bool(value) => Scalar::Bool(*value),
+            Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+                (Some(value), _) => Scalar::Int(value),
+                (None, Some(value)) => Scalar::Uint(value),
+                (None, None) => Scalar::Float(number.as_f64().unwrap_or_default()),
+            },
+            Value::String(value) => Scalar::String(value.clone()),
+            Value::Array(_) | Value::Object(_) => return None,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Scalar::Null => Value::Null,
+            Scalar::Bool(value) => Value::from(*value),
+            Scalar::Int(value) => Value::from(*value),
+            Scalar::Uint(value) => Value::from(*value),
+            Scalar::Float(value) => Value::from(*value),
+            Scalar::String(value) => Value::from(value.as_str()),
+        }
+    }
+}
+
+/// Write to a register, tagged with the operation that made it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(super) struct Write {
+    value: Scalar,
+    id: OpId,
+}
+
+/// Contents a new node is created with
+pub(super) enum NodeInit {
+    /// Maps for objects, lists for arrays and registers for everything else
+    Json(Value),
+    Counter(i64),
+    Text(String),
+}
+
+/// List of nodes, ordered by an RGA
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct JsonList {
+    pub(super) order: Rga<()>,
+    /// Contents of the visible elements
+    #[serde(with = "map_as_seq")]
+    pub(super) items: HashMap<PositionId, JsonNode>,
+}
+
+impl JsonList {
+    pub(super) fn get(&self, index: usize) -> Option<&JsonNode> {
+        self.items.get(&self.order.position_at(index)?)
+    }
+
+    /// Nodes of the visible elements in order, with their positions
+    pub(super) fn iter(&self) -> impl Iterator<Item = (&PositionId, &JsonNode)> + '_ {
+        self.order
+            .positions()
+            .filter_map(|position| self.items.get(position).map(|item| (position, item)))
+    }
+
+    fn merge(&mut self, other: &Self) -> Result<(), JsonError> {
+        self.order.merge(&other.order)?;
+        for (position, item) in &other.items {
+            // Updates to an element deleted concurrently are dropped with it
+            if self.order.index_of(position).is_none() {
+                continue;
+            }
+            match self.items.get_mut(position) {
+                Some(ours) => ours.merge(item)?,
+                None => {
+                    self.items.insert(position.clone(), item.clone());
+                }
+            }
+        }
+        let order = &self.order;
+        self.items.retain(|position, _| order.index_of(position).is_some());
+        Ok(())
+    }
+
+    /// Forget what `observed` holds, like [`Reset::reset`]
+    ///
+    /// The order stays whole, but each element's node is reset on its own.
+    fn reset(&mut self, observed: &Self) -> bool {
+        let mut left = self.order.reset(&observed.order);
+        for (position, item) in &mut self.items {
+            // An element missing there is unknown, which the order reports, or deleted
+            if let Some(theirs) = observed.items.get(position) {
+                left |= item.reset(theirs);
+            }
+        }
+        left
+    }
+}
+
+/// CRDT behind a node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) enum NodeValue {
+    Map(OrMap<String, JsonNode>),
+    List(JsonList),
+    /// Scalar, keeping concurrent writes until one is written over them
+    Register(MvRegister<Write>),
+    Counter(PNCounter),
+    Text(TextCrdt),
+}
+
+/// Value in a JSON document
+///
+/// A node keeps the ID of the operation that created it. Nodes with the same
+/// ID merge recursively; when two operations put different nodes in the same
+/// place, the later one replaces the other.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct JsonNode {
+    pub(super) id: OpId,
+    pub(super) value: NodeValue,
+}
+
+/// Whether `id` was created after `other`, breaking ties by replica
+fn is_newer(id: &OpId, other: &OpId) -> bool {
+    (id.seq, id.replica) > (other.seq, other.replica)
+}
+
+impl JsonNode {
+    /// Empty map, the root of every document
+    pub(super) fn root() -> Self {
+        Self {
+            id: OpId::new(ReplicaId::from(uuid::Uuid::nil()), 0),
+            value: NodeValue::Map(OrMap::new()),
+        }
+    }
+
+    /// Create a node, and any nodes nested in it, as operation `id`
+    pub(super) fn new(init: NodeInit, id: OpId, replica: ReplicaId) -> Result<Self, JsonError> {
+        let value = match init {
+            NodeInit::Json(Value::Object(object)) => {
+                let mut map = OrMap::new();
+                for (key, value) in object {
+                    let child = Self::new(NodeInit::Json(value), id, replica)?;
+                    map.update_with(key, replica, || child, |_| {});
+                }
+                NodeValue::Map(map)
+            }
+            NodeInit::Json(Value::Array(array)) => {
+                let mut list = JsonList {
+                    order: Rga::new(replica),
+                    items: HashMap::new(),
+                };
+                let mut after = None;
+                for value in array {
+                    let position = list.order.insert_after((), after)?;
+                    list.items.insert(position.clone(), Self::new(NodeInit::Json(value), id, replica)?);
+                    after = Some(position);
+                }
+                NodeValue::List(list)
+            }
+            NodeInit::Json(value) => {
+                let value = Scalar::from_json(&value).unwrap_or(Scalar::Null);
+                NodeValue::Register(MvRegister::with_value(Write { value, id }, replica))
+            }
+            NodeInit::Counter(initial) => {
+                let mut counter = PNCounter::new();
+                counter_add(&mut counter, replica, initial);
+                NodeValue::Counter(counter)
+            }
+            NodeInit::Text(text) => {
+                let mut crdt = TextCrdt::new(replica);
+                crdt.insert(0, &text)?;
+                NodeValue::Text(crdt)
+            }
+        };
+        Ok(Self { id, value })
+    }
+
+    /// Name of the node's type, as used in errors
+    pub(super) fn kind(&self) -> &'static str {
+        match self.value {
+            NodeValue::Map(_) => "map",
+            NodeValue::List(_) => "list",
+            NodeValue::Register(_) => "value",
+            NodeValue::Counter(_) => "counter",
+            NodeValue::Text(_) => "text",
+        }
+    }
+
+    /// The node as plain JSON, resolving concurrent writes to the latest one
+    pub(super) fn to_json(&self) -> Value {
+        match &self.value {
+            NodeValue::Map(map) => Value::Object(map.iter().map(|(key, child)| (key.clone(), child.to_json())).collect()),
+            NodeValue::List(list) => Value::Array(list.iter().map(|(_, item)| item.to_json()).collect()),
+            NodeValue::Register(_) => self.values().into_iter().next().unwrap_or(Value::Null),
+            NodeValue::Counter(counter) => Value::from(counter.value()),
+            NodeValue::Text(text) => Value::from(text.to_string()),
+        }
+    }
+
+    /// Concurrent values of a register, latest first; the node itself otherwise
+    pub(super) fn values(&self) -> Vec<Value> {
+        let NodeValue::Register(register) = &self.value else {
+            return vec![self.to_json()];
+        };
+        let mut writes = register.values();
+        writes.sort_by_key(|write| Reverse((write.id.seq, write.id.replica)));
+        writes.into_iter().map(|write| write.value.to_json()).collect()
+    }
+
+    /// Child at `segment`, if this is a map or list that has one
+    pub(super) fn child(&self, segment: &PathSegment) -> Option<&JsonNode> {
+        match &self.value {
+            NodeValue::Map(map) => map.get(&segment.as_key()),
+            NodeValue::List(list) => list.get(segment.as_index()?),
+            _ => None,
+        }
+    }
+
+    /// Descendant at `path`
+    pub(super) fn find(&self, path: &[PathSegment]) -> Option<&JsonNode> {
+        path.iter().try_fold(self, |node, segment| node.child(segment))
+    }
+
+    /// Run `f` on the descendant at `path`, recording the edit in every map on the way
+    ///
+    /// Recording the edit keeps those keys alive when another replica removes
+    /// them concurrently, like any other update to a map value.
+    pub(super) fn modify<R>(&mut self, path: &[PathSegment], replica: ReplicaId, f: impl FnOnce(&mut JsonNode) -> R) -> Option<R> {
+        let Some((segment, rest)) = path.split_first() else {
+            return Some(f(self));
+        };
+        match &mut self.value {
+            NodeValue::Map(map) => map
+                .update_existing(&segment.as_key(), replica, |child| child.modify(rest, replica, f))
+                .flatten(),
+            NodeValue::List(list) => {
+                let position = list.order.position_at(segment.as_index()?)?;
+                list.items.get_mut(&position)?.modify(rest, replica, f)
+            }
+            _ => None,
+        }
+    }
+
+    /// Put `node` at `segment` of this map or list
+    ///
+    /// Scalars written over a scalar update its register in place, so that
+    /// concurrent writes are kept side by side instead of one replacing the other.
+    pub(super) fn set_child(&mut self, segment: &PathSegment, node: JsonNode, replica: ReplicaId) -> bool {
+        match &mut self.value {
+            NodeValue::Map(map) => {
+                let key = segment.as_key();
+                if map.contains_key(&key) {
+                    map.update_existing(&key, replica, |child| child.overwrite(node, replica));
+                } else {
+                    map.update_with(key, replica, || node, |_| {});
+                }
+                true
+            }
+            NodeValue::List(list) => {
+                let Some(position) = segment.as_index().and_then(|index| list.order.position_at(index)) else {
+                    return false;
+                };
+                match list.items.get_mut(&position) {
+                    Some(item) => item.overwrite(node, replica),
+                    None => {
+                        list.items.insert(position, node);
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Insert `node` into this list before the element at `index`, returning whether this is a list
+    pub(super) fn insert_child(&mut self, index: usize, node: JsonNode, replica: ReplicaId) -> Result<bool, JsonError> {
+        let NodeValue::List(list) = &mut self.value else {
+            return Ok(false);
+        };
+        list.order.set_replica_id(replica);
+        let after = index.checked_sub(1).and_then(|index| list.order.position_at(index));
+        let position = list.order.insert_after((), after)?;
+        list.items.insert(position, node);
+        Ok(true)
+    }
+
+    /// Remove the child at `segment`, returning whether there was one
+    pub(super) fn remove_child(&mut self, segment: &PathSegment) -> Result<bool, JsonError> {
+        match &mut self.value {
+            NodeValue::Map(map) => Ok(map.remove(&segment.as_key()).is_some()),
+            NodeValue::List(list) => {
+                let Some(position) = segment.as_index().and_then(|index| list.order.position_at(index)) else {
+                    return Ok(false);
+                };
+                list.order.delete(&position)?;
+                list.items.remove(&position);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn overwrite(&mut self, node: JsonNode, replica: ReplicaId) {
+        if let (NodeValue::Register(register), NodeValue::Register(written)) = (&mut self.value, &node.value) {
+            if let Some(write) = written.value() {
+                register.update(write.clone(), replica);
+                return;
+            }
+        }
+        *self = node;
+    }
+}
+
+/// Add a signed amount to a counter
+pub(super) fn counter_add(counter: &mut PNCounter, replica: ReplicaId, amount: i64) {
+    if amount >= 0 {
+        counter.increment_by(replica, amount as u64);
+    } else {
+        counter.decrement_by(replica, amount.unsigned_abs());
+    }
+}
+
+impl Mergeable for JsonNode {
+    type Error = JsonError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        if self.id != other.id {
+            if is_newer(&other.id, &self.id) {
+                *self = other.clone();
+            }
+            return Ok(());
+        }
+        match (&mut self.value, &other.value) {
+            (NodeValue::Map(ours), NodeValue::Map(theirs)) => ours.merge(theirs),
+            (NodeValue::List(ours), NodeValue::List(theirs)) => ours.merge(theirs),
+            (NodeValue::Register(ours), NodeValue::Register(theirs)) => {
+                ours.merge(theirs).map_err(|error| JsonError::Merge(error.to_string()))
+            }
+            (NodeValue::Counter(ours), NodeValue::Counter(theirs)) => {
+                ours.merge(theirs).map_err(|error| JsonError::Merge(error.to_string()))
+            }
+            (NodeValue::Text(ours), NodeValue::Text(theirs)) => Ok(ours.merge(theirs)?),
+            (_, _) => Err(JsonError::Merge(format!(
+                "Node {:?} is a {} here and a {} there",
+                self.id,
+                self.kind(),
+                other.kind()
+            ))),
+        }
+    }
+
+    fn has_conflict(&self, other: &Self) -> bool {
+        if self.id != other.id {
+            return true;
+        }
+        match (&self.value, &other.value) {
+            (NodeValue::Map(ours), NodeValue::Map(theirs)) => ours.has_conflict(theirs),
+            (NodeValue::List(ours), NodeValue::List(theirs)) => ours
+                .items
+                .iter()
+                .any(|(position, item)| theirs.items.get(position).is_some_and(|their_item| item.has_conflict(their_item))),
+            (NodeValue::Register(ours), NodeValue::Register(theirs)) => ours.has_conflict(theirs),
+            (NodeValue::Counter(_), NodeValue::Counter(_)) | (NodeValue::Text(_), NodeValue::Text(_)) => false,
+            (_, _) => true,
+        }
+    }
+}
+
+impl Reset for JsonNode {
+    fn reset(&mut self, observed: &Self) -> bool {
+        if self.id != observed.id {
+            // Different nodes replace each other whole
+            return is_newer(&self.id, &observed.id);
+        }
+        match (&mut self.value, &observed.value) {
+            (NodeValue::Map(ours), NodeValue::Map(theirs)) => ours.reset(theirs),
+            (NodeValue::List(ours), NodeValue::List(theirs)) => ours.reset(theirs),
+            (NodeValue::Register(ours), NodeValue::Register(theirs)) => ours.reset(theirs),
+            (NodeValue::Counter(ours), NodeValue::Counter(theirs)) => ours.reset(theirs),
+            (NodeValue::Text(ours), NodeValue::Text(theirs)) => ours.reset(theirs),
+            // Merging the two fails, so nothing is covered
+            (_, _) => true,
+        }
+    }
+}
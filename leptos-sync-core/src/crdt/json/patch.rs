@@ -0,0 +1,79 @@
+//! JSON Patch (RFC 6902) output for document changes
+
+use super::node::{JsonNode, NodeValue};
+use super::path::JsonPath;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Change to the plain JSON view of a document
+///
+/// Serializes as a JSON Patch operation. Patches are listed in the order they
+/// apply, so each path refers to the document left by the previous patch.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatch {
+    /// `value` was added at `path`, inserting into a list or adding a map key
+    Add { path: JsonPath, value: Value },
+    /// The value at `path` was removed
+    Remove { path: JsonPath },
+    /// The value at `path` was replaced by `value`
+    Replace { path: JsonPath, value: Value },
+}
+
+/// Patches turning the view of `old` into the view of `new`
+pub(super) fn diff(old: &JsonNode, new: &JsonNode, path: &mut JsonPath, patches: &mut Vec<JsonPatch>) {
+    if old.id == new.id {
+        match (&old.value, &new.value) {
+            (NodeValue::Map(old_map), NodeValue::Map(new_map)) => {
+                let mut keys: Vec<&String> = old_map.keys().chain(new_map.keys()).collect::<HashSet<_>>().into_iter().collect();
+                keys.sort();
+                for key in keys {
+                    path.push(key.as_str());
+                    match (old_map.get(key), new_map.get(key)) {
+                        (Some(old_child), Some(new_child)) => diff(old_child, new_child, path, patches),
+                        (Some(_), None) => patches.push(JsonPatch::Remove { path: path.clone() }),
+                        (None, Some(new_child)) => patches.push(JsonPatch::Add {
+                            path: path.clone(),
+                            value: new_child.to_json(),
+                        }),
+                        (None, None) => {}
+                    }
+                    path.pop();
+                }
+                return;
+            }
+            (NodeValue::List(old_list), NodeValue::List(new_list)) => {
+                // Elements never move, so once the removed ones are gone the
+                // remaining ones are already in their new order
+                let new_items: HashSet<_> = new_list.iter().map(|(position, _)| position).collect();
+                let old_items: Vec<_> = old_list.iter().collect();
+                for (index, (position, _)) in old_items.iter().enumerate().rev() {
+                    if !new_items.contains(position) {
+                        path.push(index);
+                        patches.push(JsonPatch::Remove { path: path.clone() });
+                        path.pop();
+                    }
+                }
+                for (index, (position, new_item)) in new_list.iter().enumerate() {
+                    path.push(index);
+                    match old_list.items.get(position) {
+                        Some(old_item) => diff(old_item, new_item, path, patches),
+                        None => patches.push(JsonPatch::Add {
+                            path: path.clone(),
+                            value: new_item.to_json(),
+                        }),
+                    }
+                    path.pop();
+                }
+                return;
+            }
+            _ => {}
+        }
+    }
+
+    let value = new.to_json();
+    if old.to_json() != value {
+        patches.push(JsonPatch::Replace { path: path.clone(), value });
+    }
+}
@@ -0,0 +1,177 @@
+//! Paths into a JSON document
+
+use super::error::JsonError;
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// Step of a path: a key into a map or an index into a list
+///
+/// Lists also accept keys holding a number, and maps accept indices as
+/// keys, so paths parsed from a JSON pointer resolve either way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+impl PathSegment {
+    /// The segment as a map key
+    pub fn as_key(&self) -> String {
+        match self {
+            PathSegment::Key(key) => key.clone(),
+            PathSegment::Index(index) => index.to_string(),
+        }
+    }
+
+    /// The segment as a list index, if it is one
+    pub fn as_index(&self) -> Option<usize> {
+        match self {
+            PathSegment::Key(key) => key.parse().ok(),
+            PathSegment::Index(index) => Some(*index),
+        }
+    }
+}
+
+impl From<&str> for PathSegment {
+    fn from(key: &str) -> Self {
+        PathSegment::Key(key.to_string())
+    }
+}
+
+impl From<String> for PathSegment {
+    fn from(key: String) -> Self {
+        PathSegment::Key(key)
+    }
+}
+
+impl From<usize> for PathSegment {
+    fn from(index: usize) -> Self {
+        PathSegment::Index(index)
+    }
+}
+
+/// Location of a value in a JSON document
+///
+/// Displayed and serialized as a JSON pointer (RFC 6901), which is also what
+/// [`FromStr`] parses. Paths mixing keys and indices are most easily built with
+/// [`json_path!`](crate::json_path).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct JsonPath(Vec<PathSegment>);
+
+impl JsonPath {
+    /// Path of the document root
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Path of a value directly inside this one
+    pub fn child(&self, segment: impl Into<PathSegment>) -> Self {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+
+    /// Append a segment
+    pub fn push(&mut self, segment: impl Into<PathSegment>) {
+        self.0.push(segment.into());
+    }
+
+    /// Remove the last segment
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.0.pop()
+    }
+
+    /// Path of the containing value and the last segment, `None` for the root
+    pub fn split_last(&self) -> Option<(JsonPath, &PathSegment)> {
+        let (last, parent) = self.0.split_last()?;
+        Some((JsonPath(parent.to_vec()), last))
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<S: Into<PathSegment>> From<Vec<S>> for JsonPath {
+    fn from(segments: Vec<S>) -> Self {
+        segments.into_iter().collect()
+    }
+}
+
+impl<S: Into<PathSegment>, const N: usize> From<[S; N]> for JsonPath {
+    fn from(segments: [S; N]) -> Self {
+        segments.into_iter().collect()
+    }
+}
+
+impl<S: Into<PathSegment>> FromIterator<S> for JsonPath {
+    fn from_iter<I: IntoIterator<Item = S>>(iter: I) -> Self {
+        Self(iter.into_iter().map(Into::into).collect())
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for segment in &self.0 {
+            match segment {
+                PathSegment::Key(key) => write!(f, "/{}", key.replace('~', "~0").replace('/', "~1"))?,
+                PathSegment::Index(index) => write!(f, "/{}", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for JsonPath {
+    type Err = JsonError;
+
+    fn from_str(pointer: &str) -> Result<Self, Self::Err> {
+        if pointer.is_empty() {
+            return Ok(Self::root());
+        }
+        let tokens = pointer
+            .strip_prefix('/')
+            .ok_or_else(|| JsonError::InvalidPointer(pointer.to_string()))?;
+        Ok(tokens
+            .split('/')
+            .map(|token| token.replace("~1", "/").replace("~0", "~"))
+            .collect())
+    }
+}
+
+impl Serialize for JsonPath {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Build a [`JsonPath`](crate::crdt::json::JsonPath) from keys and indices
+///
+/// ```
+/// use leptos_sync_core::json_path;
+///
+/// let path = json_path!["tasks", 3, "title"];
+/// assert_eq!(path.to_string(), "/tasks/3/title");
+/// ```
+#[macro_export]
+macro_rules! json_path {
+    ($($segment:expr),* $(,)?) => {
+        $crate::crdt::json::JsonPath::from(::std::vec![$($crate::crdt::json::PathSegment::from($segment)),*] as ::std::vec::Vec<$crate::crdt::json::PathSegment>)
+    };
+}
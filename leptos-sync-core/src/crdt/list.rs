@@ -1,5 +1,9 @@
-use super::{CRDT, Mergeable, ReplicaId};
-use serde::{Deserialize, Serialize};
+use super::{CRDT, DeltaCrdt, HlcTimestamp, MergeReport, Mergeable, ReplicaId, ReportingMerge, VersionVector};
+use super::cursor::{Bias, RelativePosition};
+use super::stability::{GarbageCollect, StabilityFrontier};
+use super::undo::{IdMap, Reverted, Undoable};
+use super::advanced::common::map_as_seq;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use std::error::Error;
 use uuid::Uuid;
@@ -48,13 +52,150 @@ impl ElementId {
     }
 }
 
+/// Digits of a fractional index, in ASCII order
+const INDEX_DIGITS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// Dense position key for list elements
+///
+/// Keys are strings of base-62 digits read as a fraction in [0, 1) and compare
+/// lexicographically. There is a key between any two distinct keys, so elements
+/// never need to be renumbered.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize)]
+#[serde(try_from = "String")]
+pub struct FractionalIndex(String);
+
+impl FractionalIndex {
+    /// Key strictly between `before` and `after`; `None` stands for the start or end
+    pub fn between(before: Option<&Self>, after: Option<&Self>) -> Result<Self, ListError> {
+        let low = before.map(Self::digits).unwrap_or_default();
+        let high = after.map(Self::digits);
+        let no_room = || ListError::new(format!("No index between {:?} and {:?}", before, after));
+        if high.as_ref().is_some_and(|high| low >= *high) {
+            return Err(no_room());
+        }
+        midpoint(&low, high.as_deref()).map(Self::from_digits).ok_or_else(no_room)
+    }
+
+    /// Key after `before`, or the first key for `None`
+    pub fn after(before: Option<&Self>) -> Self {
+        let low = before.map(Self::digits).unwrap_or_default();
+        Self::from_digits(midpoint(&low, None).expect("there is always room at the end"))
+    }
+
+    /// The key as a string
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn from_digits(digits: Vec<u8>) -> Self {
+        Self(digits.into_iter().map(|digit| INDEX_DIGITS[digit as usize] as char).collect())
+    }
+
+    /// Digits of the key, which only holds valid ones since that is checked on creation
+    fn digits(&self) -> Vec<u8> {
+        self.0.bytes().filter_map(digit_value).collect()
+    }
+}
+
+impl TryFrom<String> for FractionalIndex {
+    type Error = ListError;
+
+    /// Accept only keys this type could have made: base-62 digits, never ending in zero
+    ///
+    /// The empty key of elements saved before keys existed is accepted too.
+    fn try_from(key: String) -> Result<Self, Self::Error> {
+        if key.bytes().any(|byte| digit_value(byte).is_none()) || key.ends_with('0') {
+            return Err(ListError::new(format!("Invalid fractional index {:?}", key)));
+        }
+        Ok(Self(key))
+    }
+}
+
+fn digit_value(byte: u8) -> Option<u8> {
+    INDEX_DIGITS.iter().position(|digit| *digit == byte).map(|value| value as u8)
+}
+
+/// Digits strictly between `low` and `high` (the end of the range for `None`)
+///
+/// Keys never end in a zero digit, so there is always room below a key. The
+/// only gap without a key is one where `high` is `low` followed by zeros,
+/// which is the same fraction; that gives `None`.
+fn midpoint(low: &[u8], high: Option<&[u8]>) -> Option<Vec<u8>> {
+    if let Some(high) = high {
+        // Keep the common prefix, reading missing digits of `low` as zeros
+        let common = high
+            .iter()
+            .enumerate()
+            .take_while(|(index, digit)| low.get(*index).copied().unwrap_or(0) == **digit)
+            .count();
+        if common > 0 {
+            let mut key = high[..common].to_vec();
+            key.extend(midpoint(low.get(common..).unwrap_or(&[]), Some(&high[common..]))?);
+            return Some(key);
+        }
+    }
+
+    let low_digit = low.first().copied().unwrap_or(0);
+    let high_digit = match high {
+        None => INDEX_DIGITS.len() as u8,
+        Some(high) => *high.first()?,
+    };
+    if high_digit.saturating_sub(low_digit) > 1 {
+        // Appends step by one digit so that a growing list keeps short keys
+        let digit = if high.is_none() { low_digit + 1 } else { (low_digit + high_digit) / 2 };
+        Some(vec![digit])
+    } else if high.is_some_and(|high| high.len() > 1) {
+        Some(vec![high_digit])
+    } else {
+        let mut key = vec![low_digit];
+        key.extend(midpoint(low.get(1..).unwrap_or(&[]), None)?);
+        Some(key)
+    }
+}
+
+/// Position of an element in its list
+///
+/// Moves are last-write-wins, so concurrent moves of one element settle on a
+/// single place instead of duplicating it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListPosition {
+    /// Sort key of the element
+    pub index: FractionalIndex,
+    /// When the element was last placed
+    pub moved_at: HlcTimestamp,
+    /// Replica that last placed the element
+    pub moved_by: ReplicaId,
+}
+
+impl ListPosition {
+    /// Create a position placed at `timestamp` by `replica`
+    pub fn new(index: FractionalIndex, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
+        Self {
+            index,
+            moved_at: timestamp.into(),
+            moved_by: replica,
+        }
+    }
+
+    /// Whether this placement wins over `other` under last-write-wins
+    pub fn wins_over(&self, other: &Self) -> bool {
+        (self.moved_at, self.moved_by, &self.index) > (other.moved_at, other.moved_by, &other.index)
+    }
+}
+
+impl Default for ListPosition {
+    fn default() -> Self {
+        Self::new(FractionalIndex::default(), ReplicaId::from(Uuid::nil()), HlcTimestamp::default())
+    }
+}
+
 /// Metadata for a list element
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ElementMetadata {
     /// When the element was created
-    pub created_at: u64,
+    pub created_at: HlcTimestamp,
     /// When the element was last modified
-    pub modified_at: u64,
+    pub modified_at: HlcTimestamp,
     /// Whether the element is marked as deleted
     pub deleted: bool,
     /// Replica that last modified the element
@@ -63,7 +204,8 @@ pub struct ElementMetadata {
 
 impl ElementMetadata {
     /// Create new metadata
-    pub fn new(replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
+        let timestamp = timestamp.into();
         Self {
             created_at: timestamp,
             modified_at: timestamp,
@@ -73,13 +215,16 @@ impl ElementMetadata {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
-        self.modified_at = timestamp;
+    ///
+    /// The modification always orders after the previous one, even if the
+    /// supplied timestamp is behind it.
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
+        self.modified_at = timestamp.into().max(self.modified_at.successor());
         self.last_modified_by = replica;
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.deleted = true;
         self.mark_modified(replica, timestamp);
     }
@@ -92,29 +237,312 @@ pub struct ListElement<T> {
     pub id: ElementId,
     /// The actual value
     pub value: T,
+    /// Place in the list
+    #[serde(default)]
+    pub position: ListPosition,
     /// Metadata
     pub metadata: ElementMetadata,
 }
 
 impl<T> ListElement<T> {
     /// Create a new list element
-    pub fn new(value: T, replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(value: T, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
         Self {
             id: ElementId::new(replica),
             value,
+            position: ListPosition::default(),
             metadata: ElementMetadata::new(replica, timestamp),
         }
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_modified(replica, timestamp);
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_deleted(replica, timestamp);
     }
+
+    /// Whether the element is marked as deleted
+    pub fn is_deleted(&self) -> bool {
+        self.metadata.deleted
+    }
+}
+
+impl<T: Clone + PartialEq> ListElement<T> {
+    /// Merge another replica's copy of this element
+    ///
+    /// The value and deletion follow the latest modification, while the
+    /// position follows the latest move. Returns whether the value or the
+    /// place in the list changed.
+    fn merge_from(&mut self, other: &Self) -> bool {
+        let mut changed = false;
+        if other.metadata.modified_at > self.metadata.modified_at {
+            changed |= self.value != other.value;
+            self.value = other.value.clone();
+            self.metadata = other.metadata.clone();
+        }
+        if other.position.wins_over(&self.position) {
+            changed |= self.position.index != other.position.index;
+            self.position = other.position.clone();
+        }
+        changed
+    }
+
+    /// Whether every replica has seen both the deletion and the last move
+    fn is_collectable(&self, frontier: &StabilityFrontier) -> bool {
+        self.is_deleted()
+            && frontier.is_stable_at(&self.metadata.last_modified_by, self.metadata.modified_at)
+            && frontier.is_stable_at(&self.position.moved_by, self.position.moved_at)
+    }
+}
+
+/// Merge `other` into `elements`, skipping new elements that `purged` says were collected here
+fn merge_elements<T: Clone + PartialEq>(
+    elements: &mut HashMap<ElementId, ListElement<T>>,
+    other: &HashMap<ElementId, ListElement<T>>,
+    purged: impl Fn(&ListElement<T>) -> bool,
+) -> MergeReport<ElementId> {
+    let mut report = MergeReport::new();
+    for (id, element) in other {
+        match elements.get_mut(id) {
+            Some(existing) => {
+                // Conflict resolution: later modification and later move win
+                let was_visible = !existing.is_deleted();
+                let changed = existing.merge_from(element);
+                report.record(id.clone(), was_visible, !existing.is_deleted(), changed);
+            }
+            None if purged(element) => {}
+            None => {
+                // New element, add it
+                elements.insert(id.clone(), element.clone());
+                report.record(id.clone(), false, !element.is_deleted(), true);
+            }
+        }
+    }
+    report
+}
+
+/// Deserialize a list's elements, giving those saved before fractional indexes a real key
+///
+/// Such elements have an empty index. They used to be ordered by creation, so
+/// they are numbered in that order, which every replica derives the same way.
+fn deserialize_elements<'de, D, T>(deserializer: D) -> Result<HashMap<ElementId, ListElement<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let mut elements: HashMap<ElementId, ListElement<T>> = map_as_seq::deserialize(deserializer)?;
+    let mut legacy: Vec<_> = elements
+        .values_mut()
+        .filter(|element| element.position.index.as_str().is_empty())
+        .collect();
+    legacy.sort_by_key(|element| (element.metadata.created_at, element.id.id));
+    let mut previous = None;
+    for element in legacy {
+        let index = FractionalIndex::after(previous.as_ref());
+        element.position.index = index.clone();
+        previous = Some(index);
+    }
+    Ok(elements)
+}
+
+/// Elements sorted into list order
+fn sorted<'a, T: 'a>(elements: impl Iterator<Item = &'a ListElement<T>>) -> Vec<&'a ListElement<T>> {
+    let mut elements: Vec<_> = elements.collect();
+    elements.sort_by(|a, b| (&a.position.index, a.id.id).cmp(&(&b.position.index, b.id.id)));
+    elements
+}
+
+/// Fractional index for an element landing at `slot` in the ordered `elements`
+fn index_at<T>(elements: &[&ListElement<T>], slot: usize) -> Result<FractionalIndex, ListError> {
+    if slot > elements.len() {
+        return Err(ListError::new(format!("Index {} beyond length {}", slot, elements.len())));
+    }
+    let before = slot.checked_sub(1).map(|before| &elements[before].position.index);
+    // Concurrent inserts can share a key; the new element then goes after all of them
+    let after = elements[slot..]
+        .iter()
+        .map(|e| &e.position.index)
+        .find(|index| before.is_none_or(|before| *index > before));
+    FractionalIndex::between(before, after)
+}
+
+/// Fractional index after every element, deleted ones included
+fn index_at_end<T>(elements: &HashMap<ElementId, ListElement<T>>) -> FractionalIndex {
+    FractionalIndex::after(elements.values().map(|e| &e.position.index).max())
+}
+
+/// Fractional index for an element inserted right after `after`
+fn index_after<T>(elements: &HashMap<ElementId, ListElement<T>>, after: &ElementId) -> Result<FractionalIndex, ListError> {
+    let ordered = sorted(elements.values());
+    let slot = ordered
+        .iter()
+        .position(|e| e.id == *after)
+        .ok_or_else(|| ListError::new("Element not found".to_string()))?;
+    index_at(&ordered, slot + 1)
+}
+
+/// Fractional index for moving `id` to visible index `index`
+fn index_for_move<T>(elements: &HashMap<ElementId, ListElement<T>>, id: &ElementId, index: usize) -> Result<FractionalIndex, ListError> {
+    let others: Vec<_> = sorted(elements.values().filter(|e| !e.is_deleted() && e.id != *id));
+    index_at(&others, index)
+}
+
+/// Local edit of a list, recorded by an [`UndoManager`](super::undo::UndoManager)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListEdit<T> {
+    /// Add an element at the end
+    Add { value: T, timestamp: HlcTimestamp },
+    /// Insert an element at visible index `index`
+    InsertAt { value: T, index: usize, timestamp: HlcTimestamp },
+    /// Insert an element right after another one
+    InsertAfter { value: T, after: ElementId, timestamp: HlcTimestamp },
+    /// Move an element to visible index `index`
+    Move { id: ElementId, index: usize, timestamp: HlcTimestamp },
+    /// Replace the value of an element
+    Update { id: ElementId, value: T, timestamp: HlcTimestamp },
+    /// Remove an element
+    Remove { id: ElementId, timestamp: HlcTimestamp },
+}
+
+/// Local change to a list recorded for undo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListChange<T> {
+    /// An element was inserted
+    Inserted(ElementId),
+    /// An element was removed, with its value and place to bring back
+    Removed { id: ElementId, value: T, index: FractionalIndex, removed_at: HlcTimestamp },
+    /// An element was overwritten, with its previous and new value
+    Updated { id: ElementId, previous: T, value: T },
+    /// An element was moved, with its previous and new place
+    Moved { id: ElementId, previous: FractionalIndex, index: FractionalIndex },
+}
+
+impl<T> ListChange<T> {
+    /// Element the change applies to
+    pub fn id(&self) -> &ElementId {
+        match self {
+            ListChange::Inserted(id)
+            | ListChange::Removed { id, .. }
+            | ListChange::Updated { id, .. }
+            | ListChange::Moved { id, .. } => id,
+        }
+    }
+}
+
+/// What the list CRDTs provide for undo
+trait UndoableList<T> {
+    fn replica(&self) -> ReplicaId;
+
+    fn element_map(&self) -> &HashMap<ElementId, ListElement<T>>;
+
+    /// Insert an element at a fractional index
+    fn place(&mut self, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId;
+
+    /// Move an element to a fractional index
+    fn reposition(&mut self, id: &ElementId, index: FractionalIndex, timestamp: HlcTimestamp) -> Result<(), ListError>;
+
+    fn overwrite(&mut self, id: &ElementId, value: T, timestamp: HlcTimestamp) -> Result<(), ListError>;
+
+    fn delete(&mut self, id: &ElementId, timestamp: HlcTimestamp) -> Result<(), ListError>;
+
+    /// Bring back a removed element, returning its id
+    fn bring_back(&mut self, id: &ElementId, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId;
+}
+
+fn find<'a, T>(elements: &'a HashMap<ElementId, ListElement<T>>, id: &ElementId) -> Result<&'a ListElement<T>, ListError> {
+    elements.get(id).ok_or_else(|| ListError::new("Element not found".to_string()))
+}
+
+/// Apply a list edit, recording what it changed
+fn edit_list<T: Clone + PartialEq, L: UndoableList<T>>(list: &mut L, edit: ListEdit<T>) -> Result<(ListChange<T>, ElementId), ListError> {
+    let elements = list.element_map();
+    let change = match edit {
+        ListEdit::Add { value, timestamp } => {
+            let index = index_at_end(elements);
+            ListChange::Inserted(list.place(value, index, timestamp))
+        }
+        ListEdit::InsertAt { value, index, timestamp } => {
+            let index = index_at(&sorted(elements.values().filter(|e| !e.is_deleted())), index)?;
+            ListChange::Inserted(list.place(value, index, timestamp))
+        }
+        ListEdit::InsertAfter { value, after, timestamp } => {
+            let index = index_after(elements, &after)?;
+            ListChange::Inserted(list.place(value, index, timestamp))
+        }
+        ListEdit::Move { id, index, timestamp } => {
+            let previous = find(elements, &id)?.position.index.clone();
+            let index = index_for_move(elements, &id, index)?;
+            list.reposition(&id, index.clone(), timestamp)?;
+            ListChange::Moved { id, previous, index }
+        }
+        ListEdit::Update { id, value, timestamp } => {
+            let previous = find(elements, &id)?.value.clone();
+            list.overwrite(&id, value.clone(), timestamp)?;
+            ListChange::Updated { id, previous, value }
+        }
+        ListEdit::Remove { id, timestamp } => {
+            let element = find(elements, &id)?;
+            let (value, index) = (element.value.clone(), element.position.index.clone());
+            list.delete(&id, timestamp)?;
+            let removed_at = list.element_map().get(&id).map_or(timestamp, |e| e.metadata.modified_at);
+            ListChange::Removed { id, value, index, removed_at }
+        }
+    };
+    let id = change.id().clone();
+    Ok((change, id))
+}
+
+/// Revert a list change, as long as no other edit has superseded it
+///
+/// Overwrites and moves are only reverted while the element still holds the
+/// value or place they gave it, and removals while this replica's deletion is
+/// the latest change to the element.
+fn revert_list<T: Clone + PartialEq, L: UndoableList<T>>(
+    list: &mut L,
+    change: &ListChange<T>,
+    ids: &mut IdMap<ElementId>,
+) -> Result<Option<(ListChange<T>, ElementId)>, ListError> {
+    let replica = list.replica();
+    let timestamp = HlcTimestamp::now();
+    let id = ids.resolve(change.id());
+    let current = list.element_map().get(&id);
+    match change {
+        ListChange::Inserted(_) => {
+            if current.is_none_or(|e| e.is_deleted()) {
+                return Ok(None);
+            }
+            edit_list(list, ListEdit::Remove { id, timestamp }).map(Some)
+        }
+        ListChange::Updated { previous, value, .. } => {
+            if current.is_none_or(|e| e.is_deleted() || e.value != *value) {
+                return Ok(None);
+            }
+            edit_list(list, ListEdit::Update { id, value: previous.clone(), timestamp }).map(Some)
+        }
+        ListChange::Moved { previous, index, .. } => {
+            if current.is_none_or(|e| e.position.index != *index) {
+                return Ok(None);
+            }
+            list.reposition(&id, previous.clone(), timestamp)?;
+            Ok(Some((ListChange::Moved { id: id.clone(), previous: index.clone(), index: previous.clone() }, id)))
+        }
+        ListChange::Removed { value, index, removed_at, .. } => {
+            // Tombstones come back in place, elements that are gone as new ones
+            let ours = current.is_none_or(|e| {
+                e.is_deleted() && e.metadata.last_modified_by == replica && e.metadata.modified_at == *removed_at
+            });
+            if !ours {
+                return Ok(None);
+            }
+            let restored = list.bring_back(&id, value.clone(), index.clone(), timestamp);
+            ids.insert(id, restored.clone());
+            Ok(Some((ListChange::Inserted(restored.clone()), restored)))
+        }
+    }
 }
 
 /// Strategy for handling list conflicts
@@ -158,9 +586,17 @@ pub struct AddWinsList<T> {
     /// Configuration
     config: ListConfig,
     /// Elements in the list
+    #[serde(
+        serialize_with = "map_as_seq::serialize",
+        deserialize_with = "deserialize_elements",
+        bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+    )]
     elements: HashMap<ElementId, ListElement<T>>,
     /// Replica ID for this instance
     replica: ReplicaId,
+    /// Latest modification timestamp seen from each replica
+    #[serde(default)]
+    version: VersionVector,
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
@@ -170,6 +606,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
             config: ListConfig::default(),
             elements: HashMap::new(),
             replica,
+            version: VersionVector::new(),
         }
     }
 
@@ -179,20 +616,39 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
             config,
             elements: HashMap::new(),
             replica,
+            version: VersionVector::new(),
         }
     }
 
-    /// Add an element to the list
-    pub fn add(&mut self, value: T, timestamp: u64) -> ElementId {
-        let element = ListElement::new(value, self.replica, timestamp);
-        let id = element.id.clone();
-        self.elements.insert(id.clone(), element);
-        id
+    /// Add an element at the end of the list
+    pub fn add(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let index = index_at_end(&self.elements);
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    /// Insert an element so that it ends up at visible index `index`
+    pub fn insert_at(&mut self, value: T, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_at(&self.visible_elements(), index)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Insert an element right after another one, deleted or not
+    pub fn insert_after(&mut self, value: T, after: &ElementId, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_after(&self.elements, after)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Move an element to visible index `index`
+    pub fn move_to(&mut self, id: &ElementId, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
+        let index = index_for_move(&self.elements, id, index)?;
+        self.reposition(id, index, timestamp.into())
     }
 
     /// Update an existing element
-    pub fn update(&mut self, id: &ElementId, value: T, timestamp: u64) -> Result<(), ListError> {
+    pub fn update(&mut self, id: &ElementId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
         if let Some(element) = self.elements.get_mut(id) {
+            let timestamp = timestamp.into().max(element.metadata.modified_at.successor());
+            let timestamp = self.version.advance(self.replica, timestamp);
             element.value = value;
             element.mark_modified(self.replica, timestamp);
             Ok(())
@@ -202,8 +658,10 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
     }
 
     /// Mark an element as deleted
-    pub fn remove(&mut self, id: &ElementId, timestamp: u64) -> Result<(), ListError> {
+    pub fn remove(&mut self, id: &ElementId, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
         if let Some(element) = self.elements.get_mut(id) {
+            let timestamp = timestamp.into().max(element.metadata.modified_at.successor());
+            let timestamp = self.version.advance(self.replica, timestamp);
             element.mark_deleted(self.replica, timestamp);
             Ok(())
         } else {
@@ -216,17 +674,19 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
         self.elements.get(id)
     }
 
-    /// Get all visible elements (not deleted)
+    /// Get all visible elements (not deleted), in list order
     pub fn visible_elements(&self) -> Vec<&ListElement<T>> {
-        self.elements
-            .values()
-            .filter(|e| !e.metadata.deleted)
-            .collect()
+        sorted(self.elements.values().filter(|e| !e.is_deleted()))
+    }
+
+    /// Iterate over the visible elements in list order
+    pub fn iter(&self) -> impl Iterator<Item = &ListElement<T>> {
+        self.visible_elements().into_iter()
     }
 
-    /// Get all elements including deleted ones
+    /// Get all elements including deleted ones, in list order
     pub fn all_elements(&self) -> Vec<&ListElement<T>> {
-        self.elements.values().collect()
+        sorted(self.elements.values())
     }
 
     /// Check if the list contains an element
@@ -244,6 +704,30 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsList<T> {
         self.len() == 0
     }
 
+    /// Anchor the gap in front of visible index `index` so it survives remote edits
+    pub fn relative_position(&self, index: usize, bias: Bias) -> Result<RelativePosition<ElementId>, ListError> {
+        RelativePosition::at(self.order(), index, bias)
+            .ok_or_else(|| ListError::new(format!("Index {} beyond length {}", index, self.len())))
+    }
+
+    /// Current index of a relative position, or `None` if its element is unknown here
+    pub fn resolve_position(&self, position: &RelativePosition<ElementId>) -> Option<usize> {
+        position.resolve(self.order())
+    }
+
+    fn order(&self) -> impl Iterator<Item = (&ElementId, bool)> {
+        self.all_elements().into_iter().map(|e| (&e.id, !e.is_deleted()))
+    }
+
+    fn insert_with_index(&mut self, value: T, index: FractionalIndex, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let timestamp = self.version.advance(self.replica, timestamp.into());
+        let mut element = ListElement::new(value, self.replica, timestamp);
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        let id = element.id.clone();
+        self.elements.insert(id.clone(), element);
+        id
+    }
+
     /// Clear all elements
     pub fn clear(&mut self) {
         self.elements.clear();
@@ -260,21 +744,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for AddWinsList<T> {
     type Error = ListError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        for (id, element) in &other.elements {
-            match self.elements.get(id) {
-                Some(existing) => {
-                    // Conflict resolution: later timestamp wins
-                    if element.metadata.modified_at > existing.metadata.modified_at {
-                        self.elements.insert(id.clone(), element.clone());
-                    }
-                }
-                None => {
-                    // New element, add it
-                    self.elements.insert(id.clone(), element.clone());
-                }
-            }
-        }
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
 
     fn has_conflict(&self, other: &Self) -> bool {
@@ -292,6 +762,110 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for AddWinsList<T> {
     }
 }
 
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for AddWinsList<T> {
+    type Key = ElementId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<ElementId>, Self::Error> {
+        let version = &self.version;
+        // Deletion already seen, so the tombstone was purged here
+        let report = merge_elements(&mut self.elements, &other.elements, |element| {
+            element.is_deleted()
+                && version.includes(&element.metadata.last_modified_by, element.metadata.modified_at.as_u64())
+        });
+        self.version.merge(&other.version);
+        Ok(report)
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> DeltaCrdt for AddWinsList<T> {
+    fn version_vector(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        let elements: HashMap<_, _> = self
+            .elements
+            .iter()
+            .filter(|(_, element)| {
+                !since.includes(&element.metadata.last_modified_by, element.metadata.modified_at.as_u64())
+                    || !since.includes(&element.position.moved_by, element.position.moved_at.as_u64())
+            })
+            .map(|(id, element)| (id.clone(), element.clone()))
+            .collect();
+        (!elements.is_empty()).then(|| Self {
+            config: self.config.clone(),
+            elements,
+            replica: self.replica,
+            version: self.version.clone(),
+        })
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> GarbageCollect for AddWinsList<T> {
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let before = self.elements.len();
+        self.elements.retain(|_, element| !element.is_collectable(frontier));
+        before - self.elements.len()
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> UndoableList<T> for AddWinsList<T> {
+    fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    fn element_map(&self) -> &HashMap<ElementId, ListElement<T>> {
+        &self.elements
+    }
+
+    fn place(&mut self, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    fn reposition(&mut self, id: &ElementId, index: FractionalIndex, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        let element = self.elements.get_mut(id).ok_or_else(|| ListError::new("Element not found".to_string()))?;
+        let timestamp = self.version.advance(self.replica, timestamp.max(element.position.moved_at.successor()));
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        Ok(())
+    }
+
+    fn overwrite(&mut self, id: &ElementId, value: T, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.update(id, value, timestamp)
+    }
+
+    fn delete(&mut self, id: &ElementId, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.remove(id, timestamp)
+    }
+
+    fn bring_back(&mut self, id: &ElementId, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        match self.elements.get_mut(id) {
+            Some(element) => {
+                let timestamp = self.version.advance(self.replica, timestamp.max(element.metadata.modified_at.successor()));
+                element.metadata.deleted = false;
+                element.mark_modified(self.replica, timestamp);
+                id.clone()
+            }
+            None => self.insert_with_index(value, index, timestamp),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> Undoable for AddWinsList<T> {
+    type Edit = ListEdit<T>;
+    type Change = ListChange<T>;
+    type Output = ElementId;
+    type Id = ElementId;
+    type Error = ListError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        edit_list(self, edit)
+    }
+
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        revert_list(self, change, ids)
+    }
+}
+
 /// Remove-Wins List CRDT implementation
 /// 
 /// This implementation completely removes deleted elements.
@@ -301,6 +875,11 @@ pub struct RemoveWinsList<T> {
     /// Configuration
     config: ListConfig,
     /// Elements in the list
+    #[serde(
+        serialize_with = "map_as_seq::serialize",
+        deserialize_with = "deserialize_elements",
+        bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+    )]
     elements: HashMap<ElementId, ListElement<T>>,
     /// Replica ID for this instance
     replica: ReplicaId,
@@ -329,16 +908,32 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsList<T> {
         }
     }
 
-    /// Add an element to the list
-    pub fn add(&mut self, value: T, timestamp: u64) -> ElementId {
-        let element = ListElement::new(value, self.replica, timestamp);
-        let id = element.id.clone();
-        self.elements.insert(id.clone(), element);
-        id
+    /// Add an element at the end of the list
+    pub fn add(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let index = index_at_end(&self.elements);
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    /// Insert an element so that it ends up at visible index `index`
+    pub fn insert_at(&mut self, value: T, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_at(&self.elements(), index)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Insert an element right after another one
+    pub fn insert_after(&mut self, value: T, after: &ElementId, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_after(&self.elements, after)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Move an element to visible index `index`
+    pub fn move_to(&mut self, id: &ElementId, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
+        let index = index_for_move(&self.elements, id, index)?;
+        self.reposition(id, index, timestamp.into())
     }
 
     /// Update an existing element
-    pub fn update(&mut self, id: &ElementId, value: T, timestamp: u64) -> Result<(), ListError> {
+    pub fn update(&mut self, id: &ElementId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
         if let Some(element) = self.elements.get_mut(id) {
             element.value = value;
             element.mark_modified(self.replica, timestamp);
@@ -362,9 +957,14 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsList<T> {
         self.elements.get(id)
     }
 
-    /// Get all elements
+    /// Get all elements in list order
     pub fn elements(&self) -> Vec<&ListElement<T>> {
-        self.elements.values().collect()
+        sorted(self.elements.values())
+    }
+
+    /// Iterate over the elements in list order
+    pub fn iter(&self) -> impl Iterator<Item = &ListElement<T>> {
+        self.elements().into_iter()
     }
 
     /// Check if the list contains an element
@@ -386,6 +986,15 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsList<T> {
     pub fn clear(&mut self) {
         self.elements.clear();
     }
+
+    fn insert_with_index(&mut self, value: T, index: FractionalIndex, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let timestamp = timestamp.into();
+        let mut element = ListElement::new(value, self.replica, timestamp);
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        let id = element.id.clone();
+        self.elements.insert(id.clone(), element);
+        id
+    }
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> CRDT for RemoveWinsList<T> {
@@ -398,21 +1007,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsList<T> {
     type Error = ListError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        for (id, element) in &other.elements {
-            match self.elements.get(id) {
-                Some(existing) => {
-                    // Conflict resolution: later timestamp wins
-                    if element.metadata.modified_at > existing.metadata.modified_at {
-                        self.elements.insert(id.clone(), element.clone());
-                    }
-                }
-                None => {
-                    // New element, add it
-                    self.elements.insert(id.clone(), element.clone());
-                }
-            }
-        }
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
 
     fn has_conflict(&self, other: &Self) -> bool {
@@ -430,6 +1025,64 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsList<T> {
     }
 }
 
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for RemoveWinsList<T> {
+    type Key = ElementId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<ElementId>, Self::Error> {
+        Ok(merge_elements(&mut self.elements, &other.elements, |_| false))
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> UndoableList<T> for RemoveWinsList<T> {
+    fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    fn element_map(&self) -> &HashMap<ElementId, ListElement<T>> {
+        &self.elements
+    }
+
+    fn place(&mut self, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    fn reposition(&mut self, id: &ElementId, index: FractionalIndex, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        let element = self.elements.get_mut(id).ok_or_else(|| ListError::new("Element not found".to_string()))?;
+        let timestamp = timestamp.max(element.position.moved_at.successor());
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        Ok(())
+    }
+
+    fn overwrite(&mut self, id: &ElementId, value: T, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.update(id, value, timestamp)
+    }
+
+    fn delete(&mut self, id: &ElementId, _timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.remove(id)
+    }
+
+    /// Removed elements are gone, so they come back as new ones
+    fn bring_back(&mut self, _id: &ElementId, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        self.insert_with_index(value, index, timestamp)
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> Undoable for RemoveWinsList<T> {
+    type Edit = ListEdit<T>;
+    type Change = ListChange<T>;
+    type Output = ElementId;
+    type Id = ElementId;
+    type Error = ListError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        edit_list(self, edit)
+    }
+
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        revert_list(self, change, ids)
+    }
+}
+
 /// Last-Write-Wins List CRDT implementation
 /// 
 /// This implementation uses timestamps to resolve conflicts.
@@ -439,6 +1092,11 @@ pub struct LwwList<T> {
     /// Configuration
     config: ListConfig,
     /// Elements in the list
+    #[serde(
+        serialize_with = "map_as_seq::serialize",
+        deserialize_with = "deserialize_elements",
+        bound(serialize = "T: Serialize", deserialize = "T: Deserialize<'de>")
+    )]
     elements: HashMap<ElementId, ListElement<T>>,
     /// Replica ID for this instance
     replica: ReplicaId,
@@ -467,16 +1125,32 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> LwwList<T> {
         }
     }
 
-    /// Add an element to the list
-    pub fn add(&mut self, value: T, timestamp: u64) -> ElementId {
-        let element = ListElement::new(value, self.replica, timestamp);
-        let id = element.id.clone();
-        self.elements.insert(id.clone(), element);
-        id
+    /// Add an element at the end of the list
+    pub fn add(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let index = index_at_end(&self.elements);
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    /// Insert an element so that it ends up at visible index `index`
+    pub fn insert_at(&mut self, value: T, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_at(&self.visible_elements(), index)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Insert an element right after another one
+    pub fn insert_after(&mut self, value: T, after: &ElementId, timestamp: impl Into<HlcTimestamp>) -> Result<ElementId, ListError> {
+        let index = index_after(&self.elements, after)?;
+        Ok(self.insert_with_index(value, index, timestamp))
+    }
+
+    /// Move an element to visible index `index`
+    pub fn move_to(&mut self, id: &ElementId, index: usize, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
+        let index = index_for_move(&self.elements, id, index)?;
+        self.reposition(id, index, timestamp.into())
     }
 
     /// Update an existing element
-    pub fn update(&mut self, id: &ElementId, value: T, timestamp: u64) -> Result<(), ListError> {
+    pub fn update(&mut self, id: &ElementId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
         if let Some(element) = self.elements.get_mut(id) {
             element.value = value;
             element.mark_modified(self.replica, timestamp);
@@ -487,7 +1161,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> LwwList<T> {
     }
 
     /// Mark an element as deleted
-    pub fn remove(&mut self, id: &ElementId, timestamp: u64) -> Result<(), ListError> {
+    pub fn remove(&mut self, id: &ElementId, timestamp: impl Into<HlcTimestamp>) -> Result<(), ListError> {
         if let Some(element) = self.elements.get_mut(id) {
             element.mark_deleted(self.replica, timestamp);
             Ok(())
@@ -501,17 +1175,19 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> LwwList<T> {
         self.elements.get(id)
     }
 
-    /// Get all visible elements (not deleted)
+    /// Get all visible elements (not deleted), in list order
     pub fn visible_elements(&self) -> Vec<&ListElement<T>> {
-        self.elements
-            .values()
-            .filter(|e| !e.metadata.deleted)
-            .collect()
+        sorted(self.elements.values().filter(|e| !e.is_deleted()))
     }
 
-    /// Get all elements including deleted ones
+    /// Iterate over the visible elements in list order
+    pub fn iter(&self) -> impl Iterator<Item = &ListElement<T>> {
+        self.visible_elements().into_iter()
+    }
+
+    /// Get all elements including deleted ones, in list order
     pub fn all_elements(&self) -> Vec<&ListElement<T>> {
-        self.elements.values().collect()
+        sorted(self.elements.values())
     }
 
     /// Check if the list contains an element
@@ -533,6 +1209,15 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> LwwList<T> {
     pub fn clear(&mut self) {
         self.elements.clear();
     }
+
+    fn insert_with_index(&mut self, value: T, index: FractionalIndex, timestamp: impl Into<HlcTimestamp>) -> ElementId {
+        let timestamp = timestamp.into();
+        let mut element = ListElement::new(value, self.replica, timestamp);
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        let id = element.id.clone();
+        self.elements.insert(id.clone(), element);
+        id
+    }
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> CRDT for LwwList<T> {
@@ -545,21 +1230,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for LwwList<T> {
     type Error = ListError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        for (id, element) in &other.elements {
-            match self.elements.get(id) {
-                Some(existing) => {
-                    // Conflict resolution: later timestamp wins
-                    if element.metadata.modified_at > existing.metadata.modified_at {
-                        self.elements.insert(id.clone(), element.clone());
-                    }
-                }
-                None => {
-                    // New element, add it
-                    self.elements.insert(id.clone(), element.clone());
-                }
-            }
-        }
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
 
     fn has_conflict(&self, other: &Self) -> bool {
@@ -577,6 +1248,78 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for LwwList<T> {
     }
 }
 
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for LwwList<T> {
+    type Key = ElementId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<ElementId>, Self::Error> {
+        Ok(merge_elements(&mut self.elements, &other.elements, |_| false))
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> GarbageCollect for LwwList<T> {
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        let before = self.elements.len();
+        self.elements.retain(|_, element| !element.is_collectable(frontier));
+        before - self.elements.len()
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> UndoableList<T> for LwwList<T> {
+    fn replica(&self) -> ReplicaId {
+        self.replica
+    }
+
+    fn element_map(&self) -> &HashMap<ElementId, ListElement<T>> {
+        &self.elements
+    }
+
+    fn place(&mut self, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        self.insert_with_index(value, index, timestamp)
+    }
+
+    fn reposition(&mut self, id: &ElementId, index: FractionalIndex, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        let element = self.elements.get_mut(id).ok_or_else(|| ListError::new("Element not found".to_string()))?;
+        let timestamp = timestamp.max(element.position.moved_at.successor());
+        element.position = ListPosition::new(index, self.replica, timestamp);
+        Ok(())
+    }
+
+    fn overwrite(&mut self, id: &ElementId, value: T, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.update(id, value, timestamp)
+    }
+
+    fn delete(&mut self, id: &ElementId, timestamp: HlcTimestamp) -> Result<(), ListError> {
+        self.remove(id, timestamp)
+    }
+
+    fn bring_back(&mut self, id: &ElementId, value: T, index: FractionalIndex, timestamp: HlcTimestamp) -> ElementId {
+        match self.elements.get_mut(id) {
+            Some(element) => {
+                element.metadata.deleted = false;
+                element.mark_modified(self.replica, timestamp);
+                id.clone()
+            }
+            None => self.insert_with_index(value, index, timestamp),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> Undoable for LwwList<T> {
+    type Edit = ListEdit<T>;
+    type Change = ListChange<T>;
+    type Output = ElementId;
+    type Id = ElementId;
+    type Error = ListError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        edit_list(self, edit)
+    }
+
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        revert_list(self, change, ids)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -603,8 +1346,8 @@ mod tests {
         let element = ListElement::new("test_value", replica, timestamp);
         
         assert_eq!(element.value, "test_value");
-        assert_eq!(element.metadata.created_at, timestamp);
-        assert_eq!(element.metadata.modified_at, timestamp);
+        assert_eq!(element.metadata.created_at, HlcTimestamp::from(timestamp));
+        assert_eq!(element.metadata.modified_at, HlcTimestamp::from(timestamp));
         assert_eq!(element.metadata.deleted, false);
         assert_eq!(element.metadata.last_modified_by, replica);
     }
@@ -708,6 +1451,57 @@ mod tests {
         assert_eq!(list1.get(&id).unwrap().value, "value2");
     }
 
+    #[test]
+    fn test_list_merge_with_changes() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut list1 = AddWinsList::new(replica1);
+        let kept = list1.add("kept", 1000);
+        let edited = list1.add("edited", 1001);
+        let removed = list1.add("removed", 1002);
+        let mut list2 = AddWinsList::new(replica2);
+        list2.merge(&list1).unwrap();
+
+        list2.update(&edited, "changed", 2000).unwrap();
+        list2.remove(&removed, 2001).unwrap();
+        let added = list2.add("added", 2002);
+
+        let report = list1.merge_with_changes(&list2).unwrap();
+        assert_eq!(report.inserted, vec![added]);
+        assert_eq!(report.updated, vec![edited]);
+        assert_eq!(report.removed, vec![removed]);
+        assert!(!report.updated.contains(&kept));
+
+        // Merging the same state again changes nothing
+        assert!(list1.merge_with_changes(&list2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_add_wins_list_delta_since() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut list1 = AddWinsList::new(replica1);
+        let kept = list1.add("kept", 1000);
+        let edited = list1.add("edited", 1001);
+        let mut list2 = AddWinsList::new(replica2);
+        list2.merge(&list1).unwrap();
+
+        // Stale timestamps still produce versions the peer has not seen
+        list1.update(&edited, "edited twice", 500).unwrap();
+        let added = list1.add("added", 1002);
+
+        let delta = list1.delta_since(&list2.version_vector()).unwrap();
+        assert_eq!(delta.all_elements().len(), 2);
+        assert!(!delta.contains(&kept));
+
+        list2.apply_delta(&delta).unwrap();
+        assert_eq!(list2.get(&edited).unwrap().value, "edited twice");
+        assert!(list2.contains(&added));
+        assert!(list1.delta_since(&list2.version_vector()).is_none());
+    }
+
     #[test]
     fn test_list_configuration() {
         let replica = create_replica(1);
@@ -721,4 +1515,170 @@ mod tests {
         assert_eq!(list.config.strategy, ListStrategy::RemoveWins);
         assert_eq!(list.config.max_elements, Some(100));
     }
+
+    #[test]
+    fn test_add_wins_list_relative_position() {
+        let mut list1 = AddWinsList::new(create_replica(1));
+        let mut list2 = AddWinsList::new(create_replica(2));
+        let first = list1.add("first", 1000);
+        list1.add("third", 3000);
+        list2.merge(&list1).unwrap();
+
+        // Selection from just after "first" to the end
+        let start = list1.relative_position(1, Bias::After).unwrap();
+        let end = list1.relative_position(2, Bias::Before).unwrap();
+
+        list2.insert_at("second", 1, 2000).unwrap();
+        list2.remove(&first, 4000).unwrap();
+        list1.merge(&list2).unwrap();
+
+        let values: Vec<_> = list1.visible_elements().iter().map(|e| e.value).collect();
+        assert_eq!(values, vec!["second", "third"]);
+        assert_eq!(list1.resolve_position(&start), Some(0));
+        assert_eq!(list1.resolve_position(&end), Some(2));
+    }
+
+    #[test]
+    fn test_fractional_index_between() {
+        let first = FractionalIndex::after(None);
+        let second = FractionalIndex::after(Some(&first));
+        assert!(first < second);
+
+        let mut low = first.clone();
+        for _ in 0..200 {
+            let mid = FractionalIndex::between(Some(&low), Some(&second)).unwrap();
+            assert!(low < mid && mid < second);
+            low = mid;
+        }
+        let before = FractionalIndex::between(None, Some(&first)).unwrap();
+        assert!(before < first);
+        assert!(FractionalIndex::between(Some(&second), Some(&first)).is_err());
+
+        // Keys that are the same fraction have nothing between them
+        let padded = FractionalIndex("10".to_string());
+        assert!(FractionalIndex::between(Some(&first), Some(&padded)).is_err());
+        let mid = FractionalIndex::between(Some(&FractionalIndex("1".to_string())), Some(&FractionalIndex("11".to_string()))).unwrap();
+        assert_eq!(mid.as_str(), "101");
+    }
+
+    #[test]
+    fn test_fractional_index_rejects_invalid_keys() {
+        assert!(serde_json::from_str::<FractionalIndex>("\"1V\"").is_ok());
+        assert!(serde_json::from_str::<FractionalIndex>("\"10\"").is_err());
+        assert!(serde_json::from_str::<FractionalIndex>("\"1-2\"").is_err());
+    }
+
+    #[test]
+    fn test_legacy_elements_get_indexes_on_load() {
+        let mut list = RemoveWinsList::new(create_replica(1));
+        list.add("second", 2000);
+        list.add("first", 1000);
+        list.add("third", 3000);
+
+        // Elements saved before fractional indexes have no position
+        let mut json = serde_json::to_value(&list).unwrap();
+        for entry in json["elements"].as_array_mut().unwrap() {
+            entry[1].as_object_mut().unwrap().remove("position");
+        }
+        let mut restored: RemoveWinsList<String> = serde_json::from_value(json).unwrap();
+
+        let values: Vec<_> = restored.iter().map(|e| e.value.clone()).collect();
+        assert_eq!(values, vec!["first", "second", "third"]);
+        restored.insert_at("zeroth".to_string(), 0, 4000).unwrap();
+        restored.insert_at("middle".to_string(), 2, 5000).unwrap();
+        let values: Vec<_> = restored.iter().map(|e| e.value.clone()).collect();
+        assert_eq!(values, vec!["zeroth", "first", "middle", "second", "third"]);
+    }
+
+    #[test]
+    fn test_lists_keep_insertion_order() {
+        let replica = create_replica(1);
+        let mut add_wins = AddWinsList::new(replica);
+        let b = add_wins.add("b", 1000);
+        add_wins.insert_at("a", 0, 2000).unwrap();
+        add_wins.insert_after("c", &b, 3000).unwrap();
+        add_wins.add("d", 4000);
+        let values: Vec<_> = add_wins.iter().map(|e| e.value).collect();
+        assert_eq!(values, vec!["a", "b", "c", "d"]);
+        assert!(add_wins.insert_at("x", 5, 5000).is_err());
+
+        let mut remove_wins = RemoveWinsList::new(replica);
+        let y = remove_wins.add("y", 1000);
+        remove_wins.insert_at("x", 0, 2000).unwrap();
+        remove_wins.insert_after("z", &y, 3000).unwrap();
+        let values: Vec<_> = remove_wins.iter().map(|e| e.value).collect();
+        assert_eq!(values, vec!["x", "y", "z"]);
+
+        let mut lww = LwwList::new(replica);
+        let one = lww.add(1, 1000);
+        lww.add(3, 2000);
+        lww.insert_after(2, &one, 3000).unwrap();
+        let values: Vec<_> = lww.iter().map(|e| e.value).collect();
+        assert_eq!(values, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_concurrent_moves_do_not_duplicate() {
+        let mut list1 = AddWinsList::new(create_replica(1));
+        let card = list1.add("card", 1000);
+        list1.add("b", 1000);
+        list1.add("c", 1000);
+        let mut list2 = AddWinsList::new(create_replica(2));
+        list2.merge(&list1).unwrap();
+
+        list1.move_to(&card, 2, 2000).unwrap();
+        list2.move_to(&card, 1, 3000).unwrap();
+        // A concurrent edit of the value survives the move
+        list1.update(&card, "edited card", 4000).unwrap();
+
+        let mut merged1 = list1.clone();
+        merged1.merge(&list2).unwrap();
+        list2.merge(&list1).unwrap();
+
+        let values: Vec<_> = merged1.iter().map(|e| e.value).collect();
+        assert_eq!(values, vec!["b", "edited card", "c"]);
+        assert_eq!(merged1.visible_elements(), list2.visible_elements());
+    }
+
+    #[test]
+    fn test_add_wins_delta_carries_moves() {
+        let mut list1 = AddWinsList::new(create_replica(1));
+        let a = list1.add("a", 1000);
+        list1.add("b", 2000);
+        let mut list2 = AddWinsList::new(create_replica(2));
+        list2.merge(&list1).unwrap();
+
+        let since = list2.version_vector();
+        list1.move_to(&a, 1, 3000).unwrap();
+        let delta = list1.delta_since(&since).unwrap();
+        list2.merge(&delta).unwrap();
+
+        let values: Vec<_> = list2.iter().map(|e| e.value).collect();
+        assert_eq!(values, vec!["b", "a"]);
+    }
+
+    #[test]
+    fn test_add_wins_list_gc_waits_for_stable_deletion() {
+        let mut list1 = AddWinsList::new(create_replica(1));
+        let a = list1.add("a", 1000);
+        list1.add("b", 1001);
+        let mut list2 = AddWinsList::new(create_replica(2));
+        list2.merge(&list1).unwrap();
+        list1.remove(&a, 2000).unwrap();
+
+        // list2 has not seen the deletion yet, so the tombstone must stay
+        let frontier = StabilityFrontier::new(list2.version_vector());
+        assert_eq!(list1.gc(&frontier), 0);
+        assert!(list1.contains(&a));
+
+        list2.merge(&list1).unwrap();
+        let frontier = StabilityFrontier::new(list2.version_vector());
+        assert_eq!(list1.gc(&frontier), 1);
+        assert!(!list1.contains(&a));
+        assert_eq!(list1.visible_elements(), list2.visible_elements());
+
+        // list2 still holds the tombstone, merging it back does not revive it
+        list1.merge(&list2).unwrap();
+        assert!(!list1.contains(&a));
+    }
 }
@@ -9,35 +9,57 @@ pub mod tree;
 pub mod graph;
 pub mod builder;
 pub mod advanced;
+pub mod cursor;
+pub mod json;
+pub mod op_log;
+pub mod stability;
+pub mod undo;
+pub mod yjs;
 
 // Re-export basic CRDTs
-pub use basic::{LwwRegister, LwwMap, GCounter, ReplicaId, Mergeable, CRDT};
+pub use basic::{
+    LwwRegister, LwwMap, GCounter, ReplicaId, Mergeable, CRDT, HlcTimestamp, HybridLogicalClock,
+    ClockError, DeltaCrdt, VersionVector, PNCounter, BoundedCounter, CounterError,
+    OrSet, OrMap, Dot, MvRegister, OpCrdt, OpId, Operation, MapChange, MapWrite,
+    MergeReport, ReportingMerge, Reset,
+};
 
 pub use list::{
     ElementId, ElementMetadata, ListElement, ListStrategy, ListConfig,
-    AddWinsList, RemoveWinsList, LwwList,
+    AddWinsList, RemoveWinsList, LwwList, FractionalIndex, ListPosition, ListEdit, ListChange,
 };
 
 pub use tree::{
     NodeId, NodeMetadata, TreeNode, TreeStrategy, TreeConfig,
-    AddWinsTree, RemoveWinsTree,
+    AddWinsTree, RemoveWinsTree, TreeChange,
 };
 
 pub use graph::{
     VertexId, EdgeId, VertexMetadata, EdgeMetadata, Vertex, Edge,
-    GraphStrategy, GraphConfig, AddWinsGraph, RemoveWinsGraph,
+    GraphStrategy, GraphConfig, AddWinsGraph, RemoveWinsGraph, GraphElementId,
+    EdgeDirection, GraphView, Traversal, WeightedPath,
 };
 
+pub use cursor::{Bias, RelativePosition};
+pub use json::{JsonDoc, JsonError, JsonPatch, JsonPath, PathSegment};
+pub use op_log::OpLog;
+pub use stability::{GarbageCollect, StabilityError, StabilityFrontier, StabilityTracker};
+pub use undo::{IdMap, Reverted, UndoManager, Undoable};
+pub use yjs::{YjsDoc, YjsError, YjsUpdate};
+
 // Re-export builder functionality
 pub use builder::{
     CrdtBuilder, CrdtBuilderConfig, FieldConfig, CrdtStrategy, 
-    CustomCrdt, GenericCrdtField, CrdtField, BuilderError
+    CustomCrdt, GenericCrdtField, CrdtField, BuilderError, CustomEdit, CustomChange,
+    FieldState, SequenceState, CounterField, TextField, ListField,
+    Migration, MigrationRegistry, MigrationStep,
 };
 
 // Re-export advanced CRDT types
 pub use advanced::{
-    Rga, RgaElement, Lseq, LseqElement, YjsTree, YjsNode, YjsTreeNode,
-    Dag, DagNode, PositionId, AdvancedCrdtError
+    Rga, RgaChange, RgaElement, Lseq, LseqElement, YjsTree, YjsNode, YjsTreeNode,
+    Dag, DagEdge, DagNode, PositionId, AdvancedCrdtError, TextCrdt, TextChange,
+    RichText, MarkType, ExpandRule, FormattedSpan,
 };
 
 #[cfg(test)]
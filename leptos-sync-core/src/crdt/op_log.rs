@@ -0,0 +1,211 @@
+//! Log of operations for operation-based CRDTs
+//!
+//! An [`OpLog`] keeps every operation of an [`OpCrdt`] in the order it was
+//! applied. Because that order respects causality, the log can be stored as a
+//! plain list and replayed into a fresh replica, and the operations another
+//! replica is missing can be read off its version vector.
+
+use super::basic::{OpCrdt, OpId, Operation, VersionVector};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::collections::HashSet;
+
+/// Operations in the order they were applied, each at most once
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpLog<O> {
+    ops: Vec<O>,
+    ids: HashSet<OpId>,
+}
+
+impl<O> Default for OpLog<O> {
+    fn default() -> Self {
+        Self {
+            ops: Vec::new(),
+            ids: HashSet::new(),
+        }
+    }
+}
+
+impl<O: Operation> OpLog<O> {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append an operation, returning `false` if it is already in the log
+    pub fn append(&mut self, op: O) -> bool {
+        if !self.ids.insert(op.id()) {
+            return false;
+        }
+        self.ops.push(op);
+        true
+    }
+
+    /// Apply an operation to `crdt` and log it
+    ///
+    /// Returns whether the operation was new. Operations that fail to apply
+    /// are not logged.
+    pub fn record<C: OpCrdt<Op = O>>(&mut self, crdt: &mut C, op: O) -> Result<bool, C::Error> {
+        if self.contains(&op.id()) {
+            return Ok(false);
+        }
+        crdt.apply(&op)?;
+        Ok(self.append(op))
+    }
+
+    /// Apply every logged operation to `crdt`, returning how many took effect
+    pub fn replay<C: OpCrdt<Op = O>>(&self, crdt: &mut C) -> Result<usize, C::Error> {
+        let mut applied = 0;
+        for op in &self.ops {
+            if crdt.apply(op)? {
+                applied += 1;
+            }
+        }
+        Ok(applied)
+    }
+
+    /// Whether an operation with this ID is in the log
+    pub fn contains(&self, id: &OpId) -> bool {
+        self.ids.contains(id)
+    }
+
+    /// All operations, in the order they were applied
+    pub fn ops(&self) -> impl Iterator<Item = &O> {
+        self.ops.iter()
+    }
+
+    /// Operations not covered by `since`, in the order they were applied
+    pub fn since<'a>(&'a self, since: &'a VersionVector) -> impl Iterator<Item = &'a O> + 'a {
+        self.ops.iter().filter(move |op| {
+            let id = op.id();
+            !since.includes(&id.replica, id.seq)
+        })
+    }
+
+    /// Latest operation logged from each replica
+    pub fn version_vector(&self) -> VersionVector {
+        self.ids.iter().map(|id| (id.replica, id.seq)).fold(VersionVector::new(), |mut version, (replica, seq)| {
+            version.observe(replica, seq);
+            version
+        })
+    }
+
+    /// Number of operations in the log
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Check if the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+}
+
+impl<O: Operation> FromIterator<O> for OpLog<O> {
+    fn from_iter<I: IntoIterator<Item = O>>(iter: I) -> Self {
+        let mut log = Self::new();
+        for op in iter {
+            log.append(op);
+        }
+        log
+    }
+}
+
+// Stored as the plain list of operations; the ID index is rebuilt on load
+impl<O: Serialize> Serialize for OpLog<O> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.ops.serialize(serializer)
+    }
+}
+
+impl<'de, O: Operation + Deserialize<'de>> Deserialize<'de> for OpLog<O> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Vec::<O>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::advanced::{Rga, RgaIntent, RgaOp};
+    use super::super::ReplicaId;
+    use super::*;
+    use crate::storage::{LocalStorage, Storage};
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    fn type_text(rga: &mut Rga<char>, log: &mut OpLog<RgaOp<char>>, text: &str) {
+        let mut after = None;
+        for value in text.chars() {
+            let op = rga.perform(RgaIntent::InsertAfter { value, after }).unwrap();
+            after = Some(op.position().clone());
+            log.append(op);
+        }
+    }
+
+    #[test]
+    fn test_replay_rebuilds_replica() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut log = OpLog::new();
+        type_text(&mut rga, &mut log, "abc");
+        let b = rga.position_at(1).unwrap();
+        log.append(rga.perform(RgaIntent::Delete(b)).unwrap());
+
+        let mut replica = Rga::new(create_replica(2));
+        assert_eq!(log.replay(&mut replica).unwrap(), 4);
+        assert_eq!(replica.to_vec(), vec!['a', 'c']);
+        // Replaying again changes nothing
+        assert_eq!(log.replay(&mut replica).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_record_skips_logged_operations() {
+        let mut source = Rga::new(create_replica(1));
+        let mut source_log = OpLog::new();
+        type_text(&mut source, &mut source_log, "ab");
+
+        let mut rga = Rga::new(create_replica(2));
+        let mut log = OpLog::new();
+        for op in source_log.ops().chain(source_log.ops()) {
+            log.record(&mut rga, op.clone()).unwrap();
+        }
+        assert_eq!(log.len(), 2);
+        assert_eq!(rga.to_vec(), vec!['a', 'b']);
+
+        // Operations that fail to apply are not logged
+        let mut empty = Rga::new(create_replica(3));
+        let second = source_log.ops().nth(1).unwrap().clone();
+        assert!(OpLog::new().record(&mut empty, second).is_err());
+    }
+
+    #[test]
+    fn test_since_returns_missing_operations() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut log = OpLog::new();
+        type_text(&mut rga, &mut log, "ab");
+        let seen = log.version_vector();
+        type_text(&mut rga, &mut log, "cd");
+
+        let missing: Vec<_> = log.since(&seen).cloned().collect();
+        assert_eq!(missing.len(), 2);
+        assert_eq!(log.since(&log.version_vector()).count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_log_round_trips_through_storage() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut log = OpLog::new();
+        type_text(&mut rga, &mut log, "abc");
+
+        let storage = Storage::memory();
+        storage.set("ops", &log).await.unwrap();
+        let loaded: OpLog<RgaOp<char>> = storage.get("ops").await.unwrap().unwrap();
+        assert_eq!(loaded, log);
+
+        let mut replica = Rga::new(create_replica(2));
+        loaded.replay(&mut replica).unwrap();
+        assert_eq!(replica.to_vec(), rga.to_vec());
+    }
+}
@@ -0,0 +1,242 @@
+//! Causal stability and tombstone garbage collection
+//!
+//! A tombstone may only be dropped once every replica has seen the deletion;
+//! a replica that has not would otherwise bring the element back on its next
+//! merge. A [`StabilityTracker`] collects what each replica has acknowledged
+//! seeing and derives a [`StabilityFrontier`], the updates known everywhere.
+//! CRDTs implementing [`GarbageCollect`] purge the tombstones behind it.
+//!
+//! Acknowledgements are expected on the same ordered channel as the updates a
+//! replica sends, so by the time a replica acknowledges a deletion, everything
+//! it did concurrently with that deletion has already been delivered.
+
+use super::basic::{HlcTimestamp, ReplicaId, VersionVector};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Errors from stability tracking
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum StabilityError {
+    /// The replica was silent for longer than the GC horizon and may hold purged tombstones
+    #[error("Replica {replica} was offline longer than the GC horizon")]
+    Expired { replica: ReplicaId },
+}
+
+/// Updates that every tracked replica has seen
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StabilityFrontier {
+    seen: VersionVector,
+}
+
+impl StabilityFrontier {
+    /// Frontier covering exactly `seen`
+    pub fn new(seen: VersionVector) -> Self {
+        Self { seen }
+    }
+
+    /// Whether every replica has seen update `version` of `replica`
+    pub fn is_stable(&self, replica: &ReplicaId, version: u64) -> bool {
+        self.seen.includes(replica, version)
+    }
+
+    /// Whether every replica has seen the update `replica` made at `timestamp`
+    pub fn is_stable_at(&self, replica: &ReplicaId, timestamp: HlcTimestamp) -> bool {
+        self.is_stable(replica, timestamp.as_u64())
+    }
+
+    /// Part of the frontier that `seen` also covers
+    ///
+    /// CRDTs restrict the frontier to their own version vector before purging,
+    /// so they never treat an update they have not received as stable.
+    pub fn meet(&self, seen: &VersionVector) -> Self {
+        Self::new(self.seen.meet(seen))
+    }
+
+    /// The frontier as a version vector
+    pub fn version_vector(&self) -> &VersionVector {
+        &self.seen
+    }
+}
+
+/// CRDTs that can drop tombstones once their deletion is causally stable
+pub trait GarbageCollect {
+    /// Purge tombstones behind `frontier`, returning how many were dropped
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize;
+}
+
+/// Latest acknowledgement received from a replica
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Acknowledgement {
+    seen: VersionVector,
+    at: HlcTimestamp,
+}
+
+/// Tracks what each replica has acknowledged seeing
+///
+/// Every tracked replica holds back garbage collection until it acknowledges
+/// an update. With a horizon set, replicas that stay silent for longer stop
+/// holding it back, and are refused from then on since they may still hold
+/// tombstones that were purged in the meantime.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StabilityTracker {
+    replicas: HashMap<ReplicaId, Acknowledgement>,
+    expired: HashSet<ReplicaId>,
+    /// Longest silence, in milliseconds, before a replica expires
+    horizon_ms: Option<u64>,
+}
+
+impl StabilityTracker {
+    /// Create a tracker that waits for every replica indefinitely
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a tracker that expires replicas silent for longer than `horizon`
+    pub fn with_horizon(horizon: Duration) -> Self {
+        let mut tracker = Self::new();
+        tracker.set_horizon(Some(horizon));
+        tracker
+    }
+
+    /// Change the GC horizon, or wait indefinitely for `None`
+    pub fn set_horizon(&mut self, horizon: Option<Duration>) {
+        self.horizon_ms = horizon.map(|horizon| horizon.as_millis() as u64);
+    }
+
+    /// Start tracking a replica that has not acknowledged anything yet
+    pub fn add_replica(&mut self, replica: ReplicaId, now: HlcTimestamp) {
+        if !self.expired.contains(&replica) {
+            self.replicas.entry(replica).or_insert_with(|| Acknowledgement {
+                seen: VersionVector::new(),
+                at: now,
+            });
+        }
+    }
+
+    /// Stop tracking a replica, e.g. one that was retired for good
+    pub fn remove_replica(&mut self, replica: &ReplicaId) {
+        self.replicas.remove(replica);
+        self.expired.remove(replica);
+    }
+
+    /// Record that `replica` has seen everything in `seen`
+    pub fn acknowledge(&mut self, replica: ReplicaId, seen: &VersionVector, now: HlcTimestamp) -> Result<(), StabilityError> {
+        self.check(&replica, now)?;
+        let acknowledgement = self.replicas.entry(replica).or_insert_with(|| Acknowledgement {
+            seen: VersionVector::new(),
+            at: now,
+        });
+        acknowledgement.seen.merge(seen);
+        acknowledgement.at = acknowledgement.at.max(now);
+        Ok(())
+    }
+
+    /// Refuse a replica that has been silent for longer than the horizon
+    pub fn check(&mut self, replica: &ReplicaId, now: HlcTimestamp) -> Result<(), StabilityError> {
+        self.expire(now);
+        if self.expired.contains(replica) {
+            return Err(StabilityError::Expired { replica: *replica });
+        }
+        Ok(())
+    }
+
+    /// Updates every live replica has acknowledged
+    ///
+    /// Empty while no replica is tracked, so nothing is purged before the
+    /// first acknowledgement arrives.
+    pub fn frontier(&mut self, now: HlcTimestamp) -> StabilityFrontier {
+        self.expire(now);
+        let seen = self
+            .replicas
+            .values()
+            .map(|acknowledgement| acknowledgement.seen.clone())
+            .reduce(|common, seen| common.meet(&seen))
+            .unwrap_or_default();
+        StabilityFrontier::new(seen)
+    }
+
+    /// Replicas currently holding back garbage collection
+    pub fn replicas(&self) -> impl Iterator<Item = &ReplicaId> {
+        self.replicas.keys()
+    }
+
+    /// Whether a replica expired and is being refused
+    pub fn is_expired(&self, replica: &ReplicaId) -> bool {
+        self.expired.contains(replica)
+    }
+
+    fn expire(&mut self, now: HlcTimestamp) {
+        let Some(horizon_ms) = self.horizon_ms else {
+            return;
+        };
+        let expired: Vec<_> = self
+            .replicas
+            .iter()
+            .filter(|(_, acknowledgement)| now.physical.saturating_sub(acknowledgement.at.physical) > horizon_ms)
+            .map(|(replica, _)| *replica)
+            .collect();
+        for replica in expired {
+            self.replicas.remove(&replica);
+            self.expired.insert(replica);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    fn vector(entries: &[(ReplicaId, u64)]) -> VersionVector {
+        let mut vector = VersionVector::new();
+        for (replica, version) in entries {
+            vector.observe(*replica, *version);
+        }
+        vector
+    }
+
+    #[test]
+    fn test_frontier_is_what_every_replica_has_seen() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let now = HlcTimestamp::new(1000, 0);
+        let mut tracker = StabilityTracker::new();
+        assert!(!tracker.frontier(now).is_stable(&replica1, 1));
+
+        tracker.acknowledge(replica1, &vector(&[(replica1, 5), (replica2, 3)]), now).unwrap();
+        tracker.add_replica(replica2, now);
+        // replica2 has not acknowledged anything, so nothing is stable yet
+        assert!(!tracker.frontier(now).is_stable(&replica1, 1));
+
+        tracker.acknowledge(replica2, &vector(&[(replica1, 4), (replica2, 7)]), now).unwrap();
+        let frontier = tracker.frontier(now);
+        assert!(frontier.is_stable(&replica1, 4));
+        assert!(!frontier.is_stable(&replica1, 5));
+        assert!(frontier.is_stable(&replica2, 3));
+        assert!(!frontier.is_stable(&replica2, 4));
+    }
+
+    #[test]
+    fn test_silent_replica_expires_after_horizon() {
+        let (replica1, replica2) = (create_replica(1), create_replica(2));
+        let mut tracker = StabilityTracker::with_horizon(Duration::from_secs(60));
+        tracker.acknowledge(replica1, &vector(&[(replica1, 5)]), HlcTimestamp::new(1_000, 0)).unwrap();
+        tracker.acknowledge(replica2, &vector(&[(replica1, 2)]), HlcTimestamp::new(1_000, 0)).unwrap();
+
+        // replica2 goes quiet while replica1 keeps acknowledging
+        tracker.acknowledge(replica1, &vector(&[(replica1, 5)]), HlcTimestamp::new(50_000, 0)).unwrap();
+        let later = HlcTimestamp::new(100_000, 0);
+        tracker.acknowledge(replica1, &vector(&[(replica1, 5)]), later).unwrap();
+        assert!(tracker.frontier(later).is_stable(&replica1, 5));
+        assert!(tracker.is_expired(&replica2));
+
+        let error = tracker.acknowledge(replica2, &vector(&[(replica1, 5)]), later).unwrap_err();
+        assert_eq!(error, StabilityError::Expired { replica: replica2 });
+        assert!(tracker.check(&replica1, later).is_ok());
+    }
+}
@@ -1,9 +1,11 @@
 //! Add-Wins Tree CRDT implementation
 
-use super::super::{CRDT, Mergeable, ReplicaId};
-use super::{config::TreeConfig, error::TreeError, types::{NodeId, TreeNode}};
+use super::super::{CRDT, DeltaCrdt, HlcTimestamp, MergeReport, Mergeable, OpCrdt, ReplicaId, ReportingMerge, VersionVector};
+use super::super::stability::{GarbageCollect, StabilityFrontier};
+use super::super::undo::{IdMap, Reverted, Undoable};
+use super::{config::TreeConfig, error::TreeError, move_log::{MoveOp, TreeHierarchy}, types::{NodeId, NodeMetadata, TreeChange, TreeIntent, TreeMoveLog, TreeNode, TreeOp, TreeSnapshot}};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Add-Wins Tree CRDT implementation
 /// 
@@ -17,6 +19,12 @@ pub struct AddWinsTree<T> {
     nodes: HashMap<NodeId, TreeNode<T>>,
     /// Replica ID for this instance
     replica: ReplicaId,
+    /// Latest modification timestamp seen from each replica
+    #[serde(default)]
+    version: VersionVector,
+    /// Timestamped parent changes, the source of truth for the hierarchy
+    #[serde(default)]
+    moves: TreeMoveLog,
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsTree<T> {
@@ -26,6 +34,8 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsTree<T> {
             config: TreeConfig::default(),
             nodes: HashMap::new(),
             replica,
+            version: VersionVector::new(),
+            moves: TreeMoveLog::new(),
         }
     }
 
@@ -35,85 +45,198 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsTree<T> {
             config,
             nodes: HashMap::new(),
             replica,
+            version: VersionVector::new(),
+            moves: TreeMoveLog::new(),
         }
     }
 
     /// Add a root node to the tree
-    pub fn add_root(&mut self, value: T, timestamp: u64) -> NodeId {
-        let node = TreeNode::new(value, self.replica, timestamp);
-        let id = node.id.clone();
-        self.nodes.insert(id.clone(), node);
-        id
+    pub fn add_root(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> NodeId {
+        let op = self.prepare_create(value, None, timestamp.into());
+        self.apply_op(&op).expect("a new root always applies");
+        match op {
+            TreeOp::Create { id, .. } => id,
+            _ => unreachable!("roots are created by a create operation"),
+        }
     }
 
     /// Add a child node
-    pub fn add_child(&mut self, parent_id: &NodeId, value: T, timestamp: u64) -> Result<NodeId, TreeError> {
-        if !self.nodes.contains_key(parent_id) {
-            return Err(TreeError::new("Parent node not found".to_string()));
-        }
+    pub fn add_child(&mut self, parent_id: &NodeId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<NodeId, TreeError> {
+        self.check_parent(parent_id)?;
 
-        let node = TreeNode::new_child(value, self.replica, timestamp, parent_id.clone());
-        let id = node.id.clone();
-        
-        // Add the child node
-        self.nodes.insert(id.clone(), node);
-        
-        // Update parent's children list
-        if let Some(parent) = self.nodes.get_mut(parent_id) {
-            parent.add_child(id.clone());
+        let op = self.prepare_create(value, Some(parent_id.clone()), timestamp.into());
+        self.apply_op(&op)?;
+        match op {
+            TreeOp::Create { id, .. } => Ok(id),
+            _ => unreachable!("children are created by a create operation"),
         }
-        
-        Ok(id)
     }
 
     /// Update an existing node
-    pub fn update(&mut self, id: &NodeId, value: T, timestamp: u64) -> Result<(), TreeError> {
-        if let Some(node) = self.nodes.get_mut(id) {
-            node.value = value;
-            node.mark_modified(self.replica, timestamp);
-            Ok(())
-        } else {
-            Err(TreeError::new("Node not found".to_string()))
-        }
+    pub fn update(&mut self, id: &NodeId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), TreeError> {
+        let op = self.prepare_update(id, Some(value), timestamp.into())?;
+        self.apply_op(&op)?;
+        Ok(())
     }
 
     /// Mark a node as deleted
-    pub fn remove(&mut self, id: &NodeId, timestamp: u64) -> Result<(), TreeError> {
-        if let Some(node) = self.nodes.get_mut(id) {
-            node.mark_deleted(self.replica, timestamp);
-            Ok(())
-        } else {
-            Err(TreeError::new("Node not found".to_string()))
-        }
+    pub fn remove(&mut self, id: &NodeId, timestamp: impl Into<HlcTimestamp>) -> Result<(), TreeError> {
+        let op = self.prepare_update(id, None, timestamp.into())?;
+        self.apply_op(&op)?;
+        Ok(())
     }
 
     /// Move a node to a new parent
-    pub fn move_node(&mut self, id: &NodeId, new_parent_id: &NodeId) -> Result<(), TreeError> {
-        if !self.nodes.contains_key(id) || !self.nodes.contains_key(new_parent_id) {
-            return Err(TreeError::new("Node not found".to_string()));
+    ///
+    /// The move is replicated as a timestamped operation. Moving a node under
+    /// itself or one of its descendants is rejected.
+    pub fn move_node(&mut self, id: &NodeId, new_parent_id: &NodeId, timestamp: impl Into<HlcTimestamp>) -> Result<(), TreeError> {
+        let op = self.prepare_move(id, Some(new_parent_id), timestamp.into())?;
+        self.apply_op(&op)?;
+        Ok(())
+    }
+
+    /// Log of the moves that shaped the hierarchy
+    pub fn move_log(&self) -> &TreeMoveLog {
+        &self.moves
+    }
+
+    /// Check that new children can be placed under a node
+    ///
+    /// Deleted nodes take no new children, so once their deletion is stable
+    /// they stay leaves and can be purged.
+    fn check_parent(&self, parent_id: &NodeId) -> Result<(), TreeError> {
+        match self.nodes.get(parent_id) {
+            None => Err(TreeError::new("Parent node not found".to_string())),
+            Some(parent) if parent.metadata.deleted => Err(TreeError::new("Parent node is deleted".to_string())),
+            Some(_) => Ok(()),
         }
+    }
+
+    /// Timestamp for a local change, ordered after every change of this replica
+    ///
+    /// Only applying the change records it in the version vector.
+    fn next_timestamp(&self, timestamp: HlcTimestamp) -> HlcTimestamp {
+        timestamp.max(HlcTimestamp::from_u64(self.version.get(&self.replica)).successor())
+    }
 
-        // Get the old parent ID first
-        let old_parent_id = self.nodes.get(id).and_then(|node| node.parent.clone());
-        
-        // Remove from old parent
-        if let Some(old_parent_id) = old_parent_id {
-            if let Some(old_parent) = self.nodes.get_mut(&old_parent_id) {
-                old_parent.remove_child(id);
+    /// Timestamp for a local move, also ordered after every move seen so far
+    fn next_move_timestamp(&self, timestamp: HlcTimestamp) -> HlcTimestamp {
+        let timestamp = match self.moves.latest() {
+            Some((latest, _)) => timestamp.max(latest.successor()),
+            None => timestamp,
+        };
+        self.next_timestamp(timestamp)
+    }
+
+    fn prepare_create(&self, value: T, parent: Option<NodeId>, timestamp: HlcTimestamp) -> TreeOp<T> {
+        // Attaching a child is its first move, so it needs a move timestamp
+        let timestamp = match parent {
+            Some(_) => self.next_move_timestamp(timestamp),
+            None => self.next_timestamp(timestamp),
+        };
+        TreeOp::Create {
+            id: NodeId::new(self.replica),
+            value,
+            metadata: NodeMetadata::new(self.replica, timestamp),
+            parent,
+        }
+    }
+
+    /// Update operation replacing the value, or deleting the node for `None`
+    fn prepare_update(&self, id: &NodeId, value: Option<T>, timestamp: HlcTimestamp) -> Result<TreeOp<T>, TreeError> {
+        let node = self.nodes.get(id).ok_or_else(|| TreeError::new("Node not found".to_string()))?;
+        let timestamp = self.next_timestamp(timestamp.max(node.metadata.modified_at.successor()));
+        let mut metadata = node.metadata.clone();
+        let value = match value {
+            Some(value) => {
+                metadata.mark_modified(self.replica, timestamp);
+                value
+            }
+            None => {
+                metadata.mark_deleted(self.replica, timestamp);
+                node.value.clone()
             }
+        };
+        Ok(TreeOp::Update { id: id.clone(), value, metadata })
+    }
+
+    /// Move operation placing a node under a new parent, or at the root for `None`
+    fn prepare_move(&self, id: &NodeId, new_parent_id: Option<&NodeId>, timestamp: HlcTimestamp) -> Result<TreeOp<T>, TreeError> {
+        if !self.nodes.contains_key(id) {
+            return Err(TreeError::new("Node not found".to_string()));
         }
-        
-        // Update the node's parent reference
-        if let Some(node) = self.nodes.get_mut(id) {
-            node.parent = Some(new_parent_id.clone());
+        if let Some(new_parent_id) = new_parent_id {
+            self.check_parent(new_parent_id)?;
         }
-        
-        // Add to new parent
-        if let Some(new_parent) = self.nodes.get_mut(new_parent_id) {
-            new_parent.add_child(id.clone());
+
+        let timestamp = self.next_move_timestamp(timestamp);
+        let op = MoveOp { timestamp: (timestamp, self.replica), child: id.clone(), parent: new_parent_id.cloned() };
+        if !TreeMoveLog::is_valid(&op, &self.nodes) {
+            return Err(TreeError::new("Moving a node under its own descendant would create a cycle".to_string()));
+        }
+        Ok(TreeOp::Move(op))
+    }
+
+    /// Update operation bringing a deleted node back
+    fn prepare_restore(&self, id: &NodeId, timestamp: HlcTimestamp) -> Result<TreeOp<T>, TreeError> {
+        let node = self.nodes.get(id).ok_or_else(|| TreeError::new("Node not found".to_string()))?;
+        let timestamp = self.next_timestamp(timestamp.max(node.metadata.modified_at.successor()));
+        let mut metadata = node.metadata.clone();
+        metadata.deleted = false;
+        metadata.mark_modified(self.replica, timestamp);
+        Ok(TreeOp::Update { id: id.clone(), value: node.value.clone(), metadata })
+    }
+
+    fn apply_op(&mut self, op: &TreeOp<T>) -> Result<bool, TreeError> {
+        match op {
+            TreeOp::Create { id, value, metadata, parent } => {
+                let creator = metadata.last_modified_by;
+                // Seen before but gone, so it was purged
+                if self.nodes.contains_key(id) || self.version.includes(&creator, metadata.created_at.as_u64()) {
+                    return Ok(false);
+                }
+                let node = TreeNode {
+                    id: id.clone(),
+                    value: value.clone(),
+                    metadata: metadata.clone(),
+                    parent: None,
+                    children: Vec::new(),
+                };
+                self.nodes.insert(id.clone(), node);
+                if let Some(parent) = parent {
+                    let attach = MoveOp { timestamp: (metadata.created_at, creator), child: id.clone(), parent: Some(parent.clone()) };
+                    self.moves.apply(attach, &mut self.nodes);
+                }
+                self.version.observe(creator, metadata.created_at.as_u64());
+                Ok(true)
+            }
+            TreeOp::Update { id, value, metadata } => {
+                let stamp = metadata.modified_at.as_u64();
+                let Some(node) = self.nodes.get_mut(id) else {
+                    if metadata.deleted && self.version.includes(&metadata.last_modified_by, stamp) {
+                        return Ok(false);
+                    }
+                    return Err(TreeError::new("Node not found".to_string()));
+                };
+                self.version.observe(metadata.last_modified_by, stamp);
+                if metadata.modified_at <= node.metadata.modified_at {
+                    return Ok(false);
+                }
+                node.value = value.clone();
+                node.metadata = metadata.clone();
+                Ok(true)
+            }
+            TreeOp::Move(op) => {
+                let purged = !self.nodes.contains_key(&op.child)
+                    || op.parent.as_ref().is_some_and(|parent| !self.nodes.contains_key(parent));
+                if purged || self.moves.contains(&op.timestamp) {
+                    return Ok(false);
+                }
+                self.version.observe(op.timestamp.1, op.timestamp.0.as_u64());
+                Ok(self.moves.apply(op.clone(), &mut self.nodes))
+            }
         }
-        
-        Ok(())
     }
 
     /// Get a node by ID
@@ -195,6 +318,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> AddWinsTree<T> {
     /// Clear all nodes
     pub fn clear(&mut self) {
         self.nodes.clear();
+        self.moves = TreeMoveLog::new();
     }
 
     /// Get the configuration
@@ -213,21 +337,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for AddWinsTree<T> {
     type Error = TreeError;
 
     fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        for (id, node) in &other.nodes {
-            match self.nodes.get(id) {
-                Some(existing) => {
-                    // Conflict resolution: later timestamp wins
-                    if node.metadata.modified_at > existing.metadata.modified_at {
-                        self.nodes.insert(id.clone(), node.clone());
-                    }
-                }
-                None => {
-                    // New node, add it
-                    self.nodes.insert(id.clone(), node.clone());
-                }
-            }
-        }
-        Ok(())
+        self.merge_with_changes(other).map(drop)
     }
 
     fn has_conflict(&self, other: &Self) -> bool {
@@ -244,3 +354,207 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for AddWinsTree<T> {
         false
     }
 }
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for AddWinsTree<T> {
+    type Key = NodeId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<NodeId>, Self::Error> {
+        let snapshot = TreeSnapshot::take(&self.nodes);
+        let mut rewritten = HashSet::new();
+        for (id, node) in &other.nodes {
+            match self.nodes.get_mut(id) {
+                Some(existing) => {
+                    // Conflict resolution: later timestamp wins
+                    if node.metadata.modified_at > existing.metadata.modified_at {
+                        if existing.value != node.value {
+                            rewritten.insert(id.clone());
+                        }
+                        existing.value = node.value.clone();
+                        existing.metadata = node.metadata.clone();
+                    }
+                }
+                // Deletion already seen, so the node was purged here
+                None if node.metadata.deleted
+                    && self.version.includes(&node.metadata.last_modified_by, node.metadata.modified_at.as_u64()) => {}
+                None => {
+                    // New node, attached by its moves below
+                    let mut node = node.clone();
+                    node.parent = None;
+                    node.children.clear();
+                    self.nodes.insert(id.clone(), node);
+                }
+            }
+        }
+
+        for op in other.moves.ops() {
+            // Every node of other is known by now, so missing ones were purged
+            let purged = !self.nodes.contains_key(&op.child)
+                || op.parent.as_ref().is_some_and(|parent| !self.nodes.contains_key(parent));
+            if !purged {
+                self.moves.apply(op.clone(), &mut self.nodes);
+            }
+        }
+
+        self.version.merge(&other.version);
+        Ok(snapshot.report(&self.nodes, &rewritten))
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> DeltaCrdt for AddWinsTree<T> {
+    fn version_vector(&self) -> VersionVector {
+        self.version.clone()
+    }
+
+    fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+        let nodes: HashMap<_, _> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| {
+                !since.includes(&node.metadata.last_modified_by, node.metadata.modified_at.as_u64())
+            })
+            .map(|(id, node)| (id.clone(), node.clone()))
+            .collect();
+        let moves = self
+            .moves
+            .filtered(|op| !since.includes(&op.timestamp.1, op.timestamp.0.as_u64()));
+        (!nodes.is_empty() || !moves.is_empty()).then(|| Self {
+            config: self.config.clone(),
+            nodes,
+            replica: self.replica,
+            version: self.version.clone(),
+            moves,
+        })
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> OpCrdt for AddWinsTree<T> {
+    type Intent = TreeIntent<T>;
+    type Op = TreeOp<T>;
+    type Error = TreeError;
+
+    fn prepare(&mut self, intent: Self::Intent) -> Result<Self::Op, Self::Error> {
+        match intent {
+            TreeIntent::AddRoot { value, timestamp } => Ok(self.prepare_create(value, None, timestamp)),
+            TreeIntent::AddChild { parent, value, timestamp } => {
+                self.check_parent(&parent)?;
+                Ok(self.prepare_create(value, Some(parent), timestamp))
+            }
+            TreeIntent::Update { id, value, timestamp } => self.prepare_update(&id, Some(value), timestamp),
+            TreeIntent::Remove { id, timestamp } => self.prepare_update(&id, None, timestamp),
+            TreeIntent::Move { id, parent, timestamp } => self.prepare_move(&id, Some(&parent), timestamp),
+        }
+    }
+
+    fn apply(&mut self, op: &Self::Op) -> Result<bool, Self::Error> {
+        self.apply_op(op)
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> Undoable for AddWinsTree<T> {
+    type Edit = TreeIntent<T>;
+    type Change = TreeChange<T>;
+    type Output = TreeOp<T>;
+    type Id = NodeId;
+    type Error = TreeError;
+
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        let previous = match &edit {
+            TreeIntent::Update { id, .. } | TreeIntent::Remove { id, .. } | TreeIntent::Move { id, .. } => {
+                Some(self.nodes.get(id).cloned().ok_or_else(|| TreeError::new("Node not found".to_string()))?)
+            }
+            TreeIntent::AddRoot { .. } | TreeIntent::AddChild { .. } => None,
+        };
+        let removing = matches!(edit, TreeIntent::Remove { .. });
+        let op = self.perform(edit)?;
+        let change = match (&op, previous) {
+            (TreeOp::Create { id, .. }, _) => TreeChange::Added(id.clone()),
+            (TreeOp::Update { id, metadata, .. }, Some(node)) if removing => TreeChange::Removed {
+                id: id.clone(),
+                value: node.value,
+                parent: node.parent,
+                children: node.children,
+                removed_at: metadata.modified_at,
+            },
+            (TreeOp::Update { id, value, .. }, Some(node)) => {
+                TreeChange::Updated { id: id.clone(), previous: node.value, value: value.clone() }
+            }
+            (TreeOp::Move(op), Some(node)) => {
+                TreeChange::Moved { id: op.child.clone(), previous: node.parent, parent: op.parent.clone() }
+            }
+            (_, None) => unreachable!("only creations have no previous node"),
+        };
+        Ok((change, op))
+    }
+
+    /// Remove an added node, restore a removed one, or write back the previous value or parent
+    ///
+    /// Values and parents are only written back while the node still has the
+    /// ones the change gave it, and removed nodes are only restored while this
+    /// replica's deletion is the latest change to them.
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        let id = ids.resolve(change.id());
+        let Some(node) = self.nodes.get(&id) else {
+            return Ok(None);
+        };
+        let timestamp = HlcTimestamp::now();
+        match change {
+            TreeChange::Added(_) if !node.metadata.deleted => self.edit(TreeIntent::Remove { id, timestamp }).map(Some),
+            TreeChange::Removed { removed_at, .. }
+                if node.metadata.deleted
+                    && node.metadata.last_modified_by == self.replica
+                    && node.metadata.modified_at == *removed_at =>
+            {
+                let op = self.prepare_restore(&id, timestamp)?;
+                self.apply_op(&op)?;
+                Ok(Some((TreeChange::Added(id), op)))
+            }
+            TreeChange::Updated { previous, value, .. } if !node.metadata.deleted && node.value == *value => {
+                self.edit(TreeIntent::Update { id, value: previous.clone(), timestamp }).map(Some)
+            }
+            TreeChange::Moved { previous, parent, .. } if node.parent == parent.as_ref().map(|parent| ids.resolve(parent)) => {
+                let current = node.parent.clone();
+                let previous = previous.as_ref().map(|parent| ids.resolve(parent));
+                // The old parent may be gone or have become a descendant since
+                let Ok(op) = self.prepare_move(&id, previous.as_ref(), timestamp) else {
+                    return Ok(None);
+                };
+                self.apply_op(&op)?;
+                Ok(Some((TreeChange::Moved { id, previous: current, parent: previous }, op)))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> GarbageCollect for AddWinsTree<T> {
+    fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+        // Purge leaves first, so that a deleted subtree goes bottom-up
+        let mut purged = HashSet::new();
+        loop {
+            let collectable: Vec<NodeId> = self
+                .nodes
+                .values()
+                .filter(|node| {
+                    node.metadata.deleted
+                        && node.children.is_empty()
+                        && frontier.is_stable_at(&node.metadata.last_modified_by, node.metadata.modified_at)
+                })
+                .map(|node| node.id.clone())
+                .collect();
+            if collectable.is_empty() {
+                break;
+            }
+            for id in collectable {
+                self.nodes.set_parent(&id, None);
+                self.nodes.remove(&id);
+                purged.insert(id);
+            }
+        }
+
+        // Moves from or into purged nodes can no longer change the tree
+        self.moves.retain(|op| {
+            !purged.contains(&op.child) && op.parent.as_ref().is_none_or(|parent| !purged.contains(parent))
+        });
+        purged.len()
+    }
+}
@@ -6,6 +6,7 @@
 pub mod add_wins;
 pub mod config;
 pub mod error;
+pub mod move_log;
 pub mod remove_wins;
 pub mod types;
 
@@ -13,12 +14,13 @@ pub mod types;
 pub use add_wins::AddWinsTree;
 pub use config::{TreeConfig, TreeStrategy};
 pub use error::TreeError;
+pub use move_log::{MoveLog, MoveOp, TreeHierarchy};
 pub use remove_wins::RemoveWinsTree;
-pub use types::{NodeId, NodeMetadata, TreeNode};
+pub use types::{NodeId, NodeMetadata, TreeChange, TreeIntent, TreeMoveLog, TreeNode, TreeOp};
 
 #[cfg(test)]
 mod tests {
-    use super::super::{Mergeable, ReplicaId};
+    use super::super::{DeltaCrdt, GarbageCollect, HlcTimestamp, Mergeable, OpCrdt, ReplicaId, ReportingMerge, StabilityFrontier};
     use super::*;
     use uuid::Uuid;
 
@@ -42,8 +44,8 @@ mod tests {
         let node = TreeNode::new("test_value", replica, timestamp);
 
         assert_eq!(node.value, "test_value");
-        assert_eq!(node.metadata.created_at, timestamp);
-        assert_eq!(node.metadata.modified_at, timestamp);
+        assert_eq!(node.metadata.created_at, HlcTimestamp::from(timestamp));
+        assert_eq!(node.metadata.modified_at, HlcTimestamp::from(timestamp));
         assert_eq!(node.metadata.deleted, false);
         assert_eq!(node.metadata.last_modified_by, replica);
         assert!(node.parent.is_none());
@@ -105,7 +107,7 @@ mod tests {
         let child2_id = tree.add_child(&child1_id, "child2", 3000).unwrap();
 
         // Move child2 to root
-        tree.move_node(&child2_id, &root_id).unwrap();
+        tree.move_node(&child2_id, &root_id, 4000).unwrap();
 
         let child2 = tree.get(&child2_id).unwrap();
         assert_eq!(child2.parent, Some(root_id.clone()));
@@ -115,6 +117,69 @@ mod tests {
 
         let child1 = tree.get(&child1_id).unwrap();
         assert!(!child1.children.contains(&child2_id));
+
+        // A node cannot be moved under its own descendant
+        assert!(tree.move_node(&root_id, &child1_id, 5000).is_err());
+        assert!(tree.get(&root_id).unwrap().parent.is_none());
+    }
+
+    #[test]
+    fn test_concurrent_moves_do_not_create_cycle() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = AddWinsTree::new(replica1);
+        let root_id = tree1.add_root("root", 1000);
+        let a_id = tree1.add_child(&root_id, "a", 1001).unwrap();
+        let b_id = tree1.add_child(&root_id, "b", 1002).unwrap();
+        let mut tree2 = AddWinsTree::new(replica2);
+        tree2.merge(&tree1).unwrap();
+
+        // A under B on one replica, B under A on the other
+        tree1.move_node(&a_id, &b_id, 2000).unwrap();
+        tree2.move_node(&b_id, &a_id, 2001).unwrap();
+
+        let mut merged1 = tree1.clone();
+        merged1.merge(&tree2).unwrap();
+        let mut merged2 = tree2.clone();
+        merged2.merge(&tree1).unwrap();
+
+        // The earlier move wins, the later one is skipped on both replicas
+        for tree in [&merged1, &merged2] {
+            assert_eq!(tree.get(&a_id).unwrap().parent, Some(b_id.clone()));
+            assert_eq!(tree.get(&b_id).unwrap().parent, Some(root_id.clone()));
+            assert_eq!(tree.descendants(&root_id).len(), 2);
+        }
+        assert_eq!(merged1.get(&b_id), merged2.get(&b_id));
+        assert_eq!(merged1.get(&root_id), merged2.get(&root_id));
+    }
+
+    #[test]
+    fn test_late_move_is_replayed_in_timestamp_order() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = RemoveWinsTree::new(replica1);
+        let root_id = tree1.add_root("root", 1000);
+        let a_id = tree1.add_child(&root_id, "a", 1001).unwrap();
+        let b_id = tree1.add_child(&root_id, "b", 1002).unwrap();
+        let c_id = tree1.add_child(&root_id, "c", 1003).unwrap();
+        let mut tree2 = RemoveWinsTree::new(replica2);
+        tree2.merge(&tree1).unwrap();
+
+        // Replica 2 moves C later, but its move reaches replica 1 first
+        tree1.move_node(&c_id, &a_id, 2000).unwrap();
+        tree2.move_node(&c_id, &b_id, 3000).unwrap();
+        let mut merged = RemoveWinsTree::new(replica1);
+        merged.merge(&tree2).unwrap();
+        merged.merge(&tree1).unwrap();
+        tree1.merge(&tree2).unwrap();
+
+        for tree in [&merged, &tree1] {
+            assert_eq!(tree.get(&c_id).unwrap().parent, Some(b_id.clone()));
+            assert!(tree.get(&a_id).unwrap().children.is_empty());
+        }
+        assert_eq!(merged.move_log(), tree1.move_log());
     }
 
     #[test]
@@ -158,6 +223,92 @@ mod tests {
         assert!(tree1.contains(&root2_id));
     }
 
+    #[test]
+    fn test_tree_merge_with_changes() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = AddWinsTree::new(replica1);
+        let root = tree1.add_root("root", 1000);
+        let a = tree1.add_child(&root, "a", 1001).unwrap();
+        let b = tree1.add_child(&root, "b", 1002).unwrap();
+        let mut tree2 = AddWinsTree::new(replica2);
+        tree2.merge(&tree1).unwrap();
+
+        tree2.move_node(&b, &a, 2000).unwrap();
+        tree2.update(&root, "renamed", 2001).unwrap();
+        tree2.remove(&a, 2002).unwrap();
+        let c = tree2.add_child(&root, "c", 2003).unwrap();
+
+        let mut report = tree1.merge_with_changes(&tree2).unwrap();
+        report.updated.sort_by_key(|id| id.id);
+        let mut updated = vec![root, b];
+        updated.sort_by_key(|id| id.id);
+        assert_eq!(report.inserted, vec![c]);
+        assert_eq!(report.updated, updated);
+        assert_eq!(report.removed, vec![a]);
+        assert!(tree1.merge_with_changes(&tree2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_tree_delta_links_new_child_to_existing_parent() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = AddWinsTree::new(replica1);
+        let root_id = tree1.add_root("root", 1000);
+        let mut tree2 = AddWinsTree::new(replica2);
+        tree2.merge(&tree1).unwrap();
+
+        let child_id = tree1.add_child(&root_id, "child", 1001).unwrap();
+        let delta = tree1.delta_since(&tree2.version_vector()).unwrap();
+        assert_eq!(delta.all_nodes().len(), 1);
+
+        tree2.apply_delta(&delta).unwrap();
+        let children = tree2.children(&root_id);
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].id, child_id);
+        assert!(tree1.delta_since(&tree2.version_vector()).is_none());
+
+        // A move alone travels as a delta without any node
+        let other_id = tree1.add_root("other", 1002);
+        tree2.apply_delta(&tree1.delta_since(&tree2.version_vector()).unwrap()).unwrap();
+        tree1.move_node(&child_id, &other_id, 1003).unwrap();
+        let delta = tree1.delta_since(&tree2.version_vector()).unwrap();
+        assert!(delta.all_nodes().is_empty());
+        tree2.apply_delta(&delta).unwrap();
+        assert_eq!(tree2.get(&child_id).unwrap().parent, Some(other_id));
+        assert!(tree2.children(&root_id).is_empty());
+    }
+
+    #[test]
+    fn test_tree_gc_purges_stable_deleted_subtrees() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = AddWinsTree::new(replica1);
+        let root_id = tree1.add_root("root", 1000);
+        let folder_id = tree1.add_child(&root_id, "folder", 1001).unwrap();
+        let file_id = tree1.add_child(&folder_id, "file", 1002).unwrap();
+        tree1.remove(&file_id, 2000).unwrap();
+        tree1.remove(&folder_id, 2001).unwrap();
+        assert!(tree1.add_child(&folder_id, "late", 2002).is_err());
+
+        let mut tree2 = AddWinsTree::new(replica2);
+        tree2.merge(&tree1).unwrap();
+        let frontier = StabilityFrontier::new(tree2.version_vector());
+        assert_eq!(tree1.gc(&frontier), 2);
+        assert!(!tree1.contains(&folder_id));
+        assert!(tree1.get(&root_id).unwrap().children.is_empty());
+        assert_eq!(tree1.move_log().len(), 0);
+
+        // A replica that has not collected yet does not bring the nodes back
+        tree1.merge(&tree2).unwrap();
+        assert!(!tree1.contains(&folder_id));
+        assert!(tree1.children(&root_id).is_empty());
+        assert_eq!(tree1.move_log().len(), 0);
+    }
+
     #[test]
     fn test_tree_configuration() {
         let replica = create_replica(1);
@@ -173,4 +324,36 @@ mod tests {
         assert_eq!(tree.config().max_depth, Some(5));
         assert_eq!(tree.config().max_children, Some(10));
     }
+
+    #[test]
+    fn test_tree_ops_converge_in_any_order() {
+        let replica1 = create_replica(1);
+        let replica2 = create_replica(2);
+
+        let mut tree1 = AddWinsTree::new(replica1);
+        let mut ops = vec![tree1.perform(TreeIntent::AddRoot { value: "root", timestamp: 1000.into() }).unwrap()];
+        let TreeOp::Create { id: root_id, .. } = ops[0].clone() else {
+            panic!("adding a root creates a node");
+        };
+        ops.push(tree1.perform(TreeIntent::AddChild { parent: root_id.clone(), value: "a", timestamp: 1001.into() }).unwrap());
+        ops.push(tree1.perform(TreeIntent::AddChild { parent: root_id.clone(), value: "b", timestamp: 1002.into() }).unwrap());
+        let (TreeOp::Create { id: a_id, .. }, TreeOp::Create { id: b_id, .. }) = (ops[1].clone(), ops[2].clone()) else {
+            panic!("adding a child creates a node");
+        };
+        ops.push(tree1.perform(TreeIntent::Move { id: a_id.clone(), parent: b_id.clone(), timestamp: 2000.into() }).unwrap());
+        ops.push(tree1.perform(TreeIntent::Update { id: a_id.clone(), value: "renamed", timestamp: 2001.into() }).unwrap());
+
+        // Creations come first, then the rest in reverse and again
+        let mut tree2 = AddWinsTree::new(replica2);
+        for op in ops[..3].iter().chain(ops[3..].iter().rev()).chain(&ops) {
+            tree2.apply(op).unwrap();
+        }
+
+        let a = tree2.get(&a_id).unwrap();
+        assert_eq!(a.value, "renamed");
+        assert_eq!(a.parent, Some(b_id));
+        assert_eq!(tree2.get(&a_id), tree1.get(&a_id));
+        assert_eq!(tree2.move_log(), tree1.move_log());
+        assert!(!tree2.apply(&ops[4]).unwrap());
+    }
 }
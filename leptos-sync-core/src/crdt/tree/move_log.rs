@@ -0,0 +1,213 @@
+//! Replicated move operation for trees
+//!
+//! Implements the move operation of Kleppmann et al., "A highly-available move
+//! operation for replicated trees". Every change of a node's parent, including
+//! attaching a new node, is a timestamped [`MoveOp`]. Replicas apply operations
+//! in timestamp order: an operation that arrives late undoes the newer ones,
+//! is applied, and the newer ones are redone on top. A move that would put a
+//! node under one of its own descendants is skipped, so concurrent moves can
+//! never create a cycle and all replicas end up with the same tree.
+
+use serde::{Deserialize, Serialize};
+
+/// Parent links of a tree that a [`MoveLog`] can rewire
+pub trait TreeHierarchy<Id> {
+    /// Whether the node exists
+    fn contains_node(&self, id: &Id) -> bool;
+
+    /// Current parent of a node, `None` for roots and unknown nodes
+    fn parent_of(&self, id: &Id) -> Option<Id>;
+
+    /// Attach a node under a new parent, or detach it for `None`
+    fn set_parent(&mut self, id: &Id, parent: Option<Id>);
+}
+
+/// Move of `child` under `parent` (`None` makes it a root)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveOp<Id, Ts> {
+    /// Unique, totally ordered timestamp of the operation
+    pub timestamp: Ts,
+    /// Node being moved
+    pub child: Id,
+    /// New parent
+    pub parent: Option<Id>,
+}
+
+/// Applied operation together with the parent it replaced, for undo
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct LogEntry<Id, Ts> {
+    op: MoveOp<Id, Ts>,
+    old_parent: Option<Id>,
+}
+
+/// Log of move operations, in timestamp order
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MoveLog<Id, Ts> {
+    entries: Vec<LogEntry<Id, Ts>>,
+}
+
+impl<Id, Ts> Default for MoveLog<Id, Ts> {
+    fn default() -> Self {
+        Self { entries: Vec::new() }
+    }
+}
+
+impl<Id: Clone + PartialEq, Ts: Clone + Ord> MoveLog<Id, Ts> {
+    /// Create an empty log
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply an operation, returning `false` if it was already in the log
+    pub fn apply<H: TreeHierarchy<Id>>(&mut self, op: MoveOp<Id, Ts>, tree: &mut H) -> bool {
+        let index = self.entries.partition_point(|entry| entry.op.timestamp < op.timestamp);
+        if self.entries.get(index).is_some_and(|entry| entry.op.timestamp == op.timestamp) {
+            return false;
+        }
+
+        // Undo everything newer, newest first
+        for entry in self.entries[index..].iter().rev() {
+            tree.set_parent(&entry.op.child, entry.old_parent.clone());
+        }
+        let entry = Self::do_op(op, tree);
+        self.entries.insert(index, entry);
+        // Redo on top, each operation re-checked against the new state
+        for entry in &mut self.entries[index + 1..] {
+            *entry = Self::do_op(entry.op.clone(), tree);
+        }
+        true
+    }
+
+    /// Whether applying `op` now would keep the tree acyclic
+    pub fn is_valid<H: TreeHierarchy<Id>>(op: &MoveOp<Id, Ts>, tree: &H) -> bool {
+        if !tree.contains_node(&op.child) {
+            return false;
+        }
+        match &op.parent {
+            None => true,
+            Some(parent) => tree.contains_node(parent) && !Self::is_ancestor(&op.child, parent, tree),
+        }
+    }
+
+    /// All operations, oldest first
+    pub fn ops(&self) -> impl Iterator<Item = &MoveOp<Id, Ts>> {
+        self.entries.iter().map(|entry| &entry.op)
+    }
+
+    /// Timestamp of the newest operation
+    pub fn latest(&self) -> Option<&Ts> {
+        self.entries.last().map(|entry| &entry.op.timestamp)
+    }
+
+    /// Whether an operation with this timestamp has been applied
+    pub fn contains(&self, timestamp: &Ts) -> bool {
+        self.entries.binary_search_by(|entry| entry.op.timestamp.cmp(timestamp)).is_ok()
+    }
+
+    /// Copy of the log holding only the operations matching `keep`
+    pub fn filtered(&self, mut keep: impl FnMut(&MoveOp<Id, Ts>) -> bool) -> Self {
+        Self {
+            entries: self.entries.iter().filter(|entry| keep(&entry.op)).cloned().collect(),
+        }
+    }
+
+    /// Drop the operations not matching `keep`
+    ///
+    /// Only safe for operations that can no longer change the tree, such as
+    /// moves of nodes that were purged.
+    pub fn retain(&mut self, mut keep: impl FnMut(&MoveOp<Id, Ts>) -> bool) {
+        self.entries.retain(|entry| keep(&entry.op));
+    }
+
+    /// Number of operations in the log
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check if the log is empty
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn do_op<H: TreeHierarchy<Id>>(op: MoveOp<Id, Ts>, tree: &mut H) -> LogEntry<Id, Ts> {
+        let old_parent = tree.parent_of(&op.child);
+        if Self::is_valid(&op, tree) {
+            tree.set_parent(&op.child, op.parent.clone());
+        }
+        LogEntry { op, old_parent }
+    }
+
+    /// Whether `ancestor` is `node` or one of its ancestors
+    fn is_ancestor<H: TreeHierarchy<Id>>(ancestor: &Id, node: &Id, tree: &H) -> bool {
+        // Every applied move keeps the tree acyclic, so this walk reaches a root
+        let mut current = Some(node.clone());
+        while let Some(id) = current {
+            if id == *ancestor {
+                return true;
+            }
+            current = tree.parent_of(&id);
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    /// Minimal hierarchy: node -> parent
+    #[derive(Default)]
+    struct Parents(HashMap<u32, Option<u32>>);
+
+    impl TreeHierarchy<u32> for Parents {
+        fn contains_node(&self, id: &u32) -> bool {
+            self.0.contains_key(id)
+        }
+
+        fn parent_of(&self, id: &u32) -> Option<u32> {
+            self.0.get(id).copied().flatten()
+        }
+
+        fn set_parent(&mut self, id: &u32, parent: Option<u32>) {
+            if let Some(current) = self.0.get_mut(id) {
+                *current = parent;
+            }
+        }
+    }
+
+    fn op(timestamp: u64, child: u32, parent: Option<u32>) -> MoveOp<u32, u64> {
+        MoveOp { timestamp, child, parent }
+    }
+
+    #[test]
+    fn test_concurrent_moves_never_create_a_cycle() {
+        // A and B are both roots; one replica moves A under B, the other B under A
+        let ops = [op(1, 1, Some(2)), op(2, 2, Some(1))];
+
+        for order in [[0, 1], [1, 0]] {
+            let mut tree = Parents([(1, None), (2, None)].into_iter().collect());
+            let mut log = MoveLog::new();
+            for index in order {
+                log.apply(ops[index].clone(), &mut tree);
+            }
+            // The older move wins, the newer one would close a cycle and is skipped
+            assert_eq!(tree.parent_of(&1), Some(2));
+            assert_eq!(tree.parent_of(&2), None);
+        }
+    }
+
+    #[test]
+    fn test_late_operation_is_applied_underneath_newer_ones() {
+        let mut tree = Parents([(1, None), (2, None), (3, None)].into_iter().collect());
+        let mut log = MoveLog::new();
+        log.apply(op(5, 3, Some(2)), &mut tree);
+        log.apply(op(1, 3, Some(1)), &mut tree);
+
+        // The newer move still decides where node 3 ends up
+        assert_eq!(tree.parent_of(&3), Some(2));
+        assert!(!log.apply(op(1, 3, Some(1)), &mut tree));
+        assert_eq!(log.len(), 2);
+        assert_eq!(log.latest(), Some(&5));
+    }
+}
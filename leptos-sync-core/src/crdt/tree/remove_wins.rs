@@ -1,9 +1,10 @@
 //! Remove-Wins Tree CRDT implementation
 
-use super::super::{CRDT, Mergeable, ReplicaId};
-use super::{config::{TreeConfig, TreeStrategy}, error::TreeError, types::{NodeId, TreeNode}};
+use super::super::{CRDT, HlcTimestamp, MergeReport, Mergeable, ReplicaId, ReportingMerge};
+use super::super::undo::{IdMap, Reverted, Undoable};
+use super::{config::{TreeConfig, TreeStrategy}, error::TreeError, move_log::MoveOp, types::{NodeId, TreeChange, TreeIntent, TreeMoveLog, TreeNode, TreeSnapshot}};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 /// Remove-Wins Tree CRDT implementation
 /// 
@@ -17,6 +18,9 @@ pub struct RemoveWinsTree<T> {
     nodes: HashMap<NodeId, TreeNode<T>>,
     /// Replica ID for this instance
     replica: ReplicaId,
+    /// Timestamped parent changes, the source of truth for the hierarchy
+    #[serde(default)]
+    moves: TreeMoveLog,
 }
 
 impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
@@ -31,6 +35,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
             },
             nodes: HashMap::new(),
             replica,
+            moves: TreeMoveLog::new(),
         }
     }
 
@@ -40,11 +45,12 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
             config,
             nodes: HashMap::new(),
             replica,
+            moves: TreeMoveLog::new(),
         }
     }
 
     /// Add a root node to the tree
-    pub fn add_root(&mut self, value: T, timestamp: u64) -> NodeId {
+    pub fn add_root(&mut self, value: T, timestamp: impl Into<HlcTimestamp>) -> NodeId {
         let node = TreeNode::new(value, self.replica, timestamp);
         let id = node.id.clone();
         self.nodes.insert(id.clone(), node);
@@ -52,27 +58,24 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
     }
 
     /// Add a child node
-    pub fn add_child(&mut self, parent_id: &NodeId, value: T, timestamp: u64) -> Result<NodeId, TreeError> {
+    pub fn add_child(&mut self, parent_id: &NodeId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<NodeId, TreeError> {
         if !self.nodes.contains_key(parent_id) {
             return Err(TreeError::new("Parent node not found".to_string()));
         }
 
-        let node = TreeNode::new_child(value, self.replica, timestamp, parent_id.clone());
+        let timestamp = self.next_timestamp(timestamp.into());
+        let node = TreeNode::new(value, self.replica, timestamp);
         let id = node.id.clone();
-        
-        // Add the child node
         self.nodes.insert(id.clone(), node);
-        
-        // Update parent's children list
-        if let Some(parent) = self.nodes.get_mut(parent_id) {
-            parent.add_child(id.clone());
-        }
-        
+
+        // Attaching the child is the first move of the node
+        let op = MoveOp { timestamp: (timestamp, self.replica), child: id.clone(), parent: Some(parent_id.clone()) };
+        self.moves.apply(op, &mut self.nodes);
         Ok(id)
     }
 
     /// Update an existing node
-    pub fn update(&mut self, id: &NodeId, value: T, timestamp: u64) -> Result<(), TreeError> {
+    pub fn update(&mut self, id: &NodeId, value: T, timestamp: impl Into<HlcTimestamp>) -> Result<(), TreeError> {
         if let Some(node) = self.nodes.get_mut(id) {
             node.value = value;
             node.mark_modified(self.replica, timestamp);
@@ -103,34 +106,45 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
     }
 
     /// Move a node to a new parent
-    pub fn move_node(&mut self, id: &NodeId, new_parent_id: &NodeId) -> Result<(), TreeError> {
-        if !self.nodes.contains_key(id) || !self.nodes.contains_key(new_parent_id) {
+    ///
+    /// The move is replicated as a timestamped operation. Moving a node under
+    /// itself or one of its descendants is rejected.
+    pub fn move_node(&mut self, id: &NodeId, new_parent_id: &NodeId, timestamp: impl Into<HlcTimestamp>) -> Result<(), TreeError> {
+        self.move_under(id, Some(new_parent_id), timestamp.into())
+    }
+
+    /// Move a node under a new parent, or to the root for `None`
+    fn move_under(&mut self, id: &NodeId, new_parent_id: Option<&NodeId>, timestamp: HlcTimestamp) -> Result<(), TreeError> {
+        if !self.nodes.contains_key(id) || new_parent_id.is_some_and(|parent| !self.nodes.contains_key(parent)) {
             return Err(TreeError::new("Node not found".to_string()));
         }
 
-        // Get the old parent ID first
-        let old_parent_id = self.nodes.get(id).and_then(|node| node.parent.clone());
-        
-        // Remove from old parent
-        if let Some(old_parent_id) = old_parent_id {
-            if let Some(old_parent) = self.nodes.get_mut(&old_parent_id) {
-                old_parent.remove_child(id);
-            }
-        }
-        
-        // Update the node's parent reference
-        if let Some(node) = self.nodes.get_mut(id) {
-            node.parent = Some(new_parent_id.clone());
-        }
-        
-        // Add to new parent
-        if let Some(new_parent) = self.nodes.get_mut(new_parent_id) {
-            new_parent.add_child(id.clone());
+        let timestamp = self.next_timestamp(timestamp);
+        let op = MoveOp { timestamp: (timestamp, self.replica), child: id.clone(), parent: new_parent_id.cloned() };
+        if !TreeMoveLog::is_valid(&op, &self.nodes) {
+            return Err(TreeError::new("Moving a node under its own descendant would create a cycle".to_string()));
         }
-        
+        self.moves.apply(op, &mut self.nodes);
         Ok(())
     }
 
+    fn node(&self, id: &NodeId) -> Result<&TreeNode<T>, TreeError> {
+        self.nodes.get(id).ok_or_else(|| TreeError::new("Node not found".to_string()))
+    }
+
+    /// Log of the moves that shaped the hierarchy
+    pub fn move_log(&self) -> &TreeMoveLog {
+        &self.moves
+    }
+
+    /// Timestamp for a local move, ordered after every move seen so far
+    fn next_timestamp(&self, timestamp: HlcTimestamp) -> HlcTimestamp {
+        match self.moves.latest() {
+            Some((latest, _)) => timestamp.max(latest.successor()),
+            None => timestamp,
+        }
+    }
+
     /// Get a node by ID
     pub fn get(&self, id: &NodeId) -> Option<&TreeNode<T>> {
         self.nodes.get(id)
@@ -197,6 +211,7 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> RemoveWinsTree<T> {
     /// Clear all nodes
     pub fn clear(&mut self) {
         self.nodes.clear();
+        self.moves = TreeMoveLog::new();
     }
 }
 
@@ -206,25 +221,91 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> CRDT for RemoveWinsTree<T> {
     }
 }
 
-impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsTree<T> {
+impl<T: Clone + PartialEq + Eq + Send + Sync> Undoable for RemoveWinsTree<T> {
+    type Edit = TreeIntent<T>;
+    type Change = TreeChange<T>;
+    type Output = NodeId;
+    type Id = NodeId;
     type Error = TreeError;
 
-    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
-        for (id, node) in &other.nodes {
-            match self.nodes.get(id) {
-                Some(existing) => {
-                    // Conflict resolution: later timestamp wins
-                    if node.metadata.modified_at > existing.metadata.modified_at {
-                        self.nodes.insert(id.clone(), node.clone());
-                    }
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error> {
+        let change = match edit {
+            TreeIntent::AddRoot { value, timestamp } => TreeChange::Added(self.add_root(value, timestamp)),
+            TreeIntent::AddChild { parent, value, timestamp } => TreeChange::Added(self.add_child(&parent, value, timestamp)?),
+            TreeIntent::Update { id, value, timestamp } => {
+                let previous = self.node(&id)?.value.clone();
+                self.update(&id, value.clone(), timestamp)?;
+                TreeChange::Updated { id, previous, value }
+            }
+            TreeIntent::Remove { id, .. } => {
+                let node = self.node(&id)?.clone();
+                self.remove(&id)?;
+                TreeChange::Removed {
+                    id,
+                    value: node.value,
+                    parent: node.parent,
+                    children: node.children,
+                    removed_at: node.metadata.modified_at,
                 }
-                None => {
-                    // New node, add it
-                    self.nodes.insert(id.clone(), node.clone());
+            }
+            TreeIntent::Move { id, parent, timestamp } => {
+                let previous = self.node(&id)?.parent.clone();
+                self.move_node(&id, &parent, timestamp)?;
+                TreeChange::Moved { id, previous, parent: Some(parent) }
+            }
+        };
+        let id = change.id().clone();
+        Ok((change, id))
+    }
+
+    /// Remove an added node, add a removed one again, or write back the previous value or parent
+    ///
+    /// Removed nodes are gone, so they come back as new nodes under their old
+    /// parent, taking back the children that were still attached to them.
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error> {
+        let id = ids.resolve(change.id());
+        let timestamp = HlcTimestamp::now();
+        let Some(node) = self.nodes.get(&id) else {
+            let TreeChange::Removed { value, parent, children, .. } = change else {
+                return Ok(None);
+            };
+            let restored = match parent.as_ref().map(|parent| ids.resolve(parent)) {
+                Some(parent) if self.nodes.contains_key(&parent) => self.add_child(&parent, value.clone(), timestamp)?,
+                Some(_) => return Ok(None),
+                None => self.add_root(value.clone(), timestamp),
+            };
+            for child in children.iter().map(|child| ids.resolve(child)) {
+                if self.nodes.get(&child).is_some_and(|node| node.parent.as_ref() == Some(&id)) {
+                    self.move_under(&child, Some(&restored), timestamp)?;
                 }
             }
+            ids.insert(id, restored.clone());
+            return Ok(Some((TreeChange::Added(restored.clone()), restored)));
+        };
+        match change {
+            TreeChange::Added(_) => self.edit(TreeIntent::Remove { id, timestamp }).map(Some),
+            TreeChange::Updated { previous, value, .. } if node.value == *value => {
+                self.edit(TreeIntent::Update { id, value: previous.clone(), timestamp }).map(Some)
+            }
+            TreeChange::Moved { previous, parent, .. } if node.parent == parent.as_ref().map(|parent| ids.resolve(parent)) => {
+                let current = node.parent.clone();
+                let previous = previous.as_ref().map(|parent| ids.resolve(parent));
+                // The old parent may be gone or have become a descendant since
+                if self.move_under(&id, previous.as_ref(), timestamp).is_err() {
+                    return Ok(None);
+                }
+                Ok(Some((TreeChange::Moved { id: id.clone(), previous: current, parent: previous }, id)))
+            }
+            _ => Ok(None),
         }
-        Ok(())
+    }
+}
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsTree<T> {
+    type Error = TreeError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        self.merge_with_changes(other).map(drop)
     }
 
     fn has_conflict(&self, other: &Self) -> bool {
@@ -241,3 +322,38 @@ impl<T: Clone + PartialEq + Eq + Send + Sync> Mergeable for RemoveWinsTree<T> {
         false
     }
 }
+
+impl<T: Clone + PartialEq + Eq + Send + Sync> ReportingMerge for RemoveWinsTree<T> {
+    type Key = NodeId;
+
+    fn merge_with_changes(&mut self, other: &Self) -> Result<MergeReport<NodeId>, Self::Error> {
+        let snapshot = TreeSnapshot::take(&self.nodes);
+        let mut rewritten = HashSet::new();
+        for (id, node) in &other.nodes {
+            match self.nodes.get_mut(id) {
+                Some(existing) => {
+                    // Conflict resolution: later timestamp wins
+                    if node.metadata.modified_at > existing.metadata.modified_at {
+                        if existing.value != node.value {
+                            rewritten.insert(id.clone());
+                        }
+                        existing.value = node.value.clone();
+                        existing.metadata = node.metadata.clone();
+                    }
+                }
+                None => {
+                    // New node, attached by its moves below
+                    let mut node = node.clone();
+                    node.parent = None;
+                    node.children.clear();
+                    self.nodes.insert(id.clone(), node);
+                }
+            }
+        }
+
+        for op in other.moves.ops() {
+            self.moves.apply(op.clone(), &mut self.nodes);
+        }
+        Ok(snapshot.report(&self.nodes, &rewritten))
+    }
+}
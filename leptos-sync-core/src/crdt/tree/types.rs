@@ -1,7 +1,9 @@
 //! Core types for tree CRDTs
 
-use super::super::ReplicaId;
+use super::super::{HlcTimestamp, MergeReport, OpId, Operation, ReplicaId};
+use super::move_log::{MoveLog, MoveOp, TreeHierarchy};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
 /// Unique identifier for a tree node
@@ -32,9 +34,9 @@ impl NodeId {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct NodeMetadata {
     /// When the node was created
-    pub created_at: u64,
+    pub created_at: HlcTimestamp,
     /// When the node was last modified
-    pub modified_at: u64,
+    pub modified_at: HlcTimestamp,
     /// Whether the node is marked as deleted
     pub deleted: bool,
     /// Replica that last modified the node
@@ -43,7 +45,8 @@ pub struct NodeMetadata {
 
 impl NodeMetadata {
     /// Create new metadata
-    pub fn new(replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
+        let timestamp = timestamp.into();
         Self {
             created_at: timestamp,
             modified_at: timestamp,
@@ -53,13 +56,16 @@ impl NodeMetadata {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
-        self.modified_at = timestamp;
+    ///
+    /// The modification always orders after the previous one, even if the
+    /// supplied timestamp is behind it.
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
+        self.modified_at = timestamp.into().max(self.modified_at.successor());
         self.last_modified_by = replica;
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.deleted = true;
         self.mark_modified(replica, timestamp);
     }
@@ -82,7 +88,7 @@ pub struct TreeNode<T> {
 
 impl<T> TreeNode<T> {
     /// Create a new tree node
-    pub fn new(value: T, replica: ReplicaId, timestamp: u64) -> Self {
+    pub fn new(value: T, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) -> Self {
         Self {
             id: NodeId::new(replica),
             value,
@@ -93,7 +99,7 @@ impl<T> TreeNode<T> {
     }
 
     /// Create a child node
-    pub fn new_child(value: T, replica: ReplicaId, timestamp: u64, parent: NodeId) -> Self {
+    pub fn new_child(value: T, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>, parent: NodeId) -> Self {
         Self {
             id: NodeId::new(replica),
             value,
@@ -104,12 +110,12 @@ impl<T> TreeNode<T> {
     }
 
     /// Mark as modified
-    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_modified(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_modified(replica, timestamp);
     }
 
     /// Mark as deleted
-    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: u64) {
+    pub fn mark_deleted(&mut self, replica: ReplicaId, timestamp: impl Into<HlcTimestamp>) {
         self.metadata.mark_deleted(replica, timestamp);
     }
 
@@ -128,3 +134,125 @@ impl<T> TreeNode<T> {
         }
     }
 }
+
+/// Move log of the tree CRDTs, ordered by timestamp and then replica
+pub type TreeMoveLog = MoveLog<NodeId, (HlcTimestamp, ReplicaId)>;
+
+/// Local change to a tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeIntent<T> {
+    /// Add a root node
+    AddRoot { value: T, timestamp: HlcTimestamp },
+    /// Add a node under `parent`
+    AddChild { parent: NodeId, value: T, timestamp: HlcTimestamp },
+    /// Replace the value of a node
+    Update { id: NodeId, value: T, timestamp: HlcTimestamp },
+    /// Mark a node as deleted
+    Remove { id: NodeId, timestamp: HlcTimestamp },
+    /// Move a node under a new parent
+    Move { id: NodeId, parent: NodeId, timestamp: HlcTimestamp },
+}
+
+/// Local change to a tree recorded for undo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeChange<T> {
+    /// A node was added
+    Added(NodeId),
+    /// A node was removed, with what it takes to bring it back
+    Removed { id: NodeId, value: T, parent: Option<NodeId>, children: Vec<NodeId>, removed_at: HlcTimestamp },
+    /// A node was overwritten, with its previous and new value
+    Updated { id: NodeId, previous: T, value: T },
+    /// A node was moved, with its previous and new parent
+    Moved { id: NodeId, previous: Option<NodeId>, parent: Option<NodeId> },
+}
+
+impl<T> TreeChange<T> {
+    /// Node the change applies to
+    pub fn id(&self) -> &NodeId {
+        match self {
+            TreeChange::Added(id)
+            | TreeChange::Removed { id, .. }
+            | TreeChange::Updated { id, .. }
+            | TreeChange::Moved { id, .. } => id,
+        }
+    }
+}
+
+/// Operation replicated by a tree
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TreeOp<T> {
+    /// Create a node, attached under `parent` if given
+    Create { id: NodeId, value: T, metadata: NodeMetadata, parent: Option<NodeId> },
+    /// Overwrite the value and metadata of a node, if newer
+    Update { id: NodeId, value: T, metadata: NodeMetadata },
+    /// Change the parent of a node
+    Move(MoveOp<NodeId, (HlcTimestamp, ReplicaId)>),
+}
+
+impl<T: Clone + Send + Sync> Operation for TreeOp<T> {
+    fn id(&self) -> OpId {
+        match self {
+            TreeOp::Create { metadata, .. } => OpId::new(metadata.last_modified_by, metadata.created_at.as_u64()),
+            TreeOp::Update { metadata, .. } => OpId::new(metadata.last_modified_by, metadata.modified_at.as_u64()),
+            TreeOp::Move(op) => OpId::new(op.timestamp.1, op.timestamp.0.as_u64()),
+        }
+    }
+}
+
+/// Visibility and parent of every node, taken before a merge to report what it changed
+pub(crate) struct TreeSnapshot(HashMap<NodeId, (bool, Option<NodeId>)>);
+
+impl TreeSnapshot {
+    /// Record the current placement of `nodes`
+    pub(crate) fn take<T>(nodes: &HashMap<NodeId, TreeNode<T>>) -> Self {
+        Self(nodes.iter().map(|(id, node)| (id.clone(), (!node.metadata.deleted, node.parent.clone()))).collect())
+    }
+
+    /// Compare against `nodes` after the merge; `rewritten` holds nodes whose value changed
+    pub(crate) fn report<T>(mut self, nodes: &HashMap<NodeId, TreeNode<T>>, rewritten: &HashSet<NodeId>) -> MergeReport<NodeId> {
+        let mut report = MergeReport::new();
+        for (id, node) in nodes {
+            let (was_visible, parent) = self.0.remove(id).unwrap_or((false, None));
+            let changed = rewritten.contains(id) || parent != node.parent;
+            report.record(id.clone(), was_visible, !node.metadata.deleted, changed);
+        }
+        // Nodes dropped by the merge
+        for (id, (was_visible, _)) in self.0 {
+            report.record(id, was_visible, false, true);
+        }
+        report
+    }
+}
+
+impl<T> TreeHierarchy<NodeId> for HashMap<NodeId, TreeNode<T>> {
+    fn contains_node(&self, id: &NodeId) -> bool {
+        self.contains_key(id)
+    }
+
+    fn parent_of(&self, id: &NodeId) -> Option<NodeId> {
+        self.get(id).and_then(|node| node.parent.clone())
+    }
+
+    fn set_parent(&mut self, id: &NodeId, parent: Option<NodeId>) {
+        let Some(node) = self.get_mut(id) else {
+            return;
+        };
+        let old_parent = std::mem::replace(&mut node.parent, parent.clone());
+        let key = (node.metadata.created_at, node.id.id);
+        if let Some(old_parent) = old_parent.and_then(|old_parent| self.get_mut(&old_parent)) {
+            old_parent.remove_child(id);
+        }
+
+        // Children are kept in creation order so every replica lists them alike
+        let Some(siblings) = parent.as_ref().and_then(|parent| self.get(parent)) else {
+            return;
+        };
+        let index = siblings.children.partition_point(|sibling| {
+            self.get(sibling)
+                .is_none_or(|sibling| (sibling.metadata.created_at, sibling.id.id) < key)
+        });
+        if let Some(parent) = parent.and_then(|parent| self.get_mut(&parent)) {
+            parent.children.insert(index, id.clone());
+        }
+    }
+}
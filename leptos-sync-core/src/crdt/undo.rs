@@ -0,0 +1,525 @@
+//! Undo and redo of local edits
+//!
+//! Restoring an earlier state would also roll back what other replicas did in
+//! the meantime. An [`UndoManager`] instead records the changes made through
+//! it and undoes each with a new edit that only reverts the local user's
+//! intent: an insert is undone by removing the inserted element, an overwrite
+//! by writing the previous value back, but only while the element still holds
+//! the value written. Undo and redo edits are ordinary edits of the CRDT, so
+//! they sync like any other change.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Default time within which consecutive edits form one undo step
+pub const DEFAULT_CAPTURE_TIMEOUT_MS: u64 = 500;
+
+/// Change reverting a reverted change, with the output of the reverting edit
+pub type Reverted<C> = Option<(<C as Undoable>::Change, <C as Undoable>::Output)>;
+
+/// CRDT whose local edits can be reverted by an [`UndoManager`]
+pub trait Undoable {
+    /// Local edit, such as an insert or an overwrite
+    type Edit;
+    /// What an edit did, with enough information to revert it
+    type Change: Clone;
+    /// Result of an edit: the operation to ship for operation-based CRDTs,
+    /// the affected element or nothing for state-based ones
+    type Output;
+    /// Identifier of the elements changes refer to
+    type Id: Clone + Eq + Hash;
+    /// Error type for edits
+    type Error;
+
+    /// Apply a local edit
+    fn edit(&mut self, edit: Self::Edit) -> Result<(Self::Change, Self::Output), Self::Error>;
+
+    /// Revert a change with a new edit, returning the change that reverts it back
+    ///
+    /// Returns `None` if there is nothing left to revert, because another
+    /// edit superseded the change or it was already reverted. Elements that
+    /// reverting re-creates under a new identifier are recorded in `ids`.
+    fn revert(&mut self, change: &Self::Change, ids: &mut IdMap<Self::Id>) -> Result<Reverted<Self>, Self::Error>;
+}
+
+/// Identifiers of elements that undo or redo re-created under a new identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdMap<I: Eq + Hash> {
+    replaced: HashMap<I, I>,
+}
+
+impl<I: Clone + Eq + Hash> IdMap<I> {
+    /// Create an empty map
+    pub fn new() -> Self {
+        Self { replaced: HashMap::new() }
+    }
+
+    /// Record that `new` replaces `old`
+    pub fn insert(&mut self, old: I, new: I) {
+        if old != new {
+            self.replaced.insert(old, new);
+        }
+    }
+
+    /// Current identifier of the element first known as `id`
+    pub fn resolve(&self, id: &I) -> I {
+        let mut id = id;
+        while let Some(new) = self.replaced.get(id) {
+            id = new;
+        }
+        id.clone()
+    }
+
+    /// Forget all replacements
+    pub fn clear(&mut self) {
+        self.replaced.clear();
+    }
+}
+
+impl<I: Clone + Eq + Hash> Default for IdMap<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Undo and redo stacks for the local edits of one replica
+///
+/// Only edits made through [`UndoManager::edit`] are recorded, so merging or
+/// applying remote changes to the CRDT never ends up on the stacks. Edits
+/// made within the capture timeout of the previous one are undone together.
+pub struct UndoManager<C: Undoable> {
+    /// Steps to undo, most recent last, each holding changes in edit order
+    undo_stack: Vec<Vec<C::Change>>,
+    /// Steps to redo, most recently undone last
+    redo_stack: Vec<Vec<C::Change>>,
+    /// Time in milliseconds within which edits join the current step
+    capture_timeout: u64,
+    /// Time of the last recorded edit, `None` to start a new step
+    last_edit: Option<u64>,
+    /// Elements re-created by undo or redo
+    ids: IdMap<C::Id>,
+}
+
+impl<C: Undoable> UndoManager<C> {
+    /// Create a manager with the default capture timeout
+    pub fn new() -> Self {
+        Self::with_capture_timeout(DEFAULT_CAPTURE_TIMEOUT_MS)
+    }
+
+    /// Create a manager grouping edits made within `capture_timeout` milliseconds
+    pub fn with_capture_timeout(capture_timeout: u64) -> Self {
+        Self {
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            capture_timeout,
+            last_edit: None,
+            ids: IdMap::new(),
+        }
+    }
+
+    /// Apply and record a local edit
+    pub fn edit(&mut self, crdt: &mut C, edit: C::Edit) -> Result<C::Output, C::Error> {
+        self.edit_at(crdt, edit, now_millis())
+    }
+
+    /// Apply and record a local edit made at `now` milliseconds
+    pub fn edit_at(&mut self, crdt: &mut C, edit: C::Edit, now: u64) -> Result<C::Output, C::Error> {
+        let (change, output) = crdt.edit(edit)?;
+        self.redo_stack.clear();
+        match (self.undo_stack.last_mut(), self.last_edit) {
+            (Some(step), Some(last)) if now.saturating_sub(last) < self.capture_timeout => step.push(change),
+            _ => self.undo_stack.push(vec![change]),
+        }
+        self.last_edit = Some(now);
+        Ok(output)
+    }
+
+    /// Start a new undo step with the next edit, regardless of the timeout
+    pub fn stop_capturing(&mut self) {
+        self.last_edit = None;
+    }
+
+    /// Undo the most recent step that still has an effect
+    ///
+    /// Returns the outputs of the reverting edits, or `None` if there was
+    /// nothing to undo.
+    pub fn undo(&mut self, crdt: &mut C) -> Result<Option<Vec<C::Output>>, C::Error> {
+        self.stop_capturing();
+        revert_step(&mut self.undo_stack, &mut self.redo_stack, crdt, &mut self.ids)
+    }
+
+    /// Redo the most recently undone step that still has an effect
+    pub fn redo(&mut self, crdt: &mut C) -> Result<Option<Vec<C::Output>>, C::Error> {
+        self.stop_capturing();
+        revert_step(&mut self.redo_stack, &mut self.undo_stack, crdt, &mut self.ids)
+    }
+
+    /// Whether there are steps to undo
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    /// Whether there are steps to redo
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    /// Forget all recorded steps
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.last_edit = None;
+        self.ids.clear();
+    }
+}
+
+impl<C: Undoable> Default for UndoManager<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Revert the latest step of `from` that still has an effect, pushing its inverse onto `to`
+fn revert_step<C: Undoable>(
+    from: &mut Vec<Vec<C::Change>>,
+    to: &mut Vec<Vec<C::Change>>,
+    crdt: &mut C,
+    ids: &mut IdMap<C::Id>,
+) -> Result<Option<Vec<C::Output>>, C::Error> {
+    while let Some(step) = from.pop() {
+        // Newest change first, so reverting the inverse step replays the original order
+        let mut inverse = Vec::new();
+        let mut outputs = Vec::new();
+        for change in step.iter().rev() {
+            if let Some((change, output)) = crdt.revert(change, ids)? {
+                inverse.push(change);
+                outputs.push(output);
+            }
+        }
+        if !inverse.is_empty() {
+            to.push(inverse);
+            return Ok(Some(outputs));
+        }
+    }
+    Ok(None)
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{
+        AddWinsList, AddWinsTree, CrdtBuilder, CrdtStrategy, CustomChange, CustomEdit, ListEdit, LwwMap, MapWrite,
+        Mergeable, OpCrdt, ReplicaId, RemoveWinsList, RemoveWinsTree, Rga,
+    };
+    use crate::crdt::advanced::{RgaIntent, RgaOp};
+    use crate::crdt::tree::TreeIntent;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn create_replica(id: u64) -> ReplicaId {
+        ReplicaId::from(Uuid::from_u64_pair(0, id))
+    }
+
+    fn type_text(manager: &mut UndoManager<Rga<char>>, rga: &mut Rga<char>, text: &str, now: u64) -> Vec<RgaOp<char>> {
+        let mut ops = Vec::new();
+        for c in text.chars() {
+            let after = rga.position_at(rga.to_vec().len().wrapping_sub(1));
+            ops.push(manager.edit_at(rga, RgaIntent::InsertAfter { value: c, after }, now).unwrap());
+        }
+        ops
+    }
+
+    fn text(rga: &Rga<char>) -> String {
+        rga.to_vec().into_iter().collect()
+    }
+
+    #[test]
+    fn test_edits_within_capture_timeout_are_undone_together() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut manager = UndoManager::with_capture_timeout(500);
+
+        type_text(&mut manager, &mut rga, "ab", 1000);
+        type_text(&mut manager, &mut rga, "c", 1400);
+        type_text(&mut manager, &mut rga, "d", 2000);
+        manager.stop_capturing();
+        type_text(&mut manager, &mut rga, "e", 2001);
+
+        manager.undo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "abcd");
+        manager.undo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "abc");
+        assert_eq!(manager.undo(&mut rga).unwrap().unwrap().len(), 3);
+        assert_eq!(text(&rga), "");
+        assert!(manager.undo(&mut rga).unwrap().is_none());
+
+        manager.redo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "abc");
+        assert!(manager.can_redo());
+    }
+
+    #[test]
+    fn test_undo_leaves_remote_edits_alone() {
+        let mut local = Rga::new(create_replica(1));
+        let mut remote = Rga::new(create_replica(2));
+        let mut manager = UndoManager::new();
+
+        for op in type_text(&mut manager, &mut local, "ab", 0) {
+            remote.apply(&op).unwrap();
+        }
+        let after = remote.position_at(1);
+        let op = remote.perform(RgaIntent::InsertAfter { value: 'x', after }).unwrap();
+        local.apply(&op).unwrap();
+        assert_eq!(text(&local), "abx");
+
+        // Remote changes are never recorded, so undo only removes the local typing
+        for op in manager.undo(&mut local).unwrap().unwrap() {
+            remote.apply(&op).unwrap();
+        }
+        assert_eq!(text(&local), "x");
+        assert_eq!(text(&remote), "x");
+        assert!(!manager.can_undo());
+
+        for op in manager.redo(&mut local).unwrap().unwrap() {
+            remote.apply(&op).unwrap();
+        }
+        assert_eq!(text(&local), "abx");
+        assert_eq!(text(&remote), "abx");
+    }
+
+    #[test]
+    fn test_undone_deletion_comes_back_as_new_element() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut manager = UndoManager::new();
+
+        type_text(&mut manager, &mut rga, "abc", 0);
+        manager.stop_capturing();
+        let b = rga.position_at(1).unwrap();
+        manager.edit_at(&mut rga, RgaIntent::Delete(b), 1000).unwrap();
+        assert_eq!(text(&rga), "ac");
+
+        manager.undo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "abc");
+        // Undoing the typing removes the re-inserted `b` too
+        manager.undo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "");
+
+        manager.redo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "abc");
+        manager.redo(&mut rga).unwrap().unwrap();
+        assert_eq!(text(&rga), "ac");
+    }
+
+    #[test]
+    fn test_new_edit_clears_redo() {
+        let mut rga = Rga::new(create_replica(1));
+        let mut manager = UndoManager::new();
+
+        type_text(&mut manager, &mut rga, "a", 0);
+        manager.undo(&mut rga).unwrap();
+        assert!(manager.can_redo());
+        type_text(&mut manager, &mut rga, "b", 10);
+        assert!(!manager.can_redo());
+        assert!(manager.redo(&mut rga).unwrap().is_none());
+        assert_eq!(text(&rga), "b");
+    }
+
+    #[test]
+    fn test_lww_map_undo_skips_remote_overwrites() {
+        let local_replica = create_replica(1);
+        let mut local = LwwMap::new();
+        let mut remote = LwwMap::new();
+        let mut manager = UndoManager::new();
+
+        manager.edit_at(&mut local, MapWrite { key: "title", value: "Draft", replica_id: local_replica }, 0).unwrap();
+        manager.edit_at(&mut local, MapWrite { key: "color", value: "red", replica_id: local_replica }, 1000).unwrap();
+        manager.edit_at(&mut local, MapWrite { key: "title", value: "Final", replica_id: local_replica }, 2000).unwrap();
+
+        remote.merge(&local).unwrap();
+        remote.insert("color", "blue", create_replica(2));
+        local.merge(&remote).unwrap();
+
+        manager.undo(&mut local).unwrap().unwrap();
+        assert_eq!(local.get(&"title"), Some(&"Draft"));
+        // The remote overwrite of `color` is newer, so that step has nothing left to undo
+        manager.undo(&mut local).unwrap().unwrap();
+        assert_eq!(local.get(&"color"), Some(&"blue"));
+        assert!(!local.contains_key(&"title"));
+        assert!(!manager.can_undo());
+
+        manager.redo(&mut local).unwrap().unwrap();
+        manager.redo(&mut local).unwrap().unwrap();
+        assert_eq!(local.get(&"title"), Some(&"Final"));
+        remote.merge(&local).unwrap();
+        assert_eq!(remote.get(&"title"), Some(&"Final"));
+    }
+
+    #[test]
+    fn test_add_wins_list_undo_syncs_by_merge() {
+        let mut local = AddWinsList::new(create_replica(1));
+        let mut remote = AddWinsList::new(create_replica(2));
+        let mut manager = UndoManager::new();
+
+        let a = manager.edit_at(&mut local, ListEdit::Add { value: "a", timestamp: 1000.into() }, 0).unwrap();
+        let b = manager.edit_at(&mut local, ListEdit::Add { value: "b", timestamp: 1001.into() }, 0).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut local, ListEdit::Move { id: b.clone(), index: 0, timestamp: 2000.into() }, 1000).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut local, ListEdit::Update { id: a.clone(), value: "A", timestamp: 3000.into() }, 2000).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut local, ListEdit::Remove { id: b.clone(), timestamp: 4000.into() }, 3000).unwrap();
+        remote.merge(&local).unwrap();
+        let values = |list: &AddWinsList<&'static str>| list.iter().map(|e| e.value).collect::<Vec<_>>();
+        assert_eq!(values(&remote), vec!["A"]);
+
+        manager.undo(&mut local).unwrap().unwrap();
+        remote.merge(&local).unwrap();
+        assert_eq!(values(&remote), vec!["b", "A"]);
+        assert!(local.contains(&b));
+
+        manager.undo(&mut local).unwrap().unwrap();
+        manager.undo(&mut local).unwrap().unwrap();
+        remote.merge(&local).unwrap();
+        assert_eq!(values(&remote), vec!["a", "b"]);
+
+        manager.undo(&mut local).unwrap().unwrap();
+        remote.merge(&local).unwrap();
+        assert!(remote.is_empty());
+    }
+
+    #[test]
+    fn test_list_update_undo_respects_newer_remote_update() {
+        let mut local = AddWinsList::new(create_replica(1));
+        let mut manager = UndoManager::new();
+
+        let a = manager.edit_at(&mut local, ListEdit::Add { value: 1, timestamp: 1000.into() }, 0).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut local, ListEdit::Update { id: a.clone(), value: 2, timestamp: 2000.into() }, 1000).unwrap();
+
+        let mut remote = local.clone();
+        remote.update(&a, 3, 5000).unwrap();
+        local.merge(&remote).unwrap();
+
+        // The update step is superseded, so undo goes on to the insert
+        manager.undo(&mut local).unwrap().unwrap();
+        assert!(local.is_empty());
+        manager.redo(&mut local).unwrap().unwrap();
+        assert_eq!(local.get(&a).unwrap().value, 3);
+    }
+
+    #[test]
+    fn test_remove_wins_list_brings_back_removed_element_in_place() {
+        let mut list = RemoveWinsList::new(create_replica(1));
+        let mut manager = UndoManager::new();
+
+        for (i, value) in ["a", "b", "c"].into_iter().enumerate() {
+            manager.edit_at(&mut list, ListEdit::Add { value, timestamp: (1000 + i as u64).into() }, 0).unwrap();
+        }
+        manager.stop_capturing();
+        let b = list.elements()[1].id.clone();
+        manager.edit_at(&mut list, ListEdit::Update { id: b.clone(), value: "B", timestamp: 2000.into() }, 1000).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut list, ListEdit::Remove { id: b.clone(), timestamp: 3000.into() }, 2000).unwrap();
+        assert!(!list.contains(&b));
+
+        manager.undo(&mut list).unwrap().unwrap();
+        let values = |list: &RemoveWinsList<&'static str>| list.iter().map(|e| e.value).collect::<Vec<_>>();
+        assert_eq!(values(&list), vec!["a", "B", "c"]);
+        assert!(!list.contains(&b));
+
+        // The update refers to the removed element, now replaced by a new one
+        manager.undo(&mut list).unwrap().unwrap();
+        assert_eq!(values(&list), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_add_wins_tree_undo_restores_and_moves_back() {
+        let mut local = AddWinsTree::new(create_replica(1));
+        let mut remote = AddWinsTree::new(create_replica(2));
+        let mut manager = UndoManager::new();
+        let ship = |ops: Vec<_>, remote: &mut AddWinsTree<&str>| {
+            for op in ops {
+                remote.apply(&op).unwrap();
+            }
+        };
+
+        let root = manager.edit_at(&mut local, TreeIntent::AddRoot { value: "root", timestamp: 1000.into() }, 0).unwrap();
+        let root_id = local.roots()[0].id.clone();
+        let a = manager.edit_at(&mut local, TreeIntent::AddChild { parent: root_id.clone(), value: "a", timestamp: 1001.into() }, 0).unwrap();
+        let a_id = local.children(&root_id)[0].id.clone();
+        let b = manager.edit_at(&mut local, TreeIntent::AddChild { parent: root_id.clone(), value: "b", timestamp: 1002.into() }, 0).unwrap();
+        let b_id = local.children(&root_id).into_iter().find(|n| n.value == "b").unwrap().id.clone();
+        ship(vec![root, a, b], &mut remote);
+        manager.stop_capturing();
+
+        let moved = manager.edit_at(&mut local, TreeIntent::Move { id: a_id.clone(), parent: b_id.clone(), timestamp: 2000.into() }, 1000).unwrap();
+        manager.stop_capturing();
+        let removed = manager.edit_at(&mut local, TreeIntent::Remove { id: b_id.clone(), timestamp: 3000.into() }, 2000).unwrap();
+        ship(vec![moved, removed], &mut remote);
+        assert!(remote.get(&b_id).unwrap().metadata.deleted);
+
+        ship(manager.undo(&mut local).unwrap().unwrap(), &mut remote);
+        assert!(!remote.get(&b_id).unwrap().metadata.deleted);
+
+        ship(manager.undo(&mut local).unwrap().unwrap(), &mut remote);
+        assert_eq!(remote.get(&a_id).unwrap().parent, Some(root_id.clone()));
+        assert_eq!(local.get(&a_id).unwrap().parent, Some(root_id));
+    }
+
+    #[test]
+    fn test_remove_wins_tree_undo_reattaches_children() {
+        let mut tree = RemoveWinsTree::new(create_replica(1));
+        let mut manager = UndoManager::new();
+
+        let root = manager.edit_at(&mut tree, TreeIntent::AddRoot { value: "root", timestamp: 1000.into() }, 0).unwrap();
+        let folder = manager.edit_at(&mut tree, TreeIntent::AddChild { parent: root.clone(), value: "folder", timestamp: 1001.into() }, 0).unwrap();
+        let file = manager.edit_at(&mut tree, TreeIntent::AddChild { parent: folder.clone(), value: "file", timestamp: 1002.into() }, 0).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut tree, TreeIntent::Remove { id: folder.clone(), timestamp: 2000.into() }, 1000).unwrap();
+        assert!(!tree.contains(&folder));
+
+        let restored = manager.undo(&mut tree).unwrap().unwrap().remove(0);
+        assert_eq!(tree.get(&restored).unwrap().value, "folder");
+        assert_eq!(tree.get(&restored).unwrap().parent, Some(root));
+        assert_eq!(tree.get(&file).unwrap().parent, Some(restored));
+
+        manager.undo(&mut tree).unwrap().unwrap();
+        assert!(tree.is_empty());
+    }
+
+    #[test]
+    fn test_custom_crdt_undo() {
+        let config = CrdtBuilder::new("Doc".to_string())
+            .add_field("title".to_string(), CrdtStrategy::Lww)
+            .add_field("votes".to_string(), CrdtStrategy::PNCounter)
+            .build();
+        let mut doc = crate::crdt::CustomCrdt::new(config, create_replica(1));
+        let mut manager = UndoManager::new();
+
+        manager.edit_at(&mut doc, CustomEdit::Set { field: "title".to_string(), value: json!("Hello") }, 0).unwrap();
+        manager.stop_capturing();
+        manager.edit_at(&mut doc, CustomEdit::Increment { field: "votes".to_string(), amount: 3 }, 1000).unwrap();
+        manager.edit_at(&mut doc, CustomEdit::Decrement { field: "votes".to_string(), amount: 1 }, 1001).unwrap();
+        assert_eq!(doc.get_field("votes"), Some(&json!(2)));
+
+        manager.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.get_field("votes"), Some(&json!(0)));
+        manager.undo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.get_field("title"), Some(&serde_json::Value::Null));
+
+        manager.redo(&mut doc).unwrap().unwrap();
+        assert_eq!(doc.get_field("title"), Some(&json!("Hello")));
+
+        // A value written since is not ours to undo
+        doc.set_field("title", json!("Edited elsewhere")).unwrap();
+        let mut ids = IdMap::new();
+        let change = CustomChange::Set { field: "title".to_string(), previous: serde_json::Value::Null, value: json!("Hello") };
+        assert!(doc.revert(&change, &mut ids).unwrap().is_none());
+    }
+}
@@ -0,0 +1,68 @@
+//! Snapshots of Yjs shared types as the CRDTs of this crate
+//!
+//! Yjs orders concurrent insertions with YATA, which differs from the RGA
+//! rules of [`Rga`], so sequences are copied in their current order rather
+//! than replicated item by item. Exchange edits through [`YjsDoc`] and take a
+//! fresh snapshot after applying updates.
+
+use super::doc::{replica_for_client, YjsDoc};
+use super::error::YjsError;
+use super::update::{Parent, TypeRef};
+use crate::crdt::{LwwMap, PositionId, Rga, YjsTree, CRDT};
+use serde_json::Value;
+
+impl YjsDoc {
+    /// The top-level `Y.Text` called `name` as an RGA of characters
+    pub fn text_as_rga(&self, name: &str) -> Result<Rga<char>, YjsError> {
+        self.sequence_as_rga(self.text(name).chars())
+    }
+
+    /// The top-level `Y.Array` called `name` as an RGA of JSON values
+    pub fn array_as_rga(&self, name: &str) -> Result<Rga<Value>, YjsError> {
+        self.sequence_as_rga(self.array(name))
+    }
+
+    /// The top-level `Y.Map` called `name` as a last-write-wins map
+    ///
+    /// Each entry is written by the replica of the Yjs client that set it.
+    pub fn map_as_lww(&self, name: &str) -> LwwMap<String, Value> {
+        let mut map = LwwMap::new();
+        for (key, value, client) in self.map_writers(&Parent::Root(name.to_string())) {
+            map.insert(key, value, replica_for_client(client));
+        }
+        map
+    }
+
+    /// The top-level `Y.XmlFragment` called `name` as a tree
+    ///
+    /// The root holds the fragment name, elements their node name and text
+    /// nodes their text, as edited by `y-prosemirror`.
+    pub fn xml_as_tree(&self, name: &str) -> Result<YjsTree<String>, YjsError> {
+        let mut tree = YjsTree::new(*self.replica_id());
+        let root = tree.add_root(name.to_string())?;
+        self.add_xml_children(&mut tree, &root, &Parent::Root(name.to_string()))?;
+        Ok(tree)
+    }
+
+    fn sequence_as_rga<T: Clone + PartialEq>(&self, values: impl IntoIterator<Item = T>) -> Result<Rga<T>, YjsError> {
+        let mut rga = Rga::new(*self.replica_id());
+        let mut after = None;
+        for value in values {
+            after = Some(rga.insert_after(value, after)?);
+        }
+        Ok(rga)
+    }
+
+    fn add_xml_children(&self, tree: &mut YjsTree<String>, node: &PositionId, parent: &Parent) -> Result<(), YjsError> {
+        for (child, kind) in self.nested_types(parent) {
+            let value = match &kind {
+                TypeRef::XmlElement(node_name) => node_name.clone(),
+                TypeRef::XmlText | TypeRef::Text => self.text_of(&child),
+                _ => continue,
+            };
+            let id = tree.add_child(node, value)?;
+            self.add_xml_children(tree, &id, &child)?;
+        }
+        Ok(())
+    }
+}
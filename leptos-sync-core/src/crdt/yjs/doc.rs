@@ -0,0 +1,963 @@
+//! Document that integrates Yjs updates
+
+use super::encoding::Any;
+use super::error::YjsError;
+use super::update::{Block, BlockId, Content, DeleteSet, Item, Parent, StateVector, TypeRef, YjsUpdate};
+use crate::crdt::{Mergeable, ReplicaId, CRDT};
+use serde::de::{Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use uuid::Uuid;
+
+/// Largest client ID Yjs can send, since JavaScript numbers have 53 bits
+const MAX_CLIENT: u64 = (1 << 53) - 1;
+
+/// Yjs client ID of a replica
+///
+/// Replica IDs built as `Uuid::from_u64_pair(0, client)` map back to `client`;
+/// others are folded to 32 bits, like the random IDs Yjs picks.
+pub fn client_id(replica_id: &ReplicaId) -> u64 {
+    let (high, low) = Uuid::from(*replica_id).as_u64_pair();
+    if high == 0 && low <= MAX_CLIENT {
+        low
+    } else {
+        (high ^ low ^ (low >> 32)) & 0xFFFF_FFFF
+    }
+}
+
+/// Replica ID of a Yjs client
+pub fn replica_for_client(client: u64) -> ReplicaId {
+    ReplicaId::from(Uuid::from_u64_pair(0, client))
+}
+
+/// An integrated item with its neighbours in the parent type
+#[derive(Debug, Clone)]
+struct Stored {
+    id: BlockId,
+    origin: Option<BlockId>,
+    right_origin: Option<BlockId>,
+    /// First IDs of the neighbouring blocks
+    left: Option<BlockId>,
+    right: Option<BlockId>,
+    parent: Parent,
+    parent_sub: Option<String>,
+    content: Content,
+    deleted: bool,
+}
+
+impl Stored {
+    fn len(&self) -> u64 {
+        self.content.len()
+    }
+
+    fn last_id(&self) -> BlockId {
+        BlockId::new(self.id.client, self.id.clock + self.len() - 1)
+    }
+
+    /// Visible units the item adds to its parent: characters of text, values of arrays
+    fn units(&self) -> usize {
+        if self.deleted || !self.content.is_countable() {
+            return 0;
+        }
+        match &self.content {
+            Content::String(text) => text.chars().count(),
+            content => content.len() as usize,
+        }
+    }
+
+    /// Clock offset of the first `units` units
+    fn offset_of(&self, units: usize) -> u64 {
+        match &self.content {
+            Content::String(text) => text.chars().take(units).map(char::len_utf16).sum::<usize>() as u64,
+            _ => units as u64,
+        }
+    }
+
+    /// The item from `offset` on, as sent in an update
+    fn to_block(&self, offset: u64) -> Block {
+        let mut item = Item {
+            id: self.id,
+            origin: self.origin,
+            right_origin: self.right_origin,
+            parent: Some(self.parent.clone()),
+            parent_sub: self.parent_sub.clone(),
+            content: self.content.clone(),
+        };
+        if offset > 0 {
+            item.id.clock += offset;
+            item.origin = Some(BlockId::new(self.id.client, item.id.clock - 1));
+            item.content = item.content.split(offset);
+        }
+        Block::Item(item)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Slot {
+    Item(Box<Stored>),
+    Gc { id: BlockId, len: u64 },
+}
+
+impl Slot {
+    fn id(&self) -> BlockId {
+        match self {
+            Slot::Item(item) => item.id,
+            Slot::Gc { id, .. } => *id,
+        }
+    }
+
+    fn len(&self) -> u64 {
+        match self {
+            Slot::Item(item) => item.len(),
+            Slot::Gc { len, .. } => *len,
+        }
+    }
+}
+
+/// Start of a sequence and current entries of a map
+#[derive(Debug, Clone, Default)]
+struct SharedType {
+    kind: Option<TypeRef>,
+    start: Option<BlockId>,
+    /// Latest item of each key
+    map: HashMap<String, BlockId>,
+}
+
+/// Replica of a Yjs document
+///
+/// Integrates items exactly as Yjs does, so a `YjsDoc` converges with
+/// JavaScript peers that exchange updates with it. Top-level texts, arrays
+/// and maps can be read and edited by name; nested types and XML are read
+/// as JSON. Deleted content is garbage-collected as soon as it is deleted.
+///
+/// Text indices count characters, not the UTF-16 units Yjs uses.
+#[derive(Debug, Clone)]
+pub struct YjsDoc {
+    replica_id: ReplicaId,
+    client: u64,
+    blocks: HashMap<u64, Vec<Slot>>,
+    types: HashMap<Parent, SharedType>,
+    /// Blocks waiting for the items they depend on
+    pending: Vec<Block>,
+    /// Deletions of units not yet received
+    pending_deletes: DeleteSet,
+    /// Blocks that may be joined with a neighbour once the current change is done
+    touched: Vec<BlockId>,
+}
+
+impl YjsDoc {
+    /// Create an empty document; edits are made as the client of [`client_id`]
+    pub fn new(replica_id: ReplicaId) -> Self {
+        Self {
+            replica_id,
+            client: client_id(&replica_id),
+            blocks: HashMap::new(),
+            types: HashMap::new(),
+            pending: Vec::new(),
+            pending_deletes: DeleteSet::new(),
+            touched: Vec::new(),
+        }
+    }
+
+    /// Yjs client ID of this replica
+    pub fn client(&self) -> u64 {
+        self.client
+    }
+
+    /// Next clock of every client
+    pub fn state_vector(&self) -> StateVector {
+        let mut state = StateVector::new();
+        for &client in self.blocks.keys() {
+            state.set(client, self.state(client));
+        }
+        state
+    }
+
+    /// Whether some received content waits for updates not yet received
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty() || !self.pending_deletes.is_empty()
+    }
+
+    /// Apply an update encoded by `Y.encodeStateAsUpdate` or received from a Yjs provider
+    pub fn apply_update_v1(&mut self, bytes: &[u8]) -> Result<(), YjsError> {
+        self.apply_update(YjsUpdate::decode_v1(bytes)?)
+    }
+
+    /// Integrate an update
+    ///
+    /// Blocks whose dependencies are missing are kept and integrated once a
+    /// later update provides them. An update with a block that fails
+    /// [`Block::validate`] is rejected as a whole.
+    pub fn apply_update(&mut self, update: YjsUpdate) -> Result<(), YjsError> {
+        for block in &update.blocks {
+            block.validate()?;
+        }
+
+        let mut queues: BTreeMap<u64, Vec<Block>> = BTreeMap::new();
+        for block in std::mem::take(&mut self.pending).into_iter().chain(update.blocks) {
+            queues.entry(block.id().client).or_default().push(block);
+        }
+        for queue in queues.values_mut() {
+            // Popped from the back, lowest clock first
+            queue.sort_by_key(|block| std::cmp::Reverse(block.id().clock));
+        }
+
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for queue in queues.values_mut() {
+                while let Some(block) = queue.last() {
+                    let id = block.id();
+                    let state = self.state(id.client);
+                    if id.clock + block.len() <= state {
+                        queue.pop();
+                        continue;
+                    }
+                    if id.clock > state || matches!(block, Block::Skip { .. }) || self.is_missing(block) {
+                        break;
+                    }
+                    let block = queue.pop().expect("checked above");
+                    self.integrate(block, state - id.clock);
+                    progress = true;
+                }
+            }
+        }
+        self.pending = queues.into_values().flatten().filter(|block| !matches!(block, Block::Skip { .. })).collect();
+
+        let mut deletes = std::mem::take(&mut self.pending_deletes);
+        for (client, clock, len) in update.delete_set.iter() {
+            deletes.insert(client, clock, len);
+        }
+        for (client, clock, len) in deletes.iter() {
+            let state = self.state(client);
+            let end = clock + len;
+            if clock < state {
+                self.delete_range(client, clock, end.min(state));
+            }
+            if end > state {
+                self.pending_deletes.insert(client, clock.max(state), end - clock.max(state));
+            }
+        }
+        self.join_touched();
+        Ok(())
+    }
+
+    /// Update with everything not covered by `since`, for `Y.applyUpdate` on a peer
+    pub fn encode_state_as_update_v1(&self, since: &StateVector) -> Vec<u8> {
+        self.diff(since).encode_v1()
+    }
+
+    /// Blocks not covered by `since`, and every deletion
+    pub fn diff(&self, since: &StateVector) -> YjsUpdate {
+        let mut update = YjsUpdate::default();
+        for (&client, slots) in &self.blocks {
+            let clock = since.get(client).max(slots[0].id().clock);
+            if let Some(first) = self.index_of(BlockId::new(client, clock)) {
+                for (index, slot) in slots.iter().enumerate().skip(first) {
+                    let offset = if index == first { clock - slot.id().clock } else { 0 };
+                    update.blocks.push(match slot {
+                        Slot::Item(item) => item.to_block(offset),
+                        Slot::Gc { id, len } => Block::Gc {
+                            id: BlockId::new(client, id.clock + offset),
+                            len: len - offset,
+                        },
+                    });
+                }
+            }
+            for slot in slots {
+                let deleted = match slot {
+                    Slot::Item(item) => item.deleted,
+                    Slot::Gc { .. } => true,
+                };
+                if deleted {
+                    update.delete_set.insert(client, slot.id().clock, slot.len());
+                }
+            }
+        }
+        update
+    }
+
+    /// Text of the top-level `Y.Text` called `name`
+    pub fn text(&self, name: &str) -> String {
+        self.text_of(&Parent::Root(name.to_string()))
+    }
+
+    /// Values of the top-level `Y.Array` called `name`
+    pub fn array(&self, name: &str) -> Vec<Value> {
+        self.array_of(&Parent::Root(name.to_string()))
+    }
+
+    /// Entries of the top-level `Y.Map` called `name`
+    pub fn map(&self, name: &str) -> Map<String, Value> {
+        self.map_of(&Parent::Root(name.to_string()))
+    }
+
+    /// Top-level `Y.XmlFragment` called `name`, as JSON elements with `nodeName`, `attributes` and `children`
+    pub fn xml(&self, name: &str) -> Vec<Value> {
+        self.array_of(&Parent::Root(name.to_string()))
+    }
+
+    /// Insert text into the top-level `Y.Text` called `name`
+    pub fn insert_text(&mut self, name: &str, index: usize, text: &str) -> Result<(), YjsError> {
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.insert_content(Parent::Root(name.to_string()), index, Content::String(text.to_string()))
+    }
+
+    /// Delete `len` characters from the top-level `Y.Text` called `name`
+    pub fn delete_text(&mut self, name: &str, index: usize, len: usize) -> Result<(), YjsError> {
+        self.delete_units(Parent::Root(name.to_string()), index, len)
+    }
+
+    /// Insert values into the top-level `Y.Array` called `name`
+    pub fn insert_array(&mut self, name: &str, index: usize, values: Vec<Value>) -> Result<(), YjsError> {
+        if values.is_empty() {
+            return Ok(());
+        }
+        let content = Content::Any(values.into_iter().map(Any::from).collect());
+        self.insert_content(Parent::Root(name.to_string()), index, content)
+    }
+
+    /// Delete `len` values from the top-level `Y.Array` called `name`
+    pub fn delete_array(&mut self, name: &str, index: usize, len: usize) -> Result<(), YjsError> {
+        self.delete_units(Parent::Root(name.to_string()), index, len)
+    }
+
+    /// Set a key of the top-level `Y.Map` called `name`
+    pub fn map_set(&mut self, name: &str, key: &str, value: impl Into<Value>) {
+        let parent = Parent::Root(name.to_string());
+        let left = self.types.get(&parent).and_then(|shared| shared.map.get(key)).copied();
+        let item = Item {
+            id: BlockId::new(self.client, self.state(self.client)),
+            origin: left.map(|left| self.stored(left).last_id()),
+            right_origin: None,
+            parent: Some(parent),
+            parent_sub: Some(key.to_string()),
+            content: Content::Any(vec![Any::from(value.into())]),
+        };
+        self.integrate(Block::Item(item), 0);
+        self.join_touched();
+    }
+
+    /// Remove a key from the top-level `Y.Map` called `name`, returning whether it was set
+    pub fn map_remove(&mut self, name: &str, key: &str) -> bool {
+        let current = self.types.get(&Parent::Root(name.to_string())).and_then(|shared| shared.map.get(key)).copied();
+        match current {
+            Some(id) if !self.stored(id).deleted => {
+                self.delete_item(id);
+                self.join_touched();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Items of a sequence in order, including deleted ones
+    fn sequence<'a>(&'a self, parent: &Parent) -> impl Iterator<Item = &'a Stored> + 'a {
+        let mut next = self.types.get(parent).and_then(|shared| shared.start);
+        std::iter::from_fn(move || {
+            let item = self.stored(next?);
+            next = item.right;
+            Some(item)
+        })
+    }
+
+    pub(super) fn text_of(&self, parent: &Parent) -> String {
+        self.sequence(parent)
+            .filter(|item| !item.deleted)
+            .filter_map(|item| match &item.content {
+                Content::String(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub(super) fn array_of(&self, parent: &Parent) -> Vec<Value> {
+        self.sequence(parent)
+            .filter(|item| !item.deleted)
+            .flat_map(|item| self.values(item))
+            .collect()
+    }
+
+    pub(super) fn map_of(&self, parent: &Parent) -> Map<String, Value> {
+        self.map_writers(parent).into_iter().map(|(key, value, _)| (key, value)).collect()
+    }
+
+    /// Kind of a nested type, if `id` holds one
+    fn type_of(&self, id: BlockId) -> Option<&TypeRef> {
+        self.types.get(&Parent::Item(id)).and_then(|shared| shared.kind.as_ref())
+    }
+
+    /// Nested types in a sequence, in order
+    pub(super) fn nested_types(&self, parent: &Parent) -> Vec<(Parent, TypeRef)> {
+        self.sequence(parent)
+            .filter(|item| !item.deleted)
+            .filter_map(|item| match &item.content {
+                Content::Type(kind) => Some((Parent::Item(item.id), kind.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Entries of a map with the client that wrote each
+    pub(super) fn map_writers(&self, parent: &Parent) -> Vec<(String, Value, u64)> {
+        let Some(shared) = self.types.get(parent) else {
+            return Vec::new();
+        };
+        shared
+            .map
+            .iter()
+            .map(|(key, id)| (key, self.stored(*id)))
+            .filter(|(_, item)| !item.deleted)
+            .filter_map(|(key, item)| Some((key.clone(), self.values(item).pop()?, item.id.client)))
+            .collect()
+    }
+
+    /// Values an item holds, with nested types as JSON
+    fn values(&self, item: &Stored) -> Vec<Value> {
+        match &item.content {
+            Content::Any(values) => values.iter().map(Any::to_json).collect(),
+            Content::Json(values) => values.iter().map(|value| value.clone().unwrap_or(Value::Null)).collect(),
+            Content::Binary(bytes) => vec![bytes.iter().copied().map(Value::from).collect()],
+            Content::Embed(value) => vec![value.clone()],
+            Content::String(text) => text.chars().map(|c| Value::String(c.to_string())).collect(),
+            Content::Doc { guid, .. } => vec![Value::String(guid.clone())],
+            Content::Type(kind) => vec![self.type_to_json(item.id, kind)],
+            Content::Deleted(_) | Content::Format { .. } => Vec::new(),
+        }
+    }
+
+    fn type_to_json(&self, id: BlockId, kind: &TypeRef) -> Value {
+        let parent = Parent::Item(id);
+        match kind {
+            TypeRef::Array | TypeRef::XmlFragment => Value::Array(self.array_of(&parent)),
+            TypeRef::Map | TypeRef::XmlHook(_) => Value::Object(self.map_of(&parent)),
+            TypeRef::Text | TypeRef::XmlText => Value::String(self.text_of(&parent)),
+            TypeRef::XmlElement(name) => serde_json::json!({
+                "nodeName": name,
+                "attributes": self.map_of(&parent),
+                "children": self.array_of(&parent),
+            }),
+        }
+    }
+
+    fn state(&self, client: u64) -> u64 {
+        self.blocks
+            .get(&client)
+            .and_then(|slots| slots.last())
+            .map_or(0, |slot| slot.id().clock + slot.len())
+    }
+
+    /// Index of the slot holding `id` in its client's blocks
+    fn index_of(&self, id: BlockId) -> Option<usize> {
+        let slots = self.blocks.get(&id.client)?;
+        let index = slots.partition_point(|slot| slot.id().clock <= id.clock).checked_sub(1)?;
+        (id.clock < slots[index].id().clock + slots[index].len()).then_some(index)
+    }
+
+    fn slot(&self, id: BlockId) -> Option<&Slot> {
+        let index = self.index_of(id)?;
+        self.blocks.get(&id.client).map(|slots| &slots[index])
+    }
+
+    /// The item starting at `id`; links always point at the start of an item
+    fn stored(&self, id: BlockId) -> &Stored {
+        match self.slot(id) {
+            Some(Slot::Item(item)) => item,
+            _ => unreachable!("linked block {}:{} is an item", id.client, id.clock),
+        }
+    }
+
+    fn stored_mut(&mut self, id: BlockId) -> &mut Stored {
+        let index = self.index_of(id).expect("linked block exists");
+        match self.blocks.get_mut(&id.client).map(|slots| &mut slots[index]) {
+            Some(Slot::Item(item)) => item,
+            _ => unreachable!("linked block {}:{} is an item", id.client, id.clock),
+        }
+    }
+
+    /// Whether a block refers to items of other clients not yet received
+    fn is_missing(&self, block: &Block) -> bool {
+        let Block::Item(item) = block else {
+            return false;
+        };
+        let parent = match &item.parent {
+            Some(Parent::Item(id)) => Some(id),
+            _ => None,
+        };
+        [item.origin.as_ref(), item.right_origin.as_ref(), parent]
+            .into_iter()
+            .flatten()
+            .any(|dep| dep.client != item.id.client && dep.clock >= self.state(dep.client))
+    }
+
+    /// Split the item holding `id` so that a block starts at `id`, returning the start of that block
+    fn split_at(&mut self, id: BlockId) -> BlockId {
+        let index = self.index_of(id).expect("split block exists");
+        let slots = self.blocks.get_mut(&id.client).expect("split block exists");
+        let Slot::Item(left) = &mut slots[index] else {
+            return slots[index].id();
+        };
+        if left.id.clock == id.clock {
+            return id;
+        }
+
+        let offset = id.clock - left.id.clock;
+        let right = Stored {
+            id,
+            origin: Some(BlockId::new(id.client, id.clock - 1)),
+            right_origin: left.right_origin,
+            left: Some(left.id),
+            right: left.right,
+            parent: left.parent.clone(),
+            parent_sub: left.parent_sub.clone(),
+            content: left.content.split(offset),
+            deleted: left.deleted,
+        };
+        left.right = Some(id);
+        let (next, parent, key) = (right.right, right.parent.clone(), right.parent_sub.clone());
+        slots.insert(index + 1, Slot::Item(Box::new(right)));
+
+        match (next, key) {
+            (Some(next), _) => self.stored_mut(next).left = Some(id),
+            (None, Some(key)) => {
+                self.types.entry(parent).or_default().map.insert(key, id);
+            }
+            (None, None) => {}
+        }
+        self.touched.push(id);
+        id
+    }
+
+    /// Start of the block ending at `id`, splitting an item if needed
+    fn clean_end(&mut self, id: BlockId) -> Option<BlockId> {
+        let slot = self.slot(id)?;
+        let start = slot.id();
+        if matches!(slot, Slot::Item(_)) && id.clock + 1 < start.clock + slot.len() {
+            self.split_at(BlockId::new(id.client, id.clock + 1));
+        }
+        Some(start)
+    }
+
+    fn is_gc(&self, id: BlockId) -> bool {
+        matches!(self.slot(id), Some(Slot::Gc { .. }))
+    }
+
+    /// Place a block whose first `offset` units are already known
+    fn integrate(&mut self, block: Block, offset: u64) {
+        let mut item = match block {
+            Block::Item(item) => item,
+            Block::Gc { id, len } | Block::Skip { id, len } => {
+                self.push(Slot::Gc {
+                    id: BlockId::new(id.client, id.clock + offset),
+                    len: len - offset,
+                });
+                return;
+            }
+        };
+        if offset > 0 {
+            item.id.clock += offset;
+            item.origin = Some(BlockId::new(item.id.client, item.id.clock - 1));
+            item.content = item.content.split(offset);
+        }
+
+        // Validated blocks only refer to units already integrated
+        if item.origin.iter().chain(&item.right_origin).any(|id| self.slot(*id).is_none()) {
+            self.push(Slot::Gc { id: item.id, len: item.content.len() });
+            return;
+        }
+        let left = item.origin.and_then(|origin| self.clean_end(origin));
+        let right = item.right_origin.map(|right_origin| self.split_at(right_origin));
+        let neighbours_collected = left.is_some_and(|id| self.is_gc(id)) || right.is_some_and(|id| self.is_gc(id));
+        let parent = if neighbours_collected {
+            None
+        } else if let Some(parent) = item.parent.take() {
+            match parent {
+                Parent::Item(id) => self
+                    .slot(id)
+                    .map(Slot::id)
+                    .filter(|start| self.type_of(*start).is_some())
+                    .map(Parent::Item),
+                root => Some(root),
+            }
+        } else {
+            // Take parent and key from the origins, dropping the item if they disagree
+            let neighbours: Vec<_> = right
+                .into_iter()
+                .chain(left)
+                .map(|id| {
+                    let neighbour = self.stored(id);
+                    (neighbour.parent.clone(), neighbour.parent_sub.clone())
+                })
+                .collect();
+            match neighbours.as_slice() {
+                [(parent, key), rest @ ..] if rest.iter().all(|other| other.0 == *parent && other.1 == *key) => {
+                    item.parent_sub = key.clone();
+                    Some(parent.clone())
+                }
+                _ => None,
+            }
+        };
+        let Some(parent) = parent else {
+            self.push(Slot::Gc { id: item.id, len: item.content.len() });
+            return;
+        };
+        if let Some(left) = left {
+            item.origin = Some(self.stored(left).last_id());
+        }
+
+        let left = self.resolve_conflicts(&item, &parent, left, right);
+        let id = item.id;
+        let right = match left {
+            Some(left) => self.stored_mut(left).right.replace(id),
+            None => match &item.parent_sub {
+                Some(key) => self.first_of_key(&parent, key),
+                None => self.types.entry(parent.clone()).or_default().start.replace(id),
+            },
+        };
+        match (right, &item.parent_sub) {
+            (Some(right), _) => self.stored_mut(right).left = Some(id),
+            (None, Some(key)) => {
+                self.types.entry(parent.clone()).or_default().map.insert(key.clone(), id);
+                // The previous value of the key is overwritten
+                if let Some(left) = left {
+                    self.delete_item(left);
+                }
+            }
+            (None, None) => {}
+        }
+
+        let deleted_content = matches!(item.content, Content::Deleted(_));
+        if let Content::Type(kind) = &item.content {
+            self.types.entry(Parent::Item(id)).or_default().kind = Some(kind.clone());
+        }
+        let overwritten = item.parent_sub.is_some() && right.is_some();
+        self.push(Slot::Item(Box::new(Stored {
+            id,
+            origin: item.origin,
+            right_origin: item.right_origin,
+            left,
+            right,
+            parent: parent.clone(),
+            parent_sub: item.parent_sub,
+            content: item.content,
+            deleted: deleted_content,
+        })));
+        let parent_deleted = match parent {
+            Parent::Item(parent) => self.stored(parent).deleted,
+            Parent::Root(_) => false,
+        };
+        if parent_deleted || overwritten {
+            self.delete_item(id);
+        }
+    }
+
+    /// YATA: the item to insert after, among concurrent insertions between `left` and `right`
+    fn resolve_conflicts(&self, item: &Item, parent: &Parent, mut left: Option<BlockId>, right: Option<BlockId>) -> Option<BlockId> {
+        let adjacent = match left {
+            Some(left) => self.stored(left).right == right,
+            None => right.is_some_and(|right| self.stored(right).left.is_none()),
+        };
+        if adjacent {
+            return left;
+        }
+
+        let mut next = match (left, &item.parent_sub) {
+            (Some(left), _) => self.stored(left).right,
+            (None, Some(key)) => self.first_of_key(parent, key),
+            (None, None) => self.types.get(parent).and_then(|shared| shared.start),
+        };
+        let mut before_origin = HashSet::new();
+        let mut conflicting = HashSet::new();
+        while let Some(id) = next {
+            if Some(id) == right {
+                break;
+            }
+            before_origin.insert(id);
+            conflicting.insert(id);
+            let other = self.stored(id);
+            if other.origin == item.origin {
+                // Concurrent insertions at the same place are ordered by client
+                if other.id.client < item.id.client {
+                    left = Some(id);
+                    conflicting.clear();
+                } else if other.right_origin == item.right_origin {
+                    break;
+                }
+            } else if let Some(origin) = other.origin.and_then(|origin| self.slot(origin)).map(Slot::id).filter(|origin| before_origin.contains(origin)) {
+                if !conflicting.contains(&origin) {
+                    left = Some(id);
+                    conflicting.clear();
+                }
+            } else {
+                break;
+            }
+            next = other.right;
+        }
+        left
+    }
+
+    /// Oldest item of a map key
+    fn first_of_key(&self, parent: &Parent, key: &str) -> Option<BlockId> {
+        let mut first = *self.types.get(parent)?.map.get(key)?;
+        while let Some(left) = self.stored(first).left {
+            first = left;
+        }
+        Some(first)
+    }
+
+    fn push(&mut self, slot: Slot) {
+        let id = slot.id();
+        self.blocks.entry(id.client).or_default().push(slot);
+        self.touched.push(id);
+    }
+
+    /// Delete an item, replacing its content and collecting any nested type
+    fn delete_item(&mut self, id: BlockId) {
+        let item = self.stored_mut(id);
+        if item.deleted {
+            return;
+        }
+        item.deleted = true;
+        let len = item.len();
+        let content = std::mem::replace(&mut item.content, Content::Deleted(len));
+        if let Content::Type(_) = content {
+            self.collect_type(Parent::Item(id));
+        }
+        self.touched.push(id);
+    }
+
+    /// Replace every item of a deleted type with collected blocks
+    fn collect_type(&mut self, parent: Parent) {
+        let Some(shared) = self.types.remove(&parent) else {
+            return;
+        };
+        let mut children = Vec::new();
+        let mut next = shared.start;
+        while let Some(id) = next {
+            children.push(id);
+            next = self.stored(id).right;
+        }
+        for last in shared.map.into_values() {
+            let mut next = Some(last);
+            while let Some(id) = next {
+                children.push(id);
+                next = self.stored(id).left;
+            }
+        }
+        for id in children {
+            let index = self.index_of(id).expect("child exists");
+            let slots = self.blocks.get_mut(&id.client).expect("child exists");
+            let len = slots[index].len();
+            let old = std::mem::replace(&mut slots[index], Slot::Gc { id, len });
+            if matches!(old, Slot::Item(item) if matches!(item.content, Content::Type(_))) {
+                self.collect_type(Parent::Item(id));
+            }
+            self.touched.push(id);
+        }
+    }
+
+    /// Delete the items in `start..end` of a client's clock
+    fn delete_range(&mut self, client: u64, start: u64, end: u64) {
+        let Some(mut index) = self.index_of(BlockId::new(client, start)) else {
+            return;
+        };
+        if let Slot::Item(item) = &self.blocks[&client][index] {
+            if !item.deleted && item.id.clock < start {
+                self.split_at(BlockId::new(client, start));
+                index += 1;
+            }
+        }
+        while let Some(slot) = self.blocks[&client].get(index) {
+            let (id, len) = (slot.id(), slot.len());
+            if id.clock >= end {
+                break;
+            }
+            if let Slot::Item(item) = slot {
+                if !item.deleted {
+                    if id.clock + len > end {
+                        self.split_at(BlockId::new(client, end));
+                    }
+                    self.delete_item(id);
+                }
+            }
+            index += 1;
+        }
+    }
+
+    /// Length in units of a sequence
+    fn units_of(&self, parent: &Parent) -> usize {
+        self.sequence(parent).map(Stored::units).sum()
+    }
+
+    /// Items around `index`, splitting the item it falls into
+    fn find_position(&mut self, parent: &Parent, index: usize) -> Result<(Option<BlockId>, Option<BlockId>), YjsError> {
+        let len = self.units_of(parent);
+        if index > len {
+            return Err(YjsError::IndexOutOfBounds { index, len });
+        }
+        let mut left = None;
+        let mut next = self.types.get(parent).and_then(|shared| shared.start);
+        let mut remaining = index;
+        while remaining > 0 {
+            let id = next.expect("index is within the sequence");
+            let item = self.stored(id);
+            let units = item.units();
+            if remaining < units {
+                let split = BlockId::new(id.client, id.clock + item.offset_of(remaining));
+                let right = self.split_at(split);
+                return Ok((Some(id), Some(right)));
+            }
+            remaining -= units;
+            left = Some(id);
+            next = item.right;
+        }
+        Ok((left, next))
+    }
+
+    fn insert_content(&mut self, parent: Parent, index: usize, content: Content) -> Result<(), YjsError> {
+        let (left, right) = self.find_position(&parent, index)?;
+        let item = Item {
+            id: BlockId::new(self.client, self.state(self.client)),
+            origin: left.map(|left| self.stored(left).last_id()),
+            right_origin: right,
+            parent: Some(parent),
+            parent_sub: None,
+            content,
+        };
+        self.integrate(Block::Item(item), 0);
+        self.join_touched();
+        Ok(())
+    }
+
+    fn delete_units(&mut self, parent: Parent, index: usize, len: usize) -> Result<(), YjsError> {
+        let total = self.units_of(&parent);
+        if index + len > total {
+            return Err(YjsError::IndexOutOfBounds { index: index + len, len: total });
+        }
+        let (_, mut next) = self.find_position(&parent, index)?;
+        let mut remaining = len;
+        while remaining > 0 {
+            let id = next.expect("range is within the sequence");
+            let item = self.stored(id);
+            let units = item.units();
+            if units > 0 {
+                if remaining < units {
+                    let split = BlockId::new(id.client, id.clock + item.offset_of(remaining));
+                    self.split_at(split);
+                }
+                self.delete_item(id);
+                remaining -= units.min(remaining);
+            }
+            next = self.stored(id).right;
+        }
+        self.join_touched();
+        Ok(())
+    }
+
+    /// Join touched blocks with their neighbours, as Yjs does after each transaction
+    fn join_touched(&mut self) {
+        for id in std::mem::take(&mut self.touched) {
+            let Some(index) = self.index_of(id) else {
+                continue;
+            };
+            if !self.join(id.client, index + 1) {
+                self.join(id.client, index);
+            }
+        }
+    }
+
+    /// Join the block at `index` into the one before it if Yjs would
+    fn join(&mut self, client: u64, index: usize) -> bool {
+        if index == 0 {
+            return false;
+        }
+        let slots = self.blocks.get_mut(&client).expect("client has blocks");
+        let Some(right) = slots.get(index) else {
+            return false;
+        };
+        let joinable = match (&slots[index - 1], right) {
+            (Slot::Gc { .. }, Slot::Gc { .. }) => true,
+            (Slot::Item(left), Slot::Item(right)) => {
+                right.origin == Some(left.last_id())
+                    && left.right == Some(right.id)
+                    && left.right_origin == right.right_origin
+                    && left.deleted == right.deleted
+                    && std::mem::discriminant(&left.content) == std::mem::discriminant(&right.content)
+                    && matches!(left.content, Content::String(_) | Content::Any(_) | Content::Json(_) | Content::Deleted(_))
+            }
+            _ => false,
+        };
+        if !joinable {
+            return false;
+        }
+
+        let right = slots.remove(index);
+        match (&mut slots[index - 1], right) {
+            (Slot::Gc { len, .. }, right) => *len += right.len(),
+            (Slot::Item(left), Slot::Item(right)) => {
+                left.content.try_merge(&right.content);
+                left.right = right.right;
+                let left_id = left.id;
+                if let Some(next) = right.right {
+                    self.stored_mut(next).left = Some(left_id);
+                } else if let Some(key) = right.parent_sub {
+                    if let Some(shared) = self.types.get_mut(&right.parent) {
+                        if shared.map.get(&key) == Some(&right.id) {
+                            shared.map.insert(key, left_id);
+                        }
+                    }
+                }
+            }
+            _ => unreachable!("checked above"),
+        }
+        true
+    }
+}
+
+impl CRDT for YjsDoc {
+    fn replica_id(&self) -> &ReplicaId {
+        &self.replica_id
+    }
+}
+
+impl Mergeable for YjsDoc {
+    type Error = YjsError;
+
+    fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+        let mut update = other.diff(&self.state_vector());
+        update.blocks.extend(other.pending.iter().cloned());
+        for (client, clock, len) in other.pending_deletes.iter() {
+            update.delete_set.insert(client, clock, len);
+        }
+        self.apply_update(update)
+    }
+
+    fn has_conflict(&self, _other: &Self) -> bool {
+        // Integration orders every concurrent insertion
+        false
+    }
+}
+
+// Stored as the replica ID and the document as a Yjs update
+impl Serialize for YjsDoc {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut update = self.diff(&StateVector::new());
+        update.blocks.extend(self.pending.iter().cloned());
+        for (client, clock, len) in self.pending_deletes.iter() {
+            update.delete_set.insert(client, clock, len);
+        }
+        (self.replica_id, update.encode_v1()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for YjsDoc {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (replica_id, bytes) = <(ReplicaId, Vec<u8>)>::deserialize(deserializer)?;
+        let mut doc = YjsDoc::new(replica_id);
+        doc.apply_update_v1(&bytes).map_err(serde::de::Error::custom)?;
+        Ok(doc)
+    }
+}
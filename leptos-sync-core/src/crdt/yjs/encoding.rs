@@ -0,0 +1,279 @@
+//! lib0 binary encoding used by Yjs
+//!
+//! Integers are variable-length little-endian groups of seven bits, strings
+//! are length-prefixed UTF-8 and [`Any`] values are tagged, as written by the
+//! `lib0/encoding` module Yjs is built on.
+
+use super::error::YjsError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Largest integer lib0 writes as a variable-length integer rather than a float
+const MAX_VAR_INT: f64 = 0x7FFF_FFFF as f64;
+
+/// A lib0 `any` value, the JSON-like values stored in Yjs maps and arrays
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Any {
+    Undefined,
+    Null,
+    Bool(bool),
+    /// JavaScript number
+    Number(f64),
+    /// JavaScript bigint
+    BigInt(i64),
+    String(String),
+    /// `Uint8Array`
+    Buffer(Vec<u8>),
+    Array(Vec<Any>),
+    /// Object, with keys in insertion order
+    Map(Vec<(String, Any)>),
+}
+
+impl Any {
+    /// The value as JSON; `undefined` becomes `null` and buffers arrays of bytes
+    pub fn to_json(&self) -> Value {
+        match self {
+            Any::Undefined | Any::Null => Value::Null,
+            Any::Bool(value) => Value::Bool(*value),
+            Any::Number(value) => {
+                if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+                    Value::from(*value as i64)
+                } else {
+                    serde_json::Number::from_f64(*value).map_or(Value::Null, Value::Number)
+                }
+            }
+            Any::BigInt(value) => Value::from(*value),
+            Any::String(value) => Value::String(value.clone()),
+            Any::Buffer(bytes) => bytes.iter().copied().map(Value::from).collect(),
+            Any::Array(values) => values.iter().map(Any::to_json).collect(),
+            Any::Map(entries) => Value::Object(entries.iter().map(|(key, value)| (key.clone(), value.to_json())).collect()),
+        }
+    }
+}
+
+impl From<Value> for Any {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Any::Null,
+            Value::Bool(value) => Any::Bool(value),
+            Value::Number(number) => Any::Number(number.as_f64().unwrap_or_default()),
+            Value::String(value) => Any::String(value),
+            Value::Array(values) => Any::Array(values.into_iter().map(Any::from).collect()),
+            Value::Object(entries) => Any::Map(entries.into_iter().map(|(key, value)| (key, Any::from(value))).collect()),
+        }
+    }
+}
+
+/// Writes lib0-encoded values to a buffer
+#[derive(Debug, Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    /// Create an empty encoder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The bytes written so far
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_var_uint(&mut self, mut value: u64) {
+        while value > 0x7F {
+            self.buf.push(0x80 | (value & 0x7F) as u8);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    /// Write a signed integer: the first byte holds the sign and six bits
+    pub fn write_var_int(&mut self, value: i64, negative: bool) {
+        let mut rest = value.unsigned_abs();
+        let first = (rest & 0x3F) as u8 | if negative { 0x40 } else { 0 };
+        rest >>= 6;
+        self.buf.push(first | if rest > 0 { 0x80 } else { 0 });
+        while rest > 0 {
+            let byte = (rest & 0x7F) as u8;
+            rest >>= 7;
+            self.buf.push(byte | if rest > 0 { 0x80 } else { 0 });
+        }
+    }
+
+    pub fn write_buf(&mut self, bytes: &[u8]) {
+        self.write_var_uint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn write_string(&mut self, value: &str) {
+        self.write_buf(value.as_bytes());
+    }
+
+    pub fn write_any(&mut self, value: &Any) {
+        match value {
+            Any::Undefined => self.write_u8(127),
+            Any::Null => self.write_u8(126),
+            Any::Bool(true) => self.write_u8(120),
+            Any::Bool(false) => self.write_u8(121),
+            Any::Number(number) => {
+                if number.fract() == 0.0 && number.abs() <= MAX_VAR_INT {
+                    self.write_u8(125);
+                    self.write_var_int(*number as i64, number.is_sign_negative());
+                } else if (*number as f32) as f64 == *number {
+                    self.write_u8(124);
+                    self.buf.extend_from_slice(&(*number as f32).to_be_bytes());
+                } else {
+                    self.write_u8(123);
+                    self.buf.extend_from_slice(&number.to_be_bytes());
+                }
+            }
+            Any::BigInt(number) => {
+                self.write_u8(122);
+                self.buf.extend_from_slice(&number.to_be_bytes());
+            }
+            Any::String(value) => {
+                self.write_u8(119);
+                self.write_string(value);
+            }
+            Any::Map(entries) => {
+                self.write_u8(118);
+                self.write_var_uint(entries.len() as u64);
+                for (key, value) in entries {
+                    self.write_string(key);
+                    self.write_any(value);
+                }
+            }
+            Any::Array(values) => {
+                self.write_u8(117);
+                self.write_var_uint(values.len() as u64);
+                for value in values {
+                    self.write_any(value);
+                }
+            }
+            Any::Buffer(bytes) => {
+                self.write_u8(116);
+                self.write_buf(bytes);
+            }
+        }
+    }
+}
+
+/// Reads lib0-encoded values from a buffer
+#[derive(Debug)]
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Whether every byte has been read
+    pub fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, YjsError> {
+        let byte = *self.buf.get(self.pos).ok_or(YjsError::UnexpectedEnd)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn read_exact(&mut self, len: usize) -> Result<&'a [u8], YjsError> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.buf.len()).ok_or(YjsError::UnexpectedEnd)?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn read_var_uint(&mut self) -> Result<u64, YjsError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            if shift > 63 || (shift == 63 && byte & 0x7F > 1) {
+                return Err(YjsError::IntegerOverflow);
+            }
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Read a signed integer, returning it with its sign bit (lib0 has a negative zero)
+    pub fn read_var_int(&mut self) -> Result<(i64, bool), YjsError> {
+        let first = self.read_u8()?;
+        let negative = first & 0x40 != 0;
+        let mut value = (first & 0x3F) as u64;
+        let mut more = first & 0x80 != 0;
+        let mut shift = 6;
+        while more {
+            let byte = self.read_u8()?;
+            if shift > 62 {
+                return Err(YjsError::IntegerOverflow);
+            }
+            value |= ((byte & 0x7F) as u64) << shift;
+            more = byte & 0x80 != 0;
+            shift += 7;
+        }
+        let value = i64::try_from(value).map_err(|_| YjsError::IntegerOverflow)?;
+        Ok((if negative { -value } else { value }, negative))
+    }
+
+    pub fn read_buf(&mut self) -> Result<&'a [u8], YjsError> {
+        let len = self.read_var_uint()?;
+        self.read_exact(usize::try_from(len).map_err(|_| YjsError::IntegerOverflow)?)
+    }
+
+    pub fn read_string(&mut self) -> Result<String, YjsError> {
+        let bytes = self.read_buf()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| YjsError::InvalidUtf8)
+    }
+
+    pub fn read_any(&mut self) -> Result<Any, YjsError> {
+        Ok(match self.read_u8()? {
+            127 => Any::Undefined,
+            126 => Any::Null,
+            125 => {
+                let (value, negative) = self.read_var_int()?;
+                let number = value as f64;
+                Any::Number(if negative && value == 0 { -0.0 } else { number })
+            }
+            124 => Any::Number(f32::from_be_bytes(self.read_exact(4)?.try_into().expect("four bytes")) as f64),
+            123 => Any::Number(f64::from_be_bytes(self.read_exact(8)?.try_into().expect("eight bytes"))),
+            122 => Any::BigInt(i64::from_be_bytes(self.read_exact(8)?.try_into().expect("eight bytes"))),
+            121 => Any::Bool(false),
+            120 => Any::Bool(true),
+            119 => Any::String(self.read_string()?),
+            118 => {
+                let len = self.read_var_uint()?;
+                let mut entries = Vec::new();
+                for _ in 0..len {
+                    let key = self.read_string()?;
+                    entries.push((key, self.read_any()?));
+                }
+                Any::Map(entries)
+            }
+            117 => {
+                let len = self.read_var_uint()?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(self.read_any()?);
+                }
+                Any::Array(values)
+            }
+            116 => Any::Buffer(self.read_buf()?.to_vec()),
+            tag => return Err(YjsError::InvalidAny(tag)),
+        })
+    }
+}
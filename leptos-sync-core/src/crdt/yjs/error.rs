@@ -0,0 +1,39 @@
+//! Error types for Yjs interop
+
+use crate::crdt::AdvancedCrdtError;
+use thiserror::Error;
+
+/// Errors from decoding Yjs updates or editing a Yjs document
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum YjsError {
+    /// The buffer ended in the middle of a value
+    #[error("Unexpected end of Yjs update")]
+    UnexpectedEnd,
+    /// A variable-length integer does not fit in 64 bits
+    #[error("Integer overflow in Yjs update")]
+    IntegerOverflow,
+    /// A string is not valid UTF-8
+    #[error("Invalid UTF-8 in Yjs update")]
+    InvalidUtf8,
+    /// An embedded JSON value could not be parsed
+    #[error("Invalid JSON in Yjs update: {0}")]
+    InvalidJson(String),
+    /// Unknown tag of a lib0 `any` value
+    #[error("Unknown value tag {0}")]
+    InvalidAny(u8),
+    /// Unknown item content reference
+    #[error("Unknown content reference {0}")]
+    InvalidContent(u8),
+    /// Unknown shared type reference
+    #[error("Unknown type reference {0}")]
+    InvalidTypeRef(u64),
+    /// A block refers to units of its own client that cannot precede it, or has no parent
+    #[error("Invalid block {client}:{clock} in Yjs update")]
+    InvalidBlock { client: u64, clock: u64 },
+    /// An index or range is past the end of a shared type
+    #[error("Index {index} out of bounds for length {len}")]
+    IndexOutOfBounds { index: usize, len: usize },
+    /// Building a snapshot of a shared type failed
+    #[error(transparent)]
+    Sequence(#[from] AdvancedCrdtError),
+}
@@ -0,0 +1,307 @@
+//! Yjs interoperability
+//!
+//! Reads and writes the binary formats Yjs clients exchange: v1 updates from
+//! `Y.encodeStateAsUpdate` and state vectors from `Y.encodeStateVector`. A
+//! [`YjsDoc`] integrates updates the way Yjs does, so a Rust replica can sync
+//! with JavaScript peers through any Yjs provider, and its texts, arrays,
+//! maps and XML fragments can be copied into [`Rga`](crate::crdt::Rga),
+//! [`LwwMap`](crate::crdt::LwwMap) and [`YjsTree`](crate::crdt::YjsTree).
+
+mod convert;
+pub mod doc;
+pub mod encoding;
+pub mod error;
+pub mod update;
+
+// Re-export public types
+pub use doc::{client_id, replica_for_client, YjsDoc};
+pub use encoding::Any;
+pub use error::YjsError;
+pub use update::{Block, BlockId, Content, DeleteSet, Item, Parent, StateVector, TypeRef, YjsUpdate};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{Mergeable, ReplicaId};
+    use serde_json::{json, Value};
+
+    fn create_replica(id: u64) -> ReplicaId {
+        replica_for_client(id)
+    }
+
+    // Updates below are laid out byte by byte as Yjs 13 encodes them
+
+    /// Client 1: `doc.getText('text').insert(0, 'abc')`
+    const TEXT_ABC: &[u8] = &[
+        0x01, 0x01, 0x01, 0x00, // one client with one block, client 1 from clock 0
+        0x04, 0x01, 0x04, b't', b'e', b'x', b't', // string item in root type "text"
+        0x03, b'a', b'b', b'c', //
+        0x00, // empty delete set
+    ];
+
+    /// Client 1 after `text.delete(1, 1)` on [`TEXT_ABC`]
+    const TEXT_AC: &[u8] = &[
+        0x01, 0x03, 0x01, 0x00, //
+        0x04, 0x01, 0x04, b't', b'e', b'x', b't', 0x01, b'a', // "a"
+        0x81, 0x01, 0x00, 0x01, // deleted unit after 1:0
+        0x84, 0x01, 0x01, 0x01, b'c', // "c" after 1:1
+        0x01, 0x01, 0x01, 0x01, 0x01, // client 1 deleted clock 1, length 1
+    ];
+
+    /// Client 2: `map.set('a', 1); map.set('b', 'x')` on `doc.getMap('map')`
+    const MAP_AB: &[u8] = &[
+        0x01, 0x02, 0x02, 0x00, //
+        0x28, 0x01, 0x03, b'm', b'a', b'p', 0x01, b'a', 0x01, 0x7D, 0x01, // key "a", any [1]
+        0x28, 0x01, 0x03, b'm', b'a', b'p', 0x01, b'b', 0x01, 0x77, 0x01, b'x', // key "b", any ["x"]
+        0x00,
+    ];
+
+    /// Client 3: `doc.getArray('array').insert(0, [1, true, null, 'x'])`
+    const ARRAY: &[u8] = &[
+        0x01, 0x01, 0x03, 0x00, //
+        0x08, 0x01, 0x05, b'a', b'r', b'r', b'a', b'y', //
+        0x04, 0x7D, 0x01, 0x78, 0x7E, 0x77, 0x01, b'x', //
+        0x00,
+    ];
+
+    /// Client 5: `<paragraph>hi</paragraph>` in `doc.getXmlFragment('prosemirror')`
+    const PROSEMIRROR: &[u8] = &[
+        0x01, 0x03, 0x05, 0x00, //
+        0x07, 0x01, 0x0B, b'p', b'r', b'o', b's', b'e', b'm', b'i', b'r', b'r', b'o', b'r', // element in root fragment
+        0x03, 0x09, b'p', b'a', b'r', b'a', b'g', b'r', b'a', b'p', b'h', //
+        0x07, 0x00, 0x05, 0x00, 0x06, // text node in item 5:0
+        0x04, 0x00, 0x05, 0x01, 0x02, b'h', b'i', // "hi" in item 5:1
+        0x00,
+    ];
+
+    /// Produced by Yjs for `text.insert(0, '0')`, `'1'` and `'2'` in one transaction
+    /// on `doc.getText('type')`, as recorded in the y-crdt test suite
+    const YJS_TEXT_210: &[u8] = &[
+        1, 3, 227, 214, 245, 198, 5, 0, 4, 1, 4, 116, 121, 112, 101, 1, 48, 68, 227, 214, 245, 198, 5, 0, 1, 49, 68,
+        227, 214, 245, 198, 5, 1, 1, 50, 0,
+    ];
+
+    fn doc_from(id: u64, update: &[u8]) -> YjsDoc {
+        let mut doc = YjsDoc::new(create_replica(id));
+        doc.apply_update_v1(update).unwrap();
+        doc
+    }
+
+    fn sync(a: &mut YjsDoc, b: &mut YjsDoc) {
+        let to_b = a.encode_state_as_update_v1(&b.state_vector());
+        let to_a = b.encode_state_as_update_v1(&a.state_vector());
+        b.apply_update_v1(&to_b).unwrap();
+        a.apply_update_v1(&to_a).unwrap();
+    }
+
+    #[test]
+    fn test_fixtures_decode_and_reencode() {
+        for fixture in [TEXT_ABC, TEXT_AC, MAP_AB, ARRAY, PROSEMIRROR, YJS_TEXT_210] {
+            let update = YjsUpdate::decode_v1(fixture).unwrap();
+            assert_eq!(update.encode_v1(), fixture);
+            // Integrating and encoding the whole document gives the same update
+            assert_eq!(doc_from(9, fixture).encode_state_as_update_v1(&StateVector::new()), fixture);
+        }
+        assert!(YjsUpdate::decode_v1(&TEXT_ABC[..8]).is_err());
+    }
+
+    #[test]
+    fn test_fixtures_read_as_json() {
+        assert_eq!(doc_from(9, TEXT_ABC).text("text"), "abc");
+        assert_eq!(doc_from(9, TEXT_AC).text("text"), "ac");
+        assert_eq!(doc_from(9, YJS_TEXT_210).text("type"), "210");
+        assert_eq!(Value::Object(doc_from(9, MAP_AB).map("map")), json!({"a": 1, "b": "x"}));
+        assert_eq!(doc_from(9, ARRAY).array("array"), vec![json!(1), json!(true), json!(null), json!("x")]);
+        assert_eq!(
+            doc_from(9, PROSEMIRROR).xml("prosemirror"),
+            vec![json!({"nodeName": "paragraph", "attributes": {}, "children": ["hi"]})]
+        );
+    }
+
+    #[test]
+    fn test_local_edits_encode_like_yjs() {
+        let mut doc = YjsDoc::new(create_replica(1));
+        doc.insert_text("text", 0, "abc").unwrap();
+        assert_eq!(doc.encode_state_as_update_v1(&StateVector::new()), TEXT_ABC);
+        doc.delete_text("text", 1, 1).unwrap();
+        assert_eq!(doc.encode_state_as_update_v1(&StateVector::new()), TEXT_AC);
+
+        let mut map = YjsDoc::new(create_replica(2));
+        map.map_set("map", "a", 1);
+        map.map_set("map", "b", "x");
+        assert_eq!(map.encode_state_as_update_v1(&StateVector::new()), MAP_AB);
+
+        let mut array = YjsDoc::new(create_replica(3));
+        array.insert_array("array", 0, vec![json!(1), json!(true), json!(null), json!("x")]).unwrap();
+        assert_eq!(array.encode_state_as_update_v1(&StateVector::new()), ARRAY);
+    }
+
+    #[test]
+    fn test_appends_join_and_diff_from_state_vector() {
+        let mut doc = doc_from(1, TEXT_ABC);
+        let seen = doc.state_vector();
+        assert_eq!(seen.encode_v1(), vec![0x01, 0x01, 0x03]);
+
+        // Typing at the end joins the item, as Yjs does after each transaction
+        let mut typist = YjsDoc::new(create_replica(1));
+        typist.apply_update_v1(TEXT_ABC).unwrap();
+        typist.insert_text("text", 3, "d").unwrap();
+        let full = typist.encode_state_as_update_v1(&StateVector::new());
+        assert_eq!(full, [&TEXT_ABC[..11], &[0x04, b'a', b'b', b'c', b'd', 0x00]].concat());
+
+        // Only the new unit is sent to a peer that has seen the first three
+        let diff = typist.encode_state_as_update_v1(&seen);
+        assert_eq!(diff, vec![0x01, 0x01, 0x01, 0x03, 0x84, 0x01, 0x02, 0x01, b'd', 0x00]);
+        doc.apply_update_v1(&diff).unwrap();
+        assert_eq!(doc.text("text"), "abcd");
+    }
+
+    #[test]
+    fn test_concurrent_inserts_order_by_client() {
+        let mut a = YjsDoc::new(create_replica(1));
+        let mut b = YjsDoc::new(create_replica(2));
+        a.insert_text("text", 0, "a").unwrap();
+        b.insert_text("text", 0, "b").unwrap();
+        sync(&mut a, &mut b);
+        assert_eq!(a.text("text"), "ab");
+        assert_eq!(b.text("text"), "ab");
+
+        // Clients are written in descending order
+        let update = a.encode_state_as_update_v1(&StateVector::new());
+        assert_eq!(update[..4], [0x02, 0x01, 0x02, 0x00]);
+        assert_eq!(b.state_vector().encode_v1(), vec![0x02, 0x02, 0x01, 0x01, 0x01]);
+    }
+
+    #[test]
+    fn test_interleaved_edits_converge() {
+        let mut a = YjsDoc::new(create_replica(1));
+        let mut b = YjsDoc::new(create_replica(2));
+        let mut c = YjsDoc::new(create_replica(3));
+        a.insert_text("text", 0, "hello world").unwrap();
+        sync(&mut a, &mut b);
+        sync(&mut b, &mut c);
+
+        a.insert_text("text", 5, ",").unwrap();
+        b.delete_text("text", 0, 6).unwrap();
+        b.insert_text("text", 0, "big ").unwrap();
+        c.insert_text("text", 11, "!").unwrap();
+        c.insert_text("text", 6, "wide ").unwrap();
+        a.map_set("meta", "title", "greeting");
+        c.map_set("meta", "title", "salute");
+
+        sync(&mut a, &mut b);
+        sync(&mut b, &mut c);
+        sync(&mut a, &mut b);
+        assert_eq!(a.text("text"), "big ,wide world!");
+        assert_eq!(a.text("text"), b.text("text"));
+        assert_eq!(b.text("text"), c.text("text"));
+        // The higher client wins concurrent writes of a key
+        assert_eq!(a.map("meta"), c.map("meta"));
+        assert_eq!(a.map("meta")["title"], json!("salute"));
+        assert_eq!(
+            a.encode_state_as_update_v1(&StateVector::new()),
+            c.encode_state_as_update_v1(&StateVector::new())
+        );
+    }
+
+    #[test]
+    fn test_updates_wait_for_missing_items() {
+        let mut a = YjsDoc::new(create_replica(1));
+        a.insert_text("text", 0, "ab").unwrap();
+        let first = a.encode_state_as_update_v1(&StateVector::new());
+        let mut b = doc_from(2, &first);
+        b.insert_text("text", 1, "x").unwrap();
+        let second = b.encode_state_as_update_v1(&a.state_vector());
+        a.delete_text("text", 0, 1).unwrap();
+        let deletion = a.encode_state_as_update_v1(&a.state_vector());
+
+        let mut c = YjsDoc::new(create_replica(3));
+        c.apply_update_v1(&second).unwrap();
+        c.apply_update_v1(&deletion).unwrap();
+        assert!(c.has_pending());
+        assert_eq!(c.text("text"), "");
+
+        c.apply_update_v1(&first).unwrap();
+        assert!(!c.has_pending());
+        assert_eq!(c.text("text"), "xb");
+    }
+
+    #[test]
+    fn test_malformed_updates_are_rejected() {
+        let mut doc = doc_from(9, TEXT_ABC);
+        // Origin 1:5 lies after the item 1:0 that refers to it
+        let ahead = [1, 1, 1, 0, 0x84, 1, 5, 1, b'a', 0];
+        assert_eq!(doc.apply_update_v1(&ahead), Err(YjsError::InvalidBlock { client: 1, clock: 0 }));
+        // Empty string item
+        let empty = [1, 1, 2, 0, 0x04, 1, 1, b't', 0, 0];
+        assert_eq!(doc.apply_update_v1(&empty), Err(YjsError::InvalidBlock { client: 2, clock: 0 }));
+        // Blocks past the end of the clock
+        let overflow = [1, 2, 2, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x02, 0x00, 0x01, 0x00];
+        assert_eq!(doc.apply_update_v1(&overflow), Err(YjsError::IntegerOverflow));
+
+        // An item with neither parent nor origins
+        let orphan = Item {
+            id: BlockId::new(2, 0),
+            origin: None,
+            right_origin: None,
+            parent: None,
+            parent_sub: None,
+            content: Content::String("x".to_string()),
+        };
+        let update = YjsUpdate { blocks: vec![Block::Item(orphan)], delete_set: DeleteSet::new() };
+        assert!(doc.apply_update(update).is_err());
+        assert_eq!(doc.text("text"), "abc");
+        assert!(!doc.has_pending());
+    }
+
+    #[test]
+    fn test_merge_and_serde_round_trip() {
+        let mut a = doc_from(1, TEXT_ABC);
+        let mut b = doc_from(2, MAP_AB);
+        b.insert_text("text", 0, ">").unwrap();
+        a.merge(&b).unwrap();
+        // Concurrent insertions at the start are ordered by client
+        assert_eq!(a.text("text"), "abc>");
+        assert_eq!(a.map("map")["b"], json!("x"));
+
+        let bytes = bincode::serialize(&a).unwrap();
+        let loaded: YjsDoc = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(loaded.client(), 1);
+        assert_eq!(loaded.text("text"), "abc>");
+        assert_eq!(loaded.state_vector(), a.state_vector());
+    }
+
+    #[test]
+    fn test_snapshots_as_crate_crdts() {
+        let mut doc = doc_from(1, TEXT_AC);
+        doc.apply_update_v1(MAP_AB).unwrap();
+        doc.apply_update_v1(ARRAY).unwrap();
+        doc.apply_update_v1(PROSEMIRROR).unwrap();
+
+        assert_eq!(doc.text_as_rga("text").unwrap().to_vec(), vec!['a', 'c']);
+        assert_eq!(doc.array_as_rga("array").unwrap().len(), 4);
+
+        let map = doc.map_as_lww("map");
+        assert_eq!(map.get(&"a".to_string()), Some(&json!(1)));
+        assert_eq!(map.get_register(&"b".to_string()).unwrap().replica_id(), create_replica(2));
+
+        let tree = doc.xml_as_tree("prosemirror").unwrap().to_tree().unwrap();
+        assert_eq!(tree.value, "prosemirror");
+        assert_eq!(tree.children[0].value, "paragraph");
+        assert_eq!(tree.children[0].children[0].value, "hi");
+    }
+
+    #[test]
+    fn test_text_indices_count_characters() {
+        let mut doc = YjsDoc::new(create_replica(1));
+        doc.insert_text("text", 0, "a😀c").unwrap();
+        doc.insert_text("text", 2, "b").unwrap();
+        assert_eq!(doc.text("text"), "a😀bc");
+        doc.delete_text("text", 1, 1).unwrap();
+        assert_eq!(doc.text("text"), "abc");
+        assert!(doc.delete_text("text", 2, 2).is_err());
+
+        // Yjs counts the emoji as two units of the clock
+        assert_eq!(doc.state_vector().get(1), 5);
+    }
+}
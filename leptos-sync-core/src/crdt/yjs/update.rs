@@ -0,0 +1,603 @@
+//! Yjs v1 update and state vector formats
+//!
+//! An update lists, for each client, a run of consecutive blocks starting at
+//! some clock, followed by a delete set of clock ranges. Clients are written
+//! in descending order, as Yjs writes them, so decoding and re-encoding an
+//! update produced by Yjs gives the same bytes.
+
+use super::encoding::{Any, Decoder, Encoder};
+use super::error::YjsError;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+const HAS_ORIGIN: u8 = 0x80;
+const HAS_RIGHT_ORIGIN: u8 = 0x40;
+const HAS_PARENT_SUB: u8 = 0x20;
+const CONTENT_MASK: u8 = 0x1F;
+
+const GC_REF: u8 = 0;
+const SKIP_REF: u8 = 10;
+
+/// ID of one unit of content: the client that created it and its clock
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId {
+    pub client: u64,
+    pub clock: u64,
+}
+
+impl BlockId {
+    pub fn new(client: u64, clock: u64) -> Self {
+        Self { client, clock }
+    }
+
+    fn write(&self, encoder: &mut Encoder) {
+        encoder.write_var_uint(self.client);
+        encoder.write_var_uint(self.clock);
+    }
+
+    fn read(decoder: &mut Decoder) -> Result<Self, YjsError> {
+        Ok(Self::new(decoder.read_var_uint()?, decoder.read_var_uint()?))
+    }
+}
+
+/// Shared type an item belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Parent {
+    /// Top-level type, by name
+    Root(String),
+    /// Type nested in the content of an item
+    Item(BlockId),
+}
+
+/// Kind of a shared type created by an item
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeRef {
+    Array,
+    Map,
+    Text,
+    XmlElement(String),
+    XmlFragment,
+    XmlHook(String),
+    XmlText,
+}
+
+impl TypeRef {
+    fn write(&self, encoder: &mut Encoder) {
+        match self {
+            TypeRef::Array => encoder.write_var_uint(0),
+            TypeRef::Map => encoder.write_var_uint(1),
+            TypeRef::Text => encoder.write_var_uint(2),
+            TypeRef::XmlElement(name) => {
+                encoder.write_var_uint(3);
+                encoder.write_string(name);
+            }
+            TypeRef::XmlFragment => encoder.write_var_uint(4),
+            TypeRef::XmlHook(name) => {
+                encoder.write_var_uint(5);
+                encoder.write_string(name);
+            }
+            TypeRef::XmlText => encoder.write_var_uint(6),
+        }
+    }
+
+    fn read(decoder: &mut Decoder) -> Result<Self, YjsError> {
+        Ok(match decoder.read_var_uint()? {
+            0 => TypeRef::Array,
+            1 => TypeRef::Map,
+            2 => TypeRef::Text,
+            3 => TypeRef::XmlElement(decoder.read_string()?),
+            4 => TypeRef::XmlFragment,
+            5 => TypeRef::XmlHook(decoder.read_string()?),
+            6 => TypeRef::XmlText,
+            other => return Err(YjsError::InvalidTypeRef(other)),
+        })
+    }
+}
+
+/// Content of an item
+#[derive(Debug, Clone, PartialEq)]
+pub enum Content {
+    /// Placeholder for this many deleted units
+    Deleted(u64),
+    /// Legacy JSON values; `None` is `undefined`
+    Json(Vec<Option<Value>>),
+    Binary(Vec<u8>),
+    /// Text, one unit per UTF-16 code unit
+    String(String),
+    /// Embedded object in text
+    Embed(Value),
+    /// Formatting mark in text
+    Format { key: String, value: Value },
+    /// A nested shared type
+    Type(TypeRef),
+    /// Values of an array or map
+    Any(Vec<Any>),
+    /// Subdocument
+    Doc { guid: String, options: Any },
+}
+
+impl Content {
+    fn ref_number(&self) -> u8 {
+        match self {
+            Content::Deleted(_) => 1,
+            Content::Json(_) => 2,
+            Content::Binary(_) => 3,
+            Content::String(_) => 4,
+            Content::Embed(_) => 5,
+            Content::Format { .. } => 6,
+            Content::Type(_) => 7,
+            Content::Any(_) => 8,
+            Content::Doc { .. } => 9,
+        }
+    }
+
+    /// Number of clock units the content takes
+    pub fn len(&self) -> u64 {
+        match self {
+            Content::Deleted(len) => *len,
+            Content::Json(values) => values.len() as u64,
+            Content::String(text) => text.encode_utf16().count() as u64,
+            Content::Any(values) => values.len() as u64,
+            _ => 1,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether the content counts towards the length of its type
+    pub fn is_countable(&self) -> bool {
+        !matches!(self, Content::Deleted(_) | Content::Format { .. })
+    }
+
+    /// Split off the content from `offset` clock units on
+    pub(crate) fn split(&mut self, offset: u64) -> Content {
+        let offset_usize = offset as usize;
+        match self {
+            Content::Deleted(len) => {
+                let right = *len - offset;
+                *len = offset;
+                Content::Deleted(right)
+            }
+            Content::Json(values) => Content::Json(values.split_off(offset_usize)),
+            Content::Any(values) => Content::Any(values.split_off(offset_usize)),
+            Content::String(text) => {
+                let units: Vec<u16> = text.encode_utf16().collect();
+                let (mut left, mut right) = (units[..offset_usize].to_vec(), units[offset_usize..].to_vec());
+                // Splitting a surrogate pair leaves a replacement character on both sides, as Yjs does
+                if left.last().is_some_and(|unit| (0xD800..0xDC00).contains(unit)) {
+                    *left.last_mut().expect("checked above") = 0xFFFD;
+                    right[0] = 0xFFFD;
+                }
+                *text = String::from_utf16_lossy(&left);
+                Content::String(String::from_utf16_lossy(&right))
+            }
+            _ => unreachable!("content of length 1 is never split"),
+        }
+    }
+
+    /// Append `other` if both are of a kind Yjs joins into one item
+    pub(crate) fn try_merge(&mut self, other: &Content) -> bool {
+        match (self, other) {
+            (Content::Deleted(len), Content::Deleted(other)) => *len += other,
+            (Content::Json(values), Content::Json(other)) => values.extend(other.iter().cloned()),
+            (Content::Any(values), Content::Any(other)) => values.extend(other.iter().cloned()),
+            (Content::String(text), Content::String(other)) => text.push_str(other),
+            _ => return false,
+        }
+        true
+    }
+
+    fn write(&self, encoder: &mut Encoder) {
+        match self {
+            Content::Deleted(len) => encoder.write_var_uint(*len),
+            Content::Json(values) => {
+                encoder.write_var_uint(values.len() as u64);
+                for value in values {
+                    match value {
+                        Some(value) => encoder.write_string(&value.to_string()),
+                        None => encoder.write_string("undefined"),
+                    }
+                }
+            }
+            Content::Binary(bytes) => encoder.write_buf(bytes),
+            Content::String(text) => encoder.write_string(text),
+            Content::Embed(value) => encoder.write_string(&value.to_string()),
+            Content::Format { key, value } => {
+                encoder.write_string(key);
+                encoder.write_string(&value.to_string());
+            }
+            Content::Type(type_ref) => type_ref.write(encoder),
+            Content::Any(values) => {
+                encoder.write_var_uint(values.len() as u64);
+                for value in values {
+                    encoder.write_any(value);
+                }
+            }
+            Content::Doc { guid, options } => {
+                encoder.write_string(guid);
+                encoder.write_any(options);
+            }
+        }
+    }
+
+    fn read(decoder: &mut Decoder, content_ref: u8) -> Result<Self, YjsError> {
+        Ok(match content_ref {
+            1 => Content::Deleted(decoder.read_var_uint()?),
+            2 => {
+                let len = decoder.read_var_uint()?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    let text = decoder.read_string()?;
+                    values.push(if text == "undefined" { None } else { Some(parse_json(&text)?) });
+                }
+                Content::Json(values)
+            }
+            3 => Content::Binary(decoder.read_buf()?.to_vec()),
+            4 => Content::String(decoder.read_string()?),
+            5 => Content::Embed(parse_json(&decoder.read_string()?)?),
+            6 => {
+                let key = decoder.read_string()?;
+                Content::Format { key, value: parse_json(&decoder.read_string()?)? }
+            }
+            7 => Content::Type(TypeRef::read(decoder)?),
+            8 => {
+                let len = decoder.read_var_uint()?;
+                let mut values = Vec::new();
+                for _ in 0..len {
+                    values.push(decoder.read_any()?);
+                }
+                Content::Any(values)
+            }
+            9 => {
+                let guid = decoder.read_string()?;
+                Content::Doc { guid, options: decoder.read_any()? }
+            }
+            other => return Err(YjsError::InvalidContent(other)),
+        })
+    }
+}
+
+fn parse_json(text: &str) -> Result<Value, YjsError> {
+    serde_json::from_str(text).map_err(|e| YjsError::InvalidJson(e.to_string()))
+}
+
+/// An inserted piece of content
+///
+/// Items are placed between their origins. The parent and map key are only
+/// sent for items with neither origin; other items take them from their
+/// origins when integrated, and carry an empty `parent_sub` if they are map
+/// entries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub id: BlockId,
+    /// Last unit to the left when the item was inserted
+    pub origin: Option<BlockId>,
+    /// First unit to the right when the item was inserted
+    pub right_origin: Option<BlockId>,
+    pub parent: Option<Parent>,
+    /// Map key of the item
+    pub parent_sub: Option<String>,
+    pub content: Content,
+}
+
+/// A run of clock units in an update
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    Item(Item),
+    /// Garbage-collected units
+    Gc { id: BlockId, len: u64 },
+    /// Units left out of the update
+    Skip { id: BlockId, len: u64 },
+}
+
+impl Block {
+    pub fn id(&self) -> BlockId {
+        match self {
+            Block::Item(item) => item.id,
+            Block::Gc { id, .. } | Block::Skip { id, .. } => *id,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        match self {
+            Block::Item(item) => item.content.len(),
+            Block::Gc { len, .. } | Block::Skip { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check what integrating the block relies on
+    ///
+    /// Blocks are not empty and end within the clock, an item has a parent
+    /// or an origin, and the units an item refers to of its own client come
+    /// before it.
+    pub fn validate(&self) -> Result<(), YjsError> {
+        let id = self.id();
+        let invalid = YjsError::InvalidBlock { client: id.client, clock: id.clock };
+        if self.is_empty() {
+            return Err(invalid);
+        }
+        id.clock.checked_add(self.len()).ok_or(YjsError::IntegerOverflow)?;
+        let Block::Item(item) = self else {
+            return Ok(());
+        };
+        if item.parent.is_none() && item.origin.is_none() && item.right_origin.is_none() {
+            return Err(invalid);
+        }
+        let parent = match &item.parent {
+            Some(Parent::Item(parent)) => Some(parent),
+            _ => None,
+        };
+        let refers_ahead = [item.origin.as_ref(), item.right_origin.as_ref(), parent]
+            .into_iter()
+            .flatten()
+            .any(|dep| dep.client == id.client && dep.clock >= id.clock);
+        if refers_ahead {
+            return Err(invalid);
+        }
+        Ok(())
+    }
+
+    fn write(&self, encoder: &mut Encoder) {
+        match self {
+            Block::Gc { len, .. } => {
+                encoder.write_u8(GC_REF);
+                encoder.write_var_uint(*len);
+            }
+            Block::Skip { len, .. } => {
+                encoder.write_u8(SKIP_REF);
+                encoder.write_var_uint(*len);
+            }
+            Block::Item(item) => {
+                let mut info = item.content.ref_number();
+                if item.origin.is_some() {
+                    info |= HAS_ORIGIN;
+                }
+                if item.right_origin.is_some() {
+                    info |= HAS_RIGHT_ORIGIN;
+                }
+                if item.parent_sub.is_some() {
+                    info |= HAS_PARENT_SUB;
+                }
+                encoder.write_u8(info);
+                if let Some(origin) = &item.origin {
+                    origin.write(encoder);
+                }
+                if let Some(right_origin) = &item.right_origin {
+                    right_origin.write(encoder);
+                }
+                if item.origin.is_none() && item.right_origin.is_none() {
+                    match &item.parent {
+                        Some(Parent::Root(name)) => {
+                            encoder.write_var_uint(1);
+                            encoder.write_string(name);
+                        }
+                        Some(Parent::Item(id)) => {
+                            encoder.write_var_uint(0);
+                            id.write(encoder);
+                        }
+                        None => debug_assert!(false, "item without origins must have a parent"),
+                    }
+                    if let Some(key) = &item.parent_sub {
+                        encoder.write_string(key);
+                    }
+                }
+                item.content.write(encoder);
+            }
+        }
+    }
+
+    fn read(decoder: &mut Decoder, id: BlockId) -> Result<Self, YjsError> {
+        let info = decoder.read_u8()?;
+        match info & CONTENT_MASK {
+            GC_REF => Ok(Block::Gc { id, len: decoder.read_var_uint()? }),
+            SKIP_REF => Ok(Block::Skip { id, len: decoder.read_var_uint()? }),
+            content_ref => {
+                let origin = if info & HAS_ORIGIN != 0 { Some(BlockId::read(decoder)?) } else { None };
+                let right_origin = if info & HAS_RIGHT_ORIGIN != 0 { Some(BlockId::read(decoder)?) } else { None };
+                let explicit_parent = origin.is_none() && right_origin.is_none();
+                let parent = if !explicit_parent {
+                    None
+                } else if decoder.read_var_uint()? == 1 {
+                    Some(Parent::Root(decoder.read_string()?))
+                } else {
+                    Some(Parent::Item(BlockId::read(decoder)?))
+                };
+                let parent_sub = match (info & HAS_PARENT_SUB != 0, explicit_parent) {
+                    (false, _) => None,
+                    (true, true) => Some(decoder.read_string()?),
+                    (true, false) => Some(String::new()),
+                };
+                let content = Content::read(decoder, content_ref)?;
+                Ok(Block::Item(Item { id, origin, right_origin, parent, parent_sub, content }))
+            }
+        }
+    }
+}
+
+/// Deleted clock ranges of each client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DeleteSet {
+    ranges: BTreeMap<u64, Vec<(u64, u64)>>,
+}
+
+impl DeleteSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `len` units from `clock`, joining it to an adjacent or overlapping range
+    ///
+    /// Ranges are cut off at the end of the clock.
+    pub fn insert(&mut self, client: u64, clock: u64, len: u64) {
+        let len = len.min(u64::MAX - clock);
+        if len == 0 {
+            return;
+        }
+        let ranges = self.ranges.entry(client).or_default();
+        ranges.push((clock, len));
+        ranges.sort_unstable();
+        let mut joined: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+        for &(clock, len) in ranges.iter() {
+            match joined.last_mut() {
+                Some((last_clock, last_len)) if *last_clock + *last_len >= clock => {
+                    *last_len = (*last_len).max(clock + len - *last_clock);
+                }
+                _ => joined.push((clock, len)),
+            }
+        }
+        *ranges = joined;
+    }
+
+    /// Whether the unit is deleted
+    pub fn contains(&self, id: &BlockId) -> bool {
+        self.ranges
+            .get(&id.client)
+            .is_some_and(|ranges| ranges.iter().any(|(clock, len)| (*clock..clock + len).contains(&id.clock)))
+    }
+
+    /// Ranges as `(client, clock, len)`
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.ranges
+            .iter()
+            .flat_map(|(client, ranges)| ranges.iter().map(move |(clock, len)| (*client, *clock, *len)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    fn write(&self, encoder: &mut Encoder) {
+        encoder.write_var_uint(self.ranges.len() as u64);
+        for (client, ranges) in self.ranges.iter().rev() {
+            encoder.write_var_uint(*client);
+            encoder.write_var_uint(ranges.len() as u64);
+            for (clock, len) in ranges {
+                encoder.write_var_uint(*clock);
+                encoder.write_var_uint(*len);
+            }
+        }
+    }
+
+    fn read(decoder: &mut Decoder) -> Result<Self, YjsError> {
+        let mut delete_set = Self::new();
+        for _ in 0..decoder.read_var_uint()? {
+            let client = decoder.read_var_uint()?;
+            for _ in 0..decoder.read_var_uint()? {
+                let clock = decoder.read_var_uint()?;
+                let len = decoder.read_var_uint()?;
+                clock.checked_add(len).ok_or(YjsError::IntegerOverflow)?;
+                delete_set.insert(client, clock, len);
+            }
+        }
+        Ok(delete_set)
+    }
+}
+
+/// A Yjs document update
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct YjsUpdate {
+    /// Blocks of each client, in clock order
+    pub blocks: Vec<Block>,
+    pub delete_set: DeleteSet,
+}
+
+impl YjsUpdate {
+    /// Decode an update in the v1 format of `Y.encodeStateAsUpdate`
+    pub fn decode_v1(bytes: &[u8]) -> Result<Self, YjsError> {
+        let mut decoder = Decoder::new(bytes);
+        let mut blocks = Vec::new();
+        for _ in 0..decoder.read_var_uint()? {
+            let count = decoder.read_var_uint()?;
+            let client = decoder.read_var_uint()?;
+            let mut clock = decoder.read_var_uint()?;
+            for _ in 0..count {
+                let block = Block::read(&mut decoder, BlockId::new(client, clock))?;
+                clock = clock.checked_add(block.len()).ok_or(YjsError::IntegerOverflow)?;
+                blocks.push(block);
+            }
+        }
+        let delete_set = DeleteSet::read(&mut decoder)?;
+        Ok(Self { blocks, delete_set })
+    }
+
+    /// Encode in the v1 format, filling gaps between a client's blocks with skips
+    pub fn encode_v1(&self) -> Vec<u8> {
+        let mut clients: BTreeMap<u64, Vec<&Block>> = BTreeMap::new();
+        for block in &self.blocks {
+            clients.entry(block.id().client).or_default().push(block);
+        }
+
+        let mut encoder = Encoder::new();
+        encoder.write_var_uint(clients.len() as u64);
+        for (client, mut blocks) in clients.into_iter().rev() {
+            blocks.sort_by_key(|block| block.id().clock);
+            let mut run = Vec::with_capacity(blocks.len());
+            let mut clock = blocks[0].id().clock;
+            for block in blocks {
+                let id = block.id();
+                if id.clock > clock {
+                    run.push(Block::Skip { id: BlockId::new(client, clock), len: id.clock - clock });
+                }
+                clock = id.clock + block.len();
+                run.push(block.clone());
+            }
+            encoder.write_var_uint(run.len() as u64);
+            encoder.write_var_uint(client);
+            encoder.write_var_uint(run[0].id().clock);
+            for block in &run {
+                block.write(&mut encoder);
+            }
+        }
+        self.delete_set.write(&mut encoder);
+        encoder.into_bytes()
+    }
+}
+
+/// Next expected clock of each client, as sent by `Y.encodeStateVector`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateVector(BTreeMap<u64, u64>);
+
+impl StateVector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of units seen from `client`
+    pub fn get(&self, client: u64) -> u64 {
+        self.0.get(&client).copied().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, client: u64, clock: u64) {
+        self.0.insert(client, clock);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.0.iter().map(|(client, clock)| (*client, *clock))
+    }
+
+    pub fn decode_v1(bytes: &[u8]) -> Result<Self, YjsError> {
+        let mut decoder = Decoder::new(bytes);
+        let mut state = Self::new();
+        for _ in 0..decoder.read_var_uint()? {
+            let client = decoder.read_var_uint()?;
+            state.set(client, decoder.read_var_uint()?);
+        }
+        Ok(state)
+    }
+
+    pub fn encode_v1(&self) -> Vec<u8> {
+        let mut encoder = Encoder::new();
+        encoder.write_var_uint(self.0.len() as u64);
+        for (client, clock) in self.0.iter().rev() {
+            encoder.write_var_uint(*client);
+            encoder.write_var_uint(*clock);
+        }
+        encoder.into_bytes()
+    }
+}
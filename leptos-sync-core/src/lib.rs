@@ -8,6 +8,7 @@ pub mod crdt;
 pub mod devtools;
 pub mod error;
 pub mod memory_pool;
+pub mod protocol;
 pub mod query;
 pub mod reliability;
 pub mod serialization;
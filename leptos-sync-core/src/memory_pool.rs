@@ -1,6 +1,6 @@
 //! Memory pooling utilities for CRDTs to reduce allocation overhead
 
-use crate::crdt::{GCounter, LwwMap, LwwRegister, ReplicaId};
+use crate::crdt::{BoundedCounter, GCounter, LwwMap, LwwRegister, PNCounter, ReplicaId};
 use parking_lot::Mutex;
 use std::sync::Arc;
 use std::collections::HashMap;
@@ -35,6 +35,8 @@ pub struct CRDTMemoryPool {
     lww_registers: Arc<Mutex<Vec<LwwRegister<String>>>>,
     lww_maps: Arc<Mutex<Vec<LwwMap<String, String>>>>,
     gcounters: Arc<Mutex<Vec<GCounter>>>,
+    pn_counters: Arc<Mutex<Vec<PNCounter>>>,
+    bounded_counters: Arc<Mutex<Vec<BoundedCounter>>>,
     stats: Arc<Mutex<PoolStats>>,
 }
 
@@ -47,6 +49,10 @@ pub struct PoolStats {
     pub lww_map_deallocations: usize,
     pub gcounter_allocations: usize,
     pub gcounter_deallocations: usize,
+    pub pn_counter_allocations: usize,
+    pub pn_counter_deallocations: usize,
+    pub bounded_counter_allocations: usize,
+    pub bounded_counter_deallocations: usize,
     pub pool_hits: usize,
     pub pool_misses: usize,
 }
@@ -64,6 +70,8 @@ impl CRDTMemoryPool {
             lww_registers: Arc::new(Mutex::new(Vec::new())),
             lww_maps: Arc::new(Mutex::new(Vec::new())),
             gcounters: Arc::new(Mutex::new(Vec::new())),
+            pn_counters: Arc::new(Mutex::new(Vec::new())),
+            bounded_counters: Arc::new(Mutex::new(Vec::new())),
             stats: Arc::new(Mutex::new(PoolStats::default())),
         };
 
@@ -91,6 +99,18 @@ impl CRDTMemoryPool {
         for _ in 0..self.config.initial_size {
             counters.push(GCounter::new());
         }
+
+        // Pre-populate PN-Counters
+        let mut pn_counters = self.pn_counters.lock();
+        for _ in 0..self.config.initial_size {
+            pn_counters.push(PNCounter::new());
+        }
+
+        // Pre-populate bounded counters
+        let mut bounded_counters = self.bounded_counters.lock();
+        for _ in 0..self.config.initial_size {
+            bounded_counters.push(BoundedCounter::new());
+        }
     }
 
     /// Get an LWW register from the pool
@@ -159,6 +179,50 @@ impl CRDTMemoryPool {
         }
     }
 
+    /// Get a PN-Counter from the pool
+    pub fn get_pn_counter(&self) -> PNCounter {
+        let mut counters = self.pn_counters.lock();
+        if let Some(counter) = counters.pop() {
+            self.stats.lock().pool_hits += 1;
+            counter
+        } else {
+            self.stats.lock().pool_misses += 1;
+            self.stats.lock().pn_counter_allocations += 1;
+            PNCounter::new()
+        }
+    }
+
+    /// Return a PN-Counter to the pool
+    pub fn return_pn_counter(&self, counter: PNCounter) {
+        let mut counters = self.pn_counters.lock();
+        if counters.len() < self.config.max_size {
+            counters.push(counter);
+            self.stats.lock().pn_counter_deallocations += 1;
+        }
+    }
+
+    /// Get a bounded counter from the pool
+    pub fn get_bounded_counter(&self) -> BoundedCounter {
+        let mut counters = self.bounded_counters.lock();
+        if let Some(counter) = counters.pop() {
+            self.stats.lock().pool_hits += 1;
+            counter
+        } else {
+            self.stats.lock().pool_misses += 1;
+            self.stats.lock().bounded_counter_allocations += 1;
+            BoundedCounter::new()
+        }
+    }
+
+    /// Return a bounded counter to the pool
+    pub fn return_bounded_counter(&self, counter: BoundedCounter) {
+        let mut counters = self.bounded_counters.lock();
+        if counters.len() < self.config.max_size {
+            counters.push(counter);
+            self.stats.lock().bounded_counter_deallocations += 1;
+        }
+    }
+
     /// Get pool statistics
     pub fn stats(&self) -> PoolStats {
         self.stats.lock().clone()
@@ -170,6 +234,8 @@ impl CRDTMemoryPool {
         sizes.insert("lww_registers".to_string(), self.lww_registers.lock().len());
         sizes.insert("lww_maps".to_string(), self.lww_maps.lock().len());
         sizes.insert("gcounters".to_string(), self.gcounters.lock().len());
+        sizes.insert("pn_counters".to_string(), self.pn_counters.lock().len());
+        sizes.insert("bounded_counters".to_string(), self.bounded_counters.lock().len());
         sizes
     }
 
@@ -178,6 +244,8 @@ impl CRDTMemoryPool {
         self.lww_registers.lock().clear();
         self.lww_maps.lock().clear();
         self.gcounters.lock().clear();
+        self.pn_counters.lock().clear();
+        self.bounded_counters.lock().clear();
     }
 
     /// Resize pools to target size
@@ -202,6 +270,18 @@ impl CRDTMemoryPool {
         for _ in 0..target_size {
             counters.push(GCounter::new());
         }
+        drop(counters);
+
+        let mut pn_counters = self.pn_counters.lock();
+        for _ in 0..target_size {
+            pn_counters.push(PNCounter::new());
+        }
+        drop(pn_counters);
+
+        let mut bounded_counters = self.bounded_counters.lock();
+        for _ in 0..target_size {
+            bounded_counters.push(BoundedCounter::new());
+        }
     }
 }
 
@@ -301,6 +381,28 @@ impl CRDTMemoryPool {
             Box::new(|c, p| p.return_gcounter(c)),
         )
     }
+
+    /// Create a pooled PN-Counter
+    pub fn create_pooled_pn_counter(&self) -> PooledCRDT<PNCounter> {
+        let counter = self.get_pn_counter();
+        let pool = Arc::new(self.clone());
+        PooledCRDT::new(
+            counter,
+            pool,
+            Box::new(|c, p| p.return_pn_counter(c)),
+        )
+    }
+
+    /// Create a pooled bounded counter
+    pub fn create_pooled_bounded_counter(&self) -> PooledCRDT<BoundedCounter> {
+        let counter = self.get_bounded_counter();
+        let pool = Arc::new(self.clone());
+        PooledCRDT::new(
+            counter,
+            pool,
+            Box::new(|c, p| p.return_bounded_counter(c)),
+        )
+    }
 }
 
 impl Clone for CRDTMemoryPool {
@@ -310,6 +412,8 @@ impl Clone for CRDTMemoryPool {
             lww_registers: Arc::clone(&self.lww_registers),
             lww_maps: Arc::clone(&self.lww_maps),
             gcounters: Arc::clone(&self.gcounters),
+            pn_counters: Arc::clone(&self.pn_counters),
+            bounded_counters: Arc::clone(&self.bounded_counters),
             stats: Arc::clone(&self.stats),
         }
     }
@@ -352,6 +456,8 @@ mod tests {
         assert_eq!(sizes["lww_registers"], 5);
         assert_eq!(sizes["lww_maps"], 5);
         assert_eq!(sizes["gcounters"], 5);
+        assert_eq!(sizes["pn_counters"], 5);
+        assert_eq!(sizes["bounded_counters"], 5);
     }
 
     #[test]
@@ -383,5 +489,28 @@ mod tests {
         assert_eq!(new_sizes["lww_registers"], 50);
         assert_eq!(new_sizes["lww_maps"], 50);
         assert_eq!(new_sizes["gcounters"], 50);
+        assert_eq!(new_sizes["pn_counters"], 50);
+        assert_eq!(new_sizes["bounded_counters"], 50);
+    }
+
+    #[test]
+    fn test_counter_pooling() {
+        let pool = CRDTMemoryPool::with_config(PoolConfig {
+            initial_size: 1,
+            ..PoolConfig::default()
+        });
+
+        let pn_counter = pool.get_pn_counter();
+        let bounded_counter = pool.get_bounded_counter();
+        let _extra = pool.get_bounded_counter();
+        pool.return_pn_counter(pn_counter);
+        pool.return_bounded_counter(bounded_counter);
+
+        let stats = pool.stats();
+        assert_eq!(stats.pool_hits, 2);
+        assert_eq!(stats.pool_misses, 1);
+        assert_eq!(stats.bounded_counter_allocations, 1);
+        assert_eq!(stats.pn_counter_deallocations, 1);
+        assert_eq!(stats.bounded_counter_deallocations, 1);
     }
 }
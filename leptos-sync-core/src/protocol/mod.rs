@@ -0,0 +1,413 @@
+//! Wire protocol spoken between replicas and the sync server
+//!
+//! Every message travels as one self-describing binary frame:
+//!
+//! | offset | size | field                                      |
+//! |--------|------|--------------------------------------------|
+//! | 0      | 2    | magic, the ASCII bytes `LS`                |
+//! | 2      | 2    | protocol version, `u16` little-endian      |
+//! | 4      | 2    | message kind, `u16` little-endian          |
+//! | 6      | 4    | payload length in bytes, `u32` little-endian |
+//! | 10     | n    | payload                                    |
+//!
+//! The payload holds the fields of the message in the order they are
+//! declared on [`Message`], encoded with bincode's default layout: integers
+//! are fixed-width little-endian, strings, byte arrays and sequences are
+//! prefixed with their length as a `u64`, an `Option` is a `0`/`1` byte
+//! followed by the value, and a version vector is a `u64` entry count followed
+//! by `(replica, counter)` pairs. Replica ids are their hyphenated UUID
+//! string and timestamps the `u64` form of [`HlcTimestamp`].
+//!
+//! Within a protocol version, messages only ever gain fields at the end and
+//! new kinds; decoders ignore trailing payload bytes and surface kinds they do
+//! not know as [`Message::Unknown`], so older peers keep working and relays
+//! can forward what they do not understand. Anything else bumps the version.
+//!
+//! Peers open with [`Message::Hello`], listing the versions and features they
+//! support, and settle on a [`Session`]. The layout of `Hello` never changes,
+//! so it decodes whatever version its frame carries. Version 1 was the JSON
+//! envelope in [`v1`], which is not framed.
+
+pub mod v1;
+
+use crate::crdt::{HlcTimestamp, ReplicaId, VersionVector};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Protocol version this build sends
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Protocol versions this build can decode, oldest first
+pub const SUPPORTED_VERSIONS: &[u16] = &[PROTOCOL_VERSION];
+
+/// Optional capabilities announced in a handshake
+pub mod features {
+    /// Delta and state vector exchange
+    pub const DELTA_SYNC: &str = "delta-sync";
+
+    /// Presence, heartbeat and departure announcements
+    pub const PRESENCE: &str = "presence";
+}
+
+/// Features this build supports
+pub const FEATURES: &[&str] = &[features::DELTA_SYNC, features::PRESENCE];
+
+const MAGIC: [u8; 2] = *b"LS";
+const HEADER_LEN: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum ProtocolError {
+    #[error("Frame truncated: expected {expected} bytes, got {actual}")]
+    Truncated { expected: usize, actual: usize },
+    #[error("Not a protocol frame")]
+    BadMagic,
+    #[error("Unsupported protocol version {0}")]
+    UnsupportedVersion(u16),
+    #[error("Payload too large: {0} bytes")]
+    PayloadTooLarge(usize),
+    #[error("Invalid payload: {0}")]
+    Payload(#[from] bincode::Error),
+    #[error("No common protocol version (peer supports {0:?})")]
+    NoCommonVersion(Vec<u16>),
+}
+
+/// Message exchanged between replicas
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    /// Handshake listing the protocol versions and features a peer supports
+    Hello { replica_id: ReplicaId, versions: Vec<u16>, features: Vec<String> },
+    /// Full state of a key
+    Sync { key: String, data: Vec<u8>, replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// State of a key after merging a peer's `Sync`
+    SyncResponse { key: String, data: Vec<u8>, replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Changes to a key that are missing from replicas whose state vector dominates `since`
+    Delta { key: String, data: Vec<u8>, since: VersionVector, replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Summary of the updates a replica holds for a key
+    StateVector { key: String, vector: VersionVector, replica_id: ReplicaId },
+    /// Acknowledgment of a change, with the updates the replica now holds for the key if known
+    Ack { key: String, replica_id: ReplicaId, vector: Option<VersionVector> },
+    /// State of a key that conflicts with the receiver's
+    Conflict { key: String, data: Vec<u8>, replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Peer presence announcement
+    Presence { replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Keeps a connection alive
+    Heartbeat { replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Peer departure announcement
+    Leave { replica_id: ReplicaId, timestamp: HlcTimestamp },
+    /// Message of a kind this build does not know, kept so it can be relayed
+    Unknown { kind: u16, payload: Vec<u8> },
+}
+
+impl Message {
+    const HELLO: u16 = 0;
+    const SYNC: u16 = 1;
+    const SYNC_RESPONSE: u16 = 2;
+    const DELTA: u16 = 3;
+    const STATE_VECTOR: u16 = 4;
+    const ACK: u16 = 5;
+    const CONFLICT: u16 = 6;
+    const PRESENCE: u16 = 7;
+    const HEARTBEAT: u16 = 8;
+    const LEAVE: u16 = 9;
+
+    /// Handshake announcing everything this build supports
+    pub fn hello(replica_id: ReplicaId) -> Self {
+        Message::Hello {
+            replica_id,
+            versions: SUPPORTED_VERSIONS.to_vec(),
+            features: FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+
+    /// Kind identifying the message in its frame header
+    pub fn kind(&self) -> u16 {
+        match self {
+            Message::Hello { .. } => Self::HELLO,
+            Message::Sync { .. } => Self::SYNC,
+            Message::SyncResponse { .. } => Self::SYNC_RESPONSE,
+            Message::Delta { .. } => Self::DELTA,
+            Message::StateVector { .. } => Self::STATE_VECTOR,
+            Message::Ack { .. } => Self::ACK,
+            Message::Conflict { .. } => Self::CONFLICT,
+            Message::Presence { .. } => Self::PRESENCE,
+            Message::Heartbeat { .. } => Self::HEARTBEAT,
+            Message::Leave { .. } => Self::LEAVE,
+            Message::Unknown { kind, .. } => *kind,
+        }
+    }
+
+    /// Replica that sent the message, unless its kind is unknown
+    pub fn replica_id(&self) -> Option<ReplicaId> {
+        match self {
+            Message::Hello { replica_id, .. }
+            | Message::Sync { replica_id, .. }
+            | Message::SyncResponse { replica_id, .. }
+            | Message::Delta { replica_id, .. }
+            | Message::StateVector { replica_id, .. }
+            | Message::Ack { replica_id, .. }
+            | Message::Conflict { replica_id, .. }
+            | Message::Presence { replica_id, .. }
+            | Message::Heartbeat { replica_id, .. }
+            | Message::Leave { replica_id, .. } => Some(*replica_id),
+            Message::Unknown { .. } => None,
+        }
+    }
+
+    /// Encode the message as a frame of the current protocol version
+    pub fn encode(&self) -> Result<Vec<u8>, ProtocolError> {
+        let payload = match self {
+            Message::Hello { replica_id, versions, features } => bincode::serialize(&(replica_id, versions, features))?,
+            Message::Sync { key, data, replica_id, timestamp }
+            | Message::SyncResponse { key, data, replica_id, timestamp }
+            | Message::Conflict { key, data, replica_id, timestamp } => {
+                bincode::serialize(&(key, data, replica_id, timestamp))?
+            }
+            Message::Delta { key, data, since, replica_id, timestamp } => {
+                bincode::serialize(&(key, data, since, replica_id, timestamp))?
+            }
+            Message::StateVector { key, vector, replica_id } => bincode::serialize(&(key, vector, replica_id))?,
+            Message::Ack { key, replica_id, vector } => bincode::serialize(&(key, replica_id, vector))?,
+            Message::Presence { replica_id, timestamp }
+            | Message::Heartbeat { replica_id, timestamp }
+            | Message::Leave { replica_id, timestamp } => bincode::serialize(&(replica_id, timestamp))?,
+            Message::Unknown { payload, .. } => payload.clone(),
+        };
+        let len = u32::try_from(payload.len()).map_err(|_| ProtocolError::PayloadTooLarge(payload.len()))?;
+
+        let mut frame = Vec::with_capacity(HEADER_LEN + payload.len());
+        frame.extend_from_slice(&MAGIC);
+        frame.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        frame.extend_from_slice(&self.kind().to_le_bytes());
+        frame.extend_from_slice(&len.to_le_bytes());
+        frame.extend_from_slice(&payload);
+        Ok(frame)
+    }
+
+    /// Decode a frame
+    ///
+    /// Bytes past the declared payload length are ignored.
+    pub fn decode(frame: &[u8]) -> Result<Self, ProtocolError> {
+        if frame.len() < HEADER_LEN {
+            return Err(ProtocolError::Truncated { expected: HEADER_LEN, actual: frame.len() });
+        }
+        if frame[0..2] != MAGIC {
+            return Err(ProtocolError::BadMagic);
+        }
+        let version = u16::from_le_bytes([frame[2], frame[3]]);
+        let kind = u16::from_le_bytes([frame[4], frame[5]]);
+        let len = u32::from_le_bytes([frame[6], frame[7], frame[8], frame[9]]) as usize;
+        let end = HEADER_LEN.saturating_add(len);
+        if frame.len() < end {
+            return Err(ProtocolError::Truncated { expected: end, actual: frame.len() });
+        }
+        let payload = &frame[HEADER_LEN..end];
+
+        if kind != Self::HELLO && !SUPPORTED_VERSIONS.contains(&version) {
+            return Err(ProtocolError::UnsupportedVersion(version));
+        }
+
+        let message = match kind {
+            Self::HELLO => {
+                let (replica_id, versions, features) = fields(payload)?;
+                Message::Hello { replica_id, versions, features }
+            }
+            Self::SYNC => {
+                let (key, data, replica_id, timestamp) = fields(payload)?;
+                Message::Sync { key, data, replica_id, timestamp }
+            }
+            Self::SYNC_RESPONSE => {
+                let (key, data, replica_id, timestamp) = fields(payload)?;
+                Message::SyncResponse { key, data, replica_id, timestamp }
+            }
+            Self::DELTA => {
+                let (key, data, since, replica_id, timestamp) = fields(payload)?;
+                Message::Delta { key, data, since, replica_id, timestamp }
+            }
+            Self::STATE_VECTOR => {
+                let (key, vector, replica_id) = fields(payload)?;
+                Message::StateVector { key, vector, replica_id }
+            }
+            Self::ACK => {
+                let (key, replica_id, vector) = fields(payload)?;
+                Message::Ack { key, replica_id, vector }
+            }
+            Self::CONFLICT => {
+                let (key, data, replica_id, timestamp) = fields(payload)?;
+                Message::Conflict { key, data, replica_id, timestamp }
+            }
+            Self::PRESENCE => {
+                let (replica_id, timestamp) = fields(payload)?;
+                Message::Presence { replica_id, timestamp }
+            }
+            Self::HEARTBEAT => {
+                let (replica_id, timestamp) = fields(payload)?;
+                Message::Heartbeat { replica_id, timestamp }
+            }
+            Self::LEAVE => {
+                let (replica_id, timestamp) = fields(payload)?;
+                Message::Leave { replica_id, timestamp }
+            }
+            _ => Message::Unknown { kind, payload: payload.to_vec() },
+        };
+        Ok(message)
+    }
+}
+
+/// Decode the leading fields of a payload, ignoring any fields appended later
+fn fields<T: DeserializeOwned>(payload: &[u8]) -> Result<T, ProtocolError> {
+    Ok(bincode::deserialize(payload)?)
+}
+
+/// Version and features two peers agreed on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub version: u16,
+    pub features: Vec<String>,
+}
+
+impl Session {
+    /// Agree on the newest version both sides support and the features both offer
+    pub fn negotiate(versions: &[u16], features: &[String]) -> Result<Self, ProtocolError> {
+        let version = SUPPORTED_VERSIONS
+            .iter()
+            .rev()
+            .find(|version| versions.contains(version))
+            .copied()
+            .ok_or_else(|| ProtocolError::NoCommonVersion(versions.to_vec()))?;
+        let features = features
+            .iter()
+            .filter(|feature| FEATURES.contains(&feature.as_str()))
+            .cloned()
+            .collect();
+        Ok(Self { version, features })
+    }
+
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+}
+
+/// Sessions negotiated with each peer
+#[derive(Debug, Clone, Default)]
+pub struct Sessions {
+    sessions: HashMap<ReplicaId, Session>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Negotiate a session from a peer's `Hello`
+    ///
+    /// Returns whether the session is new, in which case the peer has not
+    /// seen our `Hello` yet and should be answered with one.
+    pub fn accept(&mut self, replica_id: ReplicaId, versions: &[u16], features: &[String]) -> Result<bool, ProtocolError> {
+        match Session::negotiate(versions, features) {
+            Ok(session) => Ok(self.sessions.insert(replica_id, session).is_none()),
+            Err(e) => {
+                // A rejected hello ends whatever session the peer had before
+                self.sessions.remove(&replica_id);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn get(&self, replica_id: &ReplicaId) -> Option<&Session> {
+        self.sessions.get(replica_id)
+    }
+
+    /// Forget a peer, so its next `Hello` is answered again
+    pub fn remove(&mut self, replica_id: &ReplicaId) -> Option<Session> {
+        self.sessions.remove(replica_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_round_trip() {
+        let replica = ReplicaId::default();
+        let mut vector = VersionVector::new();
+        vector.increment(replica);
+        let timestamp = HlcTimestamp::new(1_700_000_000_000, 3);
+        let messages = vec![
+            Message::hello(replica),
+            Message::Sync { key: "doc".to_string(), data: vec![1, 2, 3], replica_id: replica, timestamp },
+            Message::Delta { key: "doc".to_string(), data: vec![4], since: vector.clone(), replica_id: replica, timestamp },
+            Message::StateVector { key: "doc".to_string(), vector: vector.clone(), replica_id: replica },
+            Message::Ack { key: "doc".to_string(), replica_id: replica, vector: Some(vector) },
+            Message::Ack { key: "doc".to_string(), replica_id: replica, vector: None },
+            Message::Leave { replica_id: replica, timestamp },
+            Message::Unknown { kind: 4242, payload: vec![9, 9] },
+        ];
+
+        for message in messages {
+            let frame = message.encode().unwrap();
+            assert_eq!(&frame[..2], b"LS");
+            assert_eq!(Message::decode(&frame).unwrap(), message);
+        }
+    }
+
+    #[test]
+    fn test_frame_layout_is_stable() {
+        let replica = ReplicaId::from(uuid::Uuid::nil());
+        let frame = Message::Heartbeat { replica_id: replica, timestamp: HlcTimestamp::new(1, 2) }.encode().unwrap();
+
+        let mut expected = b"LS".to_vec();
+        expected.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+        expected.extend_from_slice(&8u16.to_le_bytes());
+        expected.extend_from_slice(&52u32.to_le_bytes());
+        expected.extend_from_slice(&36u64.to_le_bytes());
+        expected.extend_from_slice(b"00000000-0000-0000-0000-000000000000");
+        expected.extend_from_slice(&((1u64 << 16) | 2).to_le_bytes());
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn test_decoding_tolerates_newer_peers() {
+        let replica = ReplicaId::default();
+        let timestamp = HlcTimestamp::new(5, 0);
+        let mut frame = Message::Presence { replica_id: replica, timestamp }.encode().unwrap();
+
+        // A field appended by a later build of the same version is skipped
+        frame.extend_from_slice(&[7, 7, 7]);
+        let len = (frame.len() - HEADER_LEN) as u32;
+        frame[6..10].copy_from_slice(&len.to_le_bytes());
+        assert_eq!(Message::decode(&frame).unwrap(), Message::Presence { replica_id: replica, timestamp });
+
+        // Frames of a version we do not speak are refused, except for the handshake
+        frame[2..4].copy_from_slice(&99u16.to_le_bytes());
+        assert!(matches!(Message::decode(&frame), Err(ProtocolError::UnsupportedVersion(99))));
+        let mut hello = Message::hello(replica).encode().unwrap();
+        hello[2..4].copy_from_slice(&99u16.to_le_bytes());
+        assert_eq!(Message::decode(&hello).unwrap(), Message::hello(replica));
+
+        assert!(matches!(Message::decode(b"{\"Sync\":{}}"), Err(ProtocolError::BadMagic)));
+        assert!(matches!(Message::decode(&frame[..12]), Err(ProtocolError::Truncated { .. })));
+    }
+
+    #[test]
+    fn test_session_negotiation() {
+        let peer = ReplicaId::default();
+        let mut sessions = Sessions::new();
+        let features = vec![features::PRESENCE.to_string(), "telepathy".to_string()];
+
+        assert!(sessions.accept(peer, &[1, PROTOCOL_VERSION, 7], &features).unwrap());
+        assert!(!sessions.accept(peer, &[PROTOCOL_VERSION], &features).unwrap());
+        let session = sessions.get(&peer).unwrap();
+        assert_eq!(session.version, PROTOCOL_VERSION);
+        assert!(session.supports(features::PRESENCE));
+        assert!(!session.supports("telepathy"));
+
+        assert!(matches!(
+            Session::negotiate(&[1], &[]),
+            Err(ProtocolError::NoCommonVersion(versions)) if versions == vec![1]
+        ));
+        assert!(sessions.accept(peer, &[1], &features).is_err());
+        assert!(sessions.get(&peer).is_none());
+    }
+}
@@ -1,6 +1,11 @@
-//! WebSocket message protocol for CRDT synchronization
-
-use crate::crdt::ReplicaId;
+//! Protocol version 1, the JSON envelope spoken before framed messages
+//!
+//! Nothing in this crate sends it any more. Peers that still do can be read
+//! by decoding their messages here and upgrading them with
+//! [`SyncMessage::upgrade`].
+
+use super::Message;
+use crate::crdt::{HlcTimestamp, ReplicaId, VersionVector};
 use serde::{Deserialize, Serialize};
 use std::time::SystemTime;
 
@@ -70,6 +75,43 @@ pub enum SyncMessage {
     },
 }
 
+impl SyncMessage {
+    /// Translate into the current protocol
+    ///
+    /// A version 1 delta carries no state vector, so it becomes a delta since
+    /// the empty vector. A welcome becomes a `Hello` offering only version 1.
+    /// Binary acknowledgments have no counterpart and yield `None`.
+    pub fn upgrade(self) -> Option<Message> {
+        let message = match self {
+            SyncMessage::Delta { collection_id, delta, timestamp, replica_id, .. } => Message::Delta {
+                key: collection_id,
+                data: delta,
+                since: VersionVector::new(),
+                replica_id,
+                timestamp: hlc(timestamp),
+            },
+            SyncMessage::Heartbeat { replica_id, timestamp } => Message::Heartbeat { replica_id, timestamp: hlc(timestamp) },
+            SyncMessage::PeerJoin { replica_id, .. } => Message::Presence { replica_id, timestamp: HlcTimestamp::now() },
+            SyncMessage::PeerLeave { replica_id } => Message::Leave { replica_id, timestamp: HlcTimestamp::now() },
+            SyncMessage::Welcome { peer_id, .. } => Message::Hello {
+                replica_id: peer_id,
+                versions: vec![MessageWrapper::PROTOCOL_VERSION as u16],
+                features: Vec::new(),
+            },
+            SyncMessage::Presence { peer_id, action: PresenceAction::Leave, timestamp } => {
+                Message::Leave { replica_id: peer_id, timestamp: hlc(timestamp) }
+            }
+            SyncMessage::Presence { peer_id, timestamp, .. } => Message::Presence { replica_id: peer_id, timestamp: hlc(timestamp) },
+            SyncMessage::BinaryAck { .. } => return None,
+        };
+        Some(message)
+    }
+}
+
+fn hlc(time: SystemTime) -> HlcTimestamp {
+    HlcTimestamp::from_datetime(time.into())
+}
+
 /// Server information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerInfo {
@@ -166,6 +208,7 @@ impl MessageCodec {
 mod tests {
     use super::*;
     use crate::crdt::ReplicaId;
+    use crate::protocol::Session;
     use std::time::UNIX_EPOCH;
 
     fn create_test_replica_id() -> ReplicaId {
@@ -282,6 +325,44 @@ mod tests {
         assert_eq!(wrapper_with_id.message_id, Some("msg123".to_string()));
     }
 
+    #[test]
+    fn test_upgrade_to_current_protocol() {
+        let replica_id = create_test_replica_id();
+        let delta = SyncMessage::Delta {
+            collection_id: "test_collection".to_string(),
+            crdt_type: CrdtType::LwwRegister,
+            delta: vec![1, 2, 3],
+            timestamp: UNIX_EPOCH,
+            replica_id,
+        };
+        let upgraded = MessageCodec::deserialize(&MessageCodec::serialize(&delta).unwrap()).unwrap().upgrade();
+        assert_eq!(
+            upgraded,
+            Some(Message::Delta {
+                key: "test_collection".to_string(),
+                data: vec![1, 2, 3],
+                since: VersionVector::new(),
+                replica_id,
+                timestamp: HlcTimestamp::new(0, 0),
+            })
+        );
+
+        let leave = SyncMessage::Presence { peer_id: replica_id, action: PresenceAction::Leave, timestamp: UNIX_EPOCH };
+        assert!(matches!(leave.upgrade(), Some(Message::Leave { replica_id: id, .. }) if id == replica_id));
+
+        // A version 1 peer cannot settle on a session with this build
+        let welcome = SyncMessage::Welcome { peer_id: replica_id, timestamp: UNIX_EPOCH, server_info: None };
+        match welcome.upgrade() {
+            Some(Message::Hello { versions, features, .. }) => {
+                assert!(Session::negotiate(&versions, &features).is_err());
+            }
+            other => panic!("Expected Hello, got {:?}", other),
+        }
+
+        let ack = SyncMessage::BinaryAck { peer_id: replica_id, size: 3, timestamp: UNIX_EPOCH };
+        assert_eq!(ack.upgrade(), None);
+    }
+
     #[test]
     fn test_compressed_serialization() {
         let replica_id = create_test_replica_id();
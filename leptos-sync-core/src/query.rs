@@ -1,39 +1,292 @@
 //! Query API for local-first collections
+//!
+//! Queries run against a [`LocalFirstCollection`]. Field predicates added with
+//! [`QueryBuilder::where_eq`] and [`QueryBuilder::where_range`] are answered from
+//! the collection's hash/B-tree indices when a suitable one exists; everything
+//! else is evaluated with a scan over the matching candidates.
 
+use crate::{
+    collection::{IndexExtractor, LocalFirstCollection},
+    crdt::Mergeable,
+    storage::{
+        indexed::{IndexError, IndexType},
+        LocalStorage, Storage, StorageError,
+    },
+    transport::SyncTransport,
+};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::broadcast;
+
+#[derive(Error, Debug)]
+pub enum QueryError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Index error: {0}")]
+    Index(#[from] IndexError),
+    #[error("Unknown field: {0}")]
+    UnknownField(String),
+}
+
+type Predicate<T> = Arc<dyn Fn(&T) -> bool + Send + Sync>;
+type Comparator<T> = Arc<dyn Fn(&T, &T) -> Ordering + Send + Sync>;
+
+/// Condition on an indexed field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldCondition {
+    /// Field equals the value
+    Eq(String),
+    /// Field lies in `start..end`
+    Range { start: String, end: String },
+}
+
+impl FieldCondition {
+    fn matches(&self, value: &str) -> bool {
+        match self {
+            FieldCondition::Eq(expected) => value == expected,
+            FieldCondition::Range { start, end } => value >= start.as_str() && value < end.as_str(),
+        }
+    }
+
+    fn usable_with(&self, index_type: &IndexType) -> bool {
+        match self {
+            FieldCondition::Eq(_) => matches!(index_type, IndexType::Hash | IndexType::BTree),
+            FieldCondition::Range { .. } => *index_type == IndexType::BTree,
+        }
+    }
+}
+
+/// How a query will be executed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryPlan {
+    /// Candidates come from an index lookup on the named field
+    IndexLookup { index: String, condition: FieldCondition },
+    /// Every item in the collection is scanned
+    FullScan,
+}
+
+/// A single change to a watched result set
+///
+/// `Removed` indices and `Moved::from` refer to the previous result set;
+/// `Added`, `Updated` and `Moved::to` refer to the new one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryChange<T> {
+    Added { key: String, index: usize, value: T },
+    Removed { key: String, index: usize },
+    Moved { key: String, from: usize, to: usize },
+    Updated { key: String, index: usize, value: T },
+}
 
 /// Query builder for filtering and sorting data
 pub struct QueryBuilder<T> {
-    _phantom: std::marker::PhantomData<T>,
+    fields: Vec<(String, FieldCondition)>,
+    predicates: Vec<Predicate<T>>,
+    comparator: Option<Comparator<T>>,
+    limit: Option<usize>,
+}
+
+impl<T> Clone for QueryBuilder<T> {
+    fn clone(&self) -> Self {
+        Self {
+            fields: self.fields.clone(),
+            predicates: self.predicates.clone(),
+            comparator: self.comparator.clone(),
+            limit: self.limit,
+        }
+    }
 }
 
 impl<T> QueryBuilder<T> {
     pub fn new() -> Self {
         Self {
-            _phantom: std::marker::PhantomData,
+            fields: Vec::new(),
+            predicates: Vec::new(),
+            comparator: None,
+            limit: None,
         }
     }
 
-    pub fn filter<F>(self, _predicate: F) -> Self
+    pub fn filter<F>(mut self, predicate: F) -> Self
     where
-        F: Fn(&T) -> bool + 'static,
+        F: Fn(&T) -> bool + Send + Sync + 'static,
     {
+        self.predicates.push(Arc::new(predicate));
+        self
+    }
+
+    /// Match items whose indexed `field` equals `value`
+    pub fn where_eq(mut self, field: impl Into<String>, value: impl Into<String>) -> Self {
+        self.fields.push((field.into(), FieldCondition::Eq(value.into())));
+        self
+    }
+
+    /// Match items whose indexed `field` lies in `start..end`
+    pub fn where_range(
+        mut self,
+        field: impl Into<String>,
+        start: impl Into<String>,
+        end: impl Into<String>,
+    ) -> Self {
+        self.fields.push((
+            field.into(),
+            FieldCondition::Range {
+                start: start.into(),
+                end: end.into(),
+            },
+        ));
         self
     }
 
-    pub fn sort_by<F>(self, _comparator: F) -> Self
+    pub fn sort_by<F>(mut self, comparator: F) -> Self
     where
-        F: Fn(&T, &T) -> std::cmp::Ordering + 'static,
+        F: Fn(&T, &T) -> Ordering + Send + Sync + 'static,
     {
+        self.comparator = Some(Arc::new(comparator));
         self
     }
 
-    pub fn limit(self, _limit: usize) -> Self {
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
         self
     }
+}
 
-    pub fn watch(self) -> Self {
-        self
+impl<T> QueryBuilder<T>
+where
+    T: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Mergeable + Default,
+{
+    /// Decide how the query would run against `collection`
+    pub async fn explain<Tr>(&self, collection: &LocalFirstCollection<T, Tr>) -> QueryPlan
+    where
+        Tr: SyncTransport + Clone + 'static,
+    {
+        for (field, condition) in &self.fields {
+            if let Some(index_type) = collection.indexed_storage().index_type(field).await {
+                if condition.usable_with(&index_type) {
+                    return QueryPlan::IndexLookup {
+                        index: field.clone(),
+                        condition: condition.clone(),
+                    };
+                }
+            }
+        }
+        QueryPlan::FullScan
+    }
+
+    /// Run the query and return matching `(key, value)` rows in result order
+    pub async fn execute<Tr>(
+        &self,
+        collection: &LocalFirstCollection<T, Tr>,
+    ) -> Result<Vec<(String, T)>, QueryError>
+    where
+        Tr: SyncTransport + Clone + 'static,
+    {
+        let mut rows = self.matching_rows(collection).await?;
+        if let Some(limit) = self.limit {
+            rows.truncate(limit);
+        }
+        Ok(rows)
+    }
+
+    /// Run the query and keep watching it for changes
+    ///
+    /// The returned [`QueryWatch`] starts at the current result set and yields
+    /// diffs as local writes and remote merges land in the collection.
+    pub async fn watch<Tr>(
+        self,
+        collection: &LocalFirstCollection<T, Tr>,
+    ) -> Result<QueryWatch<T>, QueryError>
+    where
+        Tr: SyncTransport + Clone + 'static,
+    {
+        // Subscribe before reading so no change slips between the two
+        let changes = collection.subscribe_changes();
+        let extractors = collection.index_extractors().await;
+        self.check_fields(&extractors)?;
+        let matches = self.matching_rows(collection).await?;
+        let window = self.window(&matches);
+
+        Ok(QueryWatch {
+            query: self,
+            storage: collection.storage().clone(),
+            extractors,
+            changes,
+            matches,
+            window,
+        })
+    }
+
+    /// All matching rows in result order, before the limit is applied
+    async fn matching_rows<Tr>(
+        &self,
+        collection: &LocalFirstCollection<T, Tr>,
+    ) -> Result<Vec<(String, T)>, QueryError>
+    where
+        Tr: SyncTransport + Clone + 'static,
+    {
+        let extractors = collection.index_extractors().await;
+        self.check_fields(&extractors)?;
+
+        let storage = collection.storage();
+        let candidates = match self.explain(collection).await {
+            QueryPlan::IndexLookup { index, condition } => {
+                let indexed = collection.indexed_storage();
+                match condition {
+                    FieldCondition::Eq(value) => indexed.query_by_index(&index, &value).await?,
+                    FieldCondition::Range { start, end } => {
+                        indexed.range_query(&index, &start, &end).await?
+                    }
+                }
+            }
+            QueryPlan::FullScan => storage.keys().await?,
+        };
+
+        let mut rows = Vec::new();
+        for key in candidates {
+            if let Some(value) = storage.get::<T>(&key).await? {
+                if self.matches(&value, &extractors) {
+                    rows.push((key, value));
+                }
+            }
+        }
+        rows.sort_by(|a, b| self.compare(a, b));
+        Ok(rows)
+    }
+
+    fn check_fields(&self, extractors: &HashMap<String, IndexExtractor<T>>) -> Result<(), QueryError> {
+        match self.fields.iter().find(|(field, _)| !extractors.contains_key(field)) {
+            Some((field, _)) => Err(QueryError::UnknownField(field.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<T> QueryBuilder<T> {
+    fn matches(&self, value: &T, extractors: &HashMap<String, IndexExtractor<T>>) -> bool {
+        self.fields.iter().all(|(field, condition)| {
+            extractors
+                .get(field)
+                .is_some_and(|extract| condition.matches(&extract(value)))
+        }) && self.predicates.iter().all(|predicate| predicate(value))
+    }
+
+    /// Order rows by the comparator, falling back to the key so results are stable
+    fn compare(&self, a: &(String, T), b: &(String, T)) -> Ordering {
+        self.comparator
+            .as_ref()
+            .map_or(Ordering::Equal, |compare| compare(&a.1, &b.1))
+            .then_with(|| a.0.cmp(&b.0))
+    }
+
+    fn window(&self, matches: &[(String, T)]) -> Vec<(String, T)>
+    where
+        T: Clone,
+    {
+        let end = self.limit.map_or(matches.len(), |limit| limit.min(matches.len()));
+        matches[..end].to_vec()
     }
 }
 
@@ -42,3 +295,314 @@ impl<T> Default for QueryBuilder<T> {
         Self::new()
     }
 }
+
+/// Live view over a query's result set
+pub struct QueryWatch<T> {
+    query: QueryBuilder<T>,
+    storage: Storage,
+    extractors: HashMap<String, IndexExtractor<T>>,
+    changes: broadcast::Receiver<String>,
+    /// Every matching row in result order, ignoring the limit
+    matches: Vec<(String, T)>,
+    /// The rows last reported to the caller
+    window: Vec<(String, T)>,
+}
+
+impl<T> QueryWatch<T>
+where
+    T: Clone + Send + Sync + Serialize + for<'de> Deserialize<'de> + Mergeable + Default,
+{
+    /// The current result set
+    pub fn results(&self) -> &[(String, T)] {
+        &self.window
+    }
+
+    /// Wait for the next change to the result set
+    ///
+    /// Changes that do not affect the visible rows are absorbed silently.
+    /// Returns `None` once the collection has been dropped.
+    pub async fn next(&mut self) -> Option<Result<Vec<QueryChange<T>>, QueryError>> {
+        loop {
+            let mut changed = HashSet::new();
+            let mut rescan = false;
+            match self.changes.recv().await {
+                Ok(key) => {
+                    changed.insert(key);
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => rescan = true,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+            // Fold everything already queued into one diff
+            loop {
+                match self.changes.try_recv() {
+                    Ok(key) => {
+                        changed.insert(key);
+                    }
+                    Err(broadcast::error::TryRecvError::Lagged(_)) => rescan = true,
+                    Err(_) => break,
+                }
+            }
+
+            let refreshed = if rescan {
+                self.rescan().await
+            } else {
+                self.apply_changes(&changed).await
+            };
+            if let Err(e) = refreshed {
+                return Some(Err(e));
+            }
+
+            let window = self.query.window(&self.matches);
+            let diff = diff_rows(&self.window, &window, &changed, rescan);
+            self.window = window;
+            if !diff.is_empty() {
+                return Some(Ok(diff));
+            }
+        }
+    }
+
+    /// Re-evaluate only the changed keys against the cached match set
+    async fn apply_changes(&mut self, changed: &HashSet<String>) -> Result<(), QueryError> {
+        self.matches.retain(|(key, _)| !changed.contains(key));
+        for key in changed {
+            if let Some(value) = self.storage.get::<T>(key).await? {
+                if self.query.matches(&value, &self.extractors) {
+                    let row = (key.clone(), value);
+                    let position = self
+                        .matches
+                        .binary_search_by(|probe| self.query.compare(probe, &row))
+                        .unwrap_or_else(|position| position);
+                    self.matches.insert(position, row);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rebuild the match set after missing change notifications
+    async fn rescan(&mut self) -> Result<(), QueryError> {
+        let mut rows = Vec::new();
+        for key in self.storage.keys().await? {
+            if let Some(value) = self.storage.get::<T>(&key).await? {
+                if self.query.matches(&value, &self.extractors) {
+                    rows.push((key, value));
+                }
+            }
+        }
+        rows.sort_by(|a, b| self.query.compare(a, b));
+        self.matches = rows;
+        Ok(())
+    }
+}
+
+/// Describe how `old` became `new` with the smallest set of moves
+fn diff_rows<T: Clone>(
+    old: &[(String, T)],
+    new: &[(String, T)],
+    changed: &HashSet<String>,
+    all_changed: bool,
+) -> Vec<QueryChange<T>> {
+    let old_positions: HashMap<&str, usize> =
+        old.iter().enumerate().map(|(i, (key, _))| (key.as_str(), i)).collect();
+    let new_positions: HashMap<&str, usize> =
+        new.iter().enumerate().map(|(i, (key, _))| (key.as_str(), i)).collect();
+    let mut diff = Vec::new();
+
+    for (index, (key, _)) in old.iter().enumerate().rev() {
+        if !new_positions.contains_key(key.as_str()) {
+            diff.push(QueryChange::Removed { key: key.clone(), index });
+        }
+    }
+
+    // Rows kept in place are those on the longest run already in order
+    let kept: Vec<(usize, usize)> = old
+        .iter()
+        .enumerate()
+        .filter_map(|(from, (key, _))| new_positions.get(key.as_str()).map(|&to| (from, to)))
+        .collect();
+    let stable = longest_increasing(&kept.iter().map(|&(_, to)| to).collect::<Vec<_>>());
+
+    for (i, &(from, to)) in kept.iter().enumerate() {
+        if !stable.contains(&i) {
+            diff.push(QueryChange::Moved { key: old[from].0.clone(), from, to });
+        }
+    }
+
+    for (index, (key, value)) in new.iter().enumerate() {
+        if !old_positions.contains_key(key.as_str()) {
+            diff.push(QueryChange::Added { key: key.clone(), index, value: value.clone() });
+        } else if all_changed || changed.contains(key) {
+            diff.push(QueryChange::Updated { key: key.clone(), index, value: value.clone() });
+        }
+    }
+
+    diff
+}
+
+/// Indices of one longest strictly increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> HashSet<usize> {
+    // tails[k] holds the index of the smallest tail of an increasing run of length k + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; values.len()];
+    for (i, &value) in values.iter().enumerate() {
+        let position = tails.partition_point(|&t| values[t] < value);
+        if position > 0 {
+            previous[i] = Some(tails[position - 1]);
+        }
+        if position == tails.len() {
+            tails.push(i);
+        } else {
+            tails[position] = i;
+        }
+    }
+
+    let mut result = HashSet::new();
+    let mut current = tails.last().copied();
+    while let Some(i) = current {
+        result.insert(i);
+        current = previous[i];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::{LwwRegister, ReplicaId};
+    use crate::storage::indexed::IndexConfig;
+    use crate::transport::InMemoryTransport;
+
+    type Task = LwwRegister<(String, u32)>;
+
+    fn task(status: &str, priority: u32) -> Task {
+        LwwRegister::new((status.to_string(), priority), ReplicaId::default())
+    }
+
+    fn index(name: &str, index_type: IndexType) -> IndexConfig {
+        IndexConfig {
+            name: name.to_string(),
+            index_type,
+            unique: false,
+            sparse: true,
+        }
+    }
+
+    async fn collection() -> LocalFirstCollection<Task, InMemoryTransport> {
+        let collection = LocalFirstCollection::new(Storage::memory(), InMemoryTransport::new());
+        collection.insert("a", &task("open", 3)).await.unwrap();
+        collection.insert("b", &task("done", 1)).await.unwrap();
+        collection.insert("c", &task("open", 2)).await.unwrap();
+        collection.insert("d", &task("open", 5)).await.unwrap();
+        collection
+    }
+
+    fn keys(rows: &[(String, Task)]) -> Vec<&str> {
+        rows.iter().map(|(key, _)| key.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_filter_sort_limit() {
+        let collection = collection().await;
+        let rows = QueryBuilder::new()
+            .filter(|t: &Task| t.value().0 == "open")
+            .sort_by(|a: &Task, b: &Task| b.value().1.cmp(&a.value().1))
+            .limit(2)
+            .execute(&collection)
+            .await
+            .unwrap();
+
+        assert_eq!(keys(&rows), vec!["d", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_index_is_used_when_it_matches() {
+        let collection = collection().await;
+        collection
+            .create_index(index("status", IndexType::Hash), |t: &Task| t.value().0.clone())
+            .await
+            .unwrap();
+        collection
+            .create_index(index("priority", IndexType::BTree), |t: &Task| t.value().1.to_string())
+            .await
+            .unwrap();
+
+        let by_status = QueryBuilder::new().where_eq("status", "open");
+        assert_eq!(
+            by_status.explain(&collection).await,
+            QueryPlan::IndexLookup {
+                index: "status".to_string(),
+                condition: FieldCondition::Eq("open".to_string()),
+            }
+        );
+        assert_eq!(keys(&by_status.execute(&collection).await.unwrap()), vec!["a", "c", "d"]);
+
+        let by_priority = QueryBuilder::new().where_range("priority", "2", "4");
+        assert!(matches!(by_priority.explain(&collection).await, QueryPlan::IndexLookup { .. }));
+        assert_eq!(keys(&by_priority.execute(&collection).await.unwrap()), vec!["a", "c"]);
+
+        // A hash index cannot answer a range query, so this falls back to a scan
+        let status_range = QueryBuilder::new().where_range("status", "d", "e");
+        assert_eq!(status_range.explain(&collection).await, QueryPlan::FullScan);
+        assert_eq!(keys(&status_range.execute(&collection).await.unwrap()), vec!["b"]);
+
+        // Index stays current after writes
+        collection.insert("a", &task("done", 3)).await.unwrap();
+        assert_eq!(keys(&by_status.execute(&collection).await.unwrap()), vec!["c", "d"]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_field_is_rejected() {
+        let collection = collection().await;
+        let result = QueryBuilder::new().where_eq("missing", "x").execute(&collection).await;
+        assert!(matches!(result, Err(QueryError::UnknownField(field)) if field == "missing"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_diffs() {
+        let collection = collection().await;
+        let mut watch = QueryBuilder::new()
+            .filter(|t: &Task| t.value().0 == "open")
+            .sort_by(|a: &Task, b: &Task| a.value().1.cmp(&b.value().1))
+            .watch(&collection)
+            .await
+            .unwrap();
+        assert_eq!(keys(watch.results()), vec!["c", "a", "d"]);
+
+        collection.insert("e", &task("open", 1)).await.unwrap();
+        let diff = watch.next().await.unwrap().unwrap();
+        assert!(matches!(&diff[..], [QueryChange::Added { key, index: 0, .. }] if key == "e"));
+
+        // Raising a's priority moves it to the end without touching the others
+        collection.insert("a", &task("open", 9)).await.unwrap();
+        let diff = watch.next().await.unwrap().unwrap();
+        assert!(diff.contains(&QueryChange::Moved { key: "a".to_string(), from: 2, to: 3 }));
+        assert!(!diff.iter().any(|change| matches!(change, QueryChange::Moved { key, .. } if key != "a")));
+
+        collection.remove("c").await.unwrap();
+        let diff = watch.next().await.unwrap().unwrap();
+        assert!(matches!(&diff[..], [QueryChange::Removed { key, index: 1 }] if key == "c"));
+        assert_eq!(keys(watch.results()), vec!["e", "d", "a"]);
+    }
+
+    #[tokio::test]
+    async fn test_watch_sees_remote_merges() {
+        let transport = InMemoryTransport::new();
+        let remote = crate::collection::CollectionBuilder::new(Storage::memory(), transport.clone())
+            .with_auto_sync(true)
+            .build::<Task>();
+        let local = LocalFirstCollection::<Task, _>::new(Storage::memory(), transport);
+        let mut watch = QueryBuilder::new().limit(10).watch(&local).await.unwrap();
+
+        remote.insert("x", &task("open", 1)).await.unwrap();
+        local.force_sync().await.unwrap();
+
+        let diff = watch.next().await.unwrap().unwrap();
+        assert!(matches!(&diff[..], [QueryChange::Added { key, .. }] if key == "x"));
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        let stable = longest_increasing(&[0, 3, 1, 2]);
+        assert_eq!(stable, HashSet::from([0, 2, 3]));
+    }
+}
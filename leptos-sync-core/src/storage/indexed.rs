@@ -83,6 +83,8 @@ pub struct IndexedStorage {
     indices: Arc<RwLock<HashMap<String, Box<dyn Index>>>>,
     /// Index metadata
     metadata: Arc<RwLock<HashMap<String, IndexMetadata>>>,
+    /// Currently indexed value of each document, per index
+    document_values: Arc<RwLock<HashMap<String, HashMap<String, String>>>>,
 }
 
 /// Index trait for different index implementations
@@ -103,6 +105,11 @@ pub trait Index: Send + Sync {
     /// Get document IDs for a value
     async fn get(&self, value: &str) -> Result<Vec<String>, IndexError>;
     
+    /// Get document IDs for values in `start..end`, in value order
+    async fn range(&self, _start: &str, _end: &str) -> Result<Vec<String>, IndexError> {
+        Err(IndexError::InvalidIndexValue)
+    }
+    
     /// Get all values in the index
     async fn values(&self) -> Result<Vec<String>, IndexError>;
     
@@ -281,6 +288,17 @@ impl Index for BTreeIndex {
         Ok(self.data.get(value).cloned().unwrap_or_default())
     }
     
+    async fn range(&self, start: &str, end: &str) -> Result<Vec<String>, IndexError> {
+        if start > end {
+            return Ok(Vec::new());
+        }
+        
+        Ok(self.data
+            .range::<str, _>((std::ops::Bound::Included(start), std::ops::Bound::Excluded(end)))
+            .flat_map(|(_, ids)| ids.iter().cloned())
+            .collect())
+    }
+    
     async fn values(&self) -> Result<Vec<String>, IndexError> {
         Ok(self.data.keys().cloned().collect())
     }
@@ -321,6 +339,7 @@ impl IndexedStorage {
             primary,
             indices: Arc::new(RwLock::new(HashMap::new())),
             metadata: Arc::new(RwLock::new(HashMap::new())),
+            document_values: Arc::new(RwLock::new(HashMap::new())),
         }
     }
     
@@ -366,6 +385,47 @@ impl IndexedStorage {
         
         self.indices.write().await.remove(name);
         self.metadata.write().await.remove(name);
+        self.document_values.write().await.remove(name);
+        
+        Ok(())
+    }
+    
+    /// Get the type of an index, if it exists
+    pub async fn index_type(&self, name: &str) -> Option<IndexType> {
+        self.indices.read().await.get(name).map(|index| index.index_type())
+    }
+    
+    /// Set (or clear, with `None`) the value a document is indexed under
+    ///
+    /// The previous entry for the document is replaced. If the new value is
+    /// rejected by the index, the previous entry is restored.
+    pub async fn update_document(&self, index_name: &str, document_id: &str, value: Option<&str>) -> Result<(), IndexError> {
+        let mut indices = self.indices.write().await;
+        let index = indices.get_mut(index_name)
+            .ok_or_else(|| IndexError::IndexNotFound(index_name.to_string()))?;
+        let mut document_values = self.document_values.write().await;
+        let values = document_values.entry(index_name.to_string()).or_default();
+        
+        let previous = values.remove(document_id);
+        if let Some(previous) = &previous {
+            index.remove(previous, document_id).await?;
+        }
+        
+        if let Some(value) = value {
+            if let Err(e) = index.insert(value, document_id).await {
+                if let Some(previous) = previous {
+                    index.insert(&previous, document_id).await?;
+                    values.insert(document_id.to_string(), previous);
+                }
+                return Err(e);
+            }
+            values.insert(document_id.to_string(), value.to_string());
+        }
+        
+        if let Some(metadata) = self.metadata.write().await.get_mut(index_name) {
+            metadata.entry_count = values.len();
+            metadata.last_updated = chrono::Utc::now();
+        }
         
         Ok(())
     }
@@ -389,8 +449,8 @@ impl IndexedStorage {
         index.get(value).await
     }
     
-    /// Range query (for B-tree indices)
-    pub async fn range_query(&self, index_name: &str, _start: &str, _end: &str) -> Result<Vec<String>, IndexError> {
+    /// Range query over `start..end` (for B-tree indices)
+    pub async fn range_query(&self, index_name: &str, start: &str, end: &str) -> Result<Vec<String>, IndexError> {
         let indices = self.indices.read().await;
         let index = indices.get(index_name)
             .ok_or_else(|| IndexError::IndexNotFound(index_name.to_string()))?;
@@ -399,9 +459,7 @@ impl IndexedStorage {
             return Err(IndexError::InvalidIndexValue);
         }
         
-        // For now, return empty result
-        // TODO: Implement proper range query
-        Ok(Vec::new())
+        index.range(start, end).await
     }
     
     /// Get index statistics
@@ -467,4 +525,52 @@ mod tests {
         assert!(indexed.drop_index("test_index").await.is_ok());
         assert!(!indexed.list_indices().await.contains(&"test_index".to_string()));
     }
+
+    #[tokio::test]
+    async fn test_update_document_and_range_query() {
+        let primary = Arc::new(StorageEnum::Memory(MemoryStorage::new()));
+        let indexed = IndexedStorage::new(primary);
+        
+        let config = IndexConfig {
+            name: "priority".to_string(),
+            index_type: IndexType::BTree,
+            unique: false,
+            sparse: false,
+        };
+        assert!(indexed.create_index(config).await.is_ok());
+        
+        indexed.update_document("priority", "a", Some("1")).await.unwrap();
+        indexed.update_document("priority", "b", Some("3")).await.unwrap();
+        indexed.update_document("priority", "c", Some("2")).await.unwrap();
+        assert_eq!(indexed.range_query("priority", "1", "3").await.unwrap(), vec!["a", "c"]);
+        
+        // Re-indexing a document moves it rather than duplicating it
+        indexed.update_document("priority", "a", Some("5")).await.unwrap();
+        assert!(indexed.query_by_index("priority", "1").await.unwrap().is_empty());
+        assert_eq!(indexed.query_by_index("priority", "5").await.unwrap(), vec!["a"]);
+        
+        indexed.update_document("priority", "a", None).await.unwrap();
+        assert!(indexed.query_by_index("priority", "5").await.unwrap().is_empty());
+        assert_eq!(indexed.get_index_metadata("priority").await.unwrap().entry_count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_unique_violation_restores_previous_entry() {
+        let primary = Arc::new(StorageEnum::Memory(MemoryStorage::new()));
+        let indexed = IndexedStorage::new(primary);
+        
+        let config = IndexConfig {
+            name: "email".to_string(),
+            index_type: IndexType::Hash,
+            unique: true,
+            sparse: false,
+        };
+        assert!(indexed.create_index(config).await.is_ok());
+        
+        indexed.update_document("email", "a", Some("a@example.com")).await.unwrap();
+        indexed.update_document("email", "b", Some("b@example.com")).await.unwrap();
+        assert!(indexed.update_document("email", "b", Some("a@example.com")).await.is_err());
+        assert_eq!(indexed.query_by_index("email", "b@example.com").await.unwrap(), vec!["b"]);
+        assert!(indexed.range_query("email", "a", "z").await.is_err());
+    }
 }
@@ -320,7 +320,7 @@ mod tests {
             replica_id,
             last_seen: SystemTime::now(),
             is_online: true,
-            user_info: Some(crate::transport::message_protocol::UserInfo {
+            user_info: Some(crate::protocol::v1::UserInfo {
                 user_id: "user123".to_string(),
                 username: Some("testuser".to_string()),
                 display_name: Some("Test User".to_string()),
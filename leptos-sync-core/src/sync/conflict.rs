@@ -78,6 +78,10 @@ impl AdvancedConflictResolver {
         self
     }
 
+    pub fn default_strategy(&self) -> &ConflictStrategy {
+        &self.default_strategy
+    }
+
     pub fn register_strategy(&mut self, name: &str, strategy: Box<dyn ConflictResolutionStrategy + Send + Sync>) {
         self.strategies.insert(name.to_string(), strategy);
     }
@@ -323,7 +327,7 @@ impl ConflictResolutionStrategy for CustomMergeStrategy {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::crdt::LwwRegister;
+    use crate::crdt::{HlcTimestamp, LwwRegister};
 
     #[tokio::test]
     async fn test_advanced_conflict_resolver_creation() {
@@ -407,7 +411,7 @@ mod tests {
         let remote_replica = ReplicaId::default();
         
         // Create registers with the same timestamp to force a conflict
-        let now = Utc::now();
+        let now = HlcTimestamp::now();
         let local = LwwRegister::new("local", local_replica).with_timestamp(now);
         let remote = LwwRegister::new("remote", remote_replica).with_timestamp(now);
         
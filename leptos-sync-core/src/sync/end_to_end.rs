@@ -2,14 +2,15 @@
 
 use super::{PeerInfo, PeerSyncStatus, SyncEngine, SyncEngineError, SyncState};
 use crate::{
-    crdt::{LwwMap, LwwRegister, Mergeable, ReplicaId},
+    crdt::{HlcTimestamp, LwwMap, LwwRegister, Mergeable, ReplicaId},
+    protocol::{Message, ProtocolError, Sessions},
     storage::{LocalStorage, StorageError},
     transport::{SyncTransport, TransportError},
 };
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::{mpsc, RwLock};
 use tokio::time::{interval, sleep};
@@ -24,6 +25,8 @@ pub enum EndToEndSyncError {
     SyncEngine(#[from] SyncEngineError),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] ProtocolError),
     #[error("Sync operation failed: {0}")]
     SyncFailed(String),
     #[error("Peer not found: {0}")]
@@ -32,42 +35,6 @@ pub enum EndToEndSyncError {
     CollectionNotFound(String),
 }
 
-/// End-to-end synchronization message
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum SyncMessage {
-    /// Sync request with data
-    SyncRequest {
-        collection_id: String,
-        replica_id: ReplicaId,
-        data: Vec<u8>,
-        timestamp: u64,
-    },
-    /// Sync response with merged data
-    SyncResponse {
-        collection_id: String,
-        replica_id: ReplicaId,
-        data: Vec<u8>,
-        timestamp: u64,
-    },
-    /// Peer presence announcement
-    Presence {
-        replica_id: ReplicaId,
-        status: String,
-        timestamp: u64,
-    },
-    /// Heartbeat message
-    Heartbeat {
-        replica_id: ReplicaId,
-        timestamp: u64,
-    },
-    /// Acknowledgment
-    Ack {
-        message_id: String,
-        replica_id: ReplicaId,
-        timestamp: u64,
-    },
-}
-
 /// Collection metadata for synchronization
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CollectionMetadata {
@@ -91,8 +58,9 @@ where
     collections: Arc<RwLock<HashMap<String, CollectionMetadata>>>,
     peers: Arc<RwLock<HashMap<ReplicaId, PeerInfo>>>,
     sync_state: Arc<RwLock<SyncState>>,
-    message_sender: mpsc::UnboundedSender<SyncMessage>,
-    message_receiver: Arc<RwLock<mpsc::UnboundedReceiver<SyncMessage>>>,
+    sessions: Arc<RwLock<Sessions>>,
+    message_sender: mpsc::UnboundedSender<Message>,
+    message_receiver: Arc<RwLock<mpsc::UnboundedReceiver<Message>>>,
     sync_interval: Duration,
     heartbeat_interval: Duration,
     is_running: Arc<RwLock<bool>>,
@@ -121,6 +89,7 @@ where
             collections: Arc::new(RwLock::new(HashMap::new())),
             peers: Arc::new(RwLock::new(HashMap::new())),
             sync_state: Arc::new(RwLock::new(SyncState::Disconnected)),
+            sessions: Arc::new(RwLock::new(Sessions::new())),
             message_sender: tx,
             message_receiver: Arc::new(RwLock::new(rx)),
             sync_interval,
@@ -150,6 +119,14 @@ where
             *state = SyncState::Connected;
         }
 
+        // Offer our protocol versions and announce ourselves
+        self.send_message(Message::hello(self.replica_id)).await?;
+        self.send_message(Message::Presence {
+            replica_id: self.replica_id,
+            timestamp: HlcTimestamp::now(),
+        })
+        .await?;
+
         // Start background tasks
         self.start_background_tasks().await;
 
@@ -168,7 +145,11 @@ where
             *state = SyncState::Disconnected;
         }
 
-        Ok(())
+        self.send_message(Message::Leave {
+            replica_id: self.replica_id,
+            timestamp: HlcTimestamp::now(),
+        })
+        .await
     }
 
     /// Start background synchronization tasks
@@ -205,6 +186,10 @@ where
                 break;
             }
 
+            if let Err(e) = self.receive_messages().await {
+                tracing::error!("Receive error: {:?}", e);
+            }
+
             if let Err(e) = self.perform_sync().await {
                 tracing::error!("Sync task error: {:?}", e);
             }
@@ -240,6 +225,22 @@ where
         }
     }
 
+    /// Decode messages from the transport and queue them for the message handler
+    async fn receive_messages(&self) -> Result<(), EndToEndSyncError> {
+        for frame in self.transport.receive().await? {
+            match Message::decode(&frame) {
+                // Our own broadcast echoed back by a shared transport
+                Ok(message) if message.replica_id() == Some(self.replica_id) => {}
+                Ok(message) => {
+                    // The handler only stops once the manager is gone
+                    let _ = self.message_sender.send(message);
+                }
+                Err(e) => tracing::warn!("Skipping undecodable message: {}", e),
+            }
+        }
+        Ok(())
+    }
+
     /// Perform synchronization with all peers
     async fn perform_sync(&self) -> Result<(), EndToEndSyncError> {
         let collections = self.collections.read().await;
@@ -274,14 +275,11 @@ where
 
         if let Some(data) = local_data {
             // Create sync request
-            let message = SyncMessage::SyncRequest {
-                collection_id: collection_id.to_string(),
-                replica_id: self.replica_id.clone(),
+            let message = Message::Sync {
+                key: collection_id.to_string(),
                 data,
-                timestamp: SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as u64,
+                replica_id: self.replica_id,
+                timestamp: HlcTimestamp::now(),
             };
 
             // Send sync request
@@ -293,59 +291,83 @@ where
 
     /// Send a heartbeat message
     async fn send_heartbeat(&self) -> Result<(), EndToEndSyncError> {
-        let message = SyncMessage::Heartbeat {
-            replica_id: self.replica_id.clone(),
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as u64,
+        let message = Message::Heartbeat {
+            replica_id: self.replica_id,
+            timestamp: HlcTimestamp::now(),
         };
 
         self.send_message(message).await
     }
 
     /// Send a message via transport
-    async fn send_message(&self, message: SyncMessage) -> Result<(), EndToEndSyncError> {
-        let serialized = serde_json::to_vec(&message)?;
+    async fn send_message(&self, message: Message) -> Result<(), EndToEndSyncError> {
+        let serialized = message.encode()?;
         self.transport.send(&serialized).await.map_err(EndToEndSyncError::from)?;
         Ok(())
     }
 
     /// Handle incoming messages
-    async fn handle_message(&self, message: SyncMessage) -> Result<(), EndToEndSyncError> {
+    async fn handle_message(&self, message: Message) -> Result<(), EndToEndSyncError> {
         match message {
-            SyncMessage::SyncRequest {
-                collection_id,
+            Message::Hello {
                 replica_id,
+                versions,
+                features,
+            } => self.handle_hello(replica_id, versions, features).await,
+            Message::Sync {
+                key,
                 data,
-                timestamp,
-            } => {
-                self.handle_sync_request(collection_id, replica_id, data, timestamp)
-                    .await
-            }
-            SyncMessage::SyncResponse {
-                collection_id,
                 replica_id,
+                timestamp,
+            } => self.handle_sync_request(key, replica_id, data, timestamp).await,
+            Message::SyncResponse {
+                key,
                 data,
+                replica_id,
                 timestamp,
-            } => {
-                self.handle_sync_response(collection_id, replica_id, data, timestamp)
-                    .await
-            }
-            SyncMessage::Presence {
+            } => self.handle_sync_response(key, replica_id, data, timestamp).await,
+            Message::Presence {
                 replica_id,
-                status,
                 timestamp,
-            } => self.handle_presence(replica_id, status, timestamp).await,
-            SyncMessage::Heartbeat {
+            } => self.handle_presence(replica_id, true, timestamp).await,
+            Message::Leave {
                 replica_id,
                 timestamp,
-            } => self.handle_heartbeat(replica_id, timestamp).await,
-            SyncMessage::Ack {
-                message_id,
+            } => self.handle_presence(replica_id, false, timestamp).await,
+            Message::Heartbeat {
                 replica_id,
                 timestamp,
-            } => self.handle_ack(message_id, replica_id, timestamp).await,
+            } => self.handle_heartbeat(replica_id, timestamp).await,
+            Message::Ack {
+                key, replica_id, ..
+            } => self.handle_ack(key, replica_id).await,
+            // Deltas and conflicts are only exchanged by the sync engine
+            Message::Delta { .. }
+            | Message::StateVector { .. }
+            | Message::Conflict { .. }
+            | Message::Unknown { .. } => Ok(()),
+        }
+    }
+
+    /// Handle a peer's handshake, answering it the first time
+    async fn handle_hello(
+        &self,
+        replica_id: ReplicaId,
+        versions: Vec<u16>,
+        features: Vec<String>,
+    ) -> Result<(), EndToEndSyncError> {
+        let accepted = self
+            .sessions
+            .write()
+            .await
+            .accept(replica_id, &versions, &features);
+        match accepted {
+            Ok(true) => self.send_message(Message::hello(self.replica_id)).await,
+            Ok(false) => Ok(()),
+            Err(e) => {
+                tracing::warn!("Peer {} is incompatible: {}", replica_id, e);
+                Ok(())
+            }
         }
     }
 
@@ -355,7 +377,7 @@ where
         collection_id: String,
         replica_id: ReplicaId,
         data: Vec<u8>,
-        timestamp: u64,
+        timestamp: HlcTimestamp,
     ) -> Result<(), EndToEndSyncError> {
         // Get local data
         let local_data = self.storage.get::<Vec<u8>>(&collection_id).await?;
@@ -363,13 +385,7 @@ where
         // Merge data (simplified - in real implementation, use proper CRDT merge)
         let merged_data = if let Some(local) = local_data {
             // Simple merge strategy - in real implementation, use proper CRDT merge
-            if timestamp
-                > SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_millis() as u64
-                    - 1000
-            {
+            if timestamp.physical > HlcTimestamp::now().physical.saturating_sub(1000) {
                 data // Use remote data if it's newer
             } else {
                 local // Use local data if it's newer
@@ -382,14 +398,11 @@ where
         self.storage.set(&collection_id, &merged_data).await?;
 
         // Send sync response
-        let response = SyncMessage::SyncResponse {
-            collection_id,
-            replica_id: self.replica_id.clone(),
+        let response = Message::SyncResponse {
+            key: collection_id,
             data: merged_data,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_millis() as u64,
+            replica_id: self.replica_id,
+            timestamp: HlcTimestamp::now(),
         };
 
         self.send_message(response).await
@@ -401,7 +414,7 @@ where
         collection_id: String,
         replica_id: ReplicaId,
         data: Vec<u8>,
-        _timestamp: u64,
+        _timestamp: HlcTimestamp,
     ) -> Result<(), EndToEndSyncError> {
         // Store the merged data
         self.storage.set(&collection_id, &data).await?;
@@ -417,33 +430,38 @@ where
         Ok(())
     }
 
-    /// Handle presence message
+    /// Handle a peer announcing its presence or departure
     async fn handle_presence(
         &self,
         replica_id: ReplicaId,
-        status: String,
-        timestamp: u64,
+        online: bool,
+        _timestamp: HlcTimestamp,
     ) -> Result<(), EndToEndSyncError> {
+        let version = {
+            let mut sessions = self.sessions.write().await;
+            if !online {
+                // A returning peer starts a new session
+                sessions.remove(&replica_id);
+            }
+            sessions.get(&replica_id).map_or(1, |session| u32::from(session.version))
+        };
+        let status = if online {
+            PeerSyncStatus::Connected
+        } else {
+            PeerSyncStatus::Disconnected
+        };
         let mut peers = self.peers.write().await;
 
         let peer_info = PeerInfo {
-            replica_id: replica_id.clone(),
+            replica_id,
             last_seen: chrono::Utc::now(),
-            is_online: status == "connected",
+            is_online: online,
             last_sync: None,
-            sync_status: if status == "connected" {
-                PeerSyncStatus::Connected
-            } else {
-                PeerSyncStatus::Disconnected
-            },
+            sync_status: status.clone(),
             // Additional fields for compatibility
-            id: replica_id.clone(),
-            status: if status == "connected" {
-                PeerSyncStatus::Connected
-            } else {
-                PeerSyncStatus::Disconnected
-            },
-            version: 1,
+            id: replica_id,
+            status,
+            version,
         };
 
         peers.insert(replica_id, peer_info);
@@ -454,7 +472,7 @@ where
     async fn handle_heartbeat(
         &self,
         replica_id: ReplicaId,
-        timestamp: u64,
+        _timestamp: HlcTimestamp,
     ) -> Result<(), EndToEndSyncError> {
         let mut peers = self.peers.write().await;
 
@@ -468,9 +486,8 @@ where
     /// Handle acknowledgment message
     async fn handle_ack(
         &self,
-        _message_id: String,
+        _key: String,
         _replica_id: ReplicaId,
-        _timestamp: u64,
     ) -> Result<(), EndToEndSyncError> {
         // Handle acknowledgment - in a real implementation, this would update message tracking
         Ok(())
@@ -544,6 +561,7 @@ where
             collections: self.collections.clone(),
             peers: self.peers.clone(),
             sync_state: self.sync_state.clone(),
+            sessions: self.sessions.clone(),
             message_sender: self.message_sender.clone(),
             message_receiver: self.message_receiver.clone(),
             sync_interval: self.sync_interval,
@@ -632,30 +650,68 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_sync_message_serialization() {
-        let message = SyncMessage::SyncRequest {
-            collection_id: "test_collection".to_string(),
-            replica_id: ReplicaId::default(),
-            data: b"test data".to_vec(),
-            timestamp: 1234567890,
-        };
-
-        let serialized = serde_json::to_string(&message).unwrap();
-        let deserialized: SyncMessage = serde_json::from_str(&serialized).unwrap();
+    async fn test_managers_handshake_and_exchange_collections() {
+        let transport = Arc::new(InMemoryTransport::new());
+        let storage1 = Arc::new(MemoryStorage::new());
+        let storage2 = Arc::new(MemoryStorage::new());
+        let (replica1, replica2) = (ReplicaId::default(), ReplicaId::default());
+        let manager1 = EndToEndSyncManager::new(
+            replica1,
+            storage1,
+            transport.clone(),
+            Duration::from_secs(5),
+            Duration::from_secs(30),
+        );
+        let manager2 = EndToEndSyncManager::new(
+            replica2,
+            storage2.clone(),
+            transport.clone(),
+            Duration::from_secs(5),
+            Duration::from_secs(30),
+        );
 
-        match deserialized {
-            SyncMessage::SyncRequest {
-                collection_id,
-                replica_id,
-                data,
-                timestamp,
-            } => {
-                assert_eq!(collection_id, "test_collection");
-                assert_eq!(replica_id.0, replica_id.0); // Just check it's valid
-                assert_eq!(data, b"test data");
-                assert_eq!(timestamp, 1234567890);
-            }
-            _ => panic!("Unexpected message type"),
+        // Both open with a handshake and a presence announcement
+        for manager in [&manager1, &manager2] {
+            manager.send_message(Message::hello(manager.replica_id)).await.unwrap();
+            manager
+                .send_message(Message::Presence {
+                    replica_id: manager.replica_id,
+                    timestamp: HlcTimestamp::now(),
+                })
+                .await
+                .unwrap();
+        }
+        let frames = transport.receive().await.unwrap();
+        for frame in &frames {
+            let message = Message::decode(frame).unwrap();
+            let receiver = if message.replica_id() == Some(replica1) { &manager2 } else { &manager1 };
+            receiver.handle_message(message).await.unwrap();
         }
+        // Each answers the other's first handshake
+        let replies = transport.receive().await.unwrap();
+        assert_eq!(replies.len(), 2);
+        assert!(replies.iter().all(|frame| matches!(Message::decode(frame), Ok(Message::Hello { .. }))));
+        let peer = manager2.get_peer(&replica1).await.unwrap().unwrap();
+        assert!(peer.is_online);
+        assert_eq!(peer.version, u32::from(crate::protocol::PROTOCOL_VERSION));
+
+        // A sync request is stored and answered with the merged state
+        let request = Message::Sync {
+            key: "notes".to_string(),
+            data: b"hello".to_vec(),
+            replica_id: replica1,
+            timestamp: HlcTimestamp::now(),
+        };
+        transport.send(&request.encode().unwrap()).await.unwrap();
+        manager2.receive_messages().await.unwrap();
+        let queued = manager2.message_receiver.write().await.recv().await.unwrap();
+        manager2.handle_message(queued).await.unwrap();
+
+        assert_eq!(storage2.get::<Vec<u8>>("notes").await.unwrap(), Some(b"hello".to_vec()));
+        let frames = transport.receive().await.unwrap();
+        assert!(matches!(
+            Message::decode(&frames[0]).unwrap(),
+            Message::SyncResponse { key, replica_id, .. } if key == "notes" && replica_id == replica2
+        ));
     }
 }
@@ -1,14 +1,21 @@
 //! Enhanced synchronization engine for real-time sync
 
+use super::conflict::{AdvancedConflictResolver, ConflictMetadata, ConflictStrategy};
 use crate::{
-    crdt::{Mergeable, ReplicaId},
-    storage::Storage,
+    crdt::{
+        ClockError, DeltaCrdt, GarbageCollect, HlcTimestamp, HybridLogicalClock, Mergeable, ReplicaId,
+        StabilityFrontier, StabilityTracker, VersionVector,
+    },
+    protocol::{Message, ProtocolError, Session, Sessions},
+    storage::{LocalStorage, Storage},
     transport::{SyncTransport, TransportError},
 };
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -19,6 +26,8 @@ pub enum SyncEngineError {
     Transport(#[from] TransportError),
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(#[from] ProtocolError),
     #[error("CRDT error: {0}")]
     CrdtError(#[from] Box<dyn std::error::Error + Send + Sync>),
     #[error("Sync operation failed: {0}")]
@@ -48,19 +57,39 @@ pub enum SyncState {
     Disconnected,
 }
 
-/// Enhanced synchronization message types
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum SyncMessage<T> {
-    /// Sync request with data
-    Sync { key: String, data: T, replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc> },
-    /// Acknowledgment of sync
-    Ack { key: String, replica_id: ReplicaId },
-    /// Peer presence announcement
-    Presence { replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc> },
-    /// Conflict resolution request
-    Conflict { key: String, data: T, replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc> },
-    /// Heartbeat to keep connection alive
-    Heartbeat { replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc> },
+/// Result of draining the transport once
+#[derive(Debug, Default)]
+struct Incoming {
+    /// Keys whose local value changed
+    applied: Vec<String>,
+    /// Keys and peers that announced a state vector, and may be missing local changes
+    vector_requests: Vec<(String, ReplicaId)>,
+    /// Keys with deltas that could not be applied because earlier changes are missing
+    stale: Vec<String>,
+}
+
+/// Event emitted by the sync engine when local state changes because of a peer
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncEvent {
+    /// A remote change was decoded, merged into local storage and persisted
+    RemoteChangeApplied {
+        key: String,
+        replica_id: ReplicaId,
+        timestamp: HlcTimestamp,
+        /// Strategy used when the change conflicted with local state
+        conflict: Option<ConflictStrategy>,
+    },
+    /// A remote change could not be applied
+    RemoteChangeRejected {
+        key: String,
+        replica_id: ReplicaId,
+        reason: String,
+    },
+    /// A peer's handshake offered no protocol version this replica speaks
+    PeerIncompatible {
+        replica_id: ReplicaId,
+        reason: String,
+    },
 }
 
 /// Enhanced synchronization manager
@@ -73,8 +102,20 @@ where
     peers: Arc<RwLock<HashMap<ReplicaId, PeerInfo>>>,
     storage: Storage,
     transport: Tr,
-    sync_queue: Arc<RwLock<Vec<SyncMessage<Vec<u8>>>>>,
-    conflict_resolver: Arc<RwLock<Option<DefaultConflictResolver>>>,
+    sync_queue: Arc<RwLock<Vec<Message>>>,
+    conflict_resolver: Arc<RwLock<AdvancedConflictResolver>>,
+    event_sender: broadcast::Sender<SyncEvent>,
+    clock: Arc<RwLock<HybridLogicalClock>>,
+    /// Latest state vector announced by each peer, per key
+    peer_vectors: Arc<RwLock<HashMap<String, HashMap<ReplicaId, VersionVector>>>>,
+    /// What each peer has acknowledged, per key, to decide which tombstones can go
+    stability: Arc<RwLock<HashMap<String, StabilityTracker>>>,
+    /// Longest a peer may stay silent before it stops holding back garbage collection
+    gc_horizon: Arc<RwLock<Option<Duration>>>,
+    /// Protocol version and features agreed with each peer
+    sessions: Arc<RwLock<Sessions>>,
+    /// Whether our `Hello` went out since we last left
+    greeted: Arc<AtomicBool>,
 }
 
 /// Information about a peer
@@ -126,18 +167,12 @@ where
     Tr: SyncTransport + Clone + 'static,
 {
     pub fn new(storage: Storage, transport: Tr) -> Self {
-        Self {
-            replica_id: ReplicaId::default(),
-            state: Arc::new(RwLock::new(SyncState::NotSynced)),
-            peers: Arc::new(RwLock::new(HashMap::new())),
-            storage,
-            transport,
-            sync_queue: Arc::new(RwLock::new(Vec::new())),
-            conflict_resolver: Arc::new(RwLock::new(Some(DefaultConflictResolver))),
-        }
+        Self::with_replica_id(storage, transport, ReplicaId::default())
     }
 
     pub fn with_replica_id(storage: Storage, transport: Tr, replica_id: ReplicaId) -> Self {
+        let (event_sender, _) = broadcast::channel(1000);
+
         Self {
             replica_id,
             state: Arc::new(RwLock::new(SyncState::NotSynced)),
@@ -145,10 +180,77 @@ where
             storage,
             transport,
             sync_queue: Arc::new(RwLock::new(Vec::new())),
-            conflict_resolver: Arc::new(RwLock::new(Some(DefaultConflictResolver))),
+            conflict_resolver: Arc::new(RwLock::new(AdvancedConflictResolver::new())),
+            event_sender,
+            clock: Arc::new(RwLock::new(HybridLogicalClock::new())),
+            peer_vectors: Arc::new(RwLock::new(HashMap::new())),
+            stability: Arc::new(RwLock::new(HashMap::new())),
+            gc_horizon: Arc::new(RwLock::new(None)),
+            sessions: Arc::new(RwLock::new(Sessions::new())),
+            greeted: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Timestamp a local event with this replica's hybrid logical clock
+    pub async fn now(&self) -> HlcTimestamp {
+        self.clock.write().await.now()
+    }
+
+    /// Reject remote messages stamped further than `max_drift_ms` ahead of the local wall clock
+    pub async fn set_max_clock_drift(&self, max_drift_ms: u64) {
+        let mut clock = self.clock.write().await;
+        *clock = clock.clone().with_max_drift(max_drift_ms);
+    }
+
+    /// Stop waiting for peers silent for longer than `horizon` before collecting garbage
+    ///
+    /// Such peers may still hold tombstones that were purged in the meantime,
+    /// so their changes are rejected from then on. `None` waits indefinitely.
+    pub async fn set_gc_horizon(&self, horizon: Option<Duration>) {
+        *self.gc_horizon.write().await = horizon;
+        for tracker in self.stability.write().await.values_mut() {
+            tracker.set_horizon(horizon);
+        }
+    }
+
+    /// Updates to `key` that every tracked peer has acknowledged
+    pub async fn stability_frontier(&self, key: &str) -> StabilityFrontier {
+        let now = self.now().await;
+        match self.stability.write().await.get_mut(key) {
+            Some(tracker) => tracker.frontier(now),
+            None => StabilityFrontier::default(),
+        }
+    }
+
+    /// Purge the tombstones of the `V` stored under `key` that every peer has seen
+    ///
+    /// Returns how many were purged; the value is only stored again if any were.
+    pub async fn collect_garbage<V>(&self, key: &str) -> Result<usize, SyncEngineError>
+    where
+        V: DeltaCrdt + GarbageCollect + Serialize + DeserializeOwned + Send + Sync,
+    {
+        let Some(mut value) = self.storage.get::<V>(key).await? else {
+            return Ok(0);
+        };
+        // Only what this replica holds itself can be stable
+        let frontier = self.stability_frontier(key).await.meet(&value.version_vector());
+        let purged = value.gc(&frontier);
+        if purged > 0 {
+            self.storage.set(key, &value).await?;
+        }
+        Ok(purged)
+    }
+
+    /// Replace the resolver used for conflicting remote changes
+    pub async fn set_conflict_resolver(&self, resolver: AdvancedConflictResolver) {
+        *self.conflict_resolver.write().await = resolver;
+    }
+
+    /// Subscribe to events produced while processing remote messages
+    pub fn subscribe(&self) -> broadcast::Receiver<SyncEvent> {
+        self.event_sender.subscribe()
+    }
+
     pub async fn state(&self) -> SyncState {
         self.state.read().await.clone()
     }
@@ -176,7 +278,8 @@ where
             tracing::info!("Transport not connected, attempting to connect...");
         }
 
-        // Announce presence to peers
+        // Offer our protocol versions, then announce presence to peers
+        self.send_message(&Message::hello(self.replica_id)).await?;
         self.announce_presence().await?;
 
         // Start background sync loop
@@ -193,6 +296,11 @@ where
         // Disconnect from transport if needed
         if self.transport.is_connected() {
             tracing::info!("Stopping sync, disconnecting from transport...");
+            self.send_message(&Message::Leave {
+                replica_id: self.replica_id,
+                timestamp: self.now().await,
+            }).await?;
+            self.greeted.store(false, Ordering::SeqCst);
         }
 
         Ok(())
@@ -207,100 +315,342 @@ where
         let data = serde_json::to_vec(value)?;
         
         // Create sync message
-        let message = SyncMessage::Sync {
+        let message = Message::Sync {
             key: key.to_string(),
             data,
             replica_id: self.replica_id,
-            timestamp: chrono::Utc::now(),
+            timestamp: self.now().await,
         };
 
         // Add to sync queue
         {
             let mut queue = self.sync_queue.write().await;
-            queue.push(SyncMessage::Sync {
-                key: key.to_string(),
-                data: serde_json::to_vec(value)?,
-                replica_id: self.replica_id,
-                timestamp: chrono::Utc::now(),
-            });
+            queue.push(message.clone());
         }
 
         // Send via transport
-        let message_bytes = serde_json::to_vec(&message)?;
-        self.transport.send(&message_bytes).await
-            .map_err(|e| SyncEngineError::Transport(TransportError::SendFailed(e.to_string())))?;
+        self.send_message(&message).await
+    }
+
+    /// Start syncing and announce the state vector of every stored `V`
+    ///
+    /// Peers answer with deltas containing only the changes this replica is missing.
+    pub async fn start_delta_sync<V>(&mut self) -> Result<(), SyncEngineError>
+    where
+        V: DeltaCrdt + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
+        self.start_sync().await?;
+        self.announce_state_vectors::<V>().await
+    }
+
+    /// Send a CRDT value to peers, shipping only what they are missing
+    ///
+    /// Falls back to the full state until at least one peer has announced a
+    /// state vector for `key`.
+    pub async fn sync_delta<V>(&mut self, key: &str, value: &V) -> Result<(), SyncEngineError>
+    where
+        V: DeltaCrdt + Serialize + Send + Sync + Clone,
+    {
+        let Some(since) = self.known_peer_vector(key).await else {
+            return self.sync(key, value).await;
+        };
+        match value.delta_since(&since) {
+            Some(delta) => self.send_delta(key, &delta, since).await,
+            None => Ok(()),
+        }
+    }
 
+    /// Announce the state vector of every stored `V`
+    pub async fn announce_state_vectors<V>(&self) -> Result<(), SyncEngineError>
+    where
+        V: DeltaCrdt + DeserializeOwned + Send + Sync,
+    {
+        for key in self.storage.keys().await? {
+            self.announce_state_vector::<V>(&key).await?;
+        }
         Ok(())
     }
 
-    /// Process incoming messages
-    pub async fn process_messages(&mut self) -> Result<(), SyncEngineError> {
+    /// Announce the state vector of the `V` stored under `key`
+    pub async fn announce_state_vector<V>(&self, key: &str) -> Result<(), SyncEngineError>
+    where
+        V: DeltaCrdt + DeserializeOwned + Send + Sync,
+    {
+        let vector = self
+            .storage
+            .get::<V>(key)
+            .await?
+            .map(|value| value.version_vector())
+            .unwrap_or_default();
+        self.send_message(&Message::StateVector {
+            key: key.to_string(),
+            vector,
+            replica_id: self.replica_id,
+        }).await
+    }
+
+    /// Updates to `key` that every peer which announced a state vector has seen
+    async fn known_peer_vector(&self, key: &str) -> Option<VersionVector> {
+        let peer_vectors = self.peer_vectors.read().await;
+        peer_vectors
+            .get(key)?
+            .values()
+            .cloned()
+            .reduce(|common, vector| common.meet(&vector))
+    }
+
+    async fn send_delta<V>(&self, key: &str, delta: &V, since: VersionVector) -> Result<(), SyncEngineError>
+    where
+        V: Serialize,
+    {
+        self.send_message(&Message::Delta {
+            key: key.to_string(),
+            data: serde_json::to_vec(delta)?,
+            since,
+            replica_id: self.replica_id,
+            timestamp: self.now().await,
+        }).await
+    }
+
+    /// Process incoming messages, merging remote `V` states into local storage
+    ///
+    /// Returns the keys whose local value changed as a result. Deltas are
+    /// ignored; use [`SyncEngine::process_delta_messages`] for delta sync.
+    pub async fn process_messages<V>(&mut self) -> Result<Vec<String>, SyncEngineError>
+    where
+        V: Mergeable + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
+        Ok(self.process_incoming::<V>(None).await?.applied)
+    }
+
+    /// Process incoming messages, applying deltas and answering peers' state vectors
+    ///
+    /// Returns the keys whose local value changed as a result.
+    pub async fn process_delta_messages<V>(&mut self) -> Result<Vec<String>, SyncEngineError>
+    where
+        V: DeltaCrdt + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
+        let incoming = self.process_incoming::<V>(Some(V::version_vector)).await?;
+
+        for (key, peer) in &incoming.vector_requests {
+            let peer_vector = self.peer_vectors.read().await
+                .get(key)
+                .and_then(|vectors| vectors.get(peer))
+                .cloned()
+                .unwrap_or_default();
+            let delta = self.storage.get::<V>(key).await?
+                .and_then(|value| value.delta_since(&peer_vector));
+            if let Some(delta) = delta {
+                self.send_delta(key, &delta, peer_vector).await?;
+            }
+        }
+
+        // Tell peers what we now have, so they can fill any remaining gaps
+        let mut announced = std::collections::HashSet::new();
+        for key in incoming.applied.iter().chain(&incoming.stale) {
+            if announced.insert(key) {
+                self.announce_state_vector::<V>(key).await?;
+            }
+        }
+
+        Ok(incoming.applied)
+    }
+
+    /// Drain the transport and handle every message
+    ///
+    /// Deltas are only applied when `version_of` is given and the local state
+    /// already contains every change the delta was computed against.
+    async fn process_incoming<V>(&mut self, version_of: Option<fn(&V) -> VersionVector>) -> Result<Incoming, SyncEngineError>
+    where
+        V: Mergeable + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
         // Receive messages from transport
         let messages = self.transport.receive().await
             .map_err(|e| SyncEngineError::Transport(TransportError::ReceiveFailed(e.to_string())))?;
+        let mut incoming = Incoming::default();
         
         for message_bytes in messages {
-            let message: SyncMessage<Vec<u8>> = serde_json::from_slice(&message_bytes)?;
+            let message = match Message::decode(&message_bytes) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!("Skipping undecodable message: {}", e);
+                    continue;
+                }
+            };
             
             match message {
-                SyncMessage::Sync { key, data, replica_id, timestamp } => {
+                Message::Hello { replica_id, .. }
+                | Message::Sync { replica_id, .. }
+                | Message::SyncResponse { replica_id, .. }
+                | Message::Conflict { replica_id, .. }
+                | Message::Delta { replica_id, .. }
+                | Message::StateVector { replica_id, .. }
+                | Message::Ack { replica_id, .. }
+                | Message::Leave { replica_id, .. }
+                    if replica_id == self.replica_id =>
+                {
+                    // Our own broadcast echoed back by a shared transport
+                    continue;
+                }
+                Message::Sync { ref key, replica_id, timestamp, .. }
+                | Message::SyncResponse { ref key, replica_id, timestamp, .. }
+                | Message::Conflict { ref key, replica_id, timestamp, .. }
+                | Message::Delta { ref key, replica_id, timestamp, .. } => {
+                    if self.sessions.read().await.get(&replica_id).is_none() {
+                        // Never said hello, or its hello was rejected
+                        tracing::warn!("Dropping change for key {} from replica {} without a session", key, replica_id);
+                        continue;
+                    }
+                    let rejection = match self.receive_timestamp(timestamp).await {
+                        Ok(now) => self.check_stability(key, replica_id, now).await.err(),
+                        Err(e) => Some(e.to_string()),
+                    };
+                    if let Some(reason) = rejection {
+                        tracing::warn!("Dropping change for key {} from replica {}: {}", key, replica_id, reason);
+                        self.emit_event(SyncEvent::RemoteChangeRejected {
+                            key: key.clone(),
+                            replica_id,
+                            reason,
+                        });
+                        continue;
+                    }
+                }
+                Message::Presence { replica_id, timestamp }
+                | Message::Heartbeat { replica_id, timestamp }
+                | Message::Leave { replica_id, timestamp } => {
+                    if let Err(e) = self.receive_timestamp(timestamp).await {
+                        tracing::warn!("Ignoring message from replica {}: {}", replica_id, e);
+                        continue;
+                    }
+                }
+                Message::Unknown { kind, .. } => {
+                    tracing::debug!("Ignoring message of unknown kind {}", kind);
+                    continue;
+                }
+                Message::Hello { .. } | Message::Ack { .. } | Message::StateVector { .. } => {}
+            }
+
+            match message {
+                Message::Hello { replica_id, versions, features } => {
+                    self.handle_hello_message(replica_id, &versions, &features).await?;
+                }
+                Message::Sync { key, data, replica_id, timestamp } => {
                     // Handle sync message
-                    self.handle_sync_message(key, data, replica_id, timestamp).await?;
+                    if self.handle_sync_message::<V>(&key, data, replica_id, timestamp, version_of).await? {
+                        incoming.applied.push(key);
+                    }
+                }
+                Message::SyncResponse { key, data, replica_id, timestamp } => {
+                    // Already an answer, so not acknowledged
+                    if self.apply_remote::<V>(&key, &data, replica_id, timestamp, false).await? {
+                        incoming.applied.push(key);
+                    }
                 }
-                SyncMessage::Ack { key, replica_id } => {
+                Message::Ack { key, replica_id, vector } => {
                     // Handle acknowledgment
-                    self.handle_ack_message(key, replica_id).await?;
+                    self.handle_ack_message(key, replica_id, vector).await?;
                 }
-                SyncMessage::Presence { replica_id, timestamp } => {
+                Message::Presence { replica_id, timestamp } => {
                     // Handle presence update
                     self.handle_presence_message(replica_id, timestamp).await?;
                 }
-                SyncMessage::Conflict { key, data, replica_id, timestamp } => {
+                Message::Conflict { key, data, replica_id, timestamp } => {
                     // Handle conflict resolution
-                    self.handle_conflict_message(key, data, replica_id, timestamp).await?;
+                    if self.handle_conflict_message::<V>(&key, data, replica_id, timestamp).await? {
+                        incoming.applied.push(key);
+                    }
                 }
-                SyncMessage::Heartbeat { replica_id, timestamp } => {
+                Message::Heartbeat { replica_id, timestamp } => {
                     // Handle heartbeat
                     self.handle_heartbeat_message(replica_id, timestamp).await?;
                 }
+                Message::Leave { replica_id, .. } => {
+                    self.handle_leave_message(replica_id).await;
+                }
+                Message::Unknown { .. } => {}
+                Message::StateVector { key, vector, replica_id } => {
+                    self.acknowledge(&key, replica_id, &vector).await;
+                    self.peer_vectors.write().await
+                        .entry(key.clone())
+                        .or_default()
+                        .insert(replica_id, vector);
+                    incoming.vector_requests.push((key, replica_id));
+                }
+                Message::Delta { key, data, since, replica_id, timestamp } => {
+                    let Some(version_of) = version_of else {
+                        tracing::debug!("Ignoring delta for key {} outside delta sync", key);
+                        continue;
+                    };
+                    let local_version = self.storage.get::<V>(&key).await?
+                        .map(|local| version_of(&local))
+                        .unwrap_or_default();
+                    if !local_version.dominates(&since) {
+                        tracing::debug!("Delta for key {} from replica {} is ahead of local state", key, replica_id);
+                        incoming.stale.push(key);
+                    } else if self.handle_sync_message::<V>(&key, data, replica_id, timestamp, Some(version_of)).await? {
+                        incoming.applied.push(key);
+                    }
+                }
             }
         }
 
-        Ok(())
+        Ok(incoming)
+    }
+
+    /// Record the updates to `key` a peer has seen, ignoring peers past the GC horizon
+    async fn acknowledge(&self, key: &str, replica_id: ReplicaId, vector: &VersionVector) {
+        let now = self.now().await;
+        let horizon = *self.gc_horizon.read().await;
+        let peers: Vec<ReplicaId> = self.peers.read().await.keys().copied().collect();
+        let mut stability = self.stability.write().await;
+        let tracker = stability.entry(key.to_string()).or_insert_with(|| {
+            // Every peer known so far holds back garbage collection until it acknowledges
+            let mut tracker = StabilityTracker::new();
+            tracker.set_horizon(horizon);
+            for peer in peers {
+                tracker.add_replica(peer, now);
+            }
+            tracker
+        });
+        if let Err(e) = tracker.acknowledge(replica_id, vector, now) {
+            tracing::debug!("Ignoring acknowledgement for key {}: {}", key, e);
+        }
+    }
+
+    /// Refuse changes from a peer that expired past the GC horizon for `key`
+    async fn check_stability(&self, key: &str, replica_id: ReplicaId, now: HlcTimestamp) -> Result<(), String> {
+        match self.stability.write().await.get_mut(key) {
+            Some(tracker) => tracker.check(&replica_id, now).map_err(|e| e.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    /// Advance the local clock past a remote timestamp
+    async fn receive_timestamp(&self, timestamp: HlcTimestamp) -> Result<HlcTimestamp, ClockError> {
+        self.clock.write().await.receive(timestamp)
     }
 
     /// Announce presence to peers
     async fn announce_presence(&self) -> Result<(), SyncEngineError> {
-        let message: SyncMessage<()> = SyncMessage::Presence {
+        self.send_message(&Message::Presence {
             replica_id: self.replica_id,
-            timestamp: chrono::Utc::now(),
-        };
-
-        let message_bytes = serde_json::to_vec(&message)?;
-        self.transport.send(&message_bytes).await
-            .map_err(|e| SyncEngineError::Transport(TransportError::SendFailed(e.to_string())))?;
-
-        Ok(())
+            timestamp: self.now().await,
+        }).await
     }
 
     /// Send heartbeat to peers
     async fn send_heartbeat(&self) -> Result<(), SyncEngineError> {
-        let message: SyncMessage<()> = SyncMessage::Heartbeat {
+        self.send_message(&Message::Heartbeat {
             replica_id: self.replica_id,
-            timestamp: chrono::Utc::now(),
-        };
-
-        let message_bytes = serde_json::to_vec(&message)?;
-        self.transport.send(&message_bytes).await
-            .map_err(|e| SyncEngineError::Transport(TransportError::SendFailed(e.to_string())))?;
-
-        Ok(())
+            timestamp: self.now().await,
+        }).await
     }
 
     /// Start background synchronization loop
     async fn start_background_sync(&self) {
         let transport = self.transport.clone();
         let replica_id = self.replica_id;
+        let clock = self.clock.clone();
         
         tokio::spawn(async move {
             let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
@@ -309,12 +659,12 @@ where
                 interval.tick().await;
                 
                 // Send heartbeat
-                let message: SyncMessage<()> = SyncMessage::Heartbeat {
+                let message = Message::Heartbeat {
                     replica_id,
-                    timestamp: chrono::Utc::now(),
+                    timestamp: clock.write().await.now(),
                 };
                 
-                if let Ok(message_bytes) = serde_json::to_vec(&message) {
+                if let Ok(message_bytes) = message.encode() {
                     let _ = transport.send(&message_bytes).await;
                 }
             }
@@ -322,36 +672,167 @@ where
     }
 
     /// Handle sync message
-    async fn handle_sync_message(&mut self, key: String, _data: Vec<u8>, replica_id: ReplicaId, _timestamp: chrono::DateTime<chrono::Utc>) -> Result<(), SyncEngineError> {
+    ///
+    /// With `version_of` given, the acknowledgement tells peers which updates
+    /// this replica now holds.
+    async fn handle_sync_message<V>(
+        &mut self,
+        key: &str,
+        data: Vec<u8>,
+        replica_id: ReplicaId,
+        timestamp: HlcTimestamp,
+        version_of: Option<fn(&V) -> VersionVector>,
+    ) -> Result<bool, SyncEngineError>
+    where
+        V: Mergeable + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
         tracing::debug!("Received sync message for key {} from replica {}", key, replica_id);
-        
-        // For now, just acknowledge
-        let ack: SyncMessage<()> = SyncMessage::Ack {
-            key,
-            replica_id,
+
+        let applied = self.apply_remote::<V>(key, &data, replica_id, timestamp, false).await?;
+        let vector = match version_of {
+            Some(version_of) => self.storage.get::<V>(key).await?.map(|local| version_of(&local)),
+            None => None,
         };
-        
-        let ack_bytes = serde_json::to_vec(&ack)?;
-        self.transport.send(&ack_bytes).await
-            .map_err(|e| SyncEngineError::Transport(TransportError::SendFailed(e.to_string())))?;
+        self.send_ack(key, vector).await?;
+
+        Ok(applied)
+    }
+
+    /// Decode a remote state, merge it with the local value and persist the result
+    async fn apply_remote<V>(
+        &mut self,
+        key: &str,
+        data: &[u8],
+        replica_id: ReplicaId,
+        timestamp: HlcTimestamp,
+        flagged_conflict: bool,
+    ) -> Result<bool, SyncEngineError>
+    where
+        V: Mergeable + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
+        let remote: V = match serde_json::from_slice(data) {
+            Ok(remote) => remote,
+            Err(e) => {
+                tracing::warn!("Failed to decode sync payload for key {}: {}", key, e);
+                self.emit_event(SyncEvent::RemoteChangeRejected {
+                    key: key.to_string(),
+                    replica_id,
+                    reason: e.to_string(),
+                });
+                return Ok(false);
+            }
+        };
+
+        let (merged, conflict) = match self.storage.get::<V>(key).await? {
+            Some(local) if flagged_conflict || self.has_conflict(&local, &remote) => {
+                let mut resolver = self.conflict_resolver.write().await;
+                let metadata = ConflictMetadata {
+                    replica_id,
+                    timestamp: timestamp.to_datetime(),
+                    version: 1,
+                    conflict_type: std::any::type_name::<V>().to_string(),
+                    resolution_strategy: resolver.default_strategy().clone(),
+                };
+                let resolution = resolver
+                    .resolve(&local, &remote, Some(metadata))
+                    .await
+                    .map_err(|e| SyncEngineError::ConflictResolution(e.to_string()))?;
+                if resolution.conflicts_resolved == 0 {
+                    // The resolver saw no real conflict; fall back to a plain merge
+                    let mut merged = local;
+                    merged
+                        .merge(&remote)
+                        .map_err(|e| SyncEngineError::CrdtError(Box::new(e)))?;
+                    (merged, None)
+                } else {
+                    (resolution.resolved_value, Some(resolution.strategy_used))
+                }
+            }
+            Some(mut local) => {
+                local
+                    .merge(&remote)
+                    .map_err(|e| SyncEngineError::CrdtError(Box::new(e)))?;
+                (local, None)
+            }
+            None => (remote, None),
+        };
+
+        self.storage.set(key, &merged).await?;
+        self.mark_peer_synced(replica_id).await;
+        self.emit_event(SyncEvent::RemoteChangeApplied {
+            key: key.to_string(),
+            replica_id,
+            timestamp,
+            conflict,
+        });
+
+        Ok(true)
+    }
+
+    /// Acknowledge that a remote change for `key` has been applied
+    async fn send_ack(&self, key: &str, vector: Option<VersionVector>) -> Result<(), SyncEngineError> {
+        self.send_message(&Message::Ack {
+            key: key.to_string(),
+            replica_id: self.replica_id,
+            vector,
+        }).await
+    }
+
+    /// Encode a message and send it via the transport
+    ///
+    /// Peers ignore changes from replicas they have no session with, so our
+    /// `Hello` goes out first if it has not yet.
+    async fn send_message(&self, message: &Message) -> Result<(), SyncEngineError> {
+        if !self.greeted.load(Ordering::SeqCst) && !matches!(message, Message::Hello { .. }) {
+            self.send_frame(&Message::hello(self.replica_id).encode()?).await?;
+            self.greeted.store(true, Ordering::SeqCst);
+        }
+        self.send_frame(&message.encode()?).await?;
+        if matches!(message, Message::Hello { .. }) {
+            self.greeted.store(true, Ordering::SeqCst);
+        }
 
         Ok(())
     }
 
+    async fn send_frame(&self, message_bytes: &[u8]) -> Result<(), SyncEngineError> {
+        self.transport.send(message_bytes).await
+            .map_err(|e| SyncEngineError::Transport(TransportError::SendFailed(e.to_string())))
+    }
+
+    /// Record a successful sync with a peer
+    async fn mark_peer_synced(&self, replica_id: ReplicaId) {
+        let now = chrono::Utc::now();
+        let mut peers = self.peers.write().await;
+        if let Some(peer_info) = peers.get_mut(&replica_id) {
+            peer_info.last_seen = now;
+            peer_info.last_sync = Some(now);
+            peer_info.sync_status = PeerSyncStatus::Success { timestamp: now };
+            peer_info.status = PeerSyncStatus::Success { timestamp: now };
+        }
+    }
+
+    fn emit_event(&self, event: SyncEvent) {
+        // Sending only fails when nobody is subscribed
+        let _ = self.event_sender.send(event);
+    }
+
     /// Handle acknowledgment message
-    async fn handle_ack_message(&mut self, _key: String, _replica_id: ReplicaId) -> Result<(), SyncEngineError> {
-        // For now, just log
-        tracing::debug!("Received ack message");
+    async fn handle_ack_message(&mut self, key: String, replica_id: ReplicaId, vector: Option<VersionVector>) -> Result<(), SyncEngineError> {
+        tracing::debug!("Received ack message for key {} from replica {}", key, replica_id);
+        if let Some(vector) = vector {
+            self.acknowledge(&key, replica_id, &vector).await;
+        }
         Ok(())
     }
 
     /// Handle presence message
-    async fn handle_presence_message(&mut self, replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc>) -> Result<(), SyncEngineError> {
+    async fn handle_presence_message(&mut self, replica_id: ReplicaId, timestamp: HlcTimestamp) -> Result<(), SyncEngineError> {
         let mut peers = self.peers.write().await;
         
         let peer_info = PeerInfo {
             replica_id: replica_id.clone(),
-            last_seen: timestamp,
+            last_seen: timestamp.to_datetime(),
             is_online: true,
             last_sync: None,
             sync_status: PeerSyncStatus::Never,
@@ -362,30 +843,74 @@ where
         };
         
         peers.insert(replica_id, peer_info);
+        drop(peers);
+
+        // A new peer holds back garbage collection until it acknowledges what it has
+        let now = self.now().await;
+        for tracker in self.stability.write().await.values_mut() {
+            tracker.add_replica(replica_id, now);
+        }
         
         tracing::debug!("Updated peer info for replica {}", replica_id);
         Ok(())
     }
 
-    /// Handle conflict message
-    async fn handle_conflict_message(&mut self, _key: String, _data: Vec<u8>, _replica_id: ReplicaId, _timestamp: chrono::DateTime<chrono::Utc>) -> Result<(), SyncEngineError> {
-        // For now, just log
-        tracing::debug!("Received conflict message");
+    /// Handle a peer's handshake, answering it the first time
+    async fn handle_hello_message(&mut self, replica_id: ReplicaId, versions: &[u16], features: &[String]) -> Result<(), SyncEngineError> {
+        let accepted = self.sessions.write().await.accept(replica_id, versions, features);
+        match accepted {
+            Ok(true) => self.send_message(&Message::hello(self.replica_id)).await?,
+            Ok(false) => {}
+            Err(e) => {
+                tracing::warn!("Replica {} is incompatible: {}", replica_id, e);
+                self.emit_event(SyncEvent::PeerIncompatible {
+                    replica_id,
+                    reason: e.to_string(),
+                });
+            }
+        }
         Ok(())
     }
 
+    /// Handle a peer leaving; its next handshake starts a new session
+    async fn handle_leave_message(&mut self, replica_id: ReplicaId) {
+        self.sessions.write().await.remove(&replica_id);
+        if let Some(peer_info) = self.peers.write().await.get_mut(&replica_id) {
+            peer_info.is_online = false;
+            peer_info.status = PeerSyncStatus::Disconnected;
+        }
+    }
+
+    /// Handle conflict message
+    async fn handle_conflict_message<V>(&mut self, key: &str, data: Vec<u8>, replica_id: ReplicaId, timestamp: HlcTimestamp) -> Result<bool, SyncEngineError>
+    where
+        V: Mergeable + Serialize + DeserializeOwned + Send + Sync + Clone,
+    {
+        tracing::debug!("Received conflict message for key {} from replica {}", key, replica_id);
+
+        let applied = self.apply_remote::<V>(key, &data, replica_id, timestamp, true).await?;
+        self.send_ack(key, None).await?;
+
+        Ok(applied)
+    }
+
     /// Handle heartbeat message
-    async fn handle_heartbeat_message(&mut self, replica_id: ReplicaId, timestamp: chrono::DateTime<chrono::Utc>) -> Result<(), SyncEngineError> {
+    async fn handle_heartbeat_message(&mut self, replica_id: ReplicaId, timestamp: HlcTimestamp) -> Result<(), SyncEngineError> {
         let mut peers = self.peers.write().await;
         
         if let Some(peer_info) = peers.get_mut(&replica_id) {
-            peer_info.last_seen = timestamp;
+            peer_info.last_seen = timestamp.to_datetime();
             tracing::debug!("Updated heartbeat for replica {}", replica_id);
         }
 
         Ok(())
     }
 
+    /// Protocol version and features agreed with a peer, once it has said hello
+    pub async fn peer_session(&self, replica_id: &ReplicaId) -> Option<Session> {
+        self.sessions.read().await.get(replica_id).cloned()
+    }
+
     /// Get all peers
     pub async fn peers(&self) -> impl Iterator<Item = (ReplicaId, PeerInfo)> + 'static {
         let peers = self.peers.read().await;
@@ -393,8 +918,240 @@ where
     }
 
     /// Check if there's a conflict between two values
-    fn has_conflict<V: Mergeable>(&self, _local: &V, _remote: &V) -> bool {
-        // For now, always return false - in a real implementation you'd check timestamps
-        false
+    fn has_conflict<V: Mergeable>(&self, local: &V, remote: &V) -> bool {
+        local.has_conflict(remote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crdt::LwwMap;
+    use std::collections::BTreeMap;
+    use crate::transport::InMemoryTransport;
+
+    type Doc = LwwMap<String, i32>;
+
+    /// Deleted keys with their deletion stamps, just enough to exercise garbage collection
+    #[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+    struct Tombstones {
+        deleted: BTreeMap<String, (ReplicaId, u64)>,
+        version: VersionVector,
+    }
+
+    impl Tombstones {
+        fn delete(&mut self, key: &str, replica: ReplicaId) {
+            let version = self.version.increment(replica);
+            self.deleted.insert(key.to_string(), (replica, version));
+        }
+    }
+
+    impl Mergeable for Tombstones {
+        type Error = std::convert::Infallible;
+
+        fn merge(&mut self, other: &Self) -> Result<(), Self::Error> {
+            for (key, stamp) in &other.deleted {
+                // Purged keys are covered by the version vector already
+                if !self.deleted.contains_key(key) && !self.version.includes(&stamp.0, stamp.1) {
+                    self.deleted.insert(key.clone(), *stamp);
+                }
+            }
+            self.version.merge(&other.version);
+            Ok(())
+        }
+
+        fn has_conflict(&self, _other: &Self) -> bool {
+            false
+        }
+    }
+
+    impl DeltaCrdt for Tombstones {
+        fn version_vector(&self) -> VersionVector {
+            self.version.clone()
+        }
+
+        fn delta_since(&self, since: &VersionVector) -> Option<Self> {
+            let deleted: BTreeMap<_, _> = self
+                .deleted
+                .iter()
+                .filter(|(_, (replica, version))| !since.includes(replica, *version))
+                .map(|(key, stamp)| (key.clone(), *stamp))
+                .collect();
+            (!deleted.is_empty()).then(|| Self { deleted, version: self.version.clone() })
+        }
+    }
+
+    impl GarbageCollect for Tombstones {
+        fn gc(&mut self, frontier: &StabilityFrontier) -> usize {
+            let before = self.deleted.len();
+            self.deleted.retain(|_, (replica, version)| !frontier.is_stable(replica, *version));
+            before - self.deleted.len()
+        }
+    }
+
+    fn document(entries: i32) -> Doc {
+        let replica = ReplicaId::default();
+        let mut map = LwwMap::new();
+        for i in 0..entries {
+            map.insert(format!("entry-{}", i), i, replica);
+        }
+        map
+    }
+
+    #[tokio::test]
+    async fn test_delta_sync_ships_only_missing_entries() {
+        let transport = InMemoryTransport::new();
+        let mut engine1 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut engine2 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut map = document(1000);
+        engine1.storage.set("doc", &map).await.unwrap();
+        engine2.storage.set("doc", &map).await.unwrap();
+
+        // Peers exchange state vectors; nothing is missing yet
+        engine2.announce_state_vectors::<Doc>().await.unwrap();
+        assert!(engine1.process_delta_messages::<Doc>().await.unwrap().is_empty());
+        // engine2 takes the hello engine1 answered with
+        assert!(engine2.process_delta_messages::<Doc>().await.unwrap().is_empty());
+        engine1.process_delta_messages::<Doc>().await.unwrap();
+        assert!(transport.receive().await.unwrap().is_empty());
+
+        map.insert("entry-7".to_string(), -7, ReplicaId::default());
+        engine1.storage.set("doc", &map).await.unwrap();
+        engine1.sync_delta("doc", &map).await.unwrap();
+
+        let messages = transport.receive().await.unwrap();
+        assert_eq!(messages.len(), 1);
+        match Message::decode(&messages[0]).unwrap() {
+            Message::Delta { data, .. } => {
+                let delta: Doc = serde_json::from_slice(&data).unwrap();
+                assert_eq!(delta.len(), 1);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        transport.send(&messages[0]).await.unwrap();
+
+        assert_eq!(engine2.process_delta_messages::<Doc>().await.unwrap(), vec!["doc".to_string()]);
+        let synced: Doc = engine2.storage.get("doc").await.unwrap().unwrap();
+        assert_eq!(synced.get(&"entry-7".to_string()), Some(&-7));
+        assert_eq!(synced.version_vector(), map.version_vector());
+    }
+
+    #[tokio::test]
+    async fn test_delta_missing_history_triggers_catch_up() {
+        let transport = InMemoryTransport::new();
+        let mut engine1 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut engine2 = SyncEngine::new(Storage::memory(), transport.clone());
+        let engine3 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut map = document(10);
+        engine1.storage.set("doc", &map).await.unwrap();
+        engine3.storage.set("doc", &map).await.unwrap();
+
+        engine3.announce_state_vectors::<Doc>().await.unwrap();
+        engine1.process_delta_messages::<Doc>().await.unwrap();
+        map.insert("entry-10".to_string(), 10, ReplicaId::default());
+        engine1.storage.set("doc", &map).await.unwrap();
+        engine1.sync_delta("doc", &map).await.unwrap();
+
+        // engine2 has never seen the document, so the delta alone is not enough
+        assert!(engine2.process_delta_messages::<Doc>().await.unwrap().is_empty());
+        assert!(engine2.storage.get::<Doc>("doc").await.unwrap().is_none());
+
+        // Its state vector announcement makes engine1 send everything it lacks
+        engine1.process_delta_messages::<Doc>().await.unwrap();
+        assert_eq!(engine2.process_delta_messages::<Doc>().await.unwrap(), vec!["doc".to_string()]);
+        let synced: Doc = engine2.storage.get("doc").await.unwrap().unwrap();
+        assert_eq!(synced.len(), 11);
+    }
+
+    #[tokio::test]
+    async fn test_tombstones_are_collected_once_every_peer_acknowledged() {
+        let transport = InMemoryTransport::new();
+        let mut engine1 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut engine2 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut tombstones = Tombstones::default();
+        tombstones.delete("a", engine1.replica_id());
+        engine1.storage.set("doc", &tombstones).await.unwrap();
+
+        // engine2 has nothing yet, so the tombstone has to stay
+        engine2.announce_state_vector::<Tombstones>("doc").await.unwrap();
+        engine1.process_delta_messages::<Tombstones>().await.unwrap();
+        assert_eq!(engine1.collect_garbage::<Tombstones>("doc").await.unwrap(), 0);
+
+        // engine2 applies the catch-up delta and acknowledges it
+        engine2.process_delta_messages::<Tombstones>().await.unwrap();
+        engine1.process_delta_messages::<Tombstones>().await.unwrap();
+        assert_eq!(engine1.collect_garbage::<Tombstones>("doc").await.unwrap(), 1);
+        let collected: Tombstones = engine1.storage.get("doc").await.unwrap().unwrap();
+        assert!(collected.deleted.is_empty());
+
+        // Past the horizon engine2 may still hold purged tombstones, so it is refused
+        engine1.set_gc_horizon(Some(Duration::from_millis(1))).await;
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let mut events = engine1.subscribe();
+        let stale: Tombstones = engine2.storage.get("doc").await.unwrap().unwrap();
+        engine2.sync("doc", &stale).await.unwrap();
+        assert!(engine1.process_delta_messages::<Tombstones>().await.unwrap().is_empty());
+        assert!(matches!(events.try_recv(), Ok(SyncEvent::RemoteChangeRejected { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_handshake_negotiates_sessions_and_skips_unknown_messages() {
+        use crate::protocol::PROTOCOL_VERSION;
+
+        let transport = InMemoryTransport::new();
+        let mut engine1 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut engine2 = SyncEngine::new(Storage::memory(), transport.clone());
+        let mut events = engine1.subscribe();
+
+        engine1.start_sync().await.unwrap();
+        engine2.process_messages::<Doc>().await.unwrap();
+        engine1.process_messages::<Doc>().await.unwrap();
+        let session = engine1.peer_session(&engine2.replica_id()).await.unwrap();
+        assert_eq!(session.version, PROTOCOL_VERSION);
+        assert_eq!(engine2.peer_session(&engine1.replica_id()).await, Some(session));
+
+        // A peer from the future, a message kind from the future and plain garbage
+        let stranger = ReplicaId::default();
+        let hello = Message::Hello { replica_id: stranger, versions: vec![99], features: Vec::new() };
+        transport.send(&hello.encode().unwrap()).await.unwrap();
+        transport.send(&Message::Unknown { kind: 999, payload: vec![1] }.encode().unwrap()).await.unwrap();
+        transport.send(b"not a frame").await.unwrap();
+
+        assert!(engine1.process_messages::<Doc>().await.unwrap().is_empty());
+        assert!(engine1.peer_session(&stranger).await.is_none());
+        assert!(matches!(
+            events.try_recv(),
+            Ok(SyncEvent::PeerIncompatible { replica_id, .. }) if replica_id == stranger
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_changes_from_incompatible_peers_are_dropped() {
+        let transport = InMemoryTransport::new();
+        let mut engine = SyncEngine::new(Storage::memory(), transport.clone());
+        let stranger = ReplicaId::default();
+        let mut map = Doc::new();
+        map.insert("entry".to_string(), 1, stranger);
+        let delta = Message::Delta {
+            key: "doc".to_string(),
+            data: serde_json::to_vec(&map).unwrap(),
+            since: VersionVector::new(),
+            replica_id: stranger,
+            timestamp: HlcTimestamp::new(chrono::Utc::now().timestamp_millis() as u64, 0),
+        };
+
+        // Before any hello, then after a hello the engine rejected
+        transport.send(&delta.encode().unwrap()).await.unwrap();
+        assert!(engine.process_delta_messages::<Doc>().await.unwrap().is_empty());
+        let hello = Message::Hello { replica_id: stranger, versions: vec![99], features: Vec::new() };
+        transport.send(&hello.encode().unwrap()).await.unwrap();
+        transport.send(&delta.encode().unwrap()).await.unwrap();
+        assert!(engine.process_delta_messages::<Doc>().await.unwrap().is_empty());
+        assert!(engine.storage.get::<Doc>("doc").await.unwrap().is_none());
+
+        // Once it speaks our protocol the same delta goes through
+        transport.send(&Message::hello(stranger).encode().unwrap()).await.unwrap();
+        transport.send(&delta.encode().unwrap()).await.unwrap();
+        assert_eq!(engine.process_delta_messages::<Doc>().await.unwrap(), vec!["doc".to_string()]);
     }
 }
@@ -14,11 +14,10 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
 
-pub use end_to_end::{
-    CollectionMetadata, EndToEndSyncError, EndToEndSyncManager, SyncMessage as EndToEndSyncMessage,
-};
+pub use end_to_end::{CollectionMetadata, EndToEndSyncError, EndToEndSyncManager};
 pub use engine::{
-    DefaultConflictResolver, PeerInfo, PeerSyncStatus, SyncEngine, SyncEngineError, SyncState,
+    DefaultConflictResolver, PeerInfo, PeerSyncStatus, SyncEngine, SyncEngineError, SyncEvent,
+    SyncState,
 };
 
 #[derive(Error, Debug)]
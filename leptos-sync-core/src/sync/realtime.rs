@@ -1,9 +1,11 @@
 //! Real-time synchronization engine for live collaboration
 
-use crate::crdt::{Mergeable, ReplicaId};
+use crate::crdt::{HlcTimestamp, MergeReport, Mergeable, ReplicaId, ReportingMerge};
+use crate::protocol::{Message, Sessions};
 use crate::storage::{Storage, LocalStorage};
 use crate::transport::SyncTransport;
 use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -72,6 +74,8 @@ pub enum ChangeType {
     Updated,
     Deleted,
     Merged,
+    /// Merged with the keys that changed, serialized as JSON
+    Patched(MergeReport<serde_json::Value>),
 }
 
 /// User information
@@ -93,6 +97,7 @@ where
     event_sender: broadcast::Sender<RealtimeEvent>,
     subscriptions: Arc<RwLock<HashMap<String, Subscription>>>,
     active_users: Arc<RwLock<HashMap<ReplicaId, UserInfo>>>,
+    sessions: Arc<RwLock<Sessions>>,
     sync_state: Arc<RwLock<SyncState>>,
     heartbeat_interval: std::time::Duration,
     presence_timeout: std::time::Duration,
@@ -133,6 +138,7 @@ where
             event_sender,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
             active_users: Arc::new(RwLock::new(HashMap::new())),
+            sessions: Arc::new(RwLock::new(Sessions::new())),
             sync_state: Arc::new(RwLock::new(SyncState {
                 is_syncing: false,
                 last_sync: None,
@@ -151,7 +157,8 @@ where
         state.is_syncing = true;
         drop(state);
 
-        // Announce presence
+        // Offer our protocol versions, then announce presence
+        self.send_message(&Message::hello(self.replica_id)).await?;
         self.announce_presence().await?;
 
         // Start heartbeat
@@ -220,6 +227,8 @@ where
     }
 
     /// Broadcast a change to all connected peers
+    ///
+    /// Peers merge it into their copy of `key` when they process incoming changes.
     pub async fn broadcast_change<T: Mergeable + Serialize + Clone>(
         &self,
         key: &str,
@@ -231,20 +240,13 @@ where
             .map_err(|e| RealtimeSyncError::Storage(e.to_string()))?;
 
         // Serialize and send via transport
-        let change_message = ChangeMessage {
+        self.send_message(&Message::Sync {
             key: key.to_string(),
             data: serde_json::to_vec(value)
                 .map_err(|e| RealtimeSyncError::Serialization(e.to_string()))?,
             replica_id: self.replica_id,
-            timestamp: Utc::now(),
-            change_type: change_type.clone(),
-        };
-
-        let message_bytes = serde_json::to_vec(&change_message)
-            .map_err(|e| RealtimeSyncError::Serialization(e.to_string()))?;
-
-        self.transport.send(&message_bytes).await
-            .map_err(|e| RealtimeSyncError::Transport(e.to_string()))?;
+            timestamp: HlcTimestamp::now(),
+        }).await?;
 
         // Emit local event
         self.emit_event(RealtimeEvent::DocumentChanged {
@@ -257,18 +259,96 @@ where
         Ok(())
     }
 
-    /// Process incoming changes from peers
-    pub async fn process_incoming_changes(&mut self) -> Result<usize, RealtimeSyncError> {
+    /// Merge a peer's state for `key` into `local` and store the result
+    ///
+    /// Emits a `DocumentChanged` event carrying [`ChangeType::Patched`] when
+    /// the merge changed anything, so subscribers can update only those keys.
+    pub async fn merge_remote<T>(
+        &self,
+        key: &str,
+        local: &mut T,
+        remote: &T,
+        remote_replica: ReplicaId,
+    ) -> Result<MergeReport<T::Key>, RealtimeSyncError>
+    where
+        T: ReportingMerge + Serialize,
+        T::Key: Serialize,
+    {
+        let report = local.merge_with_changes(remote)
+            .map_err(|e| RealtimeSyncError::InvalidOperation(e.to_string()))?;
+        if report.is_empty() {
+            return Ok(report);
+        }
+
+        self.storage.set(key, local).await
+            .map_err(|e| RealtimeSyncError::Storage(e.to_string()))?;
+
+        self.emit_event(RealtimeEvent::DocumentChanged {
+            key: key.to_string(),
+            replica_id: remote_replica,
+            timestamp: Utc::now(),
+            change_type: ChangeType::Patched(serialize_keys(&report)?),
+        }).await;
+
+        Ok(report)
+    }
+
+    /// Process incoming changes from peers, merging remote `T` states into storage
+    ///
+    /// Changes go through [`merge_remote`](Self::merge_remote), so subscribers
+    /// see the keys they changed as [`ChangeType::Patched`]. A key not stored
+    /// yet takes the remote state and is reported as [`ChangeType::Created`].
+    /// Returns the number of changes that altered local state.
+    pub async fn process_incoming_changes<T>(&mut self) -> Result<usize, RealtimeSyncError>
+    where
+        T: ReportingMerge + Serialize + DeserializeOwned,
+        T::Key: Serialize,
+    {
         let messages = self.transport.receive().await
             .map_err(|e| RealtimeSyncError::Transport(e.to_string()))?;
 
         let mut changes_processed = 0;
 
         for message_bytes in messages {
-            if let Ok(change_message) = serde_json::from_slice::<ChangeMessage>(&message_bytes) {
-                // Process the change
-                self.process_change(change_message).await?;
-                changes_processed += 1;
+            let message = match Message::decode(&message_bytes) {
+                Ok(message) => message,
+                Err(e) => {
+                    tracing::warn!("Skipping undecodable message: {}", e);
+                    continue;
+                }
+            };
+            if message.replica_id() == Some(self.replica_id) {
+                // Our own broadcast echoed back by a shared transport
+                continue;
+            }
+
+            match message {
+                Message::Sync { key, data, replica_id, timestamp }
+                | Message::SyncResponse { key, data, replica_id, timestamp }
+                | Message::Delta { key, data, replica_id, timestamp, .. }
+                | Message::Conflict { key, data, replica_id, timestamp } => {
+                    if self.process_change::<T>(&key, &data, replica_id, timestamp).await? {
+                        changes_processed += 1;
+                    }
+                }
+                Message::Hello { replica_id, versions, features } => {
+                    let accepted = self.sessions.write().await.accept(replica_id, &versions, &features);
+                    match accepted {
+                        Ok(true) => self.send_message(&Message::hello(self.replica_id)).await?,
+                        Ok(false) => {}
+                        Err(e) => tracing::warn!("Replica {} is incompatible: {}", replica_id, e),
+                    }
+                }
+                Message::Presence { replica_id, timestamp } => {
+                    self.process_presence(replica_id, timestamp).await;
+                }
+                Message::Leave { replica_id, timestamp } => {
+                    self.process_departure(replica_id, timestamp).await;
+                }
+                Message::StateVector { .. }
+                | Message::Ack { .. }
+                | Message::Heartbeat { .. }
+                | Message::Unknown { .. } => {}
             }
         }
 
@@ -292,29 +372,23 @@ where
 
     /// Announce presence to peers
     async fn announce_presence(&self) -> Result<(), RealtimeSyncError> {
-        let presence_message = PresenceMessage {
+        self.send_message(&Message::Presence {
             replica_id: self.replica_id,
-            timestamp: Utc::now(),
-            user_info: None, // Could be populated with actual user info
-        };
-
-        let message_bytes = serde_json::to_vec(&presence_message)
-            .map_err(|e| RealtimeSyncError::Serialization(e.to_string()))?;
-
-        self.transport.send(&message_bytes).await
-            .map_err(|e| RealtimeSyncError::Transport(e.to_string()))?;
-
-        Ok(())
+            timestamp: HlcTimestamp::now(),
+        }).await
     }
 
     /// Announce departure to peers
     async fn announce_departure(&self) -> Result<(), RealtimeSyncError> {
-        let departure_message = DepartureMessage {
+        self.send_message(&Message::Leave {
             replica_id: self.replica_id,
-            timestamp: Utc::now(),
-        };
+            timestamp: HlcTimestamp::now(),
+        }).await
+    }
 
-        let message_bytes = serde_json::to_vec(&departure_message)
+    /// Encode a message and send it via the transport
+    async fn send_message(&self, message: &Message) -> Result<(), RealtimeSyncError> {
+        let message_bytes = message.encode()
             .map_err(|e| RealtimeSyncError::Serialization(e.to_string()))?;
 
         self.transport.send(&message_bytes).await
@@ -336,12 +410,12 @@ where
                 interval_timer.tick().await;
                 
                 // Send heartbeat
-                let heartbeat_message = HeartbeatMessage {
+                let heartbeat_message = Message::Heartbeat {
                     replica_id,
-                    timestamp: Utc::now(),
+                    timestamp: HlcTimestamp::now(),
                 };
 
-                if let Ok(message_bytes) = serde_json::to_vec(&heartbeat_message) {
+                if let Ok(message_bytes) = heartbeat_message.encode() {
                     let _ = transport.send(&message_bytes).await;
                 }
             }
@@ -371,17 +445,74 @@ where
         });
     }
 
-    /// Process an incoming change
-    async fn process_change(&mut self, change_message: ChangeMessage) -> Result<(), RealtimeSyncError> {
-        // Emit event for the change
+    /// Merge a remote state into the stored one, returning whether anything changed
+    async fn process_change<T>(&mut self, key: &str, data: &[u8], replica_id: ReplicaId, timestamp: HlcTimestamp) -> Result<bool, RealtimeSyncError>
+    where
+        T: ReportingMerge + Serialize + DeserializeOwned,
+        T::Key: Serialize,
+    {
+        let remote: T = match serde_json::from_slice(data) {
+            Ok(remote) => remote,
+            Err(e) => {
+                tracing::warn!("Skipping undecodable change for key {}: {}", key, e);
+                return Ok(false);
+            }
+        };
+
+        let local = self.storage.get::<T>(key).await
+            .map_err(|e| RealtimeSyncError::Storage(e.to_string()))?;
+        if let Some(mut local) = local {
+            let report = self.merge_remote(key, &mut local, &remote, replica_id).await?;
+            return Ok(!report.is_empty());
+        }
+
+        self.storage.set(key, &remote).await
+            .map_err(|e| RealtimeSyncError::Storage(e.to_string()))?;
         self.emit_event(RealtimeEvent::DocumentChanged {
-            key: change_message.key.clone(),
-            replica_id: change_message.replica_id,
-            timestamp: change_message.timestamp,
-            change_type: change_message.change_type,
+            key: key.to_string(),
+            replica_id,
+            timestamp: timestamp.to_datetime(),
+            change_type: ChangeType::Created,
         }).await;
 
-        Ok(())
+        Ok(true)
+    }
+
+    /// Track a peer that announced its presence
+    async fn process_presence(&self, replica_id: ReplicaId, timestamp: HlcTimestamp) {
+        let joined = {
+            let mut users = self.active_users.write().await;
+            let joined = !users.contains_key(&replica_id);
+            users.entry(replica_id).or_insert(UserInfo { name: None, avatar: None, color: None });
+            self.sync_state.write().await.connected_users = users.len();
+            joined
+        };
+
+        if joined {
+            self.emit_event(RealtimeEvent::UserJoined {
+                replica_id,
+                timestamp: timestamp.to_datetime(),
+                user_info: None,
+            }).await;
+        }
+    }
+
+    /// Forget a peer that left; its next handshake starts a new session
+    async fn process_departure(&self, replica_id: ReplicaId, timestamp: HlcTimestamp) {
+        self.sessions.write().await.remove(&replica_id);
+        let left = {
+            let mut users = self.active_users.write().await;
+            let left = users.remove(&replica_id).is_some();
+            self.sync_state.write().await.connected_users = users.len();
+            left
+        };
+
+        if left {
+            self.emit_event(RealtimeEvent::UserLeft {
+                replica_id,
+                timestamp: timestamp.to_datetime(),
+            }).await;
+        }
     }
 
     /// Emit an event to all subscribers
@@ -407,44 +538,30 @@ where
     }
 }
 
-/// Change message for broadcasting updates
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct ChangeMessage {
-    key: String,
-    data: Vec<u8>,
-    replica_id: ReplicaId,
-    timestamp: DateTime<Utc>,
-    change_type: ChangeType,
-}
-
-/// Presence message for announcing user presence
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct PresenceMessage {
-    replica_id: ReplicaId,
-    timestamp: DateTime<Utc>,
-    user_info: Option<UserInfo>,
-}
-
-/// Departure message for announcing user departure
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct DepartureMessage {
-    replica_id: ReplicaId,
-    timestamp: DateTime<Utc>,
-}
-
-/// Heartbeat message for keeping connections alive
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct HeartbeatMessage {
-    replica_id: ReplicaId,
-    timestamp: DateTime<Utc>,
+/// Serialize the keys of a merge report for a `DocumentChanged` event
+fn serialize_keys<K: Serialize>(report: &MergeReport<K>) -> Result<MergeReport<serde_json::Value>, RealtimeSyncError> {
+    let serialize = |keys: &[K]| {
+        keys.iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| RealtimeSyncError::Serialization(e.to_string()))
+    };
+    Ok(MergeReport {
+        inserted: serialize(&report.inserted)?,
+        updated: serialize(&report.updated)?,
+        removed: serialize(&report.removed)?,
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::crdt::LwwMap;
     use crate::storage::memory::MemoryStorage;
     use crate::transport::memory::InMemoryTransport;
 
+    type Doc = LwwMap<String, String>;
+
     #[tokio::test]
     async fn test_realtime_sync_manager_creation() {
         let storage = Arc::new(Storage::memory());
@@ -475,6 +592,101 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_merge_remote_reports_changed_keys() {
+        let storage = Arc::new(Storage::memory());
+        let transport = InMemoryTransport::new();
+        let replica_id = ReplicaId::default();
+        let remote_replica = ReplicaId::default();
+
+        let manager = RealtimeSyncManager::new(replica_id, transport, storage);
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        manager.subscribe(
+            vec!["document_changed".to_string()],
+            Box::new(move |event| sink.lock().unwrap().push(event)),
+        ).await.unwrap();
+
+        let mut local = LwwMap::new();
+        local.insert("title".to_string(), "Draft".to_string(), replica_id);
+        let mut remote = local.clone();
+        remote.insert("title".to_string(), "Final".to_string(), remote_replica);
+        remote.insert("author".to_string(), "Ada".to_string(), remote_replica);
+
+        let report = manager.merge_remote("doc", &mut local, &remote, remote_replica).await.unwrap();
+        assert_eq!(report.inserted, vec!["author".to_string()]);
+        assert_eq!(report.updated, vec!["title".to_string()]);
+        assert_eq!(local.get(&"title".to_string()), Some(&"Final".to_string()));
+
+        // Nothing new the second time, so no event
+        let report = manager.merge_remote("doc", &mut local, &remote, remote_replica).await.unwrap();
+        assert!(report.is_empty());
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            RealtimeEvent::DocumentChanged { key, change_type: ChangeType::Patched(keys), .. } => {
+                assert_eq!(key, "doc");
+                assert_eq!(keys.inserted, vec![serde_json::json!("author")]);
+                assert_eq!(keys.updated, vec![serde_json::json!("title")]);
+                assert!(keys.removed.is_empty());
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peers_exchange_changes_and_presence() {
+        let transport = InMemoryTransport::new();
+        let (replica1, replica2) = (ReplicaId::default(), ReplicaId::default());
+        let mut manager1 = RealtimeSyncManager::new(replica1, transport.clone(), Arc::new(Storage::memory()));
+        let mut manager2 = RealtimeSyncManager::new(replica2, transport.clone(), Arc::new(Storage::memory()));
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = events.clone();
+        manager2.subscribe(
+            vec!["*".to_string()],
+            Box::new(move |event| sink.lock().unwrap().push(event)),
+        ).await.unwrap();
+
+        manager1.start().await.unwrap();
+        let mut map = LwwMap::new();
+        map.insert("title".to_string(), "Draft".to_string(), replica1);
+        manager1.broadcast_change("doc", &map, ChangeType::Created).await.unwrap();
+
+        assert_eq!(manager2.process_incoming_changes::<Doc>().await.unwrap(), 1);
+        assert!(manager2.get_active_users().await.contains_key(&replica1));
+        // manager2 answered the handshake
+        assert_eq!(manager1.process_incoming_changes::<Doc>().await.unwrap(), 0);
+
+        // Later changes are merged into the stored copy
+        map.insert("title".to_string(), "Final".to_string(), replica1);
+        map.insert("author".to_string(), "Ada".to_string(), replica1);
+        manager1.broadcast_change("doc", &map, ChangeType::Updated).await.unwrap();
+        assert_eq!(manager2.process_incoming_changes::<Doc>().await.unwrap(), 1);
+        let stored: Doc = manager2.storage.get("doc").await.unwrap().unwrap();
+        assert_eq!(stored.get(&"author".to_string()), Some(&"Ada".to_string()));
+
+        manager1.stop().await.unwrap();
+        manager2.process_incoming_changes::<Doc>().await.unwrap();
+        assert!(manager2.get_active_users().await.is_empty());
+
+        let events = events.lock().unwrap();
+        assert!(matches!(&events[0], RealtimeEvent::UserJoined { replica_id, .. } if *replica_id == replica1));
+        assert!(matches!(
+            &events[1],
+            RealtimeEvent::DocumentChanged { key, change_type: ChangeType::Created, .. } if key == "doc"
+        ));
+        match &events[2] {
+            RealtimeEvent::DocumentChanged { replica_id, change_type: ChangeType::Patched(keys), .. } => {
+                assert_eq!(*replica_id, replica1);
+                assert_eq!(keys.inserted, vec![serde_json::json!("author")]);
+                assert_eq!(keys.updated, vec![serde_json::json!("title")]);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(events.last(), Some(RealtimeEvent::UserLeft { .. })));
+    }
+
     #[tokio::test]
     async fn test_sync_state_management() {
         let storage = Arc::new(Storage::memory());
@@ -1,9 +1,12 @@
 //! leptos-ws-pro transport implementation for leptos-sync
 //!
 //! This module provides a WebSocket transport implementation using leptos-ws-pro
-//! that integrates with the existing SyncTransport trait.
+//! that integrates with the existing SyncTransport trait. It carries frames of
+//! [`crate::protocol`] untouched and opens every connection with a `Hello`.
 
 use super::{SyncTransport, TransportError};
+use crate::crdt::ReplicaId;
+use crate::protocol::Message;
 #[cfg(feature = "websocket")]
 use leptos_ws_pro::*;
 use serde::{Deserialize, Serialize};
@@ -69,6 +72,7 @@ impl Default for LeptosWsProConfig {
 /// leptos-ws-pro based WebSocket transport
 pub struct LeptosWsProTransport {
     config: LeptosWsProConfig,
+    replica_id: ReplicaId,
     connection_state: Arc<RwLock<ConnectionState>>,
     message_queue: Arc<RwLock<VecDeque<Vec<u8>>>>,
     ws_context: Option<()>, // Placeholder for WebSocketContext
@@ -89,6 +93,7 @@ impl LeptosWsProTransport {
     pub fn new(config: LeptosWsProConfig) -> Self {
         Self {
             config,
+            replica_id: ReplicaId::default(),
             connection_state: Arc::new(RwLock::new(ConnectionState::Disconnected)),
             message_queue: Arc::new(RwLock::new(VecDeque::new())),
             ws_context: None,
@@ -105,6 +110,17 @@ impl LeptosWsProTransport {
         Self::new(config)
     }
 
+    /// Announce the given replica in the handshake
+    pub fn with_replica_id(mut self, replica_id: ReplicaId) -> Self {
+        self.replica_id = replica_id;
+        self
+    }
+
+    /// Get the replica announced in the handshake
+    pub fn replica_id(&self) -> ReplicaId {
+        self.replica_id
+    }
+
     /// Get the current URL
     pub fn url(&self) -> &str {
         &self.config.url
@@ -136,7 +152,13 @@ impl LeptosWsProTransport {
                 Ok(()) => {
                     let mut state = self.connection_state.write().await;
                     *state = ConnectionState::Connected;
-                    return Ok(());
+                    drop(state);
+
+                    // Announce ourselves before anything else
+                    let hello = Message::hello(self.replica_id)
+                        .encode()
+                        .map_err(|e| LeptosWsProError::SerializationFailed(e.to_string()))?;
+                    return self.send_message(&hello).await;
                 }
                 Err(e) => {
                     if attempt < self.config.max_reconnect_attempts - 1 {
@@ -276,6 +298,7 @@ impl Clone for LeptosWsProTransport {
     fn clone(&self) -> Self {
         Self {
             config: self.config.clone(),
+            replica_id: self.replica_id,
             connection_state: self.connection_state.clone(),
             message_queue: self.message_queue.clone(),
             ws_context: None, // Context cannot be cloned
@@ -386,6 +409,22 @@ mod tests {
         assert_eq!(state, ConnectionState::Disconnected);
     }
 
+    #[tokio::test]
+    async fn test_connection_opens_with_hello() {
+        let replica_id = ReplicaId::default();
+        let transport = LeptosWsProTransport::new(LeptosWsProConfig::default()).with_replica_id(replica_id);
+        transport.connect().await.unwrap();
+
+        let frames = transport.receive().await.unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(Message::decode(&frames[0]).unwrap(), Message::hello(replica_id));
+
+        // Frames reach the transport untouched, including kinds this build does not know
+        let unknown = Message::Unknown { kind: 4242, payload: vec![1, 2, 3] }.encode().unwrap();
+        transport.send(&unknown).await.unwrap();
+        assert_eq!(transport.receive().await.unwrap(), vec![unknown]);
+    }
+
     #[tokio::test]
     async fn test_message_protocol_adapter() {
         let config = LeptosWsProConfig::default();
@@ -11,7 +11,6 @@ pub mod multi_transport;
 pub mod leptos_ws_pro_transport;
 pub mod compatibility_layer;
 pub mod hybrid_transport_impl;
-pub mod message_protocol;
 pub mod websocket_client;
 pub mod websocket_integration;
 
@@ -147,7 +146,6 @@ pub use hybrid_transport_impl::HybridTransport;
 
 // Re-export WebSocket types
 pub use websocket_client::{WebSocketClient, WebSocketClientConfig, WebSocketClientError};
-pub use message_protocol::{SyncMessage, MessageCodec, CrdtType, UserInfo, PresenceAction, ServerInfo};
 pub use websocket_integration::{WebSocketSyncEngine, WebSocketIntegrationConfig, WebSocketSyncEngineBuilder};
 
 /// Transport configuration
@@ -1,11 +1,14 @@
 //! WebSocket client transport implementation
+//!
+//! The client carries frames of [`crate::protocol`] and opens every
+//! connection with a `Hello`.
 
 use super::{SyncTransport, TransportError};
-use super::message_protocol::{SyncMessage, MessageCodec};
-use crate::crdt::ReplicaId;
+use crate::crdt::{HlcTimestamp, ReplicaId};
+use crate::protocol::Message;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
 use tokio::sync::{RwLock, mpsc};
 use tokio::time::{timeout, interval};
 use thiserror::Error;
@@ -136,7 +139,11 @@ impl WebSocketClient {
                 Ok(()) => {
                     let mut state = self.connection_state.write().await;
                     *state = ConnectionState::Connected;
-                    
+                    drop(state);
+
+                    // Announce ourselves before anything else
+                    self.send_message(&Message::hello(self.replica_id)).await?;
+
                     // Start heartbeat task
                     self.start_heartbeat().await;
                     
@@ -181,16 +188,17 @@ impl WebSocketClient {
         Ok(())
     }
 
-    /// Send a synchronization message
-    pub async fn send_message(&self, message: SyncMessage) -> Result<(), WebSocketClientError> {
+    /// Send a protocol message
+    pub async fn send_message(&self, message: &Message) -> Result<(), WebSocketClientError> {
         if !self.is_connected().await {
             return Err(WebSocketClientError::NotConnected);
         }
 
-        let serialized = MessageCodec::serialize(&message)
+        let frame = message
+            .encode()
             .map_err(|e| WebSocketClientError::SerializationFailed(e.to_string()))?;
 
-        self.send_raw(&serialized).await
+        self.send_raw(&frame).await
     }
 
     /// Send raw bytes
@@ -200,17 +208,20 @@ impl WebSocketClient {
         }
 
         // TODO: Implement actual WebSocket sending
+        // Until then frames loop back, as they would from an echo server
         tracing::debug!("Would send {} bytes via WebSocket", data.len());
-        Ok(())
+        self.message_sender
+            .send(data.to_vec())
+            .map_err(|e| WebSocketClientError::SendFailed(e.to_string()))
     }
 
-    /// Receive a synchronization message
-    pub async fn receive_message(&self) -> Result<Option<SyncMessage>, WebSocketClientError> {
+    /// Receive a protocol message
+    pub async fn receive_message(&self) -> Result<Option<Message>, WebSocketClientError> {
         let mut receiver = self.message_receiver.write().await;
         
         match timeout(self.config.message_timeout, receiver.recv()).await {
-            Ok(Some(data)) => {
-                let message = MessageCodec::deserialize(&data)
+            Ok(Some(frame)) => {
+                let message = Message::decode(&frame)
                     .map_err(|e| WebSocketClientError::SerializationFailed(e.to_string()))?;
                 Ok(Some(message))
             }
@@ -256,12 +267,12 @@ impl WebSocketClient {
                 }
                 
                 // Send heartbeat message
-                let heartbeat = SyncMessage::Heartbeat {
+                let heartbeat = Message::Heartbeat {
                     replica_id,
-                    timestamp: SystemTime::now(),
+                    timestamp: HlcTimestamp::now(),
                 };
                 
-                match MessageCodec::serialize(&heartbeat) {
+                match heartbeat.encode() {
                     Ok(data) => {
                         if sender.send(data).is_err() {
                             tracing::warn!("Failed to send heartbeat - connection may be lost");
@@ -299,15 +310,13 @@ impl SyncTransport for WebSocketClient {
 
     fn receive(&self) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<Vec<Vec<u8>>, Self::Error>> + Send + '_>> {
         Box::pin(async move {
-            match self.receive_message().await {
-                Ok(Some(message)) => {
-                    let data = MessageCodec::serialize(&message)
-                        .map_err(|e| TransportError::SerializationFailed(e.to_string()))?;
-                    Ok(vec![data])
-                }
-                Ok(None) => Ok(Vec::new()),
-                Err(e) => Err(e.into()),
+            // Frames are handed over as they arrived; decoding is up to the caller
+            let mut receiver = self.message_receiver.write().await;
+            let mut frames = Vec::new();
+            while let Ok(frame) = receiver.try_recv() {
+                frames.push(frame);
             }
+            Ok(frames)
         })
     }
 
@@ -381,15 +390,30 @@ mod tests {
         client.connect().await.unwrap();
         
         // Send a heartbeat message
-        let message = SyncMessage::Heartbeat {
-            replica_id: replica_id.clone(),
-            timestamp: SystemTime::now(),
+        let message = Message::Heartbeat {
+            replica_id,
+            timestamp: HlcTimestamp::now(),
         };
         
-        let result = client.send_message(message).await;
+        let result = client.send_message(&message).await;
         assert!(result.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_connection_opens_with_hello() {
+        let replica_id = create_test_replica_id();
+        let client = WebSocketClient::new(WebSocketClientConfig::default(), replica_id);
+        client.connect().await.unwrap();
+
+        let frames = client.receive().await.unwrap();
+        assert_eq!(Message::decode(&frames[0]).unwrap(), Message::hello(replica_id));
+
+        // Frames reach the transport untouched, including kinds this build does not know
+        let unknown = Message::Unknown { kind: 4242, payload: vec![1, 2, 3] }.encode().unwrap();
+        client.send(&unknown).await.unwrap();
+        assert_eq!(client.receive().await.unwrap(), vec![unknown]);
+    }
+
     #[tokio::test]
     async fn test_sync_transport_implementation() {
         let replica_id = create_test_replica_id();
@@ -1,12 +1,12 @@
 //! WebSocket integration with sync engine
 
-use super::{CrdtType, SyncMessage, WebSocketClient, WebSocketClientConfig};
-use crate::crdt::{Mergeable, ReplicaId};
+use super::{WebSocketClient, WebSocketClientConfig};
+use crate::crdt::{Mergeable, ReplicaId, VersionVector};
+use crate::protocol::Message;
 use crate::storage::Storage;
 use crate::sync::{SyncEngine, SyncEngineError};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use std::time::SystemTime;
 use thiserror::Error;
 use tokio::sync::RwLock;
 use tokio::time::{interval, Duration};
@@ -115,22 +115,22 @@ impl WebSocketSyncEngine {
         Ok(())
     }
 
-    /// Send a CRDT delta to peers
+    /// Send the changes to a key made since `since` to peers
     pub async fn send_delta(
         &self,
-        collection_id: String,
-        crdt_type: CrdtType,
+        key: String,
         delta: Vec<u8>,
+        since: VersionVector,
     ) -> Result<(), WebSocketIntegrationError> {
-        let message = SyncMessage::Delta {
-            collection_id,
-            crdt_type,
-            delta,
-            timestamp: SystemTime::now(),
+        let message = Message::Delta {
+            key,
+            data: delta,
+            since,
             replica_id: self.websocket_client.replica_id(),
+            timestamp: self.sync_engine.now().await,
         };
 
-        self.websocket_client.send_message(message).await?;
+        self.websocket_client.send_message(&message).await?;
         Ok(())
     }
 
@@ -185,7 +185,7 @@ impl WebSocketSyncEngine {
                 // TODO: Implement delta collection from sync engine
                 // For now, just simulate
                 if delta_buffer.len() >= config.delta_batch_size {
-                    Self::send_delta_batch(&websocket_client, &mut delta_buffer).await;
+                    Self::send_delta_batch(&sync_engine, &websocket_client, &mut delta_buffer).await;
                 }
             }
         });
@@ -203,42 +203,29 @@ impl WebSocketSyncEngine {
 
     async fn handle_incoming_message(
         sync_engine: &Arc<SyncEngine<WebSocketClient>>,
-        message: SyncMessage,
+        message: Message,
     ) -> Result<(), WebSocketIntegrationError> {
         match message {
-            SyncMessage::Delta {
-                collection_id,
-                crdt_type,
-                delta,
-                replica_id,
-                ..
-            } => {
+            Message::Delta { key, replica_id, .. } => {
                 // Apply delta to local CRDT
                 tracing::debug!(
-                    "Received delta for collection {} from replica {:?}",
-                    collection_id,
+                    "Received delta for key {} from replica {:?}",
+                    key,
                     replica_id
                 );
 
                 // TODO: Apply delta to the appropriate CRDT in the sync engine
                 // This would involve deserializing the delta and merging it
             }
-            SyncMessage::Heartbeat { replica_id, .. } => {
+            Message::Heartbeat { replica_id, .. } => {
                 tracing::debug!("Received heartbeat from replica {:?}", replica_id);
                 // Update peer info in sync engine
             }
-            SyncMessage::PeerJoin {
-                replica_id,
-                user_info,
-            } => {
-                tracing::info!(
-                    "Peer joined: {:?} with user info: {:?}",
-                    replica_id,
-                    user_info
-                );
+            Message::Presence { replica_id, .. } => {
+                tracing::info!("Peer joined: {:?}", replica_id);
                 // Add peer to sync engine
             }
-            SyncMessage::PeerLeave { replica_id } => {
+            Message::Leave { replica_id, .. } => {
                 tracing::info!("Peer left: {:?}", replica_id);
                 // Remove peer from sync engine
             }
@@ -251,19 +238,20 @@ impl WebSocketSyncEngine {
     }
 
     async fn send_delta_batch(
+        sync_engine: &Arc<SyncEngine<WebSocketClient>>,
         websocket_client: &Arc<WebSocketClient>,
-        delta_buffer: &mut Vec<(String, CrdtType, Vec<u8>)>,
+        delta_buffer: &mut Vec<(String, Vec<u8>, VersionVector)>,
     ) {
-        for (collection_id, crdt_type, delta) in delta_buffer.drain(..) {
-            let message = SyncMessage::Delta {
-                collection_id,
-                crdt_type,
-                delta,
-                timestamp: SystemTime::now(),
+        for (key, delta, since) in delta_buffer.drain(..) {
+            let message = Message::Delta {
+                key,
+                data: delta,
+                since,
                 replica_id: websocket_client.replica_id(),
+                timestamp: sync_engine.now().await,
             };
 
-            if let Err(e) = websocket_client.send_message(message).await {
+            if let Err(e) = websocket_client.send_message(&message).await {
                 tracing::error!("Failed to send delta: {}", e);
             }
         }
@@ -361,11 +349,7 @@ mod tests {
 
         let delta_data = b"test delta".to_vec();
         let result = engine
-            .send_delta(
-                "test_collection".to_string(),
-                CrdtType::LwwRegister,
-                delta_data,
-            )
+            .send_delta("test_collection".to_string(), delta_data, VersionVector::new())
             .await;
 
         // Should succeed even without connection in test environment
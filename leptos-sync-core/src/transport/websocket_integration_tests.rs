@@ -2,9 +2,11 @@
 
 use super::{
     WebSocketClient, WebSocketClientConfig, WebSocketSyncEngine, WebSocketIntegrationConfig,
-    WebSocketSyncEngineBuilder, SyncMessage, MessageCodec, CrdtType,
+    WebSocketSyncEngineBuilder,
 };
-use crate::crdt::ReplicaId;
+use crate::crdt::{HlcTimestamp, ReplicaId, VersionVector};
+use crate::protocol::Message;
+use crate::protocol::v1::{CrdtType, MessageCodec, SyncMessage};
 use crate::storage::memory::MemoryStorage;
 use std::time::SystemTime;
 use tokio::time::{sleep, Duration};
@@ -31,11 +33,11 @@ async fn test_websocket_client_basic_operations() {
     assert_eq!(client.connection_state().await, crate::transport::websocket_client::ConnectionState::Connected);
 
     // Test sending a message
-    let message = SyncMessage::Heartbeat {
-        replica_id: replica_id.clone(),
-        timestamp: SystemTime::now(),
+    let message = Message::Heartbeat {
+        replica_id,
+        timestamp: HlcTimestamp::now(),
     };
-    let result = client.send_message(message).await;
+    let result = client.send_message(&message).await;
     assert!(result.is_ok());
 
     // Test disconnection
@@ -44,7 +46,7 @@ async fn test_websocket_client_basic_operations() {
     assert_eq!(client.connection_state().await, crate::transport::websocket_client::ConnectionState::Disconnected);
 }
 
-/// Test message protocol serialization
+/// Test version 1 message serialization
 #[tokio::test]
 async fn test_message_protocol_serialization() {
     let replica_id = create_test_replica_id();
@@ -151,8 +153,8 @@ async fn test_send_delta() {
     let delta_data = b"test delta data".to_vec();
     let result = engine.send_delta(
         "test_collection".to_string(),
-        CrdtType::LwwRegister,
         delta_data,
+        VersionVector::new(),
     ).await;
     
     assert!(result.is_ok());
@@ -184,17 +186,17 @@ async fn test_multiple_clients() {
     assert!(client2.connect().await.is_ok());
     
     // Send messages between clients (simulated)
-    let message1 = SyncMessage::PeerJoin {
+    let message1 = Message::Presence {
         replica_id: replica_id1,
-        user_info: None,
+        timestamp: HlcTimestamp::now(),
     };
-    let message2 = SyncMessage::PeerJoin {
+    let message2 = Message::Presence {
         replica_id: replica_id2,
-        user_info: None,
+        timestamp: HlcTimestamp::now(),
     };
     
-    assert!(client1.send_message(message1).await.is_ok());
-    assert!(client2.send_message(message2).await.is_ok());
+    assert!(client1.send_message(&message1).await.is_ok());
+    assert!(client2.send_message(&message2).await.is_ok());
     
     // Disconnect both clients
     assert!(client1.disconnect().await.is_ok());
@@ -296,12 +298,12 @@ async fn test_error_handling() {
     let client = WebSocketClient::new(WebSocketClientConfig::default(), replica_id);
     
     // Try to send message without connecting
-    let message = SyncMessage::Heartbeat {
+    let message = Message::Heartbeat {
         replica_id,
-        timestamp: SystemTime::now(),
+        timestamp: HlcTimestamp::now(),
     };
     
-    let result = client.send_message(message).await;
+    let result = client.send_message(&message).await;
     assert!(result.is_err()); // Should fail because not connected
 }
 
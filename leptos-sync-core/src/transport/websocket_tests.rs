@@ -8,10 +8,10 @@ mod tests {
     use super::*;
     use crate::transport::{
         WebSocketClient, WebSocketClientConfig, WebSocketClientError,
-        message_protocol::{SyncMessage, MessageCodec, UserInfo, ServerInfo, PresenceAction},
         SyncTransport,
     };
-    use crate::crdt::{ReplicaId, CrdtType};
+    use crate::crdt::ReplicaId;
+    use crate::protocol::v1::{SyncMessage, MessageCodec, UserInfo, ServerInfo, PresenceAction, CrdtType};
     use std::time::{SystemTime, Duration};
     use uuid::Uuid;
     use tokio::time::timeout;
@@ -1,4 +1,4 @@
-//! Schema validation for CRDT messages
+//! Schema validation for protocol version 1 messages
 //!
 //! Provides runtime validation of messages against JSON schemas to ensure
 //! contract compliance between client and server implementations.
@@ -7,7 +7,7 @@ use serde_json::Value;
 use std::sync::OnceLock;
 use thiserror::Error;
 
-use crate::transport::message_protocol::SyncMessage;
+use crate::protocol::v1::SyncMessage;
 
 /// Validation error types
 #[derive(Error, Debug)]
@@ -132,9 +132,8 @@ pub fn validate_json_conditional(json: &Value) -> Result<(), ValidationError> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::transport::CrdtType;
     use crate::crdt::ReplicaId;
-    use crate::transport::message_protocol::{PresenceAction, ServerInfo, UserInfo};
+    use crate::protocol::v1::{CrdtType, PresenceAction, ServerInfo, UserInfo};
     use std::time::SystemTime;
     use uuid::Uuid;
 
@@ -1,28 +1,39 @@
 //! Production-ready WebSocket server for real-time synchronization
+//!
+//! Clients exchange binary frames of [`leptos_sync_core::protocol`]. The server
+//! answers the handshake, relays every other frame to the remaining peers
+//! unchanged (including kinds it does not know) and announces departures.
 
+use leptos_sync_core::crdt::{HlcTimestamp, ReplicaId};
+use leptos_sync_core::protocol::{Message, Session};
 use tokio::net::{TcpListener, TcpStream};
 use tokio_tungstenite::{accept_async, WebSocketStream, MaybeTlsStream};
-use futures_util::{SinkExt, StreamExt, future::join_all};
-use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use futures_util::{SinkExt, StreamExt};
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::{RwLock, mpsc, broadcast};
-use tokio::time::{interval, timeout};
+use tokio::time::interval;
 use uuid::Uuid;
-use tracing::{info, warn, error, instrument};
+use tracing::{info, warn, error};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 // Configuration
 const MAX_CONNECTIONS: usize = 1000;
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
-const CONNECTION_TIMEOUT: Duration = Duration::from_secs(300); // 5 minutes
 const MAX_MESSAGE_SIZE: usize = 1024 * 1024; // 1MB
 
+/// Connection details, most of which are only read when debugging
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 struct Peer {
     id: String,
-    sender: mpsc::UnboundedSender<Value>,
+    /// Replica behind the connection, known once it sent a message
+    replica_id: Option<ReplicaId>,
+    /// Protocol version and features agreed in the handshake
+    session: Option<Session>,
+    sender: mpsc::UnboundedSender<Vec<u8>>,
     connected_at: Instant,
     last_heartbeat: Instant,
     user_agent: Option<String>,
@@ -38,18 +49,27 @@ struct ServerStats {
     start_time: Instant,
 }
 
+/// Frame relayed to every connection except the one it came from
+#[derive(Debug, Clone)]
+struct Relay {
+    from: Option<String>,
+    frame: Vec<u8>,
+}
+
 struct WebSocketServer {
+    replica_id: ReplicaId,
     peers: Arc<RwLock<HashMap<String, Peer>>>,
     stats: Arc<RwLock<ServerStats>>,
-    broadcast_tx: broadcast::Sender<Value>,
+    broadcast_tx: broadcast::Sender<Relay>,
     shutdown_tx: mpsc::UnboundedSender<()>,
 }
 
 impl WebSocketServer {
     fn new() -> Self {
+        let replica_id = ReplicaId::default();
         let (broadcast_tx, _) = broadcast::channel(1000);
         let (shutdown_tx, shutdown_rx) = mpsc::unbounded_channel();
-        
+
         let stats = Arc::new(RwLock::new(ServerStats {
             total_connections: 0,
             active_connections: 0,
@@ -57,67 +77,70 @@ impl WebSocketServer {
             uptime: Duration::ZERO,
             start_time: Instant::now(),
         }));
-        
+
         let peers = Arc::new(RwLock::new(HashMap::new()));
-        
+
         // Start background tasks
-        let peers_clone = peers.clone();
         let stats_clone = stats.clone();
         let broadcast_tx_clone = broadcast_tx.clone();
-        
+
         tokio::spawn(async move {
-            Self::run_background_tasks(peers_clone, stats_clone, broadcast_tx_clone, shutdown_rx).await;
+            Self::run_background_tasks(replica_id, stats_clone, broadcast_tx_clone, shutdown_rx).await;
         });
-        
+
         Self {
+            replica_id,
             peers,
             stats,
             broadcast_tx,
             shutdown_tx,
         }
     }
-    
+
     async fn run_background_tasks(
-        peers: Arc<RwLock<HashMap<String, Peer>>>,
+        replica_id: ReplicaId,
         stats: Arc<RwLock<ServerStats>>,
-        broadcast_tx: broadcast::Sender<Value>,
+        broadcast_tx: broadcast::Sender<Relay>,
         mut shutdown_rx: mpsc::UnboundedReceiver<()>,
     ) {
         let mut heartbeat_interval = interval(HEARTBEAT_INTERVAL);
         let mut stats_interval = interval(Duration::from_secs(60));
-        
+
         loop {
             tokio::select! {
                 _ = heartbeat_interval.tick() => {
-                    Self::send_heartbeats(&peers, &broadcast_tx).await;
+                    Self::send_heartbeats(replica_id, &broadcast_tx);
                 }
                 _ = stats_interval.tick() => {
                     Self::update_stats(&stats).await;
                 }
-                _ = &mut shutdown_rx => {
+                _ = shutdown_rx.recv() => {
                     info!("Shutdown signal received, stopping background tasks");
                     break;
                 }
             }
         }
     }
-    
-    async fn send_heartbeats(peers: &Arc<RwLock<HashMap<String, Peer>>>, broadcast_tx: &broadcast::Sender<Value>) {
-        let heartbeat = json!({
-            "type": "heartbeat",
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-            "server_id": Uuid::new_v4().to_string(),
-        });
-        
-        if let Err(e) = broadcast_tx.send(heartbeat) {
-            warn!("Failed to broadcast heartbeat: {}", e);
+
+    fn send_heartbeats(replica_id: ReplicaId, broadcast_tx: &broadcast::Sender<Relay>) {
+        let heartbeat = Message::Heartbeat {
+            replica_id,
+            timestamp: HlcTimestamp::now(),
+        };
+
+        match heartbeat.encode() {
+            // Sending only fails while nobody is connected
+            Ok(frame) => {
+                let _ = broadcast_tx.send(Relay { from: None, frame });
+            }
+            Err(e) => warn!("Failed to encode heartbeat: {}", e),
         }
     }
-    
+
     async fn update_stats(stats: &Arc<RwLock<ServerStats>>) {
         let mut stats_guard = stats.write().await;
         stats_guard.uptime = stats_guard.start_time.elapsed();
-        
+
         info!(
             "Server Stats - Connections: {}, Messages: {}, Uptime: {:?}",
             stats_guard.active_connections,
@@ -125,7 +148,7 @@ impl WebSocketServer {
             stats_guard.uptime
         );
     }
-    
+
     async fn handle_connection(
         stream: TcpStream,
         addr: std::net::SocketAddr,
@@ -133,9 +156,9 @@ impl WebSocketServer {
     ) {
         let peer_id = Uuid::new_v4().to_string();
         let ip_address = addr.ip().to_string();
-        
+
         info!("New connection from {} (peer: {})", addr, peer_id);
-        
+
         // Check connection limits
         {
             let peers = server.peers.read().await;
@@ -144,121 +167,96 @@ impl WebSocketServer {
                 return;
             }
         }
-        
+
         // Accept WebSocket connection
-        let ws_stream = match accept_async(stream).await {
+        let ws_stream = match accept_async(MaybeTlsStream::Plain(stream)).await {
             Ok(ws) => ws,
             Err(e) => {
                 error!("Failed to accept WebSocket connection from {}: {}", addr, e);
                 return;
             }
         };
-        
+
         // Handle the WebSocket connection
-        if let Err(e) = server.handle_websocket(ws_stream, peer_id, ip_address).await {
+        if let Err(e) = server.handle_websocket(ws_stream, peer_id.clone(), ip_address).await {
             error!("WebSocket error for peer {}: {}", peer_id, e);
         }
-        
+
         info!("Connection closed for peer {}", peer_id);
     }
-    
+
     async fn handle_websocket(
         &self,
         ws_stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
         peer_id: String,
         ip_address: String,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
-        
+        let (mut ws_sender, ws_receiver) = ws_stream.split();
+
         // Create message channel for this peer
-        let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
-        let mut broadcast_rx = self.broadcast_tx.subscribe();
-        
+        let (tx, rx) = mpsc::unbounded_channel::<Vec<u8>>();
+        let broadcast_rx = self.broadcast_tx.subscribe();
+
         // Store peer information
         let peer = Peer {
             id: peer_id.clone(),
+            replica_id: None,
+            session: None,
             sender: tx,
             connected_at: Instant::now(),
             last_heartbeat: Instant::now(),
             user_agent: None,
             ip_address,
         };
-        
+
         {
             let mut peers = self.peers.write().await;
             peers.insert(peer_id.clone(), peer);
-            
+
             let mut stats = self.stats.write().await;
             stats.total_connections += 1;
             stats.active_connections = peers.len();
         }
-        
-        // Send welcome message
-        let welcome = json!({
-            "type": "welcome",
-            "peer_id": peer_id,
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-            "server_info": {
-                "version": env!("CARGO_PKG_VERSION"),
-                "max_connections": MAX_CONNECTIONS,
-                "heartbeat_interval": HEARTBEAT_INTERVAL.as_secs(),
-            }
-        });
-        
-        if let Err(e) = ws_sender.send(tokio_tungstenite::tungstenite::Message::Text(welcome.to_string())).await {
-            error!("Failed to send welcome message to peer {}: {}", peer_id, e);
-        }
-        
-        // Broadcast presence
-        let presence = json!({
-            "type": "presence",
-            "peer_id": peer_id,
-            "action": "connected",
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-        });
-        
-        if let Err(e) = self.broadcast_tx.send(presence) {
-            warn!("Failed to broadcast presence for peer {}: {}", peer_id, e);
+
+        // Open with our handshake; the client answers with its own
+        let hello = Message::hello(self.replica_id).encode()?;
+        if let Err(e) = ws_sender.send(WsMessage::Binary(hello)).await {
+            error!("Failed to send handshake to peer {}: {}", peer_id, e);
         }
-        
-        // Handle incoming messages and outgoing messages concurrently
-        let (incoming_result, outgoing_result) = tokio::join!(
-            Self::handle_incoming_messages(ws_receiver, peer_id.clone(), self),
-            Self::handle_outgoing_messages(ws_sender, rx, broadcast_rx, peer_id.clone())
-        );
-        
+
+        // Handle incoming and outgoing messages until either side stops
+        let result = tokio::select! {
+            result = self.handle_incoming_messages(ws_receiver, &peer_id) => result,
+            result = Self::handle_outgoing_messages(ws_sender, rx, broadcast_rx, &peer_id) => result,
+        };
+
         // Clean up peer
-        {
+        let departed = {
             let mut peers = self.peers.write().await;
-            peers.remove(&peer_id);
-            
+            let departed = peers.remove(&peer_id);
+
             let mut stats = self.stats.write().await;
             stats.active_connections = peers.len();
+            departed
+        };
+
+        // Announce the departure on the replica's behalf
+        if let Some(replica_id) = departed.and_then(|peer| peer.replica_id) {
+            let leave = Message::Leave {
+                replica_id,
+                timestamp: HlcTimestamp::now(),
+            };
+            // Sending only fails while nobody is connected
+            let _ = self.broadcast_tx.send(Relay { from: Some(peer_id), frame: leave.encode()? });
         }
-        
-        // Broadcast disconnection
-        let disconnect = json!({
-            "type": "presence",
-            "peer_id": peer_id,
-            "action": "disconnected",
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-        });
-        
-        if let Err(e) = self.broadcast_tx.send(disconnect) {
-            warn!("Failed to broadcast disconnection for peer {}: {}", peer_id, e);
-        }
-        
-        // Return any errors
-        incoming_result?;
-        outgoing_result?;
-        
-        Ok(())
+
+        result
     }
-    
+
     async fn handle_incoming_messages(
+        &self,
         mut ws_receiver: futures_util::stream::SplitStream<WebSocketStream<MaybeTlsStream<TcpStream>>>,
-        peer_id: String,
-        server: &WebSocketServer,
+        peer_id: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         while let Some(msg) = ws_receiver.next().await {
             let msg = match msg {
@@ -268,71 +266,46 @@ impl WebSocketServer {
                     break;
                 }
             };
-            
+
             match msg {
-                tokio_tungstenite::tungstenite::Message::Text(text) => {
-                    if text.len() > MAX_MESSAGE_SIZE {
-                        warn!("Message too large from peer {}: {} bytes", peer_id, text.len());
-                        continue;
-                    }
-                    
-                    // Parse JSON message
-                    let message: Value = match serde_json::from_str(&text) {
-                        Ok(msg) => msg,
-                        Err(e) => {
-                            warn!("Invalid JSON from peer {}: {}", peer_id, e);
-                            continue;
-                        }
-                    };
-                    
-                    // Process message
-                    if let Err(e) = server.process_message(&peer_id, message).await {
-                        warn!("Failed to process message from peer {}: {}", peer_id, e);
-                    }
-                }
-                tokio_tungstenite::tungstenite::Message::Binary(data) => {
+                WsMessage::Binary(data) => {
                     if data.len() > MAX_MESSAGE_SIZE {
-                        warn!("Binary message too large from peer {}: {} bytes", peer_id, data.len());
+                        warn!("Message too large from peer {}: {} bytes", peer_id, data.len());
                         continue;
                     }
-                    
-                    // Handle binary messages (e.g., file uploads)
-                    if let Err(e) = server.process_binary_message(&peer_id, &data).await {
-                        warn!("Failed to process binary message from peer {}: {}", peer_id, e);
+
+                    if let Err(e) = self.process_frame(peer_id, &data).await {
+                        warn!("Failed to process message from peer {}: {}", peer_id, e);
                     }
                 }
-                tokio_tungstenite::tungstenite::Message::Ping(data) => {
-                    // Respond to ping with pong
-                    if let Err(e) = ws_receiver.send(tokio_tungstenite::tungstenite::Message::Pong(data)).await {
-                        warn!("Failed to send pong to peer {}: {}", peer_id, e);
-                    }
+                WsMessage::Text(_) => {
+                    warn!("Ignoring text message from peer {}; the protocol is binary", peer_id);
                 }
-                tokio_tungstenite::tungstenite::Message::Close(_) => {
+                WsMessage::Close(_) => {
                     info!("Peer {} requested connection close", peer_id);
                     break;
                 }
                 _ => {
-                    // Ignore other message types
+                    // Pings are answered by tungstenite itself
                 }
             }
         }
-        
+
         Ok(())
     }
-    
+
     async fn handle_outgoing_messages(
-        mut ws_sender: futures_util::sink::SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, tokio_tungstenite::tungstenite::Message>,
-        mut rx: mpsc::UnboundedReceiver<Value>,
-        mut broadcast_rx: broadcast::Receiver<Value>,
-        peer_id: String,
+        mut ws_sender: futures_util::stream::SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, WsMessage>,
+        mut rx: mpsc::UnboundedReceiver<Vec<u8>>,
+        mut broadcast_rx: broadcast::Receiver<Relay>,
+        peer_id: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         loop {
             tokio::select! {
-                msg = rx.recv() => {
-                    match msg {
-                        Some(msg) => {
-                            let text = serde_json::to_string(&msg)?;
-                            if let Err(e) = ws_sender.send(tokio_tungstenite::tungstenite::Message::Text(text)).await {
+                frame = rx.recv() => {
+                    match frame {
+                        Some(frame) => {
+                            if let Err(e) = ws_sender.send(WsMessage::Binary(frame)).await {
                                 error!("Failed to send message to peer {}: {}", peer_id, e);
                                 break;
                             }
@@ -340,114 +313,83 @@ impl WebSocketServer {
                         None => break, // Channel closed
                     }
                 }
-                msg = broadcast_rx.recv() => {
-                    match msg {
-                        Ok(msg) => {
-                            let text = serde_json::to_string(&msg)?;
-                            if let Err(e) = ws_sender.send(tokio_tungstenite::tungstenite::Message::Text(text)).await {
+                relay = broadcast_rx.recv() => {
+                    match relay {
+                        Ok(relay) if relay.from.as_deref() == Some(peer_id) => {}
+                        Ok(relay) => {
+                            if let Err(e) = ws_sender.send(WsMessage::Binary(relay.frame)).await {
                                 error!("Failed to broadcast message to peer {}: {}", peer_id, e);
                                 break;
                             }
                         }
-                        Err(e) => {
-                            warn!("Broadcast receive error for peer {}: {}", peer_id, e);
-                            break;
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Peer {} fell behind, {} messages dropped", peer_id, skipped);
                         }
+                        Err(broadcast::error::RecvError::Closed) => break,
                     }
                 }
             }
         }
-        
+
         Ok(())
     }
-    
-    async fn process_message(&self, peer_id: &str, message: Value) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let msg_type = message.get("type").and_then(|t| t.as_str()).unwrap_or("unknown");
-        
-        match msg_type {
-            "sync" => {
-                // Broadcast sync message to all other peers
-                let sync_msg = json!({
-                    "type": "sync",
-                    "peer_id": peer_id,
-                    "data": message.get("data"),
-                    "timestamp": chrono::Utc::now().to_rfc3339(),
-                });
-                
-                if let Err(e) = self.broadcast_tx.send(sync_msg) {
-                    warn!("Failed to broadcast sync message: {}", e);
-                }
-                
-                // Update stats
-                {
-                    let mut stats = self.stats.write().await;
-                    stats.total_messages += 1;
-                }
-            }
-            "heartbeat" => {
-                // Update peer's last heartbeat
-                if let Some(peer) = self.peers.read().await.get(peer_id) {
-                    // Note: We can't modify the peer here due to borrow checker
-                    // In a real implementation, we'd need a different approach
+
+    /// Inspect a frame from a peer and relay it to everyone else
+    async fn process_frame(&self, peer_id: &str, frame: &[u8]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // Frames of a version we do not speak cannot be relayed faithfully either
+        let message = Message::decode(frame)?;
+
+        {
+            let mut peers = self.peers.write().await;
+            if let Some(peer) = peers.get_mut(peer_id) {
+                if peer.replica_id.is_none() {
+                    peer.replica_id = message.replica_id();
                 }
-            }
-            "presence" => {
-                // Broadcast presence update
-                let presence_msg = json!({
-                    "type": "presence",
-                    "peer_id": peer_id,
-                    "action": message.get("action"),
-                    "timestamp": chrono::Utc::now().to_rfc3339(),
-                });
-                
-                if let Err(e) = self.broadcast_tx.send(presence_msg) {
-                    warn!("Failed to broadcast presence message: {}", e);
+                match &message {
+                    Message::Hello { versions, features, .. } => {
+                        match Session::negotiate(versions, features) {
+                            Ok(session) => {
+                                info!("Peer {} speaks protocol version {}", peer_id, session.version);
+                                peer.session = Some(session);
+                            }
+                            // The client sees from our handshake that we cannot talk
+                            Err(e) => warn!("Peer {} is incompatible: {}", peer_id, e),
+                        }
+                    }
+                    Message::Heartbeat { .. } => {
+                        peer.last_heartbeat = Instant::now();
+                    }
+                    _ => {}
                 }
             }
-            _ => {
-                warn!("Unknown message type '{}' from peer {}", msg_type, peer_id);
-            }
         }
-        
-        Ok(())
-    }
-    
-    async fn process_binary_message(&self, peer_id: &str, data: &[u8]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        // Handle binary messages (e.g., file uploads, large data chunks)
-        info!("Received binary message from peer {}: {} bytes", peer_id, data.len());
-        
-        // For now, just acknowledge receipt
-        let ack = json!({
-            "type": "binary_ack",
-            "peer_id": peer_id,
-            "size": data.len(),
-            "timestamp": chrono::Utc::now().to_rfc3339(),
-        });
-        
-        // Find peer and send acknowledgment
-        if let Some(peer) = self.peers.read().await.get(peer_id) {
-            if let Err(e) = peer.sender.send(ack) {
-                warn!("Failed to send binary ack to peer {}: {}", peer_id, e);
-            }
+
+        // Sending only fails while nobody else is connected
+        let _ = self.broadcast_tx.send(Relay { from: Some(peer_id.to_string()), frame: frame.to_vec() });
+
+        // Update stats
+        {
+            let mut stats = self.stats.write().await;
+            stats.total_messages += 1;
         }
-        
+
         Ok(())
     }
-    
+
     async fn shutdown(&self) {
         info!("Shutting down WebSocket server...");
-        
+
         // Send shutdown signal to background tasks
         if let Err(e) = self.shutdown_tx.send(()) {
             error!("Failed to send shutdown signal: {}", e);
         }
-        
+
         // Close all peer connections
         let peers = self.peers.read().await;
         for (peer_id, _) in peers.iter() {
             info!("Closing connection for peer {}", peer_id);
         }
-        
+
         info!("WebSocket server shutdown complete");
     }
 }
@@ -461,19 +403,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         ))
         .with(tracing_subscriber::fmt::layer())
         .init();
-    
+
     info!("Starting Leptos-Sync WebSocket Server v{}", env!("CARGO_PKG_VERSION"));
-    
+
     let addr = std::env::var("WS_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".into());
     let listener = TcpListener::bind(&addr).await?;
-    
+
     info!("WebSocket server listening on: {}", addr);
     info!("Max connections: {}", MAX_CONNECTIONS);
     info!("Heartbeat interval: {:?}", HEARTBEAT_INTERVAL);
-    
+    info!("Protocol version: {}", leptos_sync_core::protocol::PROTOCOL_VERSION);
+
     let server = Arc::new(WebSocketServer::new());
     let server_clone = server.clone();
-    
+
     // Handle shutdown signals
     let shutdown_server = server.clone();
     tokio::spawn(async move {
@@ -482,15 +425,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         shutdown_server.shutdown().await;
         std::process::exit(0);
     });
-    
+
     // Accept connections
     while let Ok((stream, addr)) = listener.accept().await {
         let server = server_clone.clone();
-        
+
         tokio::spawn(async move {
             WebSocketServer::handle_connection(stream, addr, server).await;
         });
     }
-    
+
     Ok(())
 }
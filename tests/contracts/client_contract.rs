@@ -3,7 +3,7 @@
 //! Tests that validate the client correctly handles all server message types
 //! and implements the client-side contract as defined in the API specification.
 
-use leptos_sync_core::transport::message_protocol::{SyncMessage, MessageCodec, UserInfo, ServerInfo, PresenceAction};
+use leptos_sync_core::protocol::v1::{SyncMessage, MessageCodec, UserInfo, ServerInfo, PresenceAction};
 use leptos_sync_core::crdt::{ReplicaId, CrdtType};
 use leptos_sync_core::transport::{WebSocketClient, WebSocketClientConfig};
 use std::time::{SystemTime, Duration};
@@ -5,7 +5,7 @@
 
 use serde_json::Value;
 use jsonschema::{JSONSchema, Draft};
-use leptos_sync_core::transport::message_protocol::{SyncMessage, MessageCodec, UserInfo, ServerInfo};
+use leptos_sync_core::protocol::v1::{SyncMessage, MessageCodec, UserInfo, ServerInfo};
 use leptos_sync_core::crdt::{ReplicaId, CrdtType};
 use std::time::SystemTime;
 use uuid::Uuid;
@@ -81,7 +81,7 @@ fn create_test_presence_message() -> SyncMessage {
     let replica_id = ReplicaId::from(Uuid::new_v4());
     SyncMessage::Presence {
         peer_id: replica_id,
-        action: leptos_sync_core::transport::message_protocol::PresenceAction::Join,
+        action: leptos_sync_core::protocol::v1::PresenceAction::Join,
         timestamp: SystemTime::now(),
     }
 }
@@ -4,10 +4,8 @@
 
 use leptos::*;
 use leptos_sync_core::crdt::{CrdtType, ReplicaId};
-use leptos_sync_core::transport::{
-    message_protocol::{PresenceAction, ServerInfo, SyncMessage, UserInfo},
-    SyncTransport, WebSocketClient, WebSocketClientConfig,
-};
+use leptos_sync_core::protocol::v1::{PresenceAction, ServerInfo, SyncMessage, UserInfo};
+use leptos_sync_core::transport::{SyncTransport, WebSocketClient, WebSocketClientConfig};
 use leptos_sync_core::*;
 use std::time::{Duration, SystemTime};
 use tokio::time::timeout;
@@ -282,8 +282,8 @@ async fn test_conflict_resolution_workflow() {
     let register2 = LwwRegister::new(document2, replica_id2);
 
     // Set different timestamps to simulate conflict
-    register1 = register1.with_timestamp(chrono::DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z").unwrap().with_timezone(&chrono::Utc));
-    let register2 = register2.with_timestamp(chrono::DateTime::parse_from_rfc3339("2023-01-01T01:00:00Z").unwrap().with_timezone(&chrono::Utc));
+    register1 = register1.with_timestamp(leptos_sync_core::crdt::HlcTimestamp::from_datetime(chrono::DateTime::parse_from_rfc3339("2023-01-01T00:00:00Z").unwrap().with_timezone(&chrono::Utc)));
+    let register2 = register2.with_timestamp(leptos_sync_core::crdt::HlcTimestamp::from_datetime(chrono::DateTime::parse_from_rfc3339("2023-01-01T01:00:00Z").unwrap().with_timezone(&chrono::Utc)));
 
     // Insert both versions
     assert!(collection.insert("conflict_doc", &register1).await.is_ok());
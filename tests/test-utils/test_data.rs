@@ -3,7 +3,7 @@
 //! Provides test data generators and utilities for comprehensive testing.
 
 use leptos_sync_core::crdt::{LwwRegister, LwwMap, GCounter, ReplicaId};
-use leptos_sync_core::transport::message_protocol::{SyncMessage, UserInfo, ServerInfo, PresenceAction};
+use leptos_sync_core::protocol::v1::{SyncMessage, UserInfo, ServerInfo, PresenceAction};
 use std::time::SystemTime;
 use uuid::Uuid;
 